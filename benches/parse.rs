@@ -0,0 +1,94 @@
+// Benchmarks for parsing large configurations, of the size generated by
+// hosting providers with a jail per customer.
+use criterion::{
+    criterion_group,
+    criterion_main,
+    BenchmarkId,
+    Criterion,
+    Throughput,
+};
+use jailconf::{
+    format_config,
+    parse,
+    parse_lenient,
+    parse_reader,
+    scan_blocks,
+    FormatOptions,
+};
+
+// A configuration with global defaults followed by the given number of jails.
+fn config(jails: usize) -> String {
+    let mut config = String::from(concat!(
+        "# Defaults for every jail.\n",
+        "$base = \"/usr/jails\";\n",
+        "exec.start = \"/bin/sh /etc/rc\";\n",
+        "exec.stop = \"/bin/sh /etc/rc.shutdown\";\n",
+        "exec.clean;\n",
+        "mount.devfs;\n",
+        "path = \"$base/$name\";\n",
+        "\n",
+    ));
+
+    for i in 0..jails {
+        config.push_str(&format!(
+            concat!(
+                "/* Customer {i} */\n",
+                "customer{i} {{\n",
+                "    host.hostname = \"customer{i}.example.com\";\n",
+                "    ip4.addr = \"lo1|10.{a}.{b}.1/32\", 10.{a}.{b}.2;\n",
+                "    ip4.addr += \"10.{a}.{b}.3\";\n",
+                "    allow.raw_sockets = 1;\n",
+                "    persist;\n",
+                "    // Keep customers apart.\n",
+                "    devfs_ruleset = 4;\n",
+                "}}\n\n",
+            ),
+            i = i,
+            a = i / 256,
+            b = i % 256,
+        ));
+    }
+
+    config
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+
+    for &jails in &[1_000, 5_000] {
+        let input = config(jails);
+
+        group.throughput(Throughput::Bytes(input.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("parse", jails), &input, |b, input| {
+            b.iter(|| parse(input).unwrap())
+        });
+
+        group.bench_with_input(BenchmarkId::new("parse_lenient", jails), &input, |b, input| {
+            b.iter(|| parse_lenient(input))
+        });
+
+        group.bench_with_input(BenchmarkId::new("parse_reader", jails), &input, |b, input| {
+            b.iter(|| parse_reader(input.as_bytes()).count())
+        });
+
+        group.bench_with_input(BenchmarkId::new("scan_blocks", jails), &input, |b, input| {
+            b.iter(|| scan_blocks(input).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_format(c: &mut Criterion) {
+    let input = config(1_000);
+    let config = parse(&input).unwrap();
+    let options = FormatOptions::new();
+
+    c.bench_function("format_config/1000", |b| {
+        b.iter(|| format_config(&config, &options))
+    });
+}
+
+criterion_group!(benches, bench_parse, bench_format);
+criterion_main!(benches);
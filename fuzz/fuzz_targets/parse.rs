@@ -0,0 +1,28 @@
+// Parse arbitrary input. The parsers must never panic, the lexer must
+// account for every byte, and a configuration which parses must render to
+// text which parses back to the same configuration.
+#![no_main]
+use jailconf::{
+    lex,
+    parse_lenient,
+    JailConfig,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &str| {
+    let text: String = lex(input).into_iter()
+        .map(|(_, span)| &input[span.range()])
+        .collect();
+
+    assert_eq!(text, input);
+
+    let _ = parse_lenient(input);
+
+    if let Ok(config) = JailConfig::parse(input) {
+        let rendered = config.to_string();
+        let reparsed = JailConfig::parse(&rendered)
+            .expect("rendered configuration should parse");
+
+        assert_eq!(reparsed.normalize(), config.normalize());
+    }
+});
@@ -0,0 +1,14 @@
+// Render an arbitrary configuration and parse it back. The result must
+// render identically and be the same configuration as was generated.
+#![no_main]
+use jailconf::JailConfig;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|config: JailConfig<'static>| {
+    let rendered = config.to_string();
+    let reparsed = JailConfig::parse(&rendered)
+        .expect("rendered configuration should parse");
+
+    assert_eq!(reparsed.to_string(), rendered);
+    assert_eq!(reparsed.normalize(), config.normalize());
+});
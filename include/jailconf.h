@@ -0,0 +1,116 @@
+#ifndef JAILCONF_H
+#define JAILCONF_H
+
+/* Generated by cbindgen from src/capi.rs, do not edit. */
+
+#include <stddef.h>
+
+/**
+ * A parsed configuration.
+ */
+typedef struct JailconfConfig JailconfConfig;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Parse a configuration. Returns NULL if it can't be parsed, in which case
+ * `*error`, if `error` isn't NULL, is set to a description of why, which
+ * the caller releases with `jailconf_string_free`.
+ *
+ * # Safety
+ *
+ * `input` must be a valid NUL terminated string, and `error` either NULL
+ * or valid to write a pointer to.
+ */
+struct JailconfConfig *jailconf_parse(const char *input, char **error);
+
+/**
+ * Release a configuration returned by `jailconf_parse`. Does nothing if
+ * `config` is NULL.
+ *
+ * # Safety
+ *
+ * `config` must be NULL or a configuration from `jailconf_parse` which
+ * hasn't already been released.
+ */
+void jailconf_free(struct JailconfConfig *config);
+
+/**
+ * The number of jails configured.
+ *
+ * # Safety
+ *
+ * `config` must be a configuration from `jailconf_parse`.
+ */
+size_t jailconf_jail_count(const struct JailconfConfig *config);
+
+/**
+ * The name of the jail at `index`, in the order the jails are first
+ * configured, or NULL if there aren't that many. The name belongs to the
+ * configuration.
+ *
+ * # Safety
+ *
+ * `config` must be a configuration from `jailconf_parse`.
+ */
+const char *jailconf_jail_name(const struct JailconfConfig *config, size_t index);
+
+/**
+ * The number of values the parameter has for the jail, 0 if the jail or
+ * parameter isn't configured. Booleans set without a value have one,
+ * "true".
+ *
+ * # Safety
+ *
+ * `config` must be a configuration from `jailconf_parse`, and `jail` and
+ * `name` valid NUL terminated strings.
+ */
+size_t jailconf_value_count(const struct JailconfConfig *config,
+                            const char *jail,
+                            const char *name);
+
+/**
+ * The value of the parameter for the jail at `index`, or NULL if it has
+ * fewer values. The caller releases the value with
+ * `jailconf_string_free`.
+ *
+ * # Safety
+ *
+ * `config` must be a configuration from `jailconf_parse`, and `jail` and
+ * `name` valid NUL terminated strings.
+ */
+char *jailconf_value(const struct JailconfConfig *config,
+                     const char *jail,
+                     const char *name,
+                     size_t index);
+
+/**
+ * The value of the parameter for the jail, its last if it has several, or
+ * NULL if it isn't set. The caller releases the value with
+ * `jailconf_string_free`.
+ *
+ * # Safety
+ *
+ * `config` must be a configuration from `jailconf_parse`, and `jail` and
+ * `name` valid NUL terminated strings.
+ */
+char *jailconf_get_param(const struct JailconfConfig *config, const char *jail, const char *name);
+
+/**
+ * Release a string returned by the other functions. Does nothing if `s` is
+ * NULL.
+ *
+ * # Safety
+ *
+ * `s` must be NULL or a string returned as `char *` which hasn't already
+ * been released.
+ */
+void jailconf_string_free(char *s);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* JAILCONF_H */
@@ -0,0 +1,126 @@
+// Structured annotations carried in comments.
+//
+// Comments immediately preceding a jail block may contain directives of the
+// form `@key: value`, eg:
+//
+// # @owner: team-web
+// # @managed-by: ansible
+// nginx {
+//     persist;
+// }
+//
+// These are collected into a metadata map on the following block, allowing
+// ownership and tagging information to live alongside the configuration.
+use std::collections::BTreeMap;
+use crate::{
+    JailComment,
+    JailConf,
+};
+
+pub type Annotations = BTreeMap<String, String>;
+
+fn is_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'
+}
+
+// Parse a single `@key: value` directive from a line of comment text.
+fn directive(line: &str) -> Option<(&str, &str)> {
+    // Leading asterisks are common in multi-line C style comments.
+    let line = line.trim().trim_start_matches('*').trim_start();
+
+    if !line.starts_with('@') {
+        return None;
+    }
+
+    let colon = line.find(':')?;
+    let key = line[1..colon].trim();
+    let value = line[colon + 1..].trim();
+
+    if key.is_empty() || !key.chars().all(is_key_char) {
+        return None;
+    }
+
+    Some((key, value))
+}
+
+// All directives found in a comment.
+fn directives(comment: &JailComment) -> Vec<(String, String)> {
+    comment.comment()
+        .lines()
+        .filter_map(directive)
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+// Collect annotations from a statement into pending, or attach those pending
+// to it if it's a block. Any other statement breaks the run of comments.
+pub(crate) fn collect(pending: &mut Annotations, statement: &mut JailConf) {
+    match statement {
+        JailConf::Comment(comment) => {
+            pending.extend(directives(comment));
+        },
+        JailConf::Block(block) => {
+            block.annotations = std::mem::take(pending);
+        },
+        _ => pending.clear(),
+    }
+}
+
+// Attach annotations from runs of comments to the block that follows them.
+// Any other statement between the comments and a block breaks the run.
+pub(crate) fn attach(mut config: Vec<JailConf>) -> Vec<JailConf> {
+    let mut pending = Annotations::new();
+
+    for statement in &mut config {
+        collect(&mut pending, statement);
+    }
+
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use indoc::indoc;
+
+    #[test]
+    fn test_directive() {
+        assert_eq!(directive(" @owner: team-web"), Some(("owner", "team-web")));
+        assert_eq!(directive(" * @managed-by:ansible "), Some(("managed-by", "ansible")));
+        assert_eq!(directive(" owner: team-web"), None);
+        assert_eq!(directive(" @not a key: value"), None);
+        assert_eq!(directive(" @no-colon"), None);
+    }
+
+    #[test]
+    fn test_annotations_attached_to_following_block() {
+        let input = indoc!(r#"
+            # @owner: team-web
+            /*
+             * @managed-by: ansible
+             */
+            nginx {
+                persist;
+            }
+
+            # @owner: team-db
+            persist;
+            postgres {
+                persist;
+            }
+            "#);
+
+        let res = parse(input).unwrap();
+        let blocks: Vec<&crate::JailBlock> = res.iter()
+            .filter_map(|statement| match statement {
+                JailConf::Block(block) => Some(block),
+                _                      => None,
+            })
+            .collect();
+
+        assert_eq!(blocks[0].annotation("owner"), Some("team-web"));
+        assert_eq!(blocks[0].annotation("managed-by"), Some("ansible"));
+        assert!(blocks[1].annotations().is_empty());
+    }
+}
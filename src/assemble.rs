@@ -0,0 +1,477 @@
+// Assembly of a host's configuration from fragments.
+//
+// Fleets rarely keep one jail.conf per host. A base policy is shared by
+// every host, teams override parts of it, and each jail may have a file of
+// its own. The Assembler merges these fragments into one configuration, in
+// order of precedence, as `JailConfig::merge` merges two: globals into the
+// globals, each block's parameters into the blocks for the same jail, `=`
+// replacing earlier values and `+=` adding to them.
+//
+// Where two fragments assign different values to a parameter in the same
+// scope, the conflict policy for the parameter decides what happens: the
+// assembly fails, the fragment with the higher precedence wins, or the one
+// with the lower precedence keeps its values. Every conflict resolved is
+// reported, naming both fragments, so that a pipeline can show what was
+// overridden and by what. Assigning a parameter more than once within one
+// fragment isn't a conflict, it's how jail(8) configurations are written.
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+};
+use std::error;
+use std::fmt;
+use crate::merge::{
+    assignment,
+    assigns,
+    current_values,
+    insert,
+};
+use crate::{
+    JailConf,
+    JailConfig,
+};
+
+/// What to do when two fragments assign different values to a parameter,
+/// see `Assembler`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Fail the assembly with `AssembleError::Conflict`.
+    #[default]
+    Error,
+    /// The fragment with the higher precedence replaces the values.
+    LastWins,
+    /// The fragment with the lower precedence keeps its values, and those of
+    /// later fragments, including their appends, are dropped.
+    FirstWins,
+}
+
+/// A conflict between two fragments over a parameter, resolved by the policy
+/// for it, or failing the assembly under `ConflictPolicy::Error`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    jail:          Option<String>,
+    name:          String,
+    policy:        ConflictPolicy,
+    first:         String,
+    first_values:  Vec<String>,
+    second:        String,
+    second_values: Vec<String>,
+}
+
+impl Conflict {
+    /// The jail the parameter was set for, or None for a global parameter.
+    pub fn jail(&self) -> Option<&str> {
+        self.jail.as_deref()
+    }
+
+    /// The name of the parameter. Variables are named with their leading
+    /// `$`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The policy the conflict was resolved by.
+    pub fn policy(&self) -> ConflictPolicy {
+        self.policy
+    }
+
+    /// The fragment with the lower precedence.
+    pub fn first(&self) -> &str {
+        &self.first
+    }
+
+    /// The values the fragment with the lower precedence gave.
+    pub fn first_values(&self) -> &[String] {
+        &self.first_values
+    }
+
+    /// The fragment with the higher precedence.
+    pub fn second(&self) -> &str {
+        &self.second
+    }
+
+    /// The values the fragment with the higher precedence gave.
+    pub fn second_values(&self) -> &[String] {
+        &self.second_values
+    }
+
+    /// The fragment whose values were kept, or None if the conflict failed
+    /// the assembly.
+    pub fn winner(&self) -> Option<&str> {
+        match self.policy {
+            ConflictPolicy::Error     => None,
+            ConflictPolicy::LastWins  => Some(&self.second),
+            ConflictPolicy::FirstWins => Some(&self.first),
+        }
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.policy == ConflictPolicy::Error {
+            f.write_str("conflicting values for ")?;
+        }
+
+        f.write_str(&self.name)?;
+
+        if let Some(jail) = &self.jail {
+            write!(f, " in jail {}", jail)?;
+        }
+
+        let first = (&self.first, self.first_values.join(", "));
+        let second = (&self.second, self.second_values.join(", "));
+
+        match self.policy {
+            ConflictPolicy::Error => {
+                write!(f, ": {:?} from {} and {:?} from {}", first.1, first.0, second.1, second.0)
+            },
+            ConflictPolicy::LastWins => {
+                write!(f, ": {} ({:?}) wins over {} ({:?})", second.0, second.1, first.0, first.1)
+            },
+            ConflictPolicy::FirstWins => {
+                write!(f, ": {} ({:?}) wins over {} ({:?})", first.0, first.1, second.0, second.1)
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssembleError {
+    /// Two fragments assign different values to a parameter whose policy is
+    /// `ConflictPolicy::Error`. Boxed, as it's much larger than the other
+    /// errors.
+    Conflict(Box<Conflict>),
+    /// The precedence order names a fragment which wasn't added.
+    UnknownFragment {
+        name: String,
+    },
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssembleError::Conflict(conflict) => conflict.fmt(f),
+            AssembleError::UnknownFragment { name } => {
+                write!(f, "no fragment named {}", name)
+            },
+        }
+    }
+}
+
+impl error::Error for AssembleError {}
+
+/// The result of an assembly: the configuration, and the conflicts resolved
+/// to make it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Assembly<'a> {
+    config:    JailConfig<'a>,
+    conflicts: Vec<Conflict>,
+}
+
+impl<'a> Assembly<'a> {
+    /// The assembled configuration.
+    pub fn config(&self) -> &JailConfig<'a> {
+        &self.config
+    }
+
+    /// The assembled configuration, consuming the assembly.
+    pub fn into_config(self) -> JailConfig<'a> {
+        self.config
+    }
+
+    /// Each conflict resolved, in the order they were found.
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+}
+
+// The jail, or None for the globals, and the parameter a value is for.
+type Key = (Option<String>, String);
+
+// The state of an assembly in progress.
+#[derive(Default)]
+struct State<'a> {
+    statements: Vec<JailConf<'a>>,
+    // The fragment which last assigned each parameter with `=`.
+    owners:     BTreeMap<Key, String>,
+    // The parameters the current fragment lost under FirstWins, whose
+    // appends are dropped too.
+    lost:       BTreeSet<Key>,
+    conflicts:  Vec<Conflict>,
+}
+
+/// Assembles one configuration from named fragments, see `src/assemble.rs`.
+#[derive(Clone, Debug, Default)]
+pub struct Assembler<'a> {
+    fragments: Vec<(String, JailConfig<'a>)>,
+    order:     Vec<String>,
+    policy:    ConflictPolicy,
+    policies:  BTreeMap<String, ConflictPolicy>,
+}
+
+impl<'a> Assembler<'a> {
+    /// An assembler with no fragments, failing on any conflict.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a fragment. Fragments are assembled in the order they're added,
+    /// lowest precedence first, unless an order is given with `precedence`.
+    pub fn fragment(mut self, name: &str, config: JailConfig<'a>) -> Self {
+        self.fragments.push((name.to_string(), config));
+        self
+    }
+
+    /// The order to assemble fragments in, by name, lowest precedence first.
+    /// Fragments which aren't named are assembled first, in the order they
+    /// were added.
+    pub fn precedence(mut self, order: &[&str]) -> Self {
+        self.order = order.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    /// The policy for conflicts over parameters without one of their own.
+    pub fn policy(mut self, policy: ConflictPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// The policy for conflicts over the named parameter, or variable if
+    /// named with its leading `$`.
+    pub fn param_policy(mut self, name: &str, policy: ConflictPolicy) -> Self {
+        self.policies.insert(name.to_string(), policy);
+        self
+    }
+
+    // The fragments in the order they're assembled.
+    fn ordered(&self) -> Result<Vec<&(String, JailConfig<'a>)>, AssembleError> {
+        for name in &self.order {
+            if !self.fragments.iter().any(|(fragment, _)| fragment == name) {
+                return Err(AssembleError::UnknownFragment {
+                    name: name.clone(),
+                });
+            }
+        }
+
+        let unordered = self.fragments.iter()
+            .filter(|(name, _)| !self.order.contains(name));
+        let ordered = self.order.iter()
+            .flat_map(|name| self.fragments.iter().filter(move |(fragment, _)| fragment == name));
+
+        Ok(unordered.chain(ordered).collect())
+    }
+
+    // Add a non-block statement of a fragment to a scope, resolving any
+    // conflict with the values already there.
+    fn add_statement(
+        &self,
+        state:     &mut State<'a>,
+        fragment:  &str,
+        jail:      Option<&str>,
+        statement: JailConf<'a>,
+    ) -> Result<(), AssembleError> {
+        let mut scope: Vec<&mut Vec<JailConf<'a>>> = match jail {
+            None       => vec![&mut state.statements],
+            Some(jail) => state.statements
+                .iter_mut()
+                .filter_map(|s| match s {
+                    JailConf::Block(block) if block.name() == jail => Some(&mut block.params),
+                    _                                              => None,
+                })
+                .collect(),
+        };
+
+        let (name, value, append) = match assignment(&statement) {
+            Some(assignment) => assignment,
+            None             => {
+                insert(&mut scope, statement);
+                return Ok(());
+            },
+        };
+
+        let key = (jail.map(String::from), name);
+
+        if state.lost.contains(&key) {
+            return Ok(());
+        }
+
+        if append {
+            insert(&mut scope, statement);
+            return Ok(());
+        }
+
+        let current = current_values(&scope, &key.1);
+        let owner = state.owners.get(&key).filter(|owner| *owner != fragment).cloned();
+
+        if let Some(owner) = owner.filter(|_| !current.is_empty() && current != [value.as_str()]) {
+            let policy = self.policies.get(&key.1).copied().unwrap_or(self.policy);
+
+            let conflict = Conflict {
+                jail:          key.0.clone(),
+                name:          key.1.clone(),
+                policy,
+                first:         owner,
+                first_values:  current,
+                second:        fragment.to_string(),
+                second_values: vec![value],
+            };
+
+            if policy == ConflictPolicy::Error {
+                return Err(AssembleError::Conflict(Box::new(conflict)));
+            }
+
+            state.conflicts.push(conflict);
+
+            if policy == ConflictPolicy::FirstWins {
+                state.lost.insert(key);
+                return Ok(());
+            }
+        }
+
+        for statements in scope.iter_mut() {
+            statements.retain(|s| !assigns(s, &key.1));
+        }
+
+        insert(&mut scope, statement);
+        state.owners.insert(key, fragment.to_string());
+
+        Ok(())
+    }
+
+    /// Assemble the fragments into one configuration, merging each into
+    /// those before it as `JailConfig::merge` does and resolving conflicts
+    /// between them by policy.
+    pub fn assemble(&self) -> Result<Assembly<'a>, AssembleError> {
+        let mut state = State::default();
+
+        for (fragment, config) in self.ordered()? {
+            state.lost.clear();
+
+            for statement in config.statements() {
+                let block = match statement {
+                    JailConf::Block(block) => block,
+                    statement              => {
+                        self.add_statement(&mut state, fragment, None, statement.clone())?;
+                        continue;
+                    },
+                };
+
+                let configured = state.statements.iter()
+                    .any(|s| matches!(s, JailConf::Block(b) if b.name() == block.name()));
+
+                // A new jail starts with an empty block, so that its
+                // parameters are recorded as the fragment's like any other.
+                if !configured {
+                    let mut empty = block.clone();
+                    empty.params.clear();
+                    state.statements.push(JailConf::Block(empty));
+                }
+
+                for param in block.params() {
+                    self.add_statement(&mut state, fragment, Some(block.name()), param.clone())?;
+                }
+            }
+        }
+
+        Ok(Assembly {
+            config:    JailConfig::new(state.statements),
+            conflicts: state.conflicts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    fn fragments() -> Assembler<'static> {
+        let base = JailConfig::parse(indoc!(r#"
+            persist;
+            path = "/jails/$name";
+            www {
+                host.hostname = www;
+                ip4.addr = 192.0.2.1;
+            }
+            "#)).unwrap();
+        let team = JailConfig::parse(indoc!(r#"
+            path = "/usr/jails/$name";
+            www {
+                ip4.addr = 198.51.100.1;
+                ip4.addr += 198.51.100.2;
+                allow.raw_sockets;
+            }
+            "#)).unwrap();
+        let jail = JailConfig::parse(indoc!(r#"
+            db {
+                host.hostname = db;
+            }
+            www {
+                host.hostname = www;
+            }
+            "#)).unwrap();
+
+        Assembler::new()
+            .fragment("team", team)
+            .fragment("jail", jail)
+            .fragment("base", base)
+            .precedence(&["base", "team", "jail"])
+    }
+
+    #[test]
+    fn test_assemble_last_wins() {
+        let assembly = fragments().policy(ConflictPolicy::LastWins).assemble().unwrap();
+        let config = assembly.config();
+
+        assert_eq!(config.get_param("www", "path").as_deref(), Some("/usr/jails/$name"));
+        assert_eq!(config.get_values("www", "ip4.addr"), ["198.51.100.1", "198.51.100.2"]);
+        assert_eq!(config.get_param("db", "host.hostname").as_deref(), Some("db"));
+
+        let conflicts: Vec<String> = assembly.conflicts().iter().map(ToString::to_string).collect();
+        assert_eq!(conflicts, [
+            "path: team (\"/usr/jails/$name\") wins over base (\"/jails/$name\")",
+            "ip4.addr in jail www: team (\"198.51.100.1\") wins over base (\"192.0.2.1\")",
+        ]);
+        assert_eq!(assembly.conflicts()[1].jail(), Some("www"));
+    }
+
+    #[test]
+    fn test_assemble_first_wins() {
+        let assembly = fragments()
+            .policy(ConflictPolicy::LastWins)
+            .param_policy("ip4.addr", ConflictPolicy::FirstWins)
+            .assemble()
+            .unwrap();
+        let config = assembly.config();
+
+        assert_eq!(config.get_param("www", "path").as_deref(), Some("/usr/jails/$name"));
+        assert_eq!(config.get_values("www", "ip4.addr"), ["192.0.2.1"]);
+        assert_eq!(config.get_param("www", "allow.raw_sockets").as_deref(), Some("true"));
+
+        let conflict = &assembly.conflicts()[1];
+        assert_eq!(conflict.winner(), Some("base"));
+        assert_eq!((conflict.first(), conflict.second()), ("base", "team"));
+        assert_eq!(conflict.first_values(), ["192.0.2.1"]);
+        assert_eq!(conflict.second_values(), ["198.51.100.1"]);
+    }
+
+    #[test]
+    fn test_assemble_error() {
+        let err = fragments().assemble().unwrap_err();
+
+        assert_eq!(err.to_string(), "conflicting values for path: \"/jails/$name\" from base and \"/usr/jails/$name\" from team");
+
+        let err = fragments().precedence(&["base", "ops"]).assemble().unwrap_err();
+        assert_eq!(err, AssembleError::UnknownFragment {
+            name: "ops".to_string(),
+        });
+
+        // The same value from two fragments isn't a conflict.
+        let assembly = Assembler::new()
+            .fragment("a", JailConfig::parse("www {\n    persist;\n}\n").unwrap())
+            .fragment("b", JailConfig::parse("www {\n    persist;\n}\n").unwrap())
+            .assemble()
+            .unwrap();
+        assert!(assembly.conflicts().is_empty());
+    }
+}
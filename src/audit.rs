@@ -0,0 +1,440 @@
+// Security review of jail configuration.
+//
+// The audit looks at the effective parameters of each jail for settings
+// which weaken the isolation between the jail and the host, or between
+// jails. Findings are returned as Lints, with a severity reflecting how much
+// isolation is lost, so that a CI pipeline can fail on those it cares about.
+//
+// The exec audit looks at the commands run as each jail starts and stops
+// instead. jail(8) expands variables in them and hands the result to sh(1),
+// on the host for most stages, so a variable whose value isn't a plain word
+// can add commands of its own. It's the usual way in for configurations
+// generated from user input.
+use std::collections::HashMap;
+use crate::lint::{
+    Lint,
+    Severity,
+};
+use crate::value::parse_bool;
+use crate::variables::references;
+use crate::{
+    EffectiveParams,
+    ExecStage,
+    JailConf,
+    JailConfig,
+    Span,
+};
+
+// Boolean permissions which are risky when enabled.
+const RISKY_ALLOWS: &[(&str, Severity, &str, &str)] = &[
+    (
+        "allow.raw_sockets",
+        Severity::Warning,
+        "raw-sockets",
+        "lets the jail create raw sockets, which can be used to spoof traffic",
+    ),
+    (
+        "allow.sysvipc",
+        Severity::Warning,
+        "sysvipc",
+        "shares System V IPC objects with the host and every other jail",
+    ),
+];
+
+// Text the shell gives a meaning to, beyond separating words.
+const SHELL_METACHARS: &[&str] = &[";", "|", "&", "`", "$(", ">", "<"];
+
+// Whether text is a single word the shell takes as it is.
+fn is_plain_word(text: &str) -> bool {
+    text.chars().all(|c| c.is_ascii_alphanumeric() || "_./:,@%+=-".contains(c))
+}
+
+// Whether a variable may expand to something other than a plain word: it's
+// set to something else, or refers to a variable which may, or to one not
+// defined at all. $name is the jail's name. seen holds the variables being
+// looked at, to stop at definitions referring to themselves.
+fn is_tainted<'s>(
+    definitions: &HashMap<&str, Vec<&'s str>>,
+    name: &'s str,
+    seen: &mut Vec<&'s str>,
+) -> bool {
+    if name == "name" || seen.contains(&name) {
+        return false;
+    }
+
+    let values = match definitions.get(name) {
+        Some(values) => values,
+        None         => return true,
+    };
+
+    seen.push(name);
+
+    let tainted = values.iter().any(|raw| {
+        let (text, names) = references(raw);
+
+        !is_plain_word(&text)
+            || names.into_iter().any(|name| is_tainted(definitions, name, seen))
+    });
+
+    seen.pop();
+
+    tainted
+}
+
+// The last value of a parameter.
+fn last<'p>(params: &'p EffectiveParams, name: &str) -> Option<&'p str> {
+    params.get(name)
+        .and_then(|values| values.last())
+        .map(String::as_str)
+}
+
+fn is_true(params: &EffectiveParams, name: &str) -> bool {
+    last(params, name).and_then(parse_bool) == Some(true)
+}
+
+// The path of a jail, with references to its own name expanded the way
+// jail(8) expands them.
+fn jail_path(params: &EffectiveParams, jail: &str) -> Option<String> {
+    last(params, "path").map(|path| {
+        path.replace("${name}", jail).replace("$name", jail)
+    })
+}
+
+impl<'a> JailConfig<'a> {
+    // Where the parameter is last set for the jail, or where the jail's first
+    // block is if it isn't set.
+    pub(crate) fn setter_span(&self, jail: &str, name: &str) -> Span {
+        let setter = self.applying(jail)
+            .into_iter()
+            .rev()
+            .find(|statement| match statement {
+                JailConf::ParamBool(param)  => param.name() == name,
+                JailConf::ParamValue(param) => param.name() == name,
+                _                           => false,
+            });
+
+        match setter {
+            Some(statement) => statement.span(),
+            None            => {
+                self.jail(jail).map_or_else(Span::default, |block| block.span())
+            },
+        }
+    }
+
+    /// Look for settings which weaken a jail's isolation: raw sockets,
+    /// System V IPC, `enforce_statfs` below 2, filesystems the jail may
+    /// mount, `children.max` not being set, and jails sharing a `path`.
+    ///
+    /// Each finding explains the risk and carries a severity, see `Lint`.
+    pub fn audit(&self) -> Vec<Lint> {
+        let mut lints = Vec::new();
+        let mut names: Vec<&str> = Vec::new();
+        let mut paths: Vec<(String, &str)> = Vec::new();
+
+        for block in self.jails() {
+            if !names.contains(&block.name()) {
+                names.push(block.name());
+            }
+        }
+
+        for jail in names {
+            let params = match self.effective_params(jail) {
+                Some(params) => params,
+                None         => continue,
+            };
+
+            let mut report = |severity, code, name: &str, message: String| {
+                lints.push(Lint::new(
+                    severity,
+                    code,
+                    message,
+                    Some(jail),
+                    self.setter_span(jail, name),
+                ));
+            };
+
+            for (name, severity, code, risk) in RISKY_ALLOWS {
+                if is_true(&params, name) {
+                    report(*severity, *code, name, format!("{} {}", name, risk));
+                }
+            }
+
+            let statfs = last(&params, "enforce_statfs")
+                .and_then(|value| value.trim().parse::<i64>().ok());
+
+            match statfs {
+                Some(0) => report(
+                    Severity::Error,
+                    "enforce-statfs",
+                    "enforce_statfs",
+                    "enforce_statfs = 0 shows the jail every mount point on \
+                     the host".to_string(),
+                ),
+                Some(1) => report(
+                    Severity::Warning,
+                    "enforce-statfs",
+                    "enforce_statfs",
+                    "enforce_statfs = 1 shows the jail mount points below its \
+                     root which it didn't mount".to_string(),
+                ),
+                _ => {},
+            }
+
+            if is_true(&params, "allow.mount") {
+                let filesystems = params.keys()
+                    .filter(|name| name.starts_with("allow.mount."))
+                    .filter(|name| is_true(&params, name));
+
+                for name in filesystems {
+                    let fs = &name["allow.mount.".len()..];
+
+                    report(
+                        Severity::Warning,
+                        "mount",
+                        name.as_str(),
+                        format!(
+                            "{} with allow.mount lets the jail mount {} \
+                             filesystems",
+                            name,
+                            fs,
+                        ),
+                    );
+                }
+            }
+
+            if !params.contains_key("children.max") {
+                report(
+                    Severity::Info,
+                    "children-max",
+                    "children.max",
+                    "children.max is not set, set it explicitly to state \
+                     whether the jail may create child jails".to_string(),
+                );
+            }
+
+            if let Some(path) = jail_path(&params, jail) {
+                match paths.iter().find(|(other, _)| *other == path) {
+                    Some((_, other)) => report(
+                        Severity::Error,
+                        "shared-path",
+                        "path",
+                        format!("path {} is also the root of jail {}", path, other),
+                    ),
+                    None => paths.push((path, jail)),
+                }
+            }
+        }
+
+        lints
+    }
+
+    /// Look for risks in the `exec.*` commands run as each jail starts and
+    /// stops: shell metacharacters, references to variables the
+    /// configuration doesn't define, and variables whose values may not be
+    /// a single plain word interpolated into the command.
+    ///
+    /// Each finding is reported where the command is set, see `Lint`.
+    pub fn audit_exec(&self) -> Vec<Lint> {
+        let mut lints = Vec::new();
+
+        for jail in self.jail_names() {
+            let statements = self.applying(jail);
+            let mut definitions: HashMap<&str, Vec<&str>> = HashMap::new();
+
+            for statement in &statements {
+                match statement {
+                    JailConf::ParamBool(param) => {
+                        definitions.entry(param.name()).or_default();
+                    },
+                    JailConf::ParamValue(param) => {
+                        definitions.entry(param.name()).or_default().push(param.raw());
+                    },
+                    JailConf::Variable(var) => {
+                        definitions.entry(var.name()).or_default().push(var.raw());
+                    },
+                    _ => {},
+                }
+            }
+
+            let commands = statements.iter().filter_map(|statement| match statement {
+                JailConf::ParamValue(param) => {
+                    ExecStage::ALL.iter()
+                        .any(|stage| stage.param() == param.name())
+                        .then_some(param)
+                },
+                _ => None,
+            });
+
+            for param in commands {
+                let mut report = |severity, code, message| {
+                    lints.push(Lint::new(severity, code, message, Some(jail), param.span()));
+                };
+
+                let (text, names) = references(param.raw());
+
+                let metachars: Vec<String> = SHELL_METACHARS.iter()
+                    .filter(|metachar| text.contains(**metachar))
+                    .map(|metachar| format!("`{}`", metachar))
+                    .collect();
+
+                if !metachars.is_empty() {
+                    report(
+                        Severity::Info,
+                        "exec-metachar",
+                        format!(
+                            "{} is run by sh(1), which interprets {} in it",
+                            param.name(),
+                            metachars.join(", "),
+                        ),
+                    );
+                }
+
+                let mut seen: Vec<&str> = Vec::new();
+
+                for name in names {
+                    if name == "name" || seen.contains(&name) {
+                        continue;
+                    }
+
+                    seen.push(name);
+
+                    if !definitions.contains_key(name) {
+                        report(
+                            Severity::Warning,
+                            "exec-unresolved",
+                            format!(
+                                "{} refers to ${}, which the configuration \
+                                 doesn't define",
+                                param.name(),
+                                name,
+                            ),
+                        );
+                    }
+                    else if is_tainted(&definitions, name, &mut Vec::new()) {
+                        report(
+                            Severity::Warning,
+                            "exec-interpolation",
+                            format!(
+                                "{} interpolates ${} into a command run by \
+                                 sh(1), and its value may not be a single \
+                                 plain word",
+                                param.name(),
+                                name,
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+
+        lints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_audit() {
+        let input = indoc!(r#"
+            path = "/usr/jails/$name";
+            children.max = 0;
+            nginx {
+                allow.raw_sockets;
+                allow.sysvipc = 0;
+                enforce_statfs = 1;
+                allow.mount;
+                allow.mount.nullfs;
+                allow.mount.zfs = false;
+            }
+            db {
+                path = /usr/jails/nginx;
+            }
+            www {
+                allow.mount.tmpfs;
+            }
+            "#);
+
+        let config = JailConfig::parse(input).unwrap();
+        let lints: Vec<String> = config.audit()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(lints, vec![
+            "warning[raw-sockets]: nginx: allow.raw_sockets lets the jail create raw sockets, which can be used to spoof traffic",
+            "warning[enforce-statfs]: nginx: enforce_statfs = 1 shows the jail mount points below its root which it didn't mount",
+            "warning[mount]: nginx: allow.mount.nullfs with allow.mount lets the jail mount nullfs filesystems",
+            "error[shared-path]: db: path /usr/jails/nginx is also the root of jail nginx",
+        ]);
+    }
+
+    #[test]
+    fn test_audit_spans() {
+        let input = "nginx {\n    persist;\n}\ndb {\n    enforce_statfs = 0;\n}\n";
+        let config = JailConfig::parse(input).unwrap();
+        let lints = config.audit();
+
+        let found: Vec<(&str, Severity, usize)> = lints.iter()
+            .map(|lint| (lint.code(), lint.severity(), lint.span().start().line()))
+            .collect();
+
+        assert_eq!(found, vec![
+            ("children-max", Severity::Info, 1),
+            ("enforce-statfs", Severity::Error, 5),
+            ("children-max", Severity::Info, 4),
+        ]);
+    }
+
+    #[test]
+    fn test_audit_exec() {
+        let input = indoc!(r#"
+            $rc = /etc/rc;
+            $greeting = "hello world";
+            $user = "$owner";
+            exec.start = "/bin/sh $rc";
+            www {
+                path = "/usr/jails/$name";
+                exec.prestart = "logger -t $name starting";
+                exec.poststart = "echo ${greeting} > /var/log/${name}.log";
+                exec.stop = "/bin/sh /etc/rc.shutdown; $user";
+                exec.poststop += '$literal; rm -rf $path';
+                exec.release = "cleanup ${token}";
+            }
+            "#);
+
+        let config = JailConfig::parse(input).unwrap();
+        let lints: Vec<String> = config.audit_exec()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(lints, vec![
+            "info[exec-metachar]: www: exec.poststart is run by sh(1), which interprets `>` in it",
+            "warning[exec-interpolation]: www: exec.poststart interpolates $greeting into a command run by sh(1), and its value may not be a single plain word",
+            "info[exec-metachar]: www: exec.stop is run by sh(1), which interprets `;` in it",
+            "warning[exec-interpolation]: www: exec.stop interpolates $user into a command run by sh(1), and its value may not be a single plain word",
+            "info[exec-metachar]: www: exec.poststop is run by sh(1), which interprets `;` in it",
+            "warning[exec-unresolved]: www: exec.release refers to $token, which the configuration doesn't define",
+        ]);
+    }
+
+    #[test]
+    fn test_audit_exec_spans() {
+        let input = "exec.start = \"sh /etc/rc; $cmd\";\nwww {\n    persist;\n}\ndb {\n    $cmd = date;\n}\n";
+        let config = JailConfig::parse(input).unwrap();
+        let lints = config.audit_exec();
+
+        let found: Vec<(&str, Option<&str>, usize)> = lints.iter()
+            .map(|lint| (lint.code(), lint.jail(), lint.span().start().line()))
+            .collect();
+
+        assert_eq!(found, vec![
+            ("exec-metachar", Some("www"), 1),
+            ("exec-unresolved", Some("www"), 1),
+            ("exec-metachar", Some("db"), 1),
+        ]);
+    }
+}
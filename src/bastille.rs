@@ -0,0 +1,347 @@
+// Import of BastilleBSD templates.
+//
+// Bastille creates each jail with a block of its own in jail.conf, and then
+// applies templates to it. A template's Bastillefile is a list of
+// instructions, most of which act within the running jail, installing
+// packages, copying files or running commands. A few change the jail's
+// configuration instead:
+//
+//   ARG DOMAIN=example.org
+//   CONFIG set host.hostname www.${DOMAIN}
+//   CONFIG set allow.raw_sockets 1
+//   CONFIG remove securelevel
+//   MOUNT /usr/ports usr/ports nullfs ro 0 0
+//
+// from_bastille_template starts from the block Bastille writes for a new
+// jail and applies those instructions to it: CONFIG sets and removes
+// parameters, and MOUNT becomes a `mount` parameter, the fstab(5) entry
+// jail(8) mounts as the jail is created. `${NAME}` is replaced with the
+// value of the ARG of that name, or the jail's name for `${JAIL_NAME}`.
+// Instructions acting within the jail are skipped, and those with an effect
+// outside of it jail.conf can't express, such as LIMITS, are logged.
+use std::error;
+use std::fmt;
+use tracing::warn;
+use crate::{
+    JailBlock,
+    JailBlockBuilder,
+    TemplateContext,
+};
+
+// Where Bastille keeps its jails, by default.
+const PREFIX: &str = "/usr/local/bastille";
+
+// Instructions which act within the jail, and so have no effect on its
+// configuration.
+const IN_JAIL: &[&str] = &[
+    "CMD",
+    "CP",
+    "OVERLAY",
+    "PKG",
+    "RENDER",
+    "RESTART",
+    "SERVICE",
+    "SYSRC",
+    "TAGS",
+];
+
+// Instructions with effects outside of jail.conf.
+const UNSUPPORTED: &[&str] = &["INCLUDE", "LIMITS", "RDR"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BastilleError {
+    /// An instruction is missing its arguments, or has the wrong ones.
+    Syntax {
+        line: usize,
+        text: String,
+    },
+    /// An instruction Bastille doesn't have.
+    UnknownInstruction {
+        line:        usize,
+        instruction: String,
+    },
+    /// An ARG without a default which wasn't given a value.
+    MissingArg {
+        line: usize,
+        name: String,
+    },
+}
+
+impl fmt::Display for BastilleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BastilleError::Syntax { line, text } => {
+                write!(f, "line {}: invalid instruction: {}", line, text)
+            },
+            BastilleError::UnknownInstruction { line, instruction } => {
+                write!(f, "line {}: unknown instruction {}", line, instruction)
+            },
+            BastilleError::MissingArg { line, name } => {
+                write!(f, "line {}: no value for ARG {}", line, name)
+            },
+        }
+    }
+}
+
+impl error::Error for BastilleError {}
+
+// The parameters Bastille gives a new jail, in the order it writes them.
+fn defaults(name: &str) -> Vec<(String, Option<String>)> {
+    let root = format!("{}/jails/{}", PREFIX, name);
+
+    [
+        ("devfs_ruleset",   Some("4".to_string())),
+        ("enforce_statfs",  Some("2".to_string())),
+        ("exec.clean",      None),
+        ("exec.consolelog", Some(format!("/var/log/bastille/{}_console.log", name))),
+        ("exec.start",      Some("/bin/sh /etc/rc".to_string())),
+        ("exec.stop",       Some("/bin/sh /etc/rc.shutdown".to_string())),
+        ("host.hostname",   Some(name.to_string())),
+        ("mount.devfs",     None),
+        ("mount.fstab",     Some(format!("{}/fstab", root))),
+        ("path",            Some(format!("{}/root", root))),
+        ("securelevel",     Some("2".to_string())),
+    ]
+    .iter()
+    .map(|(param, value)| (param.to_string(), value.clone()))
+    .collect()
+}
+
+// Remove a pair of quotes surrounding value.
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value.strip_prefix(quote).and_then(|v| v.strip_suffix(quote)) {
+            return inner;
+        }
+    }
+
+    value
+}
+
+// Replace each `${NAME}` in text with the ARG's value. References to
+// anything which isn't an ARG are left as they are.
+fn substitute(
+    text: &str,
+    args: &[(String, Option<String>)],
+    line: usize,
+) -> Result<String, BastilleError> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None      => break,
+        };
+        let name = &rest[start + 2..end];
+
+        out.push_str(&rest[..start]);
+
+        match args.iter().rev().find(|(arg, _)| arg == name) {
+            Some((_, Some(value))) => out.push_str(value),
+            Some((_, None))        => {
+                return Err(BastilleError::MissingArg {
+                    line,
+                    name: name.to_string(),
+                });
+            },
+            None => out.push_str(&rest[start..=end]),
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Build the jail block Bastille would give a new jail of the given name
+/// once the template in a Bastillefile is applied to it. Values for the
+/// template's ARGs are taken from args, falling back to the defaults the
+/// template gives.
+pub fn from_bastille_template<C>(
+    name: &str,
+    template: &str,
+    args: &C,
+) -> Result<JailBlock<'static>, BastilleError>
+where
+    C: TemplateContext + ?Sized,
+{
+    let mut params = defaults(name);
+    let mut mounts: Vec<String> = Vec::new();
+    let mut known = vec![("JAIL_NAME".to_string(), Some(name.to_string()))];
+
+    for (number, text) in template.lines().enumerate() {
+        let line = number + 1;
+        let text = text.trim();
+
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        let text = substitute(text, &known, line)?;
+        let syntax = || BastilleError::Syntax {
+            line,
+            text: text.clone(),
+        };
+
+        let (instruction, rest) = match text.split_once(char::is_whitespace) {
+            Some((instruction, rest)) => (instruction, rest.trim()),
+            None                      => (text.as_str(), ""),
+        };
+
+        match instruction.to_ascii_uppercase().as_str() {
+            "ARG" => {
+                let (arg, default) = match rest.split_once('=') {
+                    Some((arg, default)) => (arg.trim(), Some(unquote(default.trim()))),
+                    None                 => (rest, None),
+                };
+
+                if arg.is_empty() {
+                    return Err(syntax());
+                }
+
+                let value = args.value(arg).or_else(|| default.map(String::from));
+
+                known.push((arg.to_string(), value));
+            },
+            "CONFIG" => {
+                let mut words = rest.splitn(2, char::is_whitespace);
+                let action = words.next().unwrap_or_default();
+                let setting = words.next().unwrap_or_default().trim();
+
+                // Bastille takes both `set name value` and `set name=value`.
+                let (param, value) = match setting.split_once(|c: char| c == '=' || c.is_whitespace()) {
+                    Some((param, value)) => (param, Some(unquote(value.trim()).to_string())),
+                    None                 => (setting, None),
+                };
+
+                if param.is_empty() {
+                    return Err(syntax());
+                }
+
+                let existing = params.iter().position(|(name, _)| name == param);
+
+                match (action, existing) {
+                    ("set", Some(index)) => params[index].1 = value,
+                    ("set", None)        => params.push((param.to_string(), value)),
+                    ("remove", Some(index)) if value.is_none() => {
+                        params.remove(index);
+                    },
+                    ("remove", None) if value.is_none() => {},
+                    _ => return Err(syntax()),
+                }
+            },
+            "MOUNT" => {
+                let fields: Vec<&str> = rest.split_whitespace().collect();
+
+                if fields.len() < 4 || fields.len() > 6 {
+                    return Err(syntax());
+                }
+
+                // The mount point is given within the jail.
+                let root = format!("{}/jails/{}/root", PREFIX, name);
+                let target = format!("{}/{}", root, fields[1].trim_start_matches('/'));
+                let mut entry = vec![fields[0], &target];
+
+                entry.extend(&fields[2..]);
+                mounts.push(entry.join(" "));
+            },
+            instruction if UNSUPPORTED.contains(&instruction) => {
+                warn!("line {}: {} has no jail.conf equivalent, ignoring it", line, instruction);
+            },
+            instruction if IN_JAIL.contains(&instruction) => {},
+            _ => {
+                return Err(BastilleError::UnknownInstruction {
+                    line,
+                    instruction: instruction.to_string(),
+                });
+            },
+        }
+    }
+
+    let mut block = JailBlockBuilder::new(name);
+
+    for (param, value) in &params {
+        block = match value {
+            Some(value) => block.param(param, value),
+            None        => block.flag(param),
+        };
+    }
+
+    for (i, mount) in mounts.iter().enumerate() {
+        block = if i == 0 {
+            block.param("mount", mount)
+        }
+        else {
+            block.append("mount", mount)
+        };
+    }
+
+    Ok(block.build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use indoc::indoc;
+
+    const TEMPLATE: &str = indoc!(r#"
+        # Web server
+        ARG DOMAIN=example.org
+        ARG ADDR
+        PKG nginx
+        SYSRC nginx_enable=YES
+        CONFIG set host.hostname www.${DOMAIN}
+        CONFIG set ip4.addr=${ADDR}
+        CONFIG set allow.raw_sockets
+        CONFIG remove securelevel
+        MOUNT /usr/ports /usr/ports nullfs ro 0 0
+        MOUNT /data/${JAIL_NAME} var/db nullfs rw
+        LIMITS memoryuse 1G
+        SERVICE nginx start
+        "#);
+
+    #[test]
+    fn test_from_bastille_template() {
+        let args: HashMap<&str, &str> = [("ADDR", "192.0.2.10")].iter().cloned().collect();
+        let block = from_bastille_template("www", TEMPLATE, &args).unwrap();
+
+        assert_eq!(block.to_string(), indoc!(r#"
+            www {
+                devfs_ruleset = 4;
+                enforce_statfs = 2;
+                exec.clean;
+                exec.consolelog = /var/log/bastille/www_console.log;
+                exec.start = "/bin/sh /etc/rc";
+                exec.stop = "/bin/sh /etc/rc.shutdown";
+                host.hostname = www.example.org;
+                mount.devfs;
+                mount.fstab = /usr/local/bastille/jails/www/fstab;
+                path = /usr/local/bastille/jails/www/root;
+                ip4.addr = 192.0.2.10;
+                allow.raw_sockets;
+                mount = "/usr/ports /usr/local/bastille/jails/www/root/usr/ports nullfs ro 0 0";
+                mount += "/data/www /usr/local/bastille/jails/www/root/var/db nullfs rw";
+            }"#));
+    }
+
+    #[test]
+    fn test_bastille_template_errors() {
+        let args: HashMap<&str, &str> = HashMap::new();
+
+        assert_eq!(
+            from_bastille_template("www", TEMPLATE, &args).unwrap_err(),
+            BastilleError::MissingArg { line: 7, name: "ADDR".to_string() },
+        );
+        assert_eq!(
+            from_bastille_template("www", "CONFIG get path\n", &args).unwrap_err(),
+            BastilleError::Syntax { line: 1, text: "CONFIG get path".to_string() },
+        );
+        assert_eq!(
+            from_bastille_template("www", "\nFROM base\n", &args).unwrap_err(),
+            BastilleError::UnknownInstruction { line: 2, instruction: "FROM".to_string() },
+        );
+    }
+}
@@ -0,0 +1,321 @@
+// The language features: diagnostics, hover, definitions and formatting.
+//
+// Each works on the whole text of a document, which is small enough that it
+// can be parsed again for every request, and returns its result as the JSON
+// the protocol expects.
+use jailconf::schema;
+use jailconf::{
+    format_config,
+    lex,
+    parse,
+    parse_lenient,
+    walk,
+    FormatOptions,
+    JailConfig,
+    JailVariable,
+    Severity,
+    Span,
+    Token,
+    Visitor,
+};
+use serde_json::{
+    json,
+    Value,
+};
+use crate::position::{
+    position,
+    range,
+};
+
+// Diagnostic severities.
+const ERROR: u8 = 1;
+const WARNING: u8 = 2;
+const INFORMATION: u8 = 3;
+
+fn diagnostic(text: &str, span: Span, severity: u8, code: Option<&str>, message: String)
+-> Value {
+    let mut diagnostic = json!({
+        "range":    range(text, span.range()),
+        "severity": severity,
+        "source":   "jailconf",
+        "message":  message,
+    });
+
+    if let Some(code) = code {
+        diagnostic["code"] = json!(code);
+    }
+
+    diagnostic
+}
+
+/// Syntax errors, invalid parameters, lints and warnings for a document, as
+/// `jailconf check` reports them.
+pub fn diagnostics(text: &str) -> Vec<Value> {
+    let (statements, errors) = parse_lenient(text);
+
+    let mut diagnostics: Vec<Value> = errors.iter()
+        .map(|error| {
+            let message = format!("expected {}", error.expected());
+
+            diagnostic(text, error.span(), ERROR, None, message)
+        })
+        .collect();
+
+    // Validation of a file with syntax errors would only report on whatever
+    // could be recovered from it.
+    if !diagnostics.is_empty() {
+        return diagnostics;
+    }
+
+    let config = JailConfig::new(statements);
+
+    for error in config.validate() {
+        diagnostics.push(diagnostic(text, error.span(), ERROR, None, error.to_string()));
+    }
+
+    for lint in config.lint() {
+        let severity = match lint.severity() {
+            Severity::Info    => INFORMATION,
+            Severity::Warning => WARNING,
+            Severity::Error   => ERROR,
+        };
+
+        let message = match lint.jail() {
+            Some(jail) => format!("{}: {}", jail, lint.message()),
+            None       => lint.message().to_string(),
+        };
+
+        diagnostics.push(diagnostic(text, lint.span(), severity, Some(lint.code()), message));
+    }
+
+    for warning in config.warnings() {
+        let code = warning.kind().code();
+        let message = match warning.jail() {
+            Some(jail) => format!("{}: {}", jail, warning.message()),
+            None       => warning.message().to_string(),
+        };
+
+        diagnostics.push(diagnostic(text, warning.span(), WARNING, Some(code), message));
+    }
+
+    diagnostics
+}
+
+// The index of the word or string token at offset, within the tokens. A
+// cursor just after a token is taken to be on it.
+fn token_at(tokens: &[(Token, Span)], offset: usize) -> Option<usize> {
+    tokens.iter().position(|(token, span)| {
+        let range = span.range();
+
+        matches!(token, Token::Word(_) | Token::String(_))
+            && range.start <= offset
+            && offset <= range.end
+    })
+}
+
+/// Information about the parameter under the cursor, from the schema.
+pub fn hover(text: &str, offset: usize) -> Option<Value> {
+    let tokens = lex(text);
+    let (token, span) = tokens[token_at(&tokens, offset)?];
+
+    let name = match token {
+        Token::Word(name) => name,
+        _                 => return None,
+    };
+
+    let param = schema::parameter(name)?;
+    let mut contents = format!("**{}**: {}", param.name(), param.kind().description());
+
+    if let Some(default) = param.default() {
+        contents.push_str(&format!(", default `{}`", default));
+    }
+
+    contents.push_str(&format!("\n\n{}", param.description()));
+    contents.push_str(&format!("\n\nAvailable since FreeBSD {}.", param.since()));
+
+    Some(json!({
+        "contents": {"kind": "markdown", "value": contents},
+        "range":    range(text, span.range()),
+    }))
+}
+
+// The name of the variable referenced at offset within a word starting at
+// start, eg. `$base` or `${base}`.
+fn reference_at(word: &str, start: usize, offset: usize) -> Option<&str> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    for (i, _) in word.match_indices('$') {
+        let after = &word[i + 1..];
+
+        let (name, len) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(close) => (&braced[..close], close + 3),
+                None        => continue,
+            }
+        }
+        else {
+            let len = after.find(|c: char| !is_name_char(c)).unwrap_or(after.len());
+
+            (&after[..len], len + 1)
+        };
+
+        if !name.is_empty() && start + i <= offset && offset <= start + i + len {
+            return Some(name);
+        }
+    }
+
+    None
+}
+
+// Whether the token at index is part of the value of a `depend` parameter.
+fn in_depend(tokens: &[(Token, Span)], index: usize) -> bool {
+    let mut before = tokens[..index].iter()
+        .rev()
+        .map(|(token, _)| token)
+        .filter(|token| !matches!(token, Token::Whitespace(_) | Token::Comment(_)));
+
+    for token in &mut before {
+        match token {
+            Token::Word(_) | Token::String(_) | Token::Comma => {},
+            Token::Assign | Token::Append => {
+                return before.next() == Some(&Token::Word("depend"));
+            },
+            _ => return false,
+        }
+    }
+
+    false
+}
+
+// Collects the spans of variable definitions.
+struct Definitions<'n> {
+    name:  &'n str,
+    spans: Vec<Span>,
+}
+
+impl<'a> Visitor<'a> for Definitions<'_> {
+    fn visit_variable(&mut self, var: &JailVariable<'a>) {
+        if var.name() == self.name {
+            self.spans.push(var.span());
+        }
+    }
+}
+
+/// The definition of the variable or `depend` target under the cursor: the
+/// last definition of a variable before it, or the block for a jail.
+pub fn definition(uri: &str, text: &str, offset: usize) -> Option<Value> {
+    let tokens = lex(text);
+    let index = token_at(&tokens, offset)?;
+    let (token, span) = tokens[index];
+    let (statements, _) = parse_lenient(text);
+
+    let location = |span: Span| json!({"uri": uri, "range": range(text, span.range())});
+
+    let (word, start) = match token {
+        Token::Word(word) => (word, span.start().offset()),
+        Token::String(string) => (&string[1..string.len() - 1], span.start().offset() + 1),
+        _ => return None,
+    };
+
+    if let Some(name) = reference_at(word, start, offset) {
+        let mut definitions = Definitions { name, spans: Vec::new() };
+
+        walk(&statements, &mut definitions);
+
+        let before = definitions.spans.iter()
+            .rev()
+            .find(|span| span.start().offset() < offset);
+
+        return before.or_else(|| definitions.spans.first())
+            .map(|span| location(*span));
+    }
+
+    if in_depend(&tokens, index) {
+        let config = JailConfig::new(statements);
+
+        return config.jail(word).map(|block| location(block.span()));
+    }
+
+    None
+}
+
+/// Edits formatting a document, or None if it doesn't parse.
+pub fn formatting(text: &str, options: &Value) -> Option<Vec<Value>> {
+    let statements = parse(text).ok()?;
+    let mut format = FormatOptions::new();
+
+    if let Some(size) = options["tabSize"].as_u64() {
+        format = format.indent(size as usize);
+    }
+
+    if let Some(spaces) = options["insertSpaces"].as_bool() {
+        format = format.tabs(!spaces);
+    }
+
+    let formatted = format_config(&statements, &format);
+
+    if formatted == text {
+        return Some(Vec::new());
+    }
+
+    Some(vec![json!({
+        "range":   {"start": position(text, 0), "end": position(text, text.len())},
+        "newText": formatted,
+    })])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = "$base = /jails;\nwww {\n    path = \"$base/www\";\n}\ndb {\n    depend = www;\n    persist = maybe;\n}\n";
+
+    fn offset_of(needle: &str) -> usize {
+        INPUT.find(needle).unwrap()
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let diagnostics = diagnostics(INPUT);
+
+        assert!(diagnostics.iter().any(|d| {
+            d["severity"] == json!(ERROR)
+                && d["message"].as_str().unwrap().contains("persist")
+        }));
+
+        let diagnostics = super::diagnostics("www {\n    = a;\n}\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["range"]["start"]["line"], json!(1));
+    }
+
+    #[test]
+    fn test_hover() {
+        let hover = hover(INPUT, offset_of("persist") + 2).unwrap();
+        let value = hover["contents"]["value"].as_str().unwrap();
+
+        assert!(value.starts_with("**persist**: a boolean, default `false`"));
+        assert!(super::hover(INPUT, offset_of("www {")).is_none());
+    }
+
+    #[test]
+    fn test_definition() {
+        let variable = definition("file:///j.conf", INPUT, offset_of("$base/") + 2).unwrap();
+        assert_eq!(variable["range"]["start"], json!({"line": 0, "character": 0}));
+
+        let jail = definition("file:///j.conf", INPUT, offset_of("www;")).unwrap();
+        assert_eq!(jail["uri"], json!("file:///j.conf"));
+        assert_eq!(jail["range"]["start"], json!({"line": 1, "character": 0}));
+
+        assert!(definition("file:///j.conf", INPUT, offset_of("maybe")).is_none());
+    }
+
+    #[test]
+    fn test_formatting() {
+        let options = json!({"tabSize": 2, "insertSpaces": true});
+        let edits = formatting("www{persist;}", &options).unwrap();
+
+        assert_eq!(edits[0]["newText"], json!("www {\n  persist;\n}\n"));
+        assert_eq!(formatting("www {\n  persist;\n}\n", &options), Some(Vec::new()));
+        assert!(formatting("www {", &options).is_none());
+    }
+}
@@ -0,0 +1,53 @@
+// jailconf-lsp: a Language Server Protocol server for jail.conf.
+//
+// The server speaks the protocol over stdin and stdout, providing
+// diagnostics from the validator and linter, hover information from the
+// parameter schema, go to definition for variables and `depend` targets,
+// and formatting.
+use std::io::{
+    self,
+    BufWriter,
+};
+use std::process;
+
+mod analysis;
+mod position;
+mod server;
+mod transport;
+
+use crate::server::Server;
+use crate::transport::{
+    read_message,
+    write_message,
+};
+
+fn run(server: &mut Server) -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut output = BufWriter::new(io::stdout());
+
+    while let Some(message) = read_message(&mut input)? {
+        for reply in server.handle(&message) {
+            write_message(&mut output, &reply)?;
+        }
+
+        if server.exit_code().is_some() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let mut server = Server::new();
+
+    if let Err(e) = run(&mut server) {
+        eprintln!("jailconf-lsp: {}", e);
+        process::exit(1);
+    }
+
+    // Input ending without an exit notification is treated as an exit
+    // without a shutdown.
+    process::exit(server.exit_code().unwrap_or(1));
+}
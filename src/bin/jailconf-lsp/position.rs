@@ -0,0 +1,81 @@
+// Conversion between byte offsets and LSP positions.
+//
+// The parser gives spans as byte offsets, while LSP positions are a line
+// counted from zero and a character within it counted in UTF-16 code units.
+use std::ops::Range;
+use serde_json::{
+    json,
+    Value,
+};
+
+/// The position of a byte offset within text.
+pub fn position(text: &str, offset: usize) -> Value {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    let line = before.matches('\n').count();
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+
+    json!({"line": line, "character": character})
+}
+
+/// The range covering byte offsets within text.
+pub fn range(text: &str, range: Range<usize>) -> Value {
+    json!({
+        "start": position(text, range.start),
+        "end":   position(text, range.end),
+    })
+}
+
+/// The byte offset of an LSP position within text. Positions past the end of
+/// a line are taken to be its end.
+pub fn offset(text: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap_or(0) as usize;
+    let character = position["character"].as_u64().unwrap_or(0) as usize;
+
+    let line_start = match line {
+        0    => 0,
+        line => match text.match_indices('\n').nth(line - 1) {
+            Some((newline, _)) => newline + 1,
+            None               => return text.len(),
+        },
+    };
+
+    let mut units = 0;
+
+    for (i, c) in text[line_start..].char_indices() {
+        if c == '\n' || units >= character {
+            return line_start + i;
+        }
+
+        units += c.len_utf16();
+    }
+
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position() {
+        let text = "a;\n\u{1f600}b = c;\n";
+
+        assert_eq!(position(text, 0), json!({"line": 0, "character": 0}));
+        assert_eq!(position(text, 3), json!({"line": 1, "character": 0}));
+        assert_eq!(position(text, 7), json!({"line": 1, "character": 2}));
+        assert_eq!(position(text, text.len()), json!({"line": 2, "character": 0}));
+    }
+
+    #[test]
+    fn test_offset() {
+        let text = "a;\n\u{1f600}b = c;\n";
+
+        for offset in &[0, 3, 7, 9, text.len()] {
+            assert_eq!(super::offset(text, &position(text, *offset)), *offset);
+        }
+
+        assert_eq!(super::offset(text, &json!({"line": 0, "character": 9})), 2);
+        assert_eq!(super::offset(text, &json!({"line": 5, "character": 0})), text.len());
+    }
+}
@@ -0,0 +1,209 @@
+// Dispatch of protocol messages.
+//
+// The server keeps the text of each open document, as sent in full by the
+// client on every change, and answers requests from it. Diagnostics are
+// published whenever a document is opened or changed.
+use std::collections::HashMap;
+use serde_json::{
+    json,
+    Value,
+};
+use crate::analysis;
+use crate::position::offset;
+
+// JSON-RPC error codes.
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+fn response(id: Value, result: Value) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "result": result})
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "method": method, "params": params})
+}
+
+#[derive(Debug, Default)]
+pub struct Server {
+    documents: HashMap<String, String>,
+    shutdown:  bool,
+    exited:    bool,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The process exit status once the client has asked the server to exit:
+    /// success only if it asked for a shutdown first.
+    pub fn exit_code(&self) -> Option<i32> {
+        match (self.exited, self.shutdown) {
+            (false, _)    => None,
+            (true, true)  => Some(0),
+            (true, false) => Some(1),
+        }
+    }
+
+    fn publish(&self, uri: &str) -> Value {
+        let diagnostics = self.documents.get(uri)
+            .map_or_else(Vec::new, |text| analysis::diagnostics(text));
+
+        notification("textDocument/publishDiagnostics", json!({
+            "uri":         uri,
+            "diagnostics": diagnostics,
+        }))
+    }
+
+    // The result of a request, or None for a method the server doesn't
+    // provide.
+    fn request(&mut self, method: &str, params: &Value) -> Option<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let text = self.documents.get(uri).map(String::as_str);
+        let cursor = text.map(|text| offset(text, &params["position"]));
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync":           1,
+                    "hoverProvider":              true,
+                    "definitionProvider":         true,
+                    "documentFormattingProvider": true,
+                },
+                "serverInfo": {
+                    "name":    "jailconf-lsp",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            }),
+            "shutdown" => {
+                self.shutdown = true;
+                Value::Null
+            },
+            "textDocument/hover" => text.zip(cursor)
+                .and_then(|(text, cursor)| analysis::hover(text, cursor))
+                .unwrap_or(Value::Null),
+            "textDocument/definition" => text.zip(cursor)
+                .and_then(|(text, cursor)| analysis::definition(uri, text, cursor))
+                .unwrap_or(Value::Null),
+            "textDocument/formatting" => text
+                .and_then(|text| analysis::formatting(text, &params["options"]))
+                .map_or(Value::Null, |edits| json!(edits)),
+            _ => return None,
+        };
+
+        Some(result)
+    }
+
+    // Handle a notification, returning any notifications to send back.
+    fn notify(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let document = &params["textDocument"];
+        let uri = document["uri"].as_str().unwrap_or_default().to_string();
+
+        match method {
+            "exit" => {
+                self.exited = true;
+                Vec::new()
+            },
+            "textDocument/didOpen" => {
+                let text = document["text"].as_str().unwrap_or_default();
+
+                self.documents.insert(uri.clone(), text.to_string());
+                vec![self.publish(&uri)]
+            },
+            "textDocument/didChange" => {
+                // Changes are always the full text, as asked for by
+                // textDocumentSync.
+                let changes = params["contentChanges"].as_array();
+                let text = changes.and_then(|changes| changes.last()?["text"].as_str());
+
+                match text {
+                    Some(text) => {
+                        self.documents.insert(uri.clone(), text.to_string());
+                        vec![self.publish(&uri)]
+                    },
+                    None => Vec::new(),
+                }
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![self.publish(&uri)]
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    /// Handle a message from the client, returning the messages to send in
+    /// reply.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = match message["method"].as_str() {
+            Some(method) => method,
+            // Responses to requests from the server, which makes none.
+            None         => return Vec::new(),
+        };
+
+        let params = &message["params"];
+
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None     => return self.notify(method, params),
+        };
+
+        if self.shutdown {
+            return vec![error(id, INVALID_REQUEST, "the server is shutting down")];
+        }
+
+        match self.request(method, params) {
+            Some(result) => vec![response(id, result)],
+            None         => vec![error(id, METHOD_NOT_FOUND, "method not found")],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+    }
+
+    #[test]
+    fn test_session() {
+        let mut server = Server::new();
+        let uri = "file:///etc/jail.conf";
+
+        let replies = server.handle(&request(1, "initialize", json!({})));
+        assert_eq!(replies[0]["result"]["capabilities"]["hoverProvider"], json!(true));
+
+        let replies = server.handle(&notification("textDocument/didOpen", json!({
+            "textDocument": {"uri": uri, "text": "www { persist = maybe; }\n"},
+        })));
+        assert_eq!(replies[0]["method"], json!("textDocument/publishDiagnostics"));
+        assert_eq!(replies[0]["params"]["diagnostics"].as_array().unwrap().len(), 1);
+
+        let replies = server.handle(&notification("textDocument/didChange", json!({
+            "textDocument":   {"uri": uri},
+            "contentChanges": [{"text": "www { persist; }\n"}],
+        })));
+        assert_eq!(replies[0]["params"]["diagnostics"], json!([]));
+
+        let replies = server.handle(&request(2, "textDocument/hover", json!({
+            "textDocument": {"uri": uri},
+            "position":     {"line": 0, "character": 8},
+        })));
+        assert!(replies[0]["result"]["contents"]["value"].is_string());
+
+        let replies = server.handle(&request(3, "textDocument/unknown", json!({})));
+        assert_eq!(replies[0]["error"]["code"], json!(METHOD_NOT_FOUND));
+
+        server.handle(&request(4, "shutdown", json!(null)));
+        assert_eq!(server.exit_code(), None);
+
+        server.handle(&notification("exit", json!(null)));
+        assert_eq!(server.exit_code(), Some(0));
+    }
+}
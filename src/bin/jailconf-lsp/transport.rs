@@ -0,0 +1,84 @@
+// Reading and writing of Language Server Protocol messages.
+//
+// Each message is a JSON-RPC object preceded by headers, of which only
+// Content-Length matters, and a blank line.
+use std::io::{
+    self,
+    BufRead,
+    Write,
+};
+use serde_json::Value;
+
+fn invalid<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Read the next message, or None at the end of input.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+
+    loop {
+        let mut line = String::new();
+
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = Some(value.trim().parse::<usize>().map_err(invalid)?);
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| invalid("missing Content-Length header"))?;
+    let mut body = vec![0; length];
+
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(invalid)
+}
+
+/// Write a message with its headers.
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_round_trip() {
+        let message = json!({"jsonrpc": "2.0", "method": "exit"});
+        let mut written = Vec::new();
+
+        write_message(&mut written, &message).unwrap();
+        write_message(&mut written, &message).unwrap();
+
+        let mut reader = &written[..];
+
+        assert_eq!(read_message(&mut reader).unwrap(), Some(message.clone()));
+        assert_eq!(read_message(&mut reader).unwrap(), Some(message));
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_missing_length() {
+        let mut reader = &b"Content-Type: x\r\n\r\n{}"[..];
+
+        assert!(read_message(&mut reader).is_err());
+    }
+}
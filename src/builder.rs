@@ -0,0 +1,319 @@
+// Construction of configuration from code.
+//
+// JailConfigBuilder and JailBlockBuilder build up a configuration one
+// statement at a time, for tools which generate jail.conf rather than parse
+// it. Values are quoted when they need to be, so callers can pass them as
+// plain strings. Every node built has source text matching its rendered
+// form, as though it had been parsed from the output.
+use std::borrow::Cow;
+use crate::escape::quote;
+use crate::{
+    is_name_char,
+    Annotations,
+    CommentStyle,
+    JailBlock,
+    JailComment,
+    JailConf,
+    JailConfig,
+    JailParamBool,
+    JailParamValue,
+    JailVariable,
+    QuoteStyle,
+    Span,
+};
+
+// Characters which may appear in a value without quoting it.
+fn is_bare_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "._-/:,@%+".contains(c)
+}
+
+// Render a value, quoting it if it's empty or contains anything which could
+// be mistaken for syntax. jail(8) reads a bare word starting with `//` as a
+// comment, so list items starting with it are quoted too.
+pub(crate) fn raw_value(value: &str) -> String {
+    let comment = value.split(',').any(|item| item.starts_with("//"));
+
+    if !value.is_empty() && value.chars().all(is_bare_char) && !comment {
+        value.to_string()
+    }
+    else {
+        quote(value)
+    }
+}
+
+pub(crate) fn param_bool(name: &str) -> JailConf<'static> {
+    JailConf::ParamBool(JailParamBool {
+        name:   Cow::Owned(name.to_string()),
+        source: Cow::Owned(format!("{};", name)),
+        span:   Span::default(),
+    })
+}
+
+pub(crate) fn param_value(name: &str, value: &str, append: bool) -> JailConf<'static> {
+    let raw = raw_value(value);
+    let op = if append { "+=" } else { "=" };
+
+    JailConf::ParamValue(JailParamValue {
+        name:   Cow::Owned(name.to_string()),
+        value:  Cow::Owned(value.to_string()),
+        source: Cow::Owned(format!("{} {} {};", name, op, raw)),
+        span:   Span::default(),
+        raw:    Cow::Owned(raw),
+        append,
+    })
+}
+
+pub(crate) fn variable(name: &str, value: &str, append: bool) -> JailConf<'static> {
+    let raw = raw_value(value);
+    let op = if append { "+=" } else { "=" };
+
+    JailConf::Variable(JailVariable {
+        name:   Cow::Owned(name.to_string()),
+        value:  Cow::Owned(value.to_string()),
+        source: Cow::Owned(format!("${} {} {};", name, op, raw)),
+        span:   Span::default(),
+        raw:    Cow::Owned(raw),
+        append,
+    })
+}
+
+// How a jail name must be quoted, names which can't be written bare are
+// quoted. A backslash in a bare name starts an escape, and a name starting
+// with comment markers would be read as a comment.
+pub(crate) fn name_quote(name: &str) -> QuoteStyle {
+    let bare = !name.is_empty()
+        && name.chars().all(|c| is_name_char(c) && c != '\\')
+        && !name.starts_with("//")
+        && !name.starts_with("/*");
+
+    if bare {
+        QuoteStyle::None
+    }
+    else {
+        QuoteStyle::Double
+    }
+}
+
+// One shell style comment per line of text.
+fn comments(text: &str) -> impl Iterator<Item = JailConf<'static>> + '_ {
+    text.lines().map(|line| {
+        let comment = if line.is_empty() {
+            String::new()
+        }
+        else {
+            format!(" {}", line)
+        };
+
+        JailConf::Comment(JailComment {
+            source:  Cow::Owned(format!("#{}", comment)),
+            span:    Span::default(),
+            comment: Cow::Owned(comment),
+            style:   CommentStyle::Shell,
+        })
+    })
+}
+
+// A block holding the given statements, quoting its name if need be.
+pub(crate) fn block(name: String, params: Vec<JailConf<'static>>) -> JailBlock<'static> {
+    let mut block = JailBlock {
+        quote:       name_quote(&name),
+        name:        Cow::Owned(name),
+        params,
+        annotations: Annotations::new(),
+        source:      Cow::Borrowed(""),
+        span:        Span::default(),
+    };
+
+    block.source = Cow::Owned(block.to_string());
+    block
+}
+
+/// Builds a jail block.
+#[derive(Clone, Debug)]
+pub struct JailBlockBuilder {
+    name:   String,
+    params: Vec<JailConf<'static>>,
+}
+
+impl JailBlockBuilder {
+    /// Start building a block for the named jail.
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self {
+            name:   name.into(),
+            params: Vec::new(),
+        }
+    }
+
+    /// Set a parameter with `=`.
+    pub fn param(mut self, name: &str, value: &str) -> Self {
+        self.params.push(param_value(name, value, false));
+        self
+    }
+
+    /// Add a value to a parameter with `+=`.
+    pub fn append(mut self, name: &str, value: &str) -> Self {
+        self.params.push(param_value(name, value, true));
+        self
+    }
+
+    /// Set a boolean parameter without a value, eg. `persist;`.
+    pub fn flag(mut self, name: &str) -> Self {
+        self.params.push(param_bool(name));
+        self
+    }
+
+    /// Define a variable, eg. `$base = "/usr/jails";`.
+    pub fn variable(mut self, name: &str, value: &str) -> Self {
+        self.params.push(variable(name, value, false));
+        self
+    }
+
+    /// Add a comment. Text with several lines becomes a comment per line.
+    pub fn comment(mut self, text: &str) -> Self {
+        self.params.extend(comments(text));
+        self
+    }
+
+    /// Add a nested block.
+    pub fn block(mut self, block: JailBlock<'static>) -> Self {
+        self.params.push(JailConf::Block(block));
+        self
+    }
+
+    /// Finish building the block.
+    pub fn build(self) -> JailBlock<'static> {
+        block(self.name, self.params)
+    }
+}
+
+/// Builds a whole configuration.
+#[derive(Clone, Debug, Default)]
+pub struct JailConfigBuilder {
+    statements: Vec<JailConf<'static>>,
+}
+
+impl JailConfigBuilder {
+    /// Start building an empty configuration.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set a global parameter with `=`.
+    pub fn param(mut self, name: &str, value: &str) -> Self {
+        self.statements.push(param_value(name, value, false));
+        self
+    }
+
+    /// Add a value to a global parameter with `+=`.
+    pub fn append(mut self, name: &str, value: &str) -> Self {
+        self.statements.push(param_value(name, value, true));
+        self
+    }
+
+    /// Set a global boolean parameter without a value, eg. `persist;`.
+    pub fn flag(mut self, name: &str) -> Self {
+        self.statements.push(param_bool(name));
+        self
+    }
+
+    /// Define a global variable, eg. `$base = "/usr/jails";`.
+    pub fn variable(mut self, name: &str, value: &str) -> Self {
+        self.statements.push(variable(name, value, false));
+        self
+    }
+
+    /// Add a comment. Text with several lines becomes a comment per line.
+    pub fn comment(mut self, text: &str) -> Self {
+        self.statements.extend(comments(text));
+        self
+    }
+
+    /// Add a jail block.
+    pub fn jail(mut self, block: JailBlock<'static>) -> Self {
+        self.statements.push(JailConf::Block(block));
+        self
+    }
+
+    /// Finish building the configuration.
+    pub fn build(self) -> JailConfig<'static> {
+        JailConfig::new(self.statements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use indoc::indoc;
+
+    #[test]
+    fn test_raw_value() {
+        assert_eq!(raw_value("0"), "0");
+        assert_eq!(raw_value("/usr/jails/nginx"), "/usr/jails/nginx");
+        assert_eq!(raw_value("lo1|127.0.1.1"), "\"lo1|127.0.1.1\"");
+        assert_eq!(raw_value("/bin/sh /etc/rc"), "\"/bin/sh /etc/rc\"");
+        assert_eq!(raw_value("$name"), "\"$name\"");
+        assert_eq!(raw_value(""), "\"\"");
+        assert_eq!(raw_value("a//b"), "a//b");
+        assert_eq!(raw_value("//srv/share"), "\"//srv/share\"");
+        assert_eq!(raw_value("a,//b"), "\"a,//b\"");
+        assert_eq!(raw_value("echo #1"), "\"echo #1\"");
+    }
+
+    #[test]
+    fn test_build_and_render() {
+        let config = JailConfigBuilder::new()
+            .comment("Generated by provisioning")
+            .flag("persist")
+            .param("path", "/usr/jails/$name")
+            .jail(
+                JailBlockBuilder::new("nginx")
+                    .param("host.hostname", "nginx.example.com")
+                    .param("ip4.addr", "lo1|127.0.1.1")
+                    .append("ip4.addr", "lo1|127.0.1.2")
+                    .param("exec.start", "/bin/sh /etc/rc")
+                    .build()
+            )
+            .build();
+
+        let expected = indoc!(r#"
+            # Generated by provisioning
+            persist;
+            path = "/usr/jails/$name";
+            nginx {
+                host.hostname = nginx.example.com;
+                ip4.addr = "lo1|127.0.1.1";
+                ip4.addr += "lo1|127.0.1.2";
+                exec.start = "/bin/sh /etc/rc";
+            }
+            "#);
+
+        let output = config.to_string();
+
+        assert_eq!(output, expected);
+
+        // Rendered output parses back to the same values.
+        let parsed = JailConfig::new(parse(&output).unwrap());
+        assert_eq!(parsed.get_values("nginx", "ip4.addr"), vec![
+            "lo1|127.0.1.1",
+            "lo1|127.0.1.2",
+        ]);
+        assert_eq!(parsed.get_param("nginx", "exec.start").as_deref(), Some("/bin/sh /etc/rc"));
+    }
+
+    #[test]
+    fn test_build_quoted_name() {
+        let block = JailBlockBuilder::new("my jail").flag("persist").build();
+
+        assert_eq!(block.name_quote_style(), QuoteStyle::Double);
+        assert_eq!(block.source_text(), "\"my jail\" {\n    persist;\n}");
+    }
+
+    #[test]
+    fn test_built_source_text() {
+        let block = JailBlockBuilder::new("nginx").flag("persist").build();
+
+        assert_eq!(block.source_text(), "nginx {\n    persist;\n}");
+        assert_eq!(block.params()[0].source_text(), "persist;");
+    }
+}
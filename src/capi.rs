@@ -0,0 +1,295 @@
+// C bindings.
+//
+// C tools can parse jail.conf with this crate rather than a parser of their
+// own. A configuration is parsed into an opaque JailconfConfig, which the
+// accessors read from and jailconf_free releases. The jails are those
+// configured, each once and leaving out wildcard blocks, and a parameter's
+// values are its effective ones, as `JailConfig::effective_params` gives
+// them, as written without variables expanded.
+//
+// Strings returned as `char *` are the caller's, to release with
+// jailconf_string_free. Those returned as `const char *` belong to the
+// configuration and last as long as it does. include/jailconf.h declares
+// all of this for C, and is generated by cbindgen with cbindgen.toml.
+use std::collections::HashMap;
+use std::ffi::{
+    CStr,
+    CString,
+};
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::OnceLock;
+use crate::config::EffectiveParams;
+use crate::{
+    into_owned,
+    parse,
+    JailConfig,
+};
+
+/// A parsed configuration.
+pub struct JailconfConfig {
+    config: JailConfig<'static>,
+    names:  Vec<CString>,
+    // The effective parameters of each jail, worked out the first time one
+    // of its parameters is asked for.
+    params: HashMap<String, OnceLock<EffectiveParams>>,
+}
+
+impl JailconfConfig {
+    fn params(&self, jail: *const c_char) -> Option<&EffectiveParams> {
+        // The caller guarantees jail is a valid string or NULL.
+        let jail = unsafe { to_str(jail) }?;
+        let params = self.params.get(jail)?;
+
+        Some(params.get_or_init(|| self.config.effective_params(jail).unwrap_or_default()))
+    }
+}
+
+// A string from C, None if it's NULL or not UTF-8.
+unsafe fn to_str<'s>(s: *const c_char) -> Option<&'s str> {
+    if s.is_null() {
+        return None;
+    }
+
+    CStr::from_ptr(s).to_str().ok()
+}
+
+// A string for C to release with jailconf_string_free, NULL if it holds a
+// NUL byte.
+fn to_c(s: &str) -> *mut c_char {
+    CString::new(s).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Parse a configuration. Returns NULL if it can't be parsed, in which case
+/// `*error`, if `error` isn't NULL, is set to a description of why, which
+/// the caller releases with `jailconf_string_free`.
+///
+/// # Safety
+///
+/// `input` must be a valid NUL terminated string, and `error` either NULL
+/// or valid to write a pointer to.
+#[no_mangle]
+pub unsafe extern "C" fn jailconf_parse(
+    input: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut JailconfConfig {
+    let fail = |message: &str| {
+        if !error.is_null() {
+            *error = to_c(message);
+        }
+
+        ptr::null_mut()
+    };
+
+    let input = match to_str(input) {
+        Some(input) => input,
+        None        => return fail("input is NULL or not UTF-8"),
+    };
+
+    let config = match parse(input) {
+        Ok(statements) => JailConfig::new(into_owned(statements)),
+        Err(e)         => return fail(&e.to_string()),
+    };
+
+    let jails = config.jail_names();
+
+    let names = jails.iter()
+        .filter_map(|name| CString::new(*name).ok())
+        .collect();
+
+    let params = jails.iter()
+        .map(|name| (name.to_string(), OnceLock::new()))
+        .collect();
+
+    Box::into_raw(Box::new(JailconfConfig {
+        config,
+        names,
+        params,
+    }))
+}
+
+/// Release a configuration returned by `jailconf_parse`. Does nothing if
+/// `config` is NULL.
+///
+/// # Safety
+///
+/// `config` must be NULL or a configuration from `jailconf_parse` which
+/// hasn't already been released.
+#[no_mangle]
+pub unsafe extern "C" fn jailconf_free(config: *mut JailconfConfig) {
+    if !config.is_null() {
+        drop(Box::from_raw(config));
+    }
+}
+
+/// The number of jails configured.
+///
+/// # Safety
+///
+/// `config` must be a configuration from `jailconf_parse`.
+#[no_mangle]
+pub unsafe extern "C" fn jailconf_jail_count(config: *const JailconfConfig) -> usize {
+    config.as_ref().map_or(0, |config| config.names.len())
+}
+
+/// The name of the jail at `index`, in the order the jails are first
+/// configured, or NULL if there aren't that many. The name belongs to the
+/// configuration.
+///
+/// # Safety
+///
+/// `config` must be a configuration from `jailconf_parse`.
+#[no_mangle]
+pub unsafe extern "C" fn jailconf_jail_name(
+    config: *const JailconfConfig,
+    index: usize,
+) -> *const c_char {
+    config.as_ref()
+        .and_then(|config| config.names.get(index))
+        .map_or(ptr::null(), |name| name.as_ptr())
+}
+
+/// The number of values the parameter has for the jail, 0 if the jail or
+/// parameter isn't configured. Booleans set without a value have one,
+/// "true".
+///
+/// # Safety
+///
+/// `config` must be a configuration from `jailconf_parse`, and `jail` and
+/// `name` valid NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn jailconf_value_count(
+    config: *const JailconfConfig,
+    jail: *const c_char,
+    name: *const c_char,
+) -> usize {
+    let name = match to_str(name) {
+        Some(name) => name,
+        None       => return 0,
+    };
+
+    config.as_ref()
+        .and_then(|config| config.params(jail))
+        .and_then(|params| params.get(name).map(Vec::len))
+        .unwrap_or(0)
+}
+
+/// The value of the parameter for the jail at `index`, or NULL if it has
+/// fewer values. The caller releases the value with
+/// `jailconf_string_free`.
+///
+/// # Safety
+///
+/// `config` must be a configuration from `jailconf_parse`, and `jail` and
+/// `name` valid NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn jailconf_value(
+    config: *const JailconfConfig,
+    jail: *const c_char,
+    name: *const c_char,
+    index: usize,
+) -> *mut c_char {
+    let name = match to_str(name) {
+        Some(name) => name,
+        None       => return ptr::null_mut(),
+    };
+
+    config.as_ref()
+        .and_then(|config| config.params(jail))
+        .and_then(|params| params.get(name)?.get(index).map(|value| to_c(value)))
+        .unwrap_or(ptr::null_mut())
+}
+
+/// The value of the parameter for the jail, its last if it has several, or
+/// NULL if it isn't set. The caller releases the value with
+/// `jailconf_string_free`.
+///
+/// # Safety
+///
+/// `config` must be a configuration from `jailconf_parse`, and `jail` and
+/// `name` valid NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn jailconf_get_param(
+    config: *const JailconfConfig,
+    jail: *const c_char,
+    name: *const c_char,
+) -> *mut c_char {
+    match jailconf_value_count(config, jail, name) {
+        0     => ptr::null_mut(),
+        count => jailconf_value(config, jail, name, count - 1),
+    }
+}
+
+/// Release a string returned by the other functions. Does nothing if `s` is
+/// NULL.
+///
+/// # Safety
+///
+/// `s` must be NULL or a string returned as `char *` which hasn't already
+/// been released.
+#[no_mangle]
+pub unsafe extern "C" fn jailconf_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Take a string returned to C back.
+    unsafe fn take(s: *mut c_char) -> Option<String> {
+        if s.is_null() {
+            return None;
+        }
+
+        let value = CStr::from_ptr(s).to_string_lossy().into_owned();
+        jailconf_string_free(s);
+
+        Some(value)
+    }
+
+    #[test]
+    fn test_capi() {
+        let input = CString::new("path = /jails/default;\n* {\n    persist;\n}\nwww {\n    ip4.addr = 192.0.2.1;\n    ip4.addr += 192.0.2.2;\n}\n").unwrap();
+        let www = CString::new("www").unwrap();
+        let addr = CString::new("ip4.addr").unwrap();
+        let path = CString::new("path").unwrap();
+        let persist = CString::new("persist").unwrap();
+
+        unsafe {
+            let config = jailconf_parse(input.as_ptr(), ptr::null_mut());
+            assert!(!config.is_null());
+
+            assert_eq!(jailconf_jail_count(config), 1);
+            assert_eq!(CStr::from_ptr(jailconf_jail_name(config, 0)).to_str(), Ok("www"));
+            assert!(jailconf_jail_name(config, 1).is_null());
+
+            assert_eq!(jailconf_value_count(config, www.as_ptr(), addr.as_ptr()), 2);
+            assert_eq!(take(jailconf_value(config, www.as_ptr(), addr.as_ptr(), 0)).as_deref(), Some("192.0.2.1"));
+            assert_eq!(take(jailconf_get_param(config, www.as_ptr(), addr.as_ptr())).as_deref(), Some("192.0.2.2"));
+            assert_eq!(take(jailconf_get_param(config, www.as_ptr(), path.as_ptr())).as_deref(), Some("/jails/default"));
+            assert_eq!(take(jailconf_get_param(config, www.as_ptr(), persist.as_ptr())).as_deref(), Some("true"));
+            assert!(jailconf_get_param(config, path.as_ptr(), path.as_ptr()).is_null());
+
+            // The parameters are worked out once, on first use.
+            assert!((&*config).params["www"].get().is_some());
+
+            jailconf_free(config);
+        }
+    }
+
+    #[test]
+    fn test_capi_parse_error() {
+        let input = CString::new("www {\n    persist\n").unwrap();
+        let mut error = ptr::null_mut();
+
+        unsafe {
+            let config = jailconf_parse(input.as_ptr(), &mut error);
+
+            assert!(config.is_null());
+            assert!(take(error).unwrap().starts_with("error at line 2"));
+        }
+    }
+}
@@ -0,0 +1,211 @@
+// jailconf check: report syntax errors, invalid parameters and lints.
+//
+// Every problem is printed, prefixed with where it was found, rather than
+// stopping at the first. Syntax errors, invalid parameters and lints of
+// error severity make the check fail, while other lints and warnings are
+// only printed.
+//
+// Besides the default output for people, problems can be printed for tools:
+// `--format gcc` prints them as `file:line:col: severity: message [code]`,
+// as compilers do, which editors and CI systems already know how to pick
+// out, and `--format json` prints an array of objects, one per problem.
+use std::io::{
+    self,
+    Write,
+};
+use std::path::PathBuf;
+use clap::ValueEnum;
+use jailconf::{
+    parse_lenient,
+    JailConfig,
+    Severity,
+    Span,
+};
+use serde_json::{
+    json,
+    Value,
+};
+use super::{
+    print_stdout,
+    read_input,
+    Error,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Format {
+    /// A line per problem, for people.
+    Human,
+    /// A line per problem as `file:line:col: severity: message [code]`.
+    Gcc,
+    /// An array of problems as JSON.
+    Json,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The file to read, or - for stdin.
+    #[arg(default_value = "-")]
+    file: PathBuf,
+
+    /// The format to print problems in.
+    #[arg(short, long, value_enum, default_value_t = Format::Human)]
+    format: Format,
+}
+
+// A problem from the parser, validator, linter or warnings, in one shape.
+struct Problem {
+    severity: Severity,
+    code:     &'static str,
+    jail:     Option<String>,
+    message:  String,
+    span:     Span,
+    // The problem as the default format prints it, after its location.
+    human:    String,
+    // Whether the problem makes the check fail.
+    fails:    bool,
+}
+
+// Every problem with the input, syntax errors first.
+fn problems(input: &str) -> Vec<Problem> {
+    let (statements, diagnostics) = parse_lenient(input);
+
+    let mut problems: Vec<Problem> = diagnostics.iter()
+        .map(|diagnostic| Problem {
+            severity: Severity::Error,
+            code:     "syntax",
+            jail:     None,
+            message:  format!("expected {}", diagnostic.expected()),
+            span:     diagnostic.span(),
+            human:    format!("error: expected {}", diagnostic.expected()),
+            fails:    true,
+        })
+        .collect();
+
+    // Validation of a file with syntax errors would only report on whatever
+    // could be recovered from it.
+    if !problems.is_empty() {
+        return problems;
+    }
+
+    let config = JailConfig::new(statements);
+
+    problems.extend(config.validate().into_iter().map(|error| Problem {
+        severity: Severity::Error,
+        code:     error.code(),
+        jail:     error.jail().map(String::from),
+        message:  error.message(),
+        span:     error.span(),
+        human:    format!("error: {}", error),
+        fails:    true,
+    }));
+
+    problems.extend(config.lint().into_iter().map(|lint| Problem {
+        severity: lint.severity(),
+        code:     lint.code(),
+        jail:     lint.jail().map(String::from),
+        message:  lint.message().to_string(),
+        span:     lint.span(),
+        human:    lint.to_string(),
+        fails:    lint.severity() == Severity::Error,
+    }));
+
+    problems.extend(config.warnings().into_iter().map(|warning| Problem {
+        severity: Severity::Warning,
+        code:     warning.kind().code(),
+        jail:     warning.jail().map(String::from),
+        message:  warning.message().to_string(),
+        span:     warning.span(),
+        human:    warning.to_string(),
+        fails:    false,
+    }));
+
+    problems
+}
+
+// The severity as compilers print it.
+fn gcc_severity(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Info    => "note",
+        Severity::Warning => "warning",
+        Severity::Error   => "error",
+    }
+}
+
+fn to_json(path: &str, problem: &Problem) -> Value {
+    let (start, end) = (problem.span.start(), problem.span.end());
+
+    json!({
+        "file":       path,
+        "line":       start.line(),
+        "column":     start.column(),
+        "end_line":   end.line(),
+        "end_column": end.column(),
+        "severity":   problem.severity.to_string(),
+        "code":       problem.code,
+        "jail":       problem.jail,
+        "message":    problem.message,
+    })
+}
+
+// Print the problems in the format asked for.
+fn print(out: &mut impl Write, path: &str, format: Format, problems: &[Problem]) -> io::Result<()> {
+    let location = |span: Span| {
+        format!("{}:{}:{}", path, span.start().line(), span.start().column())
+    };
+
+    match format {
+        Format::Human => {
+            for problem in problems {
+                writeln!(out, "{}: {}", location(problem.span), problem.human)?;
+            }
+        },
+        Format::Gcc => {
+            for problem in problems {
+                let jail = problem.jail.as_ref()
+                    .map(|jail| format!("{}: ", jail))
+                    .unwrap_or_default();
+
+                writeln!(
+                    out,
+                    "{}: {}: {}{} [{}]",
+                    location(problem.span),
+                    gcc_severity(problem.severity),
+                    jail,
+                    problem.message,
+                    problem.code,
+                )?;
+            }
+        },
+        Format::Json => {
+            let problems = problems.iter()
+                .map(|problem| to_json(path, problem))
+                .collect();
+
+            // A Value always serializes.
+            let output = serde_json::to_string_pretty(&Value::Array(problems))
+                .expect("JSON serialization failed");
+
+            writeln!(out, "{}", output)?;
+        },
+    }
+
+    Ok(())
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let input = read_input(&args.file)?;
+    let path = args.file.display().to_string();
+    let problems = problems(&input);
+
+    print_stdout(|out| print(out, &path, args.format, &problems))?;
+
+    let errors = problems.iter().filter(|problem| problem.fails).count();
+
+    match errors {
+        0      => Ok(()),
+        errors => Err(Error::Problems {
+            errors,
+            warnings: problems.len() - errors,
+        }),
+    }
+}
@@ -0,0 +1,36 @@
+// jailconf command: the jail(8) command line which would create a jail.
+use std::io::Write;
+use std::path::PathBuf;
+use super::{
+    parse_config,
+    print_stdout,
+    read_input,
+    Error,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The file to read, or - for stdin.
+    file: PathBuf,
+
+    /// The name of the jail.
+    jail: String,
+
+    /// Expand variable references in values first.
+    #[arg(short, long)]
+    resolve: bool,
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let input = read_input(&args.file)?;
+    let mut config = parse_config(&args.file, &input)?;
+
+    if args.resolve {
+        config = config.resolve_variables()?;
+    }
+
+    let command = config.to_jail_command(&args.jail)
+        .ok_or_else(|| Error::NotFound(format!("jail {}", args.jail)))?;
+
+    print_stdout(|out| writeln!(out, "{}", command))
+}
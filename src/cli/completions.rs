@@ -0,0 +1,31 @@
+// jailconf completions: a shell completion script for jailconf.
+//
+// The script is printed for the packager or user to install where their
+// shell looks for completions, eg.
+// `jailconf completions zsh > /usr/local/share/zsh/site-functions/_jailconf`.
+use std::io::Write;
+use clap::CommandFactory;
+use clap_complete::Shell;
+use super::{
+    print_stdout,
+    Cli,
+    Error,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The shell to complete for.
+    #[arg(value_enum)]
+    shell: Shell,
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let mut command = Cli::command();
+    let mut script = Vec::new();
+
+    // Generated into a buffer first, as writing to stdout directly would
+    // panic if it were closed early.
+    clap_complete::generate(args.shell, &mut command, "jailconf", &mut script);
+
+    print_stdout(|out| out.write_all(&script))
+}
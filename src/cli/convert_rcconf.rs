@@ -0,0 +1,32 @@
+// jailconf convert-rcconf: jail.conf from legacy rc.conf jail variables.
+use std::io::Write;
+use std::path::PathBuf;
+use jailconf::{
+    format_config,
+    from_rc_conf,
+    FormatOptions,
+};
+use super::{
+    print_stdout,
+    read_input,
+    Error,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The rc.conf to read, or - for stdin.
+    #[arg(default_value = "/etc/rc.conf")]
+    file: PathBuf,
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let input = read_input(&args.file)?;
+    let config = from_rc_conf(&input).map_err(|source| Error::Convert {
+        path: args.file.display().to_string(),
+        source,
+    })?;
+
+    let formatted = format_config(config.statements(), &FormatOptions::new());
+
+    print_stdout(|out| out.write_all(formatted.as_bytes()))
+}
@@ -0,0 +1,109 @@
+// jailconf dump: the parsed configuration, for other tools to consume.
+//
+// The debug format prints the statements as parsed. JSON and YAML print one
+// object per jail, with the jail's effective parameters after global ones
+// have been applied. Every parameter maps to a list of its values, so that
+// parameters built up with `+=` don't need handling differently.
+use std::io::{
+    self,
+    Write,
+};
+use std::path::PathBuf;
+use clap::ValueEnum;
+use jailconf::{
+    Environment,
+    JailConfig,
+};
+use serde_json::{
+    json,
+    Value,
+};
+use super::{
+    parse_config,
+    print_stdout,
+    read_input,
+    Error,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Format {
+    /// The parsed statements, as Rust's Debug output.
+    Debug,
+    /// An array of jails as JSON.
+    Json,
+    /// An array of jails as YAML.
+    Yaml,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The file to read, or - for stdin.
+    #[arg(default_value = "-")]
+    file: PathBuf,
+
+    /// The format to print in.
+    #[arg(short, long, value_enum, default_value_t = Format::Debug)]
+    format: Format,
+
+    /// Expand variable references in values first.
+    #[arg(short, long)]
+    resolve: bool,
+
+    /// Expand variable references, taking any the file doesn't define from
+    /// the environment.
+    #[arg(short, long)]
+    env: bool,
+}
+
+// One object per jail, in the order the jails are first configured.
+fn jails(config: &JailConfig) -> Value {
+    let mut names: Vec<&str> = Vec::new();
+
+    for block in config.jails() {
+        if !names.contains(&block.name()) {
+            names.push(block.name());
+        }
+    }
+
+    let jails = names.iter()
+        .map(|name| json!({
+            "name":       name,
+            "parameters": config.effective_params(name).unwrap_or_default(),
+        }))
+        .collect();
+
+    Value::Array(jails)
+}
+
+fn print(out: &mut impl Write, config: &JailConfig, format: Format) -> io::Result<()> {
+    match format {
+        Format::Debug => writeln!(out, "{:#?}", config.statements()),
+        Format::Json  => {
+            // A Value always serializes.
+            let output = serde_json::to_string_pretty(&jails(config))
+                .expect("JSON serialization failed");
+
+            writeln!(out, "{}", output)
+        },
+        Format::Yaml  => {
+            let output = serde_yaml::to_string(&jails(config))
+                .expect("YAML serialization failed");
+
+            write!(out, "{}", output)
+        },
+    }
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let input = read_input(&args.file)?;
+    let mut config = parse_config(&args.file, &input)?;
+
+    if args.env {
+        config = config.resolve_variables_with(&Environment)?;
+    }
+    else if args.resolve {
+        config = config.resolve_variables()?;
+    }
+
+    print_stdout(|out| print(out, &config, args.format))
+}
@@ -0,0 +1,123 @@
+// Errors from the subcommands.
+use std::error;
+use std::fmt;
+use std::io;
+use jailconf::{
+    ConvertError,
+    DependencyError,
+    EditError,
+    MergeError,
+    ParseError,
+    VariableError,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    /// A file couldn't be read or written.
+    Io {
+        path:   String,
+        source: io::Error,
+    },
+    /// A file couldn't be parsed. The input is kept for showing where, and
+    /// the error boxed, as it's much larger than the others.
+    Parse {
+        path:   String,
+        source: Box<ParseError>,
+        input:  String,
+    },
+    /// An rc.conf couldn't be converted.
+    Convert {
+        path:   String,
+        source: ConvertError,
+    },
+    /// Configurations couldn't be merged.
+    Merge(MergeError),
+    /// Variables couldn't be resolved.
+    Variable(VariableError),
+    /// A file couldn't be edited as asked.
+    Edit(EditError),
+    /// Jails depend on each other in a cycle, or on jails which aren't
+    /// configured.
+    Dependency(DependencyError),
+    /// A jail, or a parameter of one, wasn't found.
+    NotFound(String),
+    /// fmt --check found a file which isn't formatted.
+    NotFormatted {
+        path: String,
+    },
+    /// check found problems, which it has already printed. Only errors
+    /// fail the check, the warnings are counted alongside them.
+    Problems {
+        errors:   usize,
+        warnings: usize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, source }      => write!(f, "{}: {}", path, source),
+            Error::Parse { path, source, input } => {
+                f.write_str(source.annotate(path, input).trim_end())
+            },
+            Error::Convert { path, source } => write!(f, "{}: {}", path, source),
+            Error::Merge(e)                 => write!(f, "{}", e),
+            Error::Variable(e)              => write!(f, "{}", e),
+            Error::Edit(e)                  => write!(f, "{}", e),
+            Error::Dependency(e)            => write!(f, "{}", e),
+            Error::NotFound(what)           => write!(f, "{} not found", what),
+            Error::NotFormatted { path }    => write!(f, "{}: not formatted", path),
+            Error::Problems { errors, warnings } => {
+                let plural = |count: usize| if count == 1 { "" } else { "s" };
+
+                write!(
+                    f,
+                    "{} error{}, {} warning{}",
+                    errors,
+                    plural(*errors),
+                    warnings,
+                    plural(*warnings),
+                )
+            },
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. }      => Some(source),
+            Error::Parse { source, .. }   => Some(&**source),
+            Error::Convert { source, .. } => Some(source),
+            Error::Merge(e)               => Some(e),
+            Error::Variable(e)            => Some(e),
+            Error::Edit(e)                => Some(e),
+            Error::Dependency(e)          => Some(e),
+            _                             => None,
+        }
+    }
+}
+
+impl From<VariableError> for Error {
+    fn from(e: VariableError) -> Self {
+        Error::Variable(e)
+    }
+}
+
+impl From<MergeError> for Error {
+    fn from(e: MergeError) -> Self {
+        Error::Merge(e)
+    }
+}
+
+impl From<EditError> for Error {
+    fn from(e: EditError) -> Self {
+        Error::Edit(e)
+    }
+}
+
+impl From<DependencyError> for Error {
+    fn from(e: DependencyError) -> Self {
+        Error::Dependency(e)
+    }
+}
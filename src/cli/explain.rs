@@ -0,0 +1,60 @@
+// jailconf explain: what a parameter is, from the parameter schema.
+//
+// The parameter may be given as it would be written in a file, so the "no"
+// form of a boolean, eg. allow.nomount, explains allow.mount, and an old
+// spelling such as ip4_addr explains ip4.addr, saying so.
+use std::io::{
+    self,
+    Write,
+};
+use jailconf::schema::{
+    parameter,
+    Parameter,
+};
+use jailconf::ParamName;
+use super::{
+    print_stdout,
+    Error,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The name of the parameter, eg. allow.mount.zfs.
+    param: String,
+}
+
+// Print the parameter, as given and as found in the schema.
+fn print(out: &mut impl Write, given: &str, name: &ParamName, param: &Parameter)
+-> io::Result<()> {
+    writeln!(out, "{}", param.name())?;
+    writeln!(out, "    type:       {}", param.kind().description())?;
+    writeln!(out, "    default:    {}", param.default().unwrap_or("none"))?;
+    writeln!(out, "    since:      FreeBSD {}", param.since())?;
+
+    if let Some(instead) = param.deprecated() {
+        writeln!(out, "    deprecated: use {} instead", instead)?;
+    }
+
+    writeln!(out)?;
+    writeln!(out, "{}", param.description())?;
+
+    if name.is_respelled() {
+        writeln!(out)?;
+        writeln!(out, "{} is an old spelling of {}, which jail(8) refuses.", given, name)?;
+    }
+    else if name.is_negated() {
+        writeln!(out)?;
+        writeln!(out, "{} sets {} to false.", given, param.name())?;
+    }
+
+    Ok(())
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let not_found = || Error::NotFound(format!("parameter {}", args.param));
+
+    let name = ParamName::parse(&args.param).map_err(|_| not_found())?;
+    let param = parameter(name.name()).ok_or_else(not_found)?;
+
+    print_stdout(|out| print(out, &args.param, &name, param))
+}
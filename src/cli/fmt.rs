@@ -0,0 +1,141 @@
+// jailconf fmt: rewrite a file in the canonical format.
+use std::io::Write;
+use std::path::PathBuf;
+use clap::ValueEnum;
+use jailconf::{
+    format_config,
+    restyle_comments,
+    CommentStyle,
+    FormatOptions,
+    GroupParams,
+    HoistGlobals,
+    SortJails,
+    Transform,
+};
+use super::{
+    parse_config,
+    parse_error,
+    print_stdout,
+    read_input,
+    write_output,
+    Error,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Style {
+    /// `# comment`
+    Shell,
+    /// `// comment`
+    Cpp,
+    /// `/* comment */`
+    C,
+}
+
+impl From<Style> for CommentStyle {
+    fn from(style: Style) -> Self {
+        match style {
+            Style::Shell => CommentStyle::Shell,
+            Style::Cpp   => CommentStyle::CPP,
+            Style::C     => CommentStyle::C,
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The file to read, or - for stdin.
+    #[arg(default_value = "-")]
+    file: PathBuf,
+
+    /// Don't write anything, exit non-zero if the file isn't formatted.
+    #[arg(long, conflicts_with = "write")]
+    check: bool,
+
+    /// Write the result back to the file rather than to stdout.
+    #[arg(short, long)]
+    write: bool,
+
+    /// The number of spaces to indent by.
+    #[arg(long, default_value_t = 4, value_name = "WIDTH")]
+    indent: usize,
+
+    /// Indent with tabs instead of spaces.
+    #[arg(long, conflicts_with = "indent")]
+    tabs: bool,
+
+    /// Don't align the = signs of consecutive parameters.
+    #[arg(long)]
+    no_align: bool,
+
+    /// Convert every comment to the given style.
+    #[arg(long, value_enum, value_name = "STYLE")]
+    comment_style: Option<Style>,
+
+    /// Move global parameters and variables given after the first jail to
+    /// before it.
+    #[arg(long)]
+    hoist_globals: bool,
+
+    /// Sort the jails by name.
+    #[arg(long)]
+    sort_jails: bool,
+
+    /// Group parameters by the part of their name before the first dot, eg.
+    /// exec.* and allow.*, with blank lines between the groups.
+    #[arg(long)]
+    group_params: bool,
+}
+
+// The transforms asked for, in the order they're applied.
+fn transforms(args: &Args) -> Vec<Box<dyn Transform>> {
+    let mut transforms: Vec<Box<dyn Transform>> = Vec::new();
+
+    if args.hoist_globals {
+        transforms.push(Box::new(HoistGlobals));
+    }
+
+    if args.sort_jails {
+        transforms.push(Box::new(SortJails));
+    }
+
+    if args.group_params {
+        transforms.push(Box::new(GroupParams));
+    }
+
+    transforms
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let input = read_input(&args.file)?;
+    let config = parse_config(&args.file, &input)?.transform(&transforms(args));
+
+    let options = FormatOptions::new()
+        .indent(args.indent)
+        .tabs(args.tabs)
+        .align(!args.no_align)
+        .separate_groups(args.group_params);
+
+    let mut formatted = format_config(config.statements(), &options);
+
+    if let Some(style) = args.comment_style {
+        formatted = restyle_comments(&formatted, style.into())
+            .map_err(|source| parse_error(&args.file, &formatted, source))?;
+    }
+
+    if args.check {
+        if formatted != input {
+            return Err(Error::NotFormatted {
+                path: args.file.display().to_string(),
+            });
+        }
+
+        return Ok(());
+    }
+
+    if args.write {
+        write_output(&args.file, &formatted)
+    }
+    else {
+        print_stdout(|out| out.write_all(formatted.as_bytes()))
+    }
+}
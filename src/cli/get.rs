@@ -0,0 +1,58 @@
+// jailconf get: print the value of a parameter for a jail.
+//
+// The value is the one jail(8) would use, taking global parameters into
+// account. Parameters with several values, built up with `+=`, print each
+// value on its own line. --explain prints how the value was arrived at
+// instead, each statement setting the parameter and where it was given.
+use std::io::Write;
+use std::path::PathBuf;
+use super::{
+    parse_config,
+    print_stdout,
+    read_input,
+    Error,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The file to read, or - for stdin.
+    file: PathBuf,
+
+    /// The name of the jail.
+    jail: String,
+
+    /// The name of the parameter.
+    param: String,
+
+    /// Print each statement setting the parameter, and the values it
+    /// leaves, rather than the final value.
+    #[arg(long)]
+    explain: bool,
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let input = read_input(&args.file)?;
+    let config = parse_config(&args.file, &input)?;
+
+    if args.explain {
+        let explanation = config.explain_param(&args.jail, &args.param)
+            .ok_or_else(|| Error::NotFound(format!("jail {}", args.jail)))?;
+
+        return print_stdout(|out| write!(out, "{}", explanation));
+    }
+
+    let params = config.effective_params(&args.jail)
+        .ok_or_else(|| Error::NotFound(format!("jail {}", args.jail)))?;
+
+    let values = params.get(&args.param).ok_or_else(|| {
+        Error::NotFound(format!("parameter {} for jail {}", args.param, args.jail))
+    })?;
+
+    print_stdout(|out| {
+        for value in values {
+            writeln!(out, "{}", value)?;
+        }
+
+        Ok(())
+    })
+}
@@ -0,0 +1,57 @@
+// jailconf graph: the dependencies between jails.
+//
+// By default each jail is printed with the jails it depends on. --dot
+// prints a Graphviz digraph instead, for rendering with dot(1), eg.
+// `jailconf graph --dot /etc/jail.conf | dot -Tsvg > jails.svg`.
+use std::io::{
+    self,
+    Write,
+};
+use std::path::PathBuf;
+use jailconf::JailConfig;
+use super::{
+    parse_config,
+    print_stdout,
+    read_input,
+    Error,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The file to read, or - for stdin.
+    #[arg(default_value = "-")]
+    file: PathBuf,
+
+    /// Print the graph in Graphviz's dot language.
+    #[arg(short, long)]
+    dot: bool,
+}
+
+// Print each jail, in the order they'd start, with its dependencies.
+fn print(out: &mut impl Write, config: &JailConfig, order: &[&str]) -> io::Result<()> {
+    for name in order {
+        let dependencies = config.dependencies(name);
+
+        if dependencies.is_empty() {
+            writeln!(out, "{}", name)?;
+        }
+        else {
+            writeln!(out, "{}: {}", name, dependencies.join(", "))?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let input = read_input(&args.file)?;
+    let config = parse_config(&args.file, &input)?;
+
+    if args.dot {
+        return print_stdout(|out| write!(out, "{}", config.dependency_dot()));
+    }
+
+    let order = config.start_order()?;
+
+    print_stdout(|out| print(out, &config, &order))
+}
@@ -0,0 +1,194 @@
+// jailconf list: the configured jails, with selected parameters.
+//
+// Without --param this prints only the names of the jails. With it, it
+// prints a table with a column for each parameter named, giving the value
+// jail(8) would use once globals, wildcards and variables are resolved.
+// Parameters with several values show them separated by commas, and those
+// which aren't set show a dash.
+//
+// --filter keeps only the jails with a parameter set, or set to a value:
+// `allow.mount` keeps jails where allow.mount is set and isn't false, and
+// `path=/jails/www` those where path has that value among its values. Every
+// filter given must match.
+use std::convert::Infallible;
+use std::io::{
+    self,
+    Write,
+};
+use std::path::PathBuf;
+use std::str::FromStr;
+use jailconf::{
+    EffectiveParams,
+    JailConfig,
+};
+use serde_json::{
+    json,
+    Map,
+    Value,
+};
+use super::{
+    parse_config,
+    print_stdout,
+    read_input,
+    Error,
+};
+
+// A condition a jail's parameters must meet to be listed.
+#[derive(Clone, Debug)]
+struct Filter {
+    name:  String,
+    value: Option<String>,
+}
+
+impl FromStr for Filter {
+    type Err = Infallible;
+
+    fn from_str(filter: &str) -> Result<Self, Self::Err> {
+        let filter = match filter.split_once('=') {
+            Some((name, value)) => Self {
+                name:  name.trim().to_string(),
+                value: Some(value.trim().to_string()),
+            },
+            None => Self {
+                name:  filter.trim().to_string(),
+                value: None,
+            },
+        };
+
+        Ok(filter)
+    }
+}
+
+impl Filter {
+    fn matches(&self, config: &JailConfig, jail: &str, params: &EffectiveParams) -> bool {
+        let values = match params.get(&self.name) {
+            Some(values) => values,
+            None         => return false,
+        };
+
+        match &self.value {
+            Some(value) => values.contains(value),
+            None        => !matches!(config.get_bool(jail, &self.name), Ok(false)),
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The file to read, or - for stdin.
+    #[arg(default_value = "-")]
+    file: PathBuf,
+
+    /// Parameters to show a column for, separated by commas.
+    #[arg(short, long, value_delimiter = ',')]
+    param: Vec<String>,
+
+    /// Only list jails with PARAM set, or set to VALUE with PARAM=VALUE. May
+    /// be given several times.
+    #[arg(short, long, value_name = "PARAM[=VALUE]")]
+    filter: Vec<Filter>,
+
+    /// Print an array of jails as JSON.
+    #[arg(short, long)]
+    json: bool,
+}
+
+// The value shown in a column.
+fn cell(params: &EffectiveParams, name: &str) -> String {
+    match params.get(name) {
+        Some(values) => values.join(","),
+        None         => "-".to_string(),
+    }
+}
+
+fn print_table(out: &mut impl Write, columns: &[String], rows: &[(&str, EffectiveParams)])
+-> io::Result<()> {
+    let header: Vec<String> = Some("NAME".to_string())
+        .into_iter()
+        .chain(columns.iter().map(|column| column.to_uppercase()))
+        .collect();
+
+    let lines: Vec<Vec<String>> = Some(header)
+        .into_iter()
+        .chain(rows.iter().map(|(name, params)| {
+            Some(name.to_string())
+                .into_iter()
+                .chain(columns.iter().map(|column| cell(params, column)))
+                .collect()
+        }))
+        .collect();
+
+    let widths: Vec<usize> = (0..=columns.len())
+        .map(|i| lines.iter().map(|line| line[i].chars().count()).max().unwrap_or(0))
+        .collect();
+
+    for line in lines {
+        let padded: Vec<String> = line.iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+
+        writeln!(out, "{}", padded.join("  ").trim_end())?;
+    }
+
+    Ok(())
+}
+
+fn print_json(out: &mut impl Write, columns: &[String], rows: &[(&str, EffectiveParams)])
+-> io::Result<()> {
+    let jails = rows.iter()
+        .map(|(name, params)| {
+            let selected: Map<String, Value> = columns.iter()
+                .filter_map(|column| {
+                    params.get(column).map(|values| (column.clone(), json!(values)))
+                })
+                .collect();
+
+            json!({
+                "name":       name,
+                "parameters": selected,
+            })
+        })
+        .collect();
+
+    // A Value always serializes.
+    let output = serde_json::to_string_pretty(&Value::Array(jails))
+        .expect("JSON serialization failed");
+
+    writeln!(out, "{}", output)
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let input = read_input(&args.file)?;
+    let mut config = parse_config(&args.file, &input)?;
+
+    // Variables are only expanded when there are values to show or match,
+    // so that listing names doesn't need every variable defined.
+    if !args.param.is_empty() || !args.filter.is_empty() {
+        config = config.resolve_variables()?;
+    }
+
+    let rows: Vec<(&str, EffectiveParams)> = config.jail_names()
+        .into_iter()
+        .map(|name| (name, config.effective_params(name).unwrap_or_default()))
+        .filter(|(name, params)| {
+            args.filter.iter().all(|filter| filter.matches(&config, name, params))
+        })
+        .collect();
+
+    print_stdout(|out| {
+        if args.json {
+            print_json(out, &args.param, &rows)
+        }
+        else if args.param.is_empty() {
+            for (name, _) in &rows {
+                writeln!(out, "{}", name)?;
+            }
+
+            Ok(())
+        }
+        else {
+            print_table(out, &args.param, &rows)
+        }
+    })
+}
@@ -0,0 +1,41 @@
+// jailconf man: manual pages for jailconf.
+//
+// The pages are generated from the same definitions as the command line
+// parsing, so they can't fall out of date with it. Without a directory the
+// page for jailconf itself is printed. With one, a page is written for
+// jailconf and each of its subcommands, eg. jailconf-check.1, for packaging.
+use std::fs;
+use std::path::PathBuf;
+use clap::CommandFactory;
+use clap_mangen::Man;
+use tracing::info;
+use super::{
+    print_stdout,
+    Cli,
+    Error,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The directory to write a page per subcommand to.
+    #[arg(short, long)]
+    out_dir: Option<PathBuf>,
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let command = Cli::command();
+
+    let dir = match &args.out_dir {
+        Some(dir) => dir,
+        None      => return print_stdout(|out| Man::new(command).render(out)),
+    };
+
+    info!("writing manual pages to {}", dir.display());
+
+    fs::create_dir_all(dir)
+        .and_then(|_| clap_mangen::generate_to(command, dir))
+        .map_err(|source| Error::Io {
+            path: dir.display().to_string(),
+            source,
+        })
+}
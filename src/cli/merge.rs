@@ -0,0 +1,43 @@
+// jailconf merge: apply a file of overrides to a base configuration.
+use std::io::Write;
+use std::path::PathBuf;
+use jailconf::MergeStrategy;
+use super::{
+    parse_config,
+    print_stdout,
+    read_input,
+    Error,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The base configuration, or - for stdin.
+    base: PathBuf,
+
+    /// The configuration to merge into the base.
+    overrides: PathBuf,
+
+    /// Fail if the overrides assign a different value to a parameter which
+    /// the base already sets.
+    #[arg(long)]
+    strict: bool,
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let base_input = read_input(&args.base)?;
+    let base = parse_config(&args.base, &base_input)?;
+
+    let overrides_input = read_input(&args.overrides)?;
+    let overrides = parse_config(&args.overrides, &overrides_input)?;
+
+    let strategy = if args.strict {
+        MergeStrategy::Strict
+    }
+    else {
+        MergeStrategy::Override
+    };
+
+    let merged = base.merge(&overrides, strategy)?;
+
+    print_stdout(|out| write!(out, "{}", merged))
+}
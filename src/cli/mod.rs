@@ -0,0 +1,211 @@
+// The jailconf command line interface.
+//
+// Each subcommand lives in its own module, with an Args struct describing
+// its arguments and a run function carrying it out. Files are given as paths,
+// with "-" meaning stdin, and problems are reported through Error so that
+// main can log them and exit non-zero. Output cut short by whatever it's
+// piped to, eg. `jailconf list | head`, isn't a problem.
+use std::fs;
+use std::io::{
+    self,
+    Read,
+    Write,
+};
+use std::path::Path;
+use clap::{
+    ArgAction,
+    Parser,
+    Subcommand,
+};
+use jailconf::{
+    write_atomic,
+    JailConfig,
+    ParseError,
+};
+use tracing::{
+    debug,
+    info,
+};
+
+mod check;
+mod command;
+mod completions;
+mod convert_rcconf;
+mod dump;
+mod error;
+mod explain;
+mod fmt;
+mod get;
+mod graph;
+mod list;
+mod man;
+mod merge;
+mod new;
+mod order;
+mod redact;
+mod set;
+mod stats;
+mod tree;
+#[cfg(feature = "tui")]
+mod tui;
+
+pub use self::error::Error;
+
+/// Inspect, check and edit jail.conf(5) files.
+#[derive(Debug, Parser)]
+#[command(name = "jailconf", version)]
+pub struct Cli {
+    /// Only log errors.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Log more detail, may be given several times.
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Check a file for errors, exiting non-zero if any are found.
+    Check(check::Args),
+    /// Print a file in the canonical format.
+    Fmt(fmt::Args),
+    /// Print the value of a parameter for a jail.
+    Get(get::Args),
+    /// Set a parameter for a jail, keeping the rest of the file as it is.
+    Set(set::Args),
+    /// List the jails configured in a file.
+    List(list::Args),
+    /// Add a block for a new jail to a file, from a template.
+    New(new::Args),
+    /// Merge a file of overrides into a base configuration.
+    Merge(merge::Args),
+    /// Print the parsed statements of a file.
+    Dump(dump::Args),
+    /// Print the parsed statements of a file as a tree, with their spans.
+    Tree(tree::Args),
+    /// Describe a jail parameter: its type, default and what it does.
+    Explain(explain::Args),
+    /// Print the order the jails would be started or stopped in.
+    Order(order::Args),
+    /// Print the dependencies between jails, optionally for Graphviz.
+    Graph(graph::Args),
+    /// Print counts of the jails, parameters and other statements in a file.
+    Stats(stats::Args),
+    /// Print a file with addresses, hostnames and other sensitive values
+    /// masked, for sharing.
+    Redact(redact::Args),
+    /// Print the jail(8) command line which would create a jail.
+    #[command(name = "command")]
+    Cmdline(command::Args),
+    /// Convert the jail_* variables of an rc.conf into jail.conf.
+    ConvertRcconf(convert_rcconf::Args),
+    /// Print a completion script for a shell.
+    Completions(completions::Args),
+    /// Print or write the manual pages.
+    Man(man::Args),
+    /// Browse the jails of a file, their parameters and lints interactively.
+    #[cfg(feature = "tui")]
+    Tui(tui::Args),
+}
+
+// Read the whole of a file, or stdin if the path is "-".
+pub(crate) fn read_input(path: &Path) -> Result<String, Error> {
+    let mut buffer = String::new();
+
+    let result = if path == Path::new("-") {
+        info!("reading from stdin");
+        io::stdin().read_to_string(&mut buffer)
+    }
+    else {
+        info!("loading {}", path.display());
+        fs::File::open(path).and_then(|mut fh| fh.read_to_string(&mut buffer))
+    };
+
+    result.map_err(|source| Error::Io {
+        path: path.display().to_string(),
+        source,
+    })?;
+
+    debug!("read {} bytes", buffer.len());
+
+    Ok(buffer)
+}
+
+// An error parsing input read from path.
+pub(crate) fn parse_error(path: &Path, input: &str, source: ParseError) -> Error {
+    Error::Parse {
+        path:   path.display().to_string(),
+        source: Box::new(source),
+        input:  input.to_string(),
+    }
+}
+
+// Parse input read from path.
+pub(crate) fn parse_config<'a>(path: &Path, input: &'a str)
+-> Result<JailConfig<'a>, Error> {
+    JailConfig::parse(input).map_err(|source| parse_error(path, input, source))
+}
+
+// Write output to a locked stdout, flushing it afterwards. A broken pipe
+// ends the output quietly rather than failing the command.
+pub(crate) fn print_stdout<F>(print: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut io::StdoutLock) -> io::Result<()>,
+{
+    let mut out = io::stdout().lock();
+
+    match print(&mut out).and_then(|_| out.flush()) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+            Err(Error::Io {
+                path:   "stdout".to_string(),
+                source: e,
+            })
+        },
+        _ => Ok(()),
+    }
+}
+
+// Replace the contents of a file atomically, or write to stdout if the path
+// is "-".
+pub(crate) fn write_output(path: &Path, contents: &str) -> Result<(), Error> {
+    if path == Path::new("-") {
+        return print_stdout(|out| out.write_all(contents.as_bytes()));
+    }
+
+    info!("writing {}", path.display());
+
+    write_atomic(path, contents).map_err(|source| Error::Io {
+        path: path.display().to_string(),
+        source,
+    })
+}
+
+/// Carry out a subcommand.
+pub fn run(command: Command) -> Result<(), Error> {
+    match command {
+        Command::Check(args)         => check::run(&args),
+        Command::Fmt(args)           => fmt::run(&args),
+        Command::Get(args)           => get::run(&args),
+        Command::Set(args)           => set::run(&args),
+        Command::List(args)          => list::run(&args),
+        Command::New(args)           => new::run(&args),
+        Command::Merge(args)         => merge::run(&args),
+        Command::Dump(args)          => dump::run(&args),
+        Command::Tree(args)          => tree::run(&args),
+        Command::Explain(args)       => explain::run(&args),
+        Command::Order(args)         => order::run(&args),
+        Command::Graph(args)         => graph::run(&args),
+        Command::Stats(args)         => stats::run(&args),
+        Command::Redact(args)        => redact::run(&args),
+        Command::ConvertRcconf(args) => convert_rcconf::run(&args),
+        Command::Cmdline(args)       => command::run(&args),
+        Command::Completions(args)   => completions::run(&args),
+        Command::Man(args)           => man::run(&args),
+        #[cfg(feature = "tui")]
+        Command::Tui(args)           => tui::run(&args),
+    }
+}
@@ -0,0 +1,108 @@
+// jailconf new: add a block for a new jail to a file.
+//
+// The block is built from a template and added at the end of the file,
+// leaving everything already in the file as it was. The basic template
+// gives the jail an IPv4 address on the host's network, while the vnet
+// template gives it a virtual network stack of its own, with the address
+// configured on the interface handed to it as it starts.
+use std::path::PathBuf;
+use clap::ValueEnum;
+use jailconf::{
+    JailBlock,
+    JailBlockBuilder,
+    LosslessConfig,
+};
+use tracing::info;
+use super::{
+    parse_error,
+    read_input,
+    write_output,
+    Error,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Template {
+    /// A jail sharing the host's network stack.
+    Basic,
+    /// A jail with a virtual network stack of its own.
+    Vnet,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The name of the new jail.
+    name: String,
+
+    /// The file to add the jail to, or - to read stdin and write stdout.
+    #[arg(default_value = "/etc/jail.conf")]
+    file: PathBuf,
+
+    /// The IPv4 address of the jail, optionally with a prefix length.
+    #[arg(long)]
+    ip: Option<String>,
+
+    /// The jail's root directory. Defaults to /usr/jails/<name>.
+    #[arg(long)]
+    path: Option<String>,
+
+    /// The kind of jail to add.
+    #[arg(short, long, value_enum, default_value_t = Template::Basic)]
+    template: Template,
+
+    /// The interface handed to a vnet jail.
+    #[arg(long, default_value = "epair0b")]
+    interface: String,
+}
+
+fn build(args: &Args) -> JailBlock<'static> {
+    let path = args.path.clone()
+        .unwrap_or_else(|| format!("/usr/jails/{}", args.name));
+
+    let mut builder = JailBlockBuilder::new(args.name.as_str())
+        .param("host.hostname", &args.name)
+        .param("path", &path);
+
+    match args.template {
+        Template::Basic => {
+            if let Some(ip) = &args.ip {
+                builder = builder.param("ip4.addr", ip);
+            }
+
+            builder = builder.param("exec.start", "/bin/sh /etc/rc");
+        },
+        Template::Vnet => {
+            builder = builder
+                .flag("vnet")
+                .param("vnet.interface", &args.interface);
+
+            match &args.ip {
+                Some(ip) => {
+                    let up = format!("/sbin/ifconfig {} inet {} up", args.interface, ip);
+
+                    builder = builder
+                        .param("exec.start", &up)
+                        .append("exec.start", "/bin/sh /etc/rc");
+                },
+                None => builder = builder.param("exec.start", "/bin/sh /etc/rc"),
+            }
+        },
+    }
+
+    builder
+        .param("exec.stop", "/bin/sh /etc/rc.shutdown")
+        .flag("exec.clean")
+        .flag("mount.devfs")
+        .build()
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let input = read_input(&args.file)?;
+    let mut config = LosslessConfig::parse(&input)
+        .map_err(|source| parse_error(&args.file, &input, source))?;
+
+    config.add_jail(build(args))?;
+
+    info!("{}", config.change_summary().trim_end());
+
+    write_output(&args.file, &config.to_source())
+}
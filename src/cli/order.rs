@@ -0,0 +1,45 @@
+// jailconf order: the order jail(8) would start or stop the jails in.
+//
+// Each jail starts after the jails it depends on, through `depend` or as
+// the parent of a hierarchical jail, and stops before them. Dependencies
+// forming a cycle are an error, as jail(8) can't start any of the jails in
+// it, and so are dependencies on jails which aren't configured.
+use std::io::Write;
+use std::path::PathBuf;
+use super::{
+    parse_config,
+    print_stdout,
+    read_input,
+    Error,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The file to read, or - for stdin.
+    #[arg(default_value = "-")]
+    file: PathBuf,
+
+    /// Print the order the jails would be stopped in instead.
+    #[arg(short, long)]
+    stop: bool,
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let input = read_input(&args.file)?;
+    let config = parse_config(&args.file, &input)?;
+
+    let order = if args.stop {
+        config.stop_order()?
+    }
+    else {
+        config.start_order()?
+    };
+
+    print_stdout(|out| {
+        for name in order {
+            writeln!(out, "{}", name)?;
+        }
+
+        Ok(())
+    })
+}
@@ -0,0 +1,64 @@
+// jailconf redact: mask sensitive values before sharing a file.
+//
+// Addresses and hostnames are masked unless asked not to be, along with any
+// paths matching --path and the whole values of any --param, leaving the
+// jails and their parameters as they were for a bug report or forum post.
+use std::io::Write;
+use std::path::PathBuf;
+use jailconf::RedactPolicy;
+use super::{
+    parse_config,
+    print_stdout,
+    read_input,
+    Error,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The file to read, or - for stdin.
+    #[arg(default_value = "-")]
+    file: PathBuf,
+
+    /// Keep IP addresses as they are.
+    #[arg(long)]
+    keep_addresses: bool,
+
+    /// Keep hostnames as they are.
+    #[arg(long)]
+    keep_hostnames: bool,
+
+    /// Mask paths matching the glob pattern, eg. '/home/*'. May be given
+    /// several times.
+    #[arg(long, value_name = "PATTERN", value_parser = pattern)]
+    path: Vec<String>,
+
+    /// Replace the whole value of the parameter, or $variable. May be given
+    /// several times.
+    #[arg(long, value_name = "NAME")]
+    param: Vec<String>,
+}
+
+// Check a glob pattern when the arguments are parsed.
+fn pattern(pattern: &str) -> Result<String, glob::PatternError> {
+    glob::Pattern::new(pattern).map(|_| pattern.to_string())
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let input = read_input(&args.file)?;
+    let config = parse_config(&args.file, &input)?;
+
+    let mut policy = RedactPolicy::new()
+        .addresses(!args.keep_addresses)
+        .hostnames(!args.keep_hostnames);
+
+    for pattern in &args.path {
+        // Checked when the arguments were parsed.
+        policy = policy.path(pattern).expect("invalid glob pattern");
+    }
+
+    for name in &args.param {
+        policy = policy.param(name);
+    }
+
+    print_stdout(|out| write!(out, "{}", config.redact(&policy)))
+}
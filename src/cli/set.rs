@@ -0,0 +1,74 @@
+// jailconf set: set a parameter for a jail in place.
+//
+// Only the statement setting the parameter is rewritten, everything else in
+// the file, including comments and whitespace, is kept as it was. With
+// --backup, the file being replaced is kept beside it first.
+use std::path::{
+    Path,
+    PathBuf,
+};
+use jailconf::{
+    LosslessConfig,
+    SaveOptions,
+};
+use tracing::info;
+use super::{
+    parse_error,
+    read_input,
+    write_output,
+    Error,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The file to edit, or - to read stdin and write stdout.
+    file: PathBuf,
+
+    /// The name of the jail.
+    jail: String,
+
+    /// The name of the parameter.
+    param: String,
+
+    /// The value to set.
+    value: String,
+
+    /// Add the value to the parameter with += rather than replacing it.
+    #[arg(short, long)]
+    append: bool,
+
+    /// Keep the file being replaced as a timestamped backup.
+    #[arg(short, long)]
+    backup: bool,
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let input = read_input(&args.file)?;
+    let mut config = LosslessConfig::parse(&input)
+        .map_err(|source| parse_error(&args.file, &input, source))?;
+
+    if args.append {
+        config.append_param(&args.jail, &args.param, &args.value)?;
+    }
+    else {
+        config.set_param(&args.jail, &args.param, &args.value)?;
+    }
+
+    info!("{}", config.change_summary().trim_end());
+
+    if !args.backup || args.file == Path::new("-") {
+        return write_output(&args.file, &config.to_source());
+    }
+
+    let options = SaveOptions::new().backup(true);
+    let backup = config.save_with(&args.file, &options).map_err(|source| Error::Io {
+        path: args.file.display().to_string(),
+        source,
+    })?;
+
+    if let Some(backup) = backup {
+        info!("kept {}", backup.display());
+    }
+
+    Ok(())
+}
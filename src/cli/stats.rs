@@ -0,0 +1,119 @@
+// jailconf stats: counts of what a file is made of.
+//
+// A quick sanity check of a generated configuration: how many jails it
+// configures, and how many parameters, variables, comments and includes it
+// has. --jails adds the number of parameters set for each jail, largest
+// first, which is where a jail the generator got wrong tends to stand out.
+use std::cmp::Reverse;
+use std::io::{
+    self,
+    Write,
+};
+use std::path::PathBuf;
+use jailconf::ConfigStats;
+use serde_json::{
+    json,
+    Map,
+    Value,
+};
+use super::{
+    parse_config,
+    print_stdout,
+    read_input,
+    Error,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The file to read, or - for stdin.
+    #[arg(default_value = "-")]
+    file: PathBuf,
+
+    /// Also print the number of parameters set for each jail.
+    #[arg(long)]
+    jails: bool,
+
+    /// Print the counts as JSON.
+    #[arg(short, long)]
+    json: bool,
+}
+
+// The counts, with their names as printed.
+fn counts(stats: &ConfigStats) -> [(&'static str, usize); 8] {
+    [
+        ("jails",     stats.jails()),
+        ("blocks",    stats.blocks()),
+        ("wildcards", stats.wildcards()),
+        ("params",    stats.params()),
+        ("appends",   stats.appends()),
+        ("variables", stats.variables()),
+        ("comments",  stats.comments()),
+        ("includes",  stats.includes()),
+    ]
+}
+
+// The per jail counts, largest first, keeping the order they're configured
+// in otherwise.
+fn jail_params(stats: &ConfigStats) -> Vec<(&str, usize)> {
+    let mut jails: Vec<(&str, usize)> = stats.jail_params()
+        .iter()
+        .map(|(name, count)| (name.as_str(), *count))
+        .collect();
+
+    jails.sort_by_key(|(_, count)| Reverse(*count));
+
+    jails
+}
+
+fn print_json(out: &mut impl Write, stats: &ConfigStats, jails: bool) -> io::Result<()> {
+    let mut output: Map<String, Value> = counts(stats).iter()
+        .map(|(name, count)| (name.to_string(), json!(count)))
+        .collect();
+
+    if jails {
+        let params = jail_params(stats).into_iter()
+            .map(|(name, count)| json!({"name": name, "params": count}))
+            .collect();
+
+        output.insert("jail_params".to_string(), Value::Array(params));
+    }
+
+    // A Value always serializes.
+    let output = serde_json::to_string_pretty(&Value::Object(output))
+        .expect("JSON serialization failed");
+
+    writeln!(out, "{}", output)
+}
+
+fn print(out: &mut impl Write, stats: &ConfigStats, jails: bool) -> io::Result<()> {
+    for (name, count) in counts(stats).iter() {
+        writeln!(out, "{:<10} {}", name, count)?;
+    }
+
+    if jails {
+        let jails = jail_params(stats);
+        let width = jails.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+
+        writeln!(out)?;
+
+        for (name, count) in jails {
+            writeln!(out, "{:<width$} {}", name, count, width = width)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let input = read_input(&args.file)?;
+    let stats = parse_config(&args.file, &input)?.stats();
+
+    print_stdout(|out| {
+        if args.json {
+            print_json(out, &stats, args.jails)
+        }
+        else {
+            print(out, &stats, args.jails)
+        }
+    })
+}
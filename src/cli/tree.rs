@@ -0,0 +1,23 @@
+// jailconf tree: the parsed statements as an indented tree, with spans.
+use std::io::Write;
+use std::path::PathBuf;
+use super::{
+    parse_config,
+    print_stdout,
+    read_input,
+    Error,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The file to read, or - for stdin.
+    #[arg(default_value = "-")]
+    file: PathBuf,
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let input = read_input(&args.file)?;
+    let config = parse_config(&args.file, &input)?;
+
+    print_stdout(|out| write!(out, "{}", config.dump_tree()))
+}
@@ -0,0 +1,400 @@
+// jailconf tui: browse a configuration interactively.
+//
+// Large configurations, inherited from whoever ran the host before, are
+// hard to follow in an editor: a jail's values come from globals, wildcard
+// blocks and parent jails as well as its own blocks. The inspector lists the
+// jails, and for the one selected every parameter it ends up with, its
+// values once variables are resolved and where the winning statement was
+// given. The source below follows the selection, with the line setting the
+// parameter marked.
+//
+// The linter is run on demand with `l`, listing its findings in place of
+// the parameters. Selecting one shows its line, and Enter on one for a jail
+// selects that jail.
+use std::convert::TryFrom;
+use std::io;
+use std::path::PathBuf;
+use jailconf::{
+    JailConfig,
+    Lint,
+};
+use ratatui::crossterm::event::{
+    self,
+    Event,
+    KeyCode,
+    KeyEventKind,
+};
+use ratatui::layout::{
+    Constraint,
+    Layout,
+    Rect,
+};
+use ratatui::style::{
+    Modifier,
+    Style,
+};
+use ratatui::text::Line;
+use ratatui::widgets::{
+    Block,
+    Borders,
+    List,
+    ListItem,
+    ListState,
+    Paragraph,
+};
+use ratatui::{
+    DefaultTerminal,
+    Frame,
+};
+use super::{
+    parse_config,
+    read_input,
+    Error,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The file to inspect.
+    file: PathBuf,
+}
+
+const HELP: &str = "↑/↓ move  tab switch pane  enter open  l lint  p parameters  q quit";
+
+// The pane keys move the selection in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Focus {
+    Jails,
+    Detail,
+}
+
+// A parameter of the selected jail, with where its value comes from.
+struct Param {
+    name:   String,
+    values: String,
+    source: String,
+    line:   Option<usize>,
+}
+
+struct App<'c, 'a> {
+    config:   &'c JailConfig<'a>,
+    resolved: &'c JailConfig<'a>,
+    lines:    Vec<&'c str>,
+    jails:    Vec<String>,
+    params:   Vec<Param>,
+    // The linter's findings, once it's been run and while they're shown.
+    lints:    Option<Vec<Lint>>,
+    focus:    Focus,
+    jail:     ListState,
+    detail:   ListState,
+    quit:     bool,
+}
+
+// Move a list's selection by delta, within len items.
+fn step(state: &mut ListState, len: usize, delta: isize) {
+    if len == 0 {
+        return;
+    }
+
+    let current = state.selected().unwrap_or(0) as isize;
+    let next = (current + delta).clamp(0, len as isize - 1);
+
+    state.select(Some(next as usize));
+}
+
+fn titled(title: &str, focused: bool) -> Block<'_> {
+    let block = Block::default().borders(Borders::ALL).title(title);
+
+    if focused {
+        block.border_style(Style::default().add_modifier(Modifier::BOLD))
+    }
+    else {
+        block
+    }
+}
+
+fn highlight() -> Style {
+    Style::default().add_modifier(Modifier::REVERSED)
+}
+
+impl<'c, 'a> App<'c, 'a> {
+    fn new(input: &'c str, config: &'c JailConfig<'a>, resolved: &'c JailConfig<'a>) -> Self {
+        let mut jails: Vec<String> = Vec::new();
+
+        // A jail may be configured across several blocks, and wildcard
+        // blocks aren't jails.
+        for block in config.jails().filter(|block| !block.is_wildcard()) {
+            if !jails.iter().any(|jail| jail == block.name()) {
+                jails.push(block.name().to_string());
+            }
+        }
+
+        let mut app = Self {
+            config,
+            resolved,
+            lines:  input.lines().collect(),
+            jails,
+            params: Vec::new(),
+            lints:  None,
+            focus:  Focus::Jails,
+            jail:   ListState::default(),
+            detail: ListState::default(),
+            quit:   false,
+        };
+
+        app.select_jail(0);
+        app
+    }
+
+    fn selected_jail(&self) -> Option<&str> {
+        self.jail.selected().and_then(|i| self.jails.get(i)).map(String::as_str)
+    }
+
+    // Select a jail, gathering its parameters.
+    fn select_jail(&mut self, index: usize) {
+        if self.jails.is_empty() {
+            return;
+        }
+
+        self.jail.select(Some(index));
+        self.detail.select(None);
+        self.params.clear();
+
+        let jail = self.jails[index].clone();
+        let params = self.resolved.inherited_params(&jail).unwrap_or_default();
+
+        for (name, values) in params {
+            let explanation = self.config.explain_param(&jail, &name);
+            let last = explanation.as_ref().and_then(|explanation| explanation.steps().last());
+
+            self.params.push(Param {
+                values: values.join(", "),
+                source: last.map_or_else(String::new, |step| step.source().to_string()),
+                line:   last.map(|step| step.statement().span().start().line()),
+                name,
+            });
+        }
+    }
+
+    // The line of the source to show, for the selection in the focused
+    // pane.
+    fn source_line(&self) -> Option<usize> {
+        let detail = self.detail.selected();
+
+        match (&self.lints, detail) {
+            (Some(lints), Some(i)) if self.focus == Focus::Detail => {
+                lints.get(i).map(|lint| lint.span().start().line())
+            },
+            (None, Some(i)) if self.focus == Focus::Detail => {
+                self.params.get(i).and_then(|param| param.line)
+            },
+            _ => {
+                let jail = self.selected_jail()?;
+
+                self.config.jail(jail).map(|block| block.span().start().line())
+            },
+        }
+    }
+
+    // Move the selection of the parameters or lints.
+    fn step_detail(&mut self, delta: isize) {
+        let len = self.lints.as_ref().map_or(self.params.len(), Vec::len);
+
+        step(&mut self.detail, len, delta);
+    }
+
+    fn open(&mut self) {
+        match self.focus {
+            Focus::Jails => {
+                self.focus = Focus::Detail;
+                self.step_detail(0);
+            },
+            Focus::Detail => {
+                let jail = self.lints.as_ref()
+                    .zip(self.detail.selected())
+                    .and_then(|(lints, i)| lints.get(i))
+                    .and_then(Lint::jail)
+                    .and_then(|jail| self.jails.iter().position(|name| name == jail));
+
+                if let Some(index) = jail {
+                    self.lints = None;
+                    self.select_jail(index);
+                    self.focus = Focus::Jails;
+                }
+            },
+        }
+    }
+
+    fn key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Jails  => Focus::Detail,
+                    Focus::Detail => Focus::Jails,
+                };
+                self.step_detail(0);
+            },
+            KeyCode::Enter => self.open(),
+            KeyCode::Char('l') => {
+                self.lints = Some(self.config.lint());
+                self.focus = Focus::Detail;
+                self.detail.select(None);
+                self.step_detail(0);
+            },
+            KeyCode::Char('p') => {
+                self.lints = None;
+                self.detail.select(None);
+            },
+            KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+            KeyCode::PageUp => self.move_by(-10),
+            KeyCode::PageDown => self.move_by(10),
+            _ => {},
+        }
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        match self.focus {
+            Focus::Jails => {
+                let mut state = self.jail.clone();
+                step(&mut state, self.jails.len(), delta);
+
+                if let Some(index) = state.selected() {
+                    self.select_jail(index);
+                }
+            },
+            Focus::Detail => self.step_detail(delta),
+        }
+    }
+
+    fn draw_detail(&mut self, frame: &mut Frame, area: Rect) {
+        let focused = self.focus == Focus::Detail;
+
+        let (title, items): (String, Vec<ListItem>) = match &self.lints {
+            Some(lints) => {
+                let items = lints.iter()
+                    .map(|lint| {
+                        let start = lint.span().start();
+
+                        ListItem::new(format!("{}:{}: {}", start.line(), start.column(), lint))
+                    })
+                    .collect();
+
+                (format!("Lints ({})", lints.len()), items)
+            },
+            None => {
+                let width = self.params.iter().map(|param| param.name.len()).max().unwrap_or(0);
+                let items = self.params.iter()
+                    .map(|param| {
+                        ListItem::new(format!(
+                            "{:width$}  {}  [{}]",
+                            param.name,
+                            param.values,
+                            param.source,
+                            width = width,
+                        ))
+                    })
+                    .collect();
+                let title = format!("Parameters of {}", self.selected_jail().unwrap_or("-"));
+
+                (title, items)
+            },
+        };
+
+        let list = List::new(items)
+            .block(titled(&title, focused))
+            .highlight_style(highlight());
+
+        frame.render_stateful_widget(list, area, &mut self.detail);
+    }
+
+    fn draw_source(&self, frame: &mut Frame, area: Rect) {
+        let current = self.source_line();
+        let width = self.lines.len().to_string().len();
+
+        let lines: Vec<Line> = self.lines.iter()
+            .enumerate()
+            .map(|(i, text)| {
+                let line = Line::from(format!("{:>width$} │ {}", i + 1, text, width = width));
+
+                if Some(i + 1) == current {
+                    line.style(highlight())
+                }
+                else {
+                    line
+                }
+            })
+            .collect();
+
+        // Keep the marked line in the middle of the pane.
+        let height = usize::from(area.height.saturating_sub(2));
+        let scroll = current.map_or(0, |line| line.saturating_sub(height / 2 + 1));
+        let scroll = u16::try_from(scroll).unwrap_or(u16::MAX);
+
+        let source = Paragraph::new(lines)
+            .block(titled("Source", false))
+            .scroll((scroll, 0));
+
+        frame.render_widget(source, area);
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, source, help] = Layout::vertical([
+            Constraint::Percentage(55),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+
+        let jail_width = self.jails.iter().map(String::len).max().unwrap_or(0) + 4;
+        let jail_width = u16::try_from(jail_width).unwrap_or(u16::MAX).clamp(12, 40);
+
+        let [jails, detail] = Layout::horizontal([
+            Constraint::Length(jail_width),
+            Constraint::Min(20),
+        ]).areas(main);
+
+        let items: Vec<ListItem> = self.jails.iter()
+            .map(|jail| ListItem::new(jail.as_str()))
+            .collect();
+        let list = List::new(items)
+            .block(titled("Jails", self.focus == Focus::Jails))
+            .highlight_style(highlight());
+
+        frame.render_stateful_widget(list, jails, &mut self.jail);
+        self.draw_detail(frame, detail);
+        self.draw_source(frame, source);
+        frame.render_widget(Paragraph::new(HELP), help);
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    self.key(key.code);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn run(args: &Args) -> Result<(), Error> {
+    let input = read_input(&args.file)?;
+    let config = parse_config(&args.file, &input)?;
+    // Values are shown as written if the variables can't be resolved, so
+    // that a configuration which jail(8) would refuse can still be browsed.
+    let resolved = config.resolve_variables().unwrap_or_else(|_| config.clone());
+    let mut app = App::new(&input, &config, &resolved);
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+
+    result.map_err(|source| Error::Io {
+        path: "terminal".to_string(),
+        source,
+    })
+}
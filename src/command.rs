@@ -0,0 +1,113 @@
+// Rendering of a jail as a jail(8) command line.
+//
+// jail(8) can create a jail from parameters given on its command line as
+// well as from jail.conf. to_jail_command gives the command line equivalent
+// to a jail's effective parameters, quoted for sh(1), which is handy for
+// seeing what jail(8) would be asked to do, and for driving jail(8) without
+// a configuration file.
+use crate::schema::{
+    parameter,
+    ParamType,
+};
+use crate::JailConfig;
+
+// Characters which never need quoting in a shell word.
+fn is_shell_safe(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "._-/:,@%+=".contains(c)
+}
+
+// Quote a word for sh(1), using single quotes where anything in it would
+// otherwise be interpreted by the shell.
+fn shell_quote(word: &str) -> String {
+    if !word.is_empty() && word.chars().all(is_shell_safe) {
+        return word.to_string();
+    }
+
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+// Address lists are given to jail(8) as a single comma separated value.
+fn is_address_list(name: &str) -> bool {
+    parameter(name).is_some_and(|param| {
+        param.kind() == ParamType::Ip4 || param.kind() == ParamType::Ip6
+    })
+}
+
+impl<'a> JailConfig<'a> {
+    /// The `jail -c` command line which would create the named jail with its
+    /// effective parameters, or None if there's no such jail.
+    ///
+    /// Booleans set without a value are given by name alone. Addresses are
+    /// joined into one comma separated value, while other parameters with
+    /// several values, such as exec.start, are given once per value. Values
+    /// are used as written, use `resolve_variables` first to expand any
+    /// variable references in them.
+    pub fn to_jail_command(&self, jail: &str) -> Option<String> {
+        let params = self.effective_params(jail)?;
+        let mut words = vec![
+            "jail".to_string(),
+            "-c".to_string(),
+            format!("name={}", shell_quote(jail)),
+        ];
+
+        for (name, values) in &params {
+            if name == "name" {
+                continue;
+            }
+
+            if values.len() == 1 && values[0] == "true" {
+                let kind = parameter(name).map(|param| param.kind());
+
+                if kind == Some(ParamType::Bool) {
+                    words.push(name.clone());
+                    continue;
+                }
+            }
+
+            if is_address_list(name) {
+                words.push(format!("{}={}", name, shell_quote(&values.join(","))));
+                continue;
+            }
+
+            for value in values {
+                words.push(format!("{}={}", name, shell_quote(value)));
+            }
+        }
+
+        Some(words.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_to_jail_command() {
+        let input = indoc!(r#"
+            exec.start = "/bin/sh /etc/rc";
+            persist;
+            nginx {
+                path = /usr/jails/nginx;
+                ip4.addr = "lo1|127.0.1.1";
+                ip4.addr += 127.0.1.2;
+                exec.poststart = "logger 'nginx up'";
+                exec.poststart += "touch /var/run/nginx";
+            }
+            "#);
+
+        let config = JailConfig::parse(input).unwrap();
+
+        assert_eq!(config.to_jail_command("nginx").unwrap(), concat!(
+            "jail -c name=nginx",
+            r#" exec.poststart='logger '\''nginx up'\'''"#,
+            " exec.poststart='touch /var/run/nginx'",
+            " exec.start='/bin/sh /etc/rc'",
+            " ip4.addr='lo1|127.0.1.1,127.0.1.2'",
+            " path=/usr/jails/nginx",
+            " persist",
+        ));
+        assert_eq!(config.to_jail_command("db"), None);
+    }
+}
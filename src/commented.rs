@@ -0,0 +1,214 @@
+// Attachment of comments to the statements they describe.
+//
+// The parser keeps comments as statements of their own, alongside the
+// parameters and blocks around them. attach_comments pairs each other
+// statement with the run of comments on the lines directly above it, its
+// leading comments, and a comment following it on the same line, its
+// trailing comment. A blank line between a comment and the statement below
+// it leaves the comment unattached.
+//
+// Attachment works from the line numbers of spans, so is only meaningful for
+// parsed statements. Statements made with the builders all claim to be on
+// the first line.
+use crate::{
+    JailComment,
+    JailConf,
+    JailConfig,
+};
+
+/// A statement along with the comments describing it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Commented<'c, 'a> {
+    statement: &'c JailConf<'a>,
+    leading:   Vec<&'c JailComment<'a>>,
+    trailing:  Option<&'c JailComment<'a>>,
+    children:  Vec<Commented<'c, 'a>>,
+}
+
+impl<'c, 'a> Commented<'c, 'a> {
+    /// The statement the comments are attached to.
+    pub fn statement(&self) -> &'c JailConf<'a> {
+        self.statement
+    }
+
+    /// The comments on the lines directly above the statement.
+    pub fn leading(&self) -> &[&'c JailComment<'a>] {
+        &self.leading
+    }
+
+    /// The comment on the same line as the end of the statement. For a block
+    /// this may also be a comment following its opening brace.
+    pub fn trailing(&self) -> Option<&'c JailComment<'a>> {
+        self.trailing
+    }
+
+    /// The text of the leading comments, one line per comment line, with
+    /// comment markers and surrounding whitespace removed. Leading asterisks
+    /// in C style comments are removed too.
+    pub fn doc(&self) -> String {
+        let lines: Vec<&str> = self.leading.iter()
+            .flat_map(|comment| comment.comment().lines())
+            .map(|line| line.trim().trim_start_matches('*').trim_start())
+            .collect();
+
+        lines.join("\n").trim().to_string()
+    }
+
+    /// The statements within a block, with their own comments attached.
+    /// Empty for other statements.
+    pub fn children(&self) -> &[Commented<'c, 'a>] {
+        &self.children
+    }
+}
+
+fn start_line(statement: &JailConf) -> usize {
+    statement.span().start().line()
+}
+
+fn end_line(statement: &JailConf) -> usize {
+    statement.span().end().line()
+}
+
+// Whether there's a blank line between the last line and line.
+fn after_gap(last: Option<usize>, line: usize) -> bool {
+    matches!(last, Some(last) if line > last + 1)
+}
+
+// Attach comments within a list of statements. opening is the line of the
+// brace opening the enclosing block, if any, and a comment on it is
+// returned separately as the block's trailing comment.
+fn attach<'c, 'a>(statements: &'c [JailConf<'a>], opening: Option<usize>)
+-> (Vec<Commented<'c, 'a>>, Option<&'c JailComment<'a>>) {
+    let mut attached: Vec<Commented> = Vec::new();
+    let mut pending: Vec<&JailComment> = Vec::new();
+    let mut opening_comment = None;
+    // The line the last statement or comment ended on.
+    let mut last_line = opening;
+
+    for statement in statements {
+        match statement {
+            JailConf::Comment(comment) => {
+                let line = start_line(statement);
+
+                match attached.last_mut() {
+                    Some(previous) if pending.is_empty()
+                        && previous.trailing.is_none()
+                        && end_line(previous.statement) == line => {
+                        previous.trailing = Some(comment);
+                    },
+                    None if pending.is_empty()
+                        && opening_comment.is_none()
+                        && opening == Some(line) => {
+                        opening_comment = Some(comment);
+                    },
+                    _ => {
+                        // A gap between comments starts a new run.
+                        if after_gap(last_line, line) {
+                            pending.clear();
+                        }
+
+                        pending.push(comment);
+                    },
+                }
+            },
+            _ => {
+                let line = start_line(statement);
+
+                if after_gap(last_line, line) {
+                    pending.clear();
+                }
+
+                let (children, trailing) = match statement {
+                    JailConf::Block(block) => attach(block.params(), Some(line)),
+                    _                      => (Vec::new(), None),
+                };
+
+                attached.push(Commented {
+                    statement,
+                    leading: std::mem::take(&mut pending),
+                    trailing,
+                    children,
+                });
+            },
+        }
+
+        last_line = Some(end_line(statement));
+    }
+
+    (attached, opening_comment)
+}
+
+/// Attach comments to the statements they describe, see `Commented`. Every
+/// statement other than a comment is returned, in order.
+pub fn attach_comments<'c, 'a>(statements: &'c [JailConf<'a>])
+-> Vec<Commented<'c, 'a>> {
+    attach(statements, None).0
+}
+
+impl<'a> JailConfig<'a> {
+    /// The statements of the configuration with their comments attached,
+    /// see `attach_comments`.
+    pub fn commented(&self) -> Vec<Commented<'_, 'a>> {
+        attach_comments(self.statements())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        # Unattached, there's a blank line below.
+
+        # Keep jails running without processes.
+        persist; # Required for vnet.
+        /*
+         * The web server.
+         */
+        www { // Public facing.
+            # Where it lives.
+            path = "/jails/www";
+            ip4.addr = "192.0.2.1"; # Primary address.
+
+            # Also unattached.
+        } # End of www.
+        "#);
+
+    #[test]
+    fn test_attach_comments() {
+        let config = JailConfig::parse(INPUT).unwrap();
+        let commented = config.commented();
+
+        assert_eq!(commented.len(), 2);
+
+        let persist = &commented[0];
+        assert_eq!(persist.statement().source_text(), "persist;");
+        assert_eq!(persist.doc(), "Keep jails running without processes.");
+        assert_eq!(persist.trailing().unwrap().comment(), " Required for vnet.");
+
+        let www = &commented[1];
+        assert_eq!(www.doc(), "The web server.");
+        assert_eq!(www.trailing().unwrap().comment(), " Public facing.");
+        assert_eq!(www.children().len(), 2);
+
+        let path = &www.children()[0];
+        assert_eq!(path.doc(), "Where it lives.");
+        assert!(path.trailing().is_none());
+
+        let addr = &www.children()[1];
+        assert!(addr.leading().is_empty());
+        assert_eq!(addr.trailing().unwrap().comment(), " Primary address.");
+    }
+
+    #[test]
+    fn test_attach_comments_without_comments() {
+        let config = JailConfig::parse("persist;\nwww {\n    persist;\n}\n").unwrap();
+        let commented = config.commented();
+
+        assert_eq!(commented.len(), 2);
+        assert!(commented.iter().all(|c| c.leading().is_empty() && c.trailing().is_none()));
+        assert_eq!(commented[1].children().len(), 1);
+        assert_eq!(commented[1].doc(), "");
+    }
+}
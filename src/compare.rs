@@ -0,0 +1,323 @@
+// Order-insensitive comparison of jail configurations.
+//
+// Two configurations are considered equivalent when every jail ends up with
+// the same effective parameters, no matter what order the jails or their
+// statements appear in. This is useful for checking that a refactor which only
+// reorganises a file hasn't changed what jail(8) will actually do.
+//
+// JailConfig::semantically_eq is stricter, also comparing the parameters
+// given outside of any jail, which jail(8) applies to jails created on its
+// command line too. Comments, whitespace, quoting and ordering don't matter.
+//
+// Both expand variables before comparing, so that changing a variable's
+// value changes every parameter referring to it, while a value written out
+// in full is the same as one built from variables. References to variables
+// defined nowhere, eg. those jail(8) would take from the environment, are
+// compared as written.
+use std::collections::BTreeMap;
+use crate::config::apply;
+use crate::{
+    EffectiveParams,
+    JailConf,
+    JailConfig,
+};
+
+// Keep references to variables the configuration doesn't define as they
+// are, other than $name, so that globals referring to it are still expanded
+// for each jail.
+fn undefined(name: &str) -> Option<String> {
+    Some(format!("${{{}}}", name)).filter(|_| name != "name")
+}
+
+// The parameters given outside of any jail, and the effective parameters of
+// every jail, with variables expanded. Configurations where they can't be,
+// as a variable refers back to itself, are compared as written.
+fn jail_params(config: &JailConfig) -> (EffectiveParams, BTreeMap<String, EffectiveParams>) {
+    match config.resolve_variables_with(&undefined) {
+        Ok(resolved) => effective(&resolved),
+        Err(_)       => effective(config),
+    }
+}
+
+fn effective(config: &JailConfig) -> (EffectiveParams, BTreeMap<String, EffectiveParams>) {
+    let mut globals = EffectiveParams::new();
+    apply(&mut globals, config.top_level_params());
+
+    let jails = config.jails()
+        .filter(|block| !block.is_wildcard())
+        .filter_map(|block| {
+            let params = config.effective_params(block.name())?;
+
+            Some((block.name().to_string(), params))
+        })
+        .collect();
+
+    (globals, jails)
+}
+
+/// Compare two configurations, ignoring comments and the order of jails and
+/// the statements within them.
+///
+/// Configurations are equivalent if every jail has the same effective
+/// parameters, once variables are expanded. The order of values appended
+/// with `+=` is still significant, as jail(8) runs appended commands in
+/// order. Configurations without any jails compare their global parameters
+/// instead.
+pub fn equivalent(a: &[JailConf], b: &[JailConf]) -> bool {
+    let (a_globals, a_jails) = jail_params(&JailConfig::new(a.to_vec()));
+    let (b_globals, b_jails) = jail_params(&JailConfig::new(b.to_vec()));
+
+    if a_jails.is_empty() && b_jails.is_empty() {
+        return a_globals == b_globals;
+    }
+
+    a_jails == b_jails
+}
+
+// 64-bit FNV-1a, see http://www.isthe.com/chongo/tech/comp/fnv/
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+struct Fnv(u64);
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    // Write a string with its length first, so that the boundaries between
+    // strings are part of the hash.
+    fn write_str(&mut self, s: &str) {
+        self.write(&(s.len() as u64).to_le_bytes());
+        self.write(s.as_bytes());
+    }
+
+    fn write_params(&mut self, params: &EffectiveParams) {
+        self.write(&(params.len() as u64).to_le_bytes());
+
+        for (name, values) in params {
+            self.write_str(name);
+            self.write(&(values.len() as u64).to_le_bytes());
+
+            for value in values {
+                self.write_str(value);
+            }
+        }
+    }
+}
+
+impl<'a> JailConfig<'a> {
+    /// Whether two configurations give every jail the same effective
+    /// parameters, see `effective_params`, and have the same parameters
+    /// outside of any jail, once variables are expanded. Comments,
+    /// whitespace, quoting and the order of parameters and jails are all
+    /// ignored.
+    pub fn semantically_eq(&self, other: &JailConfig) -> bool {
+        jail_params(self) == jail_params(other)
+    }
+
+    /// A hash of the effective parameters, equal for any two configurations
+    /// which are `semantically_eq`. The hash is 64-bit FNV-1a, which doesn't
+    /// depend on the release of Rust, so it can be stored to spot when a
+    /// deployed configuration has changed without keeping the old one.
+    pub fn semantic_hash(&self) -> u64 {
+        let (globals, jails) = jail_params(self);
+        let mut hasher = Fnv(FNV_OFFSET);
+
+        hasher.write_params(&globals);
+        hasher.write(&(jails.len() as u64).to_le_bytes());
+
+        for (name, params) in &jails {
+            hasher.write_str(name);
+            hasher.write_params(params);
+        }
+
+        hasher.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use indoc::indoc;
+
+    #[test]
+    fn test_equivalent_reordered() {
+        let a = parse(indoc!(r#"
+            nginx {
+                host.hostname = "nginx";
+                persist;
+            }
+            jail2 {
+                persist;
+            }
+            "#)).unwrap();
+
+        let b = parse(indoc!(r#"
+            # Comments are ignored
+            jail2 {
+                persist;
+            }
+            nginx {
+                persist;
+                host.hostname = "nginx";
+            }
+            "#)).unwrap();
+
+        assert!(equivalent(&a, &b));
+    }
+
+    #[test]
+    fn test_equivalent_globals_hoisted() {
+        let a = parse("persist;\nnginx {\n    path = \"/jails/nginx\";\n}\n").unwrap();
+        let b = parse("nginx {\n    path = \"/jails/nginx\";\n    persist;\n}\n").unwrap();
+
+        assert!(equivalent(&a, &b));
+    }
+
+    #[test]
+    fn test_equivalent_overridden_value() {
+        let a = parse("nginx {\n    path = \"/a\";\n    path = \"/b\";\n}\n").unwrap();
+        let b = parse("nginx {\n    path = \"/b\";\n}\n").unwrap();
+
+        assert!(equivalent(&a, &b));
+    }
+
+    #[test]
+    fn test_not_equivalent_append_order() {
+        let a = parse(indoc!(r#"
+            nginx {
+                exec.start += "/bin/one";
+                exec.start += "/bin/two";
+            }
+            "#)).unwrap();
+
+        let b = parse(indoc!(r#"
+            nginx {
+                exec.start += "/bin/two";
+                exec.start += "/bin/one";
+            }
+            "#)).unwrap();
+
+        assert!(!equivalent(&a, &b));
+    }
+
+    #[test]
+    fn test_equivalent_booleans() {
+        let a = parse("nginx {\n    persist;\n}\n").unwrap();
+        let b = parse("nginx {\n    persist = true;\n}\n").unwrap();
+
+        assert!(equivalent(&a, &b));
+
+        let a = parse("nginx {\n    allow.nomount;\n}\n").unwrap();
+        let b = parse("nginx {\n    allow.mount = false;\n}\n").unwrap();
+
+        assert!(equivalent(&a, &b));
+    }
+
+    #[test]
+    fn test_not_equivalent_different_value() {
+        let a = parse("nginx {\n    path = \"/a\";\n}\n").unwrap();
+        let b = parse("nginx {\n    path = \"/b\";\n}\n").unwrap();
+
+        assert!(!equivalent(&a, &b));
+    }
+
+    #[test]
+    fn test_not_equivalent_variable() {
+        let a = parse("$base = \"/a\";\nwww {\n    path = \"$base/www\";\n}\n").unwrap();
+        let b = parse("$base = \"/b\";\nwww {\n    path = \"$base/www\";\n}\n").unwrap();
+
+        assert!(!equivalent(&a, &b));
+
+        // A value written out in full is the same as one from variables.
+        let c = parse("www {\n    path = \"/a/www\";\n}\n").unwrap();
+
+        assert!(equivalent(&a, &c));
+    }
+
+    #[test]
+    fn test_semantically_eq() {
+        let a = JailConfig::parse(indoc!(r#"
+            persist;
+            nginx {
+                host.hostname = "nginx";
+                ip4.addr = 127.0.1.1;
+                ip4.addr += 127.0.1.2;
+            }
+            "#)).unwrap();
+
+        let b = JailConfig::parse(indoc!(r#"
+            # Comments and layout are ignored.
+            persist = true;
+            nginx { ip4.addr = "127.0.1.1"; ip4.addr += '127.0.1.2'; host.hostname = nginx; }
+            "#)).unwrap();
+
+        assert!(a.semantically_eq(&b));
+        assert_eq!(a.semantic_hash(), b.semantic_hash());
+
+        // Moving a global parameter into the jail is a change in structure.
+        let c = JailConfig::parse(indoc!(r#"
+            nginx {
+                persist;
+                host.hostname = "nginx";
+                ip4.addr = 127.0.1.1;
+                ip4.addr += 127.0.1.2;
+            }
+            "#)).unwrap();
+
+        assert!(!a.semantically_eq(&c));
+        assert_ne!(a.semantic_hash(), c.semantic_hash());
+        assert!(equivalent(a.statements(), c.statements()));
+    }
+
+    #[test]
+    fn test_semantically_eq_effective() {
+        // Merging the values into one list changes what jail(8) sees.
+        let a = JailConfig::parse("www {\n    exec.start = \"echo a,b\";\n    exec.start += \"echo c\";\n}\n").unwrap();
+        let b = JailConfig::parse("www {\n    exec.start = \"echo a,b\", \"echo c\";\n}\n").unwrap();
+
+        assert!(!a.semantically_eq(&b));
+
+        // The "no" form of a boolean is the boolean set to false.
+        let c = JailConfig::parse("www {\n    allow.nomount;\n}\n").unwrap();
+        let d = JailConfig::parse("www {\n    allow.mount = false;\n}\n").unwrap();
+
+        assert!(c.semantically_eq(&d));
+        assert_eq!(c.semantic_hash(), d.semantic_hash());
+    }
+
+    #[test]
+    fn test_semantically_eq_variables() {
+        let a = JailConfig::parse("$base = \"/a\";\npath = \"$base/$name\";\nwww {\n}\n").unwrap();
+        let b = JailConfig::parse("$base = \"/b\";\npath = \"$base/$name\";\nwww {\n}\n").unwrap();
+
+        assert!(!a.semantically_eq(&b));
+        assert_ne!(a.semantic_hash(), b.semantic_hash());
+
+        let c = JailConfig::parse("$base = \"/a\";\npath = \"${base}/${name}\";\nwww {\n}\n").unwrap();
+
+        assert!(a.semantically_eq(&c));
+        assert_eq!(a.semantic_hash(), c.semantic_hash());
+
+        // Variables defined nowhere are compared as written.
+        let d = JailConfig::parse("www {\n    path = \"$HOME\";\n}\n").unwrap();
+        let e = JailConfig::parse("www {\n    path = \"$USER\";\n}\n").unwrap();
+
+        assert!(d.semantically_eq(&d.clone()));
+        assert!(!d.semantically_eq(&e));
+    }
+
+    #[test]
+    fn test_semantic_hash_stable() {
+        let config = JailConfig::parse("persist;\nwww {\n    path = /jails/www;\n}\n").unwrap();
+
+        // The hash mustn't change between releases of Rust.
+        assert_eq!(config.semantic_hash(), 9_871_298_026_080_824_527);
+        assert_ne!(config.semantic_hash(), JailConfig::parse("persist;\n").unwrap().semantic_hash());
+    }
+}
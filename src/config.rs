@@ -0,0 +1,531 @@
+// A parsed configuration with convenient lookups.
+//
+// The parser produces a flat list of statements which consumers would
+// otherwise have to pattern match themselves. JailConfig wraps that list and
+// answers the common questions directly: which jails are configured, and what
+// value a parameter has for a given jail once global parameters are taken
+// into account.
+//
+// Looking a jail up by name would mean scanning every statement, so the
+// position of each jail's first block is indexed the first time one is
+// looked up. A JailConfig can't be changed once made, so the index never
+// goes stale. It's built behind a OnceLock so that a configuration can be
+// shared between threads, see ArcJailConfig. The map by_name returns is made
+// from the index, borrowing the blocks rather than copying them.
+use std::collections::{
+    BTreeMap,
+    HashMap,
+    HashSet,
+};
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+#[cfg(feature = "serde")]
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use crate::name::negated_bool;
+use crate::{
+    into_owned,
+    parse,
+    parse_with,
+    resolve_variables,
+    resolve_variables_with,
+    JailBlock,
+    JailComment,
+    JailConf,
+    ParseError,
+    ParseOptions,
+    VariableError,
+    VariableResolver,
+};
+
+/// The effective values of every parameter for a jail, keyed by parameter
+/// name. Values are in the order they were declared.
+pub type EffectiveParams = BTreeMap<String, Vec<String>>;
+
+// The name and value of a parameter statement. Booleans given without a
+// value, eg. `persist;`, have the value "true".
+fn pair<'c>(statement: &'c JailConf) -> Option<(&'c str, &'c str)> {
+    match statement {
+        JailConf::ParamBool(param)  => Some((param.name(), "true")),
+        JailConf::ParamValue(param) => Some((param.name(), param.value())),
+        _                           => None,
+    }
+}
+
+// Add every comment in statements to comments, including those within
+// blocks.
+fn collect_comments<'c, 'a>(
+    statements: &'c [JailConf<'a>],
+    comments:   &mut Vec<&'c JailComment<'a>>,
+) {
+    for statement in statements {
+        match statement {
+            JailConf::Comment(comment) => comments.push(comment),
+            JailConf::Block(block)     => collect_comments(block.params(), comments),
+            _                          => {},
+        }
+    }
+}
+
+// Apply parameter statements in order on top of params. An assignment with
+// `=` replaces any earlier values while `+=` adds to them. The "no" form of a
+// boolean sets the boolean itself, eg. `allow.nomount;` sets allow.mount to
+// false.
+pub(crate) fn apply<'s, 'c: 's, I>(params: &mut EffectiveParams, statements: I)
+where I: IntoIterator<Item = &'s JailConf<'c>> {
+    for statement in statements {
+        let negated = match statement {
+            JailConf::ParamBool(param)  => negated_bool(param.name(), None),
+            JailConf::ParamValue(param) if !param.is_append() => {
+                negated_bool(param.name(), Some(param.value()))
+            },
+            _ => None,
+        };
+
+        if let Some((name, value)) = negated {
+            params.insert(name, vec![value.to_string()]);
+            continue;
+        }
+
+        match statement {
+            JailConf::ParamValue(param) => {
+                let values = params
+                    .entry(param.name().to_string())
+                    .or_default();
+
+                if !param.is_append() {
+                    values.clear();
+                }
+
+                values.push(param.value().to_string());
+            },
+            JailConf::ParamBool(param) => {
+                params.insert(param.name().to_string(), vec!["true".into()]);
+            },
+            _ => {},
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct JailConfig<'a> {
+    statements: Vec<JailConf<'a>>,
+    // The position in statements of the first block for each jail, built
+    // on first use.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    index:      OnceLock<HashMap<String, usize>>,
+}
+
+// Two configurations are equal if their statements are, whether or not
+// either has been indexed yet.
+impl<'a> PartialEq for JailConfig<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.statements == other.statements
+    }
+}
+
+impl<'a> JailConfig<'a> {
+    /// Create a configuration from already parsed statements.
+    pub fn new(statements: Vec<JailConf<'a>>) -> Self {
+        Self {
+            statements,
+            index: OnceLock::new(),
+        }
+    }
+
+    fn index(&self) -> &HashMap<String, usize> {
+        self.index.get_or_init(|| {
+            let mut index = HashMap::new();
+
+            for (i, statement) in self.statements.iter().enumerate() {
+                if let JailConf::Block(block) = statement {
+                    index.entry(block.name().to_string()).or_insert(i);
+                }
+            }
+
+            index
+        })
+    }
+
+    /// Parse the given input into a configuration.
+    pub fn parse(input: &'a str) -> Result<Self, ParseError> {
+        parse(input).map(Self::new)
+    }
+
+    /// Parse the given input into a configuration, within the limits set by
+    /// options.
+    pub fn parse_with(input: &'a str, options: &ParseOptions) -> Result<Self, ParseError> {
+        parse_with(input, options).map(Self::new)
+    }
+
+    /// All statements in the configuration, in the order they were given.
+    pub fn statements(&self) -> &[JailConf<'a>] {
+        &self.statements
+    }
+
+    /// Consume the configuration, returning its statements.
+    pub fn into_statements(self) -> Vec<JailConf<'a>> {
+        self.statements
+    }
+
+    /// The top level jail blocks, in the order they were given.
+    pub fn jails(&self) -> impl Iterator<Item = &JailBlock<'a>> {
+        self.statements.iter().filter_map(|statement| match statement {
+            JailConf::Block(block) => Some(block),
+            _                      => None,
+        })
+    }
+
+    /// The names of the configured jails, each once, in the order they're
+    /// first configured. A jail may be configured across several blocks,
+    /// and wildcard blocks aren't jails.
+    pub fn jail_names(&self) -> Vec<&str> {
+        let mut seen = HashSet::new();
+
+        self.jails()
+            .filter(|block| !block.is_wildcard())
+            .map(JailBlock::name)
+            .filter(|name| seen.insert(*name))
+            .collect()
+    }
+
+    /// The parameters given outside of any jail block, skipping comments
+    /// and any other statements.
+    pub fn top_level_params(&self) -> impl Iterator<Item = &JailConf<'a>> {
+        self.statements.iter().filter(|statement| statement.is_param())
+    }
+
+    /// Every comment in the configuration, including those within jail
+    /// blocks, in the order they were given.
+    pub fn comments(&self) -> impl Iterator<Item = &JailComment<'a>> {
+        let mut comments = Vec::new();
+
+        collect_comments(&self.statements, &mut comments);
+        comments.into_iter()
+    }
+
+    /// Find the block for the named jail. If the jail has several blocks,
+    /// this is the first of them.
+    pub fn jail(&self, name: &str) -> Option<&JailBlock<'a>> {
+        match self.statements.get(*self.index().get(name)?) {
+            Some(JailConf::Block(block)) => Some(block),
+            _                            => None,
+        }
+    }
+
+    /// The first block for every jail, keyed by jail name, for tools making
+    /// many lookups. The blocks are borrowed from the configuration.
+    pub fn by_name(&self) -> HashMap<&str, &JailBlock<'a>> {
+        self.index()
+            .iter()
+            .filter_map(|(name, i)| match &self.statements[*i] {
+                JailConf::Block(block) => Some((name.as_str(), block)),
+                _                      => None,
+            })
+            .collect()
+    }
+
+    /// The names and values of the global parameters, those given outside of
+    /// any jail block.
+    pub fn globals<'c>(&'c self)
+    -> impl Iterator<Item = (&'c str, &'c str)> + 'c {
+        let statements: &'c [JailConf<'c>] = &self.statements;
+
+        statements.iter().filter_map(pair)
+    }
+
+    /// The names and values of every parameter applying to the named jail,
+    /// global parameters first, followed by those in any wildcard blocks
+    /// matching the jail and then those in the jail's own blocks. Yields
+    /// nothing if the jail doesn't exist.
+    pub fn params<'c>(&'c self, jail: &str)
+    -> impl Iterator<Item = (&'c str, &'c str)> + 'c {
+        let statements = match self.jail(jail) {
+            Some(_) => self.applying(jail),
+            None    => Vec::new(),
+        };
+
+        statements.into_iter().filter_map(pair)
+    }
+
+    /// The value of a parameter for the named jail, see `effective_params`.
+    /// A value in the jail's block takes precedence over one in a wildcard
+    /// block, which takes precedence over a global one. For parameters built
+    /// up with `+=`, this is the last value added, see `get_values` for all
+    /// of them.
+    pub fn get_param(&self, jail: &str, name: &str) -> Option<String> {
+        self.get_values(jail, name).pop()
+    }
+
+    /// All values of a parameter for the named jail, see `effective_params`.
+    /// An assignment with `=` replaces any earlier values, while `+=` adds
+    /// to them.
+    pub fn get_values(&self, jail: &str, name: &str) -> Vec<String> {
+        self.effective_params(jail)
+            .and_then(|mut params| params.remove(name))
+            .unwrap_or_default()
+    }
+
+    /// The fully resolved parameters for the named jail, or None if there's
+    /// no such jail.
+    ///
+    /// Global parameters are applied first as defaults, followed by those in
+    /// every wildcard block matching the jail, eg. `*` or `web.*`, and then
+    /// those in every block for the jail. An assignment with `=` replaces
+    /// any earlier values while `+=` adds to them, so list parameters end up
+    /// with all of their values in declaration order. Booleans given without
+    /// a value have the value "true", and the "no" form of a boolean, eg.
+    /// `allow.nomount`, gives the boolean the value "false".
+    ///
+    /// Values are as written, use `resolve_variables` first to expand any
+    /// variable references in them.
+    pub fn effective_params(&self, jail: &str) -> Option<EffectiveParams> {
+        self.jail(jail)?;
+
+        let mut params = EffectiveParams::new();
+
+        apply(&mut params, self.applying(jail));
+
+        Some(params)
+    }
+
+    /// Expand variable references in every value, see `resolve_variables`.
+    pub fn resolve_variables(&self) -> Result<Self, VariableError> {
+        resolve_variables(&self.statements).map(Self::new)
+    }
+
+    /// Expand variable references in every value, looking up those the
+    /// configuration doesn't define with the resolver, see
+    /// `resolve_variables_with`.
+    pub fn resolve_variables_with<R>(&self, resolver: &R) -> Result<Self, VariableError>
+    where
+        R: VariableResolver,
+    {
+        resolve_variables_with(&self.statements, resolver).map(Self::new)
+    }
+}
+
+impl<'a> JailBlock<'a> {
+    /// The values of every parameter set within the block, keyed by name,
+    /// as `JailConfig::effective_params` applies them. An assignment with `=`
+    /// replaces any earlier values while `+=` adds to them, booleans given
+    /// without a value have the value "true", and the "no" form of a boolean
+    /// sets the boolean to "false".
+    pub fn param_map(&self) -> EffectiveParams {
+        let mut params = EffectiveParams::new();
+
+        apply(&mut params, self.params());
+        params
+    }
+}
+
+impl<'a> From<Vec<JailConf<'a>>> for JailConfig<'a> {
+    fn from(statements: Vec<JailConf<'a>>) -> Self {
+        Self::new(statements)
+    }
+}
+
+impl<'a> TryFrom<&'a str> for JailConfig<'a> {
+    type Error = ParseError;
+
+    fn try_from(input: &'a str) -> Result<Self, Self::Error> {
+        Self::parse(input)
+    }
+}
+
+// FromStr can't borrow from the string it's given, so the configuration owns
+// all of its data.
+impl FromStr for JailConfig<'static> {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s).map(|config| Self::new(into_owned(config)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        # Global settings
+        persist;
+        path = "/jails/default";
+        nginx {
+            path = "/jails/nginx";
+            # Listens on two addresses.
+            ip4.addr = "127.0.1.1";
+            ip4.addr += "127.0.1.2";
+        }
+        postgres {
+            host.hostname = "db";
+        }
+        "#);
+
+    #[test]
+    fn test_jails() {
+        let config = JailConfig::parse(INPUT).unwrap();
+        let names: Vec<&str> = config.jails().map(|jail| jail.name()).collect();
+
+        assert_eq!(names, vec!["nginx", "postgres"]);
+        assert_eq!(config.jail("postgres").unwrap().name(), "postgres");
+        assert!(config.jail("missing").is_none());
+    }
+
+    #[test]
+    fn test_iterators() {
+        let config = JailConfig::parse(INPUT).unwrap();
+
+        let globals: Vec<&str> = config.top_level_params()
+            .map(JailConf::source_text)
+            .collect();
+        assert_eq!(globals, vec!["persist;", "path = \"/jails/default\";"]);
+
+        let comments: Vec<&str> = config.comments().map(JailComment::comment).collect();
+        assert_eq!(comments, vec![" Global settings", " Listens on two addresses."]);
+
+        let nginx = config.jail("nginx").unwrap();
+        assert_eq!(nginx.params_iter().count(), 3);
+        assert!(nginx.params_iter().all(JailConf::is_param));
+    }
+
+    #[test]
+    fn test_by_name() {
+        let config = JailConfig::parse(INPUT).unwrap();
+        let jails = config.by_name();
+
+        assert_eq!(jails.len(), 2);
+        assert_eq!(jails["nginx"].params().len(), 4);
+        assert!(std::ptr::eq(jails["postgres"], config.jail("postgres").unwrap()));
+        assert!(!jails.contains_key("missing"));
+
+        let params = jails["nginx"].param_map();
+        assert_eq!(params["path"], vec!["/jails/nginx"]);
+        assert_eq!(params["ip4.addr"], vec!["127.0.1.1", "127.0.1.2"]);
+    }
+
+    #[test]
+    fn test_jail_names() {
+        let config = JailConfig::parse("www {\n}\n* {\n}\ndb {\n}\nwww {\n}\n").unwrap();
+
+        assert_eq!(config.jail_names(), vec!["www", "db"]);
+    }
+
+    #[test]
+    fn test_param_map_negated() {
+        let config = JailConfig::parse("www {\n    allow.mount;\n    allow.nomount;\n    persist;\n}\n").unwrap();
+        let params = config.jail("www").unwrap().param_map();
+
+        assert_eq!(params["allow.mount"], vec!["false"]);
+        assert_eq!(params["persist"], vec!["true"]);
+        assert!(!params.contains_key("allow.nomount"));
+    }
+
+    #[test]
+    fn test_conversions() {
+        let parsed: JailConfig = INPUT.parse().unwrap();
+        let converted = JailConfig::try_from(INPUT).unwrap();
+
+        assert_eq!(parsed, JailConfig::parse(INPUT).unwrap());
+        assert_eq!(converted, parsed);
+        assert!("persist".parse::<JailConfig>().is_err());
+    }
+
+    #[test]
+    fn test_globals() {
+        let config = JailConfig::parse(INPUT).unwrap();
+        let globals: Vec<(&str, &str)> = config.globals().collect();
+
+        assert_eq!(globals, vec![
+            ("persist", "true"),
+            ("path", "/jails/default"),
+        ]);
+    }
+
+    #[test]
+    fn test_get_param() {
+        let config = JailConfig::parse(INPUT).unwrap();
+
+        assert_eq!(config.get_param("nginx", "path").as_deref(), Some("/jails/nginx"));
+        assert_eq!(config.get_param("postgres", "path").as_deref(), Some("/jails/default"));
+        assert_eq!(config.get_param("postgres", "persist").as_deref(), Some("true"));
+        assert_eq!(config.get_param("nginx", "ip4.addr").as_deref(), Some("127.0.1.2"));
+        assert_eq!(config.get_param("nginx", "host.hostname"), None);
+        assert_eq!(config.get_param("missing", "path"), None);
+    }
+
+    #[test]
+    fn test_get_values() {
+        let config = JailConfig::parse(INPUT).unwrap();
+
+        assert_eq!(
+            config.get_values("nginx", "ip4.addr"),
+            vec!["127.0.1.1", "127.0.1.2"],
+        );
+        assert_eq!(config.get_values("nginx", "path"), vec!["/jails/nginx"]);
+        assert!(config.get_values("missing", "path").is_empty());
+    }
+
+    #[test]
+    fn test_get_param_negated() {
+        let config = JailConfig::parse("allow.mount;\nwww {\n    allow.nomount;\n}\n").unwrap();
+
+        assert_eq!(config.get_param("www", "allow.mount").as_deref(), Some("false"));
+        assert_eq!(config.get_values("www", "allow.mount"), vec!["false"]);
+        assert_eq!(
+            config.get_values("www", "allow.mount"),
+            config.effective_params("www").unwrap()["allow.mount"],
+        );
+    }
+
+    #[test]
+    fn test_effective_params() {
+        let input = indoc!(r#"
+            persist;
+            exec.start = "/bin/sh /etc/rc";
+            ip4.addr = "127.0.0.1";
+            nginx {
+                ip4.addr += "127.0.1.1";
+                ip4.addr += "127.0.1.2";
+            }
+            postgres {
+                exec.start = "/usr/local/bin/pg";
+            }
+            nginx {
+                allow.mount;
+            }
+            "#);
+
+        let config = JailConfig::parse(input).unwrap();
+        let nginx = config.effective_params("nginx").unwrap();
+
+        assert_eq!(nginx.len(), 4);
+        assert_eq!(nginx["persist"], vec!["true"]);
+        assert_eq!(nginx["allow.mount"], vec!["true"]);
+        assert_eq!(nginx["exec.start"], vec!["/bin/sh /etc/rc"]);
+        assert_eq!(nginx["ip4.addr"], vec!["127.0.0.1", "127.0.1.1", "127.0.1.2"]);
+
+        let postgres = config.effective_params("postgres").unwrap();
+
+        assert_eq!(postgres["exec.start"], vec!["/usr/local/bin/pg"]);
+        assert_eq!(postgres["ip4.addr"], vec!["127.0.0.1"]);
+        assert!(config.effective_params("missing").is_none());
+    }
+
+    #[test]
+    fn test_params() {
+        let config = JailConfig::parse(INPUT).unwrap();
+        let names: Vec<&str> = config.params("postgres")
+            .map(|(name, _)| name)
+            .collect();
+
+        assert_eq!(names, vec!["persist", "path", "host.hostname"]);
+    }
+}
@@ -0,0 +1,547 @@
+// Conversion of legacy rc.conf jail definitions.
+//
+// Before jail.conf(5), jails were configured with `jail_<name>_<setting>`
+// variables in rc.conf(5), with `jail_<setting>` variables giving defaults
+// for every jail. from_rc_conf reads those variables and builds the
+// equivalent jail.conf configuration, with the defaults the old rc.d script
+// used for anything left unset.
+//
+// ezjail keeps each jail's configuration in a file of its own under
+// /usr/local/etc/ezjail in the same format, exported for its rc.d script,
+// with every setting written out and those not in use left empty.
+// from_ezjail reads one of those files as a single jail block.
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use tracing::warn;
+use crate::{
+    JailBlock,
+    JailBlockBuilder,
+    JailConfig,
+    JailConfigBuilder,
+};
+
+const DEFAULT_EXEC_START: &str = "/bin/sh /etc/rc";
+const DEFAULT_EXEC_STOP: &str = "/bin/sh /etc/rc.shutdown";
+
+// Per jail settings which map directly onto a parameter.
+const VALUES: &[(&str, &str)] = &[
+    ("rootdir",       "path"),
+    ("hostname",      "host.hostname"),
+    ("interface",     "interface"),
+    ("devfs_ruleset", "devfs_ruleset"),
+    ("consolelog",    "exec.consolelog"),
+    ("exec_start",    "exec.start"),
+    ("exec_stop",     "exec.stop"),
+];
+
+// Per jail settings which are YES or NO, and the boolean parameter they
+// become.
+const FLAGS: &[(&str, &str)] = &[
+    ("devfs_enable",   "mount.devfs"),
+    ("fdescfs_enable", "mount.fdescfs"),
+    ("procfs_enable",  "mount.procfs"),
+    ("vnet_enable",    "vnet"),
+];
+
+// Numbered per jail settings, eg. jail_www_exec_prestart0, and the list
+// parameter they become.
+const SEQUENCES: &[(&str, &str)] = &[
+    ("exec_prestart",  "exec.prestart"),
+    ("exec_poststart", "exec.poststart"),
+    ("exec_prestop",   "exec.prestop"),
+    ("exec_poststop",  "exec.poststop"),
+];
+
+// Global settings which became allow parameters.
+const ALLOWS: &[(&str, &str)] = &[
+    ("jail_set_hostname_allow", "allow.set_hostname"),
+    ("jail_sysvipc_allow",      "allow.sysvipc"),
+];
+
+// Settings which are recognised, but have nothing to convert to.
+const IGNORED: &[&str] = &["enable", "list", "flags", "parallel_start"];
+
+// Settings ezjail keeps for its own use, which jail(8) has no equivalent of.
+const EZJAIL_IGNORED: &[&str] = &[
+    "image",
+    "imagetype",
+    "attachparams",
+    "attachblocking",
+    "forceblocking",
+    "zfs_datasets",
+    "cpuset",
+    "parentzfs",
+    "post_start_script",
+    "retention_policy",
+];
+
+// The rulesets defined by /etc/defaults/devfs.rules, which ezjail names
+// where jail(8) needs their numbers.
+const DEVFS_RULESETS: &[(&str, &str)] = &[
+    ("devfsrules_hide_all",     "1"),
+    ("devfsrules_unhide_basic", "2"),
+    ("devfsrules_unhide_login", "3"),
+    ("devfsrules_jail",         "4"),
+    ("devfsrules_jail_vnet",    "5"),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConvertError {
+    /// A line isn't a variable assignment.
+    Syntax {
+        line: usize,
+        text: String,
+    },
+    /// A quoted value isn't closed.
+    UnterminatedQuote {
+        line: usize,
+    },
+    /// The input doesn't configure any jail.
+    NoJail,
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConvertError::Syntax { line, text } => {
+                write!(f, "line {}: expected a variable assignment: {}", line, text)
+            },
+            ConvertError::UnterminatedQuote { line } => {
+                write!(f, "line {}: unterminated quote", line)
+            },
+            ConvertError::NoJail => write!(f, "no jail is configured"),
+        }
+    }
+}
+
+impl error::Error for ConvertError {}
+
+// Parse a shell word, which may be quoted, returning it along with the rest
+// of the line, or None if a quote isn't closed. As in sh(1), a `#` only
+// starts a comment at the start of a word.
+fn word(input: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = input.char_indices();
+    let mut quote = None;
+
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (None, c) if c.is_whitespace() => return Some((value, &input[i..])),
+            (None, '#') if i == 0          => return Some((value, &input[i..])),
+            (None, '"') | (None, '\'')     => quote = Some(c),
+            (Some(q), c) if c == q         => quote = None,
+            (Some('\''), c)                => value.push(c),
+            (_, '\\')                      => {
+                if let Some((_, escaped)) = chars.next() {
+                    value.push(escaped);
+                }
+            },
+            (_, c)                         => value.push(c),
+        }
+    }
+
+    match quote {
+        Some(_) => None,
+        None    => Some((value, "")),
+    }
+}
+
+// The variable assignments in rc.conf input, later assignments replacing
+// earlier ones.
+fn assignments(input: &str) -> Result<HashMap<String, String>, ConvertError> {
+    let mut vars = HashMap::new();
+
+    for (number, line) in input.lines().enumerate() {
+        let text = line.trim();
+        let line = number + 1;
+
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        let text = text.strip_prefix("export ").unwrap_or(text);
+
+        let (name, rest) = match text.find('=') {
+            Some(eq) => (&text[..eq], &text[eq + 1..]),
+            None     => {
+                return Err(ConvertError::Syntax {
+                    line,
+                    text: text.to_string(),
+                });
+            },
+        };
+
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(ConvertError::Syntax {
+                line,
+                text: text.to_string(),
+            });
+        }
+
+        let (value, _) = word(rest).ok_or(ConvertError::UnterminatedQuote { line })?;
+
+        vars.insert(name.to_string(), value);
+    }
+
+    Ok(vars)
+}
+
+// Whether an rc.conf value means yes, as checkyesno in rc.subr does.
+fn yes(value: &str) -> bool {
+    ["yes", "true", "on", "1"].contains(&value.to_ascii_lowercase().as_str())
+}
+
+struct RcConf {
+    vars: HashMap<String, String>,
+}
+
+impl RcConf {
+    // A setting for a jail, falling back to the global default.
+    fn setting(&self, jail: &str, setting: &str) -> Option<&str> {
+        self.vars.get(&format!("jail_{}_{}", jail, setting))
+            .or_else(|| self.vars.get(&format!("jail_{}", setting)))
+            .map(String::as_str)
+    }
+
+    // The jails to convert, either from jail_list or, failing that, every
+    // jail with a setting.
+    fn jails(&self) -> Vec<String> {
+        if let Some(list) = self.vars.get("jail_list") {
+            return list.split_whitespace().map(String::from).collect();
+        }
+
+        let mut names: Vec<String> = self.vars.keys()
+            .filter_map(|var| var.strip_prefix("jail_"))
+            .filter_map(|var| {
+                VALUES.iter().map(|(setting, _)| *setting)
+                    .chain(FLAGS.iter().map(|(setting, _)| *setting))
+                    .filter_map(|setting| var.strip_suffix(setting))
+                    .filter_map(|name| name.strip_suffix('_'))
+                    .find(|name| !name.is_empty())
+            })
+            .map(String::from)
+            .collect();
+
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    // Warn about jail variables which weren't converted.
+    fn check_unused(&self, jails: &[String]) {
+        for var in self.vars.keys() {
+            let setting = match var.strip_prefix("jail_") {
+                Some(setting) => setting,
+                None          => continue,
+            };
+
+            let setting = jails.iter()
+                .filter_map(|jail| setting.strip_prefix(jail.as_str()))
+                .filter_map(|rest| rest.strip_prefix('_'))
+                .next()
+                .unwrap_or(setting);
+
+            let known = VALUES.iter().chain(FLAGS).chain(SEQUENCES)
+                .any(|(name, _)| setting.starts_with(name))
+                || ALLOWS.iter().any(|(name, _)| *name == var.as_str())
+                || ["fstab", "mount_enable", "ip", "parameters"].contains(&setting)
+                || IGNORED.contains(&setting);
+
+            if !known {
+                warn!("{} has no jail.conf equivalent, ignoring it", var);
+            }
+        }
+    }
+
+    fn block(&self, jail: &str) -> JailBlockBuilder {
+        let mut block = JailBlockBuilder::new(jail);
+
+        for (setting, param) in VALUES {
+            let value = match *setting {
+                // The defaults for these are set globally.
+                "exec_start" | "exec_stop" => {
+                    self.vars.get(&format!("jail_{}_{}", jail, setting))
+                        .map(String::as_str)
+                },
+                _ => self.setting(jail, setting),
+            };
+
+            if let Some(value) = value {
+                block = block.param(param, value);
+            }
+        }
+
+        // Addresses of both families were given together.
+        if let Some(addrs) = self.setting(jail, "ip") {
+            let mut seen = Vec::new();
+
+            for addr in addrs.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+                let ip = addr.rsplit('|').next().unwrap_or(addr);
+                let param = if ip.contains(':') { "ip6.addr" } else { "ip4.addr" };
+
+                block = if seen.contains(&param) {
+                    block.append(param, addr)
+                }
+                else {
+                    seen.push(param);
+                    block.param(param, addr)
+                };
+            }
+        }
+
+        for (setting, param) in FLAGS {
+            if self.setting(jail, setting).is_some_and(yes) {
+                block = block.flag(param);
+            }
+        }
+
+        if self.setting(jail, "mount_enable").is_some_and(yes) {
+            let fstab = self.setting(jail, "fstab")
+                .map(String::from)
+                .unwrap_or_else(|| format!("/etc/fstab.{}", jail));
+
+            block = block.param("mount.fstab", &fstab);
+        }
+
+        for (setting, param) in SEQUENCES {
+            let commands = (0..)
+                .map(|n| self.vars.get(&format!("jail_{}_{}{}", jail, setting, n)))
+                .take_while(Option::is_some)
+                .flatten();
+
+            for (n, command) in commands.enumerate() {
+                block = if n == 0 {
+                    block.param(param, command)
+                }
+                else {
+                    block.append(param, command)
+                };
+            }
+        }
+
+        // Extra parameters given as `name=value` or `name` words.
+        if let Some(params) = self.setting(jail, "parameters") {
+            for param in params.split_whitespace() {
+                block = match param.find('=') {
+                    Some(eq) => block.param(&param[..eq], &param[eq + 1..]),
+                    None     => block.flag(param),
+                };
+            }
+        }
+
+        block
+    }
+}
+
+/// Convert the `jail_*` variables of an rc.conf into jail.conf
+/// configuration, with a block for each jail in `jail_list`, or for every
+/// jail with settings if there's no list.
+///
+/// Global `jail_*` settings are applied to each jail as the old rc.d script
+/// did. The default exec.start and exec.stop scripts become global
+/// parameters. Variables with no equivalent are logged and skipped.
+pub fn from_rc_conf(input: &str) -> Result<JailConfig<'static>, ConvertError> {
+    let rc = RcConf {
+        vars: assignments(input)?,
+    };
+    let jails = rc.jails();
+
+    rc.check_unused(&jails);
+
+    let exec_start = rc.vars.get("jail_exec_start")
+        .map_or(DEFAULT_EXEC_START, String::as_str);
+    let exec_stop = rc.vars.get("jail_exec_stop")
+        .map_or(DEFAULT_EXEC_STOP, String::as_str);
+
+    let mut config = JailConfigBuilder::new()
+        .param("exec.start", exec_start)
+        .param("exec.stop", exec_stop);
+
+    for (var, param) in ALLOWS {
+        if let Some(value) = rc.vars.get(*var) {
+            let value = if yes(value) { "1" } else { "0" };
+
+            config = config.param(param, value);
+        }
+    }
+
+    for jail in &jails {
+        config = config.jail(rc.block(jail).build());
+    }
+
+    Ok(config.build())
+}
+
+/// Convert an ezjail jail configuration, as kept in /usr/local/etc/ezjail,
+/// into a jail block. The block is named as ezjail names the jail's
+/// variables, with the hostname given by its `host.hostname`.
+///
+/// Empty settings are skipped, named devfs rulesets from the system's
+/// defaults are given by number, and the default exec.start and exec.stop
+/// scripts are filled in if ezjail left them to its rc.d script. Settings
+/// ezjail keeps for itself, such as its image and ZFS options, are logged
+/// and skipped.
+pub fn from_ezjail(input: &str) -> Result<JailBlock<'static>, ConvertError> {
+    let mut vars = assignments(input)?;
+
+    vars.retain(|_, value| !value.is_empty());
+
+    let mut rc = RcConf {
+        vars,
+    };
+    let jail = rc.jails().into_iter().next().ok_or(ConvertError::NoJail)?;
+    let var = |setting: &str| format!("jail_{}_{}", jail, setting);
+
+    for setting in EZJAIL_IGNORED {
+        if rc.vars.remove(&var(setting)).is_some() {
+            warn!("{} has no jail.conf equivalent, ignoring it", var(setting));
+        }
+    }
+
+    let fib = rc.vars.remove(&var("fib"));
+
+    if let Some(ruleset) = rc.vars.get_mut(&var("devfs_ruleset")) {
+        if let Some((_, number)) = DEVFS_RULESETS.iter().find(|(name, _)| name == ruleset) {
+            *ruleset = number.to_string();
+        }
+    }
+
+    rc.check_unused(std::slice::from_ref(&jail));
+
+    let mut block = rc.block(&jail);
+
+    if let Some(fib) = fib {
+        block = block.param("exec.fib", &fib);
+    }
+
+    if !rc.vars.contains_key(&var("exec_start")) {
+        block = block.param("exec.start", DEFAULT_EXEC_START);
+    }
+
+    if !rc.vars.contains_key(&var("exec_stop")) {
+        block = block.param("exec.stop", DEFAULT_EXEC_STOP);
+    }
+
+    Ok(block.build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::to_string;
+    use indoc::indoc;
+
+    #[test]
+    fn test_from_rc_conf() {
+        let input = indoc!(r#"
+            # Jails
+            jail_enable="YES"
+            jail_list="www db"
+            jail_devfs_enable="YES"
+            jail_sysvipc_allow="NO"
+            jail_www_rootdir="/usr/jails/www"
+            jail_www_hostname="www.example.org"
+            jail_www_ip="em0|192.0.2.10,192.0.2.11, 2001:db8::10"
+            jail_www_exec_prestart0="logger starting www"
+            jail_www_exec_prestart1='logger -t www "started"'
+            jail_www_mount_enable=YES  # mounts
+            jail_db_rootdir=/usr/jails/db
+            jail_db_devfs_enable="NO"
+            jail_db_parameters="allow.raw_sockets=1 persist"
+            "#);
+
+        let config = from_rc_conf(input).unwrap();
+        let expected = indoc!(r#"
+            exec.start = "/bin/sh /etc/rc";
+            exec.stop = "/bin/sh /etc/rc.shutdown";
+            allow.sysvipc = 0;
+            www {
+                path = /usr/jails/www;
+                host.hostname = www.example.org;
+                ip4.addr = "em0|192.0.2.10";
+                ip4.addr += 192.0.2.11;
+                ip6.addr = 2001:db8::10;
+                mount.devfs;
+                mount.fstab = /etc/fstab.www;
+                exec.prestart = "logger starting www";
+                exec.prestart += "logger -t www \"started\"";
+            }
+
+            db {
+                path = /usr/jails/db;
+                allow.raw_sockets = 1;
+                persist;
+            }
+            "#);
+
+        assert_eq!(to_string(config.statements()), expected);
+    }
+
+    #[test]
+    fn test_word_with_hash() {
+        assert_eq!(word("a#b c"), Some(("a#b".to_string(), " c")));
+        assert_eq!(word("\"echo '#1'\"#x"), Some(("echo '#1'#x".to_string(), "")));
+        assert_eq!(word("#comment"), Some((String::new(), "#comment")));
+    }
+
+    #[test]
+    fn test_jails_without_list() {
+        let input = "jail_b_rootdir=/b\njail_a_hostname=a\njail_a_devfs_enable=YES\n";
+        let config = from_rc_conf(input).unwrap();
+        let names: Vec<&str> = config.jails().map(|jail| jail.name()).collect();
+
+        assert_eq!(names, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_convert_errors() {
+        assert_eq!(
+            from_rc_conf("jail_enable=YES\njail_list\n"),
+            Err(ConvertError::Syntax { line: 2, text: "jail_list".into() }),
+        );
+        assert_eq!(
+            from_rc_conf("jail_www_hostname=\"www\n"),
+            Err(ConvertError::UnterminatedQuote { line: 1 }),
+        );
+    }
+
+    #[test]
+    fn test_from_ezjail() {
+        let input = indoc!(r#"
+            # To specify the start up order of your ezjails, use these lines
+            # PROVIDE: standard_ezjail
+            export jail_www_example_org_hostname="www.example.org"
+            export jail_www_example_org_ip="em0|192.0.2.10"
+            export jail_www_example_org_rootdir="/usr/jails/www.example.org"
+            export jail_www_example_org_exec_start="/bin/sh /etc/rc"
+            export jail_www_example_org_exec_stop=""
+            export jail_www_example_org_mount_enable="YES"
+            export jail_www_example_org_devfs_enable="YES"
+            export jail_www_example_org_devfs_ruleset="devfsrules_jail"
+            export jail_www_example_org_procfs_enable="NO"
+            export jail_www_example_org_image=""
+            export jail_www_example_org_zfs_datasets="tank/www"
+            export jail_www_example_org_fib="1"
+            export jail_www_example_org_parameters="allow.raw_sockets=1"
+            "#);
+
+        let block = from_ezjail(input).unwrap();
+
+        assert_eq!(block.to_string(), indoc!(r#"
+            www_example_org {
+                path = /usr/jails/www.example.org;
+                host.hostname = www.example.org;
+                devfs_ruleset = 4;
+                exec.start = "/bin/sh /etc/rc";
+                ip4.addr = "em0|192.0.2.10";
+                mount.devfs;
+                mount.fstab = /etc/fstab.www_example_org;
+                allow.raw_sockets = 1;
+                exec.fib = 1;
+                exec.stop = "/bin/sh /etc/rc.shutdown";
+            }"#));
+
+        assert_eq!(from_ezjail("# nothing\n").unwrap_err(), ConvertError::NoJail);
+    }
+}
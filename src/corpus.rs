@@ -0,0 +1,291 @@
+// Checks of a corpus of sample configurations.
+//
+// check_corpus runs every `.conf` file in a directory through the parser and
+// checks that nothing is lost on the way back out: the lossless tree gives
+// the input back byte for byte, the rendered configuration parses to the same
+// configuration, and formatting it a second time changes nothing. The
+// validation errors and lints found in each sample are compared with those
+// recorded next to it in a `.expected` file, so that a change in what's
+// reported shows up as a failure to review.
+//
+// This is how the crate's own corpus in tests/corpus is checked, and it's
+// public so that users can do the same with their own configurations before
+// upgrading: record the results with the version in use by setting
+// JAILCONF_BLESS, then check them against the new one.
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use crate::{
+    format_config,
+    FormatOptions,
+    JailConfig,
+    LosslessConfig,
+    ParseError,
+};
+
+/// The environment variable which, when set, records each sample's results
+/// in its `.expected` file rather than comparing them.
+pub const CORPUS_BLESS_VAR: &str = "JAILCONF_BLESS";
+
+#[derive(Debug)]
+pub enum CorpusError {
+    /// The directory or a file in it couldn't be read or written.
+    Io {
+        path:  PathBuf,
+        error: io::Error,
+    },
+}
+
+impl fmt::Display for CorpusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CorpusError::Io { path, error } => {
+                write!(f, "couldn't access {}: {}", path.display(), error)
+            },
+        }
+    }
+}
+
+impl error::Error for CorpusError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            CorpusError::Io { error, .. } => Some(error),
+        }
+    }
+}
+
+/// A way in which a sample failed its checks.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CorpusFailure {
+    /// The sample doesn't parse.
+    Parse(ParseError),
+    /// The lossless tree doesn't give the sample back as it was.
+    Lossless,
+    /// The rendered configuration doesn't parse to the same configuration.
+    RoundTrip,
+    /// Formatting the formatted configuration changes it.
+    Format,
+    /// The sample has no `.expected` file.
+    Unrecorded,
+    /// The validation errors and lints differ from those recorded.
+    Results {
+        expected: String,
+        actual:   String,
+    },
+}
+
+impl fmt::Display for CorpusFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CorpusFailure::Parse(error) => write!(f, "doesn't parse: {}", error),
+            CorpusFailure::Lossless => {
+                f.write_str("the lossless tree doesn't reproduce the input")
+            },
+            CorpusFailure::RoundTrip => {
+                f.write_str("the rendered configuration doesn't parse back the same")
+            },
+            CorpusFailure::Format => f.write_str("formatting isn't stable"),
+            CorpusFailure::Unrecorded => {
+                write!(f, "no expected results, set {} to record them", CORPUS_BLESS_VAR)
+            },
+            CorpusFailure::Results { expected, actual } => {
+                write!(
+                    f,
+                    "results differ\n--- expected\n{}--- actual\n{}",
+                    expected,
+                    actual,
+                )
+            },
+        }
+    }
+}
+
+/// The outcome of checking one sample.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CorpusReport {
+    path:     PathBuf,
+    failures: Vec<CorpusFailure>,
+}
+
+impl CorpusReport {
+    /// The sample checked.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Every way the sample failed, empty if it passed.
+    pub fn failures(&self) -> &[CorpusFailure] {
+        &self.failures
+    }
+
+    /// Whether the sample passed every check.
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+// The validation errors and lints of a configuration, a line each.
+fn results(config: &JailConfig) -> String {
+    let mut lines = Vec::new();
+
+    for error in config.validate() {
+        let start = error.span().start();
+        let jail = error.jail().map(|jail| format!("{}: ", jail)).unwrap_or_default();
+
+        lines.push(format!(
+            "{}:{}: error[{}]: {}{}",
+            start.line(),
+            start.column(),
+            error.code(),
+            jail,
+            error.message(),
+        ));
+    }
+
+    for lint in config.lint() {
+        let start = lint.span().start();
+
+        lines.push(format!("{}:{}: {}", start.line(), start.column(), lint));
+    }
+
+    lines.into_iter().map(|line| line + "\n").collect()
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> CorpusError + '_ {
+    move |error| CorpusError::Io {
+        path: path.to_path_buf(),
+        error,
+    }
+}
+
+/// Check a single sample, see `check_corpus`.
+pub fn check_sample<P>(path: P) -> Result<CorpusReport, CorpusError>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let input = fs::read_to_string(path).map_err(io_error(path))?;
+    let mut failures = Vec::new();
+
+    let config = match JailConfig::parse(&input) {
+        Ok(config) => config,
+        Err(error) => {
+            failures.push(CorpusFailure::Parse(error));
+
+            return Ok(CorpusReport {
+                path: path.to_path_buf(),
+                failures,
+            });
+        },
+    };
+
+    let lossless = LosslessConfig::parse(&input).map(|config| config.to_source());
+
+    if lossless.as_deref() != Ok(input.as_str()) {
+        failures.push(CorpusFailure::Lossless);
+    }
+
+    let rendered = config.to_string();
+    let same = JailConfig::parse(&rendered)
+        .is_ok_and(|reparsed| reparsed.semantically_eq(&config));
+
+    if !same {
+        failures.push(CorpusFailure::RoundTrip);
+    }
+
+    let options = FormatOptions::new();
+    let formatted = format_config(config.statements(), &options);
+    let stable = JailConfig::parse(&formatted)
+        .is_ok_and(|reparsed| format_config(reparsed.statements(), &options) == formatted);
+
+    if !stable {
+        failures.push(CorpusFailure::Format);
+    }
+
+    let actual = results(&config);
+    let expected_path = path.with_extension("expected");
+
+    if env::var_os(CORPUS_BLESS_VAR).is_some() {
+        fs::write(&expected_path, &actual).map_err(io_error(&expected_path))?;
+    }
+    else {
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == actual => {},
+            Ok(expected) => failures.push(CorpusFailure::Results { expected, actual }),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                failures.push(CorpusFailure::Unrecorded);
+            },
+            Err(error) => return Err(io_error(&expected_path)(error)),
+        }
+    }
+
+    Ok(CorpusReport {
+        path: path.to_path_buf(),
+        failures,
+    })
+}
+
+/// Check every `.conf` file in a directory, in alphabetical order: that it
+/// parses, that its lossless tree gives it back as it was, that rendering
+/// and formatting it don't change the configuration, and that its
+/// validation errors and lints are those recorded in the `.expected` file
+/// next to it. With `CORPUS_BLESS_VAR` set, the results are recorded instead.
+pub fn check_corpus<P>(dir: P) -> Result<Vec<CorpusReport>, CorpusError>
+where
+    P: AsRef<Path>,
+{
+    let dir = dir.as_ref();
+    let mut samples = Vec::new();
+
+    for entry in fs::read_dir(dir).map_err(io_error(dir))? {
+        let path = entry.map_err(io_error(dir))?.path();
+
+        if path.extension().is_some_and(|extension| extension == "conf") {
+            samples.push(path);
+        }
+    }
+
+    samples.sort();
+
+    samples.iter().map(check_sample).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::scratch_dir;
+
+    #[test]
+    fn test_check_corpus() {
+        let dir = scratch_dir("corpus-check");
+
+        fs::write(dir.join("a.conf"), "www {\n    persist;\n    persist;\n}\n").unwrap();
+        fs::write(dir.join("a.expected"), "3:5: warning[duplicate-assignment]: www: persist is assigned more than once, the last value wins\n").unwrap();
+        fs::write(dir.join("b.conf"), "db {\n    host.hostname = db;\n}\n").unwrap();
+        fs::write(dir.join("c.conf"), "db {\n    persist\n").unwrap();
+        fs::write(dir.join("notes.txt"), "not a sample").unwrap();
+
+        let reports = check_corpus(&dir).unwrap();
+        let names: Vec<_> = reports.iter()
+            .map(|report| report.path().file_name().unwrap().to_str().unwrap())
+            .collect();
+
+        assert_eq!(names, vec!["a.conf", "b.conf", "c.conf"]);
+        assert!(reports[0].is_ok(), "{:?}", reports[0].failures());
+        assert_eq!(reports[1].failures(), [CorpusFailure::Unrecorded]);
+        assert!(matches!(reports[2].failures(), [CorpusFailure::Parse(_)]));
+
+        fs::write(dir.join("b.expected"), "1:1: error[unknown-parameter]: db: unknown parameter x\n").unwrap();
+
+        let report = check_sample(dir.join("b.conf")).unwrap();
+        assert!(matches!(report.failures(), [CorpusFailure::Results { actual, .. }] if actual.is_empty()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,285 @@
+// Lossless parsing, for editing configuration in place.
+//
+// The AST produced by `parse` keeps the source text of each statement but not
+// the whitespace between them, so rendering it again reformats the whole
+// file. LosslessConfig wraps each statement in a CstNode which also records
+// the whitespace preceding it and where it was found in the input. Block
+// nodes hold their own contents as nodes, along with the text of their
+// opening and closing braces. Writing the nodes back out in order reproduces
+// the input exactly.
+use std::borrow::Cow;
+use std::ops::Range;
+use crate::{
+    parse,
+    Edit,
+    JailConf,
+    JailConfig,
+    ParseError,
+};
+
+// The byte offset of text within input. Text must be a slice of input, as
+// every source text borrowed from it during parsing is.
+fn offset_in(input: &str, text: &str) -> usize {
+    let offset = text.as_ptr() as usize - input.as_ptr() as usize;
+
+    debug_assert!(offset + text.len() <= input.len());
+    offset
+}
+
+// The length of a block's header, from the start of its name to just after
+// the opening brace. Quoted names may themselves contain braces.
+pub(crate) fn header_len(source: &str) -> usize {
+    let bytes = source.as_bytes();
+    let mut pos = 0;
+
+    match bytes.first() {
+        Some(b'"') => {
+            pos = 1;
+
+            while pos < bytes.len() {
+                match bytes[pos] {
+                    b'\\' => pos += 2,
+                    b'"'  => {
+                        pos += 1;
+                        break;
+                    },
+                    _     => pos += 1,
+                }
+            }
+        },
+        Some(b'\'') => {
+            pos = source[1..].find('\'').map_or(source.len(), |end| end + 2);
+        },
+        _ => {},
+    }
+
+    let pos = pos.min(source.len());
+
+    source[pos..].find('{').map_or(source.len(), |brace| pos + brace + 1)
+}
+
+// Wrap statements found within input[pos..], returning the nodes and the
+// offset just after the last of them.
+fn wrap<'a>(input: &'a str, mut pos: usize, statements: Vec<JailConf<'a>>)
+-> (Vec<CstNode<'a>>, usize) {
+    let mut nodes = Vec::with_capacity(statements.len());
+
+    for statement in statements {
+        let source = statement.source_text();
+        let start = offset_in(input, source);
+        let end = start + source.len();
+        let leading = Cow::Borrowed(&input[pos..start]);
+
+        let block = match &statement {
+            JailConf::Block(block) => {
+                let open_end = start + header_len(source);
+                let (children, last) = wrap(input, open_end, block.params().to_vec());
+
+                Some(CstBlock {
+                    open:  Cow::Borrowed(&input[start..open_end]),
+                    children,
+                    close: Cow::Borrowed(&input[last..end]),
+                })
+            },
+            _ => None,
+        };
+
+        nodes.push(CstNode {
+            leading,
+            span: start..end,
+            statement,
+            block,
+        });
+
+        pos = end;
+    }
+
+    (nodes, pos)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct CstBlock<'a> {
+    pub(crate) open:     Cow<'a, str>,
+    pub(crate) children: Vec<CstNode<'a>>,
+    pub(crate) close:    Cow<'a, str>,
+}
+
+/// A statement along with the whitespace preceding it and its position in
+/// the input.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CstNode<'a> {
+    pub(crate) leading:   Cow<'a, str>,
+    pub(crate) span:      Range<usize>,
+    pub(crate) statement: JailConf<'a>,
+    pub(crate) block:     Option<CstBlock<'a>>,
+}
+
+impl<'a> CstNode<'a> {
+    /// The statement this node holds.
+    pub fn statement(&self) -> &JailConf<'a> {
+        &self.statement
+    }
+
+    /// The whitespace between the previous statement, or the start of the
+    /// enclosing block, and this one.
+    pub fn leading(&self) -> &str {
+        &self.leading
+    }
+
+    /// The byte offsets of the statement within the input.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// The nodes within a block. Empty for any other statement.
+    pub fn children(&self) -> &[CstNode<'a>] {
+        match &self.block {
+            Some(block) => &block.children,
+            None        => &[],
+        }
+    }
+
+    // Append the node's text, including its leading whitespace, to out.
+    fn write_source(&self, out: &mut String) {
+        out.push_str(&self.leading);
+        self.write_text(out);
+    }
+
+    // Append the node's text, without its leading whitespace, to out.
+    pub(crate) fn write_text(&self, out: &mut String) {
+        match &self.block {
+            Some(block) => {
+                out.push_str(&block.open);
+
+                for child in &block.children {
+                    child.write_source(out);
+                }
+
+                out.push_str(&block.close);
+            },
+            None => out.push_str(self.statement.source_text()),
+        }
+    }
+}
+
+/// A parsed configuration which retains everything needed to reproduce its
+/// input.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LosslessConfig<'a> {
+    pub(crate) nodes:    Vec<CstNode<'a>>,
+    pub(crate) trailing: Cow<'a, str>,
+    pub(crate) journal:  Vec<Edit>,
+}
+
+impl<'a> LosslessConfig<'a> {
+    /// Parse the given input, keeping its formatting.
+    pub fn parse(input: &'a str) -> Result<Self, ParseError> {
+        let (nodes, end) = wrap(input, 0, parse(input)?);
+
+        Ok(Self {
+            nodes,
+            trailing: Cow::Borrowed(&input[end..]),
+            journal:  Vec::new(),
+        })
+    }
+
+    /// The top level nodes, in the order they were given.
+    pub fn nodes(&self) -> &[CstNode<'a>] {
+        &self.nodes
+    }
+
+    /// The whitespace following the last statement.
+    pub fn trailing(&self) -> &str {
+        &self.trailing
+    }
+
+    /// The statements without their formatting.
+    pub fn to_config(&self) -> JailConfig<'a> {
+        self.nodes.iter()
+            .map(|node| node.statement.clone())
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    /// Render the configuration. Unless it has been changed, this is exactly
+    /// the input it was parsed from.
+    pub fn to_source(&self) -> String {
+        let mut out = String::new();
+
+        for node in &self.nodes {
+            node.write_source(&mut out);
+        }
+
+        out.push_str(&self.trailing);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        # Defaults for every jail
+        persist;
+        path   =   "/usr/jails/$name" ;
+
+        /* Web */
+        nginx{
+          ip4.addr = 127.0.1.1;   ip4.addr += '127.0.1.2';
+
+            allow.mount;
+        }
+        "my {jail}"  {  }
+        db { host.hostname = db; }
+
+        "#);
+
+    #[test]
+    fn test_to_source_round_trip() {
+        let config = LosslessConfig::parse(INPUT).unwrap();
+
+        assert_eq!(config.to_source(), INPUT);
+        assert_eq!(config.trailing(), "\n\n");
+    }
+
+    #[test]
+    fn test_round_trip_edge_cases() {
+        let inputs = [
+            "",
+            "\n\n   \n",
+            "persist;",
+            "  nginx {}  ",
+            "a { b { c; } }\r\n",
+        ];
+
+        for input in inputs.iter() {
+            let config = LosslessConfig::parse(input).unwrap();
+
+            assert_eq!(config.to_source(), *input);
+        }
+    }
+
+    #[test]
+    fn test_nodes_and_spans() {
+        let config = LosslessConfig::parse(INPUT).unwrap();
+        let nodes = config.nodes();
+
+        assert_eq!(nodes.len(), 7);
+        assert_eq!(nodes[1].leading(), "\n");
+        assert_eq!(&INPUT[nodes[2].span()], "path   =   \"/usr/jails/$name\" ;");
+
+        let nginx = &nodes[4];
+        let children = nginx.children();
+
+        assert_eq!(children.len(), 3);
+        assert_eq!(children[0].leading(), "\n  ");
+        assert_eq!(children[1].leading(), "   ");
+        assert_eq!(children[2].leading(), "\n\n    ");
+        assert_eq!(&INPUT[children[1].span()], "ip4.addr += '127.0.1.2';");
+        assert!(nodes[0].children().is_empty());
+
+        assert_eq!(config.to_config().jail("my {jail}").unwrap().params().len(), 0);
+    }
+}
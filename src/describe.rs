@@ -0,0 +1,321 @@
+// Configuration described as structured data.
+//
+// The serde support of the AST types mirrors the parser's statements, which
+// is awkward to write by hand. A Description is the shape people keep jail
+// definitions in instead, eg. in YAML alongside other infrastructure:
+//
+//   parameters:
+//     exec.start: /bin/sh /etc/rc
+//   jails:
+//     - name: www
+//       parameters:
+//         path: /usr/jails/www
+//         ip4.addr: [192.0.2.10, 192.0.2.11]
+//         persist: true
+//
+// It deserializes from any format serde supports, JSON, YAML, TOML or
+// otherwise, and JailConfig::from_description turns it into configuration
+// ready to render as jail.conf. Lists become an assignment of the first
+// value followed by `+=` for each other, so list parameters such as
+// `exec.start` keep every value. to_description goes the other way, giving
+// the same shape `jailconf dump` prints.
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use crate::config::apply;
+use crate::{
+    EffectiveParams,
+    JailBlockBuilder,
+    JailConfig,
+    JailConfigBuilder,
+};
+
+/// The value of a parameter in a Description.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum DescribedValue {
+    /// A boolean, `true` being written as a parameter without a value.
+    Bool(bool),
+    /// An integer.
+    Int(i64),
+    /// A string.
+    String(String),
+    /// Several values, each added to the parameter in turn.
+    List(Vec<DescribedValue>),
+}
+
+impl fmt::Display for DescribedValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DescribedValue::Bool(value)   => write!(f, "{}", value),
+            DescribedValue::Int(value)    => write!(f, "{}", value),
+            DescribedValue::String(value) => f.write_str(value),
+            DescribedValue::List(values)  => {
+                let values: Vec<String> = values.iter().map(ToString::to_string).collect();
+
+                f.write_str(&values.join(","))
+            },
+        }
+    }
+}
+
+impl DescribedValue {
+    // The values to assign in turn, lists within lists being flattened.
+    fn values(&self) -> Vec<String> {
+        match self {
+            DescribedValue::List(values) => values.iter().flat_map(Self::values).collect(),
+            value                        => vec![value.to_string()],
+        }
+    }
+}
+
+/// The parameters of a single jail in a Description.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct JailDescription {
+    name:       String,
+    #[serde(default)]
+    parameters: BTreeMap<String, DescribedValue>,
+}
+
+impl JailDescription {
+    /// A jail without any parameters.
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self {
+            name:       name.into(),
+            parameters: BTreeMap::new(),
+        }
+    }
+
+    /// The jail's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The jail's parameters, keyed by name.
+    pub fn parameters(&self) -> &BTreeMap<String, DescribedValue> {
+        &self.parameters
+    }
+
+    /// Set a parameter.
+    pub fn parameter(mut self, name: &str, value: DescribedValue) -> Self {
+        self.parameters.insert(name.to_string(), value);
+        self
+    }
+}
+
+/// Jails and global parameters as structured data, see
+/// `JailConfig::from_description`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Description {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    parameters: BTreeMap<String, DescribedValue>,
+    #[serde(default)]
+    jails:      Vec<JailDescription>,
+}
+
+impl Description {
+    /// An empty description.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The global parameters, keyed by name.
+    pub fn parameters(&self) -> &BTreeMap<String, DescribedValue> {
+        &self.parameters
+    }
+
+    /// The jails, in the order they're rendered.
+    pub fn jails(&self) -> &[JailDescription] {
+        &self.jails
+    }
+
+    /// Set a global parameter.
+    pub fn parameter(mut self, name: &str, value: DescribedValue) -> Self {
+        self.parameters.insert(name.to_string(), value);
+        self
+    }
+
+    /// Add a jail.
+    pub fn jail(mut self, jail: JailDescription) -> Self {
+        self.jails.push(jail);
+        self
+    }
+}
+
+// A description of the values of each parameter.
+fn describe(params: EffectiveParams) -> BTreeMap<String, DescribedValue> {
+    params.into_iter()
+        .map(|(name, mut values)| {
+            let value = match values.len() {
+                1 => DescribedValue::String(values.remove(0)),
+                _ => DescribedValue::List(values.into_iter().map(DescribedValue::String).collect()),
+            };
+
+            (name, value)
+        })
+        .collect()
+}
+
+// Call set for the first value of a parameter and add for the others, or
+// flag for a true boolean.
+fn add_param<T>(
+    target: T,
+    name: &str,
+    value: &DescribedValue,
+    flag: impl Fn(T, &str) -> T,
+    set: impl Fn(T, &str, &str) -> T,
+    add: impl Fn(T, &str, &str) -> T,
+) -> T {
+    if *value == DescribedValue::Bool(true) {
+        return flag(target, name);
+    }
+
+    value.values()
+        .iter()
+        .enumerate()
+        .fold(target, |target, (i, value)| match i {
+            0 => set(target, name, value),
+            _ => add(target, name, value),
+        })
+}
+
+impl JailConfig<'static> {
+    /// Build configuration from a description of it, with a block for each
+    /// jail in the order given.
+    pub fn from_description(description: &Description) -> Self {
+        let mut config = JailConfigBuilder::new();
+
+        for (name, value) in &description.parameters {
+            config = add_param(
+                config,
+                name,
+                value,
+                JailConfigBuilder::flag,
+                JailConfigBuilder::param,
+                JailConfigBuilder::append,
+            );
+        }
+
+        for jail in &description.jails {
+            let mut block = JailBlockBuilder::new(jail.name.as_str());
+
+            for (name, value) in &jail.parameters {
+                block = add_param(
+                    block,
+                    name,
+                    value,
+                    JailBlockBuilder::flag,
+                    JailBlockBuilder::param,
+                    JailBlockBuilder::append,
+                );
+            }
+
+            config = config.jail(block.build());
+        }
+
+        config.build()
+    }
+}
+
+impl<'a> JailConfig<'a> {
+    /// Describe the configuration as structured data: the global
+    /// parameters, and the parameters set within the blocks for each jail.
+    /// Comments, variable definitions and nested blocks aren't described.
+    pub fn to_description(&self) -> Description {
+        let mut globals = EffectiveParams::new();
+        apply(&mut globals, self.statements());
+
+        let mut jails: Vec<(&str, EffectiveParams)> = Vec::new();
+
+        for block in self.jails() {
+            let index = match jails.iter().position(|(name, _)| *name == block.name()) {
+                Some(index) => index,
+                None        => {
+                    jails.push((block.name(), EffectiveParams::new()));
+                    jails.len() - 1
+                },
+            };
+
+            apply(&mut jails[index].1, block.params());
+        }
+
+        Description {
+            parameters: describe(globals),
+            jails:      jails.into_iter()
+                .map(|(name, params)| JailDescription {
+                    name:       name.to_string(),
+                    parameters: describe(params),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_from_description() {
+        let description: Description = serde_yaml::from_str(indoc!(r#"
+            parameters:
+              exec.start: /bin/sh /etc/rc
+            jails:
+              - name: www
+                parameters:
+                  path: /usr/jails/www
+                  ip4.addr: [192.0.2.10, 192.0.2.11]
+                  persist: true
+                  devfs_ruleset: 4
+                  allow.mount: false
+              - name: db
+            "#)).unwrap();
+
+        let config = JailConfig::from_description(&description);
+
+        assert_eq!(config.to_string(), indoc!(r#"
+            exec.start = "/bin/sh /etc/rc";
+            www {
+                allow.mount = false;
+                devfs_ruleset = 4;
+                ip4.addr = 192.0.2.10;
+                ip4.addr += 192.0.2.11;
+                path = /usr/jails/www;
+                persist;
+            }
+
+            db {
+            }
+            "#));
+    }
+
+    #[test]
+    fn test_description_round_trip() {
+        let config = JailConfig::parse(indoc!(r#"
+            # Comments aren't described.
+            exec.clean;
+            www {
+                exec.start = "/bin/sh /etc/rc";
+                exec.start += "/usr/local/bin/app";
+                path = "/usr/jails/www";
+            }
+            "#)).unwrap();
+
+        let json = serde_json::to_string(&config.to_description()).unwrap();
+
+        assert_eq!(json, concat!(
+            r#"{"parameters":{"exec.clean":"true"},"jails":[{"name":"www","parameters":{"#,
+            r#""exec.start":["/bin/sh /etc/rc","/usr/local/bin/app"],"path":"/usr/jails/www"}}]}"#,
+        ));
+
+        let description: Description = serde_json::from_str(&json).unwrap();
+        let rebuilt = JailConfig::from_description(&description);
+
+        assert_eq!(rebuilt.effective_params("www"), config.effective_params("www"));
+    }
+}
@@ -0,0 +1,139 @@
+// The devfs rulesets used by jails.
+//
+// A jail with `mount.devfs` gets devfs(5) mounted on its /dev, with the
+// ruleset numbered by `devfs_ruleset` deciding which devices it can see.
+// Rulesets are defined in devfs.rules(5), and a number that was never
+// defined there is an empty ruleset, leaving every device of the host
+// visible in the jail without any error. check_devfs_rules compares the
+// rulesets jails use with those defined, counting the rulesets of
+// /etc/defaults/devfs.rules as always defined.
+mod rules;
+
+use crate::lint::{
+    Lint,
+    Severity,
+};
+use crate::value::{
+    invalid,
+    ValueError,
+};
+use crate::JailConfig;
+
+pub use self::rules::{
+    parse_devfs_rules,
+    DevfsRules,
+    DevfsRulesError,
+    DevfsRuleset,
+};
+
+// The rulesets defined by /etc/defaults/devfs.rules.
+const DEFAULT_RULESETS: &[(&str, u16)] = &[
+    ("devfsrules_hide_all",     1),
+    ("devfsrules_unhide_basic", 2),
+    ("devfsrules_unhide_login", 3),
+    ("devfsrules_jail",         4),
+    ("devfsrules_jail_vnet",    5),
+];
+
+impl<'a> JailConfig<'a> {
+    /// The number of the devfs ruleset the jail uses, or None if
+    /// `devfs_ruleset` isn't set for it.
+    pub fn devfs_ruleset(&self, jail: &str) -> Result<Option<u16>, ValueError> {
+        let value = match self.get_param(jail, "devfs_ruleset") {
+            Some(value) => value,
+            None        => return Ok(None),
+        };
+
+        value.trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| invalid("devfs_ruleset", &value, "a devfs ruleset number"))
+    }
+
+    /// Check the `devfs_ruleset` of every jail against the rulesets defined
+    /// in devfs.rules, reporting values which aren't ruleset numbers and
+    /// numbers no ruleset has. Values referring to variables are skipped.
+    pub fn check_devfs_rules(&self, rules: &DevfsRules) -> Vec<Lint> {
+        let mut lints = Vec::new();
+        let mut names: Vec<&str> = Vec::new();
+
+        for block in self.jails() {
+            if !names.contains(&block.name()) {
+                names.push(block.name());
+            }
+        }
+
+        for jail in names {
+            if self.get_param(jail, "devfs_ruleset").is_some_and(|value| value.contains('$')) {
+                continue;
+            }
+
+            let span = self.setter_span(jail, "devfs_ruleset");
+
+            let number = match self.devfs_ruleset(jail) {
+                Ok(Some(number)) => number,
+                Ok(None)         => continue,
+                Err(e)           => {
+                    lints.push(Lint::new(
+                        Severity::Error,
+                        "invalid-devfs-ruleset",
+                        e.to_string(),
+                        Some(jail),
+                        span,
+                    ));
+
+                    continue;
+                },
+            };
+
+            let defined = number == 0
+                || rules.ruleset(number).is_some()
+                || DEFAULT_RULESETS.iter().any(|(_, default)| *default == number);
+
+            if !defined {
+                lints.push(Lint::new(
+                    Severity::Error,
+                    "undefined-devfs-ruleset",
+                    format!("devfs ruleset {} isn't defined in devfs.rules", number),
+                    Some(jail),
+                    span,
+                ));
+            }
+        }
+
+        lints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_check_devfs_rules() {
+        let config = JailConfig::parse(indoc!(r#"
+            mount.devfs;
+            www { devfs_ruleset = 10; }
+            db { devfs_ruleset = 4; }
+            mail { devfs_ruleset = 12; }
+            dns { devfs_ruleset = four; }
+            ntp { devfs_ruleset = $ruleset; }
+            "#)).unwrap();
+
+        let rules = parse_devfs_rules("[devfsrules_jail_bpf=10]\nadd path 'bpf*' unhide\n").unwrap();
+
+        assert_eq!(config.devfs_ruleset("www"), Ok(Some(10)));
+        assert_eq!(config.devfs_ruleset("nope"), Ok(None));
+
+        let lints: Vec<String> = config.check_devfs_rules(&rules)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(lints, vec![
+            "error[undefined-devfs-ruleset]: mail: devfs ruleset 12 isn't defined in devfs.rules",
+            "error[invalid-devfs-ruleset]: dns: devfs_ruleset = \"four\" is not a devfs ruleset number",
+        ]);
+    }
+}
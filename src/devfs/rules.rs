@@ -0,0 +1,202 @@
+// A minimal parser for devfs.rules(5).
+//
+// The file is a series of rulesets, each a `[name=number]` header followed
+// by the rules in it, one per line:
+//
+//   [devfsrules_jail_bpf=10]
+//   add include $devfsrules_jail
+//   add path 'bpf*' unhide
+//
+// Only the structure is parsed, the rules themselves being kept as the text
+// devfs(8) is given, which is enough to know which rulesets exist and what
+// they include.
+use std::error;
+use std::fmt;
+
+/// A ruleset defined in devfs.rules.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DevfsRuleset {
+    name:   String,
+    number: u16,
+    rules:  Vec<String>,
+    line:   usize,
+}
+
+impl DevfsRuleset {
+    /// The ruleset's name, eg. "devfsrules_jail".
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The ruleset's number, as used by `devfs_ruleset`.
+    pub fn number(&self) -> u16 {
+        self.number
+    }
+
+    /// The rules in the ruleset, as written.
+    pub fn rules(&self) -> &[String] {
+        &self.rules
+    }
+
+    /// The names of the rulesets included by `add include $name` rules.
+    pub fn includes(&self) -> impl Iterator<Item = &str> {
+        self.rules.iter().filter_map(|rule| {
+            let mut words = rule.split_whitespace();
+
+            match (words.next(), words.next(), words.next()) {
+                (Some("add"), Some("include"), Some(name)) => name.strip_prefix('$'),
+                _                                          => None,
+            }
+        })
+    }
+
+    /// The line of the file the ruleset's header is on.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+/// The rulesets of a devfs.rules file, see `parse_devfs_rules`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DevfsRules {
+    rulesets: Vec<DevfsRuleset>,
+}
+
+impl DevfsRules {
+    /// Every ruleset, in the order they were defined.
+    pub fn rulesets(&self) -> &[DevfsRuleset] {
+        &self.rulesets
+    }
+
+    /// Look up a ruleset by number.
+    pub fn ruleset(&self, number: u16) -> Option<&DevfsRuleset> {
+        self.rulesets.iter().find(|ruleset| ruleset.number == number)
+    }
+
+    /// Look up a ruleset by name.
+    pub fn by_name(&self, name: &str) -> Option<&DevfsRuleset> {
+        self.rulesets.iter().find(|ruleset| ruleset.name == name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DevfsRulesError {
+    /// A `[` line isn't a `[name=number]` header.
+    Header {
+        line: usize,
+        text: String,
+    },
+    /// A rule comes before any ruleset header.
+    NoRuleset {
+        line: usize,
+    },
+}
+
+impl fmt::Display for DevfsRulesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DevfsRulesError::Header { line, text } => {
+                write!(f, "line {}: expected a [name=number] header: {}", line, text)
+            },
+            DevfsRulesError::NoRuleset { line } => {
+                write!(f, "line {}: rule outside of any ruleset", line)
+            },
+        }
+    }
+}
+
+impl error::Error for DevfsRulesError {}
+
+// The name and number of a ruleset header, eg. `[devfsrules_jail=4]`.
+fn header(text: &str) -> Option<(&str, u16)> {
+    let (name, number) = text.strip_prefix('[')?
+        .strip_suffix(']')?
+        .split_once('=')?;
+
+    let name = name.trim();
+    let number = number.trim().parse().ok()?;
+
+    (!name.is_empty()).then_some((name, number))
+}
+
+/// Parse the rulesets of a devfs.rules(5) file. Blank lines and `#`
+/// comments are skipped.
+pub fn parse_devfs_rules(input: &str) -> Result<DevfsRules, DevfsRulesError> {
+    let mut rulesets: Vec<DevfsRuleset> = Vec::new();
+
+    for (number, line) in input.lines().enumerate() {
+        let text = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None          => line,
+        }.trim();
+        let line = number + 1;
+
+        if text.is_empty() {
+            continue;
+        }
+
+        if text.starts_with('[') {
+            let (name, number) = header(text).ok_or_else(|| DevfsRulesError::Header {
+                line,
+                text: text.to_string(),
+            })?;
+
+            rulesets.push(DevfsRuleset {
+                name: name.to_string(),
+                number,
+                rules: Vec::new(),
+                line,
+            });
+
+            continue;
+        }
+
+        match rulesets.last_mut() {
+            Some(ruleset) => ruleset.rules.push(text.to_string()),
+            None          => return Err(DevfsRulesError::NoRuleset { line }),
+        }
+    }
+
+    Ok(DevfsRules {
+        rulesets,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_parse_devfs_rules() {
+        let rules = parse_devfs_rules(indoc!(r#"
+            # Jails which can use bpf(4).
+            [devfsrules_jail_bpf=10]
+            add include $devfsrules_jail
+            add path 'bpf*' unhide  # for dhclient
+
+            [ devfsrules_jail_tun = 11 ]
+            add path 'tun*' unhide
+            "#)).unwrap();
+
+        assert_eq!(rules.rulesets().len(), 2);
+
+        let bpf = rules.ruleset(10).unwrap();
+        assert_eq!(bpf.name(), "devfsrules_jail_bpf");
+        assert_eq!(bpf.rules(), &["add include $devfsrules_jail", "add path 'bpf*' unhide"]);
+        assert_eq!(bpf.includes().collect::<Vec<_>>(), vec!["devfsrules_jail"]);
+        assert_eq!(rules.by_name("devfsrules_jail_tun").unwrap().line(), 6);
+    }
+
+    #[test]
+    fn test_parse_devfs_rules_errors() {
+        assert_eq!(
+            parse_devfs_rules("[devfsrules_jail]\n").unwrap_err(),
+            DevfsRulesError::Header { line: 1, text: "[devfsrules_jail]".to_string() },
+        );
+        assert_eq!(
+            parse_devfs_rules("# rules\nadd path 'bpf*' unhide\n").unwrap_err(),
+            DevfsRulesError::NoRuleset { line: 2 },
+        );
+    }
+}
@@ -0,0 +1,181 @@
+// Parsing of a jail.conf.d directory.
+//
+// rc.d/jail reads every file matching `/etc/jail.conf.d/*.conf` along with
+// /etc/jail.conf. parse_dir does the same for a directory, reading each
+// `.conf` file in lexical order of its name and combining their statements
+// into a single configuration. Include directives within the files are
+// expanded as parse_with_includes does.
+//
+// Every top level statement records the file it came from, so that problems
+// found in the combined configuration can be reported against the file to
+// edit. Statements brought in by an include are recorded against the file
+// containing the directive.
+use std::fs;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::ptr;
+use tracing::debug;
+use crate::include::load;
+use crate::{
+    IncludeError,
+    JailConf,
+    JailConfig,
+};
+
+/// The combined configuration of a directory of files.
+#[derive(Clone, Debug, Default)]
+pub struct DirConfig {
+    config:  JailConfig<'static>,
+    files:   Vec<PathBuf>,
+    // The index into files of each top level statement.
+    origins: Vec<usize>,
+}
+
+// Whether node is statement, or is within it.
+fn contains(statement: &JailConf, node: &JailConf) -> bool {
+    if ptr::eq(statement, node) {
+        return true;
+    }
+
+    match statement {
+        JailConf::Block(block) => {
+            block.params().iter().any(|param| contains(param, node))
+        },
+        _ => false,
+    }
+}
+
+impl DirConfig {
+    /// The combined configuration, with the statements of each file in turn.
+    pub fn config(&self) -> &JailConfig<'static> {
+        &self.config
+    }
+
+    /// Consume the directory, returning the combined configuration.
+    pub fn into_config(self) -> JailConfig<'static> {
+        self.config
+    }
+
+    /// The files that were read, in the order they were read.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// The file a node of the combined configuration came from. The node
+    /// must be borrowed from `config()`, nodes compare by identity rather
+    /// than by value.
+    pub fn origin(&self, node: &JailConf) -> Option<&Path> {
+        self.config.statements().iter()
+            .position(|statement| contains(statement, node))
+            .map(|index| self.files[self.origins[index]].as_path())
+    }
+}
+
+/// Read every `.conf` file in the directory, in lexical order of their
+/// names, into a single configuration.
+pub fn parse_dir<P>(dir: P) -> Result<DirConfig, IncludeError>
+where
+    P: AsRef<Path>,
+{
+    let dir = dir.as_ref();
+    let io_error = |error| IncludeError::Io {
+        path: dir.to_path_buf(),
+        error,
+    };
+
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(dir).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+
+        if path.extension() == Some("conf".as_ref()) && path.is_file() {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+
+    let mut statements = Vec::new();
+    let mut origins = Vec::new();
+
+    for (index, path) in files.iter().enumerate() {
+        let config = load(path)?;
+
+        origins.extend(config.iter().map(|_| index));
+        statements.extend(config);
+    }
+
+    debug!("read {} files from {}", files.len(), dir.display());
+
+    Ok(DirConfig {
+        config: JailConfig::new(statements),
+        files,
+        origins,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use crate::scratch::scratch_dir;
+
+    #[test]
+    fn test_parse_dir() {
+        let dir = scratch_dir("dir-order");
+
+        fs::write(dir.join("20-www.conf"), "www {\n    persist;\n}\n").unwrap();
+        fs::write(dir.join("10-base.conf"), "persist;\ndb {\n    path = \"/db\";\n}\n").unwrap();
+        fs::write(dir.join("notes.txt"), "not {\n").unwrap();
+        fs::create_dir(dir.join("skipped.conf")).unwrap();
+
+        let parsed = parse_dir(&dir).unwrap();
+        let names: Vec<&str> = parsed.config().jails().map(|j| j.name()).collect();
+
+        assert_eq!(names, vec!["db", "www"]);
+        assert_eq!(parsed.files(), &[dir.join("10-base.conf"), dir.join("20-www.conf")]);
+
+        let www = parsed.config().jail("www").unwrap();
+        assert_eq!(
+            parsed.origin(&www.params()[0]),
+            Some(dir.join("20-www.conf").as_path()),
+        );
+        assert_eq!(
+            parsed.origin(&parsed.config().statements()[0]),
+            Some(dir.join("10-base.conf").as_path()),
+        );
+
+        // An equal node from elsewhere has no origin.
+        let other = JailConfig::parse("persist;\n").unwrap();
+        assert!(parsed.origin(&other.statements()[0]).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_dir_parse_error() {
+        let dir = scratch_dir("dir-error");
+
+        fs::write(dir.join("a.conf"), "persist;\n").unwrap();
+        fs::write(dir.join("b.conf"), "www {\n    path = \"/a\"\n}\n").unwrap();
+
+        match parse_dir(&dir) {
+            Err(IncludeError::Parse { path, error }) => {
+                assert_eq!(path, dir.join("b.conf"));
+                assert_eq!(error.line(), 2);
+            },
+            res => panic!("expected a parse error, got {:?}", res),
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_dir_missing() {
+        let dir = env::temp_dir().join("jailconf-dir-does-not-exist");
+
+        assert!(matches!(parse_dir(&dir), Err(IncludeError::Io { .. })));
+    }
+}
@@ -0,0 +1,178 @@
+// Parsing of several inputs into a single document.
+//
+// Each input is given a name, usually a file name, when it's added. Parsed
+// nodes borrow their source text from the input they came from, so any node
+// taken from the document, even after it has been moved into the results of
+// later processing, can be traced back to its input and line. This lets
+// diagnostics say exactly which file to edit.
+use std::fmt;
+use crate::{
+    parse,
+    JailConf,
+    ParseError,
+};
+
+/// The input, and line within it, that a node was parsed from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Origin<'a> {
+    name: &'a str,
+    line: usize,
+}
+
+impl<'a> Origin<'a> {
+    /// The name the input was added with.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// The line number within the input, starting from 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl<'a> fmt::Display for Origin<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.name, self.line)
+    }
+}
+
+#[derive(Debug)]
+struct Source<'a> {
+    name: &'a str,
+    text: &'a str,
+}
+
+#[derive(Debug, Default)]
+pub struct Document<'a> {
+    sources:    Vec<Source<'a>>,
+    statements: Vec<JailConf<'a>>,
+}
+
+impl<'a> Document<'a> {
+    /// Create an empty document.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Parse several named inputs, in order, into a single document.
+    pub fn from_inputs<I>(inputs: I) -> Result<Self, ParseError>
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        let mut document = Self::new();
+
+        for (name, input) in inputs {
+            document.add(name, input)?;
+        }
+
+        Ok(document)
+    }
+
+    /// Parse the given input and append its statements to the document.
+    pub fn add(&mut self, name: &'a str, input: &'a str)
+    -> Result<(), ParseError> {
+        let statements = parse(input)?;
+
+        self.sources.push(Source{
+            name,
+            text: input,
+        });
+        self.statements.extend(statements);
+
+        Ok(())
+    }
+
+    /// All statements in the document, in the order their inputs were added.
+    pub fn statements(&self) -> &[JailConf<'a>] {
+        &self.statements
+    }
+
+    /// Consume the document, returning its statements.
+    pub fn into_statements(self) -> Vec<JailConf<'a>> {
+        self.statements
+    }
+
+    /// Find where a node was parsed from.
+    pub fn origin(&self, node: &JailConf) -> Option<Origin<'a>> {
+        self.origin_of(node.source_text())
+    }
+
+    /// Find where a piece of source text, such as that returned by a node's
+    /// `source_text()`, was parsed from.
+    pub fn origin_of(&self, text: &str) -> Option<Origin<'a>> {
+        let ptr = text.as_ptr() as usize;
+
+        self.sources.iter().find_map(|source| {
+            let start = source.text.as_ptr() as usize;
+            let end = start + source.text.len();
+
+            if ptr < start || ptr + text.len() > end {
+                return None;
+            }
+
+            let offset = ptr - start;
+            let line = source.text[..offset].matches('\n').count() + 1;
+
+            Some(Origin{
+                name: source.name,
+                line,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_origins() {
+        let base = String::from("persist;\n\nnginx {\n    allow.mount;\n}\n");
+        let extra = String::from("# Extra jails\njail2 {\n    persist;\n}\n");
+
+        let document = Document::from_inputs(vec![
+            ("base.conf", base.as_str()),
+            ("extra.conf", extra.as_str()),
+        ]).unwrap();
+
+        let statements = document.statements();
+        assert_eq!(statements.len(), 4);
+
+        let origins: Vec<String> = statements.iter()
+            .map(|s| document.origin(s).unwrap().to_string())
+            .collect();
+
+        assert_eq!(origins, vec![
+            "base.conf:1",
+            "base.conf:3",
+            "extra.conf:1",
+            "extra.conf:2",
+        ]);
+    }
+
+    #[test]
+    fn test_document_nested_origin() {
+        let input = String::from("nginx {\n    persist;\n    allow.mount;\n}\n");
+        let mut document = Document::new();
+        document.add("jail.conf", &input).unwrap();
+
+        if let JailConf::Block(block) = &document.statements()[0] {
+            let origin = document.origin(&block.params[1]).unwrap();
+
+            assert_eq!(origin.name(), "jail.conf");
+            assert_eq!(origin.line(), 3);
+        }
+        else {
+            panic!("expected a block");
+        }
+    }
+
+    #[test]
+    fn test_document_unknown_origin() {
+        let input = String::from("persist;\n");
+        let document = Document::from_inputs(vec![("a.conf", input.as_str())]).unwrap();
+
+        assert!(document.origin_of("persist;").is_none());
+    }
+}
@@ -0,0 +1,909 @@
+// Editing of losslessly parsed configuration.
+//
+// Edits are made to the nodes of a LosslessConfig, replacing, adding or
+// removing only the statements involved. Everything else keeps the text and
+// whitespace it was parsed with, so `to_source` after an edit differs from
+// the input only on the lines that were touched. New statements are indented
+// to match their neighbours.
+//
+// New statements can also be placed relative to those already there, before
+// or after a parameter, a comment or a jail, so that generated edits land
+// where a person would have put them rather than always at the end.
+//
+// Each successful edit is recorded in the configuration's journal as an
+// Edit, so that tools saving the result can say what they changed, eg. in an
+// audit log.
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+use crate::builder::{
+    name_quote,
+    param_value,
+    raw_value,
+};
+use crate::cst::{
+    header_len,
+    CstBlock,
+    CstNode,
+    LosslessConfig,
+};
+use crate::escape::quote;
+use crate::name::boolean;
+use crate::{
+    CommentStyle,
+    JailBlock,
+    JailConf,
+    QuoteStyle,
+};
+
+const INDENT: &str = "    ";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EditError {
+    /// There's no block for the jail.
+    UnknownJail {
+        name: String,
+    },
+    /// A jail with the name is already configured.
+    JailExists {
+        name: String,
+    },
+    /// No statement matches the anchor a new one was to be placed by.
+    AnchorNotFound {
+        anchor: String,
+    },
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EditError::UnknownJail { name } => {
+                write!(f, "jail {} is not configured", name)
+            },
+            EditError::JailExists { name } => {
+                write!(f, "jail {} is already configured", name)
+            },
+            EditError::AnchorNotFound { anchor } => {
+                write!(f, "no {} to place the statement by", anchor)
+            },
+        }
+    }
+}
+
+impl error::Error for EditError {}
+
+/// A change made to a LosslessConfig.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Edit {
+    /// A parameter was set with `=`.
+    ParamSet {
+        jail:  String,
+        name:  String,
+        value: String,
+    },
+    /// A value was added to a parameter with `+=`.
+    ParamAppended {
+        jail:  String,
+        name:  String,
+        value: String,
+    },
+    /// Every statement setting a parameter was removed.
+    ParamRemoved {
+        jail: String,
+        name: String,
+    },
+    /// A block for a new jail was added.
+    JailAdded {
+        name: String,
+    },
+    /// A jail was renamed.
+    JailRenamed {
+        from: String,
+        to:   String,
+    },
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Edit::ParamSet { jail, name, value } => {
+                write!(f, "{}: set {} = {}", jail, name, raw_value(value))
+            },
+            Edit::ParamAppended { jail, name, value } => {
+                write!(f, "{}: appended {} += {}", jail, name, raw_value(value))
+            },
+            Edit::ParamRemoved { jail, name } => {
+                write!(f, "{}: removed {}", jail, name)
+            },
+            Edit::JailAdded { name } => write!(f, "added jail {}", name),
+            Edit::JailRenamed { from, to } => {
+                write!(f, "renamed jail {} to {}", from, to)
+            },
+        }
+    }
+}
+
+/// A statement a new one can be placed next to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Anchor<'s> {
+    /// A statement setting the parameter.
+    Param(&'s str),
+    /// A comment whose text contains the string.
+    Comment(&'s str),
+    /// A block for the jail.
+    Jail(&'s str),
+}
+
+impl<'s> Anchor<'s> {
+    fn matches(&self, statement: &JailConf) -> bool {
+        match (self, statement) {
+            (Anchor::Comment(text), JailConf::Comment(comment)) => {
+                comment.comment().contains(text)
+            },
+            (Anchor::Jail(name), JailConf::Block(block)) => block.name() == *name,
+            (Anchor::Param(name), statement) => param_name(statement) == Some(name),
+            _ => false,
+        }
+    }
+}
+
+impl<'s> fmt::Display for Anchor<'s> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Anchor::Param(name)   => write!(f, "parameter {}", name),
+            Anchor::Comment(text) => write!(f, "comment containing {:?}", text),
+            Anchor::Jail(name)    => write!(f, "jail {}", name),
+        }
+    }
+}
+
+/// Where to place a new statement.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Placement<'s> {
+    /// Before everything else.
+    Start,
+    /// After everything else.
+    End,
+    /// Directly before the first statement matching the anchor.
+    Before(Anchor<'s>),
+    /// Directly after the last statement matching the anchor.
+    After(Anchor<'s>),
+}
+
+// Where a statement goes among a list of nodes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Slot {
+    Before(usize),
+    After(usize),
+    End,
+}
+
+impl Slot {
+    // The slot for placement among nodes, if its anchor is found there.
+    fn find(nodes: &[CstNode], placement: Placement) -> Option<Self> {
+        let slot = match placement {
+            Placement::Start if nodes.is_empty() => Slot::End,
+            Placement::Start => Slot::Before(0),
+            Placement::End   => Slot::End,
+            Placement::Before(anchor) => {
+                Slot::Before(nodes.iter().position(|node| anchor.matches(&node.statement))?)
+            },
+            Placement::After(anchor) => {
+                Slot::After(nodes.iter().rposition(|node| anchor.matches(&node.statement))?)
+            },
+        };
+
+        Some(slot)
+    }
+}
+
+// The error for a placement which couldn't be found. Only those relative to
+// an anchor can fail.
+fn not_found(placement: Placement) -> EditError {
+    let anchor = match placement {
+        Placement::Before(anchor) | Placement::After(anchor) => anchor.to_string(),
+        Placement::Start | Placement::End                    => String::new(),
+    };
+
+    EditError::AnchorNotFound {
+        anchor,
+    }
+}
+
+// The name of the parameter a statement sets, if it sets one.
+fn param_name<'c>(statement: &'c JailConf) -> Option<&'c str> {
+    match statement {
+        JailConf::ParamBool(param)  => Some(param.name()),
+        JailConf::ParamValue(param) => Some(param.name()),
+        _                           => None,
+    }
+}
+
+// The parameter a name sets, the boolean itself for the "no" form of one,
+// eg. allow.mount for allow.nomount.
+fn param_key(name: &str) -> Cow<'_, str> {
+    match boolean(name) {
+        Some((positive, _)) => Cow::Owned(positive),
+        None                => Cow::Borrowed(name),
+    }
+}
+
+// Whether a statement sets the parameter with the given key, in either form
+// if it's a boolean.
+fn sets(statement: &JailConf, key: &str) -> bool {
+    param_name(statement).is_some_and(|name| param_key(name) == key)
+}
+
+fn is_jail(node: &CstNode, jail: &str) -> bool {
+    match &node.statement {
+        JailConf::Block(block) => block.name() == jail,
+        _                      => false,
+    }
+}
+
+// A jail name as it should be written.
+fn render_name(name: &str) -> String {
+    match name_quote(name) {
+        QuoteStyle::Double => quote(name),
+        _                  => name.to_string(),
+    }
+}
+
+// Whether a statement runs to the end of its line, so that nothing can
+// follow it on the same line.
+fn ends_line(statement: &JailConf) -> bool {
+    match statement {
+        JailConf::Comment(comment) => comment.style() != CommentStyle::C,
+        _                          => false,
+    }
+}
+
+// Whitespace to put before a statement added at the end of a block, following
+// the indentation of the statement before it.
+fn child_leading(block: &mut CstBlock) -> String {
+    if let Some(last) = block.children.last() {
+        return match last.leading.rfind('\n') {
+            Some(newline) => last.leading[newline..].to_string(),
+            None if ends_line(&last.statement) => format!("\n{}", INDENT),
+            None          => last.leading.to_string(),
+        };
+    }
+
+    match block.close.rfind('\n') {
+        Some(newline) => {
+            let indent = block.close[newline + 1..].trim_end_matches('}');
+
+            format!("\n{}{}", indent, INDENT)
+        },
+        None => {
+            // An empty block on one line stays on one line.
+            if block.close == "}" {
+                block.close = Cow::Borrowed(" }");
+            }
+
+            " ".to_string()
+        },
+    }
+}
+
+// Whitespace to put before a statement added directly after node, putting it
+// on a line of its own if node is on one.
+fn leading_after(node: &CstNode) -> String {
+    match node.leading.rfind('\n') {
+        Some(newline) => node.leading[newline..].to_string(),
+        None if ends_line(&node.statement) => format!("\n{}", INDENT),
+        None          => " ".to_string(),
+    }
+}
+
+// Rebuild a block node's statement after its contents have changed.
+fn sync(node: &mut CstNode) {
+    let mut source = String::new();
+
+    node.write_text(&mut source);
+
+    if let (JailConf::Block(block), Some(cst)) = (&mut node.statement, &node.block) {
+        block.params = cst.children.iter()
+            .map(|child| child.statement.clone())
+            .collect();
+        block.source = Cow::Owned(source);
+    }
+}
+
+// Create a node for a statement which wasn't parsed, formatted as the writer
+// would format it at the given depth.
+fn new_node<'a>(statement: JailConf<'a>, leading: String, depth: usize, at: usize)
+-> CstNode<'a> {
+    let block = match &statement {
+        JailConf::Block(block) => {
+            let rendered = block.to_string();
+            let indent = format!("\n{}", INDENT.repeat(depth + 1));
+
+            let children = block.params()
+                .iter()
+                .map(|child| new_node(child.clone(), indent.clone(), depth + 1, at))
+                .collect();
+
+            Some(CstBlock {
+                open:  Cow::Owned(rendered[..header_len(&rendered)].to_string()),
+                children,
+                close: Cow::Owned(format!("\n{}}}", INDENT.repeat(depth))),
+            })
+        },
+        _ => None,
+    };
+
+    let mut node = CstNode {
+        leading: Cow::Owned(leading),
+        span: at..at,
+        statement,
+        block,
+    };
+
+    sync(&mut node);
+    node
+}
+
+impl<'a> LosslessConfig<'a> {
+    // The block nodes for the named jail, or an UnknownJail error if there
+    // are none.
+    fn jail_nodes(&mut self, jail: &str)
+    -> Result<Vec<&mut CstNode<'a>>, EditError> {
+        let nodes: Vec<&mut CstNode<'a>> = self.nodes.iter_mut()
+            .filter(|node| is_jail(node, jail))
+            .collect();
+
+        if nodes.is_empty() {
+            return Err(EditError::UnknownJail {
+                name: jail.to_string(),
+            });
+        }
+
+        Ok(nodes)
+    }
+
+    /// Set a parameter for the named jail with `=`. The last statement
+    /// setting the parameter in the jail's blocks is replaced, keeping its
+    /// position, otherwise the parameter is added to the end of the jail's
+    /// last block. A boolean and its "no" form, eg. allow.mount and
+    /// allow.nomount, are the same parameter.
+    pub fn set_param(&mut self, jail: &str, name: &str, value: &str)
+    -> Result<(), EditError> {
+        let statement = param_value(name, value, false);
+        let key = param_key(name);
+        let mut nodes = self.jail_nodes(jail)?;
+
+        let sets_param = |node: &CstNode| sets(&node.statement, &key);
+
+        let target = nodes.iter()
+            .rposition(|node| node.children().iter().any(sets_param))
+            .unwrap_or(nodes.len() - 1);
+
+        let node = &mut *nodes[target];
+        let at = node.span.end;
+
+        if let Some(block) = &mut node.block {
+            match block.children.iter().rposition(sets_param) {
+                Some(index) => {
+                    let child = &mut block.children[index];
+
+                    child.statement = statement;
+                    child.block = None;
+                },
+                None => {
+                    let leading = child_leading(block);
+
+                    block.children.push(new_node(statement, leading, 1, at));
+                },
+            }
+        }
+
+        sync(node);
+
+        self.journal.push(Edit::ParamSet {
+            jail:  jail.to_string(),
+            name:  name.to_string(),
+            value: value.to_string(),
+        });
+
+        Ok(())
+    }
+
+    /// Add a value to a parameter for the named jail with `+=`. The new
+    /// statement goes directly after the last one setting the parameter in
+    /// the jail's blocks, otherwise at the end of the jail's last block.
+    pub fn append_param(&mut self, jail: &str, name: &str, value: &str)
+    -> Result<(), EditError> {
+        let statement = param_value(name, value, true);
+        let mut nodes = self.jail_nodes(jail)?;
+
+        let sets_param = |node: &CstNode| param_name(&node.statement) == Some(name);
+
+        let target = nodes.iter()
+            .rposition(|node| node.children().iter().any(sets_param))
+            .unwrap_or(nodes.len() - 1);
+
+        let node = &mut *nodes[target];
+        let at = node.span.end;
+
+        if let Some(block) = &mut node.block {
+            match block.children.iter().rposition(sets_param) {
+                Some(index) => {
+                    let leading = leading_after(&block.children[index]);
+
+                    block.children.insert(index + 1, new_node(statement, leading, 1, at));
+                },
+                None => {
+                    let leading = child_leading(block);
+
+                    block.children.push(new_node(statement, leading, 1, at));
+                },
+            }
+        }
+
+        sync(node);
+
+        self.journal.push(Edit::ParamAppended {
+            jail:  jail.to_string(),
+            name:  name.to_string(),
+            value: value.to_string(),
+        });
+
+        Ok(())
+    }
+
+    /// Remove every statement setting a parameter from the named jail's
+    /// blocks, along with the whitespace before them, including those
+    /// setting a boolean in its "no" form. Returns whether any were found.
+    pub fn remove_param(&mut self, jail: &str, name: &str)
+    -> Result<bool, EditError> {
+        let key = param_key(name);
+        let mut removed = false;
+
+        for node in self.jail_nodes(jail)? {
+            if let Some(block) = &mut node.block {
+                let before = block.children.len();
+
+                block.children.retain(|child| !sets(&child.statement, &key));
+
+                if block.children.len() != before {
+                    removed = true;
+                }
+            }
+
+            sync(node);
+        }
+
+        if removed {
+            self.journal.push(Edit::ParamRemoved {
+                jail: jail.to_string(),
+                name: name.to_string(),
+            });
+        }
+
+        Ok(removed)
+    }
+
+    /// Add a block for a new jail at the end of the configuration.
+    pub fn add_jail(&mut self, block: JailBlock<'a>) -> Result<(), EditError> {
+        if self.nodes.iter().any(|node| is_jail(node, block.name())) {
+            return Err(EditError::JailExists {
+                name: block.name().to_string(),
+            });
+        }
+
+        let leading = if self.nodes.is_empty() { "" } else { "\n" };
+        let at = self.nodes.last().map_or(0, |node| node.span.end);
+
+        self.journal.push(Edit::JailAdded {
+            name: block.name().to_string(),
+        });
+
+        self.nodes.push(new_node(
+            JailConf::Block(block),
+            leading.to_string(),
+            0,
+            at,
+        ));
+
+        Ok(())
+    }
+
+    /// Add a parameter to the named jail with `=`, placed as placement
+    /// says among the statements of the jail's blocks. Unlike `set_param`,
+    /// statements already setting the parameter are left as they are.
+    pub fn insert_param(
+        &mut self,
+        jail: &str,
+        name: &str,
+        value: &str,
+        placement: Placement,
+    ) -> Result<(), EditError> {
+        let statement = param_value(name, value, false);
+        let mut nodes = self.jail_nodes(jail)?;
+
+        // The first block for the start, the last for the end, and otherwise
+        // the block holding the first or last statement matching the anchor.
+        let found = match placement {
+            Placement::Start | Placement::Before(_) => {
+                nodes.iter().enumerate().find_map(|(i, node)| {
+                    Some((i, Slot::find(node.children(), placement)?))
+                })
+            },
+            Placement::End | Placement::After(_) => {
+                nodes.iter().enumerate().rev().find_map(|(i, node)| {
+                    Some((i, Slot::find(node.children(), placement)?))
+                })
+            },
+        };
+
+        let (target, slot) = found.ok_or_else(|| not_found(placement))?;
+
+        let node = &mut *nodes[target];
+        let at = node.span.end;
+
+        if let Some(block) = &mut node.block {
+            match slot {
+                Slot::Before(index) => {
+                    let next = &mut block.children[index];
+                    let leading = next.leading.to_string();
+
+                    next.leading = Cow::Owned(leading_after(next));
+                    block.children.insert(index, new_node(statement, leading, 1, at));
+                },
+                Slot::After(index) => {
+                    let leading = leading_after(&block.children[index]);
+
+                    block.children.insert(index + 1, new_node(statement, leading, 1, at));
+                },
+                Slot::End => {
+                    let leading = child_leading(block);
+
+                    block.children.push(new_node(statement, leading, 1, at));
+                },
+            }
+        }
+
+        sync(node);
+
+        self.journal.push(Edit::ParamSet {
+            jail:  jail.to_string(),
+            name:  name.to_string(),
+            value: value.to_string(),
+        });
+
+        Ok(())
+    }
+
+    /// Add a block for a new jail, placed as placement says among the top
+    /// level statements, with a blank line separating it from those around
+    /// it.
+    pub fn insert_jail(&mut self, block: JailBlock<'a>, placement: Placement)
+    -> Result<(), EditError> {
+        if self.nodes.iter().any(|node| is_jail(node, block.name())) {
+            return Err(EditError::JailExists {
+                name: block.name().to_string(),
+            });
+        }
+
+        let slot = Slot::find(&self.nodes, placement).ok_or_else(|| not_found(placement))?;
+
+        let name = block.name().to_string();
+
+        let (index, leading, at) = match slot {
+            Slot::Before(index) => {
+                let next = &mut self.nodes[index];
+                let leading = next.leading.to_string();
+
+                next.leading = Cow::Borrowed("\n\n");
+                (index, leading, next.span.start)
+            },
+            Slot::After(index) => (index + 1, "\n\n".to_string(), self.nodes[index].span.end),
+            Slot::End => return self.add_jail(block),
+        };
+
+        self.nodes.insert(index, new_node(JailConf::Block(block), leading, 0, at));
+        self.journal.push(Edit::JailAdded {
+            name,
+        });
+
+        Ok(())
+    }
+
+    /// Rename a jail, changing the name of each of its blocks.
+    pub fn rename_jail(&mut self, jail: &str, name: &str)
+    -> Result<(), EditError> {
+        if jail != name && self.nodes.iter().any(|node| is_jail(node, name)) {
+            return Err(EditError::JailExists {
+                name: name.to_string(),
+            });
+        }
+
+        for node in self.jail_nodes(jail)? {
+            if let Some(cst) = &mut node.block {
+                // Keep whatever spacing came between the name and the brace.
+                let open = &cst.open[..cst.open.len() - 1];
+                let name_end = open.trim_end().len();
+                let rest = cst.open[name_end..].to_string();
+
+                cst.open = Cow::Owned(format!("{}{}", render_name(name), rest));
+            }
+
+            if let JailConf::Block(block) = &mut node.statement {
+                block.name = Cow::Owned(name.to_string());
+                block.quote = name_quote(name);
+            }
+
+            sync(node);
+        }
+
+        self.journal.push(Edit::JailRenamed {
+            from: jail.to_string(),
+            to:   name.to_string(),
+        });
+
+        Ok(())
+    }
+
+    /// The edits made since the configuration was parsed, or since the
+    /// journal was last taken, in the order they were made.
+    pub fn journal(&self) -> &[Edit] {
+        &self.journal
+    }
+
+    /// Take the edits made so far, leaving the journal empty.
+    pub fn take_journal(&mut self) -> Vec<Edit> {
+        std::mem::take(&mut self.journal)
+    }
+
+    /// A summary of the edits in the journal, one per line, eg. for an
+    /// audit log.
+    pub fn change_summary(&self) -> String {
+        self.journal.iter()
+            .map(|edit| format!("{}\n", edit))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::JailBlockBuilder;
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        # Web servers
+        persist;
+
+        nginx  {
+            path = "/usr/jails/nginx";   # keep me
+            ip4.addr = 127.0.1.1;
+            ip4.addr += 127.0.1.2;
+        }
+
+        db { host.hostname = db; }
+        "#);
+
+    #[test]
+    fn test_set_existing_param() {
+        let mut config = LosslessConfig::parse(INPUT).unwrap();
+
+        config.set_param("nginx", "path", "/jails/nginx").unwrap();
+
+        assert_eq!(config.to_source(), INPUT.replace(
+            "path = \"/usr/jails/nginx\";",
+            "path = /jails/nginx;",
+        ));
+        assert_eq!(
+            config.to_config().get_param("nginx", "path").as_deref(),
+            Some("/jails/nginx"),
+        );
+    }
+
+    #[test]
+    fn test_set_replaces_last_value() {
+        let mut config = LosslessConfig::parse(INPUT).unwrap();
+
+        config.set_param("nginx", "ip4.addr", "lo1|10.0.0.1").unwrap();
+
+        assert_eq!(config.to_source(), INPUT.replace(
+            "ip4.addr += 127.0.1.2;",
+            "ip4.addr = \"lo1|10.0.0.1\";",
+        ));
+        assert_eq!(
+            config.to_config().get_values("nginx", "ip4.addr"),
+            vec!["lo1|10.0.0.1"],
+        );
+    }
+
+    #[test]
+    fn test_set_new_param() {
+        let mut config = LosslessConfig::parse(INPUT).unwrap();
+
+        config.set_param("nginx", "allow.mount", "1").unwrap();
+        config.set_param("db", "path", "/jails/db").unwrap();
+
+        let expected = INPUT
+            .replace(
+                "ip4.addr += 127.0.1.2;\n",
+                "ip4.addr += 127.0.1.2;\n    allow.mount = 1;\n",
+            )
+            .replace(
+                "host.hostname = db; }",
+                "host.hostname = db; path = /jails/db; }",
+            );
+
+        assert_eq!(config.to_source(), expected);
+        assert_eq!(
+            config.set_param("missing", "path", "/"),
+            Err(EditError::UnknownJail { name: "missing".into() }),
+        );
+    }
+
+    #[test]
+    fn test_append_param() {
+        let mut config = LosslessConfig::parse(INPUT).unwrap();
+
+        config.append_param("nginx", "ip4.addr", "127.0.1.3").unwrap();
+        config.append_param("db", "ip4.addr", "127.0.2.1").unwrap();
+
+        let expected = INPUT
+            .replace(
+                "ip4.addr += 127.0.1.2;\n",
+                "ip4.addr += 127.0.1.2;\n    ip4.addr += 127.0.1.3;\n",
+            )
+            .replace(
+                "host.hostname = db; }",
+                "host.hostname = db; ip4.addr += 127.0.2.1; }",
+            );
+
+        assert_eq!(config.to_source(), expected);
+        assert_eq!(
+            config.to_config().get_values("nginx", "ip4.addr"),
+            vec!["127.0.1.1", "127.0.1.2", "127.0.1.3"],
+        );
+    }
+
+    #[test]
+    fn test_remove_param() {
+        let mut config = LosslessConfig::parse(INPUT).unwrap();
+
+        assert_eq!(config.remove_param("nginx", "ip4.addr"), Ok(true));
+        assert_eq!(config.remove_param("nginx", "ip4.addr"), Ok(false));
+
+        assert_eq!(config.to_source(), INPUT.replace(
+            "\n    ip4.addr = 127.0.1.1;\n    ip4.addr += 127.0.1.2;",
+            "",
+        ));
+        assert!(config.to_config().get_values("nginx", "ip4.addr").is_empty());
+    }
+
+    #[test]
+    fn test_set_negated_param() {
+        let input = "www {\n    allow.nomount;\n    persist;\n}\n";
+        let mut config = LosslessConfig::parse(input).unwrap();
+
+        config.set_param("www", "allow.mount", "true").unwrap();
+
+        assert_eq!(config.to_source(), "www {\n    allow.mount = true;\n    persist;\n}\n");
+        assert_eq!(config.to_config().get_bool("www", "allow.mount"), Ok(true));
+
+        config.set_param("www", "nopersist", "true").unwrap();
+
+        assert_eq!(config.to_source(), "www {\n    allow.mount = true;\n    nopersist = true;\n}\n");
+    }
+
+    #[test]
+    fn test_remove_negated_param() {
+        let input = "www {\n    allow.mount;\n    allow.nomount = 1;\n    persist;\n}\n";
+        let mut config = LosslessConfig::parse(input).unwrap();
+
+        assert_eq!(config.remove_param("www", "allow.mount"), Ok(true));
+        assert_eq!(config.to_source(), "www {\n    persist;\n}\n");
+
+        let mut config = LosslessConfig::parse(input).unwrap();
+
+        assert_eq!(config.remove_param("www", "allow.nomount"), Ok(true));
+        assert_eq!(config.to_source(), "www {\n    persist;\n}\n");
+    }
+
+    #[test]
+    fn test_add_jail() {
+        let mut config = LosslessConfig::parse(INPUT).unwrap();
+        let block = JailBlockBuilder::new("www")
+            .param("path", "/jails/www")
+            .build();
+
+        config.add_jail(block.clone()).unwrap();
+
+        let expected = format!("{}\nwww {{\n    path = /jails/www;\n}}\n", INPUT.trim_end());
+        assert_eq!(config.to_source(), expected);
+
+        // The new block can itself be edited.
+        config.set_param("www", "persist", "1").unwrap();
+        assert!(config.to_source().contains("    path = /jails/www;\n    persist = 1;\n}"));
+
+        assert_eq!(
+            config.add_jail(block),
+            Err(EditError::JailExists { name: "www".into() }),
+        );
+    }
+
+    #[test]
+    fn test_insert_param() {
+        let mut config = LosslessConfig::parse(INPUT).unwrap();
+
+        let after_addr = Placement::After(Anchor::Param("ip4.addr"));
+        let after_comment = Placement::After(Anchor::Comment("keep"));
+        let before_hostname = Placement::Before(Anchor::Param("host.hostname"));
+
+        config.insert_param("nginx", "ip4.addr", "127.0.1.3", after_addr).unwrap();
+        config.insert_param("nginx", "host.hostname", "nginx", Placement::Start).unwrap();
+        config.insert_param("nginx", "exec.start", "/bin/sh /etc/rc", after_comment).unwrap();
+        config.insert_param("db", "path", "/jails/db", before_hostname).unwrap();
+
+        let expected = INPUT
+            .replace(
+                "nginx  {\n",
+                "nginx  {\n    host.hostname = nginx;\n",
+            )
+            .replace(
+                "ip4.addr += 127.0.1.2;\n",
+                "ip4.addr += 127.0.1.2;\n    ip4.addr = 127.0.1.3;\n",
+            )
+            .replace(
+                "# keep me\n",
+                "# keep me\n    exec.start = \"/bin/sh /etc/rc\";\n",
+            )
+            .replace("db { host", "db { path = /jails/db; host");
+
+        assert_eq!(config.to_source(), expected);
+        assert_eq!(config.journal().len(), 4);
+        assert_eq!(
+            config.insert_param("db", "persist", "1", Placement::Before(Anchor::Comment("missing"))),
+            Err(EditError::AnchorNotFound { anchor: "comment containing \"missing\"".into() }),
+        );
+    }
+
+    #[test]
+    fn test_insert_jail() {
+        let mut config = LosslessConfig::parse(INPUT).unwrap();
+        let block = |name| JailBlockBuilder::new(name).flag("persist").build();
+
+        config.insert_jail(block("www"), Placement::Before(Anchor::Jail("db"))).unwrap();
+        config.insert_jail(block("mail"), Placement::After(Anchor::Comment("Web"))).unwrap();
+
+        let expected = INPUT
+            .replace("# Web servers\n", "# Web servers\n\nmail {\n    persist;\n}\n")
+            .replace("\ndb {", "\nwww {\n    persist;\n}\n\ndb {");
+
+        assert_eq!(config.to_source(), expected);
+        assert!(LosslessConfig::parse(&expected).is_ok());
+        assert_eq!(
+            config.insert_jail(block("www"), Placement::Start),
+            Err(EditError::JailExists { name: "www".into() }),
+        );
+        assert!(matches!(
+            config.insert_jail(block("dns"), Placement::After(Anchor::Jail("missing"))),
+            Err(EditError::AnchorNotFound { .. }),
+        ));
+    }
+
+    #[test]
+    fn test_rename_jail() {
+        let mut config = LosslessConfig::parse(INPUT).unwrap();
+
+        config.rename_jail("nginx", "web server").unwrap();
+
+        assert_eq!(config.to_source(), INPUT.replace("nginx  {", "\"web server\"  {"));
+        assert!(config.to_config().jail("web server").is_some());
+        assert_eq!(
+            config.rename_jail("db", "web server"),
+            Err(EditError::JailExists { name: "web server".into() }),
+        );
+        assert_eq!(
+            config.rename_jail("nginx", "www"),
+            Err(EditError::UnknownJail { name: "nginx".into() }),
+        );
+    }
+}
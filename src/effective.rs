@@ -0,0 +1,352 @@
+// The effective configuration of a jail as a typed snapshot.
+//
+// effective_params gives every parameter of a jail as strings, leaving each
+// caller to look up the names it knows and convert their values. The
+// parameters which tooling built on jail.conf almost always wants, where the
+// jail lives, what it's called, its addresses, the commands run as it starts
+// and stops, what's mounted for it and what it's allowed to do, are given
+// their own fields in an EffectiveJail instead, already converted. Every
+// other parameter is kept in `extra`, as effective_params would give it, so
+// nothing set for the jail is lost.
+//
+// Values are taken as written, so variables should be expanded with
+// resolve_variables first.
+use std::collections::{
+    BTreeMap,
+    HashMap,
+};
+use std::path::{
+    Path,
+    PathBuf,
+};
+use crate::value::{
+    invalid,
+    parse_bool,
+};
+use crate::{
+    JailAddress,
+    JailConfig,
+    ValueError,
+};
+
+/// A point in a jail's life at which `exec.*` commands are run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ExecStage {
+    /// `exec.prepare`, run before anything else.
+    Prepare,
+    /// `exec.prestart`, run on the host before the jail is created.
+    Prestart,
+    /// `exec.created`, run on the host once the jail is created.
+    Created,
+    /// `exec.start`, run within the jail.
+    Start,
+    /// `exec.poststart`, run on the host once the jail has started.
+    Poststart,
+    /// `exec.prestop`, run on the host before the jail is stopped.
+    Prestop,
+    /// `exec.stop`, run within the jail.
+    Stop,
+    /// `exec.poststop`, run on the host once the jail is removed.
+    Poststop,
+    /// `exec.release`, run after everything else.
+    Release,
+}
+
+impl ExecStage {
+    /// Every stage, in the order jail(8) runs them.
+    pub const ALL: &'static [ExecStage] = &[
+        ExecStage::Prepare,
+        ExecStage::Prestart,
+        ExecStage::Created,
+        ExecStage::Start,
+        ExecStage::Poststart,
+        ExecStage::Prestop,
+        ExecStage::Stop,
+        ExecStage::Poststop,
+        ExecStage::Release,
+    ];
+
+    /// The parameter giving the stage's commands, eg. "exec.prestart".
+    pub fn param(&self) -> &'static str {
+        match self {
+            ExecStage::Prepare   => "exec.prepare",
+            ExecStage::Prestart  => "exec.prestart",
+            ExecStage::Created   => "exec.created",
+            ExecStage::Start     => "exec.start",
+            ExecStage::Poststart => "exec.poststart",
+            ExecStage::Prestop   => "exec.prestop",
+            ExecStage::Stop      => "exec.stop",
+            ExecStage::Poststop  => "exec.poststop",
+            ExecStage::Release   => "exec.release",
+        }
+    }
+}
+
+/// What's mounted for a jail as it starts.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mounts {
+    entries: Vec<String>,
+    fstab:   Option<PathBuf>,
+    devfs:   bool,
+    fdescfs: bool,
+    procfs:  bool,
+}
+
+impl Mounts {
+    /// The fstab(5) style entries given with `mount`, in order.
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// The fstab(5) file given with `mount.fstab`.
+    pub fn fstab(&self) -> Option<&Path> {
+        self.fstab.as_deref()
+    }
+
+    /// Whether devfs(5) is mounted on the jail's /dev.
+    pub fn devfs(&self) -> bool {
+        self.devfs
+    }
+
+    /// Whether fdescfs(5) is mounted on the jail's /dev/fd.
+    pub fn fdescfs(&self) -> bool {
+        self.fdescfs
+    }
+
+    /// Whether procfs(5) is mounted on the jail's /proc.
+    pub fn procfs(&self) -> bool {
+        self.procfs
+    }
+}
+
+/// The effective configuration of a jail, see
+/// `JailConfig::effective_jail`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EffectiveJail {
+    name:     String,
+    path:     Option<PathBuf>,
+    hostname: Option<String>,
+    ip4:      Vec<JailAddress>,
+    ip6:      Vec<JailAddress>,
+    exec:     BTreeMap<ExecStage, Vec<String>>,
+    mounts:   Mounts,
+    allow:    BTreeMap<String, bool>,
+    extra:    HashMap<String, Vec<String>>,
+}
+
+impl EffectiveJail {
+    /// The jail's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The jail's root directory, `path`.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The jail's hostname, `host.hostname`.
+    pub fn hostname(&self) -> Option<&str> {
+        self.hostname.as_deref()
+    }
+
+    /// The jail's IPv4 addresses, `ip4.addr`.
+    pub fn ip4(&self) -> &[JailAddress] {
+        &self.ip4
+    }
+
+    /// The jail's IPv6 addresses, `ip6.addr`.
+    pub fn ip6(&self) -> &[JailAddress] {
+        &self.ip6
+    }
+
+    /// The commands run at a stage, in order.
+    pub fn exec(&self, stage: ExecStage) -> &[String] {
+        self.exec.get(&stage).map_or(&[], Vec::as_slice)
+    }
+
+    /// What's mounted for the jail.
+    pub fn mounts(&self) -> &Mounts {
+        &self.mounts
+    }
+
+    /// The `allow.*` permissions set for the jail, keyed by their full
+    /// name, eg. "allow.mount.zfs".
+    pub fn allow(&self) -> &BTreeMap<String, bool> {
+        &self.allow
+    }
+
+    /// Whether the jail has a permission, given with or without its
+    /// "allow." prefix, eg. "mount.zfs". Permissions not set aren't given.
+    pub fn is_allowed(&self, permission: &str) -> bool {
+        let name = if permission.starts_with("allow.") {
+            permission.to_string()
+        }
+        else {
+            format!("allow.{}", permission)
+        };
+
+        self.allow.get(&name).copied().unwrap_or(false)
+    }
+
+    /// Every other parameter set for the jail, with its values.
+    pub fn extra(&self) -> &HashMap<String, Vec<String>> {
+        &self.extra
+    }
+}
+
+// The last value of a boolean parameter, false if it isn't set.
+fn flag(params: &mut BTreeMap<String, Vec<String>>, name: &str) -> Result<bool, ValueError> {
+    match params.remove(name).and_then(|mut values| values.pop()) {
+        Some(value) => parse_bool(&value).ok_or_else(|| invalid(name, &value, "a boolean")),
+        None        => Ok(false),
+    }
+}
+
+impl<'a> JailConfig<'a> {
+    /// The effective configuration of the named jail, with the common
+    /// parameters converted to their types, or None if there's no such
+    /// jail. Parameters which can't be converted are an error.
+    pub fn effective_jail(&self, jail: &str) -> Result<Option<EffectiveJail>, ValueError> {
+        let mut params = match self.effective_params(jail) {
+            Some(params) => params,
+            None         => return Ok(None),
+        };
+
+        let (ip4, ip6) = self.addresses(jail)?
+            .into_iter()
+            .partition(|address| address.param() == "ip4.addr");
+
+        params.remove("ip4.addr");
+        params.remove("ip6.addr");
+
+        let mut last = |name: &str| params.remove(name).and_then(|mut values| values.pop());
+
+        let path = last("path").map(PathBuf::from);
+        let hostname = last("host.hostname");
+        let fstab = last("mount.fstab").map(PathBuf::from);
+
+        let exec = ExecStage::ALL.iter()
+            .filter_map(|stage| Some((*stage, params.remove(stage.param())?)))
+            .collect();
+
+        let mounts = Mounts {
+            entries: params.remove("mount").unwrap_or_default(),
+            fstab,
+            devfs:   flag(&mut params, "mount.devfs")?,
+            fdescfs: flag(&mut params, "mount.fdescfs")?,
+            procfs:  flag(&mut params, "mount.procfs")?,
+        };
+
+        let permissions: Vec<String> = params.keys()
+            .filter(|name| name.starts_with("allow."))
+            .cloned()
+            .collect();
+
+        let mut allow = BTreeMap::new();
+
+        for name in permissions {
+            let value = flag(&mut params, &name)?;
+            allow.insert(name, value);
+        }
+
+        Ok(Some(EffectiveJail {
+            name: jail.to_string(),
+            path,
+            hostname,
+            ip4,
+            ip6,
+            exec,
+            mounts,
+            allow,
+            extra: params.into_iter().collect(),
+        }))
+    }
+
+    /// The effective configuration of every configured jail, in the order
+    /// they're first configured, see `effective_jail`.
+    pub fn effective_jails(&self) -> Result<Vec<EffectiveJail>, ValueError> {
+        let mut jails = Vec::new();
+
+        for name in self.jail_names() {
+            jails.extend(self.effective_jail(name)?);
+        }
+
+        Ok(jails)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use std::net::IpAddr;
+
+    #[test]
+    fn test_effective_jail() {
+        let config = JailConfig::parse(indoc!(r#"
+            exec.start = "/bin/sh /etc/rc";
+            mount.devfs;
+            allow.raw_sockets = 1;
+            www {
+                path = "/jails/www";
+                host.hostname = "www.example.org";
+                ip4.addr = "em0|192.0.2.1/24";
+                ip4.addr += 192.0.2.2;
+                ip6.addr = 2001:db8::1;
+                exec.prestart = "echo one";
+                exec.prestart += "echo two";
+                mount += "/data /jails/www/data nullfs ro 0 0";
+                allow.nomount;
+                allow.mount.zfs;
+                devfs_ruleset = 4;
+                persist;
+            }
+            "#)).unwrap();
+
+        let www = config.effective_jail("www").unwrap().unwrap();
+
+        assert_eq!(www.name(), "www");
+        assert_eq!(www.path(), Some(Path::new("/jails/www")));
+        assert_eq!(www.hostname(), Some("www.example.org"));
+
+        let ip4: Vec<IpAddr> = www.ip4().iter().map(JailAddress::addr).collect();
+        assert_eq!(ip4, vec!["192.0.2.1".parse::<IpAddr>().unwrap(), "192.0.2.2".parse().unwrap()]);
+        assert_eq!(www.ip4()[0].interface(), Some("em0"));
+        assert_eq!(www.ip6().len(), 1);
+
+        assert_eq!(www.exec(ExecStage::Prestart), ["echo one", "echo two"]);
+        assert_eq!(www.exec(ExecStage::Start), ["/bin/sh /etc/rc"]);
+        assert!(www.exec(ExecStage::Stop).is_empty());
+
+        assert!(www.mounts().devfs());
+        assert!(!www.mounts().procfs());
+        assert_eq!(www.mounts().entries().len(), 1);
+
+        assert!(www.is_allowed("raw_sockets"));
+        assert!(www.is_allowed("allow.mount.zfs"));
+        assert!(!www.is_allowed("mount"));
+        assert!(!www.is_allowed("sysvipc"));
+        assert_eq!(www.allow().len(), 3);
+
+        assert_eq!(www.extra()["devfs_ruleset"], vec!["4"]);
+        assert_eq!(www.extra()["persist"], vec!["true"]);
+        assert_eq!(www.extra().len(), 2);
+
+        assert_eq!(config.effective_jail("missing"), Ok(None));
+        assert_eq!(config.effective_jails().unwrap(), vec![www]);
+    }
+
+    #[test]
+    fn test_effective_jail_invalid() {
+        let config = JailConfig::parse("www {\n    mount.devfs = maybe;\n}\n").unwrap();
+
+        assert_eq!(
+            config.effective_jail("www").unwrap_err().to_string(),
+            "mount.devfs = \"maybe\" is not a boolean",
+        );
+
+        let config = JailConfig::parse("www {\n    ip4.addr = www;\n}\n").unwrap();
+        assert!(config.effective_jail("www").is_err());
+    }
+}
@@ -0,0 +1,383 @@
+// Errors produced while parsing.
+//
+// A ParseError records where in the input parsing failed, as a byte offset
+// and as a line and column, along with the text found there and a short
+// description of what the parser expected instead. This is enough for a
+// command line tool to point the user at the line to fix. Its kind tells
+// syntax errors apart from input refused for exceeding the parser's limits,
+// or for not being UTF-8.
+//
+// Error brings together everything that can go wrong between reading a
+// file and having a usable configuration: reading and parsing it, with the
+// path of the file, expanding its includes, resolving its variables,
+// validating it, and rendering it from a template. Each of the more specific
+// errors converts into it, so a program using several of these steps can use
+// `?` throughout.
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use crate::{
+    IncludeError,
+    TemplateError,
+    ValidationError,
+    VariableError,
+};
+use crate::schema::Version;
+use crate::Position;
+
+/// What a ParseError was raised for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// The input isn't valid jail.conf.
+    Syntax,
+    /// Blocks are nested deeper than the limit, see
+    /// `ParseOptions::max_depth`.
+    TooDeep {
+        limit: usize,
+    },
+    /// The input is larger than the limit in bytes, see
+    /// `ParseOptions::max_size`.
+    TooLarge {
+        limit: usize,
+    },
+    /// The input isn't valid UTF-8, see `parse_bytes`.
+    InvalidUtf8,
+    /// An include couldn't be expanded, see
+    /// `ParseOptions::resolve_includes`.
+    Include,
+    /// A parameter isn't available on the release being targeted, see
+    /// `ParseOptions::target_version`.
+    Unavailable {
+        version: Version,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    kind:     ParseErrorKind,
+    offset:   usize,
+    line:     usize,
+    column:   usize,
+    fragment: String,
+    expected: String,
+}
+
+impl ParseError {
+    // Create an error at the given byte offset into input.
+    pub(crate) fn new(input: &[u8], offset: usize, expected: &str) -> Self {
+        let offset = offset.min(input.len());
+        let before = &input[..offset];
+
+        let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+        let line_start = before.iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |newline| newline + 1);
+
+        let line_end = input[offset..].iter()
+            .position(|&b| b == b'\n')
+            .map_or(input.len(), |newline| offset + newline);
+
+        let fragment = String::from_utf8_lossy(&input[offset..line_end])
+            .trim_end()
+            .to_string();
+
+        Self {
+            kind: ParseErrorKind::Syntax,
+            offset,
+            line,
+            column:   offset - line_start + 1,
+            fragment,
+            expected: expected.to_string(),
+        }
+    }
+
+    // Move an error found in input starting at base within some larger
+    // input to its position in the larger input.
+    pub(crate) fn starting_at(mut self, base: Position) -> Self {
+        if self.line == 1 {
+            self.column += base.column() - 1;
+        }
+
+        self.offset += base.offset();
+        self.line += base.line() - 1;
+        self
+    }
+
+    pub(crate) fn with_kind(mut self, kind: ParseErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Whether the error is in the syntax of the input, the input exceeded
+    /// one of the parser's limits, or it was refused by one of the other
+    /// ParseOptions.
+    pub fn kind(&self) -> ParseErrorKind {
+        self.kind
+    }
+
+    /// The byte offset into the input where the error was found.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The line the error was found on, starting from 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The column the error was found at, in bytes, starting from 1.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// The input from the error to the end of its line.
+    pub fn fragment(&self) -> &str {
+        &self.fragment
+    }
+
+    /// A description of what the parser expected to find.
+    pub fn expected(&self) -> &str {
+        &self.expected
+    }
+
+    /// A suggestion of what's likely wrong, for the most common mistakes,
+    /// eg. a parameter missing its `;`.
+    pub fn hint(&self) -> Option<&'static str> {
+        let hint = match self.kind {
+            ParseErrorKind::TooDeep { .. } => "see ParseOptions::max_depth to allow deeper nesting",
+            ParseErrorKind::TooLarge { .. } => "see ParseOptions::max_size to allow larger input",
+            ParseErrorKind::InvalidUtf8 => "the file isn't UTF-8, check the encoding it was saved in",
+            ParseErrorKind::Include => "check the files the include matches exist and parse",
+            ParseErrorKind::Unavailable { .. } => {
+                "the parameter was added in a later release, see ParseOptions::target_version"
+            },
+            ParseErrorKind::Syntax => match self.expected.as_str() {
+                "';' after parameter value" => {
+                    "did you forget to end the parameter with ';'?"
+                },
+                "';' after parameter name" => {
+                    "parameters end with ';', or take a value with '='"
+                },
+                "closing '\"' after value" | "closing \"'\" after value" => {
+                    "is the value missing its closing quote?"
+                },
+                "'}' to close jail block" => "is the jail's block missing its closing '}'?",
+                "'*/' to close comment" => "is the comment missing its closing '*/'?",
+                "a parameter, comment or jail block before '}'" => {
+                    "is there a '}' too many, or a '{' missing before it?"
+                },
+                "a jail name before '{'" => "blocks are named by the jail they're for",
+                "more input" => "the input ends part way through a statement",
+                _ => return None,
+            },
+        };
+
+        Some(hint)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "error at line {}, column {}: expected {}",
+            self.line,
+            self.column,
+            self.expected,
+        )
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// Any error from reading, parsing or checking a configuration.
+#[derive(Debug)]
+pub enum Error {
+    /// The file couldn't be read.
+    Io {
+        path:  PathBuf,
+        error: io::Error,
+    },
+    /// The file couldn't be parsed.
+    Parse {
+        path:  PathBuf,
+        error: ParseError,
+    },
+    /// The includes of a file couldn't be expanded.
+    Include(IncludeError),
+    /// A variable couldn't be resolved.
+    Variable(VariableError),
+    /// A parameter failed validation. Boxed, as it's much larger than the
+    /// other errors.
+    Validation(Box<ValidationError>),
+    /// A template couldn't be rendered.
+    Template(TemplateError),
+}
+
+impl Error {
+    /// The file the error was found in, if known.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::Io { path, .. } |
+            Error::Parse { path, .. } => Some(path),
+            Error::Include(IncludeError::Io { path, .. }) |
+            Error::Include(IncludeError::Parse { path, .. }) |
+            Error::Include(IncludeError::Cycle { path, .. }) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, error } => {
+                write!(f, "couldn't read {}: {}", path.display(), error)
+            },
+            Error::Parse { path, error } => {
+                write!(f, "{}: {}", path.display(), error)
+            },
+            Error::Include(e)    => write!(f, "{}", e),
+            Error::Variable(e)   => write!(f, "{}", e),
+            Error::Validation(e) => write!(f, "{}", e),
+            Error::Template(e)   => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { error, .. }    => Some(error),
+            Error::Parse { error, .. } => Some(error),
+            Error::Include(e)          => Some(e),
+            Error::Variable(e)         => Some(e),
+            Error::Validation(e)       => Some(&**e),
+            Error::Template(e)         => Some(e),
+        }
+    }
+}
+
+impl From<IncludeError> for Error {
+    fn from(e: IncludeError) -> Self {
+        Error::Include(e)
+    }
+}
+
+impl From<VariableError> for Error {
+    fn from(e: VariableError) -> Self {
+        Error::Variable(e)
+    }
+}
+
+impl From<ValidationError> for Error {
+    fn from(e: ValidationError) -> Self {
+        Error::Validation(Box::new(e))
+    }
+}
+
+impl From<TemplateError> for Error {
+    fn from(e: TemplateError) -> Self {
+        Error::Template(e)
+    }
+}
+
+// Guess what was expected from the input remaining where parsing stopped.
+// The parser itself only knows that none of its alternatives matched, so
+// look at the statement that failed for the most likely mistake.
+pub(crate) fn expected(rest: &str) -> &'static str {
+    let line = rest.lines().next().unwrap_or("");
+
+    if rest.is_empty() {
+        "more input"
+    }
+    else if rest.starts_with("/*") {
+        "'*/' to close comment"
+    }
+    else if rest.starts_with('}') {
+        "a parameter, comment or jail block before '}'"
+    }
+    else if line.matches('"').count() % 2 == 1 {
+        "closing '\"' after value"
+    }
+    else if line.contains('{') {
+        "'}' to close jail block"
+    }
+    else if line.contains('=') {
+        "';' after parameter value"
+    }
+    else {
+        "';' after parameter name"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_error_position() {
+        let input = b"persist;\nnginx {\n    path = \"/a\"\n}\n";
+        let err = ParseError::new(input, 21, "';' after parameter value");
+
+        assert_eq!(err.offset(), 21);
+        assert_eq!(err.line(), 3);
+        assert_eq!(err.column(), 5);
+        assert_eq!(err.fragment(), "path = \"/a\"");
+        assert_eq!(
+            err.to_string(),
+            "error at line 3, column 5: expected ';' after parameter value",
+        );
+        assert_eq!(err.hint(), Some("did you forget to end the parameter with ';'?"));
+    }
+
+    #[test]
+    fn test_parse_error_at_end() {
+        let err = ParseError::new(b"persist;", 100, "more input");
+
+        assert_eq!(err.offset(), 8);
+        assert_eq!(err.line(), 1);
+        assert_eq!(err.column(), 9);
+        assert_eq!(err.fragment(), "");
+    }
+
+    #[test]
+    fn test_expected() {
+        assert_eq!(expected("path = \"/a\"\n}"), "';' after parameter value");
+        assert_eq!(expected("path = \"/a;\n"), "closing '\"' after value");
+        assert_eq!(expected("/* never closed"), "'*/' to close comment");
+        assert_eq!(expected("} extra"), "a parameter, comment or jail block before '}'");
+        assert_eq!(expected("persist\n"), "';' after parameter name");
+        assert_eq!(expected(""), "more input");
+    }
+
+    #[test]
+    fn test_error_conversions() {
+        let err = Error::from(VariableError::Recursive {
+            name: "a".to_string(),
+        });
+
+        assert_eq!(err.to_string(), "variable $a refers to itself");
+        assert!(err.path().is_none());
+        assert!(error::Error::source(&err).is_some());
+
+        let err = Error::from(IncludeError::Cycle {
+            path:  PathBuf::from("/etc/jail.conf"),
+            chain: vec![
+                PathBuf::from("/etc/jail.conf"),
+                PathBuf::from("/etc/jail.conf.d/www.conf"),
+                PathBuf::from("/etc/jail.conf"),
+            ],
+        });
+
+        assert_eq!(err.path(), Some(Path::new("/etc/jail.conf")));
+        assert_eq!(
+            err.to_string(),
+            "/etc/jail.conf includes itself: /etc/jail.conf -> /etc/jail.conf.d/www.conf -> /etc/jail.conf",
+        );
+    }
+}
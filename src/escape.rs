@@ -0,0 +1,305 @@
+// Quoting and escape sequences in values.
+//
+// Values may contain backslash escapes as in C, eg. `\"`, `\\`, `\n`, or
+// `\101` in octal and `\x41` in hex. A backslash at the end of a line
+// continues the value on the next line. Any other escaped character stands
+// for itself, which is how a literal `$` is written as `\$`.
+//
+// Single quoted values are taken literally, without any escapes.
+use std::borrow::Cow;
+use crate::{
+    bare_len,
+    QuoteStyle,
+};
+
+// How a value as written in the source was quoted.
+pub(crate) fn quote_style(raw: &str) -> QuoteStyle {
+    let quoted = |q: char| raw.len() >= 2 && raw.starts_with(q) && raw.ends_with(q);
+
+    if quoted('"') {
+        QuoteStyle::Double
+    }
+    else if quoted('\'') {
+        QuoteStyle::Single
+    }
+    else {
+        QuoteStyle::None
+    }
+}
+
+// Whether text starts with a backslash ending its line, which continues a
+// statement on the next line.
+pub(crate) fn is_continuation(text: &str) -> bool {
+    text.starts_with("\\\n") || text.starts_with("\\\r\n")
+}
+
+// The end of the fragment starting at start within raw.
+fn fragment_end(raw: &str, start: usize) -> usize {
+    let bytes = raw.as_bytes();
+    let mut i = start;
+
+    match bytes[i] {
+        b'"' => {
+            i += 1;
+
+            while i < bytes.len() {
+                match bytes[i] {
+                    b'\\' => i += 2,
+                    b'"'  => {
+                        i += 1;
+                        break;
+                    },
+                    _     => i += 1,
+                }
+            }
+        },
+        b'\'' => {
+            i = raw[i + 1..].find('\'').map_or(bytes.len(), |close| i + close + 2);
+        },
+        _ => i += bare_len(&raw[i..]).max(1),
+    }
+
+    i.min(bytes.len())
+}
+
+// Split a value as written in the source into the fragments it's
+// concatenated from, eg. `"/bin/sh " "/etc/rc"` into `"/bin/sh "` and
+// `"/etc/rc"`. Each fragment is either quoted or bare, and fragments may be
+// separated by spaces or continued lines. A value of a single fragment is
+// returned as is.
+pub(crate) fn fragments(raw: &str) -> Vec<&str> {
+    let bytes = raw.as_bytes();
+    let mut fragments = Vec::new();
+    let mut i = 0;
+
+    loop {
+        loop {
+            match bytes.get(i) {
+                Some(b' ') | Some(b'\t') => i += 1,
+                Some(b'\\') if is_continuation(&raw[i..]) => {
+                    i += raw[i..].find('\n').map_or(1, |newline| newline + 1);
+                },
+                _ => break,
+            }
+        }
+
+        if i >= bytes.len() {
+            break;
+        }
+
+        let start = i;
+
+        i = fragment_end(raw, i);
+        fragments.push(&raw[start..i]);
+    }
+
+    if fragments.is_empty() {
+        fragments.push(raw);
+    }
+
+    fragments
+}
+
+// The text of a value without its surrounding quotes, if it has them.
+pub(crate) fn inner(raw: &str) -> &str {
+    match quote_style(raw) {
+        QuoteStyle::None => raw,
+        _                => &raw[1..raw.len() - 1],
+    }
+}
+
+// Decode a value as written in the source into the value it represents.
+// Values without any escapes are borrowed rather than copied.
+pub(crate) fn unescape(raw: &str) -> Cow<'_, str> {
+    let text = inner(raw);
+
+    if quote_style(raw) == QuoteStyle::Single || !text.contains('\\') {
+        return Cow::Borrowed(text);
+    }
+
+    let mut value = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+
+        let escaped = match chars.next() {
+            Some(escaped) => escaped,
+            // A trailing backslash has nothing to escape.
+            None          => {
+                value.push('\\');
+                break;
+            },
+        };
+
+        match escaped {
+            'a'  => value.push('\x07'),
+            'b'  => value.push('\x08'),
+            'f'  => value.push('\x0c'),
+            'n'  => value.push('\n'),
+            'r'  => value.push('\r'),
+            't'  => value.push('\t'),
+            'v'  => value.push('\x0b'),
+            '\n' => {},
+            '\r' if chars.peek() == Some(&'\n') => {
+                chars.next();
+            },
+            '0'..='7' => {
+                let mut code = escaped.to_digit(8).unwrap_or(0);
+
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            code = code * 8 + digit;
+                            chars.next();
+                        },
+                        None => break,
+                    }
+                }
+
+                value.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+            },
+            'x' => {
+                let mut code = 0;
+                let mut digits = 0;
+
+                while digits < 2 {
+                    match chars.peek().and_then(|c| c.to_digit(16)) {
+                        Some(digit) => {
+                            code = code * 16 + digit;
+                            digits += 1;
+                            chars.next();
+                        },
+                        None => break,
+                    }
+                }
+
+                if digits == 0 {
+                    value.push('x');
+                }
+                else {
+                    value.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+                }
+            },
+            other => value.push(other),
+        }
+    }
+
+    Cow::Owned(value)
+}
+
+// Decode a value as written in the source, which may be concatenated from
+// several fragments, into the value it represents.
+pub(crate) fn decode(raw: &str) -> Cow<'_, str> {
+    // Most values are a single fragment, which can be decoded without
+    // splitting the value up.
+    if raw.is_empty() || fragment_end(raw, 0) == raw.len() {
+        return unescape(raw);
+    }
+
+    match &fragments(raw)[..] {
+        [_]       => unescape(raw),
+        fragments => Cow::Owned(fragments.iter().map(|f| unescape(f)).collect()),
+    }
+}
+
+// Escape a value for use within double quotes, escaping `$` as well if it
+// must be taken literally rather than as a variable reference.
+fn escape_into(escaped: &mut String, value: &str, literal: bool) {
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            '$' if literal => escaped.push_str("\\$"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            _    => escaped.push(c),
+        }
+    }
+}
+
+// Escape a value which must be taken literally, without quoting it.
+pub(crate) fn escape_literal(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    escape_into(&mut escaped, value, true);
+    escaped
+}
+
+// Quote a value for output, escaping any characters which would otherwise
+// end the string early or be lost.
+pub(crate) fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+
+    quoted.push('"');
+    escape_into(&mut quoted, value, false);
+    quoted.push('"');
+    quoted
+}
+
+// Quote a value which must be taken literally, escaping `$` as well so that
+// it isn't mistaken for a variable reference.
+pub(crate) fn quote_literal(value: &str) -> String {
+    format!("\"{}\"", escape_literal(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape(r#""plain""#), "plain");
+        assert_eq!(unescape("bare"), "bare");
+        assert_eq!(unescape(r#""say \"hi\"""#), "say \"hi\"");
+        assert_eq!(unescape(r#""a\\b""#), "a\\b");
+        assert_eq!(unescape(r#""one\ntwo\tthree""#), "one\ntwo\tthree");
+        assert_eq!(unescape(r#""\101\x42""#), "AB");
+        assert_eq!(unescape(r#""\$name""#), "$name");
+        assert_eq!(unescape("\"line \\\ncontinued\""), "line continued");
+    }
+
+    #[test]
+    fn test_single_quoted() {
+        assert_eq!(quote_style("'$x \\n'"), QuoteStyle::Single);
+        assert_eq!(quote_style("\"x\""), QuoteStyle::Double);
+        assert_eq!(quote_style("x"), QuoteStyle::None);
+        assert_eq!(unescape("'$x \\n'"), "$x \\n");
+        assert_eq!(escape_literal("$x \\n"), "\\$x \\\\n");
+    }
+
+    #[test]
+    fn test_fragments() {
+        assert_eq!(fragments(r#""/bin/sh " "/etc/rc""#), vec![r#""/bin/sh ""#, r#""/etc/rc""#]);
+        assert_eq!(fragments(r#""a \" b"'c'd"#), vec![r#""a \" b""#, "'c'", "d"]);
+        assert_eq!(fragments("bare"), vec!["bare"]);
+        assert_eq!(fragments(""), vec![""]);
+
+        assert_eq!(decode(r#""/bin/sh " "/etc/rc""#), "/bin/sh /etc/rc");
+        assert_eq!(decode(r#""a\tb" '\t'"#), "a\tb\\t");
+        assert_eq!(decode(r#""say \"hi\"""#), "say \"hi\"");
+    }
+
+    #[test]
+    fn test_unescape_borrows_without_escapes() {
+        match unescape(r#""/usr/jails""#) {
+            Cow::Borrowed(value) => assert_eq!(value, "/usr/jails"),
+            Cow::Owned(_)        => panic!("expected a borrowed value"),
+        }
+    }
+
+    #[test]
+    fn test_quote_round_trip() {
+        let value = "echo \"$1\"\n\\done";
+
+        assert_eq!(quote(value), r#""echo \"$1\"\n\\done""#);
+        assert_eq!(unescape(&quote(value)), value);
+        assert_eq!(quote_literal("$5"), r#""\$5""#);
+    }
+}
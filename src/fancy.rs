@@ -0,0 +1,114 @@
+// Annotated parse errors, with the fancy-errors feature.
+//
+// A ParseError's Display gives a line and column, which is enough for an
+// editor but leaves a person at a terminal counting characters. With this
+// feature ParseError is a miette Diagnostic, labelled at the offset it was
+// found at and with its hint as help, so programs already reporting through
+// miette can attach the source and get the offending line printed with a
+// caret under the error. annotate does the same for programs which only
+// want the text.
+use std::error;
+use std::fmt;
+use miette::{
+    Diagnostic,
+    GraphicalReportHandler,
+    GraphicalTheme,
+    LabeledSpan,
+    NamedSource,
+    SourceCode,
+};
+use crate::ParseError;
+
+impl ParseError {
+    // The span to mark: the character the error was found at, if any. The
+    // message already says what was expected, so it isn't repeated here.
+    fn label(&self) -> LabeledSpan {
+        let len = self.fragment().chars().next().map_or(0, char::len_utf8);
+        let span = self.offset()..self.offset() + len;
+
+        LabeledSpan::new_primary_with_span(Some("here".to_string()), span)
+    }
+
+    /// The error as it would be printed by miette, naming the file it's in
+    /// and showing the line at fault with the error marked and a hint, if
+    /// there is one. Source must be the input the error was found in.
+    pub fn annotate(&self, name: &str, source: &str) -> String {
+        let annotated = Annotated {
+            error:  self,
+            source: NamedSource::new(name, source.to_string()),
+        };
+
+        let mut out = String::new();
+
+        GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+            .render_report(&mut out, &annotated)
+            .expect("writing to a String doesn't fail");
+
+        out
+    }
+}
+
+impl Diagnostic for ParseError {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new("jailconf::parse"))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.hint().map(|hint| Box::new(hint) as Box<dyn fmt::Display>)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        Some(Box::new(std::iter::once(self.label())))
+    }
+}
+
+// An error along with the source it was found in, for rendering.
+#[derive(Debug)]
+struct Annotated<'e> {
+    error:  &'e ParseError,
+    source: NamedSource<String>,
+}
+
+impl<'e> fmt::Display for Annotated<'e> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected {}", self.error.expected())
+    }
+}
+
+impl<'e> error::Error for Annotated<'e> {}
+
+impl<'e> Diagnostic for Annotated<'e> {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.error.code()
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.error.help()
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.source)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        self.error.labels()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::JailConfig;
+
+    #[test]
+    fn test_annotate() {
+        let input = "persist;\nnginx {\n    path = \"/a\"\n}\n";
+        let error = JailConfig::parse(input).unwrap_err();
+        let annotated = error.annotate("jail.conf", input);
+
+        assert!(annotated.contains("expected ';' after parameter value"));
+        assert!(annotated.contains("[jail.conf:3:5]"));
+        assert!(annotated.contains("3 │     path = \"/a\""));
+        assert!(annotated.contains("╰── here"));
+        assert!(annotated.contains("did you forget to end the parameter with ';'?"));
+    }
+}
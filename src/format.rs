@@ -0,0 +1,460 @@
+// Canonical formatting of configuration.
+//
+// Unlike Display, which writes values exactly as they were given, the
+// formatter normalises a configuration: one statement per line, a consistent
+// indent, `=` signs aligned within runs of parameters, blank lines around
+// top level blocks, and values quoted only when they need to be. Formatting
+// the output again leaves it unchanged.
+//
+// Optionally, groups of parameters, see transform::GroupParams, are set
+// apart by blank lines too.
+use crate::builder::{
+    name_quote,
+    raw_value,
+};
+use crate::escape::{
+    fragments,
+    quote,
+    quote_literal,
+    quote_style,
+};
+use crate::transform::statement_group;
+use crate::{
+    JailBlock,
+    JailConf,
+    QuoteStyle,
+    Span,
+};
+
+/// Options controlling `format_config`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormatOptions {
+    indent:          usize,
+    tabs:            bool,
+    align:           bool,
+    separate_groups: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indent:          4,
+            tabs:            false,
+            align:           true,
+            separate_groups: false,
+        }
+    }
+}
+
+impl FormatOptions {
+    /// The default options: four space indents with aligned `=` signs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Indent by the given number of spaces per level.
+    pub fn indent(mut self, width: usize) -> Self {
+        self.indent = width;
+        self
+    }
+
+    /// Indent with one tab per level instead of spaces.
+    pub fn tabs(mut self, tabs: bool) -> Self {
+        self.tabs = tabs;
+        self
+    }
+
+    /// Whether to align the `=` signs of consecutive parameters.
+    pub fn align(mut self, align: bool) -> Self {
+        self.align = align;
+        self
+    }
+
+    /// Whether to put a blank line between consecutive parameters in
+    /// different groups, by the part of their name before the first dot. A
+    /// comment before a parameter is kept with it.
+    pub fn separate_groups(mut self, separate: bool) -> Self {
+        self.separate_groups = separate;
+        self
+    }
+}
+
+// The width of what comes before the `=` of a statement, if it has one. The
+// `+` of an append counts as part of it, so that `=` signs line up.
+fn label_len(statement: &JailConf) -> Option<usize> {
+    let (len, append) = match statement {
+        JailConf::ParamValue(param) => (param.name().len(), param.is_append()),
+        JailConf::Variable(var)     => (var.name().len() + 1, var.is_append()),
+        _                           => return None,
+    };
+
+    Some(if append { len + 1 } else { len })
+}
+
+// The group of each parameter, and of each comment before one, None for
+// everything else.
+fn unit_groups<'s>(statements: &'s [JailConf]) -> Vec<Option<&'s str>> {
+    let mut groups = vec![None; statements.len()];
+    let mut next = None;
+
+    for (i, statement) in statements.iter().enumerate().rev() {
+        if !matches!(statement, JailConf::Comment(_)) {
+            next = statement_group(statement);
+        }
+
+        groups[i] = next;
+    }
+
+    groups
+}
+
+// Whether a comment follows the statement before it on the same line, and
+// so is that statement's trailing comment, see attach_comments. Statements
+// made with the builders have no position and are never on a line together.
+fn is_trailing(previous: &JailConf, statement: &JailConf) -> bool {
+    matches!(statement, JailConf::Comment(_))
+        && !matches!(previous, JailConf::Comment(_))
+        && statement.span() != Span::default()
+        && statement.span().start().line() == previous.span().end().line()
+}
+
+// Which statements are trailing comments, see is_trailing.
+fn trailing_comments(statements: &[JailConf]) -> Vec<bool> {
+    let mut trailing = vec![false; statements.len()];
+
+    for i in 1..statements.len() {
+        trailing[i] = is_trailing(&statements[i - 1], &statements[i]);
+    }
+
+    trailing
+}
+
+// Whether the statement at i starts a new group of parameters.
+fn starts_group(groups: &[Option<&str>], i: usize) -> bool {
+    i > 0 && matches!(
+        (groups[i - 1], groups[i]),
+        (Some(previous), Some(group)) if previous != group,
+    )
+}
+
+// The width to pad the label of each statement to. Labels are aligned within
+// runs of parameters and variables, which comments, includes and blocks
+// break, as does the start of a new group of parameters, see unit_groups.
+// Trailing comments stay on the line of their statement, so don't.
+fn label_widths(statements: &[JailConf], groups: &[Option<&str>], trailing: &[bool])
+-> Vec<usize> {
+    let mut widths = vec![0; statements.len()];
+    let mut start = 0;
+
+    for end in 0..=statements.len() {
+        let breaks_run = !matches!(
+            statements.get(end),
+            Some(JailConf::ParamValue(_) | JailConf::ParamBool(_) | JailConf::Variable(_)),
+        ) && !trailing.get(end).copied().unwrap_or(false);
+
+        if breaks_run || (end > start && starts_group(groups, end)) {
+            let run = &statements[start..end];
+            let width = run.iter().filter_map(label_len).max().unwrap_or(0);
+
+            for slot in &mut widths[start..end] {
+                *slot = width;
+            }
+
+            start = if breaks_run { end + 1 } else { end };
+        }
+    }
+
+    widths
+}
+
+// A value as it should be written. Values written bare are kept as they
+// are, other values are left bare where possible, and otherwise double
+// quoted, with adjacent fragments joined. Single quoted values have any `$`
+// escaped so that it's still taken literally, and values which escape a `$`
+// are kept as they are, since decoding them loses the escape. So are values
+// of several fragments containing a `$`, which may be taken literally in
+// some fragments but not others.
+pub(crate) fn normalize_raw(raw: &str, value: &str) -> String {
+    if raw.contains("\\$") || (value.contains('$') && fragments(raw).len() > 1) {
+        return raw.to_string();
+    }
+
+    match quote_style(raw) {
+        QuoteStyle::None if fragments(raw).len() == 1 => raw.to_string(),
+        QuoteStyle::Single if value.contains('$')     => quote_literal(value),
+        _                                             => raw_value(value),
+    }
+}
+
+struct Formatter<'o> {
+    options: &'o FormatOptions,
+    output:  String,
+}
+
+impl<'o> Formatter<'o> {
+    fn indent(&mut self, depth: usize) {
+        for _ in 0..depth {
+            if self.options.tabs {
+                self.output.push('\t');
+            }
+            else {
+                self.output.extend((0..self.options.indent).map(|_| ' '));
+            }
+        }
+    }
+
+    fn labelled(&mut self, label: &str, width: usize, append: bool, raw: String) {
+        let (op, width) = if append {
+            ("+=", width.saturating_sub(1))
+        }
+        else {
+            ("=", width)
+        };
+
+        self.output.push_str(&format!("{:width$} {} {};", label, op, raw, width = width));
+    }
+
+    fn block(&mut self, block: &JailBlock, depth: usize) {
+        match name_quote(block.name()) {
+            QuoteStyle::None => self.output.push_str(block.name()),
+            _                => self.output.push_str(&quote(block.name())),
+        }
+
+        self.output.push_str(" {");
+
+        // A comment after the opening brace stays there.
+        let params = match block.params().split_first() {
+            Some((JailConf::Comment(comment), rest))
+                if comment.span() != Span::default()
+                    && comment.span().start().line() == block.span().start().line() => {
+                self.output.push(' ');
+                self.output.push_str(&comment.to_string());
+                rest
+            },
+            _ => block.params(),
+        };
+
+        self.output.push('\n');
+        self.statements(params, depth + 1);
+        self.indent(depth);
+        self.output.push('}');
+    }
+
+    fn statements(&mut self, statements: &[JailConf], depth: usize) {
+        let trailing = trailing_comments(statements);
+
+        let mut groups = if self.options.separate_groups {
+            unit_groups(statements)
+        }
+        else {
+            vec![None; statements.len()]
+        };
+
+        // A trailing comment is in the group of its statement, rather than
+        // that of the statement after it.
+        for i in 1..statements.len() {
+            if trailing[i] {
+                groups[i] = groups[i - 1];
+            }
+        }
+
+        let widths = if self.options.align {
+            label_widths(statements, &groups, &trailing)
+        }
+        else {
+            vec![0; statements.len()]
+        };
+
+        for (i, statement) in statements.iter().enumerate() {
+            if trailing[i] {
+                self.output.pop();
+                self.output.push(' ');
+                self.output.push_str(&statement.to_string());
+                self.output.push('\n');
+                continue;
+            }
+
+            if starts_group(&groups, i) {
+                self.output.push('\n');
+            }
+
+            // Top level blocks are set apart by blank lines, keeping any
+            // comment directly before a block with it.
+            if depth == 0 && i > 0 {
+                let previous = match i {
+                    i if trailing[i - 1] => &statements[i - 2],
+                    i                    => &statements[i - 1],
+                };
+                let blank = match (previous, statement) {
+                    (JailConf::Block(_), _)                     => true,
+                    (JailConf::Comment(_), JailConf::Block(_))  => false,
+                    (_, JailConf::Block(_))                     => true,
+                    _                                           => false,
+                };
+
+                if blank {
+                    self.output.push('\n');
+                }
+            }
+
+            self.indent(depth);
+
+            match statement {
+                JailConf::Block(block)      => self.block(block, depth),
+                JailConf::Comment(comment)  => {
+                    self.output.push_str(&comment.to_string());
+                },
+                JailConf::Include(include)  => {
+                    self.output.push_str(&include.to_string());
+                },
+                JailConf::ParamBool(param)  => {
+                    self.output.push_str(param.name());
+                    self.output.push(';');
+                },
+                JailConf::ParamValue(param) => self.labelled(
+                    param.name(),
+                    widths[i],
+                    param.is_append(),
+                    normalize_raw(param.raw(), param.value()),
+                ),
+                JailConf::Variable(var)     => self.labelled(
+                    &format!("${}", var.name()),
+                    widths[i],
+                    var.is_append(),
+                    normalize_raw(var.raw(), var.value()),
+                ),
+            }
+
+            self.output.push('\n');
+        }
+    }
+}
+
+/// Render a configuration in the canonical format, see `FormatOptions`.
+///
+/// Comments are kept as they were placed: on lines of their own, or
+/// following a statement on its line.
+pub fn format_config(config: &[JailConf], options: &FormatOptions) -> String {
+    let mut formatter = Formatter {
+        options,
+        output: String::new(),
+    };
+
+    formatter.statements(config, 0);
+    formatter.output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        # Defaults
+        exec.start = "/bin/sh /etc/rc";
+        persist; $domain = "example.org";
+        "web server" {
+          host.hostname = "www.$domain";
+          path = '/jails/$name';
+            ip4.addr = "127.0.1.1";
+          allow.mount;
+          ip4.addr += lo1|127.0.1.2;
+        }
+        db{children.max = 0;}
+        "#);
+
+    #[test]
+    fn test_format_config() {
+        let config = parse(INPUT).unwrap();
+        let expected = indoc!(r#"
+            # Defaults
+            exec.start = "/bin/sh /etc/rc";
+            persist;
+            $domain    = example.org;
+
+            "web server" {
+                host.hostname = "www.$domain";
+                path          = "/jails/\$name";
+                ip4.addr      = 127.0.1.1;
+                allow.mount;
+                ip4.addr     += lo1|127.0.1.2;
+            }
+
+            db {
+                children.max = 0;
+            }
+            "#);
+
+        let formatted = format_config(&config, &FormatOptions::new());
+
+        assert_eq!(formatted, expected);
+
+        // Formatting is idempotent.
+        let reparsed = parse(&formatted).unwrap();
+        assert_eq!(format_config(&reparsed, &FormatOptions::new()), formatted);
+    }
+
+    #[test]
+    fn test_format_trailing_comments() {
+        let input = indoc!(r#"
+            persist;   # Keep running
+            # Web server
+            nginx { # Public
+              path = /jails/nginx;   # keep
+              host.hostname = nginx;
+            } /* end */
+            db {
+              path = /jails/db;
+            }
+            "#);
+
+        let expected = indoc!(r#"
+            persist; # Keep running
+            # Web server
+            nginx { # Public
+                path          = /jails/nginx; # keep
+                host.hostname = nginx;
+            } /* end */
+
+            db {
+                path = /jails/db;
+            }
+            "#);
+
+        let config = parse(input).unwrap();
+        let formatted = format_config(&config, &FormatOptions::new());
+
+        assert_eq!(formatted, expected);
+
+        // The comments are still attached to the same statements.
+        let reparsed = crate::JailConfig::parse(&formatted).unwrap();
+        let commented = reparsed.commented();
+
+        assert_eq!(commented[0].trailing().unwrap().comment(), " Keep running");
+        assert_eq!(commented[1].trailing().unwrap().comment(), " Public");
+        assert_eq!(commented[1].children()[0].trailing().unwrap().comment(), " keep");
+        assert!(commented[1].children()[1].leading().is_empty());
+        assert_eq!(format_config(reparsed.statements(), &FormatOptions::new()), formatted);
+    }
+
+    #[test]
+    fn test_format_options() {
+        let config = parse("nginx {\n  path = /a;\n  host.hostname = a;\n}\n").unwrap();
+        let options = FormatOptions::new()
+            .tabs(true)
+            .align(false);
+
+        assert_eq!(
+            format_config(&config, &options),
+            "nginx {\n\tpath = /a;\n\thost.hostname = a;\n}\n",
+        );
+
+        let options = FormatOptions::new().indent(2);
+
+        assert_eq!(
+            format_config(&config, &options),
+            "nginx {\n  path          = /a;\n  host.hostname = a;\n}\n",
+        );
+    }
+}
@@ -0,0 +1,310 @@
+// Checks of the files and directories a configuration refers to.
+//
+// A configuration can be valid and still fail when jail(8) comes to use it,
+// because the jail's root directory was never created, its fstab was
+// mistyped, or its console log can't be written. check_filesystem looks at
+// the host's filesystem for each jail: that `path` is a directory, that
+// `mount.fstab` is a file which parses as fstab(5), and that the directory
+// `exec.consolelog` is written to exists and is writable.
+//
+// Every check made is returned, with what was found, so that tools can show
+// what passed as well as what didn't. Values are checked after variables are
+// expanded, and values which still refer to variables are skipped.
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use crate::JailConfig;
+
+/// What a filesystem check found.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FsStatus {
+    /// Everything was as it should be.
+    Ok,
+    /// Nothing exists at the path.
+    Missing,
+    /// A directory was expected, but something else exists at the path.
+    NotADirectory,
+    /// A file was expected, but something else exists at the path.
+    NotAFile,
+    /// The directory isn't writable.
+    NotWritable,
+    /// The file isn't valid fstab(5), the line given being the first which
+    /// isn't.
+    InvalidFstab {
+        line: usize,
+        text: String,
+    },
+    /// The path couldn't be looked at.
+    Error(io::ErrorKind),
+}
+
+/// A single check of a path given by a jail's parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FsCheck {
+    jail:   String,
+    param:  &'static str,
+    path:   PathBuf,
+    status: FsStatus,
+}
+
+impl FsCheck {
+    /// The jail the parameter applies to.
+    pub fn jail(&self) -> &str {
+        &self.jail
+    }
+
+    /// The parameter giving the path, eg. "path".
+    pub fn param(&self) -> &'static str {
+        self.param
+    }
+
+    /// The path checked. For `exec.consolelog` this is the directory the log
+    /// is written to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// What was found.
+    pub fn status(&self) -> &FsStatus {
+        &self.status
+    }
+
+    /// Whether the check passed.
+    pub fn is_ok(&self) -> bool {
+        self.status == FsStatus::Ok
+    }
+}
+
+/// The checks made by `JailConfig::check_filesystem`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FsReport {
+    checks: Vec<FsCheck>,
+}
+
+impl FsReport {
+    /// Every check made, in the order of the jails.
+    pub fn checks(&self) -> &[FsCheck] {
+        &self.checks
+    }
+
+    /// The checks which failed.
+    pub fn problems(&self) -> impl Iterator<Item = &FsCheck> {
+        self.checks.iter().filter(|check| !check.is_ok())
+    }
+
+    /// Whether every check passed.
+    pub fn is_ok(&self) -> bool {
+        self.checks.iter().all(FsCheck::is_ok)
+    }
+}
+
+// The status of an IO error looking at a path.
+fn io_status(e: &io::Error) -> FsStatus {
+    match e.kind() {
+        io::ErrorKind::NotFound => FsStatus::Missing,
+        kind                    => FsStatus::Error(kind),
+    }
+}
+
+// The first line of input which isn't a valid fstab(5) entry, if any.
+fn invalid_fstab_line(input: &str) -> Option<(usize, &str)> {
+    for (number, line) in input.lines().enumerate() {
+        let text = line.trim();
+
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        let numeric = fields.iter().skip(4).all(|field| field.parse::<u32>().is_ok());
+
+        if fields.len() < 4 || fields.len() > 6 || !numeric {
+            return Some((number + 1, line));
+        }
+    }
+
+    None
+}
+
+fn check_dir(path: &Path) -> FsStatus {
+    match fs::metadata(path) {
+        Ok(meta) if meta.is_dir() => FsStatus::Ok,
+        Ok(_)                     => FsStatus::NotADirectory,
+        Err(e)                    => io_status(&e),
+    }
+}
+
+fn check_fstab(path: &Path) -> FsStatus {
+    match fs::metadata(path) {
+        Ok(meta) if meta.is_file() => {},
+        Ok(_)                      => return FsStatus::NotAFile,
+        Err(e)                     => return io_status(&e),
+    }
+
+    match fs::read_to_string(path) {
+        Ok(input) => match invalid_fstab_line(&input) {
+            Some((line, text)) => FsStatus::InvalidFstab {
+                line,
+                text: text.to_string(),
+            },
+            None => FsStatus::Ok,
+        },
+        Err(e) => io_status(&e),
+    }
+}
+
+// Whether the current user may create files in dir.
+#[cfg(unix)]
+fn writable(dir: &Path, _meta: &fs::Metadata) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    match CString::new(dir.as_os_str().as_bytes()) {
+        // SAFETY: path is a valid NUL terminated string for the call.
+        Ok(path) => unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 },
+        Err(_)   => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn writable(_dir: &Path, meta: &fs::Metadata) -> bool {
+    !meta.permissions().readonly()
+}
+
+fn check_log_dir(dir: &Path) -> FsStatus {
+    match fs::metadata(dir) {
+        Ok(meta) if !meta.is_dir()        => FsStatus::NotADirectory,
+        Ok(meta) if !writable(dir, &meta) => FsStatus::NotWritable,
+        Ok(_)                             => FsStatus::Ok,
+        Err(e)                            => io_status(&e),
+    }
+}
+
+impl<'a> JailConfig<'a> {
+    /// Check the paths each jail's parameters refer to on this host's
+    /// filesystem: that `path` is a directory, that `mount.fstab` is a valid
+    /// fstab(5) file, and that the directory `exec.consolelog` is written to
+    /// is writable. Values referring to variables which can't be expanded
+    /// are skipped.
+    pub fn check_filesystem(&self) -> FsReport {
+        let resolved = self.resolve_variables().ok();
+        let config = resolved.as_ref().unwrap_or(self);
+        let mut seen = HashSet::new();
+        let mut checks = Vec::new();
+
+        for block in config.jails() {
+            let jail = block.name();
+
+            if !seen.insert(jail) {
+                continue;
+            }
+
+            let mut check = |param: &'static str, path: PathBuf, status: FsStatus| {
+                checks.push(FsCheck {
+                    jail: jail.to_string(),
+                    param,
+                    path,
+                    status,
+                });
+            };
+
+            let value = |param: &str| {
+                config.get_param(jail, param).filter(|value| !value.contains('$'))
+            };
+
+            if let Some(path) = value("path") {
+                let path = PathBuf::from(path);
+                let status = check_dir(&path);
+
+                check("path", path, status);
+            }
+
+            if let Some(fstab) = value("mount.fstab") {
+                let fstab = PathBuf::from(fstab);
+                let status = check_fstab(&fstab);
+
+                check("mount.fstab", fstab, status);
+            }
+
+            if let Some(log) = value("exec.consolelog") {
+                let dir = match Path::new(&log).parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                    _                                        => PathBuf::from("."),
+                };
+                let status = check_log_dir(&dir);
+
+                check("exec.consolelog", dir, status);
+            }
+        }
+
+        FsReport {
+            checks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::scratch_dir;
+
+    #[test]
+    fn test_check_filesystem() {
+        let dir = scratch_dir("fscheck-report");
+
+        fs::create_dir(dir.join("www")).unwrap();
+        fs::write(dir.join("www.fstab"), "# Ports\n/usr/ports /jails/www/usr/ports nullfs ro 0 0\n").unwrap();
+        fs::write(dir.join("db.fstab"), "/usr/ports /jails/db/usr/ports nullfs\n").unwrap();
+
+        let input = format!(
+            r#"
+            $root = "{root}";
+            www {{
+                path = "$root/www";
+                mount.fstab = "$root/www.fstab";
+                exec.consolelog = "$root/www.log";
+            }}
+            db {{
+                path = "$root/www.fstab";
+                mount.fstab = "$root/db.fstab";
+                exec.consolelog = "$root/logs/db.log";
+            }}
+            "#,
+            root = dir.display(),
+        );
+
+        let config = JailConfig::parse(&input).unwrap();
+        let report = config.check_filesystem();
+
+        assert_eq!(report.checks().len(), 6);
+        assert!(report.checks()[..3].iter().all(FsCheck::is_ok));
+
+        let problems: Vec<(&str, &FsStatus)> = report.problems()
+            .map(|check| (check.param(), check.status()))
+            .collect();
+
+        assert_eq!(problems, vec![
+            ("path", &FsStatus::NotADirectory),
+            ("mount.fstab", &FsStatus::InvalidFstab {
+                line: 1,
+                text: "/usr/ports /jails/db/usr/ports nullfs".to_string(),
+            }),
+            ("exec.consolelog", &FsStatus::Missing),
+        ]);
+        assert_eq!(report.problems().last().unwrap().path(), dir.join("logs"));
+        assert!(!report.is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_fstab_line() {
+        assert_eq!(invalid_fstab_line("# comment\n\nproc /proc procfs rw 0 0\n"), None);
+        assert_eq!(invalid_fstab_line("proc /proc procfs\n"), Some((1, "proc /proc procfs")));
+        assert_eq!(invalid_fstab_line("proc /proc procfs rw x\n"), Some((1, "proc /proc procfs rw x")));
+    }
+}
@@ -0,0 +1,252 @@
+// Generation of arbitrary configuration, for fuzzing and property tests.
+//
+// With the arbitrary feature, each AST type implements arbitrary::Arbitrary.
+// Nodes are generated as the builder would make them: names which can be
+// written in jail.conf, values of any text quoted when they need to be, and
+// source text matching the rendered node. Rendering a generated
+// configuration and parsing the result should give back the same
+// configuration, which is what the round_trip fuzz target checks.
+use std::borrow::Cow;
+use arbitrary::{
+    Arbitrary,
+    Result,
+    Unstructured,
+};
+use crate::builder::{
+    name_quote,
+    raw_value,
+};
+use crate::schema;
+use crate::{
+    Annotations,
+    CommentStyle,
+    JailBlock,
+    JailComment,
+    JailConf,
+    JailConfig,
+    JailInclude,
+    JailParamBool,
+    JailParamValue,
+    JailVariable,
+    Span,
+};
+
+// Blocks aren't generated nested any deeper than this.
+const MAX_DEPTH: usize = 4;
+
+// The most statements generated for a single scope.
+const MAX_STATEMENTS: usize = 16;
+
+// A name of up to 16 characters, the first from first and the rest from rest.
+fn name(u: &mut Unstructured, first: &[u8], rest: &[u8]) -> Result<String> {
+    let len = u.int_in_range(0..=15)?;
+    let mut name = String::with_capacity(len + 1);
+
+    name.push(char::from(*u.choose(first)?));
+
+    for _ in 0..len {
+        name.push(char::from(*u.choose(rest)?));
+    }
+
+    Ok(name)
+}
+
+const LOWER: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const PARAM_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789_.";
+const VARIABLE_FIRST: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz_";
+const VARIABLE_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789_";
+const PATH_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789/._-*";
+
+// A parameter name, usually one that jail(8) knows.
+fn param_name(u: &mut Unstructured) -> Result<String> {
+    if u.ratio(2, 3)? {
+        Ok(u.choose(schema::parameters())?.name().to_string())
+    }
+    else {
+        name(u, LOWER, PARAM_CHARS)
+    }
+}
+
+fn statements(u: &mut Unstructured, depth: usize) -> Result<Vec<JailConf<'static>>> {
+    let len = u.int_in_range(0..=MAX_STATEMENTS)?;
+
+    (0..len).map(|_| statement(u, depth)).collect()
+}
+
+fn statement(u: &mut Unstructured, depth: usize) -> Result<JailConf<'static>> {
+    let kinds = if depth < MAX_DEPTH { 6 } else { 5 };
+
+    let statement = match u.choose_index(kinds)? {
+        0 => JailConf::Comment(JailComment::arbitrary(u)?),
+        1 => JailConf::Include(JailInclude::arbitrary(u)?),
+        2 => JailConf::ParamBool(JailParamBool::arbitrary(u)?),
+        3 => JailConf::ParamValue(JailParamValue::arbitrary(u)?),
+        4 => JailConf::Variable(JailVariable::arbitrary(u)?),
+        _ => JailConf::Block(block(u, depth + 1)?),
+    };
+
+    Ok(statement)
+}
+
+fn block(u: &mut Unstructured, depth: usize) -> Result<JailBlock<'static>> {
+    let name = match String::arbitrary(u)? {
+        name if name.is_empty() => "jail".to_string(),
+        name                    => name,
+    };
+
+    let mut block = JailBlock {
+        quote:       name_quote(&name),
+        name:        Cow::Owned(name),
+        params:      statements(u, depth)?,
+        annotations: Annotations::new(),
+        source:      Cow::Borrowed(""),
+        span:        Span::default(),
+    };
+
+    block.source = Cow::Owned(block.to_string());
+    Ok(block)
+}
+
+impl<'a> Arbitrary<'a> for JailComment<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let style = *u.choose(&[CommentStyle::C, CommentStyle::CPP, CommentStyle::Shell])?;
+        let mut comment = String::arbitrary(u)?;
+
+        // Cut the comment short at anything which would end it early.
+        let end = match style {
+            CommentStyle::C => comment.find("*/"),
+            _               => comment.find('\n'),
+        };
+
+        if let Some(end) = end {
+            comment.truncate(end);
+        }
+
+        let mut comment = JailComment {
+            comment: Cow::Owned(comment),
+            style,
+            source:  Cow::Borrowed(""),
+            span:    Span::default(),
+        };
+
+        comment.source = Cow::Owned(comment.to_string());
+        Ok(comment)
+    }
+}
+
+impl<'a> Arbitrary<'a> for JailInclude<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut include = JailInclude {
+            path:   Cow::Owned(name(u, PATH_CHARS, PATH_CHARS)?),
+            source: Cow::Borrowed(""),
+            span:   Span::default(),
+        };
+
+        include.source = Cow::Owned(include.to_string());
+        Ok(include)
+    }
+}
+
+impl<'a> Arbitrary<'a> for JailParamBool<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let name = param_name(u)?;
+
+        Ok(JailParamBool {
+            source: Cow::Owned(format!("{};", name)),
+            name:   Cow::Owned(name),
+            span:   Span::default(),
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for JailParamValue<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let name = param_name(u)?;
+        let value = String::arbitrary(u)?;
+        let append = bool::arbitrary(u)?;
+        let raw = raw_value(&value);
+        let op = if append { "+=" } else { "=" };
+
+        Ok(JailParamValue {
+            source: Cow::Owned(format!("{} {} {};", name, op, raw)),
+            name:   Cow::Owned(name),
+            value:  Cow::Owned(value),
+            raw:    Cow::Owned(raw),
+            append,
+            span:   Span::default(),
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for JailVariable<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let name = name(u, VARIABLE_FIRST, VARIABLE_CHARS)?;
+        let value = String::arbitrary(u)?;
+        let append = bool::arbitrary(u)?;
+        let raw = raw_value(&value);
+        let op = if append { "+=" } else { "=" };
+
+        Ok(JailVariable {
+            source: Cow::Owned(format!("${} {} {};", name, op, raw)),
+            name:   Cow::Owned(name),
+            value:  Cow::Owned(value),
+            raw:    Cow::Owned(raw),
+            append,
+            span:   Span::default(),
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for JailBlock<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        block(u, 1)
+    }
+}
+
+impl<'a> Arbitrary<'a> for JailConf<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        statement(u, 0)
+    }
+}
+
+impl<'a> Arbitrary<'a> for JailConfig<'static> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(JailConfig::new(statements(u, 0)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pseudo-random bytes, the same on every run.
+    fn bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for seed in 0..500 {
+            let data = bytes(seed, 1024);
+            let config = JailConfig::arbitrary(&mut Unstructured::new(&data)).unwrap();
+            let rendered = config.to_string();
+
+            let reparsed = match JailConfig::parse(&rendered) {
+                Ok(reparsed) => reparsed,
+                Err(e)       => panic!("{}\n{}", e, rendered),
+            };
+
+            assert_eq!(reparsed.to_string(), rendered);
+            assert_eq!(reparsed.normalize(), config.normalize(), "{}", rendered);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+// Ordering of jails by their dependencies.
+//
+// A jail lists the jails it depends on in its `depend` parameter, and
+// jail(8) starts those first and stops them last. A jail within another,
+// eg. `foo.bar`, likewise depends on its parent. As with jail(8), depending
+// on a jail which isn't configured is an error, as is depending on itself,
+// which a global `depend` makes the jail it names do.
+//
+// dependency_dot draws the dependencies as a Graphviz digraph, with an edge
+// from each jail to each jail it depends on.
+use std::error;
+use std::fmt;
+use std::fmt::Write;
+use crate::JailConfig;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CycleError {
+    jails: Vec<String>,
+}
+
+impl CycleError {
+    /// The jails forming the cycle, each depending on the one after it and
+    /// the last depending on the first.
+    pub fn jails(&self) -> &[String] {
+        &self.jails
+    }
+}
+
+impl fmt::Display for CycleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut path = self.jails.join(" -> ");
+
+        if let Some(first) = self.jails.first() {
+            path.push_str(" -> ");
+            path.push_str(first);
+        }
+
+        write!(f, "dependency cycle: {}", path)
+    }
+}
+
+impl error::Error for CycleError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DependencyError {
+    /// Jails depend on each other in a cycle, or a jail on itself.
+    Cycle(CycleError),
+    /// A jail depends on a jail which isn't configured.
+    Undefined {
+        jail:       String,
+        dependency: String,
+    },
+}
+
+impl fmt::Display for DependencyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DependencyError::Cycle(error) => write!(f, "{}", error),
+            DependencyError::Undefined { jail, dependency } => {
+                write!(f, "{} depends on undefined jail {}", jail, dependency)
+            },
+        }
+    }
+}
+
+impl error::Error for DependencyError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DependencyError::Cycle(error) => Some(error),
+            _                             => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mark {
+    Unvisited,
+    Visiting,
+    Done,
+}
+
+impl<'a> JailConfig<'a> {
+    // The names of the jails the named jail depends on, configured or not,
+    // its parent first followed by those in its `depend` parameter.
+    fn depends(&self, jail: &str) -> Vec<String> {
+        let mut depends: Vec<String> = Vec::new();
+
+        let parent = self.parent_of(jail).map(String::from);
+
+        for dependency in parent.into_iter().chain(self.get_list(jail, "depend")) {
+            if !depends.contains(&dependency) {
+                depends.push(dependency);
+            }
+        }
+
+        depends
+    }
+
+    /// The configured jails the named jail depends on, its parent first
+    /// followed by those in its `depend` parameter. A jail depending on
+    /// itself is included, while dependencies on jails which aren't
+    /// configured are left out, see `start_order`.
+    pub fn dependencies(&self, jail: &str) -> Vec<&str> {
+        let names = self.jail_names();
+
+        self.depends(jail)
+            .iter()
+            .filter_map(|dependency| names.iter().find(|name| **name == dependency).copied())
+            .collect()
+    }
+
+    /// The configured jails in an order they can be started in, each after
+    /// the jails it depends on. Jails are otherwise kept in the order they
+    /// were configured. A jail which depends on itself, directly or through
+    /// others, is an error, as is one depending on a jail which isn't
+    /// configured.
+    pub fn start_order(&self) -> Result<Vec<&str>, DependencyError> {
+        let names = self.jail_names();
+        let mut marks = vec![Mark::Unvisited; names.len()];
+        let mut order = Vec::with_capacity(names.len());
+
+        for index in 0..names.len() {
+            self.visit(&names, index, &mut marks, &mut Vec::new(), &mut order)?;
+        }
+
+        Ok(order)
+    }
+
+    /// The configured jails in an order they can be stopped in, the reverse
+    /// of `start_order`.
+    pub fn stop_order(&self) -> Result<Vec<&str>, DependencyError> {
+        let mut order = self.start_order()?;
+        order.reverse();
+
+        Ok(order)
+    }
+
+    /// The dependencies between the configured jails as a Graphviz digraph,
+    /// with an edge from each jail to each jail it depends on.
+    pub fn dependency_dot(&self) -> String {
+        let mut dot = String::from("digraph jails {\n");
+
+        // Writing to a String can't fail.
+        for name in self.jail_names() {
+            let _ = writeln!(dot, "    {:?};", name);
+
+            for dependency in self.dependencies(name) {
+                let _ = writeln!(dot, "    {:?} -> {:?};", name, dependency);
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    // Depth first visit of the jail at index, adding it to order after its
+    // dependencies. stack holds the jails being visited, to report a cycle.
+    fn visit<'c>(
+        &'c self,
+        names: &[&'c str],
+        index: usize,
+        marks: &mut [Mark],
+        stack: &mut Vec<usize>,
+        order: &mut Vec<&'c str>,
+    ) -> Result<(), DependencyError> {
+        match marks[index] {
+            Mark::Done     => return Ok(()),
+            Mark::Visiting => {
+                let start = stack.iter().position(|&i| i == index).unwrap_or(0);
+                let jails = stack[start..].iter()
+                    .map(|&i| names[i].to_string())
+                    .collect();
+
+                return Err(DependencyError::Cycle(CycleError { jails }));
+            },
+            Mark::Unvisited => {},
+        }
+
+        marks[index] = Mark::Visiting;
+        stack.push(index);
+
+        for dependency in self.depends(names[index]) {
+            let next = names.iter()
+                .position(|name| *name == dependency)
+                .ok_or_else(|| DependencyError::Undefined {
+                    jail: names[index].to_string(),
+                    dependency,
+                })?;
+
+            self.visit(names, next, marks, stack, order)?;
+        }
+
+        stack.pop();
+        marks[index] = Mark::Done;
+        order.push(names[index]);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_start_and_stop_order() {
+        let config = JailConfig::parse(indoc!(r#"
+            www {
+                depend = "db, cache";
+            }
+            cache {
+                depend = "db";
+            }
+            db {
+                persist;
+            }
+            mail {
+                depend = "db";
+            }
+            "#)).unwrap();
+
+        assert_eq!(config.dependencies("www"), vec!["db", "cache"]);
+        assert_eq!(config.dependencies("mail"), vec!["db"]);
+
+        assert_eq!(config.start_order().unwrap(), vec!["db", "cache", "www", "mail"]);
+        assert_eq!(config.stop_order().unwrap(), vec!["mail", "www", "cache", "db"]);
+    }
+
+    #[test]
+    fn test_start_order_parent_first() {
+        let config = JailConfig::parse(indoc!(r#"
+            foo.bar {
+                persist;
+            }
+            foo {
+                depend += "net";
+            }
+            net {
+                persist;
+            }
+            "#)).unwrap();
+
+        assert_eq!(config.dependencies("foo.bar"), vec!["foo"]);
+        assert_eq!(config.start_order().unwrap(), vec!["net", "foo", "foo.bar"]);
+    }
+
+    #[test]
+    fn test_dependency_dot() {
+        let config = JailConfig::parse(indoc!(r#"
+            * {
+                persist;
+            }
+            www {
+                depend = "db";
+            }
+            db {
+                persist;
+            }
+            "#)).unwrap();
+
+        assert_eq!(config.dependency_dot(), indoc!(r#"
+            digraph jails {
+                "www";
+                "www" -> "db";
+                "db";
+            }
+            "#));
+    }
+
+    #[test]
+    fn test_start_order_cycle() {
+        let config = JailConfig::parse(indoc!(r#"
+            a {
+                depend = b;
+            }
+            b {
+                depend = c;
+            }
+            c {
+                depend = a;
+            }
+            "#)).unwrap();
+
+        let err = match config.start_order() {
+            Err(DependencyError::Cycle(err)) => err,
+            res                              => panic!("expected a cycle, got {:?}", res),
+        };
+
+        assert_eq!(err.jails(), &["a", "b", "c"]);
+        assert_eq!(err.to_string(), "dependency cycle: a -> b -> c -> a");
+        assert!(config.stop_order().is_err());
+    }
+
+    #[test]
+    fn test_start_order_self_dependency() {
+        let config = JailConfig::parse("a {\n    depend = a;\n}\n").unwrap();
+
+        assert_eq!(config.dependencies("a"), vec!["a"]);
+        assert_eq!(
+            config.start_order().unwrap_err().to_string(),
+            "dependency cycle: a -> a",
+        );
+
+        // A global depend applies to the jail it names too.
+        let config = JailConfig::parse("depend = b;\na {\n    persist;\n}\nb {\n    persist;\n}\n").unwrap();
+
+        assert_eq!(config.dependencies("a"), vec!["b"]);
+        assert_eq!(
+            config.start_order().unwrap_err(),
+            DependencyError::Cycle(CycleError { jails: vec!["b".into()] }),
+        );
+    }
+
+    #[test]
+    fn test_start_order_undefined_dependency() {
+        let config = JailConfig::parse("mail {\n    depend = external;\n}\n").unwrap();
+
+        assert!(config.dependencies("mail").is_empty());
+        assert_eq!(config.start_order().unwrap_err(), DependencyError::Undefined {
+            jail:       "mail".into(),
+            dependency: "external".into(),
+        });
+        assert_eq!(
+            config.stop_order().unwrap_err().to_string(),
+            "mail depends on undefined jail external",
+        );
+    }
+}
@@ -0,0 +1,153 @@
+// Hierarchical jails.
+//
+// A jail may be created within another jail, in which case its name is the
+// parent's name followed by a dot and its own name, eg. `foo.bar` is the jail
+// `bar` within `foo`. The lookups here treat the dotted names of top level
+// blocks as that hierarchy.
+use crate::config::{
+    apply,
+    EffectiveParams,
+};
+use crate::JailConfig;
+
+// The name of the parent of the named jail, if it has one.
+fn parent_name(name: &str) -> Option<&str> {
+    match name.rfind('.') {
+        Some(0) | None => None,
+        Some(dot)      => Some(&name[..dot]),
+    }
+}
+
+// The names of the ancestors of the named jail, outermost first.
+pub(crate) fn ancestor_names(name: &str) -> Vec<&str> {
+    let mut ancestors = Vec::new();
+    let mut current = name;
+
+    while let Some(parent) = parent_name(current) {
+        ancestors.push(parent);
+        current = parent;
+    }
+
+    ancestors.reverse();
+    ancestors
+}
+
+impl<'a> JailConfig<'a> {
+    /// The name of the parent of the named jail, if the jail has a parent
+    /// and a block for it is configured. `parent_of("foo.bar")` is `foo`.
+    pub fn parent_of<'n>(&self, jail: &'n str) -> Option<&'n str> {
+        parent_name(jail).filter(|parent| self.jail(parent).is_some())
+    }
+
+    /// The names of the configured jails directly within the named jail, in
+    /// the order they were given. Grandchildren, eg. `foo.bar.baz` for
+    /// `foo`, aren't included.
+    pub fn children_of(&self, jail: &str) -> Vec<&str> {
+        let mut children: Vec<&str> = Vec::new();
+
+        for block in self.jails() {
+            let name = block.name();
+
+            if parent_name(name) == Some(jail) && !children.contains(&name) {
+                children.push(name);
+            }
+        }
+
+        children
+    }
+
+    /// The fully resolved parameters for the named jail as `effective_params`
+    /// gives them, but with each configured ancestor's parameters applied
+    /// between the global and wildcard ones and the jail's own. A child jail
+    /// takes its defaults from its parent, which takes them from its own
+    /// parent. None if there's no such jail.
+    pub fn inherited_params(&self, jail: &str) -> Option<EffectiveParams> {
+        self.jail(jail)?;
+
+        let mut params = EffectiveParams::new();
+
+        apply(&mut params, self.statements());
+
+        let wildcards = self.wildcards().filter(|block| block.applies_to(jail));
+        apply(&mut params, wildcards.flat_map(|block| block.params()));
+
+        for name in ancestor_names(jail).into_iter().chain(Some(jail)) {
+            let blocks = self.jails().filter(|block| block.name() == name);
+
+            apply(&mut params, blocks.flat_map(|block| block.params()));
+        }
+
+        Some(params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        path = "/jails/default";
+        foo {
+            persist;
+            ip4.addr = "192.0.2.1";
+        }
+        foo.bar {
+            path = "/jails/foo/bar";
+            ip4.addr += "192.0.2.2";
+        }
+        foo.baz {
+            host.hostname = "baz";
+        }
+        foo.bar.qux {
+            path = "/jails/foo/bar/qux";
+        }
+        foo.bar {
+            allow.mount;
+        }
+        "#);
+
+    #[test]
+    fn test_parent_name() {
+        assert_eq!(parent_name("foo.bar.qux"), Some("foo.bar"));
+        assert_eq!(parent_name("foo"), None);
+        assert_eq!(parent_name(".foo"), None);
+        assert_eq!(ancestor_names("foo.bar.qux"), vec!["foo", "foo.bar"]);
+    }
+
+    #[test]
+    fn test_parent_and_children() {
+        let config = JailConfig::parse(INPUT).unwrap();
+
+        assert_eq!(config.parent_of("foo.bar"), Some("foo"));
+        assert_eq!(config.parent_of("foo.bar.qux"), Some("foo.bar"));
+        assert_eq!(config.parent_of("foo"), None);
+        assert_eq!(config.parent_of("missing.child"), None);
+
+        assert_eq!(config.children_of("foo"), vec!["foo.bar", "foo.baz"]);
+        assert_eq!(config.children_of("foo.bar"), vec!["foo.bar.qux"]);
+        assert!(config.children_of("foo.baz").is_empty());
+    }
+
+    #[test]
+    fn test_inherited_params() {
+        let config = JailConfig::parse(INPUT).unwrap();
+        let qux = config.inherited_params("foo.bar.qux").unwrap();
+
+        assert_eq!(qux["path"], vec!["/jails/foo/bar/qux"]);
+        assert_eq!(qux["persist"], vec!["true"]);
+        assert_eq!(qux["allow.mount"], vec!["true"]);
+        assert_eq!(qux["ip4.addr"], vec!["192.0.2.1", "192.0.2.2"]);
+
+        let baz = config.inherited_params("foo.baz").unwrap();
+
+        assert_eq!(baz["path"], vec!["/jails/default"]);
+        assert!(!baz.contains_key("allow.mount"));
+
+        // Without inheritance only globals apply.
+        let plain = config.effective_params("foo.baz").unwrap();
+        assert!(!plain.contains_key("persist"));
+
+        assert!(config.inherited_params("foo.missing").is_none());
+    }
+}
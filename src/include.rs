@@ -0,0 +1,415 @@
+// Expansion of .include directives.
+//
+// The parser records `.include` directives as nodes without reading the
+// files they name. parse_with_includes reads a configuration from disk and
+// replaces each directive with the statements of every file it matches,
+// recursively, giving a single tree as jail(8) would see it.
+//
+// Patterns are expanded with glob, so `.include "/etc/jail.conf.d/*.conf";`
+// includes each matching file in alphabetical order. Relative patterns are
+// relative to the directory of the file containing the directive. A pattern
+// matching nothing includes nothing, as jail(8) allows, so that an empty
+// jail.conf.d isn't an error, but a path without glob characters must name
+// a file which exists.
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use tracing::debug;
+use crate::{
+    into_owned,
+    parse,
+    JailConf,
+    JailConfOwned,
+    ParseError,
+};
+
+#[derive(Debug)]
+pub enum IncludeError {
+    /// A file couldn't be read.
+    Io {
+        path:  PathBuf,
+        error: io::Error,
+    },
+    /// A file couldn't be parsed.
+    Parse {
+        path:  PathBuf,
+        error: ParseError,
+    },
+    /// An include directive had an invalid glob pattern.
+    Pattern {
+        pattern: String,
+        error:   glob::PatternError,
+    },
+    /// A file includes itself, either directly or through other files.
+    /// chain holds the files included on the way, starting and ending with
+    /// the file itself.
+    Cycle {
+        path:  PathBuf,
+        chain: Vec<PathBuf>,
+    },
+}
+
+impl fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IncludeError::Io { path, error } => {
+                write!(f, "couldn't read {}: {}", path.display(), error)
+            },
+            IncludeError::Parse { path, error } => {
+                write!(f, "{}: {}", path.display(), error)
+            },
+            IncludeError::Pattern { pattern, error } => {
+                write!(f, "invalid include pattern {}: {}", pattern, error)
+            },
+            IncludeError::Cycle { path, chain } => {
+                let chain: Vec<String> = chain.iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+
+                write!(f, "{} includes itself: {}", path.display(), chain.join(" -> "))
+            },
+        }
+    }
+}
+
+impl error::Error for IncludeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            IncludeError::Io { error, .. }      => Some(error),
+            IncludeError::Parse { error, .. }   => Some(error),
+            IncludeError::Pattern { error, .. } => Some(error),
+            _                                   => None,
+        }
+    }
+}
+
+fn has_glob_chars(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+// Find the files matched by an include pattern, which may be none. A
+// pattern without any glob characters is returned as is, even if it doesn't
+// exist, so that a missing file is reported rather than silently skipped.
+pub(crate) fn matches(dir: &Path, pattern: &str) -> Result<Vec<PathBuf>, IncludeError> {
+    let pattern = dir.join(pattern);
+
+    if !has_glob_chars(&pattern.to_string_lossy()) {
+        return Ok(vec![pattern]);
+    }
+
+    let pattern = pattern.to_string_lossy();
+    let paths = glob::glob(&pattern)
+        .map_err(|error| IncludeError::Pattern {
+            pattern: pattern.to_string(),
+            error,
+        })?;
+
+    paths
+        .map(|entry| entry.map_err(|error| IncludeError::Io {
+            path:  error.path().to_path_buf(),
+            error: error.into(),
+        }))
+        .collect()
+}
+
+// The file each statement came from, as an index into the files read,
+// with that of each statement within a block.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Origins {
+    pub(crate) file:   usize,
+    pub(crate) params: Vec<Origins>,
+}
+
+// The files read while expanding includes, and which included which.
+#[derive(Debug, Default)]
+pub(crate) struct Trace {
+    pub(crate) files:    Vec<PathBuf>,
+    pub(crate) includes: Vec<(usize, usize)>,
+    canonical:           Vec<PathBuf>,
+    // The files currently being expanded, to catch files which include
+    // themselves.
+    stack:               Vec<PathBuf>,
+}
+
+impl Trace {
+    // The index of a file, read for the first time if it's new.
+    fn file(&mut self, path: &Path, canonical: &Path) -> usize {
+        match self.canonical.iter().position(|file| file == canonical) {
+            Some(index) => index,
+            None => {
+                self.files.push(path.to_path_buf());
+                self.canonical.push(canonical.to_path_buf());
+                self.files.len() - 1
+            },
+        }
+    }
+}
+
+// The error for a file found on the stack of those being expanded.
+pub(crate) fn cycle(path: &Path, canonical: &Path, stack: &[PathBuf]) -> Option<IncludeError> {
+    let start = stack.iter().position(|file| file == canonical)?;
+    let chain = stack[start..].iter()
+        .cloned()
+        .chain(Some(canonical.to_path_buf()))
+        .collect();
+
+    Some(IncludeError::Cycle {
+        path: path.to_path_buf(),
+        chain,
+    })
+}
+
+// Replace include directives in statements with the statements of the files
+// they match. Directives within blocks are expanded in place.
+fn expand(config: Vec<JailConfOwned>, dir: &Path, file: usize, trace: &mut Trace)
+-> Result<(Vec<JailConfOwned>, Vec<Origins>), IncludeError> {
+    let mut expanded = Vec::with_capacity(config.len());
+    let mut origins = Vec::with_capacity(config.len());
+
+    for statement in config {
+        match statement {
+            JailConf::Include(include) => {
+                for path in matches(dir, include.path())? {
+                    let (included, index, included_origins) = load_traced(&path, trace)?;
+
+                    if !trace.includes.contains(&(file, index)) {
+                        trace.includes.push((file, index));
+                    }
+
+                    expanded.extend(included);
+                    origins.extend(included_origins);
+                }
+            },
+            JailConf::Block(mut block) => {
+                let (params, params_origins) = expand(block.params, dir, file, trace)?;
+
+                block.params = params;
+                expanded.push(JailConf::Block(block));
+                origins.push(Origins {
+                    file,
+                    params: params_origins,
+                });
+            },
+            statement => {
+                expanded.push(statement);
+                origins.push(Origins {
+                    file,
+                    params: Vec::new(),
+                });
+            },
+        }
+    }
+
+    Ok((expanded, origins))
+}
+
+// Read, parse and expand a single file, recording in trace the files read
+// and where each statement came from. Returns the file's index in trace
+// along with its statements.
+pub(crate) fn load_traced(path: &Path, trace: &mut Trace)
+-> Result<(Vec<JailConfOwned>, usize, Vec<Origins>), IncludeError> {
+    let io_error = |error| IncludeError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+
+    let canonical = path.canonicalize().map_err(io_error)?;
+
+    if let Some(error) = cycle(path, &canonical, &trace.stack) {
+        return Err(error);
+    }
+
+    let text = fs::read_to_string(path).map_err(io_error)?;
+    let config = parse(&text)
+        .map_err(|error| IncludeError::Parse {
+            path: path.to_path_buf(),
+            error,
+        })?;
+
+    debug!("read {} statements from {}", config.len(), path.display());
+
+    let config = into_owned(config);
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let file = trace.file(path, &canonical);
+
+    trace.stack.push(canonical);
+    let expanded = expand(config, dir, file, trace);
+    trace.stack.pop();
+
+    expanded.map(|(expanded, origins)| (expanded, file, origins))
+}
+
+// Read, parse and expand a single file.
+pub(crate) fn load(path: &Path) -> Result<Vec<JailConfOwned>, IncludeError> {
+    load_traced(path, &mut Trace::default()).map(|(config, _, _)| config)
+}
+
+// Expand the include directives of statements parsed from a string rather
+// than read from a file, with relative patterns relative to dir. An error is
+// returned with the offset of the directive it was raised for.
+pub(crate) fn expand_parsed<'a>(statements: Vec<JailConf<'a>>, dir: &Path, trace: &mut Trace)
+-> Result<Vec<JailConf<'a>>, (usize, IncludeError)> {
+    let mut expanded = Vec::with_capacity(statements.len());
+
+    for statement in statements {
+        match statement {
+            JailConf::Include(include) => {
+                let offset = include.span().start().offset();
+                let paths = matches(dir, include.path()).map_err(|error| (offset, error))?;
+
+                for path in paths {
+                    let (included, _, _) = load_traced(&path, trace)
+                        .map_err(|error| (offset, error))?;
+                    let included: Vec<JailConf<'a>> = included;
+
+                    expanded.extend(included);
+                }
+            },
+            JailConf::Block(mut block) => {
+                block.params = expand_parsed(block.params, dir, trace)?;
+                expanded.push(JailConf::Block(block));
+            },
+            statement => expanded.push(statement),
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// Read the configuration at path, expanding any `.include` directives into
+/// the statements of the files they match.
+pub fn parse_with_includes<P>(path: P) -> Result<Vec<JailConfOwned>, IncludeError>
+where
+    P: AsRef<Path>,
+{
+    load(path.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::scratch_dir;
+
+    fn block_names(config: &[JailConfOwned]) -> Vec<&str> {
+        config.iter()
+            .filter_map(|statement| match statement {
+                JailConf::Block(block) => Some(block.name()),
+                _                      => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_with_includes_glob() {
+        let dir = scratch_dir("include-glob");
+        fs::create_dir(dir.join("jail.conf.d")).unwrap();
+
+        fs::write(
+            dir.join("jail.conf"),
+            "persist;\n.include \"jail.conf.d/*.conf\";\nlast {\n    persist;\n}\n",
+        ).unwrap();
+        fs::write(dir.join("jail.conf.d/b.conf"), "b {\n    persist;\n}\n").unwrap();
+        fs::write(dir.join("jail.conf.d/a.conf"), "a {\n    persist;\n}\n").unwrap();
+        fs::write(dir.join("jail.conf.d/ignored.txt"), "ignored {\n}\n").unwrap();
+
+        let config = parse_with_includes(dir.join("jail.conf")).unwrap();
+
+        assert_eq!(block_names(&config), vec!["a", "b", "last"]);
+        assert!(!config.iter().any(|s| matches!(s, JailConf::Include(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_with_includes_quoted() {
+        let dir = scratch_dir("include-quoted");
+        fs::create_dir(dir.join("inc")).unwrap();
+
+        fs::write(dir.join("jail.conf"), ".include 'inc/*.conf';\n.include \"a\\\"b.conf\";\n").unwrap();
+        fs::write(dir.join("inc/www.conf"), "www {\n    persist;\n}\n").unwrap();
+        fs::write(dir.join("a\"b.conf"), "db {\n    persist;\n}\n").unwrap();
+
+        let config = parse_with_includes(dir.join("jail.conf")).unwrap();
+
+        assert_eq!(block_names(&config), vec!["www", "db"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_with_includes_no_matches() {
+        let dir = scratch_dir("include-no-matches");
+        fs::create_dir(dir.join("jail.conf.d")).unwrap();
+        fs::write(dir.join("jail.conf"), "persist;\n.include \"jail.conf.d/*.conf\";\n").unwrap();
+
+        let config = parse_with_includes(dir.join("jail.conf")).unwrap();
+
+        assert_eq!(config.len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_with_includes_missing_file() {
+        let dir = scratch_dir("include-missing");
+        fs::write(dir.join("jail.conf"), ".include \"nope.conf\";\n").unwrap();
+
+        match parse_with_includes(dir.join("jail.conf")) {
+            Err(IncludeError::Io { path, .. }) => {
+                assert_eq!(path, dir.join("nope.conf"));
+            },
+            res => panic!("expected an io error, got {:?}", res),
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_with_includes_cycle() {
+        let dir = scratch_dir("include-cycle");
+        fs::write(dir.join("a.conf"), ".include \"b.conf\";\n").unwrap();
+        fs::write(dir.join("b.conf"), ".include \"a.conf\";\n").unwrap();
+
+        match parse_with_includes(dir.join("a.conf")) {
+            Err(IncludeError::Cycle { path, chain }) => {
+                let dir = dir.canonicalize().unwrap();
+
+                assert_eq!(path, dir.join("a.conf"));
+                assert_eq!(chain, [dir.join("a.conf"), dir.join("b.conf"), dir.join("a.conf")]);
+            },
+            res => panic!("expected a cycle error, got {:?}", res),
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_with_resolve_includes() {
+        use crate::{
+            parse_with,
+            ParseErrorKind,
+            ParseOptions,
+        };
+
+        let dir = scratch_dir("include-options");
+        fs::write(dir.join("www.conf"), "www {\n    persist;\n}\n").unwrap();
+
+        let options = ParseOptions::new().resolve_includes(&dir);
+        let config = parse_with("persist;\n.include \"www.conf\";\n", &options).unwrap();
+
+        assert_eq!(block_names(&config), vec!["www"]);
+
+        let error = parse_with("persist;\n.include \"nope.conf\";\n", &options).unwrap_err();
+
+        assert_eq!(error.kind(), ParseErrorKind::Include);
+        assert_eq!((error.line(), error.column()), (2, 1));
+        assert!(error.expected().starts_with("an include which can be expanded, couldn't read"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
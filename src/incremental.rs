@@ -0,0 +1,176 @@
+// Reparsing a configuration after an edit to its source.
+//
+// An editor changes a few bytes at a time, and reparsing a large
+// configuration in full on every change repeats work for statements the
+// change can't have touched. reparse_edit parses only the statements around
+// the edit: those on the lines the edit starts and ends on, along with the
+// whitespace and comments between them. The statements before and after
+// are kept as they were, with the spans of those after moved to where they
+// now are.
+//
+// Whole lines are reparsed, rather than just the statements the edit falls
+// in, as a comment runs to the end of its line: adding `#` before a
+// statement comments out the rest of the line. Anything else an edit can
+// start, a block, a quoted string or a `/* */` comment, is left unclosed at
+// the end of the reparsed text unless it's closed within it, and so fails
+// to parse. When the reparsed text doesn't parse, the whole edited source
+// is parsed instead, so that any error is that of the whole source.
+use std::ops::Range;
+use crate::span::{
+    self,
+    Lines,
+    Position,
+};
+use crate::{
+    annotation,
+    into_owned,
+    parse,
+    JailConfig,
+    JailConfOwned,
+    ParseError,
+};
+
+impl<'a> JailConfig<'a> {
+    /// Apply an edit to `source`, the input the configuration was parsed
+    /// from, replacing the bytes in `range` with `new_text`, and reparse
+    /// only the statements the edit can affect. The result is the same as
+    /// parsing the edited source in full, spans included.
+    ///
+    /// # Panics
+    ///
+    /// If `range` isn't within `source` or doesn't fall on character
+    /// boundaries.
+    pub fn reparse_edit(&self, source: &str, range: Range<usize>, new_text: &str)
+    -> Result<JailConfig<'static>, ParseError> {
+        let lines = Lines::new(source);
+        let first_line = lines.position(range.start).line();
+        let last_line = lines.position(range.end).line();
+        let statements = self.statements();
+
+        // Statements ending on a line before the edit are kept, as are those
+        // starting on a line after it.
+        let before = statements.iter()
+            .take_while(|statement| statement.span().end().line() < first_line)
+            .count();
+        let after = statements[before..].iter()
+            .position(|statement| statement.span().start().line() > last_line)
+            .map_or(statements.len(), |after| before + after);
+
+        let region_start = match before {
+            0 => Position::default(),
+            _ => statements[before - 1].span().end(),
+        };
+        let region_end = match statements.get(after) {
+            Some(statement) => statement.span().start(),
+            None            => lines.position(source.len()),
+        };
+
+        let text = [
+            &source[region_start.offset()..range.start],
+            new_text,
+            &source[range.end..region_end.offset()],
+        ].concat();
+
+        let mut reparsed = match parse(&text) {
+            Ok(reparsed) => reparsed,
+            Err(_) => {
+                let edited = [&source[..range.start], new_text, &source[range.end..]].concat();
+
+                return parse(&edited).map(|parsed| JailConfig::new(into_owned(parsed)));
+            },
+        };
+
+        span::assign_at(&text, region_start, &mut reparsed);
+
+        let new_end = Lines::starting_at(&text, region_start).position(text.len());
+        let mut tail: Vec<JailConfOwned> = statements[after..].iter()
+            .map(|statement| statement.to_owned())
+            .collect();
+
+        span::shift(&mut tail, region_end, new_end);
+
+        let patched = statements[..before].iter()
+            .map(|statement| statement.to_owned())
+            .chain(into_owned(reparsed))
+            .chain(tail)
+            .collect();
+
+        Ok(JailConfig::new(annotation::attach(patched)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        # Defaults.
+        persist;
+        path = "/usr/jails/$name";
+
+        www {
+            host.hostname = www.example.org;
+            ip4.addr = 192.0.2.1;
+        }
+
+        db {
+            mount.devfs;
+        }
+        "#);
+
+    // Apply an edit with reparse_edit, checking that it gives the same
+    // configuration, spans included, as parsing the edited input in full.
+    fn check_edit(input: &str, find: &str, new_text: &str) {
+        let start = input.find(find).unwrap();
+        let range = start..start + find.len();
+        let edited = [&input[..range.start], new_text, &input[range.end..]].concat();
+
+        let config = JailConfig::parse(input).unwrap();
+        let reparsed = config.reparse_edit(input, range, new_text).unwrap();
+        let expected = JailConfig::parse(&edited).unwrap();
+
+        assert_eq!(reparsed.statements(), expected.statements(), "\n{}", edited);
+    }
+
+    #[test]
+    fn test_reparse_edit() {
+        check_edit(INPUT, "192.0.2.1", "192.0.2.10");
+        check_edit(INPUT, "www {", "web {");
+        check_edit(INPUT, "persist;\n", "");
+        check_edit(INPUT, "mount.devfs;", "mount.devfs;\n    allow.mount;\n    allow.mount.zfs;");
+        check_edit(INPUT, "\ndb {", "\nmail {\n    persist;\n}\n\ndb {");
+        check_edit(INPUT, "# Defaults.", "");
+        check_edit(INPUT, "}\n\ndb", "}\ndb");
+    }
+
+    #[test]
+    fn test_reparse_edit_annotations() {
+        let input = "persist;\n\ndb {\n    persist;\n}\n";
+        let config = JailConfig::parse(input).unwrap();
+        let reparsed = config.reparse_edit(input, 0..8, "# @owner: team-db").unwrap();
+
+        assert_eq!(reparsed.jail("db").unwrap().annotation("owner"), Some("team-db"));
+
+        check_edit(input, "persist;", "# @owner: team-db");
+    }
+
+    #[test]
+    fn test_reparse_edit_comments_out_line() {
+        let input = "persist;  mount.devfs;\nallow.mount;\n";
+
+        check_edit(input, "  mount", "  # mount");
+    }
+
+    #[test]
+    fn test_reparse_edit_error() {
+        let input = "www {\n    persist;\n}\ndb {\n    persist;\n}\n";
+        let config = JailConfig::parse(input).unwrap();
+        let start = input.find("persist;\n}\ndb").unwrap();
+        let edited = [&input[..start], "persist;\n{", &input[start + 10..]].concat();
+
+        let reparsed = config.reparse_edit(input, start..start + 10, "persist;\n{");
+
+        assert_eq!(reparsed.unwrap_err(), JailConfig::parse(&edited).unwrap_err());
+    }
+}
@@ -0,0 +1,281 @@
+// Parsing which recovers from errors.
+//
+// `parse` stops at the first statement it can't understand. Editors want to
+// show every problem in a file at once, so parse_lenient instead reports the
+// statement as a Diagnostic, skips past it to the next `;`, or up to the next
+// `}`, and carries on. Blocks containing errors are kept, with whatever of
+// their contents could be parsed.
+use std::fmt;
+use std::ops::Range;
+use nom::branch::alt;
+use nom::character::complete::{
+    char,
+    space0,
+};
+use nom::sequence::tuple;
+use nom::IResult;
+use crate::error::expected;
+use crate::limit;
+use crate::span::{
+    self,
+    Lines,
+};
+use crate::{
+    annotation,
+    escape,
+    param_name,
+    parse_statement,
+    quoted,
+    Annotations,
+    JailBlock,
+    JailConf,
+    ParseOptions,
+    Span,
+};
+
+/// A part of the input which couldn't be parsed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    span:     Span,
+    expected: String,
+}
+
+impl Diagnostic {
+    /// The input which was skipped over.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// A description of what the parser expected to find.
+    pub fn expected(&self) -> &str {
+        &self.expected
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let start = self.span.start();
+
+        write!(
+            f,
+            "error at line {}, column {}: expected {}",
+            start.line(),
+            start.column(),
+            self.expected,
+        )
+    }
+}
+
+// Recognise the start of a block, returning the name as written.
+fn block_header(input: &str) -> IResult<&str, &str> {
+    let (rest, (name, _, _)) = tuple((
+        alt((quoted, param_name)),
+        space0,
+        char('{'),
+    ))(input)?;
+
+    Ok((rest, name))
+}
+
+// The length of a statement which couldn't be parsed. This runs through the
+// next `;`, or up to the next `}` closing the enclosing block. A stray `}`
+// outside of any block is skipped along with the rest.
+fn skipped_len(rest: &str, nested: bool) -> usize {
+    match rest.find([';', '}']) {
+        Some(end) if nested && rest[end..].starts_with('}') => end,
+        Some(end)                                           => end + 1,
+        None                                                => rest.len(),
+    }
+}
+
+struct Recovery<'a> {
+    input:       &'a str,
+    lines:       Lines,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Recovery<'a> {
+    fn report(&mut self, range: Range<usize>, expected: &str) {
+        self.diagnostics.push(Diagnostic {
+            span:     self.lines.span(range),
+            expected: expected.to_string(),
+        });
+    }
+
+    fn offset(&self, rest: &str) -> usize {
+        self.input.len() - rest.len()
+    }
+
+    // Parse statements until the end of input, or of the enclosing block if
+    // nested. Returns them along with the input from the closing brace on.
+    fn statements(&mut self, mut rest: &'a str, nested: bool)
+    -> (Vec<JailConf<'a>>, &'a str) {
+        let mut statements = Vec::new();
+
+        loop {
+            rest = rest.trim_start();
+
+            if rest.is_empty() || (nested && rest.starts_with('}')) {
+                break;
+            }
+
+            if let Ok((after, statement)) = parse_statement(rest) {
+                statements.push(statement);
+                rest = after;
+                continue;
+            }
+
+            // A block with errors inside it, recover within the block.
+            if let Ok((body, name)) = block_header(rest) {
+                let (params, after) = self.statements(body, true);
+
+                let after = match after.strip_prefix('}') {
+                    Some(after) => after,
+                    None        => {
+                        let end = self.offset(after);
+
+                        self.report(end..end, "'}' to close jail block");
+                        after
+                    },
+                };
+
+                statements.push(JailConf::Block(JailBlock {
+                    name:        escape::unescape(name),
+                    quote:       escape::quote_style(name),
+                    params,
+                    annotations: Annotations::new(),
+                    source:      rest[..rest.len() - after.len()].into(),
+                    span:        Span::default(),
+                }));
+
+                rest = after;
+                continue;
+            }
+
+            let start = self.offset(rest);
+            let len = skipped_len(rest, nested);
+
+            self.report(start..start + len, expected(rest));
+            rest = &rest[len..];
+        }
+
+        (annotation::attach(statements), rest)
+    }
+}
+
+/// Parse as much of the input as possible, skipping over anything that can't
+/// be parsed rather than stopping. Returns the statements parsed, along with
+/// a Diagnostic for each part of the input that was skipped.
+pub fn parse_lenient(input: &str) -> (Vec<JailConf<'_>>, Vec<Diagnostic>) {
+    let mut recovery = Recovery {
+        input,
+        lines:       Lines::new(input),
+        diagnostics: Vec::new(),
+    };
+
+    // There's no recovering from input nested too deeply to parse, so none
+    // of it is.
+    if let Err(e) = limit::check(input, &ParseOptions::new()) {
+        recovery.report(e.offset()..input.len(), e.expected());
+
+        return (Vec::new(), recovery.diagnostics);
+    }
+
+    let (mut statements, _) = recovery.statements(input, false);
+
+    span::assign(input, &mut statements);
+
+    (statements, recovery.diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use indoc::indoc;
+
+    #[test]
+    fn test_valid_input_has_no_diagnostics() {
+        let input = "persist;\nnginx {\n    ip4.addr = 127.0.1.1;\n}\n";
+        let (statements, diagnostics) = parse_lenient(input);
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(statements, parse(input).unwrap());
+    }
+
+    #[test]
+    fn test_collects_every_error() {
+        let input = indoc!(r#"
+            persist
+            allow.mount;
+            nginx {
+                path = "/usr/jails/nginx"
+                host.hostname = nginx;
+                = oops;
+            }
+            }
+            db {
+                persist;
+            "#);
+
+        let (statements, diagnostics) = parse_lenient(input);
+
+        let errors: Vec<(usize, &str)> = diagnostics.iter()
+            .map(|d| (d.span().start().line(), d.expected()))
+            .collect();
+
+        assert_eq!(errors, vec![
+            (1, "';' after parameter name"),
+            (4, "';' after parameter value"),
+            (6, "';' after parameter value"),
+            (8, "a parameter, comment or jail block before '}'"),
+            (11, "'}' to close jail block"),
+        ]);
+
+        // The first error swallows the statement after it, up to its `;`.
+        assert_eq!(&input[diagnostics[0].span().range()], "persist\nallow.mount;");
+
+        let names: Vec<&str> = statements.iter()
+            .filter_map(|statement| match statement {
+                JailConf::Block(block) => Some(block.name()),
+                _                      => None,
+            })
+            .collect();
+
+        assert_eq!(names, vec!["nginx", "db"]);
+
+        match &statements[0] {
+            JailConf::Block(block) => {
+                assert!(block.params().is_empty());
+                assert!(block.source_text().starts_with("nginx {"));
+                assert!(block.source_text().ends_with('}'));
+            },
+            _ => panic!("expected a block"),
+        }
+
+        match &statements[1] {
+            JailConf::Block(block) => assert_eq!(block.params().len(), 1),
+            _                      => panic!("expected a block"),
+        }
+    }
+
+    #[test]
+    fn test_diagnostic_display() {
+        let (_, diagnostics) = parse_lenient("persist;\n  bad\n");
+
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "error at line 2, column 3: expected ';' after parameter name",
+        );
+    }
+
+    #[test]
+    fn test_too_deep() {
+        let input = "a {".repeat(10_000);
+        let (statements, diagnostics) = parse_lenient(&input);
+
+        assert!(statements.is_empty());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].expected(), "blocks nested at most 64 deep");
+    }
+}
@@ -0,0 +1,259 @@
+// Splitting of input into tokens.
+//
+// The parser works on characters directly, so has no token stream of its
+// own. lex provides one for tools which need to see the input at that
+// level, such as syntax highlighters. Every byte of the input belongs to
+// exactly one token, whitespace and comments included, so joining the text of
+// the tokens in order gives back the input. Input which can't be a token,
+// such as an unterminated string, becomes an Unknown token rather than an
+// error, so that the rest of the input can still be lexed.
+//
+// Tokens follow jail(8)'s lexer: commas separate list items and `+=` is a
+// token of its own, while a `$` reference or backslash escape is part of the
+// word it's found in.
+use crate::escape::is_continuation;
+use crate::span::Lines;
+use crate::{
+    is_name_char,
+    quoted,
+    Span,
+};
+
+/// A token of jail.conf syntax.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Token<'a> {
+    /// Spaces, newlines and backslash continued lines.
+    Whitespace(&'a str),
+    /// A comment of any style, including its markers.
+    Comment(&'a str),
+    /// An unquoted name or value, eg. `allow.mount`, `/usr/jails` or
+    /// `$name`.
+    Word(&'a str),
+    /// A quoted string, including its quotes.
+    String(&'a str),
+    /// `=`
+    Assign,
+    /// `+=`
+    Append,
+    /// `;`
+    Semi,
+    /// `,`
+    Comma,
+    /// `{`
+    LBrace,
+    /// `}`
+    RBrace,
+    /// Input which can't start a token, or an unterminated string or
+    /// comment.
+    Unknown(&'a str),
+}
+
+// Characters which end a word.
+fn ends_word(rest: &str) -> bool {
+    let c = match rest.chars().next() {
+        Some(c) => c,
+        None    => return true,
+    };
+
+    c.is_whitespace()
+        || "\"';={},#".contains(c)
+        || rest.starts_with("+=")
+        || is_continuation(rest)
+}
+
+// The length of the word at the start of input.
+fn word_len(input: &str) -> usize {
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(i, c)) = chars.peek() {
+        let rest = &input[i..];
+
+        if i > 0 && ends_word(rest) {
+            return i;
+        }
+
+        match c {
+            '$' if rest.starts_with("${") => match rest.find('}') {
+                Some(close) if rest[2..close].chars().all(is_name_char) => {
+                    while chars.next_if(|&(j, _)| j <= i + close).is_some() {}
+                },
+                _ => {
+                    chars.next();
+                },
+            },
+            '\\' if rest.len() > 1 => {
+                chars.next();
+                chars.next();
+            },
+            _ => {
+                chars.next();
+            },
+        }
+    }
+
+    input.len()
+}
+
+// The length of the whitespace at the start of input, including continued
+// lines.
+fn whitespace_len(input: &str) -> usize {
+    let mut len = 0;
+
+    loop {
+        let rest = &input[len..];
+
+        if is_continuation(rest) {
+            len += rest.find('\n').map_or(rest.len(), |newline| newline + 1);
+        }
+        else {
+            match rest.chars().next() {
+                Some(c) if c.is_whitespace() => len += c.len_utf8(),
+                _                            => return len,
+            }
+        }
+    }
+}
+
+// The next token at the start of input, and its length.
+fn next_token(input: &str) -> (Token<'_>, usize) {
+    let to_line_end = || input.find('\n').unwrap_or(input.len());
+
+    let first = match input.chars().next() {
+        Some(c) => c,
+        None    => return (Token::Unknown(""), 0),
+    };
+
+    if first.is_whitespace() || is_continuation(input) {
+        let len = whitespace_len(input);
+
+        return (Token::Whitespace(&input[..len]), len);
+    }
+
+    if first == '#' || input.starts_with("//") {
+        let len = to_line_end();
+
+        return (Token::Comment(&input[..len]), len);
+    }
+
+    if let Some(body) = input.strip_prefix("/*") {
+        return match body.find("*/") {
+            Some(end) => (Token::Comment(&input[..end + 4]), end + 4),
+            None      => (Token::Unknown(input), input.len()),
+        };
+    }
+
+    if input.starts_with("+=") {
+        return (Token::Append, 2);
+    }
+
+    match first {
+        '"' | '\'' => match quoted(input) {
+            Ok((_, string)) => (Token::String(string), string.len()),
+            Err(_)          => (Token::Unknown(input), input.len()),
+        },
+        '=' => (Token::Assign, 1),
+        ';' => (Token::Semi, 1),
+        ',' => (Token::Comma, 1),
+        '{' => (Token::LBrace, 1),
+        '}' => (Token::RBrace, 1),
+        _   => {
+            let len = word_len(input);
+
+            (Token::Word(&input[..len]), len)
+        },
+    }
+}
+
+/// Split input into tokens, each with the span it was found at. Every byte
+/// of the input is part of a token, so the tokens' text joined in order is
+/// the input.
+pub fn lex(input: &str) -> Vec<(Token<'_>, Span)> {
+    let lines = Lines::new(input);
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < input.len() {
+        let (token, len) = next_token(&input[pos..]);
+
+        tokens.push((token, lines.span(pos..pos + len)));
+        pos += len;
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    fn kinds(input: &str) -> Vec<Token<'_>> {
+        lex(input).into_iter()
+            .map(|(token, _)| token)
+            .filter(|token| !matches!(token, Token::Whitespace(_)))
+            .collect()
+    }
+
+    #[test]
+    fn test_lex() {
+        assert_eq!(kinds("www { ip4.addr += \"192.0.2.1\", lo1|127.0.1.1; }"), vec![
+            Token::Word("www"),
+            Token::LBrace,
+            Token::Word("ip4.addr"),
+            Token::Append,
+            Token::String("\"192.0.2.1\""),
+            Token::Comma,
+            Token::Word("lo1|127.0.1.1"),
+            Token::Semi,
+            Token::RBrace,
+        ]);
+
+        assert_eq!(kinds("$base=/jails/${name}/a+b; # done"), vec![
+            Token::Word("$base"),
+            Token::Assign,
+            Token::Word("/jails/${name}/a+b"),
+            Token::Semi,
+            Token::Comment("# done"),
+        ]);
+
+        assert_eq!(kinds("a = \"#1\" '// 2' \"/* 3 */\"; # 4"), vec![
+            Token::Word("a"),
+            Token::Assign,
+            Token::String("\"#1\""),
+            Token::String("'// 2'"),
+            Token::String("\"/* 3 */\""),
+            Token::Semi,
+            Token::Comment("# 4"),
+        ]);
+
+        assert_eq!(kinds("path = \"unterminated;\n"), vec![
+            Token::Word("path"),
+            Token::Assign,
+            Token::Unknown("\"unterminated;\n"),
+        ]);
+    }
+
+    #[test]
+    fn test_lex_is_lossless() {
+        let input = indoc!(r#"
+            /* Defaults */
+            persist; // Keep running.
+            exec.start = "/bin/sh \
+                /etc/rc";
+            "my jail" {
+                path = a\ b;
+            }
+            "#);
+
+        let tokens = lex(input);
+        let text: String = tokens.iter()
+            .map(|(_, span)| &input[span.range()])
+            .collect();
+
+        assert_eq!(text, input);
+
+        let (token, span) = tokens[tokens.len() - 2];
+        assert_eq!(token, Token::RBrace);
+        assert_eq!((span.start().line(), span.start().column()), (7, 1));
+    }
+}
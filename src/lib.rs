@@ -1,130 +1,1019 @@
-use std::error;
-use std::fmt;
-use nom::*;
-use nom::types::CompleteStr;
-
-#[derive(Debug, PartialEq)]
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+use nom::branch::alt;
+use nom::bytes::complete::{
+    tag,
+    take_until,
+    take_while,
+};
+use nom::character::complete::{
+    char,
+    multispace0,
+    space0,
+    space1,
+};
+use nom::combinator::{
+    consumed,
+    map,
+    opt,
+    recognize,
+    verify,
+};
+use nom::error::{
+    Error as NomError,
+    ErrorKind,
+};
+use nom::multi::{
+    many0,
+    many0_count,
+};
+use nom::sequence::{
+    delimited,
+    pair,
+    preceded,
+    tuple,
+};
+use nom::{
+    Err,
+    IResult,
+};
+use tracing::debug;
+
+#[cfg(feature = "serde")]
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+mod annotation;
+mod assemble;
+mod audit;
+mod bastille;
+mod builder;
+#[cfg(feature = "capi")]
+mod capi;
+mod command;
+mod commented;
+mod compare;
+mod config;
+mod convert;
+mod corpus;
+mod cst;
+#[cfg(feature = "serde")]
+mod describe;
+mod devfs;
+mod dir;
+mod document;
+mod edit;
+mod effective;
+mod error;
+mod escape;
+#[cfg(feature = "fancy-errors")]
+mod fancy;
+mod format;
+#[cfg(feature = "fs-checks")]
+mod fscheck;
+#[cfg(feature = "arbitrary")]
+mod generate;
+mod graph;
+mod hierarchy;
+mod include;
+mod incremental;
+mod lenient;
+mod lex;
+mod limit;
+mod lint;
+mod merge;
+mod name;
+mod network;
+#[cfg(feature = "tokio")]
+mod nonblocking;
+mod normalize;
+mod origin;
+mod overrides;
+mod owned;
+mod patch;
+mod provenance;
+mod rctl;
+mod redact;
+mod restyle;
+#[cfg(all(feature = "sys", target_os = "freebsd"))]
+mod running;
+mod save;
+mod scan;
+#[cfg(test)]
+mod scratch;
+mod series;
+mod shared;
+mod span;
+mod stats;
+mod stream;
+mod strict;
+#[cfg(all(feature = "sys", target_os = "freebsd"))]
+mod sys;
+mod template;
+mod transform;
+mod tree;
+mod ucl;
+mod utf8;
+mod validate;
+mod value;
+mod variables;
+mod visit;
+mod vnet;
+mod warning;
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "watch")]
+mod watch;
+mod wildcard;
+mod writer;
+
+pub mod prelude;
+pub mod schema;
+pub mod testutil;
+
+pub use crate::annotation::Annotations;
+pub use crate::assemble::{
+    AssembleError,
+    Assembler,
+    Assembly,
+    Conflict,
+    ConflictPolicy,
+};
+pub use crate::bastille::{
+    from_bastille_template,
+    BastilleError,
+};
+pub use crate::builder::{
+    JailBlockBuilder,
+    JailConfigBuilder,
+};
+pub use crate::commented::{
+    attach_comments,
+    Commented,
+};
+pub use crate::compare::equivalent;
+pub use crate::config::{
+    EffectiveParams,
+    JailConfig,
+};
+pub use crate::convert::{
+    from_ezjail,
+    from_rc_conf,
+    ConvertError,
+};
+pub use crate::corpus::{
+    check_corpus,
+    check_sample,
+    CorpusError,
+    CorpusFailure,
+    CorpusReport,
+    CORPUS_BLESS_VAR,
+};
+pub use crate::cst::{
+    CstNode,
+    LosslessConfig,
+};
+#[cfg(feature = "serde")]
+pub use crate::describe::{
+    DescribedValue,
+    Description,
+    JailDescription,
+};
+pub use crate::devfs::{
+    parse_devfs_rules,
+    DevfsRules,
+    DevfsRulesError,
+    DevfsRuleset,
+};
+pub use crate::dir::{
+    parse_dir,
+    DirConfig,
+};
+pub use crate::document::{
+    Document,
+    Origin,
+};
+pub use crate::edit::{
+    Anchor,
+    Edit,
+    EditError,
+    Placement,
+};
+pub use crate::effective::{
+    EffectiveJail,
+    ExecStage,
+    Mounts,
+};
+pub use crate::error::{
+    Error,
+    ParseError,
+    ParseErrorKind,
+};
+pub use crate::format::{
+    format_config,
+    FormatOptions,
+};
+#[cfg(feature = "fs-checks")]
+pub use crate::fscheck::{
+    FsCheck,
+    FsReport,
+    FsStatus,
+};
+pub use crate::graph::{
+    CycleError,
+    DependencyError,
+};
+pub use crate::include::{
+    parse_with_includes,
+    IncludeError,
+};
+pub use crate::lenient::{
+    parse_lenient,
+    Diagnostic,
+};
+pub use crate::lex::{
+    lex,
+    Token,
+};
+pub use crate::limit::{
+    ParseOptions,
+    DEFAULT_MAX_DEPTH,
+};
+pub use crate::lint::{
+    Lint,
+    Severity,
+};
+pub use crate::merge::{
+    MergeError,
+    MergeStrategy,
+};
+pub use crate::name::{
+    NameError,
+    ParamName,
+};
+pub use crate::network::{
+    JailAddress,
+    Subnet,
+};
+#[cfg(feature = "tokio")]
+pub use crate::nonblocking::{
+    parse_file_async,
+    parse_reader_async,
+    AsyncStatementReader,
+};
+pub use crate::normalize::{
+    BoolStyle,
+    NormalizeOptions,
+};
+pub use crate::origin::{
+    parse_include_tree,
+    FileOrigin,
+    IncludeTree,
+};
+pub use crate::owned::{
+    into_owned,
+    JailConfOwned,
+};
+pub use crate::patch::{
+    JailPatch,
+    PatchOp,
+};
+pub use crate::provenance::{
+    ParamExplanation,
+    ParamSource,
+    ParamStep,
+};
+pub use crate::rctl::{
+    parse_rctl,
+    RctlError,
+    RctlReport,
+    RctlRule,
+    RctlSubject,
+};
+pub use crate::redact::{
+    RedactPolicy,
+    REDACTED,
+};
+pub use crate::restyle::restyle_comments;
+#[cfg(all(feature = "sys", target_os = "freebsd"))]
+pub use crate::running::RunningDiff;
+pub use crate::save::{
+    write_atomic,
+    SaveOptions,
+};
+pub use crate::scan::{
+    extract_jail,
+    scan_blocks,
+    LazyBlock,
+};
+pub use crate::series::{
+    generate_series,
+    SeriesError,
+};
+pub use crate::shared::ArcJailConfig;
+pub use crate::span::{
+    Position,
+    Span,
+};
+pub use crate::stats::ConfigStats;
+pub use crate::stream::{
+    parse_reader,
+    StatementReader,
+};
+#[cfg(all(feature = "sys", target_os = "freebsd"))]
+pub use crate::sys::{
+    create_jail,
+    running_jails,
+    update_jail,
+    RunningJail,
+    SysError,
+};
+pub use crate::template::{
+    render_template,
+    TemplateContext,
+    TemplateError,
+};
+pub use crate::transform::{
+    GroupParams,
+    HoistGlobals,
+    SortJails,
+    Then,
+    Transform,
+};
+pub use crate::tree::dump_tree;
+pub use crate::ucl::UclError;
+pub use crate::utf8::{
+    parse_bytes,
+    parse_bytes_preserving,
+    ByteConfig,
+    Utf8Policy,
+};
+pub use crate::validate::ValidationError;
+pub use crate::value::ValueError;
+pub use crate::variables::{
+    resolve_variables,
+    resolve_variables_with,
+    Environment,
+    VariableError,
+    VariableResolver,
+};
+pub use crate::visit::{
+    walk,
+    walk_block,
+    walk_block_mut,
+    walk_mut,
+    walk_statement_mut,
+    Visitor,
+    VisitorMut,
+};
+pub use crate::vnet::{
+    epair,
+    JailVnet,
+};
+pub use crate::warning::{
+    parse_with_warnings,
+    ParseOutput,
+    Warning,
+    WarningKind,
+};
+#[cfg(feature = "wasm")]
+pub use crate::wasm::parse_to_json;
+#[cfg(feature = "watch")]
+pub use crate::watch::{
+    watch_config,
+    ConfigWatcher,
+};
+pub use crate::writer::{
+    to_string,
+    write_config,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum CommentStyle {
     C,
     CPP,
     Shell,
 }
 
-#[derive(Debug, PartialEq)]
+/// How a value was quoted in the source.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum QuoteStyle {
+    /// A bare value, eg. `persist = 1;`.
+    #[default]
+    None,
+    /// A double quoted value, which may contain escapes and variables.
+    Double,
+    /// A single quoted value, which is taken literally.
+    Single,
+}
+
+impl QuoteStyle {
+    // Whether the default, bare quoting, which serialization leaves out.
+    #[cfg(feature = "serde")]
+    fn is_none(&self) -> bool {
+        *self == QuoteStyle::None
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct JailComment<'a> {
-    comment: CompleteStr<'a>,
+    comment: Cow<'a, str>,
     style:   CommentStyle,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    source:  Cow<'a, str>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    span:    Span,
+}
+
+impl<'a> JailComment<'a> {
+    /// The text of the comment, without its comment markers.
+    pub fn comment(&self) -> &str {
+        &self.comment
+    }
+
+    /// The style of comment used.
+    pub fn style(&self) -> CommentStyle {
+        self.style
+    }
+
+    /// The comment exactly as it appeared in the source, including markers.
+    pub fn source_text(&self) -> &str {
+        &self.source
+    }
+
+    /// Where in the input this node was parsed from.
+    pub fn span(&self) -> Span {
+        self.span
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct JailParamBool<'a> {
-    name: CompleteStr<'a>,
+    name:   Cow<'a, str>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    source: Cow<'a, str>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    span:   Span,
+}
+
+impl<'a> JailParamBool<'a> {
+    /// The parameter name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The parameter exactly as it appeared in the source.
+    pub fn source_text(&self) -> &str {
+        &self.source
+    }
+
+    /// Where in the input this node was parsed from.
+    pub fn span(&self) -> Span {
+        self.span
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct JailParamValue<'a> {
-    name:   CompleteStr<'a>,
-    value:  CompleteStr<'a>,
+    name:   Cow<'a, str>,
+    value:  Cow<'a, str>,
+    raw:    Cow<'a, str>,
+    append: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    source: Cow<'a, str>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    span:   Span,
+}
+
+impl<'a> JailParamValue<'a> {
+    /// The parameter name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The value exactly as it appeared in the source, including any quotes.
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    /// The decoded value, with surrounding quotes removed and any escape
+    /// sequences interpreted. A value written as adjacent fragments is
+    /// joined into one.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// The decoded value as it's held. It's borrowed from the input unless
+    /// decoding it needed a copy, because it contained escape sequences or
+    /// was written as several fragments.
+    pub fn value_cow(&self) -> &Cow<'a, str> {
+        &self.value
+    }
+
+    /// The fragments the value was written as, each exactly as it appeared
+    /// in the source. Most values are a single fragment, but adjacent ones
+    /// are concatenated, eg. `"/bin/sh " "/etc/rc"`.
+    pub fn fragments(&self) -> Vec<&str> {
+        escape::fragments(&self.raw)
+    }
+
+    /// How the value was quoted. For a value of several fragments, this is
+    /// how the first of them was quoted.
+    pub fn quote_style(&self) -> QuoteStyle {
+        escape::quote_style(self.fragments()[0])
+    }
+
+    /// Whether the value was appended with `+=` rather than assigned.
+    pub fn is_append(&self) -> bool {
+        self.append
+    }
+
+    /// The whole parameter statement exactly as it appeared in the source.
+    pub fn source_text(&self) -> &str {
+        &self.source
+    }
+
+    /// Where in the input this node was parsed from.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct JailVariable<'a> {
+    name:   Cow<'a, str>,
+    value:  Cow<'a, str>,
+    raw:    Cow<'a, str>,
     append: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    source: Cow<'a, str>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    span:   Span,
+}
+
+impl<'a> JailVariable<'a> {
+    /// The variable name, without the leading `$`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The value exactly as it appeared in the source, including any quotes.
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    /// The decoded value, with surrounding quotes removed and any escape
+    /// sequences interpreted. A value written as adjacent fragments is
+    /// joined into one. References to other variables are left unexpanded,
+    /// see `resolve_variables`.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// The decoded value as it's held, borrowed from the input unless
+    /// decoding it needed a copy. See `JailParamValue::value_cow`.
+    pub fn value_cow(&self) -> &Cow<'a, str> {
+        &self.value
+    }
+
+    /// The fragments the value was written as, each exactly as it appeared
+    /// in the source.
+    pub fn fragments(&self) -> Vec<&str> {
+        escape::fragments(&self.raw)
+    }
+
+    /// How the value was quoted. For a value of several fragments, this is
+    /// how the first of them was quoted.
+    pub fn quote_style(&self) -> QuoteStyle {
+        escape::quote_style(self.fragments()[0])
+    }
+
+    /// Whether the value was appended with `+=` rather than assigned.
+    pub fn is_append(&self) -> bool {
+        self.append
+    }
+
+    /// The whole definition exactly as it appeared in the source.
+    pub fn source_text(&self) -> &str {
+        &self.source
+    }
+
+    /// Where in the input this node was parsed from.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct JailInclude<'a> {
+    path:   Cow<'a, str>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    source: Cow<'a, str>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    span:   Span,
 }
 
-#[derive(Debug, PartialEq)]
+impl<'a> JailInclude<'a> {
+    /// The path or glob pattern to include, without quotes. Relative paths
+    /// are relative to the directory of the including file.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The whole directive exactly as it appeared in the source.
+    pub fn source_text(&self) -> &str {
+        &self.source
+    }
+
+    /// Where in the input this node was parsed from.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct JailBlock<'a> {
-    name:   CompleteStr<'a>,
-    params: Vec<JailConf<'a>>,
+    name:        Cow<'a, str>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "QuoteStyle::is_none")
+    )]
+    quote:       QuoteStyle,
+    params:      Vec<JailConf<'a>>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Annotations::is_empty")
+    )]
+    annotations: Annotations,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    source:      Cow<'a, str>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    span:        Span,
 }
 
-#[derive(Debug, PartialEq)]
+impl<'a> JailBlock<'a> {
+    /// The name of the jail this block configures.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The name as it's held, borrowed from the input unless it was quoted
+    /// with escape sequences which had to be decoded.
+    pub fn name_cow(&self) -> &Cow<'a, str> {
+        &self.name
+    }
+
+    /// How the name was quoted in the source.
+    pub fn name_quote_style(&self) -> QuoteStyle {
+        self.quote
+    }
+
+    /// The statements within the block.
+    pub fn params(&self) -> &[JailConf<'a>] {
+        &self.params
+    }
+
+    /// The parameters set within the block, skipping comments and any other
+    /// statements.
+    pub fn params_iter(&self) -> impl Iterator<Item = &JailConf<'a>> {
+        self.params.iter().filter(|statement| statement.is_param())
+    }
+
+    /// Metadata from `@key: value` directives in the comments immediately
+    /// preceding this block.
+    pub fn annotations(&self) -> &Annotations {
+        &self.annotations
+    }
+
+    /// Look up a single annotation by key.
+    pub fn annotation(&self, key: &str) -> Option<&str> {
+        self.annotations.get(key).map(String::as_str)
+    }
+
+    /// The whole block, from its name to the closing brace, exactly as it
+    /// appeared in the source.
+    pub fn source_text(&self) -> &str {
+        &self.source
+    }
+
+    /// Where in the input this node was parsed from.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", rename_all = "snake_case")
+)]
 pub enum JailConf<'a> {
     Block(JailBlock<'a>),
     Comment(JailComment<'a>),
+    Include(JailInclude<'a>),
     ParamBool(JailParamBool<'a>),
     ParamValue(JailParamValue<'a>),
+    Variable(JailVariable<'a>),
 }
 
-#[derive(Debug)]
-pub struct ParseError;
+impl<'a> JailConf<'a> {
+    /// The source text this node was parsed from, preserving the user's
+    /// original formatting.
+    pub fn source_text(&self) -> &str {
+        match self {
+            JailConf::Block(block)      => block.source_text(),
+            JailConf::Comment(comment)  => comment.source_text(),
+            JailConf::Include(include)  => include.source_text(),
+            JailConf::ParamBool(param)  => param.source_text(),
+            JailConf::ParamValue(param) => param.source_text(),
+            JailConf::Variable(var)     => var.source_text(),
+        }
+    }
 
-impl fmt::Display for ParseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "could not parse jail configuration")
+    /// Where in the input this node was parsed from.
+    pub fn span(&self) -> Span {
+        match self {
+            JailConf::Block(block)      => block.span(),
+            JailConf::Comment(comment)  => comment.span(),
+            JailConf::Include(include)  => include.span(),
+            JailConf::ParamBool(param)  => param.span(),
+            JailConf::ParamValue(param) => param.span(),
+            JailConf::Variable(var)     => var.span(),
+        }
     }
-}
 
-impl error::Error for ParseError {
-    fn description(&self) -> &str {
-        "could not parse jail configuration"
+    /// Whether this node sets a parameter, with or without a value.
+    pub fn is_param(&self) -> bool {
+        matches!(self, JailConf::ParamBool(_) | JailConf::ParamValue(_))
     }
 
-    fn cause(&self) -> Option<&error::Error> {
-        None
+    /// Whether every string held by this node, and by any statements within
+    /// it, is borrowed from the input. Parsing only copies text which had to
+    /// be decoded, so this is true of most parsed statements.
+    pub fn is_borrowed(&self) -> bool {
+        let borrowed = |text: &Cow<str>| matches!(text, Cow::Borrowed(_));
+
+        match self {
+            JailConf::Block(block) => {
+                borrowed(&block.name)
+                    && borrowed(&block.source)
+                    && block.params.iter().all(JailConf::is_borrowed)
+            },
+            JailConf::Comment(comment) => {
+                borrowed(&comment.comment) && borrowed(&comment.source)
+            },
+            JailConf::Include(include) => {
+                borrowed(&include.path) && borrowed(&include.source)
+            },
+            JailConf::ParamBool(param) => {
+                borrowed(&param.name) && borrowed(&param.source)
+            },
+            JailConf::ParamValue(param) => {
+                [&param.name, &param.value, &param.raw, &param.source]
+                    .iter()
+                    .all(|text| borrowed(text))
+            },
+            JailConf::Variable(var) => {
+                [&var.name, &var.value, &var.raw, &var.source]
+                    .iter()
+                    .all(|text| borrowed(text))
+            },
+        }
     }
 }
 
+// Comments and strings are recognised as in jail(8)'s lexer, which every
+// scanner in the crate follows too:
+//
+//   - Within a quoted string, `#`, `//` and `/*` are ordinary characters.
+//   - Within a comment, quotes are ordinary characters. A C style comment
+//     ends at the first `*/`, even one which looks like it's in a string.
+//   - Elsewhere, `#` always starts a comment, while `//` and `/*` only start
+//     one at the start of a word, so `/jails//www` is a single value.
+//   - A backslash escaped character never starts a comment or string.
+
 // Parse a C style comment, eg:
 // /*
 //  * C style comment
 //  */
-named!(
-    parse_comment_c_style<CompleteStr, JailComment>,
-    do_parse!(
-        res: delimited!(
-            tag!("/*"),
-            take_until!("*/"),
-            tag!("*/")
-        ) >>
-        (JailComment{
-            comment: res,
+fn parse_comment_c_style(input: &str) -> IResult<&str, JailComment<'_>> {
+    map(
+        consumed(delimited(tag("/*"), take_until("*/"), tag("*/"))),
+        |(source, comment): (&str, &str)| JailComment{
+            comment: comment.into(),
             style:   CommentStyle::C,
-        })
-    )
-);
+            source:  source.into(),
+            span:    Span::default(),
+        },
+    )(input)
+}
+
+fn not_newline(c: char) -> bool {
+    c != '\n'
+}
+
+// Recognise the rest of a line, up to but not including its newline. The
+// last line of the input needn't end with one.
+fn rest_of_line(input: &str) -> IResult<&str, &str> {
+    take_while(not_newline)(input)
+}
 
 // Parse a CPP style comment, eg:
 // // C++ style comment
-named!(
-    parse_comment_cpp_style<CompleteStr, JailComment>,
-    do_parse!(
-             tag!("//")        >>
-        res: take_until!("\n") >>
-        (JailComment{
-            comment: res,
+fn parse_comment_cpp_style(input: &str) -> IResult<&str, JailComment<'_>> {
+    map(
+        consumed(preceded(tag("//"), rest_of_line)),
+        |(source, comment): (&str, &str)| JailComment{
+            comment: comment.into(),
             style:   CommentStyle::CPP,
-        })
-    )
-);
+            source:  source.into(),
+            span:    Span::default(),
+        },
+    )(input)
+}
 
 // Parse a shell style comment, eg:
 // # Shell style comment
-named!(
-    parse_comment_shell_style<CompleteStr, JailComment>,
-    do_parse!(
-             tag!("#")         >>
-        res: take_until!("\n") >>
-        (JailComment{
-            comment: res,
+fn parse_comment_shell_style(input: &str) -> IResult<&str, JailComment<'_>> {
+    map(
+        consumed(preceded(tag("#"), rest_of_line)),
+        |(source, comment): (&str, &str)| JailComment{
+            comment: comment.into(),
             style:   CommentStyle::Shell,
-        })
-    )
-);
+            source:  source.into(),
+            span:    Span::default(),
+        },
+    )(input)
+}
+
+// Characters which may appear in parameter and jail names.
+fn is_name_char(c: char) -> bool {
+    !c.is_whitespace() && !"\"';=+{}#".contains(c)
+}
+
+// Recognise a parameter or jail name. As in values, a comment marker after a
+// comma starts a comment rather than continuing the name.
+fn param_name(input: &str) -> IResult<&str, &str> {
+    let len = input
+        .char_indices()
+        .find(|&(i, c)| !is_name_char(c) || (c == ',' && starts_comment(&input[i + 1..])))
+        .map_or(input.len(), |(i, _)| i);
+
+    if len == 0 {
+        return Err(Err::Error(NomError::new(input, ErrorKind::TakeWhile1)));
+    }
+
+    Ok((&input[len..], &input[..len]))
+}
+
+// Recognise a quoted string, either double quoted which may contain
+// backslash escapes, or single quoted which is taken literally. The quotes
+// are included in the result.
+fn quoted(input: &str) -> IResult<&str, &str> {
+    let bytes = input.as_bytes();
+    let unterminated = || Err(Err::Error(NomError::new(input, ErrorKind::Escaped)));
+
+    let len = match bytes.first() {
+        Some(b'\'') => {
+            match input[1..].find('\'') {
+                Some(close) => close + 2,
+                None        => return unterminated(),
+            }
+        },
+        Some(b'"') => {
+            let mut i = 1;
+
+            loop {
+                match bytes.get(i) {
+                    Some(b'\\') => i += 2,
+                    Some(b'"')  => break i + 1,
+                    Some(_)     => i += 1,
+                    None        => return unterminated(),
+                }
+            }
+        },
+        _ => return Err(Err::Error(NomError::new(input, ErrorKind::Char))),
+    };
+
+    Ok((&input[len..], &input[..len]))
+}
+
+// Characters which may appear in an unquoted value, following the lexer of
+// jail(8). Anything outside ASCII is allowed too, while whitespace, quotes,
+// `#`, `;`, `=` and braces all end a bare word.
+pub(crate) fn is_bare_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || !c.is_ascii() || "_!%&()*+,-./:<>?@[]^`|~".contains(c)
+}
+
+// The length of the bare word at the start of input. Besides the characters
+// of is_bare_char, a bare word may contain variable references, `$name` or
+// `${name}`, and backslash escapes other than a continued line.
+pub(crate) fn bare_len(input: &str) -> usize {
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(i, c)) = chars.peek() {
+        let rest = &input[i..];
+
+        match c {
+            '$' if rest.starts_with("${") => match rest.find('}') {
+                Some(close) if rest[2..close].chars().all(is_name_char) => {
+                    while chars.next_if(|&(j, _)| j <= i + close).is_some() {}
+                },
+                _ => return i,
+            },
+            '$' => {
+                chars.next();
+            },
+            '\\' if !escape::is_continuation(rest) && rest.len() > 1 => {
+                chars.next();
+                chars.next();
+            },
+            // A comma separates list items, so a comment marker after one
+            // starts a comment, as it would at the start of the value.
+            ',' if starts_comment(&rest[1..]) => return i,
+            c if is_bare_char(c) => {
+                chars.next();
+            },
+            _ => return i,
+        }
+    }
+
+    input.len()
+}
+
+// Whether input starts with a comment, when found at the start of a word.
+fn starts_comment(input: &str) -> bool {
+    input.starts_with('#') || input.starts_with("//") || input.starts_with("/*")
+}
+
+// Recognise one fragment of a value, either a quoted string or a bare word.
+// A bare word can't start with a comment, eg. the `// note` of
+// `x = "a" // note`.
+fn fragment(input: &str) -> IResult<&str, &str> {
+    if input.starts_with('"') || input.starts_with('\'') {
+        return quoted(input);
+    }
+
+    if starts_comment(input) {
+        return Ok((input, ""));
+    }
+
+    let len = bare_len(input);
+
+    Ok((&input[len..], &input[..len]))
+}
+
+// Recognise spaces around a value, which may include a backslash at the end
+// of a line to continue the statement on the next.
+fn value_space(input: &str) -> IResult<&str, &str> {
+    recognize(many0_count(alt((space1, tag("\\\n"), tag("\\\r\n")))))(input)
+}
+
+// Recognise a value. Adjacent fragments are concatenated into a single value,
+// eg. `"/bin/sh " "/etc/rc"`, and may be separated by spaces or continued
+// lines.
+fn value(input: &str) -> IResult<&str, &str> {
+    recognize(pair(
+        fragment,
+        many0_count(preceded(value_space, verify(fragment, |f: &str| !f.is_empty()))),
+    ))(input)
+}
 
 // Parse a valueless boolean in the style of:
 //   - allow.mount;
-//   - persist;
+//   - persist ;
 //   - etc;
 //
-// Other types of value will error.
-named!(
-    parse_bool_param_no_value<CompleteStr, JailParamBool>,
-    do_parse!(
-        name: take_until_either!(" +=;\n") >> // Consume until an interesting char
-              not!(is_a!(" +=\n"))         >> // Ensure it's not a banned char
-              char!(';')                   >> // Consume terminating ;
-        (JailParamBool{
-            name: name,
-        })
-    )
-);
+// Other types of value will error. Statements may share a line, but may not
+// be split across lines.
+fn parse_bool_param_no_value(input: &str) -> IResult<&str, JailParamBool<'_>> {
+    map(
+        consumed(tuple((
+            param_name, // Parameter name
+            space0,     // Optional spaces
+            char(';'),  // Consume terminating ;
+        ))),
+        |(source, (name, _, _))| JailParamBool{
+            name:   name.into(),
+            source: source.into(),
+            span:   Span::default(),
+        },
+    )(input)
+}
 
 // Parse a parameter with an associated value.
 //   - allow.mount = true;
@@ -133,31 +1022,76 @@ named!(
 //   - ip4.addr += "127.0.1.2";
 //
 // Other types of value will error.
-// This will choke if a value contained a quoted double quote, we should be
-// able to use escaped!() to help with this.
-named!(
-    parse_param_with_value<CompleteStr, JailParamValue>,
-    do_parse!(
-        name:  take_until_either!(" +=;\n") >>
-               not!(is_a!(";\n"))           >> // We don't want end of line yet
-               space0                       >> // Optional spaces
-        plus:  opt!(char!('+'))             >> // Optional +
-               char!('=')                   >> // = is mandatory
-               space0                       >> // Optional spaces
-        value: delimited!(
-                   opt_res!(tag!("\"")),        // Possible opening quote
-                   take_until_either!("\";\n"), // value
-                   opt_res!(tag!("\""))         // Possible closing quote
-               )                            >>
-               not!(is_a!("\n"))            >> // Ensure no new line yet
-               char!(';')                   >> // Terminating ;
-        (JailParamValue{
-            name:   name,
-            value:  value,
+// Quoted values may contain backslash escapes, see the escape module. Long
+// values may be split across lines by ending each line but the last with a
+// backslash, eg:
+//   - exec.start = "/bin/sh \
+//         /etc/rc";
+fn parse_param_with_value(input: &str) -> IResult<&str, JailParamValue<'_>> {
+    map(
+        consumed(tuple((
+            param_name,      // Parameter name
+            space0,          // Optional spaces
+            opt(char('+')),  // Optional +
+            char('='),       // = is mandatory
+            value_space,     // Optional spaces or continued lines
+            value,           // Quoted or bare value
+            value_space,     // Optional spaces or continued lines
+            char(';'),       // Terminating ;
+        ))),
+        |(source, (name, _, plus, _, _, raw, _, _))| JailParamValue{
+            name:   name.into(),
+            value:  escape::decode(raw),
+            raw:    raw.into(),
             append: plus.is_some(),
-        })
-    )
-);
+            source: source.into(),
+            span:   Span::default(),
+        },
+    )(input)
+}
+
+// Parse an include directive, eg:
+//   - .include "/etc/jail.conf.d/*.conf";
+//   - .include '/etc/jail.conf.d/*.conf';
+//
+// The path is quoted and escaped as any other value is.
+// Included files are not read by the parser, see parse_with_includes.
+fn parse_include(input: &str) -> IResult<&str, JailInclude<'_>> {
+    map(
+        consumed(tuple((
+            tag(".include"),
+            space1,
+            verify(value, |raw: &str| !raw.is_empty()),
+            value_space,
+            char(';'),
+        ))),
+        |(source, (_, _, raw, _, _))| JailInclude{
+            path:   escape::decode(raw),
+            source: source.into(),
+            span:   Span::default(),
+        },
+    )(input)
+}
+
+// Parse a variable definition, eg:
+//   - $base = "/usr/jails";
+//   - $flags += "-l";
+//
+// The value is kept as written, references to other variables are expanded
+// later by resolve_variables.
+fn parse_variable(input: &str) -> IResult<&str, JailVariable<'_>> {
+    map(
+        consumed(preceded(char('$'), parse_param_with_value)),
+        |(source, param)| JailVariable{
+            name:   param.name,
+            value:  param.value,
+            raw:    param.raw,
+            append: param.append,
+            source: source.into(),
+            span:   Span::default(),
+        },
+    )(input)
+}
 
 // Attempt to parse a jail block.
 // eg.
@@ -166,75 +1100,256 @@ named!(
 //     allow.mount;
 //     persist;
 // }
-named!(
-    parse_block<CompleteStr, JailConf>,
-    do_parse!(
-        name:  take_until_either!(" {;\n") >> // Read the name
-               space0                      >> // Optional spaces
-               not!(is_a!(";\n"))          >> // Invalid chars before block
-               char!('{')                  >> // Mandatory opening {
-        block: parse_input                 >> // Recursive parsing. Oh no.
-               char!('}')                  >> // Mandatory terminating }
-        (JailConf::Block(                     // JailBlock to return
+//
+// Blocks may also be written on a single line, eg. `jailname { persist; }`,
+// and names may be quoted, eg. `"my-jail.example.com" { ... }`.
+fn parse_block(input: &str) -> IResult<&str, JailConf<'_>> {
+    map(
+        consumed(tuple((
+            alt((quoted, param_name)), // Read the name, maybe quoted
+            space0,                    // Optional spaces
+            char('{'),                 // Mandatory opening {
+            parse_input,               // Recursive parsing. Oh no.
+            multispace0,               // Possibly empty block
+            char('}'),                 // Mandatory terminating }
+        ))),
+        |(source, (name, _, _, params, _, _))| JailConf::Block(
             JailBlock{
-                name:   name,
-                params: block,
+                name:        escape::unescape(name),
+                quote:       escape::quote_style(name),
+                params,
+                annotations: Annotations::new(),
+                source:      source.into(),
+                span:        Span::default(),
             }
-        ))
-    )
-);
+        ),
+    )(input)
+}
+
+// Parse a statement starting with a name: a parameter, with or without a
+// value, or a jail block. The name is recognised once, and what follows it
+// decides which of them to parse, rather than trying each in turn.
+fn parse_named(input: &str) -> IResult<&str, JailConf<'_>> {
+    let (rest, _) = param_name(input)?;
+
+    match rest.trim_start_matches([' ', '\t']).chars().next() {
+        Some(';') => map(parse_bool_param_no_value, JailConf::ParamBool)(input),
+        Some('{') => parse_block(input),
+        _         => map(parse_param_with_value, JailConf::ParamValue)(input),
+    }
+}
+
+// The length of a jail block's header, from its name to its opening brace,
+// if the statement at the start of input is a block. The name is recognised
+// as parse_statement does, so that the block scanner finds the same blocks.
+pub(crate) fn block_header(input: &str) -> Option<usize> {
+    let name = match input.chars().next() {
+        Some('.') if parse_include(input).is_ok()  => return None,
+        Some('$') if parse_variable(input).is_ok() => return None,
+        Some('"') | Some('\'') => quoted(input),
+        _                      => param_name(input),
+    };
+
+    let (rest, _) = name.ok()?;
+    let rest = rest.trim_start_matches([' ', '\t']).strip_prefix('{')?;
+
+    Some(input.len() - rest.len())
+}
+
+// Parse any one statement. Config could be in pretty much any order, so
+// dispatch on the first character to the parsers for the statements which
+// can start with it. Names may contain most characters, so a statement which
+// looks like an include or variable but isn't one may still be a parameter
+// or block. One which starts with a comment marker is always a comment.
+fn parse_statement(input: &str) -> IResult<&str, JailConf<'_>> {
+    match input.chars().next() {
+        // Shell style comments
+        Some('#') => map(parse_comment_shell_style, JailConf::Comment)(input),
+        // CPP style comments
+        Some('/') if input.starts_with("//") => {
+            map(parse_comment_cpp_style, JailConf::Comment)(input)
+        },
+        // C style comments
+        Some('/') if input.starts_with("/*") => {
+            map(parse_comment_c_style, JailConf::Comment)(input)
+        },
+        // Include directives
+        Some('.') => alt((map(parse_include, JailConf::Include), parse_named))(input),
+        // Variable definitions
+        Some('$') => alt((map(parse_variable, JailConf::Variable), parse_named))(input),
+        // Only a jail block's name may be quoted
+        Some('"') | Some('\'') => parse_block(input),
+        _ => parse_named(input),
+    }
+}
 
 // Attempt to parse the given jail.conf input
-named!(
-    parse_input<CompleteStr, Vec<JailConf>>,
-    do_parse!(
-        // We attempt parsers many times until the input is exhausted.
-        config: many0!(
-            // Config could be in pretty much any order.
-            // Surrounding whitespace will be trimmed.
-            ws!(alt!(
-                // Parse C style comments
-                parse_comment_c_style => { |comment|
-                    JailConf::Comment(comment)
-                } |
-                // Parse CPP style comments
-                parse_comment_cpp_style => { |comment|
-                    JailConf::Comment(comment)
-                } |
-                // Parse Shell style comments
-                parse_comment_shell_style => { |comment|
-                    JailConf::Comment(comment)
-                } |
-                // Parse a boolean parameter with no values.
-                parse_bool_param_no_value => { |param|
-                    JailConf::ParamBool(param)
-                } |
-                // Parse a parameter with a value.
-                parse_param_with_value => { |param|
-                    JailConf::ParamValue(param)
-                } |
-                // Parse a named jail block
-                // Returns a JailConf::Block
-                parse_block
-            ))
-        ) >>
-        (config)
-    )
-);
-
-// Public entry point into the parser.
-pub fn parse(input: &str) -> Result<Vec<JailConf>, ParseError> {
-    let res = parse_input(input.into());
-
-    match res {
-        Ok(r) => {
-            let (_unparsed, parsed) = r;
-            Ok(parsed)
+fn parse_input(input: &str) -> IResult<&str, Vec<JailConf<'_>>> {
+    map(
+        // We attempt statements many times until the input is exhausted.
+        // Surrounding whitespace will be trimmed.
+        many0(delimited(multispace0, parse_statement, multispace0)),
+        annotation::attach,
+    )(input)
+}
+
+// The input remaining at the point a parser failed.
+fn remaining<'a>(input: &'a str, err: Err<NomError<&'a str>>) -> &'a str {
+    match err {
+        Err::Error(e) | Err::Failure(e) => e.input,
+        Err::Incomplete(_)              => &input[input.len()..],
+    }
+}
+
+// Find where parsing really failed, given the input remaining after it
+// stopped. When a block fails to parse, the remainder starts at the block's
+// name, so look inside it for the statement at fault.
+fn failure_point(rest: &str) -> &str {
+    let rest = rest.trim_start();
+    let line = rest.lines().next().unwrap_or("");
+
+    // Only a block header has a brace without an assignment before it.
+    let open = match line.find('{') {
+        Some(open) if !line[..open].contains(&['=', '"'][..]) => open,
+        _                                                     => return rest,
+    };
+
+    match parse_input(&rest[open + 1..]) {
+        Ok((inner, _)) if !inner.trim_start().starts_with('}') => {
+            failure_point(inner)
         },
-        Err(_e) => Err(ParseError),
+        _ => rest,
     }
 }
 
+/// Parse as much of the input as possible, stopping at the first statement
+/// that can't be parsed. Returns the statements parsed along with the
+/// remaining unparsed input, which is empty if everything was parsed.
+pub fn parse_partial(input: &str) -> Result<(Vec<JailConf<'_>>, &str), ParseError> {
+    parse_partial_with(input, &ParseOptions::new())
+}
+
+fn parse_partial_with<'a>(input: &'a str, options: &ParseOptions)
+-> Result<(Vec<JailConf<'a>>, &'a str), ParseError> {
+    limit::check(input, options)?;
+
+    match parse_input(input) {
+        Ok((rest, mut parsed)) => {
+            span::assign_with_tabs(input, options.tabs(), &mut parsed);
+
+            Ok((parsed, rest))
+        },
+        Err(e) => {
+            let offset = input.len() - remaining(input, e).len();
+            let expected = error::expected(&input[offset..]);
+
+            Err(ParseError::new(input.as_bytes(), offset, expected))
+        },
+    }
+}
+
+// Remove comments from statements and from the blocks within them.
+fn drop_comments(statements: &mut Vec<JailConf>) {
+    statements.retain(|statement| !matches!(statement, JailConf::Comment(_)));
+
+    for statement in statements {
+        if let JailConf::Block(block) = statement {
+            drop_comments(&mut block.params);
+        }
+    }
+}
+
+// Apply the options for what's done with statements once they're parsed.
+fn finish<'a>(input: &'a str, mut parsed: Vec<JailConf<'a>>, options: &ParseOptions)
+-> Result<Vec<JailConf<'a>>, ParseError> {
+    if let Some(version) = options.version() {
+        if let Some(span) = validate::first_unavailable(version, &parsed) {
+            let expected = format!("a parameter available on FreeBSD {}", version);
+
+            return Err(ParseError::new(input.as_bytes(), span.start().offset(), &expected)
+                .with_kind(ParseErrorKind::Unavailable { version }));
+        }
+    }
+
+    if let Some(dir) = options.include_dir() {
+        parsed = include::expand_parsed(parsed, dir, &mut include::Trace::default())
+            .map_err(|(offset, error)| {
+                let expected = format!("an include which can be expanded, {}", error);
+
+                ParseError::new(input.as_bytes(), offset, &expected)
+                    .with_kind(ParseErrorKind::Include)
+            })?;
+    }
+
+    if !options.keeps_trivia() {
+        drop_comments(&mut parsed);
+    }
+
+    Ok(parsed)
+}
+
+/// Parse the given input. Any input that can't be parsed is an error, see
+/// `parse_partial` to get the statements before it instead. Blocks nested
+/// more than `DEFAULT_MAX_DEPTH` deep are refused, see `parse_with` to set
+/// other limits.
+pub fn parse(input: &str) -> Result<Vec<JailConf<'_>>, ParseError> {
+    parse_with(input, &ParseOptions::new())
+}
+
+/// Parse the given input, refusing input which exceeds the limits set by
+/// options with an error of the matching `ParseErrorKind`. With
+/// `ParseOptions::strict`, the input is parsed by jail(8)'s own grammar.
+/// The other options say what's done with the statements once they're
+/// parsed: whether comments are kept and includes expanded, which release
+/// they're checked against, and how the columns of their spans are counted.
+pub fn parse_with<'a>(input: &'a str, options: &ParseOptions)
+-> Result<Vec<JailConf<'a>>, ParseError> {
+    if options.is_strict() {
+        limit::check(input, options)?;
+
+        let mut parsed = strict::parse(input)?;
+        span::assign_with_tabs(input, options.tabs(), &mut parsed);
+
+        return finish(input, parsed, options);
+    }
+
+    let (parsed, rest) = parse_partial_with(input, options)?;
+
+    if !rest.trim().is_empty() {
+        let point = failure_point(rest);
+        let offset = input.len() - point.len();
+
+        return Err(ParseError::new(
+            input.as_bytes(),
+            offset,
+            error::expected(point),
+        ));
+    }
+
+    debug!("parsed {} top level statements", parsed.len());
+
+    finish(input, parsed, options)
+}
+
+/// Read and parse the file at path. Errors include the path of the file.
+pub fn parse_file<P: AsRef<Path>>(path: P) -> Result<Vec<JailConfOwned>, Error> {
+    let path = path.as_ref();
+
+    let text = fs::read_to_string(path)
+        .map_err(|error| Error::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+
+    let config = parse(&text)
+        .map_err(|error| Error::Parse {
+            path: path.to_path_buf(),
+            error,
+        })?;
+
+    Ok(into_owned(config))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -243,31 +1358,35 @@ mod tests {
     // Valueless boolean params
     #[test]
     fn test_parse_bool_param_no_value() {
-        let item = "allow.mount;".into();
+        let item = "allow.mount;";
         let res = parse_bool_param_no_value(item);
         let jc = JailParamBool{
-            name: "allow.mount".into(),
+            name:   "allow.mount".into(),
+            source: "allow.mount;".into(),
+            span:   Span::default(),
         };
-        let ok = Ok(("".into(), jc));
+        let ok = Ok(("", jc));
 
         assert_eq!(res, ok);
     }
 
     #[test]
     fn test_parse_bool_param_no_value_trailing_newline() {
-        let item = "allow.mount;\n".into();
+        let item = "allow.mount;\n";
         let res = parse_bool_param_no_value(item);
         let jc = JailParamBool{
-            name: "allow.mount".into(),
+            name:   "allow.mount".into(),
+            source: "allow.mount;".into(),
+            span:   Span::default(),
         };
-        let ok = Ok(("\n".into(), jc));
+        let ok = Ok(("\n", jc));
 
         assert_eq!(res, ok);
     }
 
     #[test]
     fn test_parse_bool_param_no_value_with_value_error() {
-        let item = "allow.mount = true;".into();
+        let item = "allow.mount = true;";
         let res = parse_bool_param_no_value(item);
 
         assert!(res.is_err());
@@ -275,19 +1394,21 @@ mod tests {
 
     #[test]
     fn test_parse_bool_param_multiline_a() {
-        let item = "allow.mount;\npersist;".into();
+        let item = "allow.mount;\npersist;";
         let res = parse_bool_param_no_value(item);
         let jc = JailParamBool{
-            name: "allow.mount".into(),
+            name:   "allow.mount".into(),
+            source: "allow.mount;".into(),
+            span:   Span::default(),
         };
-        let ok = Ok(("\npersist;".into(), jc));
+        let ok = Ok(("\npersist;", jc));
 
         assert_eq!(res, ok);
     }
 
     #[test]
     fn test_parse_bool_param_multiline_error() {
-        let item = "allow.mount\n;".into();
+        let item = "allow.mount\n;";
         let res = parse_bool_param_no_value(item);
 
         assert!(res.is_err());
@@ -296,102 +1417,359 @@ mod tests {
     // Parameters with values
     #[test]
     fn test_parse_param_with_value() {
-        let item = "allow.mount = true;".into();
+        let item = "allow.mount = true;";
         let res = parse_param_with_value(item);
         let jc = JailParamValue{
             name:   "allow.mount".into(),
             value:  "true".into(),
+            raw:    "true".into(),
             append: false,
+            source: "allow.mount = true;".into(),
+            span:   Span::default(),
         };
-        let ok = Ok(("".into(), jc));
+        let ok = Ok(("", jc));
 
         assert_eq!(res, ok);
     }
 
     #[test]
     fn test_parse_param_with_quoted_value_with_space() {
-        let item = "exec.stop = \"/bin/sh /etc/rc.shutdown\";".into();
+        let item = "exec.stop = \"/bin/sh /etc/rc.shutdown\";";
         let res = parse_param_with_value(item);
         let jc = JailParamValue{
             name:  "exec.stop".into(),
             value: "/bin/sh /etc/rc.shutdown".into(),
+            raw:   r#""/bin/sh /etc/rc.shutdown""#.into(),
             append: false,
+            source: r#"exec.stop = "/bin/sh /etc/rc.shutdown";"#.into(),
+            span:   Span::default(),
         };
-        let ok = Ok(("".into(), jc));
+        let ok = Ok(("", jc));
 
         assert_eq!(res, ok);
     }
 
     #[test]
     fn test_parse_param_with_value_trailing_newline() {
-        let item = "allow.mount = true;\n".into();
+        let item = "allow.mount = true;\n";
         let res = parse_param_with_value(item);
         let jc = JailParamValue{
             name:  "allow.mount".into(),
             value: "true".into(),
+            raw:   "true".into(),
             append: false,
+            source: "allow.mount = true;".into(),
+            span:   Span::default(),
         };
-        let ok = Ok((CompleteStr("\n"), jc));
+        let ok = Ok(("\n", jc));
 
         assert_eq!(res, ok);
     }
 
     #[test]
     fn test_parse_param_with_quoted_value() {
-        let item = "allow.mount = \"true\";".into();
+        let item = "allow.mount = \"true\";";
         let res = parse_param_with_value(item);
         let jc = JailParamValue{
             name:   "allow.mount".into(),
             value:  "true".into(),
+            raw:    r#""true""#.into(),
             append: false,
+            source: r#"allow.mount = "true";"#.into(),
+            span:   Span::default(),
         };
-        let ok = Ok(("".into(), jc));
+        let ok = Ok(("", jc));
 
         assert_eq!(res, ok);
     }
 
     #[test]
     fn test_parse_param_with_quoted_emoji_value() {
-        let item = "smile.emoji = \"😊\";".into();
+        let item = "smile.emoji = \"😊\";";
         let res = parse_param_with_value(item);
         let jc = JailParamValue{
             name:   "smile.emoji".into(),
             value:  "😊".into(),
+            raw:    r#""😊""#.into(),
             append: false,
+            source: r#"smile.emoji = "😊";"#.into(),
+            span:   Span::default(),
         };
-        let ok = Ok(("".into(), jc));
+        let ok = Ok(("", jc));
 
         assert_eq!(res, ok);
     }
 
     #[test]
     fn test_parse_param_with_quoted_value_trailing_newline() {
-        let item = "allow.mount = \"true\";\n".into();
+        let item = "allow.mount = \"true\";\n";
         let res = parse_param_with_value(item);
         let jc = JailParamValue{
             name:   "allow.mount".into(),
             value:  "true".into(),
+            raw:    r#""true""#.into(),
             append: false,
+            source: r#"allow.mount = "true";"#.into(),
+            span:   Span::default(),
         };
-        let ok = Ok(("\n".into(), jc));
+        let ok = Ok(("\n", jc));
 
         assert_eq!(res, ok);
     }
 
     #[test]
     fn test_parse_param_with_quoted_value_no_spaces() {
-        let item = "allow.mount=\"true\";".into();
+        let item = "allow.mount=\"true\";";
         let res = parse_param_with_value(item);
         let jc = JailParamValue{
             name:   "allow.mount".into(),
             value:  "true".into(),
+            raw:    r#""true""#.into(),
             append: false,
+            source: r#"allow.mount="true";"#.into(),
+            span:   Span::default(),
+        };
+        let ok = Ok(("", jc));
+
+        assert_eq!(res, ok);
+    }
+
+    #[test]
+    fn test_parse_include() {
+        let item = ".include \"/etc/jail.conf.d/*.conf\";";
+        let res = parse_include(item);
+        let ji = JailInclude{
+            path:   "/etc/jail.conf.d/*.conf".into(),
+            source: ".include \"/etc/jail.conf.d/*.conf\";".into(),
+            span:   Span::default(),
         };
-        let ok = Ok(("".into(), jc));
+        let ok = Ok(("", ji));
 
         assert_eq!(res, ok);
     }
 
+    #[test]
+    fn test_parse_include_quoting() {
+        let path = |input| parse_include(input).map(|(_, include)| include.path().to_string());
+
+        assert_eq!(path(".include '/tmp/inc/*.conf';").unwrap(), "/tmp/inc/*.conf");
+        assert_eq!(path(".include \"a\\\"b\";").unwrap(), "a\"b");
+        assert_eq!(path(".include \"a\\tb\";").unwrap(), "a\tb");
+        assert_eq!(path(".include jail.conf.d/*.conf ;").unwrap(), "jail.conf.d/*.conf");
+        assert!(path(".include ;").is_err());
+        assert!(path(".include #\"q\";").is_err());
+    }
+
+    #[test]
+    fn test_parse_variable() {
+        let item = "$base += \"/usr/jails\";";
+        let res = parse_variable(item);
+        let jv = JailVariable{
+            name:   "base".into(),
+            value:  "/usr/jails".into(),
+            raw:    r#""/usr/jails""#.into(),
+            append: true,
+            source: "$base += \"/usr/jails\";".into(),
+            span:   Span::default(),
+        };
+        let ok = Ok(("", jv));
+
+        assert_eq!(res, ok);
+    }
+
+    #[test]
+    fn test_parse_param_with_escaped_quotes() {
+        let item = r#"exec.start = "echo \"hi\" \\ bye";"#;
+        let (rest, param) = parse_param_with_value(item).unwrap();
+
+        assert_eq!(rest, "");
+        assert_eq!(param.raw(), r#""echo \"hi\" \\ bye""#);
+        assert_eq!(param.value(), r#"echo "hi" \ bye"#);
+    }
+
+    #[test]
+    fn test_parse_param_with_single_quoted_value() {
+        let item = r#"exec.start = '$HOME "\n"';"#;
+        let (_, param) = parse_param_with_value(item).unwrap();
+
+        assert_eq!(param.raw(), r#"'$HOME "\n"'"#);
+        assert_eq!(param.value(), r#"$HOME "\n""#);
+        assert_eq!(param.quote_style(), QuoteStyle::Single);
+    }
+
+    #[test]
+    fn test_parse_comment_markers_in_quoted_values() {
+        let input = indoc!(r#"
+            exec.start = "echo '#hello'";
+            exec.start += "echo // not a comment";
+            exec.start += "echo /* nor this */";
+            exec.stop = 'echo #, // and /*';
+            www {
+                path = "/jails/#www"; # a comment
+                $x = "}#{";
+            }
+            "#);
+
+        let config = JailConfig::parse(input).unwrap();
+        let values: Vec<&str> = config.globals().map(|(_, value)| value).collect();
+
+        assert_eq!(values, vec![
+            "echo '#hello'",
+            "echo // not a comment",
+            "echo /* nor this */",
+            "echo #, // and /*",
+        ]);
+        assert_eq!(config.get_param("www", "path").as_deref(), Some("/jails/#www"));
+
+        let statements = config.jail("www").unwrap().params();
+        assert!(matches!(statements[1], JailConf::Comment(_)));
+        assert!(matches!(&statements[2], JailConf::Variable(var) if var.value() == "}#{"));
+
+        // The statement reader and block scanner agree.
+        let streamed: Vec<JailConf> = parse_reader(input.as_bytes())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(streamed, config.statements());
+
+        let block = scan_blocks(input).unwrap()[0].parse().unwrap();
+        assert_eq!(block.to_string(), config.jail("www").unwrap().to_string());
+    }
+
+    #[test]
+    fn test_parse_comment_precedence() {
+        let input = indoc!(r#"
+            /* exec.start = "}"; */
+            path = /jails//www;
+            exec.start = a\#b;
+            exec.stop = a//b,c; // note
+            "#);
+
+        let config = JailConfig::parse(input).unwrap();
+        let statements = config.statements();
+
+        assert!(matches!(&statements[0], JailConf::Comment(c) if c.comment() == " exec.start = \"}\"; "));
+        assert_eq!(config.globals().collect::<Vec<_>>(), vec![
+            ("path", "/jails//www"),
+            ("exec.start", "a#b"),
+            ("exec.stop", "a//b,c"),
+        ]);
+
+        // A C comment ends at the first `*/`, even within quotes.
+        assert!(parse(r#"/* a "*/" */"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_param_with_line_continuation() {
+        let input = indoc!(r#"
+            nginx {
+                exec.start = "/usr/local/bin/nginx \
+                    -c /usr/local/etc/nginx/nginx.conf";
+                exec.stop = \
+                    "/usr/local/bin/nginx" \
+                    " -s stop" \
+                    ;
+                exec.poststop = /usr/local/bin/\
+            cleanup;
+            }
+            "#);
+
+        let res = parse(input).unwrap();
+        let block = match &res[..] {
+            [JailConf::Block(block)] => block,
+            res                      => panic!("expected a block, got {:?}", res),
+        };
+
+        let values: Vec<&str> = block.params()
+            .iter()
+            .filter_map(|param| match param {
+                JailConf::ParamValue(param) => Some(param.value()),
+                _                           => None,
+            })
+            .collect();
+
+        assert_eq!(values, vec![
+            "/usr/local/bin/nginx         -c /usr/local/etc/nginx/nginx.conf",
+            "/usr/local/bin/nginx -s stop",
+            "/usr/local/bin/cleanup",
+        ]);
+
+        match &block.params()[1] {
+            JailConf::ParamValue(param) => {
+                assert_eq!(param.fragments(), vec![r#""/usr/local/bin/nginx""#, r#"" -s stop""#]);
+                assert_eq!(param.span().start().line(), 4);
+                assert_eq!(param.span().end().line(), 7);
+            },
+            _ => panic!("expected a parameter"),
+        }
+
+        let item = "path = /a \\\r\n    /b;";
+        let (_, param) = parse_param_with_value(item).unwrap();
+
+        assert_eq!(param.value(), "/a/b");
+    }
+
+    #[test]
+    fn test_parse_param_with_adjacent_strings() {
+        let item = r#"exec.start = "/bin/sh " "/etc/rc" ;"#;
+        let (rest, param) = parse_param_with_value(item).unwrap();
+
+        assert_eq!(rest, "");
+        assert_eq!(param.raw(), r#""/bin/sh " "/etc/rc""#);
+        assert_eq!(param.value(), "/bin/sh /etc/rc");
+        assert_eq!(param.fragments(), vec![r#""/bin/sh ""#, r#""/etc/rc""#]);
+        assert_eq!(param.source_text(), item);
+
+        let item = r#"path = /usr/jails/"my jail"'$1';"#;
+        let (_, param) = parse_param_with_value(item).unwrap();
+
+        assert_eq!(param.value(), "/usr/jails/my jail$1");
+        assert_eq!(param.fragments(), vec!["/usr/jails/", r#""my jail""#, "'$1'"]);
+        assert_eq!(param.quote_style(), QuoteStyle::None);
+
+        let (_, var) = parse_variable(r#"$cmd = 'a' "b";"#).unwrap();
+
+        assert_eq!(var.value(), "ab");
+        assert_eq!(var.quote_style(), QuoteStyle::Single);
+    }
+
+    #[test]
+    fn test_param_value_raw_and_value() {
+        let item = "host.hostname = \"nginx\";";
+        let (_, param) = parse_param_with_value(item).unwrap();
+
+        assert_eq!(param.raw(), "\"nginx\"");
+        assert_eq!(param.value(), "nginx");
+    }
+
+    #[test]
+    fn test_parse_param_with_unquoted_values() {
+        let tests = vec![
+            ("path = /usr/jails/nginx;", "/usr/jails/nginx"),
+            ("mount.fstab = /etc/fstab.nginx;", "/etc/fstab.nginx"),
+            ("securelevel = 3;", "3"),
+            ("ip4.addr = lo1|127.0.1.1/32;", "lo1|127.0.1.1/32"),
+            ("ip6.addr = em0|2001:db8::1/64;", "em0|2001:db8::1/64"),
+            ("devfs_ruleset = ~4;", "~4"),
+            ("path = /jails/${name}-data;", "/jails/${name}-data"),
+            ("exec.start = a\\ b;", "a b"),
+            ("host.hostname = nginx.example.org ;", "nginx.example.org"),
+        ];
+
+        for (item, value) in tests {
+            let (rest, param) = parse_param_with_value(item).unwrap();
+
+            assert_eq!(rest, "", "{}", item);
+            assert_eq!(param.value(), value, "{}", item);
+            assert_eq!(param.quote_style(), QuoteStyle::None, "{}", item);
+        }
+
+        // Characters outside of the unquoted set must be quoted.
+        assert!(parse_param_with_value("path = /a{b;").is_err());
+        assert!(parse_param_with_value("path = a=b;").is_err());
+        assert!(parse_param_with_value("path = a#b;").is_err());
+        assert!(parse_param_with_value("path = ${a;").is_err());
+    }
+
     // Integration testing, testing the main input parser.
     #[test]
     fn test_parse_input_no_blocks() {
@@ -402,28 +1780,38 @@ mod tests {
             exec.stop = "/bin/sh /etc/rc.shutdown";
             "#);
 
-        let res = parse_input(input.into());
+        let res = parse_input(input);
 
         let jc = vec![
             JailConf::ParamBool(JailParamBool{
-                name: "allow.mount".into(),
+                name:   "allow.mount".into(),
+                source: "allow.mount;".into(),
+                span:   Span::default(),
             }),
             JailConf::ParamBool(JailParamBool{
-                name: "persist".into(),
+                name:   "persist".into(),
+                source: "persist;".into(),
+                span:   Span::default(),
             }),
             JailConf::ParamValue(JailParamValue{
                 name:   "allow.raw_sockets".into(),
                 value:  "1".into(),
+                raw:    r#""1""#.into(),
                 append: false,
+                source: r#"allow.raw_sockets = "1";"#.into(),
+                span:   Span::default(),
             }),
             JailConf::ParamValue(JailParamValue{
                 name:   "exec.stop".into(),
                 value:  "/bin/sh /etc/rc.shutdown".into(),
+                raw:    r#""/bin/sh /etc/rc.shutdown""#.into(),
                 append: false,
+                source: r#"exec.stop = "/bin/sh /etc/rc.shutdown";"#.into(),
+                span:   Span::default(),
             }),
         ];
 
-        let ok = Ok(("".into(), jc));
+        let ok = Ok(("", jc));
 
         assert_eq!(res, ok);
     }
@@ -437,19 +1825,26 @@ mod tests {
             }
             "#);
 
-        let res = parse_block(input.into());
+        let res = parse_block(input);
         let jc = JailConf::Block(JailBlock{
             name: "nginx".into(),
+            quote: QuoteStyle::None,
             params: vec![
                 JailConf::ParamValue(JailParamValue{
                     name:   "host.hostname".into(),
                     value:  "nginx".into(),
+                    raw:    r#""nginx""#.into(),
                     append: false,
+                    source: r#"host.hostname = "nginx";"#.into(),
+                    span:   Span::default(),
                 }),
             ],
+            annotations: Annotations::new(),
+            source: input.trim_end().into(),
+            span:   Span::default(),
         });
 
-        let ok = Ok((CompleteStr("\n"), jc));
+        let ok = Ok(("\n", jc));
         assert_eq!(res, ok);
     }
 
@@ -477,73 +1872,110 @@ mod tests {
             }
             "#);
 
-        let res = parse_input(input.into());
+        let res = parse_input(input);
         let jc = vec![
             JailConf::Comment(JailComment{
                 comment: "\n * Opening C style comment\n ".into(),
                 style:   CommentStyle::C,
+                source:  "/*\n * Opening C style comment\n */".into(),
+                span:    Span::default(),
             }),
             JailConf::ParamBool(JailParamBool{
-                name: "allow.mount".into(),
+                name:   "allow.mount".into(),
+                source: "allow.mount;".into(),
+                span:   Span::default(),
             }),
             JailConf::Comment(JailComment{
                 comment: " Allow mounting".into(),
                 style:   CommentStyle::CPP,
+                source:  "// Allow mounting".into(),
+                span:    Span::default(),
             }),
             JailConf::ParamBool(JailParamBool{
-                name: "persist".into(),
+                name:   "persist".into(),
+                source: "persist;".into(),
+                span:   Span::default(),
             }),
             JailConf::Comment(JailComment{
                 comment: " Persist jail ".into(),
                 style:   CommentStyle::C,
+                source:  "/* Persist jail */".into(),
+                span:    Span::default(),
             }),
             JailConf::ParamValue(JailParamValue{
                 name:   "allow.raw_sockets".into(),
                 value:  "1".into(),
+                raw:    r#""1""#.into(),
                 append: false,
+                source: r#"allow.raw_sockets = "1";"#.into(),
+                span:   Span::default(),
             }),
             JailConf::Comment(JailComment{
                 comment: " Allow raw sockets".into(),
                 style:   CommentStyle::Shell,
+                source:  "# Allow raw sockets".into(),
+                span:    Span::default(),
             }),
             JailConf::ParamValue(JailParamValue{
                 name:   "exec.stop".into(),
                 value:  "/bin/sh /etc/rc.shutdown".into(),
+                raw:    r#""/bin/sh /etc/rc.shutdown""#.into(),
                 append: false,
+                source: r#"exec.stop = "/bin/sh /etc/rc.shutdown";"#.into(),
+                span:   Span::default(),
             }),
             JailConf::Comment(JailComment{
                 comment: " CPP style comment".into(),
                 style:   CommentStyle::CPP,
+                source:  "// CPP style comment".into(),
+                span:    Span::default(),
             }),
             JailConf::Block(JailBlock{
                 name:  "nginx".into(),
+                quote: QuoteStyle::None,
                 params: vec![
                     JailConf::Comment(JailComment{
                         comment: " Shell style comment".into(),
                         style:   CommentStyle::Shell,
+                        source:  "# Shell style comment".into(),
+                        span:    Span::default(),
                     }),
                     JailConf::ParamValue(JailParamValue{
                         name:   "host.hostname".into(),
                         value:  "nginx".into(),
+                        raw:    r#""nginx""#.into(),
                         append: false,
+                        source: r#"host.hostname = "nginx";"#.into(),
+                        span:   Span::default(),
                     }),
                 ],
+                annotations: Annotations::new(),
+                source: "nginx {\n    # Shell style comment\n    host.hostname = \"nginx\";\n}".into(),
+                span:   Span::default(),
             }),
             JailConf::Comment(JailComment{
                 comment: " Multiple jails could be configured".into(),
                 style:   CommentStyle::Shell,
+                source:  "# Multiple jails could be configured".into(),
+                span:    Span::default(),
             }),
             JailConf::Block(JailBlock{
                 name:  "jail2".into(),
+                quote: QuoteStyle::None,
                 params: vec![
                     JailConf::ParamBool(JailParamBool{
-                        name: "persist".into(),
+                        name:   "persist".into(),
+                        source: "persist;".into(),
+                        span:   Span::default(),
                     }),
                 ],
+                annotations: Annotations::new(),
+                source: "jail2 {\n    persist;\n}".into(),
+                span:   Span::default(),
             }),
         ];
 
-        let ok = Ok(("".into(), jc));
+        let ok = Ok(("", jc));
         assert_eq!(res, ok);
     }
 
@@ -576,120 +2008,440 @@ mod tests {
             }
             "#);
 
-        let res = parse_block(input.into());
+        let res = parse_block(input);
         let jc = JailConf::Block(JailBlock{
             name:   "nginx".into(),
+            quote:  QuoteStyle::None,
             params: vec![
                 JailConf::ParamValue(JailParamValue{
                     name:   "host.hostname".into(),
                     value:  "nginx".into(),
+                    raw:    r#""nginx""#.into(),
                     append: false,
+                    source: r#"host.hostname = "nginx";"#.into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamValue(JailParamValue{
                     name:   "path".into(),
                     value:  "/usr/jails/nginx".into(),
+                    raw:    r#""/usr/jails/nginx""#.into(),
                     append: false,
+                    source: r#"path = "/usr/jails/nginx";"#.into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamValue(JailParamValue{
                     name:   "ip4.addr".into(),
                     value:  "lo1|127.0.1.1/32".into(),
+                    raw:    r#""lo1|127.0.1.1/32""#.into(),
                     append: false,
+                    source: r#"ip4.addr = "lo1|127.0.1.1/32";"#.into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamValue(JailParamValue{
                     name:   "ip6.addr".into(),
                     value:  "lo1|fd00:0:0:1::1/64".into(),
+                    raw:    r#""lo1|fd00:0:0:1::1/64""#.into(),
                     append: true,
+                    source: r#"ip6.addr += "lo1|fd00:0:0:1::1/64";"#.into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamValue(JailParamValue{
                     name:   "ip4.addr".into(),
                     value:  "em0|192.168.5.1/32".into(),
+                    raw:    r#""em0|192.168.5.1/32""#.into(),
                     append: true,
+                    source: r#"ip4.addr += "em0|192.168.5.1/32";"#.into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamValue(JailParamValue{
                     name:   "exec.start".into(),
                     value:  "sleep  2 ".into(),
+                    raw:    r#""sleep  2 ""#.into(),
                     append: true,
+                    source: r#"exec.start += "sleep  2 ";"#.into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamValue(JailParamValue{
                     name:   "allow.raw_sockets".into(),
                     value:  "0".into(),
+                    raw:    "0".into(),
                     append: false,
+                    source: "allow.raw_sockets = 0;".into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamBool(JailParamBool{
-                    name: "exec.clean".into(),
+                    name:   "exec.clean".into(),
+                    source: "exec.clean;".into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamValue(JailParamValue{
                     name:   "exec.system_user".into(),
                     value:  "root".into(),
+                    raw:    r#""root""#.into(),
                     append: false,
+                    source: r#"exec.system_user = "root";"#.into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamValue(JailParamValue{
                     name:   "exec.jail_user".into(),
                     value:  "root".into(),
+                    raw:    r#""root""#.into(),
                     append: false,
+                    source: r#"exec.jail_user = "root";"#.into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamValue(JailParamValue{
                     name:   "exec.start".into(),
                     value:  "/bin/sh /etc/rc".into(),
+                    raw:    r#""/bin/sh /etc/rc""#.into(),
                     append: true,
+                    source: r#"exec.start += "/bin/sh /etc/rc";"#.into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamValue(JailParamValue{
                     name:   "exec.stop".into(),
                     value:  "".into(),
+                    raw:    r#""""#.into(),
                     append: false,
+                    source: r#"exec.stop = "";"#.into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamValue(JailParamValue{
                     name:   "exec.consolelog".into(),
                     value:  "/var/log/jail_nginx_console.log".into(),
+                    raw:    r#""/var/log/jail_nginx_console.log""#.into(),
                     append: false,
+                    source: r#"exec.consolelog = "/var/log/jail_nginx_console.log";"#.into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamValue(JailParamValue{
                     name:   "mount.fstab".into(),
                     value:  "/etc/fstab.nginx".into(),
+                    raw:    r#""/etc/fstab.nginx""#.into(),
                     append: false,
+                    source: r#"mount.fstab = "/etc/fstab.nginx";"#.into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamBool(JailParamBool{
-                    name: "mount.devfs".into(),
+                    name:   "mount.devfs".into(),
+                    source: "mount.devfs;".into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamBool(JailParamBool{
-                    name: "mount.fdescfs".into(),
+                    name:   "mount.fdescfs".into(),
+                    source: "mount.fdescfs;".into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamBool(JailParamBool{
-                    name: "mount.procfs".into(),
+                    name:   "mount.procfs".into(),
+                    source: "mount.procfs;".into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamBool(JailParamBool{
-                    name: "allow.mount".into(),
+                    name:   "allow.mount".into(),
+                    source: "allow.mount;".into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamValue(JailParamValue{
                     name:   "allow.set_hostname".into(),
                     value:  "0".into(),
+                    raw:    "0".into(),
                     append: false,
+                    source: "allow.set_hostname = 0;".into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamValue(JailParamValue{
                     name:   "allow.sysvipc".into(),
                     value:  "0".into(),
+                    raw:    "0".into(),
                     append: false,
+                    source: "allow.sysvipc = 0;".into(),
+                    span:   Span::default(),
                 }),
                 JailConf::ParamValue(JailParamValue{
                     name:   "enforce_statfs".into(),
                     value:  "2".into(),
+                    raw:    r#""2""#.into(),
                     append: false,
+                    source: r#"enforce_statfs = "2";"#.into(),
+                    span:   Span::default(),
                 }),
             ],
+            annotations: Annotations::new(),
+            source: input.trim_end().into(),
+            span:   Span::default(),
         });
 
-        let ok = Ok(("\n".into(), jc));
+        let ok = Ok(("\n", jc));
 
         assert_eq!(res, ok);
     }
 
+    // The example configuration from the README, parsed in full.
+    #[test]
+    fn test_parse_example_fixture() {
+        let input = include_str!("../jail.ioc-test-jail.conf");
+        let res = parse(input).unwrap();
+
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].source_text(), input.trim_end());
+
+        let block = match &res[0] {
+            JailConf::Block(block) => block,
+            _                      => panic!("expected a block"),
+        };
+
+        assert_eq!(block.name(), "ioc-test-jail");
+
+        // Every statement is on its own line, and is recorded exactly.
+        let lines: Vec<&str> = input.lines()
+            .skip(1)
+            .take_while(|line| *line != "}")
+            .map(str::trim)
+            .collect();
+        let sources: Vec<&str> = block.params()
+            .iter()
+            .map(JailConf::source_text)
+            .collect();
+
+        assert_eq!(sources, lines);
+
+        match (&block.params()[0], &block.params()[26]) {
+            (JailConf::ParamValue(addr), JailConf::ParamBool(persist)) => {
+                assert_eq!(addr.name(), "ip4.addr");
+                assert_eq!(addr.value(), "lo1|127.0.1.1/32");
+                assert!(addr.is_append());
+                assert_eq!(persist.name(), "persist");
+            },
+            _ => panic!("unexpected block contents"),
+        }
+
+        // Rendering and parsing again changes nothing that matters.
+        let output = to_string(&res);
+        assert!(equivalent(&parse(&output).unwrap(), &res));
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("jailconf-parse-file-{}.conf", std::process::id()));
+
+        fs::write(&path, "www {\n    persist;\n}\n").unwrap();
+        let config = parse_file(&path).unwrap();
+        assert_eq!(config.len(), 1);
+
+        fs::write(&path, "www {\n    persist\n}\n").unwrap();
+        let err = parse_file(&path).unwrap_err();
+        let expected = "error at line 2, column 5: expected ';' after parameter name";
+        assert_eq!(err.to_string(), format!("{}: {}", path.display(), expected));
+
+        fs::remove_file(&path).unwrap();
+        match parse_file(&path) {
+            Err(Error::Io { path: missing, .. }) => assert_eq!(missing, path),
+            other => panic!("expected an IO error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_trailing_input_is_err() {
+        let input = "persist;\nnginx {\n    path = \"/a\"\n}\n";
+        let err = parse(input).unwrap_err();
+
+        assert_eq!(err.line(), 3);
+        assert_eq!(err.column(), 5);
+        assert_eq!(err.fragment(), "path = \"/a\"");
+        assert_eq!(err.expected(), "';' after parameter value");
+    }
+
+    #[test]
+    fn test_parse_partial() {
+        let input = "persist;\nallow.mount\n";
+        let (res, rest) = parse_partial(input).unwrap();
+
+        assert_eq!(res.len(), 1);
+        assert_eq!(rest, "allow.mount\n");
+        assert!(parse(input).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let input = "# Web\nnginx {\n    ip4.addr += \"127.0.1.1\";\n    persist;\n}\n";
+        let config = parse(input).unwrap();
+        let json = serde_json::to_string(&config).unwrap();
+
+        assert_eq!(
+            json,
+            concat!(
+                r#"[{"type":"comment","comment":" Web","style":"shell"},"#,
+                r#"{"type":"block","name":"nginx","params":["#,
+                r#"{"type":"param_value","name":"ip4.addr","value":"127.0.1.1","#,
+                r#""raw":"\"127.0.1.1\"","append":true},"#,
+                r#"{"type":"param_bool","name":"persist"}]}]"#,
+            ),
+        );
+
+        let decoded: Vec<JailConf> = serde_json::from_str(&json).unwrap();
+        assert_eq!(to_string(&decoded), to_string(&config));
+    }
+
+    #[test]
+    fn test_parse_statements_sharing_a_line() {
+        let input = "persist; allow.mount ;\nnginx { ip4.addr = 127.0.1.1; persist; } empty {\n}\n# EOF";
+        let res = parse(input).unwrap();
+
+        assert_eq!(res.len(), 5);
+
+        match &res[2] {
+            JailConf::Block(block) => {
+                assert_eq!(block.name(), "nginx");
+                assert_eq!(block.params().len(), 2);
+                assert_eq!(block.source_text(), "nginx { ip4.addr = 127.0.1.1; persist; }");
+            },
+            _ => panic!("expected a block"),
+        }
+
+        match &res[3] {
+            JailConf::Block(block) => assert!(block.params().is_empty()),
+            _                      => panic!("expected a block"),
+        }
+
+        match &res[4] {
+            JailConf::Comment(comment) => assert_eq!(comment.comment(), " EOF"),
+            _                          => panic!("expected a comment"),
+        }
+    }
+
+    #[test]
+    fn test_parse_quoted_block_name() {
+        let input = "\"my-jail.example.com\" {\n    persist;\n}\nweb/01.example { persist; }\n";
+        let res = parse(input).unwrap();
+
+        match &res[..] {
+            [JailConf::Block(quoted), JailConf::Block(bare)] => {
+                assert_eq!(quoted.name(), "my-jail.example.com");
+                assert_eq!(quoted.name_quote_style(), QuoteStyle::Double);
+                assert_eq!(bare.name(), "web/01.example");
+                assert_eq!(bare.name_quote_style(), QuoteStyle::None);
+            },
+            _ => panic!("expected two blocks"),
+        }
+    }
+
+    #[test]
+    fn test_parse_borrows_from_input() {
+        let input = indoc!(r#"
+            # Defaults
+            persist;
+            www {
+                path = "/jails/www";
+                exec.start = "/bin/sh" " /etc/rc";
+                host.hostname = "www\tone";
+            }
+            "#);
+
+        let res = parse(input).unwrap();
+
+        assert!(res[0].is_borrowed());
+        assert!(res[1].is_borrowed());
+        assert!(!res[2].is_borrowed());
+
+        let block = match &res[2] {
+            JailConf::Block(block) => block,
+            _                      => panic!("expected a block"),
+        };
+
+        assert!(matches!(block.name_cow(), Cow::Borrowed("www")));
+
+        let values: Vec<bool> = block.params().iter()
+            .map(|param| match param {
+                JailConf::ParamValue(p) => matches!(p.value_cow(), Cow::Borrowed(_)),
+                _                       => panic!("expected a value"),
+            })
+            .collect();
+
+        assert_eq!(values, vec![true, false, false]);
+    }
+
+    #[test]
+    fn test_parse_statement_dispatch() {
+        let kind = |input| match parse_statement(input) {
+            Ok((_, JailConf::Block(_)))      => "block",
+            Ok((_, JailConf::Comment(_)))    => "comment",
+            Ok((_, JailConf::Include(_)))    => "include",
+            Ok((_, JailConf::ParamBool(_)))  => "bool",
+            Ok((_, JailConf::ParamValue(_))) => "value",
+            Ok((_, JailConf::Variable(_)))   => "variable",
+            Err(_)                           => "error",
+        };
+
+        assert_eq!(kind("/* c */ persist;"), "comment");
+        assert_eq!(kind("/*x;"), "error");
+        assert_eq!(kind(".include \"a\";"), "include");
+        assert_eq!(kind(".hidden = 1;"), "value");
+        assert_eq!(kind("$base = /jails;"), "variable");
+        assert_eq!(kind("$base { }"), "block");
+        assert_eq!(kind("'web' { }"), "block");
+        assert_eq!(kind("persist ;"), "bool");
+        assert_eq!(kind("path += /a;"), "value");
+        assert_eq!(kind("\"a\" = 1;"), "error");
+    }
+
+    #[test]
+    fn test_source_text_preserves_formatting() {
+        let input = "nginx   {\n  persist;\n\thost.hostname=\"nginx\";\n}";
+        let res = parse(input).unwrap();
+
+        assert_eq!(res[0].source_text(), input);
+
+        if let JailConf::Block(block) = &res[0] {
+            assert_eq!(block.params[0].source_text(), "persist;");
+            assert_eq!(block.params[1].source_text(), "host.hostname=\"nginx\";");
+        }
+        else {
+            panic!("expected a block");
+        }
+    }
+
+    #[test]
+    fn test_public_accessors() {
+        let input = indoc!(r#"
+            nginx {
+                # Comment
+                persist;
+                ip4.addr += "127.0.1.1";
+            }
+            "#);
+
+        let res = parse(input).unwrap();
+        let block = match &res[0] {
+            JailConf::Block(block) => block,
+            _                      => panic!("expected a block"),
+        };
+
+        assert_eq!(block.name(), "nginx");
+        assert_eq!(block.params().len(), 3);
+
+        match block.params() {
+            [JailConf::Comment(comment), JailConf::ParamBool(bool_param), JailConf::ParamValue(param)] => {
+                assert_eq!(comment.comment(), " Comment");
+                assert_eq!(comment.style(), CommentStyle::Shell);
+                assert_eq!(bool_param.name(), "persist");
+                assert_eq!(param.name(), "ip4.addr");
+                assert_eq!(param.value(), "127.0.1.1");
+                assert!(param.is_append());
+            },
+            _ => panic!("unexpected block contents"),
+        }
+    }
+
     #[test]
     fn test_parse_block_with_invalid_semicolon_is_err() {
         let input = indoc!(r#"invalid; {
                 persist;
             }"#);
 
-        let res = parse_block(input.into());
+        let res = parse_block(input);
         assert!(res.is_err());
     }
 
@@ -700,7 +2452,7 @@ mod tests {
                 persist;
             }"#);
 
-        let res = parse_block(input.into());
+        let res = parse_block(input);
         assert!(res.is_err());
     }
 
@@ -712,13 +2464,15 @@ mod tests {
              */
             "#);
 
-        let res = parse_comment_c_style(input.into());
+        let res = parse_comment_c_style(input);
         let jc = JailComment{
             comment: "\n * Test comment\n ".into(),
             style:   CommentStyle::C,
+            source:  "/*\n * Test comment\n */".into(),
+            span:    Span::default(),
         };
 
-        let ok = Ok(("\n".into(), jc));
+        let ok = Ok(("\n", jc));
 
         assert_eq!(res, ok);
     }
@@ -729,13 +2483,15 @@ mod tests {
             // CPP style comment
             "#);
 
-        let res = parse_comment_cpp_style(input.into());
+        let res = parse_comment_cpp_style(input);
         let jc = JailComment{
             comment: " CPP style comment".into(),
             style:   CommentStyle::CPP,
+            source:  "// CPP style comment".into(),
+            span:    Span::default(),
         };
 
-        let ok = Ok(("\n".into(), jc));
+        let ok = Ok(("\n", jc));
 
         assert_eq!(res, ok);
     }
@@ -746,14 +2502,59 @@ mod tests {
             # Shell style comment
             "#);
 
-        let res = parse_comment_shell_style(input.into());
+        let res = parse_comment_shell_style(input);
         let jc = JailComment{
             comment: " Shell style comment".into(),
             style:   CommentStyle::Shell,
+            source:  "# Shell style comment".into(),
+            span:    Span::default(),
         };
 
-        let ok = Ok(("\n".into(), jc));
+        let ok = Ok(("\n", jc));
 
         assert_eq!(res, ok);
     }
+
+    #[test]
+    fn test_parse_comment_at_eof() {
+        for (input, style) in &[
+            ("# last comment", CommentStyle::Shell),
+            ("// last comment", CommentStyle::CPP),
+        ] {
+            let input_with_param = format!("persist;\n{}", input);
+            let res = parse(&input_with_param).unwrap();
+
+            match &res[..] {
+                [JailConf::ParamBool(_), JailConf::Comment(comment)] => {
+                    assert_eq!(comment.comment(), " last comment");
+                    assert_eq!(comment.style(), *style);
+                    assert_eq!(comment.source_text(), *input);
+                },
+                res => panic!("expected a parameter and a comment, got {:?}", res),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_comment_at_end_of_block() {
+        for input in &["nginx {\n    persist; # last\n}", "nginx { persist;\n// last\n}"] {
+            let res = parse(input).unwrap();
+
+            match &res[..] {
+                [JailConf::Block(block)] => match block.params() {
+                    [JailConf::ParamBool(_), JailConf::Comment(comment)] => {
+                        assert_eq!(comment.comment(), " last");
+                    },
+                    params => panic!("expected a parameter and a comment, got {:?}", params),
+                },
+                res => panic!("expected a block, got {:?}", res),
+            }
+        }
+
+        // The block's contents end with the comment, without a newline.
+        let (rest, params) = parse_input("persist; # last").unwrap();
+
+        assert_eq!(rest, "");
+        assert_eq!(params.len(), 2);
+    }
 }
@@ -0,0 +1,271 @@
+// Limits on the input the parser accepts.
+//
+// Blocks are parsed by recursion, taking stack for each level of nesting, so
+// input with thousands of nested blocks would overflow the stack and abort
+// the process. Before parsing, the input is scanned without recursion for
+// its deepest nesting, and input nested deeper than the limit is refused
+// with a ParseError instead. Services parsing untrusted configuration can
+// also limit the size of the input they'll look at.
+//
+// ParseOptions also holds the options for what parse_with does with the
+// statements once they're parsed, see the builders for each.
+use std::path::{
+    Path,
+    PathBuf,
+};
+use crate::error::ParseErrorKind;
+use crate::scan::{
+    Found,
+    Scanner,
+};
+use crate::schema::Version;
+use crate::ParseError;
+
+/// The deepest nesting of blocks accepted by default. jail(8) itself only
+/// nests blocks for child jails, so real configurations are far shallower.
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+/// Options controlling `parse_with`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseOptions {
+    max_depth: usize,
+    max_size:  Option<usize>,
+    strict:    bool,
+    trivia:    bool,
+    includes:  Option<PathBuf>,
+    version:   Option<Version>,
+    tab_width: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_size:  None,
+            strict:    false,
+            trivia:    true,
+            includes:  None,
+            version:   None,
+            tab_width: 1,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// The default options: blocks nested at most `DEFAULT_MAX_DEPTH` deep,
+    /// input of any size, and the default, lenient, grammar, keeping
+    /// comments and includes as they are, with columns counted in bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The deepest nesting of blocks to accept, with 1 allowing blocks but
+    /// no blocks within them.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// The largest input to accept, in bytes.
+    pub fn max_size(mut self, bytes: usize) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Whether to parse by jail(8)'s own grammar, accepting exactly what it
+    /// accepts. See the strict module for how this differs from the default.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Whether to keep comments. Without them the statements are only
+    /// those jail(8) acts on, though each block's source text still has the
+    /// comments within it.
+    pub fn preserve_trivia(mut self, preserve: bool) -> Self {
+        self.trivia = preserve;
+        self
+    }
+
+    /// Expand `.include` directives, as `parse_with_includes` does, with
+    /// relative patterns relative to dir. An include which can't be
+    /// expanded is refused with a `ParseErrorKind::Include` error at the
+    /// directive.
+    pub fn resolve_includes<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.includes = Some(dir.into());
+        self
+    }
+
+    /// Refuse parameters the given FreeBSD release doesn't have yet with a
+    /// `ParseErrorKind::Unavailable` error, where `JailConfig::validate_for`
+    /// would only warn of them. Parameters no release knows are accepted,
+    /// and those of included files aren't checked.
+    pub fn target_version(mut self, version: Version) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// The width of a tab when counting the columns of spans, moving the
+    /// column on to the next multiple of width. The default of 1 counts a
+    /// tab as one column, like any other byte. Error columns are always in
+    /// bytes.
+    pub fn tab_width(mut self, width: usize) -> Self {
+        self.tab_width = width.max(1);
+        self
+    }
+
+    pub(crate) fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    pub(crate) fn keeps_trivia(&self) -> bool {
+        self.trivia
+    }
+
+    pub(crate) fn include_dir(&self) -> Option<&Path> {
+        self.includes.as_deref()
+    }
+
+    pub(crate) fn version(&self) -> Option<Version> {
+        self.version
+    }
+
+    pub(crate) fn tabs(&self) -> usize {
+        self.tab_width
+    }
+}
+
+// The position of the first opening brace nested deeper than max_depth.
+fn too_deep(input: &str, max_depth: usize) -> Option<usize> {
+    let mut depth: usize = 0;
+
+    for piece in Scanner::new(input.as_bytes(), 0) {
+        match piece {
+            Ok((pos, Found::Byte(b'{'))) => {
+                depth += 1;
+
+                if depth > max_depth {
+                    return Some(pos);
+                }
+            },
+            Ok((_, Found::Byte(b'}'))) => depth = depth.saturating_sub(1),
+            Ok(_)  => {},
+            // The parser reports the unterminated comment or string.
+            Err(_) => return None,
+        }
+    }
+
+    None
+}
+
+// Check input against the limits before it's parsed.
+pub(crate) fn check(input: &str, options: &ParseOptions) -> Result<(), ParseError> {
+    let bytes = input.as_bytes();
+
+    if let Some(limit) = options.max_size {
+        if input.len() > limit {
+            let expected = format!("at most {} bytes of input", limit);
+
+            return Err(ParseError::new(bytes, limit, &expected)
+                .with_kind(ParseErrorKind::TooLarge { limit }));
+        }
+    }
+
+    if let Some(pos) = too_deep(input, options.max_depth) {
+        let limit = options.max_depth;
+        let expected = format!("blocks nested at most {} deep", limit);
+
+        return Err(ParseError::new(bytes, pos, &expected)
+            .with_kind(ParseErrorKind::TooDeep { limit }));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parse,
+        parse_with,
+        JailConf,
+    };
+
+    fn nested(depth: usize) -> String {
+        format!("{}persist;{}", "a {".repeat(depth), "}".repeat(depth))
+    }
+
+    #[test]
+    fn test_too_deep() {
+        assert_eq!(too_deep("a { b { } } c { }", 2), None);
+        assert_eq!(too_deep("a { b { } } c { }", 1), Some(6));
+        assert_eq!(too_deep("a { path = \"{{\"; x = ${y}; # {\n }", 1), None);
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        assert!(parse(&nested(DEFAULT_MAX_DEPTH)).is_ok());
+
+        // Deep enough to overflow the stack if it were parsed.
+        let input = nested(100_000);
+        let error = parse(&input).unwrap_err();
+
+        assert_eq!(error.kind(), ParseErrorKind::TooDeep { limit: DEFAULT_MAX_DEPTH });
+        assert_eq!(error.offset(), DEFAULT_MAX_DEPTH * 3 + 2);
+
+        let options = ParseOptions::new().max_depth(1);
+        assert!(parse_with("a { persist; }", &options).is_ok());
+        assert!(parse_with(&nested(2), &options).is_err());
+    }
+
+    #[test]
+    fn test_size_limit() {
+        let options = ParseOptions::new().max_size(8);
+        let error = parse_with("persist; allow.mount;", &options).unwrap_err();
+
+        assert_eq!(error.kind(), ParseErrorKind::TooLarge { limit: 8 });
+        assert_eq!(error.to_string(), "error at line 1, column 9: expected at most 8 bytes of input");
+        assert!(parse_with("persist;", &options).is_ok());
+    }
+
+    #[test]
+    fn test_preserve_trivia() {
+        let input = "# Web.\nwww {\n    persist; # Always.\n}\n";
+        let options = ParseOptions::new().preserve_trivia(false);
+        let parsed = parse_with(input, &options).unwrap();
+
+        assert_eq!(parsed.len(), 1);
+        match &parsed[0] {
+            JailConf::Block(block) => assert_eq!(block.params().len(), 1),
+            _                      => panic!("expected a block"),
+        }
+        assert_eq!(parse(input).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_target_version() {
+        let input = "persist;\nwww {\n    allow.routing;\n    no.such.param;\n}\n";
+        let options = ParseOptions::new().target_version(Version::FreeBSD14_0);
+        let error = parse_with(input, &options).unwrap_err();
+
+        assert_eq!(error.kind(), ParseErrorKind::Unavailable { version: Version::FreeBSD14_0 });
+        assert_eq!(error.to_string(), "error at line 3, column 5: expected a parameter available on FreeBSD 14.0");
+
+        let options = options.target_version(Version::FreeBSD14_1);
+        assert!(parse_with(input, &options).is_ok());
+    }
+
+    #[test]
+    fn test_tab_width() {
+        let input = "www {\n\tpersist;\n}\n";
+        let options = ParseOptions::new().tab_width(8);
+
+        let column = |parsed: &[JailConf]| match &parsed[0] {
+            JailConf::Block(block) => block.params()[0].span().start().column(),
+            _                      => panic!("expected a block"),
+        };
+
+        assert_eq!(column(&parse_with(input, &options).unwrap()), 9);
+        assert_eq!(column(&parse(input).unwrap()), 2);
+    }
+}
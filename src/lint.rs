@@ -0,0 +1,340 @@
+// Lints for configuration which parses, but probably doesn't do what was
+// intended.
+//
+// jail(8) accepts a parameter being assigned several times, with the last
+// assignment silently winning, including a boolean being set by both its
+// positive and "no" forms, eg. `allow.mount;` and `allow.nomount;`, and
+// accepts parameters which have no effect in combination with others. Each
+// such case found is returned as a Lint with a severity, so tools can
+// decide which ones to fail on.
+use std::fmt;
+use crate::name::{
+    boolean,
+    negate,
+};
+use crate::schema::{
+    parameter,
+    ParamType,
+};
+use crate::value::parse_bool;
+use crate::vnet;
+use crate::{
+    JailConf,
+    JailConfig,
+    Span,
+};
+
+/// How serious a lint is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Worth knowing about, but likely intended.
+    Info,
+    /// Probably a mistake.
+    Warning,
+    /// Almost certainly a mistake.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self {
+            Severity::Info    => "info",
+            Severity::Warning => "warning",
+            Severity::Error   => "error",
+        };
+
+        f.write_str(severity)
+    }
+}
+
+/// A problem found with a configuration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lint {
+    severity: Severity,
+    code:     &'static str,
+    message:  String,
+    jail:     Option<String>,
+    span:     Span,
+}
+
+impl Lint {
+    pub(crate) fn new(
+        severity: Severity,
+        code: &'static str,
+        message: String,
+        jail: Option<&str>,
+        span: Span,
+    ) -> Self {
+        Self {
+            severity,
+            code,
+            message,
+            jail: jail.map(String::from),
+            span,
+        }
+    }
+
+    /// How serious the problem is.
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// A short identifier for the kind of problem, eg.
+    /// "duplicate-assignment".
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+
+    /// A description of the problem.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The jail the problem was found in, or None for global parameters.
+    pub fn jail(&self) -> Option<&str> {
+        self.jail.as_deref()
+    }
+
+    /// Where the statement at fault was parsed from.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}[{}]: ", self.severity, self.code)?;
+
+        if let Some(jail) = &self.jail {
+            write!(f, "{}: ", jail)?;
+        }
+
+        f.write_str(&self.message)
+    }
+}
+
+// Pairs of parameters which shouldn't both be set for a jail. The second is
+// ignored, or refused, when the first is enabled.
+const CONFLICTS: &[(&str, &str, &str)] = &[
+    ("vnet", "ip4.addr", "a vnet jail configures its own addresses"),
+    ("vnet", "ip6.addr", "a vnet jail configures its own addresses"),
+];
+
+// The name, value and span of a parameter statement, with booleans given
+// without a value having the value "true".
+fn param<'c>(statement: &'c JailConf) -> Option<(&'c str, &'c str, bool, Span)> {
+    match statement {
+        JailConf::ParamBool(param)  => Some((param.name(), "true", false, param.span())),
+        JailConf::ParamValue(param) => {
+            Some((param.name(), param.value(), param.is_append(), param.span()))
+        },
+        _ => None,
+    }
+}
+
+// Whether a parameter's value turns it on, eg. `vnet = new;` or `vnet;`.
+pub(crate) fn enabled(value: &str) -> bool {
+    value == "new" || parse_bool(value) == Some(true)
+}
+
+// Parameters assigned with `=` more than once in the same scope.
+fn duplicates(jail: Option<&str>, statements: &[JailConf], lints: &mut Vec<Lint>) {
+    let mut assigned: Vec<&str> = Vec::new();
+
+    for (name, _, append, span) in statements.iter().filter_map(param) {
+        if append {
+            continue;
+        }
+
+        if assigned.contains(&name) {
+            lints.push(Lint::new(
+                Severity::Warning,
+                "duplicate-assignment",
+                format!("{} is assigned more than once, the last value wins", name),
+                jail,
+                span,
+            ));
+        }
+        else {
+            assigned.push(name);
+        }
+    }
+}
+
+// Booleans set in both their positive and "no" forms in the same scope.
+fn contradictions(jail: Option<&str>, statements: &[JailConf], lints: &mut Vec<Lint>) {
+    let mut seen: Vec<(String, bool)> = Vec::new();
+
+    for (name, _, append, span) in statements.iter().filter_map(param) {
+        let (positive, negated) = match boolean(name) {
+            Some(form) if !append => form,
+            _                     => continue,
+        };
+
+        if seen.iter().any(|(seen, form)| *seen == positive && *form != negated) {
+            lints.push(Lint::new(
+                Severity::Warning,
+                "contradictory-boolean",
+                format!(
+                    "{} and {} are both set, the last one wins",
+                    positive,
+                    negate(&positive),
+                ),
+                jail,
+                span,
+            ));
+        }
+
+        seen.push((positive, negated));
+    }
+}
+
+impl<'a> JailConfig<'a> {
+    /// Look for likely mistakes: parameters assigned twice in the same
+    /// block, booleans set both ways in the same block, parameters which
+    /// conflict with each other, such as `ip4.addr` in a vnet jail, lists set
+    /// globally which are replaced rather than added to by a jail, and vnet
+    /// jails which are given no interface, share one with another jail, or
+    /// create an epair(4) which is never destroyed, see `JailConfig::vnet`.
+    pub fn lint(&self) -> Vec<Lint> {
+        let mut lints = Vec::new();
+        let globals: Vec<_> = self.statements().iter().filter_map(param).collect();
+
+        duplicates(None, self.statements(), &mut lints);
+        contradictions(None, self.statements(), &mut lints);
+
+        for block in self.jails() {
+            let jail = Some(block.name());
+            let params: Vec<_> = block.params().iter().filter_map(param).collect();
+
+            duplicates(jail, block.params(), &mut lints);
+            contradictions(jail, block.params(), &mut lints);
+
+            // Lists set globally and then assigned, rather than appended to.
+            for (name, _, append, span) in &params {
+                let is_list = parameter(name)
+                    .is_some_and(|param| param.kind() == ParamType::List);
+
+                if is_list && !append && globals.iter().any(|global| global.0 == *name) {
+                    lints.push(Lint::new(
+                        Severity::Warning,
+                        "overridden-global",
+                        format!(
+                            "{} replaces the global value, use += to add to it",
+                            name,
+                        ),
+                        jail,
+                        *span,
+                    ));
+                }
+            }
+
+            // The effective value of a parameter, global or in this block.
+            let effective = |name: &str| {
+                globals.iter()
+                    .chain(&params)
+                    .rfind(|param| param.0 == name)
+            };
+
+            for (first, second, reason) in CONFLICTS {
+                let first_enabled = effective(first)
+                    .is_some_and(|param| enabled(param.1));
+
+                if let (true, Some(param)) = (first_enabled, effective(second)) {
+                    lints.push(Lint::new(
+                        Severity::Warning,
+                        "conflicting-parameters",
+                        format!("{} has no effect with {}, {}", second, first, reason),
+                        jail,
+                        param.3,
+                    ));
+                }
+            }
+        }
+
+        lints.extend(vnet::check(self));
+
+        lints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_lint() {
+        let input = indoc!(r#"
+            exec.start = "/bin/sh /etc/rc";
+            persist;
+            nginx {
+                path = "/jails/a";
+                path = "/jails/b";
+                ip4.addr = 127.0.1.1;
+                ip4.addr += 127.0.1.2;
+                exec.start = "/usr/local/bin/nginx";
+                vnet;
+            }
+            db {
+                exec.start += "/usr/local/bin/pg";
+                vnet = inherit;
+                ip4.addr = 127.0.1.3;
+            }
+            "#);
+
+        let config = JailConfig::parse(input).unwrap();
+        let lints: Vec<String> = config.lint()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(lints, vec![
+            "warning[duplicate-assignment]: nginx: path is assigned more than once, the last value wins",
+            "warning[overridden-global]: nginx: exec.start replaces the global value, use += to add to it",
+            "warning[conflicting-parameters]: nginx: ip4.addr has no effect with vnet, a vnet jail configures its own addresses",
+            "warning[vnet-without-interface]: nginx: vnet jail has no interfaces but lo0, give it one with vnet.interface",
+        ]);
+    }
+
+    #[test]
+    fn test_lint_contradictory_boolean() {
+        let input = indoc!(r#"
+            allow.set_hostname;
+            nginx {
+                allow.noset_hostname;
+                allow.mount = 1;
+                allow.nomount;
+            }
+            db {
+                allow.mount = false;
+                allow.nomount;
+            }
+            "#);
+
+        let config = JailConfig::parse(input).unwrap();
+        let lints = config.lint();
+
+        // The global and the block are separate scopes. Both forms are
+        // flagged even where they agree, as in db, since one is redundant.
+        assert_eq!(lints.len(), 2);
+        assert_eq!(
+            lints[0].to_string(),
+            "warning[contradictory-boolean]: nginx: allow.mount and allow.nomount are both set, the last one wins",
+        );
+        assert_eq!(lints[0].span().start().line(), 5);
+        assert_eq!(lints[1].jail(), Some("db"));
+    }
+
+    #[test]
+    fn test_lint_span() {
+        let input = "persist;\npersist;\n";
+        let config = JailConfig::parse(input).unwrap();
+        let lints = config.lint();
+
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].jail(), None);
+        assert_eq!(lints[0].severity(), Severity::Warning);
+        assert_eq!(lints[0].span().start().line(), 2);
+    }
+}
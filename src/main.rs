@@ -1,29 +1,44 @@
-use std::env;
-use std::fs::File;
-use std::io;
+use std::io::{
+    self,
+    IsTerminal,
+};
+use std::process;
+use clap::Parser;
+use tracing::{
+    error,
+    Level,
+};
+
+mod cli;
+
+use crate::cli::Cli;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let mut input: Box<io::Read> = if args.len() > 1 {
-        let filename = &args[1];
-        let fh = File::open(filename).unwrap();
-        Box::new(fh)
+    let cli = Cli::parse();
+
+    // -v may be given multiple times, or as -vv, to increase verbosity, while
+    // -q silences everything but errors.
+    let level = if cli.quiet {
+        Level::ERROR
     }
     else {
-        let stdin = io::stdin();
-        Box::new(stdin)
+        match cli.verbose {
+            0 => Level::WARN,
+            1 => Level::INFO,
+            2 => Level::DEBUG,
+            _ => Level::TRACE,
+        }
     };
 
-    let mut buffer = String::new();
-    input.read_to_string(&mut buffer).unwrap();
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .without_time()
+        .init();
 
-    let result = jailconf::parse(&buffer);
-    let result = match result {
-        Ok(r)  => r,
-        Err(e) => {
-            eprintln!("{:?}", e);
-            std::process::exit(1);
-        },
-    };
-    println!("{:?}", result);
+    if let Err(e) = cli::run(cli.command) {
+        error!("{}", e);
+        process::exit(1);
+    }
 }
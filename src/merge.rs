@@ -0,0 +1,362 @@
+// Merging of one configuration into another.
+//
+// A common layout is a base configuration shared between hosts, with a
+// smaller file of host specific overrides. Merging applies the statements of
+// the overrides to the base: global parameters to the globals, and those in a
+// jail's block to the block for the same jail, which is added if the base
+// doesn't configure it. Parameters given with `=` replace the values the
+// base gives them while those given with `+=` are added to them. Under the
+// strict strategy replacing a value with a different one is a conflict.
+use std::error;
+use std::fmt;
+use crate::{
+    JailConf,
+    JailConfig,
+    Position,
+};
+
+/// How assignments which replace a value already in the base are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MergeStrategy {
+    /// The value being merged in replaces the value in the base.
+    #[default]
+    Override,
+    /// Assigning a different value to a parameter the base already sets is
+    /// an error. Appending with `+=` is always allowed.
+    Strict,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MergeError {
+    /// Both configurations assign a parameter, with different values.
+    /// Variables are named with their leading `$`.
+    Conflict {
+        jail:     Option<String>,
+        name:     String,
+        base:     Vec<String>,
+        other:    String,
+        position: Position,
+    },
+}
+
+impl MergeError {
+    /// The jail the parameter was set for, or None for a global parameter.
+    pub fn jail(&self) -> Option<&str> {
+        match self {
+            MergeError::Conflict { jail, .. } => jail.as_deref(),
+        }
+    }
+
+    /// The name of the parameter.
+    pub fn name(&self) -> &str {
+        match self {
+            MergeError::Conflict { name, .. } => name,
+        }
+    }
+
+    /// Where the conflicting statement starts in the configuration being
+    /// merged in.
+    pub fn position(&self) -> Position {
+        match self {
+            MergeError::Conflict { position, .. } => *position,
+        }
+    }
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MergeError::Conflict { jail, name, base, other, .. } => {
+                write!(f, "conflicting values for {}", name)?;
+
+                if let Some(jail) = jail {
+                    write!(f, " in jail {}", jail)?;
+                }
+
+                write!(f, ": {:?} and {:?}", base.join(", "), other)
+            },
+        }
+    }
+}
+
+impl error::Error for MergeError {}
+
+// The key a statement assigns to, with variables prefixed by `$` so that
+// they can't be confused with parameters, its value and whether it's
+// appended.
+pub(crate) fn assignment(statement: &JailConf) -> Option<(String, String, bool)> {
+    match statement {
+        JailConf::ParamBool(param) => {
+            Some((param.name().to_string(), "true".into(), false))
+        },
+        JailConf::ParamValue(param) => {
+            Some((param.name().to_string(), param.value().into(), param.is_append()))
+        },
+        JailConf::Variable(var) => {
+            Some((format!("${}", var.name()), var.value().into(), var.is_append()))
+        },
+        _ => None,
+    }
+}
+
+pub(crate) fn assigns(statement: &JailConf, key: &str) -> bool {
+    matches!(assignment(statement), Some((name, _, _)) if name == key)
+}
+
+// The current values of key across the statements of a scope.
+pub(crate) fn current_values(scope: &[&mut Vec<JailConf>], key: &str) -> Vec<String> {
+    let mut values = Vec::new();
+
+    for (name, value, append) in scope.iter().flat_map(|s| s.iter()).filter_map(assignment) {
+        if name != key {
+            continue;
+        }
+
+        if !append {
+            values.clear();
+        }
+
+        values.push(value);
+    }
+
+    values
+}
+
+// Add a statement to the last list of a scope, ahead of any blocks so that
+// global parameters stay above the jails.
+pub(crate) fn insert<'a>(scope: &mut [&mut Vec<JailConf<'a>>], statement: JailConf<'a>) {
+    if let Some(statements) = scope.last_mut() {
+        let at = statements.iter()
+            .position(|s| matches!(s, JailConf::Block(_)))
+            .unwrap_or(statements.len());
+
+        statements.insert(at, statement);
+    }
+}
+
+// Merge a single non-block statement into a scope. The scope is every list
+// of statements making up the globals or a jail, as a jail may be
+// configured in more than one block.
+fn merge_statement<'a>(
+    scope:     &mut [&mut Vec<JailConf<'a>>],
+    statement: JailConf<'a>,
+    strategy:  MergeStrategy,
+    jail:      Option<&str>,
+) -> Result<(), MergeError> {
+    let (key, value, append) = match assignment(&statement) {
+        Some(assignment) => assignment,
+        None             => {
+            insert(scope, statement);
+            return Ok(());
+        },
+    };
+
+    if !append {
+        let base = current_values(scope, &key);
+
+        if strategy == MergeStrategy::Strict
+            && !base.is_empty()
+            && base != [value.as_str()]
+        {
+            return Err(MergeError::Conflict {
+                jail:     jail.map(String::from),
+                name:     key,
+                base,
+                other:    value,
+                position: statement.span().start(),
+            });
+        }
+
+        for statements in scope.iter_mut() {
+            statements.retain(|s| !assigns(s, &key));
+        }
+    }
+
+    insert(scope, statement);
+
+    Ok(())
+}
+
+impl<'a> JailConfig<'a> {
+    /// Merge another configuration into this one, returning the result.
+    ///
+    /// Global statements of other are merged into the global statements, and
+    /// the statements of each of its blocks into the block for the same jail,
+    /// which is added at the end if there isn't one. A parameter assigned
+    /// with `=` replaces every value the base gives it in the same scope,
+    /// while one assigned with `+=` is added to them. With
+    /// `MergeStrategy::Strict`, assigning a different value to a parameter
+    /// already assigned in the same scope is an error.
+    pub fn merge(&self, other: &JailConfig<'a>, strategy: MergeStrategy)
+    -> Result<JailConfig<'a>, MergeError> {
+        let mut statements = self.statements().to_vec();
+
+        for statement in other.statements() {
+            match statement {
+                JailConf::Block(block) => {
+                    let mut scope: Vec<&mut Vec<JailConf<'a>>> = statements
+                        .iter_mut()
+                        .filter_map(|s| match s {
+                            JailConf::Block(b) if b.name() == block.name() => {
+                                Some(&mut b.params)
+                            },
+                            _ => None,
+                        })
+                        .collect();
+
+                    if scope.is_empty() {
+                        statements.push(statement.clone());
+                        continue;
+                    }
+
+                    for param in block.params() {
+                        merge_statement(
+                            &mut scope,
+                            param.clone(),
+                            strategy,
+                            Some(block.name()),
+                        )?;
+                    }
+                },
+                statement => {
+                    merge_statement(
+                        &mut [&mut statements],
+                        statement.clone(),
+                        strategy,
+                        None,
+                    )?;
+                },
+            }
+        }
+
+        Ok(JailConfig::new(statements))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const BASE: &str = indoc!(r#"
+        persist;
+        path = "/jails/$name";
+        exec.start = "/bin/sh /etc/rc";
+        www {
+            host.hostname = "www";
+            ip4.addr = "192.0.2.1";
+        }
+        db {
+            host.hostname = "db";
+        }
+        "#);
+
+    #[test]
+    fn test_merge_override() {
+        let base = JailConfig::parse(BASE).unwrap();
+        let other = JailConfig::parse(indoc!(r#"
+            path = "/usr/jails/$name";
+            www {
+                ip4.addr = "198.51.100.1";
+                allow.raw_sockets;
+            }
+            mail {
+                host.hostname = "mail";
+            }
+            "#)).unwrap();
+
+        let merged = base.merge(&other, MergeStrategy::Override).unwrap();
+
+        assert_eq!(merged.to_string(), indoc!(r#"
+            persist;
+            exec.start = "/bin/sh /etc/rc";
+            path = "/usr/jails/$name";
+            www {
+                host.hostname = "www";
+                ip4.addr = "198.51.100.1";
+                allow.raw_sockets;
+            }
+
+            db {
+                host.hostname = "db";
+            }
+
+            mail {
+                host.hostname = "mail";
+            }
+            "#));
+    }
+
+    #[test]
+    fn test_merge_append() {
+        let base = JailConfig::parse(BASE).unwrap();
+        let other = JailConfig::parse(indoc!(r#"
+            exec.start += "/bin/sh /etc/rc.local";
+            www {
+                ip4.addr += "192.0.2.2";
+            }
+            "#)).unwrap();
+
+        let merged = base.merge(&other, MergeStrategy::Strict).unwrap();
+        let www = merged.effective_params("www").unwrap();
+
+        assert_eq!(www["ip4.addr"], vec!["192.0.2.1", "192.0.2.2"]);
+        assert_eq!(
+            www["exec.start"],
+            vec!["/bin/sh /etc/rc", "/bin/sh /etc/rc.local"],
+        );
+    }
+
+    #[test]
+    fn test_merge_repeated_blocks() {
+        let base = JailConfig::parse(indoc!(r#"
+            www {
+                path = "/a";
+            }
+            www {
+                ip4.addr = "192.0.2.1";
+            }
+            "#)).unwrap();
+        let other = JailConfig::parse("www {\n    path = \"/b\";\n}\n").unwrap();
+
+        let merged = base.merge(&other, MergeStrategy::Override).unwrap();
+
+        assert_eq!(merged.effective_params("www").unwrap()["path"], vec!["/b"]);
+        assert!(merged.jails().next().unwrap().params().is_empty());
+    }
+
+    #[test]
+    fn test_merge_strict_conflict() {
+        let base = JailConfig::parse(BASE).unwrap();
+        let other = JailConfig::parse(indoc!(r#"
+            persist;
+            www {
+                host.hostname = "www";
+                ip4.addr = "198.51.100.1";
+            }
+            "#)).unwrap();
+
+        let err = base.merge(&other, MergeStrategy::Strict).unwrap_err();
+
+        assert_eq!(err.jail(), Some("www"));
+        assert_eq!(err.name(), "ip4.addr");
+        assert_eq!(err.position().line(), 4);
+        assert_eq!(
+            err.to_string(),
+            "conflicting values for ip4.addr in jail www: \"192.0.2.1\" and \"198.51.100.1\"",
+        );
+    }
+
+    #[test]
+    fn test_merge_variables() {
+        let base = JailConfig::parse("$base = \"/jails\";\n").unwrap();
+        let other = JailConfig::parse("$base = \"/usr/jails\";\n").unwrap();
+
+        let merged = base.merge(&other, MergeStrategy::Override).unwrap();
+        assert_eq!(merged.to_string(), "$base = \"/usr/jails\";\n");
+
+        let err = base.merge(&other, MergeStrategy::Strict).unwrap_err();
+        assert_eq!(err.name(), "$base");
+        assert_eq!(err.jail(), None);
+    }
+}
@@ -0,0 +1,367 @@
+// Parameter names as jail(8) reads them.
+//
+// The parser takes anything up to whitespace or punctuation as a name, so
+// `allow,mount` or `allow..mount` parse as readily as `allow.mount`.
+// ParamName checks a name is made of dot separated components of letters,
+// digits and underscores, as every parameter's is.
+//
+// A boolean parameter can also be set false by prefixing the last component
+// of its name with "no", eg. `allow.nomount;` for `allow.mount = false;`.
+// ParamName recognises these forms for the booleans the schema knows. It
+// also recognises names from configurations written for the rc.conf(5)
+// variables, which used underscores where jail.conf uses dots, eg. ip4_addr
+// for ip4.addr. jail(8) refuses those, so they're offered respelled.
+//
+// normalize_names rewrites every parameter to its canonical name, and writes
+// booleans as a flag of their positive or "no" form, whichever gives the
+// value without stating it.
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+use crate::builder::param_bool;
+use crate::schema::{
+    parameter,
+    ParamType,
+};
+use crate::value::parse_bool;
+use crate::{
+    JailConf,
+    JailConfig,
+};
+
+// The most underscores in a name tried as dots when looking for what an old
+// spelling meant. Each one doubles the spellings tried.
+const MAX_RESPELLINGS: usize = 6;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NameError {
+    /// The name is empty.
+    Empty,
+    /// The name has a character no parameter name has.
+    InvalidChar {
+        name:  String,
+        found: char,
+    },
+    /// The name starts or ends with a dot, or has two dots together.
+    EmptyComponent {
+        name: String,
+    },
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NameError::Empty => write!(f, "empty parameter name"),
+            NameError::InvalidChar { name, found } => {
+                write!(f, "invalid character {:?} in parameter name {}", found, name)
+            },
+            NameError::EmptyComponent { name } => {
+                write!(f, "parameter name {} has an empty component", name)
+            },
+        }
+    }
+}
+
+impl error::Error for NameError {}
+
+// The name of a boolean parameter when it's false, eg. allow.nomount.
+pub(crate) fn negate(name: &str) -> String {
+    match name.rfind('.') {
+        Some(dot) => format!("{}.no{}", &name[..dot], &name[dot + 1..]),
+        None      => format!("no{}", name),
+    }
+}
+
+fn is_bool(name: &str) -> bool {
+    matches!(parameter(name), Some(param) if param.kind() == ParamType::Bool)
+}
+
+// The boolean a "no" form of name negates, eg. allow.mount for
+// allow.nomount.
+fn negated(name: &str) -> Option<String> {
+    let (parent, last) = match name.rfind('.') {
+        Some(dot) => (&name[..=dot], &name[dot + 1..]),
+        None      => ("", name),
+    };
+
+    let positive = format!("{}{}", parent, last.strip_prefix("no")?);
+
+    if parameter(name).is_none() && is_bool(&positive) {
+        Some(positive)
+    }
+    else {
+        None
+    }
+}
+
+fn is_known(name: &str) -> bool {
+    parameter(name).is_some() || negated(name).is_some()
+}
+
+// The known name an unknown one with underscores for dots stands for, eg.
+// ip4.addr for ip4_addr.
+fn respell(name: &str) -> Option<String> {
+    let underscores: Vec<usize> = name.match_indices('_').map(|(i, _)| i).collect();
+
+    if is_known(name) || underscores.len() > MAX_RESPELLINGS {
+        return None;
+    }
+
+    // Try every choice of underscores to make dots.
+    (1..1_usize << underscores.len()).find_map(|choice| {
+        let mut respelled = name.to_string().into_bytes();
+
+        for (bit, &at) in underscores.iter().enumerate() {
+            if choice & (1 << bit) != 0 {
+                respelled[at] = b'.';
+            }
+        }
+
+        let respelled = String::from_utf8(respelled).ok()?;
+
+        if is_known(&respelled) { Some(respelled) } else { None }
+    })
+}
+
+/// A parameter name checked and interpreted as jail(8) would, see
+/// `ParamName::parse`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParamName {
+    written: String,
+    name:    String,
+    negated: bool,
+}
+
+impl ParamName {
+    /// Check a parameter name, recognising the "no" forms of booleans and
+    /// old spellings with underscores for dots.
+    pub fn parse(name: &str) -> Result<Self, NameError> {
+        if name.is_empty() {
+            return Err(NameError::Empty);
+        }
+
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+
+        if let Some(found) = name.chars().find(|&c| !valid(c)) {
+            return Err(NameError::InvalidChar {
+                name: name.to_string(),
+                found,
+            });
+        }
+
+        if name.split('.').any(str::is_empty) {
+            return Err(NameError::EmptyComponent {
+                name: name.to_string(),
+            });
+        }
+
+        let spelled = respell(name).unwrap_or_else(|| name.to_string());
+
+        let (positive, negated) = match negated(&spelled) {
+            Some(positive) => (positive, true),
+            None           => (spelled, false),
+        };
+
+        Ok(Self {
+            written: name.to_string(),
+            name:    positive,
+            negated,
+        })
+    }
+
+    /// The parameter named, in its positive form, eg. allow.mount for
+    /// allow.nomount.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the name is the "no" form of a boolean, setting it false.
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+
+    /// Whether the name was an old spelling jail(8) won't accept, eg.
+    /// ip4_addr.
+    pub fn is_respelled(&self) -> bool {
+        self.written != self.canonical()
+    }
+
+    /// The name as jail(8) spells it, in its "no" form if it was written in
+    /// one.
+    pub fn canonical(&self) -> String {
+        if self.negated {
+            negate(&self.name)
+        }
+        else {
+            self.name.clone()
+        }
+    }
+}
+
+impl fmt::Display for ParamName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.canonical())
+    }
+}
+
+// The boolean a name sets, and whether it's the "no" form, eg. allow.mount
+// and true for allow.nomount.
+pub(crate) fn boolean(name: &str) -> Option<(String, bool)> {
+    match negated(name) {
+        Some(positive)         => Some((positive, true)),
+        None if is_bool(name)  => Some((name.to_string(), false)),
+        None                   => None,
+    }
+}
+
+// The boolean a statement sets in its "no" form, and the value it sets it
+// to, eg. allow.mount and false for `allow.nomount;`. Value is None for a
+// flag.
+pub(crate) fn negated_bool(name: &str, value: Option<&str>) -> Option<(String, bool)> {
+    let positive = negated(name)?;
+
+    match value {
+        None        => Some((positive, false)),
+        Some(value) => parse_bool(value).map(|value| (positive, !value)),
+    }
+}
+
+// A boolean flag, in whichever form gives value.
+fn flag(name: &ParamName, value: bool) -> JailConf<'static> {
+    if value {
+        param_bool(name.name())
+    }
+    else {
+        param_bool(&negate(name.name()))
+    }
+}
+
+fn normalize(statement: &JailConf) -> JailConf<'static> {
+    match statement {
+        JailConf::ParamBool(param) => match ParamName::parse(param.name()) {
+            Ok(name) if name.is_respelled() => param_bool(&name.canonical()),
+            _                               => statement.clone().into_owned(),
+        },
+        JailConf::ParamValue(param) => {
+            let name = match ParamName::parse(param.name()) {
+                Ok(name) => name,
+                Err(_)   => return statement.clone().into_owned(),
+            };
+
+            let value = parse_bool(param.value()).filter(|_| {
+                is_bool(name.name()) && !param.is_append()
+            });
+
+            match value {
+                Some(value) => flag(&name, value != name.is_negated()),
+                None if name.is_respelled() => {
+                    let mut param = param.clone().into_owned();
+                    let op = if param.append { "+=" } else { "=" };
+
+                    param.source = Cow::Owned(format!("{} {} {};", name, op, param.raw));
+                    param.name = Cow::Owned(name.canonical());
+
+                    JailConf::ParamValue(param)
+                },
+                None => statement.clone().into_owned(),
+            }
+        },
+        JailConf::Block(block) => {
+            let mut block = block.clone().into_owned();
+
+            block.params = block.params.iter().map(normalize).collect();
+            block.source = Cow::Owned(block.to_string());
+
+            JailConf::Block(block)
+        },
+        _ => statement.clone().into_owned(),
+    }
+}
+
+impl<'a> JailConfig<'a> {
+    /// Rewrite every parameter name to how jail(8) spells it, eg. ip4_addr
+    /// to ip4.addr, and every boolean set with a value to a flag, eg.
+    /// `allow.mount = false;` to `allow.nomount;`.
+    pub fn normalize_names(&self) -> JailConfig<'static> {
+        self.statements()
+            .iter()
+            .map(normalize)
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_param_name() {
+        let name = ParamName::parse("allow.noset_hostname").unwrap();
+
+        assert_eq!(name.name(), "allow.set_hostname");
+        assert!(name.is_negated());
+        assert!(!name.is_respelled());
+        assert_eq!(name.to_string(), "allow.noset_hostname");
+
+        let name = ParamName::parse("ip4_addr").unwrap();
+
+        assert_eq!(name.name(), "ip4.addr");
+        assert!(name.is_respelled());
+
+        let name = ParamName::parse("allow_mount_nonullfs").unwrap();
+
+        assert_eq!(name.canonical(), "allow.mount.nonullfs");
+        assert!(name.is_negated());
+
+        // Unknown names are only checked.
+        let name = ParamName::parse("custom.no_thing").unwrap();
+
+        assert_eq!(name.name(), "custom.no_thing");
+        assert!(!name.is_negated() && !name.is_respelled());
+    }
+
+    #[test]
+    fn test_param_name_errors() {
+        assert_eq!(ParamName::parse(""), Err(NameError::Empty));
+        assert_eq!(
+            ParamName::parse("allow,mount"),
+            Err(NameError::InvalidChar { name: "allow,mount".into(), found: ',' }),
+        );
+        assert_eq!(
+            ParamName::parse("allow..mount").unwrap_err().to_string(),
+            "parameter name allow..mount has an empty component",
+        );
+        assert!(ParamName::parse(".persist").is_err());
+    }
+
+    #[test]
+    fn test_normalize_names() {
+        let input = indoc!(r#"
+            ip4_addr = 192.0.2.1;
+            allow_raw_sockets;
+            www {
+                allow.mount = false;
+                allow.nochflags = no;
+                mount.devfs = 1;
+                persist = maybe;
+                allow.noset_hostname;
+            }
+            "#);
+
+        let config = JailConfig::parse(input).unwrap().normalize_names();
+
+        assert_eq!(config.to_string(), indoc!(r#"
+            ip4.addr = 192.0.2.1;
+            allow.raw_sockets;
+            www {
+                allow.nomount;
+                allow.chflags;
+                mount.devfs;
+                persist = maybe;
+                allow.noset_hostname;
+            }
+            "#));
+    }
+}
@@ -0,0 +1,406 @@
+// Inventory of the addresses assigned to jails.
+//
+// Each entry of `ip4.addr` and `ip6.addr` is an address, optionally preceded
+// by the interface to add it to and followed by a prefix length or netmask,
+// eg. `em0|192.0.2.1/24`. The inventory parses every entry of every jail, and
+// check_addresses reports addresses given to more than one jail, or falling
+// outside the subnets a host is allowed to use, as Lints.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use crate::lint::{
+    Lint,
+    Severity,
+};
+use crate::value::invalid;
+use crate::{
+    JailConfig,
+    ValueError,
+};
+
+const ADDRESS_PARAMS: &[&str] = &["ip4.addr", "ip6.addr"];
+
+/// A network, eg. `192.0.2.0/24`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Subnet {
+    addr:   IpAddr,
+    prefix: u8,
+}
+
+// The number of bits in addresses of the same family as addr.
+fn max_prefix(addr: &IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+// An address as a 128 bit integer, with IPv4 addresses in the top bits so
+// that prefixes line up for both families.
+fn bits(addr: &IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(addr) => u128::from(u32::from(*addr)) << 96,
+        IpAddr::V6(addr) => u128::from(*addr),
+    }
+}
+
+// The prefix length of a netmask, eg. 24 for 255.255.255.0. None if the
+// mask isn't contiguous.
+fn mask_prefix(mask: &IpAddr) -> Option<u8> {
+    let bits = bits(mask);
+    let ones = bits.leading_ones();
+
+    match bits.checked_shl(ones) {
+        Some(rest) if rest != 0 => None,
+        _                       => Some(ones as u8),
+    }
+}
+
+impl Subnet {
+    /// Create a subnet from an address and prefix length. None if the prefix
+    /// is too long for the address family.
+    pub fn new(addr: IpAddr, prefix: u8) -> Option<Self> {
+        if prefix > max_prefix(&addr) {
+            return None;
+        }
+
+        Some(Self {
+            addr,
+            prefix,
+        })
+    }
+
+    /// The address the subnet was given with.
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// The prefix length.
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Whether the address is within the subnet. Addresses of the other
+    /// family never are.
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        if self.addr.is_ipv4() != addr.is_ipv4() {
+            return false;
+        }
+
+        let mask = match self.prefix {
+            0      => 0,
+            prefix => u128::MAX << (128 - u32::from(prefix)),
+        };
+
+        bits(&self.addr) & mask == bits(addr) & mask
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = ValueError;
+
+    /// Parse a subnet given as `addr/prefix`. An address without a prefix
+    /// is a subnet containing only itself.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || invalid("subnet", s, "a subnet");
+
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None                 => (s, None),
+        };
+
+        let addr: IpAddr = addr.trim().parse().map_err(|_| error())?;
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse().map_err(|_| error())?,
+            None         => max_prefix(&addr),
+        };
+
+        Subnet::new(addr, prefix).ok_or_else(error)
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// An address assigned to a jail.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JailAddress {
+    param:     &'static str,
+    interface: Option<String>,
+    addr:      IpAddr,
+    prefix:    Option<u8>,
+}
+
+impl JailAddress {
+    /// The parameter the address was given in, `ip4.addr` or `ip6.addr`.
+    pub fn param(&self) -> &'static str {
+        self.param
+    }
+
+    /// The interface the address is added to, if one was given.
+    pub fn interface(&self) -> Option<&str> {
+        self.interface.as_deref()
+    }
+
+    /// The address itself.
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// The prefix length, if one was given. Netmasks are converted to their
+    /// prefix length.
+    pub fn prefix(&self) -> Option<u8> {
+        self.prefix
+    }
+}
+
+// Parse an address list entry, eg. `em0|192.0.2.1/24`.
+fn parse_entry(param: &'static str, entry: &str) -> Result<JailAddress, ValueError> {
+    let error = || invalid(param, entry, "an IP address");
+
+    let (interface, rest) = match entry.split_once('|') {
+        Some((interface, rest)) => (Some(interface.trim().to_string()), rest),
+        None                    => (None, entry),
+    };
+
+    let (addr, prefix) = match rest.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix.trim())),
+        None                 => (rest, None),
+    };
+
+    let addr: IpAddr = addr.trim().parse().map_err(|_| error())?;
+    let prefix = match prefix {
+        None         => None,
+        Some(prefix) => {
+            let length = match prefix.parse::<u8>() {
+                Ok(length) => Some(length),
+                Err(_)     => prefix.parse().ok().as_ref().and_then(mask_prefix),
+            };
+
+            match length {
+                Some(length) if length <= max_prefix(&addr) => Some(length),
+                _                                           => return Err(error()),
+            }
+        },
+    };
+
+    Ok(JailAddress {
+        param,
+        interface,
+        addr,
+        prefix,
+    })
+}
+
+impl<'a> JailConfig<'a> {
+    /// Every address assigned to the named jail by `ip4.addr` and
+    /// `ip6.addr`, in the order they were given. Empty if the jail doesn't
+    /// exist or has no addresses.
+    pub fn addresses(&self, jail: &str) -> Result<Vec<JailAddress>, ValueError> {
+        let params = match self.effective_params(jail) {
+            Some(params) => params,
+            None         => return Ok(Vec::new()),
+        };
+
+        let mut addresses = Vec::new();
+
+        for param in ADDRESS_PARAMS {
+            let entries = params.get(*param)
+                .into_iter()
+                .flatten()
+                .flat_map(|value| value.split(','))
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty());
+
+            for entry in entries {
+                addresses.push(parse_entry(param, entry)?);
+            }
+        }
+
+        Ok(addresses)
+    }
+
+    /// The addresses of every configured jail, keyed by jail name.
+    pub fn address_inventory(&self)
+    -> Result<BTreeMap<String, Vec<JailAddress>>, ValueError> {
+        let mut inventory = BTreeMap::new();
+
+        for block in self.jails() {
+            if !inventory.contains_key(block.name()) {
+                let addresses = self.addresses(block.name())?;
+
+                inventory.insert(block.name().to_string(), addresses);
+            }
+        }
+
+        Ok(inventory)
+    }
+
+    /// Check the addresses of every jail, reporting entries which can't be
+    /// parsed, addresses assigned to more than one jail, and, if any allowed
+    /// subnets are given, addresses outside all of them.
+    pub fn check_addresses(&self, allowed: &[Subnet]) -> Vec<Lint> {
+        let mut lints = Vec::new();
+        let mut names: Vec<&str> = Vec::new();
+        let mut seen: Vec<(IpAddr, &str)> = Vec::new();
+
+        for block in self.jails() {
+            if !names.contains(&block.name()) {
+                names.push(block.name());
+            }
+        }
+
+        for jail in names {
+            let addresses = match self.addresses(jail) {
+                Ok(addresses) => addresses,
+                Err(e)        => {
+                    let name = match &e {
+                        ValueError::Invalid { name, .. } => name.as_str(),
+                        ValueError::Missing { name, .. } => name.as_str(),
+                    };
+
+                    lints.push(Lint::new(
+                        Severity::Error,
+                        "invalid-address",
+                        e.to_string(),
+                        Some(jail),
+                        self.setter_span(jail, name),
+                    ));
+
+                    continue;
+                },
+            };
+
+            for address in addresses {
+                let addr = address.addr();
+                let span = self.setter_span(jail, address.param());
+
+                match seen.iter().find(|(other, _)| *other == addr) {
+                    Some((_, other)) if *other != jail => lints.push(Lint::new(
+                        Severity::Error,
+                        "duplicate-address",
+                        format!("address {} is also assigned to jail {}", addr, other),
+                        Some(jail),
+                        span,
+                    )),
+                    Some(_) => {},
+                    None    => seen.push((addr, jail)),
+                }
+
+                if !allowed.is_empty() && !allowed.iter().any(|net| net.contains(&addr)) {
+                    lints.push(Lint::new(
+                        Severity::Warning,
+                        "address-outside-subnet",
+                        format!("address {} is outside of the allowed subnets", addr),
+                        Some(jail),
+                        span,
+                    ));
+                }
+            }
+        }
+
+        lints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use std::net::{
+        Ipv4Addr,
+        Ipv6Addr,
+    };
+
+    #[test]
+    fn test_subnet() {
+        let net: Subnet = "192.0.2.0/24".parse().unwrap();
+
+        assert!(net.contains(&"192.0.2.200".parse().unwrap()));
+        assert!(!net.contains(&"192.0.3.1".parse().unwrap()));
+        assert!(!net.contains(&"::1".parse().unwrap()));
+        assert_eq!(net.to_string(), "192.0.2.0/24");
+
+        let net: Subnet = "fd00::/8".parse().unwrap();
+        assert!(net.contains(&"fd12::1".parse().unwrap()));
+
+        let single: Subnet = "198.51.100.7".parse().unwrap();
+        assert_eq!(single.prefix(), 32);
+
+        assert!("192.0.2.0/33".parse::<Subnet>().is_err());
+        assert!("nonsense".parse::<Subnet>().is_err());
+    }
+
+    #[test]
+    fn test_addresses() {
+        let config = JailConfig::parse(indoc!(r#"
+            www {
+                ip4.addr = "em0|192.0.2.1/24, 192.0.2.2";
+                ip4.addr += "lo1|127.0.1.1/255.255.255.255";
+                ip6.addr = "em0|2001:db8::1/64";
+            }
+            "#)).unwrap();
+
+        let addresses = config.addresses("www").unwrap();
+
+        assert_eq!(addresses.len(), 4);
+        assert_eq!(addresses[0].interface(), Some("em0"));
+        assert_eq!(addresses[0].addr(), IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)));
+        assert_eq!(addresses[0].prefix(), Some(24));
+        assert_eq!(addresses[1].interface(), None);
+        assert_eq!(addresses[1].prefix(), None);
+        assert_eq!(addresses[2].prefix(), Some(32));
+        assert_eq!(addresses[3].param(), "ip6.addr");
+        assert_eq!(
+            addresses[3].addr(),
+            IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+        );
+
+        assert!(config.addresses("missing").unwrap().is_empty());
+        assert_eq!(config.address_inventory().unwrap()["www"], addresses);
+    }
+
+    #[test]
+    fn test_addresses_invalid() {
+        let config = JailConfig::parse("www {\n    ip4.addr = \"192.0.2.1/40\";\n}\n").unwrap();
+
+        assert_eq!(
+            config.addresses("www").unwrap_err().to_string(),
+            "ip4.addr = \"192.0.2.1/40\" is not an IP address",
+        );
+    }
+
+    #[test]
+    fn test_check_addresses() {
+        let config = JailConfig::parse(indoc!(r#"
+            www {
+                ip4.addr = "192.0.2.1";
+            }
+            db {
+                ip4.addr = "192.0.2.1, 10.0.0.1";
+            }
+            mail {
+                ip4.addr = "not-an-address";
+            }
+            "#)).unwrap();
+
+        let allowed = vec!["192.0.2.0/24".parse().unwrap()];
+        let lints: Vec<String> = config.check_addresses(&allowed)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(lints, vec![
+            "error[duplicate-address]: db: address 192.0.2.1 is also assigned to jail www",
+            "warning[address-outside-subnet]: db: address 10.0.0.1 is outside of the allowed subnets",
+            "error[invalid-address]: mail: ip4.addr = \"not-an-address\" is not an IP address",
+        ]);
+
+        // Without allowed subnets only duplicates are reported.
+        assert_eq!(config.check_addresses(&[]).len(), 2);
+    }
+}
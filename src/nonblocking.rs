@@ -0,0 +1,244 @@
+// Parsing without blocking an async runtime.
+//
+// parse_reader and parse_with_includes do blocking IO, which stalls every
+// other task on a tokio worker thread while a large configuration tree is
+// read. parse_reader_async reads statements from an AsyncBufRead exactly as
+// parse_reader does from a BufRead, a line at a time, sharing its parsing.
+// parse_file_async reads a file and the files its `.include` directives
+// match with tokio's filesystem functions, as parse_with_includes would. The
+// parsing of each file, and the directory listing needed to expand an
+// include pattern, is handed to tokio's blocking thread pool.
+use std::future::Future;
+use std::io;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::pin::Pin;
+use tokio::io::{
+    AsyncBufRead,
+    AsyncBufReadExt,
+};
+use tokio::task;
+use tracing::debug;
+use crate::include::{
+    cycle,
+    matches,
+};
+use crate::stream::{
+    Pending,
+    Step,
+};
+use crate::{
+    into_owned,
+    parse,
+    IncludeError,
+    JailConf,
+    JailConfOwned,
+    ParseError,
+};
+
+/// The statements read from an async reader, see `parse_reader_async`.
+pub struct AsyncStatementReader<R> {
+    reader:  R,
+    pending: Pending,
+    failed:  bool,
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncStatementReader<R> {
+    async fn read_statement(&mut self) -> Result<Option<JailConf<'static>>, ParseError> {
+        loop {
+            match self.pending.step()? {
+                Step::Statement(statement) => return Ok(Some(statement)),
+                Step::Done                 => return Ok(None),
+                Step::ReadLine             => {
+                    let read = self.reader.read_line(&mut self.pending.buffer).await;
+
+                    self.pending.line_read(read)?;
+                },
+            }
+        }
+    }
+
+    /// The next statement, None once the input has been read, or after the
+    /// first error.
+    pub async fn next_statement(&mut self) -> Option<Result<JailConf<'static>, ParseError>> {
+        if self.failed {
+            return None;
+        }
+
+        match self.read_statement().await {
+            Ok(statement) => statement.map(Ok),
+            Err(e)        => {
+                self.failed = true;
+                Some(Err(e))
+            },
+        }
+    }
+
+    /// Read every remaining statement, stopping at the first error.
+    pub async fn read_all(mut self) -> Result<Vec<JailConfOwned>, ParseError> {
+        let mut statements = Vec::new();
+
+        while let Some(statement) = self.next_statement().await {
+            statements.push(statement?);
+        }
+
+        Ok(statements)
+    }
+}
+
+/// Parse statements from an async reader as they're read, as `parse_reader`
+/// does for blocking readers.
+pub fn parse_reader_async<R: AsyncBufRead + Unpin>(reader: R) -> AsyncStatementReader<R> {
+    AsyncStatementReader {
+        reader,
+        pending: Pending::default(),
+        failed:  false,
+    }
+}
+
+type Loading<'s> = Pin<Box<dyn Future<Output = Result<Vec<JailConfOwned>, IncludeError>> + Send + 's>>;
+
+// Run blocking work on tokio's blocking thread pool.
+async fn blocking<F, T>(path: &Path, work: F) -> Result<T, IncludeError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    task::spawn_blocking(work).await.map_err(|error| IncludeError::Io {
+        path:  path.to_path_buf(),
+        error: io::Error::other(error),
+    })
+}
+
+// Replace include directives with the statements of the files they match,
+// as include::expand does.
+fn expand(config: Vec<JailConfOwned>, dir: PathBuf, stack: &mut Vec<PathBuf>) -> Loading<'_> {
+    Box::pin(async move {
+        let mut expanded = Vec::with_capacity(config.len());
+
+        for statement in config {
+            match statement {
+                JailConf::Include(include) => {
+                    let pattern = include.path().to_string();
+                    let within = dir.clone();
+                    let paths = blocking(&dir, move || matches(&within, &pattern)).await??;
+
+                    for path in paths {
+                        expanded.extend(load(path, stack).await?);
+                    }
+                },
+                JailConf::Block(mut block) => {
+                    block.params = expand(block.params, dir.clone(), stack).await?;
+                    expanded.push(JailConf::Block(block));
+                },
+                statement => expanded.push(statement),
+            }
+        }
+
+        Ok(expanded)
+    })
+}
+
+// Read, parse and expand a single file, as include::load does.
+fn load(path: PathBuf, stack: &mut Vec<PathBuf>) -> Loading<'_> {
+    Box::pin(async move {
+        let io_error = |error| IncludeError::Io {
+            path: path.clone(),
+            error,
+        };
+
+        let canonical = tokio::fs::canonicalize(&path).await.map_err(io_error)?;
+
+        if let Some(error) = cycle(&path, &canonical, stack) {
+            return Err(error);
+        }
+
+        let text = tokio::fs::read_to_string(&path).await.map_err(io_error)?;
+        let config = blocking(&path, move || parse(&text).map(into_owned)).await?
+            .map_err(|error| IncludeError::Parse {
+                path: path.clone(),
+                error,
+            })?;
+
+        debug!("read {} statements from {}", config.len(), path.display());
+
+        let dir = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+
+        stack.push(canonical);
+        let expanded = expand(config, dir, stack).await;
+        stack.pop();
+
+        expanded
+    })
+}
+
+/// Read the configuration at path without blocking, expanding any
+/// `.include` directives into the statements of the files they match, as
+/// `parse_with_includes` does.
+pub async fn parse_file_async<P>(path: P) -> Result<Vec<JailConfOwned>, IncludeError>
+where
+    P: AsRef<Path>,
+{
+    load(path.as_ref().to_path_buf(), &mut Vec::new()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use indoc::indoc;
+    use crate::scratch::scratch_dir;
+
+    #[tokio::test]
+    async fn test_parse_reader_async() {
+        let input = indoc!(r#"
+            persist;
+            www {
+                path = "/jails/www";
+            }
+            db {
+            "#);
+
+        let mut reader = parse_reader_async(input.as_bytes());
+
+        assert!(matches!(reader.next_statement().await, Some(Ok(JailConf::ParamBool(_)))));
+
+        let www = reader.next_statement().await.unwrap().unwrap();
+        assert_eq!(www.span().start().line(), 2);
+
+        assert!(reader.next_statement().await.unwrap().is_err());
+        assert!(reader.next_statement().await.is_none());
+
+        let statements = parse_reader_async(&b"a;\nb { c; }\n"[..]).read_all().await.unwrap();
+        assert_eq!(statements, parse("a;\nb { c; }\n").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_parse_file_async() {
+        let dir = scratch_dir("async");
+        fs::create_dir(dir.join("jail.conf.d")).unwrap();
+
+        fs::write(
+            dir.join("jail.conf"),
+            "persist;\n.include \"jail.conf.d/*.conf\";\n",
+        ).unwrap();
+        fs::write(dir.join("jail.conf.d/b.conf"), "b {\n    persist;\n}\n").unwrap();
+        fs::write(dir.join("jail.conf.d/a.conf"), "a {\n    .include \"../c.inc\";\n}\n").unwrap();
+        fs::write(dir.join("c.inc"), "path = /jails/a;\n").unwrap();
+
+        let config = parse_file_async(dir.join("jail.conf")).await.unwrap();
+
+        assert_eq!(config, crate::parse_with_includes(dir.join("jail.conf")).unwrap());
+        assert_eq!(config.len(), 3);
+
+        fs::write(dir.join("c.inc"), ".include \"jail.conf\";\n").unwrap();
+        assert!(matches!(
+            parse_file_async(dir.join("jail.conf")).await,
+            Err(IncludeError::Cycle { .. }),
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,363 @@
+// Rewriting of configuration into a canonical form.
+//
+// The same configuration can be written in many ways: with comments or
+// without, parameters in any order, values quoted or bare, lists given in a
+// single assignment or built up with `+=`. normalize rewrites a configuration
+// so that any two ways of writing the same thing come out identical, and so
+// compare equal with `==`.
+//
+// Within each scope, the top level and each jail, the canonical form is its
+// variable definitions and includes in their original order, then its
+// parameters sorted by name, then its jail blocks sorted by name. Comments,
+// and the annotations taken from them, are dropped. Blocks for the same jail
+// are merged into one. Each parameter is set by a statement with `=`, or
+// `+=` only if the scope adds to a value set outside of it, followed by a
+// `+=` for each further value. The values aren't joined into one list, as
+// `a, b` is read back as the single value "a,b". A boolean and its "no"
+// form are one parameter, set by whichever came last. Values are quoted as
+// the formatter quotes them.
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use crate::builder::{
+    name_quote,
+    param_bool,
+};
+use crate::format::normalize_raw;
+use crate::name::boolean;
+use crate::schema::{
+    self,
+    ParamType,
+};
+use crate::{
+    Annotations,
+    JailBlock,
+    JailConf,
+    JailConfig,
+    JailParamValue,
+    JailVariable,
+    Span,
+};
+
+/// How boolean parameters are written by `JailConfig::normalize_with`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BoolStyle {
+    /// Without a value, eg. `persist;`.
+    #[default]
+    Flag,
+    /// With an explicit value, eg. `persist = true;`.
+    Explicit,
+}
+
+/// Options controlling `JailConfig::normalize_with`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NormalizeOptions {
+    bools: BoolStyle,
+}
+
+impl NormalizeOptions {
+    /// The default options, writing booleans as flags.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How to write boolean parameters. Only parameters known to be
+    /// booleans are written as flags, others keep an explicit `true`.
+    pub fn bools(mut self, style: BoolStyle) -> Self {
+        self.bools = style;
+        self
+    }
+}
+
+// A value as the formatter would write it, along with the value itself.
+fn item(raw: &str, value: &str) -> (String, String) {
+    (normalize_raw(raw, value), value.to_string())
+}
+
+// The value of a parameter once all of a scope's statements have been
+// applied, under the name of the statement which last set it, which for a
+// boolean may be its "no" form.
+enum Setting {
+    Flag {
+        name: String,
+    },
+    List {
+        name:   String,
+        append: bool,
+        items:  Vec<(String, String)>,
+    },
+}
+
+fn is_bool(name: &str) -> bool {
+    matches!(schema::parameter(name), Some(param) if param.kind() == ParamType::Bool)
+}
+
+fn param_value(name: &str, append: bool, (raw, value): &(String, String))
+-> JailConf<'static> {
+    let op = if append { "+=" } else { "=" };
+
+    JailConf::ParamValue(JailParamValue {
+        name:   Cow::Owned(name.to_string()),
+        value:  Cow::Owned(value.clone()),
+        source: Cow::Owned(format!("{} {} {};", name, op, raw)),
+        span:   Span::default(),
+        raw:    Cow::Owned(raw.clone()),
+        append,
+    })
+}
+
+// The statements giving a parameter its setting: one with `=`, or `+=` if
+// the scope adds to a value set outside of it, followed by one with `+=`
+// for each further value.
+fn setting(setting: Setting, options: &NormalizeOptions) -> Vec<JailConf<'static>> {
+    let flag = options.bools == BoolStyle::Flag;
+    let truth = || vec![("true".to_string(), "true".to_string())];
+
+    match setting {
+        Setting::Flag { name } if flag || !is_bool(&name) => vec![param_bool(&name)],
+        Setting::Flag { name } => vec![param_value(&name, false, &truth()[0])],
+        Setting::List { name, append: false, ref items }
+            if flag && *items == truth() && is_bool(&name) => vec![param_bool(&name)],
+        Setting::List { name, append, items } => {
+            items.iter()
+                .enumerate()
+                .map(|(i, item)| param_value(&name, append || i > 0, item))
+                .collect()
+        },
+    }
+}
+
+fn variable(var: &JailVariable) -> JailConf<'static> {
+    let raw = normalize_raw(var.raw(), var.value());
+    let op = if var.is_append() { "+=" } else { "=" };
+
+    JailConf::Variable(JailVariable {
+        name:   Cow::Owned(var.name().to_string()),
+        value:  Cow::Owned(var.value().to_string()),
+        source: Cow::Owned(format!("${} {} {};", var.name(), op, raw)),
+        span:   Span::default(),
+        raw:    Cow::Owned(raw),
+        append: var.is_append(),
+    })
+}
+
+fn block(name: &str, params: Vec<JailConf<'static>>) -> JailConf<'static> {
+    let mut block = JailBlock {
+        quote:       name_quote(name),
+        name:        Cow::Owned(name.to_string()),
+        params,
+        annotations: Annotations::new(),
+        source:      Cow::Borrowed(""),
+        span:        Span::default(),
+    };
+
+    block.source = Cow::Owned(block.to_string());
+    JailConf::Block(block)
+}
+
+// The parameter a name sets, so that a boolean and its "no" form, eg.
+// allow.mount and allow.nomount, are one setting.
+fn key(name: &str) -> String {
+    boolean(name).map_or_else(|| name.to_string(), |(positive, _)| positive)
+}
+
+// Normalize the statements of one scope.
+fn scope<'s, 'a: 's, I>(statements: I, options: &NormalizeOptions)
+-> Vec<JailConf<'static>>
+where
+    I: IntoIterator<Item = &'s JailConf<'a>>,
+{
+    let mut ordered = Vec::new();
+    let mut settings: BTreeMap<String, Setting> = BTreeMap::new();
+    let mut blocks: BTreeMap<&str, Vec<&JailConf>> = BTreeMap::new();
+
+    for statement in statements {
+        match statement {
+            JailConf::Block(b) => {
+                blocks.entry(b.name()).or_default().extend(b.params());
+            },
+            JailConf::Comment(_) => {},
+            JailConf::Include(include) => {
+                let mut include = include.clone().into_owned();
+
+                include.source = Cow::Owned(include.to_string());
+                include.span = Span::default();
+                ordered.push(JailConf::Include(include));
+            },
+            JailConf::Variable(var) => ordered.push(variable(var)),
+            JailConf::ParamBool(param) => {
+                let name = param.name().to_string();
+
+                settings.insert(key(&name), Setting::Flag { name });
+            },
+            JailConf::ParamValue(param) => {
+                let name = param.name().to_string();
+                let new = item(param.raw(), param.value());
+
+                let updated = match settings.remove(&key(&name)) {
+                    _ if !param.is_append() => Setting::List {
+                        name,
+                        append: false,
+                        items:  vec![new],
+                    },
+                    Some(Setting::List { name, append, mut items }) => {
+                        items.push(new);
+                        Setting::List { name, append, items }
+                    },
+                    Some(Setting::Flag { name }) => Setting::List {
+                        name,
+                        append: false,
+                        items:  vec![("true".to_string(), "true".to_string()), new],
+                    },
+                    None => Setting::List {
+                        name,
+                        append: true,
+                        items:  vec![new],
+                    },
+                };
+
+                settings.insert(key(param.name()), updated);
+            },
+        }
+    }
+
+    for (_, value) in settings {
+        ordered.extend(setting(value, options));
+    }
+
+    for (name, params) in blocks {
+        ordered.push(block(name, scope(params, options)));
+    }
+
+    ordered
+}
+
+impl<'a> JailConfig<'a> {
+    /// Rewrite the configuration into its canonical form with the default
+    /// options, see `normalize_with`.
+    pub fn normalize(&self) -> JailConfig<'static> {
+        self.normalize_with(&NormalizeOptions::new())
+    }
+
+    /// Rewrite the configuration into a canonical form, so that any two
+    /// configurations which differ only in how they're written compare
+    /// equal.
+    ///
+    /// Comments are dropped, blocks for the same jail are merged, and within
+    /// each scope parameters and then blocks are sorted by name after any
+    /// variables and includes. Every parameter is set by one statement for
+    /// each of its values, the first with `=` unless the scope adds to a
+    /// value set outside of it, and values are quoted only when they need to
+    /// be. The effective parameters of every jail are unchanged.
+    pub fn normalize_with(&self, options: &NormalizeOptions) -> JailConfig<'static> {
+        JailConfig::new(scope(self.statements(), options))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_normalize() {
+        let config = JailConfig::parse(indoc!(r#"
+            # Defaults
+            persist;
+            exec.start = "/bin/sh /etc/rc";
+            www {
+                ip4.addr = "192.0.2.1";
+                ip4.addr += 192.0.2.2;
+                allow.mount = true;
+                host.hostname = "www";
+            }
+            db { path = '/jails/$name'; }
+            "#)).unwrap();
+
+        let expected = indoc!(r#"
+            exec.start = "/bin/sh /etc/rc";
+            persist;
+            db {
+                path = "/jails/\$name";
+            }
+
+            www {
+                allow.mount;
+                host.hostname = www;
+                ip4.addr = 192.0.2.1;
+                ip4.addr += 192.0.2.2;
+            }
+            "#);
+
+        let normalized = config.normalize();
+
+        assert_eq!(normalized.to_string(), expected);
+        assert_eq!(normalized.normalize(), normalized);
+    }
+
+    #[test]
+    fn test_normalize_equal_configs() {
+        let a = JailConfig::parse(indoc!(r#"
+            www {
+                ip4.addr = "192.0.2.1";
+                exec.start = "/bin/sh /etc/rc";
+                ip4.addr += 192.0.2.2;
+                persist;
+            }
+            "#)).unwrap();
+
+        let b = JailConfig::parse(indoc!(r#"
+            // The web server.
+            www { persist = true; }
+            www {
+                exec.start = "/bin/sh " /etc/rc;
+                ip4.addr = 192.0.2.1;
+                ip4.addr += "192.0.2.2";
+            }
+            "#)).unwrap();
+
+        assert_ne!(a, b);
+        assert_eq!(a.normalize(), b.normalize());
+    }
+
+    #[test]
+    fn test_normalize_bools() {
+        let config = JailConfig::parse("persist;\nmount.devfs = true;\nx.y;\n").unwrap();
+        let options = NormalizeOptions::new().bools(BoolStyle::Explicit);
+        let explicit = config.normalize_with(&options).to_string();
+
+        assert_eq!(explicit, "mount.devfs = true;\npersist = true;\nx.y;\n");
+
+        let flags = config.normalize().to_string();
+        assert_eq!(flags, "mount.devfs;\npersist;\nx.y;\n");
+    }
+
+    #[test]
+    fn test_normalize_preserves_effective_params() {
+        let config = JailConfig::parse(indoc!(r#"
+            exec.start = "echo a,b";
+            exec.start += "echo c";
+            allow.mount;
+            www {
+                allow.nomount;
+                ip4.addr = 192.0.2.1, 192.0.2.2;
+                ip4.addr += 192.0.2.3;
+            }
+            db {
+                allow.nomount;
+                allow.mount = 1;
+                persist;
+                persist += x;
+            }
+            "#)).unwrap();
+        let normalized = config.normalize();
+
+        for jail in ["www", "db"] {
+            assert_eq!(normalized.effective_params(jail), config.effective_params(jail), "{}", jail);
+        }
+
+        let www = normalized.effective_params("www").unwrap();
+        assert_eq!(www["exec.start"], ["echo a,b", "echo c"]);
+        assert_eq!(www["allow.mount"], ["false"]);
+        assert_eq!(normalized.normalize(), normalized);
+    }
+}
@@ -0,0 +1,239 @@
+// Where each statement of an expanded configuration came from.
+//
+// parse_with_includes gives a single tree as jail(8) would see it, losing
+// which file each statement was read from. parse_include_tree expands
+// includes in the same way but also keeps the include graph: every file
+// read, which files each one includes, and for every node, at any depth,
+// the file it came from. Along with the node's span, which is within that
+// file, this is enough for a problem to be reported where it can be fixed,
+// eg. "defined in /etc/jail.conf.d/web.conf:12".
+//
+// A file included more than once, by different files, is read each time
+// but listed once. Include cycles are refused with IncludeError::Cycle,
+// which lists the files making up the cycle.
+use std::fmt;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::ptr;
+use crate::include::{
+    load_traced,
+    Origins,
+    Trace,
+};
+use crate::{
+    IncludeError,
+    JailConf,
+    JailConfig,
+    Span,
+};
+
+/// The file a node was read from, and where in it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FileOrigin<'t> {
+    path: &'t Path,
+    span: Span,
+}
+
+impl<'t> FileOrigin<'t> {
+    /// The file the node was read from.
+    pub fn path(&self) -> &'t Path {
+        self.path
+    }
+
+    /// Where in the file the node was parsed from.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+// The file and line, as an editor would take them.
+impl<'t> fmt::Display for FileOrigin<'t> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.path.display(), self.span.start().line())
+    }
+}
+
+/// A configuration with its includes expanded, along with the files they
+/// came from, see `parse_include_tree`.
+#[derive(Clone, Debug, Default)]
+pub struct IncludeTree {
+    config:   JailConfig<'static>,
+    files:    Vec<PathBuf>,
+    includes: Vec<(usize, usize)>,
+    origins:  Vec<Origins>,
+}
+
+// The file index of node, found by identity within statements.
+fn find(statements: &[JailConf], origins: &[Origins], node: &JailConf) -> Option<usize> {
+    for (statement, origin) in statements.iter().zip(origins) {
+        if ptr::eq(statement, node) {
+            return Some(origin.file);
+        }
+
+        if let JailConf::Block(block) = statement {
+            if let Some(file) = find(block.params(), &origin.params, node) {
+                return Some(file);
+            }
+        }
+    }
+
+    None
+}
+
+// The name of the parameter a statement sets, if it sets one.
+fn param_name<'c>(statement: &'c JailConf) -> Option<&'c str> {
+    match statement {
+        JailConf::ParamBool(param)  => Some(param.name()),
+        JailConf::ParamValue(param) => Some(param.name()),
+        _                           => None,
+    }
+}
+
+impl IncludeTree {
+    /// The expanded configuration.
+    pub fn config(&self) -> &JailConfig<'static> {
+        &self.config
+    }
+
+    /// Consume the tree, returning the expanded configuration.
+    pub fn into_config(self) -> JailConfig<'static> {
+        self.config
+    }
+
+    /// Every file read, in the order they were first read, starting with
+    /// the one given to `parse_include_tree`.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// The files a file includes directly, in the order they're included.
+    pub fn includes(&self, path: &Path) -> Vec<&Path> {
+        self.edges()
+            .filter(|(from, _)| *from == path)
+            .map(|(_, to)| to)
+            .collect()
+    }
+
+    /// The files which include a file directly.
+    pub fn included_by(&self, path: &Path) -> Vec<&Path> {
+        self.edges()
+            .filter(|(_, to)| *to == path)
+            .map(|(from, _)| from)
+            .collect()
+    }
+
+    /// Every include, as the including file and the file included.
+    pub fn edges(&self) -> impl Iterator<Item = (&Path, &Path)> {
+        self.includes.iter()
+            .map(move |(from, to)| (self.files[*from].as_path(), self.files[*to].as_path()))
+    }
+
+    /// The file a node of the expanded configuration came from, and where
+    /// in it. The node must be borrowed from `config()`, nodes compare by
+    /// identity rather than by value.
+    pub fn origin(&self, node: &JailConf) -> Option<FileOrigin<'_>> {
+        let file = find(self.config.statements(), &self.origins, node)?;
+
+        Some(FileOrigin {
+            path: &self.files[file],
+            span: node.span(),
+        })
+    }
+
+    /// Where each block configuring the named jail is, in order.
+    pub fn jail_origins(&self, jail: &str) -> Vec<FileOrigin<'_>> {
+        self.config.statements()
+            .iter()
+            .filter(|statement| matches!(statement, JailConf::Block(block) if block.name() == jail))
+            .filter_map(|statement| self.origin(statement))
+            .collect()
+    }
+
+    /// Where the statement giving a parameter its value for the named jail
+    /// is: the last setting it, whether globally, in a wildcard block
+    /// matching the jail or in the jail's own blocks.
+    pub fn param_origin(&self, jail: &str, name: &str) -> Option<FileOrigin<'_>> {
+        self.config.applying(jail)
+            .into_iter()
+            .rev()
+            .find(|statement| param_name(statement) == Some(name))
+            .and_then(|statement| self.origin(statement))
+    }
+}
+
+/// Read the configuration at path, expanding any `.include` directives as
+/// `parse_with_includes` does, and keeping the files each node came from.
+pub fn parse_include_tree<P>(path: P) -> Result<IncludeTree, IncludeError>
+where
+    P: AsRef<Path>,
+{
+    let mut trace = Trace::default();
+    let (statements, _, origins) = load_traced(path.as_ref(), &mut trace)?;
+
+    Ok(IncludeTree {
+        config:   JailConfig::new(statements),
+        files:    trace.files,
+        includes: trace.includes,
+        origins,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::scratch::scratch_dir;
+
+    #[test]
+    fn test_parse_include_tree() {
+        let dir = scratch_dir("origin-tree");
+        fs::create_dir(dir.join("jail.conf.d")).unwrap();
+
+        fs::write(
+            dir.join("jail.conf"),
+            "persist;\n.include \"jail.conf.d/*.conf\";\nwww {\n    .include \"www.inc\";\n}\n",
+        ).unwrap();
+        fs::write(dir.join("jail.conf.d/common.conf"), "mount.devfs;\n").unwrap();
+        fs::write(
+            dir.join("jail.conf.d/web.conf"),
+            ".include \"../www.inc\";\n\nwww {\n    host.hostname = www;\n}\n",
+        ).unwrap();
+        fs::write(dir.join("www.inc"), "# Shared.\npath = /jails/www;\n").unwrap();
+
+        let tree = parse_include_tree(dir.join("jail.conf")).unwrap();
+        let main = dir.join("jail.conf");
+        let common = dir.join("jail.conf.d/common.conf");
+        let web = dir.join("jail.conf.d/web.conf");
+        let inc = dir.join("jail.conf.d/../www.inc");
+
+        assert_eq!(tree.files(), [main.clone(), common.clone(), web.clone(), inc.clone()]);
+        assert_eq!(tree.includes(&main), [common.as_path(), web.as_path(), inc.as_path()]);
+        assert_eq!(tree.included_by(&inc), [web.as_path(), main.as_path()]);
+        assert!(tree.includes(&common).is_empty());
+
+        let www = tree.jail_origins("www");
+        assert_eq!(www.len(), 2);
+        assert_eq!(www[0].to_string(), format!("{}:3", web.display()));
+        assert_eq!(www[1].to_string(), format!("{}:3", main.display()));
+
+        let hostname = tree.param_origin("www", "host.hostname").unwrap();
+        assert_eq!(hostname.path(), web);
+        assert_eq!(hostname.span().start().line(), 4);
+
+        let devfs = tree.param_origin("www", "mount.devfs").unwrap();
+        assert_eq!(devfs.to_string(), format!("{}:1", common.display()));
+
+        let path = tree.param_origin("www", "path").unwrap();
+        assert_eq!(path.to_string(), format!("{}:2", inc.display()));
+
+        assert!(tree.param_origin("www", "allow.mount").is_none());
+
+        // An equal node from elsewhere has no origin.
+        let other = JailConfig::parse("persist;\n").unwrap();
+        assert!(tree.origin(&other.statements()[0]).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+// Overriding parameters from the environment.
+//
+// Where configuration is templated by environment rather than edited on
+// disk, as in containers and CI, it helps to be able to change a jail's
+// parameter without touching the file. apply_env_overrides reads variables
+// named for a jail and a parameter, eg.
+//
+//   JAILCONF_nginx_ip4__addr=10.0.0.5
+//
+// with the prefix, the jail's name and the parameter's name separated by
+// `_`, and each `.` of the parameter's name written as `__`, since a `.`
+// can't appear in the name of a shell variable. Jail names may themselves
+// contain `_`, so a variable is matched against the jails the configuration
+// has, the longest name first. Variables for jails which aren't configured
+// are ignored.
+//
+// An override replaces the parameter in the jail's blocks with a single `=`
+// at the end of its last block, so that it wins over any global or wildcard
+// value too. A boolean's "no" form is replaced along with it. Values are as
+// written in the file would be, so variable references in them are expanded
+// by resolve_variables as usual.
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::env;
+use tracing::debug;
+use crate::builder::param_value;
+use crate::name::boolean;
+use crate::{
+    JailConf,
+    JailConfig,
+};
+
+// The boolean or other parameter a name sets, so that `allow.nomount` and
+// `allow.mount` are the same parameter.
+fn param_key(name: &str) -> String {
+    boolean(name).map_or_else(|| name.to_string(), |(positive, _)| positive)
+}
+
+// The name a statement sets, if it's a parameter.
+fn statement_param<'c>(statement: &'c JailConf) -> Option<&'c str> {
+    match statement {
+        JailConf::ParamBool(param)  => Some(param.name()),
+        JailConf::ParamValue(param) => Some(param.name()),
+        _                           => None,
+    }
+}
+
+// The jail and parameter a variable's name, less the prefix, is for.
+fn target<'n>(jails: &[&'n str], rest: &str) -> Option<(&'n str, String)> {
+    let jail = jails.iter()
+        .filter(|jail| {
+            rest.strip_prefix(**jail)
+                .and_then(|rest| rest.strip_prefix('_'))
+                .is_some_and(|name| !name.is_empty())
+        })
+        .max_by_key(|jail| jail.len())?;
+
+    let name = rest[jail.len() + 1..].replace("__", ".");
+
+    Some((jail, name))
+}
+
+impl<'a> JailConfig<'a> {
+    /// Override parameters of the configured jails from environment
+    /// variables named `PREFIX_jail_param`, with each `.` of the parameter's
+    /// name written as `__`, eg. `JAILCONF_nginx_ip4__addr` for nginx's
+    /// `ip4.addr` with the prefix `JAILCONF`. See `apply_overrides`.
+    pub fn apply_env_overrides(self, prefix: &str) -> Self {
+        let vars = env::vars_os()
+            .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)));
+
+        self.apply_overrides(prefix, vars)
+    }
+
+    /// Override parameters of the configured jails from variables named as
+    /// for `apply_env_overrides`, taken from vars rather than the
+    /// environment. Each override replaces every statement setting the
+    /// parameter in the jail's blocks with `name = value;` at the end of its
+    /// last block. Overrides are applied in the order of their variables'
+    /// names, and variables without the prefix, or for a jail that isn't
+    /// configured, are ignored.
+    pub fn apply_overrides<I, K, V>(self, prefix: &str, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let prefix = format!("{}_", prefix);
+        let jails: Vec<String> = self.jail_names().into_iter().map(String::from).collect();
+        let jails: Vec<&str> = jails.iter().map(String::as_str).collect();
+        let mut overrides: BTreeMap<String, (&str, String, String)> = BTreeMap::new();
+
+        for (key, value) in vars {
+            let key = key.as_ref();
+            let rest = match key.strip_prefix(&prefix) {
+                Some(rest) => rest,
+                None       => continue,
+            };
+
+            match target(&jails, rest) {
+                Some((jail, name)) => {
+                    overrides.insert(key.to_string(), (jail, name, value.as_ref().to_string()));
+                },
+                None => debug!("ignoring {}, it doesn't name a configured jail", key),
+            }
+        }
+
+        let mut statements = self.into_statements();
+
+        for (key, (jail, name, value)) in overrides {
+            debug!("overriding {} of {} from {}", name, jail, key);
+
+            let param = param_key(&name);
+            let last = statements.iter()
+                .rposition(|statement| matches!(statement, JailConf::Block(block) if block.name() == jail));
+
+            for (i, statement) in statements.iter_mut().enumerate() {
+                let block = match statement {
+                    JailConf::Block(block) if block.name() == jail => block,
+                    _                                              => continue,
+                };
+
+                block.params.retain(|statement| {
+                    statement_param(statement).is_none_or(|name| param_key(name) != param)
+                });
+
+                if Some(i) == last {
+                    block.params.push(param_value(&name, &value, false));
+                }
+
+                block.source = Cow::Owned(block.to_string());
+            }
+        }
+
+        JailConfig::new(statements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        ip4.addr = 192.0.2.1;
+        nginx {
+            ip4.addr = 192.0.2.2;
+            allow.nomount;
+        }
+        web_db {
+            path = /jails/db;
+        }
+        web {
+            persist;
+        }
+        nginx {
+            ip4.addr += 192.0.2.3;
+        }
+        "#);
+
+    #[test]
+    fn test_apply_overrides() {
+        let config = JailConfig::parse(INPUT).unwrap();
+        let vars = vec![
+            ("JAILCONF_nginx_ip4__addr", "10.0.0.5"),
+            ("JAILCONF_nginx_allow__mount", "1"),
+            ("JAILCONF_web_db_path", "/jails/db 2"),
+            ("JAILCONF_web_host__hostname", "web.example.org"),
+            ("JAILCONF_missing_persist", "1"),
+            ("OTHER_nginx_persist", "1"),
+        ];
+
+        let config = config.apply_overrides("JAILCONF", vars);
+
+        assert_eq!(config.get_values("nginx", "ip4.addr"), ["10.0.0.5"]);
+        assert_eq!(config.get_param("web_db", "path").as_deref(), Some("/jails/db 2"));
+        assert_eq!(config.get_param("web", "host.hostname").as_deref(), Some("web.example.org"));
+        assert!(config.jail("missing").is_none());
+        assert!(config.get_param("nginx", "persist").is_none());
+
+        assert_eq!(config.to_string(), indoc!(r#"
+            ip4.addr = 192.0.2.1;
+            nginx {
+            }
+
+            web_db {
+                path = "/jails/db 2";
+            }
+
+            web {
+                persist;
+                host.hostname = web.example.org;
+            }
+
+            nginx {
+                allow.mount = 1;
+                ip4.addr = 10.0.0.5;
+            }
+            "#));
+    }
+
+    #[test]
+    fn test_apply_env_overrides() {
+        let config = JailConfig::parse(INPUT).unwrap();
+        let unchanged = config.clone().apply_env_overrides("JAILCONF_TEST_UNSET_PREFIX");
+
+        assert_eq!(unchanged, config);
+    }
+}
@@ -0,0 +1,162 @@
+// Conversion of borrowed configuration into owned configuration.
+//
+// Parsing borrows from the input string, which makes the result awkward to
+// store in long lived structures or send between threads. Every AST type can
+// be converted into a 'static version of itself which owns all of its data,
+// including the source text it was parsed from.
+use std::borrow::Cow;
+use crate::{
+    JailBlock,
+    JailComment,
+    JailConf,
+    JailInclude,
+    JailParamBool,
+    JailParamValue,
+    JailVariable,
+};
+
+/// A configuration node that owns all of its data.
+pub type JailConfOwned = JailConf<'static>;
+
+fn owned(s: Cow<str>) -> Cow<'static, str> {
+    Cow::Owned(s.into_owned())
+}
+
+impl<'a> JailComment<'a> {
+    /// Convert into a comment which owns all of its data.
+    pub fn into_owned(self) -> JailComment<'static> {
+        JailComment {
+            comment: owned(self.comment),
+            style:   self.style,
+            source:  owned(self.source),
+            span:    self.span,
+        }
+    }
+}
+
+impl<'a> JailInclude<'a> {
+    /// Convert into an include which owns all of its data.
+    pub fn into_owned(self) -> JailInclude<'static> {
+        JailInclude {
+            path:   owned(self.path),
+            source: owned(self.source),
+            span:   self.span,
+        }
+    }
+}
+
+impl<'a> JailParamBool<'a> {
+    /// Convert into a parameter which owns all of its data.
+    pub fn into_owned(self) -> JailParamBool<'static> {
+        JailParamBool {
+            name:   owned(self.name),
+            source: owned(self.source),
+            span:   self.span,
+        }
+    }
+}
+
+impl<'a> JailParamValue<'a> {
+    /// Convert into a parameter which owns all of its data.
+    pub fn into_owned(self) -> JailParamValue<'static> {
+        JailParamValue {
+            name:   owned(self.name),
+            value:  owned(self.value),
+            raw:    owned(self.raw),
+            append: self.append,
+            source: owned(self.source),
+            span:   self.span,
+        }
+    }
+}
+
+impl<'a> JailVariable<'a> {
+    /// Convert into a variable which owns all of its data.
+    pub fn into_owned(self) -> JailVariable<'static> {
+        JailVariable {
+            name:   owned(self.name),
+            value:  owned(self.value),
+            raw:    owned(self.raw),
+            append: self.append,
+            source: owned(self.source),
+            span:   self.span,
+        }
+    }
+}
+
+impl<'a> JailBlock<'a> {
+    /// Convert into a block which owns all of its data.
+    pub fn into_owned(self) -> JailBlock<'static> {
+        JailBlock {
+            name:        owned(self.name),
+            quote:       self.quote,
+            params:      self.params.into_iter().map(JailConf::into_owned).collect(),
+            annotations: self.annotations,
+            source:      owned(self.source),
+            span:        self.span,
+        }
+    }
+}
+
+impl<'a> JailConf<'a> {
+    /// Convert into a node which owns all of its data.
+    pub fn into_owned(self) -> JailConfOwned {
+        match self {
+            JailConf::Block(block)      => JailConf::Block(block.into_owned()),
+            JailConf::Comment(comment)  => JailConf::Comment(comment.into_owned()),
+            JailConf::Include(include)  => JailConf::Include(include.into_owned()),
+            JailConf::ParamBool(param)  => JailConf::ParamBool(param.into_owned()),
+            JailConf::ParamValue(param) => JailConf::ParamValue(param.into_owned()),
+            JailConf::Variable(var)     => JailConf::Variable(var.into_owned()),
+        }
+    }
+
+    /// Create a copy of this node which owns all of its data.
+    pub fn to_owned(&self) -> JailConfOwned {
+        self.clone().into_owned()
+    }
+}
+
+/// Convert a whole parsed configuration into one which owns all of its data.
+pub fn into_owned(config: Vec<JailConf>) -> Vec<JailConfOwned> {
+    config.into_iter().map(JailConf::into_owned).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use std::thread;
+
+    fn parse_owned(input: &str) -> Vec<JailConfOwned> {
+        let input = input.to_string();
+
+        // input is dropped at the end of this function, so the result must
+        // not borrow from it.
+        into_owned(parse(&input).unwrap())
+    }
+
+    #[test]
+    fn test_into_owned_outlives_input() {
+        let config = parse_owned("nginx {\n    host.hostname = \"nginx\";\n}\n");
+
+        let handle = thread::spawn(move || {
+            match &config[0] {
+                JailConf::Block(block) => block.name().to_string(),
+                _                      => panic!("expected a block"),
+            }
+        });
+
+        assert_eq!(handle.join().unwrap(), "nginx");
+    }
+
+    #[test]
+    fn test_to_owned_is_equal() {
+        let input = "persist;\n# comment\nnginx {\n    ip4.addr += \"127.0.1.1\";\n}\n";
+        let config = parse(input).unwrap();
+
+        for statement in &config {
+            assert_eq!(&statement.to_owned(), statement);
+        }
+    }
+}
@@ -0,0 +1,343 @@
+// Declarative changes to the parameters of jails.
+//
+// A JailPatch lists, for each jail, parameters to set, append to or unset,
+// and is applied to a LosslessConfig with the same edits `set_param`,
+// `append_param` and `remove_param` make, so everything it doesn't touch
+// keeps its formatting. With the serde feature a patch serializes as a map
+// of jail names to lists of operations, eg. in JSON:
+//
+//   {"www": [{"op": "set", "name": "host.hostname", "value": "www"},
+//            {"op": "unset", "name": "persist"}]}
+//
+// so that orchestration tools can send the change to make rather than a
+// whole file.
+//
+// Applying a patch gives back the patch reverting it, built from the values
+// each parameter had before it was changed. A patch is applied as a whole:
+// if any operation fails, eg. for a jail which isn't configured, the
+// configuration is left as it was.
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use crate::{
+    EditError,
+    JailConf,
+    LosslessConfig,
+};
+
+/// A change to one of a jail's parameters, see `JailPatch`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "op", rename_all = "lowercase"))]
+pub enum PatchOp {
+    /// Set the parameter with `=`, see `LosslessConfig::set_param`.
+    Set {
+        name:  String,
+        value: String,
+    },
+    /// Add a value to the parameter with `+=`, see
+    /// `LosslessConfig::append_param`.
+    Append {
+        name:  String,
+        value: String,
+    },
+    /// Remove every statement setting the parameter, see
+    /// `LosslessConfig::remove_param`.
+    Unset {
+        name: String,
+    },
+}
+
+impl PatchOp {
+    /// The parameter changed.
+    pub fn name(&self) -> &str {
+        match self {
+            PatchOp::Set { name, .. }    => name,
+            PatchOp::Append { name, .. } => name,
+            PatchOp::Unset { name }      => name,
+        }
+    }
+}
+
+impl fmt::Display for PatchOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatchOp::Set { name, value }    => write!(f, "set {} = {}", name, value),
+            PatchOp::Append { name, value } => write!(f, "append {} += {}", name, value),
+            PatchOp::Unset { name }         => write!(f, "unset {}", name),
+        }
+    }
+}
+
+/// Changes to the parameters of jails, applied in order for each jail. See
+/// `apply`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct JailPatch {
+    jails: BTreeMap<String, Vec<PatchOp>>,
+}
+
+// The statements setting the named parameter in the jail's blocks, as their
+// values and whether they were appended. Booleans have the value "true".
+fn current(config: &LosslessConfig, jail: &str, name: &str) -> Vec<(String, bool)> {
+    config.nodes()
+        .iter()
+        .filter(|node| matches!(node.statement(), JailConf::Block(block) if block.name() == jail))
+        .flat_map(|node| node.children())
+        .filter_map(|child| match child.statement() {
+            JailConf::ParamBool(param) if param.name() == name => {
+                Some(("true".to_string(), false))
+            },
+            JailConf::ParamValue(param) if param.name() == name => {
+                Some((param.value().to_string(), param.is_append()))
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+// The operations putting the parameter back to how it was, given the
+// statements setting it before op was applied.
+fn inverse(op: &PatchOp, before: Vec<(String, bool)>) -> Vec<PatchOp> {
+    let name = op.name().to_string();
+
+    // Setting a parameter set once with `=` only replaced its value.
+    if let (PatchOp::Set { .. }, [(value, false)]) = (op, before.as_slice()) {
+        return vec![PatchOp::Set {
+            name,
+            value: value.clone(),
+        }];
+    }
+
+    let mut ops = vec![PatchOp::Unset {
+        name: name.clone(),
+    }];
+
+    for (value, append) in before {
+        let name = name.clone();
+
+        ops.push(match append {
+            false => PatchOp::Set { name, value },
+            true  => PatchOp::Append { name, value },
+        });
+    }
+
+    // Unsetting a parameter which wasn't set changed nothing.
+    if matches!(op, PatchOp::Unset { .. }) && ops.len() == 1 {
+        ops.clear();
+    }
+
+    ops
+}
+
+impl JailPatch {
+    /// An empty patch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an operation for the named jail, after any already added for it.
+    pub fn push(mut self, jail: &str, op: PatchOp) -> Self {
+        self.jails.entry(jail.to_string()).or_default().push(op);
+        self
+    }
+
+    /// Set a parameter of the named jail with `=`.
+    pub fn set(self, jail: &str, name: &str, value: &str) -> Self {
+        self.push(jail, PatchOp::Set {
+            name:  name.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    /// Add a value to a parameter of the named jail with `+=`.
+    pub fn append(self, jail: &str, name: &str, value: &str) -> Self {
+        self.push(jail, PatchOp::Append {
+            name:  name.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    /// Remove a parameter from the named jail.
+    pub fn unset(self, jail: &str, name: &str) -> Self {
+        self.push(jail, PatchOp::Unset {
+            name: name.to_string(),
+        })
+    }
+
+    /// Whether the patch has no operations.
+    pub fn is_empty(&self) -> bool {
+        self.jails.values().all(Vec::is_empty)
+    }
+
+    /// The jails the patch changes, by name, with their operations in order.
+    pub fn jails(&self) -> impl Iterator<Item = (&str, &[PatchOp])> {
+        self.jails.iter().map(|(jail, ops)| (jail.as_str(), ops.as_slice()))
+    }
+
+    /// Apply the patch to the configuration, returning the patch which
+    /// reverts it. Applying that restores each parameter's values, though a
+    /// parameter set by several statements is put back at the end of the
+    /// jail's last block, and a boolean as `name = true`.
+    ///
+    /// If any operation fails, eg. for a jail which isn't configured, the
+    /// configuration is left as it was and the error returned.
+    pub fn apply(&self, config: &mut LosslessConfig) -> Result<JailPatch, EditError> {
+        let mut patched = config.clone();
+        let mut revert = JailPatch::new();
+
+        for (jail, ops) in &self.jails {
+            let mut undo: Vec<Vec<PatchOp>> = Vec::new();
+
+            for op in ops {
+                let before = current(&patched, jail, op.name());
+
+                match op {
+                    PatchOp::Set { name, value } => patched.set_param(jail, name, value)?,
+                    PatchOp::Append { name, value } => {
+                        patched.append_param(jail, name, value)?
+                    },
+                    PatchOp::Unset { name } => {
+                        patched.remove_param(jail, name)?;
+                    },
+                }
+
+                undo.push(inverse(op, before));
+            }
+
+            // Undone from the last operation back.
+            let undo: Vec<PatchOp> = undo.into_iter().rev().flatten().collect();
+
+            if !undo.is_empty() {
+                revert.jails.insert(jail.clone(), undo);
+            }
+        }
+
+        *config = patched;
+
+        Ok(revert)
+    }
+}
+
+// Each operation on a line of its own, after the jail it's for.
+impl fmt::Display for JailPatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (jail, ops) in self.jails() {
+            for op in ops {
+                writeln!(f, "{}: {}", jail, op)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        # Web servers.
+        www {
+            host.hostname   = www;     # Public name.
+            ip4.addr        = 192.0.2.1;
+            ip4.addr       += 192.0.2.2;
+            persist;
+        }
+
+        db {
+            path = /jails/db;
+        }
+        "#);
+
+    #[test]
+    fn test_apply() {
+        let mut config = LosslessConfig::parse(INPUT).unwrap();
+        let patch = JailPatch::new()
+            .set("www", "host.hostname", "web")
+            .append("www", "ip4.addr", "192.0.2.3")
+            .unset("www", "persist")
+            .set("db", "allow.mount", "1");
+
+        patch.apply(&mut config).unwrap();
+
+        assert_eq!(config.to_source(), indoc!(r#"
+            # Web servers.
+            www {
+                host.hostname = web;     # Public name.
+                ip4.addr        = 192.0.2.1;
+                ip4.addr       += 192.0.2.2;
+                ip4.addr += 192.0.2.3;
+            }
+
+            db {
+                path = /jails/db;
+                allow.mount = 1;
+            }
+            "#));
+    }
+
+    #[test]
+    fn test_revert() {
+        let original = LosslessConfig::parse(INPUT).unwrap();
+        let mut config = original.clone();
+        let patch = JailPatch::new()
+            .set("www", "host.hostname", "web")
+            .set("www", "ip4.addr", "192.0.2.9")
+            .unset("www", "persist")
+            .unset("db", "allow.mount");
+
+        let revert = patch.apply(&mut config).unwrap();
+
+        assert_eq!(revert.to_string(), indoc!("
+            www: unset persist
+            www: set persist = true
+            www: unset ip4.addr
+            www: set ip4.addr = 192.0.2.1
+            www: append ip4.addr += 192.0.2.2
+            www: set host.hostname = www
+            "));
+
+        revert.apply(&mut config).unwrap();
+
+        let restored = config.to_config();
+        let www = restored.effective_params("www").unwrap();
+
+        assert!(restored.semantically_eq(&original.to_config()));
+        assert_eq!(www["ip4.addr"], ["192.0.2.1", "192.0.2.2"]);
+        assert!(config.to_source().contains("host.hostname = www;     # Public name."));
+    }
+
+    #[test]
+    fn test_apply_error() {
+        let mut config = LosslessConfig::parse(INPUT).unwrap();
+        let patch = JailPatch::new()
+            .set("db", "persist", "1")
+            .set("missing", "persist", "1");
+
+        assert_eq!(patch.apply(&mut config), Err(EditError::UnknownJail {
+            name: "missing".to_string(),
+        }));
+        assert_eq!(config.to_source(), INPUT);
+        assert!(JailPatch::new().is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_patch_serde() {
+        let json = r#"{"www":[{"op":"set","name":"host.hostname","value":"web"},{"op":"unset","name":"persist"}]}"#;
+        let patch: JailPatch = serde_json::from_str(json).unwrap();
+
+        assert_eq!(patch, JailPatch::new()
+            .set("www", "host.hostname", "web")
+            .unset("www", "persist"));
+        assert_eq!(serde_json::to_string(&patch).unwrap(), json);
+    }
+}
@@ -0,0 +1,72 @@
+// The stable core of the API.
+//
+// The crate root exports everything the library offers, including items
+// which follow how the parser works inside, such as the token stream, the
+// nodes of the lossless tree and the lint and schema details, which can
+// change as the parser is reworked. The prelude is the part which won't
+// change without a new major version: parsing, the configuration and its
+// statements, editing, formatting, and the errors each can return.
+//
+// Downstream code which only needs these can `use jailconf::prelude::*;` and
+// be unaffected by changes elsewhere. The parser's nom combinators aren't
+// exported at all, so the parser can move to a new nom, or away from it,
+// without any change here.
+pub use crate::{
+    format_config,
+    parse,
+    parse_file,
+    parse_with,
+    parse_with_includes,
+    CommentStyle,
+    EditError,
+    EffectiveParams,
+    Error,
+    FormatOptions,
+    IncludeError,
+    JailBlock,
+    JailComment,
+    JailConf,
+    JailConfOwned,
+    JailConfig,
+    JailInclude,
+    JailParamBool,
+    JailParamValue,
+    JailVariable,
+    LosslessConfig,
+    ParseError,
+    ParseErrorKind,
+    ParseOptions,
+    Position,
+    QuoteStyle,
+    Span,
+    ValidationError,
+    VariableError,
+    Visitor,
+    VisitorMut,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Everything a typical program does, through the prelude alone.
+    #[test]
+    fn test_prelude() {
+        let input = "persist;\n\nwww {\n    host.hostname = www;\n}\n";
+        let config = JailConfig::parse(input).unwrap();
+
+        assert_eq!(config.get_param("www", "host.hostname").as_deref(), Some("www"));
+        assert_eq!(format_config(config.statements(), &FormatOptions::new()), input);
+
+        let mut lossless = LosslessConfig::parse(input).unwrap();
+        lossless.set_param("www", "path", "/jails/www").unwrap();
+        assert!(lossless.to_source().contains("path = /jails/www;"));
+
+        let error: ParseError = parse("www {\n").unwrap_err();
+        assert_eq!(error.kind(), ParseErrorKind::Syntax);
+        assert!(matches!(Error::from(IncludeError::Parse {
+            path:  "jail.conf".into(),
+            error,
+        }), Error::Include(_)));
+    }
+}
@@ -0,0 +1,284 @@
+// Where a jail's parameter gets its value from.
+//
+// A jail's value for a parameter can be built up from several places: a
+// global default, wildcard blocks matching the jail, the blocks of the jails
+// it's within and its own blocks, with each `=` replacing what came before
+// and each `+=` adding to it. When a jail doesn't behave as expected, the
+// question is usually which of these won. explain_param follows the same
+// steps as inherited_params, recording each statement setting the parameter,
+// where it was given, and the values the parameter has once it's applied.
+use std::fmt;
+use std::iter;
+use crate::config::{
+    apply,
+    EffectiveParams,
+};
+use crate::hierarchy::ancestor_names;
+use crate::name::negated_bool;
+use crate::{
+    JailConf,
+    JailConfig,
+};
+
+/// Where a statement applying to a jail was given.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParamSource {
+    /// Outside of any block, applying to every jail.
+    Global,
+    /// In a wildcard block matching the jail, eg. `web.*`.
+    Wildcard(String),
+    /// In a block of a jail the jail is within, eg. `foo` for `foo.bar`.
+    Parent(String),
+    /// In one of the jail's own blocks.
+    Jail,
+}
+
+impl fmt::Display for ParamSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamSource::Global         => f.write_str("global"),
+            ParamSource::Wildcard(name) => write!(f, "wildcard block {}", name),
+            ParamSource::Parent(name)   => write!(f, "parent jail {}", name),
+            ParamSource::Jail           => f.write_str("jail's own block"),
+        }
+    }
+}
+
+/// A statement setting the parameter, see `ParamExplanation`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParamStep<'c, 'a> {
+    source:    ParamSource,
+    statement: &'c JailConf<'a>,
+    values:    Vec<String>,
+}
+
+impl<'c, 'a> ParamStep<'c, 'a> {
+    /// Where the statement was given.
+    pub fn source(&self) -> &ParamSource {
+        &self.source
+    }
+
+    /// The statement, for its span and source text.
+    pub fn statement(&self) -> &'c JailConf<'a> {
+        self.statement
+    }
+
+    /// Whether the statement adds to the values before it, with `+=`,
+    /// rather than replacing them.
+    pub fn is_append(&self) -> bool {
+        matches!(self.statement, JailConf::ParamValue(param) if param.is_append())
+    }
+
+    /// The parameter's values once the statement is applied.
+    pub fn values(&self) -> &[String] {
+        &self.values
+    }
+}
+
+/// How a jail's parameter came to have its values, see
+/// `JailConfig::explain_param`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParamExplanation<'c, 'a> {
+    jail:  String,
+    name:  String,
+    steps: Vec<ParamStep<'c, 'a>>,
+}
+
+impl<'c, 'a> ParamExplanation<'c, 'a> {
+    /// The jail explained.
+    pub fn jail(&self) -> &str {
+        &self.jail
+    }
+
+    /// The parameter explained.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Each statement setting the parameter, in the order they apply.
+    pub fn steps(&self) -> &[ParamStep<'c, 'a>] {
+        &self.steps
+    }
+
+    /// The parameter's final values, empty if it isn't set.
+    pub fn values(&self) -> &[String] {
+        self.steps.last().map_or(&[], |step| step.values())
+    }
+}
+
+// Each step with its line and where it was given, followed by the values
+// it leaves the parameter with.
+impl<'c, 'a> fmt::Display for ParamExplanation<'c, 'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.steps.is_empty() {
+            return writeln!(f, "{} isn't set for {}", self.name, self.jail);
+        }
+
+        writeln!(f, "{} for {}:", self.name, self.jail)?;
+
+        for step in &self.steps {
+            let start = step.statement.span().start();
+
+            writeln!(
+                f,
+                "  {}:{}: {}: {}",
+                start.line(),
+                start.column(),
+                step.source,
+                step.statement.source_text(),
+            )?;
+            writeln!(f, "      = {}", step.values.join(", "))?;
+        }
+
+        writeln!(f, "{} is {}", self.name, self.values().join(", "))
+    }
+}
+
+// Whether statement sets the named parameter, including by the "no" form of
+// a boolean, as apply reads it.
+fn sets(statement: &JailConf, name: &str) -> bool {
+    let negated = match statement {
+        JailConf::ParamBool(param) if param.name() == name => return true,
+        JailConf::ParamValue(param) if param.name() == name => return true,
+        JailConf::ParamBool(param) => negated_bool(param.name(), None),
+        JailConf::ParamValue(param) if !param.is_append() => {
+            negated_bool(param.name(), Some(param.value()))
+        },
+        _ => None,
+    };
+
+    negated.is_some_and(|(positive, _)| positive == name)
+}
+
+impl<'a> JailConfig<'a> {
+    /// Every statement setting a parameter for the named jail, where it was
+    /// given and what the parameter's values are once it's applied, in the
+    /// order `inherited_params` applies them: global parameters, wildcard
+    /// blocks matching the jail, the blocks of the jails it's within and
+    /// then its own. None if there's no such jail.
+    ///
+    /// The explanation's `Display` prints the derivation, a statement at a
+    /// time.
+    pub fn explain_param<'c>(&'c self, jail: &str, name: &str)
+    -> Option<ParamExplanation<'c, 'a>> {
+        self.jail(jail)?;
+
+        let globals = self.statements().iter()
+            .map(|statement| (ParamSource::Global, statement));
+
+        let wildcards = self.wildcards()
+            .filter(|block| block.applies_to(jail))
+            .flat_map(|block| {
+                block.params()
+                    .iter()
+                    .map(move |param| (ParamSource::Wildcard(block.name().to_string()), param))
+            });
+
+        let ancestors = ancestor_names(jail)
+            .into_iter()
+            .map(|ancestor| (ParamSource::Parent(ancestor.to_string()), ancestor))
+            .chain(iter::once((ParamSource::Jail, jail)))
+            .flat_map(|(source, owner)| {
+                self.jails()
+                    .filter(move |block| block.name() == owner)
+                    .flat_map(|block| block.params())
+                    .map(move |param| (source.clone(), param))
+            });
+
+        let mut params = EffectiveParams::new();
+        let mut steps = Vec::new();
+
+        for (source, statement) in globals.chain(wildcards).chain(ancestors) {
+            if !sets(statement, name) {
+                continue;
+            }
+
+            apply(&mut params, iter::once(statement));
+
+            steps.push(ParamStep {
+                source,
+                statement,
+                values: params.get(name).cloned().unwrap_or_default(),
+            });
+        }
+
+        Some(ParamExplanation {
+            jail: jail.to_string(),
+            name: name.to_string(),
+            steps,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        ip4.addr = 192.0.2.1;
+        allow.mount;
+        web.* {
+            ip4.addr += 192.0.2.2;
+        }
+        web {
+            allow.nomount;
+        }
+        web.nginx {
+            ip4.addr += 192.0.2.3;
+            host.hostname = nginx;
+        }
+        "#);
+
+    #[test]
+    fn test_explain_param() {
+        let config = JailConfig::parse(INPUT).unwrap();
+        let explanation = config.explain_param("web.nginx", "ip4.addr").unwrap();
+
+        let sources: Vec<_> = explanation.steps().iter().map(|step| step.source()).collect();
+        assert_eq!(sources, [
+            &ParamSource::Global,
+            &ParamSource::Wildcard("web.*".to_string()),
+            &ParamSource::Jail,
+        ]);
+        assert!(explanation.steps()[2].is_append());
+        assert_eq!(explanation.steps()[1].values(), ["192.0.2.1", "192.0.2.2"]);
+        assert_eq!(explanation.values(), ["192.0.2.1", "192.0.2.2", "192.0.2.3"]);
+
+        let inherited = config.inherited_params("web.nginx").unwrap();
+        assert_eq!(explanation.values(), inherited["ip4.addr"].as_slice());
+
+        assert_eq!(explanation.to_string(), indoc!("
+            ip4.addr for web.nginx:
+              1:1: global: ip4.addr = 192.0.2.1;
+                  = 192.0.2.1
+              4:5: wildcard block web.*: ip4.addr += 192.0.2.2;
+                  = 192.0.2.1, 192.0.2.2
+              10:5: jail's own block: ip4.addr += 192.0.2.3;
+                  = 192.0.2.1, 192.0.2.2, 192.0.2.3
+            ip4.addr is 192.0.2.1, 192.0.2.2, 192.0.2.3
+            "));
+    }
+
+    #[test]
+    fn test_explain_param_negated_parent() {
+        let config = JailConfig::parse(INPUT).unwrap();
+        let explanation = config.explain_param("web.nginx", "allow.mount").unwrap();
+
+        assert_eq!(explanation.steps().len(), 2);
+        assert_eq!(explanation.steps()[1].source(), &ParamSource::Parent("web".to_string()));
+        assert_eq!(explanation.values(), ["false"]);
+    }
+
+    #[test]
+    fn test_explain_param_unset() {
+        let config = JailConfig::parse(INPUT).unwrap();
+        let explanation = config.explain_param("web", "path").unwrap();
+
+        assert!(explanation.steps().is_empty());
+        assert!(explanation.values().is_empty());
+        assert_eq!(explanation.to_string(), "path isn't set for web\n");
+
+        assert!(config.explain_param("missing", "path").is_none());
+    }
+}
@@ -0,0 +1,342 @@
+// Resource limit rules from rctl.conf(5).
+//
+// Limits on what jails may use are set with rctl(8) rules rather than in
+// jail.conf, usually kept in /etc/rctl.conf with one rule per line:
+//
+//   jail:www:memoryuse:deny=1g
+//   jail:db:pcpu:deny=50/jail
+//
+// Each rule is `subject:subject-id:resource:action=amount/per`, where the
+// amount may carry a k, m, g, t or p suffix and `/per` is optional. Subjects
+// may be abbreviated to their first letter, or `lc` for loginclass, as
+// rctl(8) allows. parse_rctl reads such a file, and
+// JailConfig::check_rctl compares the jail rules in it against the jails a
+// configuration defines, since a rule for a jail that's been renamed or
+// removed silently limits nothing.
+use std::error;
+use std::fmt;
+use crate::JailConfig;
+
+/// What an rctl rule applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RctlSubject {
+    /// A process, by pid.
+    Process,
+    /// A user, by name or uid.
+    User,
+    /// A login class, by name.
+    LoginClass,
+    /// A jail, by name or jid.
+    Jail,
+}
+
+impl RctlSubject {
+    /// The subject as rctl(8) writes it, eg. "jail".
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RctlSubject::Process    => "process",
+            RctlSubject::User       => "user",
+            RctlSubject::LoginClass => "loginclass",
+            RctlSubject::Jail       => "jail",
+        }
+    }
+
+    fn parse(subject: &str) -> Option<Self> {
+        match subject {
+            "process" | "p"     => Some(RctlSubject::Process),
+            "user" | "u"        => Some(RctlSubject::User),
+            "loginclass" | "lc" => Some(RctlSubject::LoginClass),
+            "jail" | "j"        => Some(RctlSubject::Jail),
+            _                   => None,
+        }
+    }
+}
+
+/// A single rctl rule, eg. `jail:www:memoryuse:deny=1g`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RctlRule {
+    subject:  RctlSubject,
+    id:       String,
+    resource: String,
+    action:   String,
+    amount:   String,
+    per:      Option<String>,
+    line:     usize,
+}
+
+impl RctlRule {
+    /// What the rule applies to.
+    pub fn subject(&self) -> RctlSubject {
+        self.subject
+    }
+
+    /// Which process, user, login class or jail the rule applies to.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The resource limited, eg. "memoryuse".
+    pub fn resource(&self) -> &str {
+        &self.resource
+    }
+
+    /// What happens when the limit is reached, eg. "deny" or "sigterm".
+    pub fn action(&self) -> &str {
+        &self.action
+    }
+
+    /// The limit, as written, eg. "1g".
+    pub fn amount(&self) -> &str {
+        &self.amount
+    }
+
+    /// What the amount is counted per, if other than the subject, eg.
+    /// "process".
+    pub fn per(&self) -> Option<&str> {
+        self.per.as_deref()
+    }
+
+    /// The line of the file the rule was read from.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for RctlRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}:{}={}",
+            self.subject.as_str(),
+            self.id,
+            self.resource,
+            self.action,
+            self.amount,
+        )?;
+
+        if let Some(per) = &self.per {
+            write!(f, "/{}", per)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RctlError {
+    /// A line isn't of the form `subject:subject-id:resource:action=amount`.
+    Syntax {
+        line: usize,
+        text: String,
+    },
+    /// A rule's subject isn't one rctl(8) knows.
+    UnknownSubject {
+        line:    usize,
+        subject: String,
+    },
+}
+
+impl fmt::Display for RctlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RctlError::Syntax { line, text } => {
+                write!(f, "line {}: expected an rctl rule: {}", line, text)
+            },
+            RctlError::UnknownSubject { line, subject } => {
+                write!(f, "line {}: unknown subject {}", line, subject)
+            },
+        }
+    }
+}
+
+impl error::Error for RctlError {}
+
+// Parse a single rule, found on the given line.
+fn rule(text: &str, line: usize) -> Result<RctlRule, RctlError> {
+    let syntax = || RctlError::Syntax {
+        line,
+        text: text.to_string(),
+    };
+
+    let (filter, limit) = text.split_once('=').ok_or_else(syntax)?;
+    let parts: Vec<&str> = filter.split(':').map(str::trim).collect();
+
+    let (subject, id, resource, action) = match parts.as_slice() {
+        [subject, id, resource, action] => (*subject, *id, *resource, *action),
+        _                                => return Err(syntax()),
+    };
+
+    let subject = RctlSubject::parse(subject).ok_or_else(|| {
+        RctlError::UnknownSubject {
+            line,
+            subject: subject.to_string(),
+        }
+    })?;
+
+    let (amount, per) = match limit.trim().split_once('/') {
+        Some((amount, per)) => (amount, Some(per.to_string())),
+        None                => (limit.trim(), None),
+    };
+
+    if id.is_empty() || resource.is_empty() || action.is_empty() || amount.is_empty() {
+        return Err(syntax());
+    }
+
+    Ok(RctlRule {
+        subject,
+        id:       id.to_string(),
+        resource: resource.to_string(),
+        action:   action.to_string(),
+        amount:   amount.to_string(),
+        per,
+        line,
+    })
+}
+
+/// Parse rctl rules in the format of rctl.conf(5), one per line. Blank
+/// lines and `#` comments are skipped.
+pub fn parse_rctl(input: &str) -> Result<Vec<RctlRule>, RctlError> {
+    let mut rules = Vec::new();
+
+    for (number, line) in input.lines().enumerate() {
+        let text = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None          => line,
+        }.trim();
+
+        if !text.is_empty() {
+            rules.push(rule(text, number + 1)?);
+        }
+    }
+
+    Ok(rules)
+}
+
+/// The result of checking rctl rules against a configuration, see
+/// `JailConfig::check_rctl`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RctlReport<'r> {
+    orphaned:  Vec<&'r RctlRule>,
+    unlimited: Vec<String>,
+}
+
+impl<'r> RctlReport<'r> {
+    /// Jail rules for jails the configuration doesn't define.
+    pub fn orphaned_rules(&self) -> &[&'r RctlRule] {
+        &self.orphaned
+    }
+
+    /// Jails the configuration defines which no rule limits.
+    pub fn unlimited_jails(&self) -> &[String] {
+        &self.unlimited
+    }
+
+    /// Whether the rules and jails match up in both directions.
+    pub fn is_clean(&self) -> bool {
+        self.orphaned.is_empty() && self.unlimited.is_empty()
+    }
+}
+
+impl<'a> JailConfig<'a> {
+    /// Compare the jail rules among rules with the jails defined here,
+    /// reporting the rules for jails which don't exist and the jails no rule
+    /// applies to. Rules for other subjects are ignored. A rule naming a jail
+    /// by jid matches the jail whose `jid` parameter sets that jid.
+    pub fn check_rctl<'r>(&self, rules: &'r [RctlRule]) -> RctlReport<'r> {
+        let jails: Vec<(&str, Option<String>)> = self.jails()
+            .filter(|block| block.name() != "*")
+            .map(|block| (block.name(), self.get_param(block.name(), "jid")))
+            .collect();
+
+        let names = |rule: &RctlRule, (name, jid): &(&str, Option<String>)| {
+            rule.id == *name || jid.as_deref() == Some(rule.id.as_str())
+        };
+
+        let jail_rules: Vec<&RctlRule> = rules.iter()
+            .filter(|rule| rule.subject == RctlSubject::Jail)
+            .collect();
+
+        let orphaned = jail_rules.iter()
+            .filter(|rule| !jails.iter().any(|jail| names(rule, jail)))
+            .copied()
+            .collect();
+
+        let mut unlimited: Vec<String> = Vec::new();
+
+        for jail in &jails {
+            let limited = jail_rules.iter().any(|rule| names(rule, jail));
+
+            if !limited && !unlimited.iter().any(|name| name == jail.0) {
+                unlimited.push(jail.0.to_string());
+            }
+        }
+
+        RctlReport {
+            orphaned,
+            unlimited,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const RULES: &str = indoc!(r#"
+        # Limits for the web jails.
+        jail:www:memoryuse:deny=1g
+        j:www:pcpu:deny=50/jail   # at most half a CPU
+        jail:old:maxproc:deny=100
+        jail:7:vmemoryuse:log=2g
+        user:joe:maxproc:sigterm=20/process
+        "#);
+
+    #[test]
+    fn test_parse_rctl() {
+        let rules = parse_rctl(RULES).unwrap();
+
+        assert_eq!(rules.len(), 5);
+        assert_eq!(rules[1].subject(), RctlSubject::Jail);
+        assert_eq!(rules[1].resource(), "pcpu");
+        assert_eq!(rules[1].amount(), "50");
+        assert_eq!(rules[1].per(), Some("jail"));
+        assert_eq!(rules[1].line(), 3);
+        assert_eq!(rules[1].to_string(), "jail:www:pcpu:deny=50/jail");
+        assert_eq!(rules[4].subject(), RctlSubject::User);
+    }
+
+    #[test]
+    fn test_parse_rctl_errors() {
+        assert_eq!(
+            parse_rctl("jail:www:memoryuse\n").unwrap_err(),
+            RctlError::Syntax { line: 1, text: "jail:www:memoryuse".to_string() },
+        );
+        assert_eq!(
+            parse_rctl("\nhost:x:maxproc:deny=1\n").unwrap_err(),
+            RctlError::UnknownSubject { line: 2, subject: "host".to_string() },
+        );
+    }
+
+    #[test]
+    fn test_check_rctl() {
+        let config = JailConfig::parse(indoc!(r#"
+            * { persist; }
+            www { path = /jails/www; }
+            db { jid = 7; }
+            mail { path = /jails/mail; }
+            "#)).unwrap();
+
+        let rules = parse_rctl(RULES).unwrap();
+        let report = config.check_rctl(&rules);
+
+        let orphaned: Vec<String> = report.orphaned_rules()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(orphaned, vec!["jail:old:maxproc:deny=100"]);
+        assert_eq!(report.unlimited_jails(), &["mail".to_string()]);
+        assert!(!report.is_clean());
+    }
+}
@@ -0,0 +1,460 @@
+// Masking of sensitive values, for sharing a configuration.
+//
+// A configuration attached to a bug report or posted to a forum often gives
+// away more than its author meant to: addresses, hostnames, where things
+// live on disk. redact replaces these while keeping everything else, the
+// jails, their parameters and the comments, as it was, so that the
+// configuration still shows the problem.
+//
+// Values are split into words of the characters addresses, hostnames and
+// paths are made of, and each word is checked in turn, so that an address
+// is found within eg. `em0|192.0.2.1/24` or an `exec.start` command. Each
+// distinct address, hostname or path is given a replacement of its own,
+// used everywhere it appears, so that a reader can still tell two jails
+// sharing an address from two which don't. Addresses are replaced from
+// ranges reserved for benchmarking and documentation, hostnames with names
+// under example.org. Loopback and unspecified addresses give nothing away
+// and are kept.
+//
+// Jail names are part of the structure and are kept, as are variable
+// references, so a value built from `$name` still is. The values of
+// variables hostnames are built from are masked as hostnames themselves.
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::{
+    IpAddr,
+    Ipv4Addr,
+    Ipv6Addr,
+};
+use glob::Pattern;
+use crate::builder::raw_value;
+use crate::escape::{
+    quote_literal,
+    quote_style,
+};
+use crate::variables::references;
+use crate::visit::{
+    walk,
+    walk_mut,
+    Visitor,
+    VisitorMut,
+};
+use crate::{
+    into_owned,
+    JailComment,
+    JailConfig,
+    JailInclude,
+    JailParamValue,
+    JailVariable,
+    QuoteStyle,
+};
+
+/// The value given to parameters listed with `RedactPolicy::param`.
+pub const REDACTED: &str = "REDACTED";
+
+// The parameters whose values are hostnames.
+const HOSTNAME_PARAMS: &[&str] = &["host.hostname", "host.domainname"];
+
+/// What `JailConfig::redact` masks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RedactPolicy {
+    addresses: bool,
+    hostnames: bool,
+    paths:     Vec<Pattern>,
+    params:    Vec<String>,
+}
+
+impl Default for RedactPolicy {
+    fn default() -> Self {
+        Self {
+            addresses: true,
+            hostnames: true,
+            paths:     Vec::new(),
+            params:    Vec::new(),
+        }
+    }
+}
+
+impl RedactPolicy {
+    /// The default policy: addresses and hostnames are masked, paths and the
+    /// values of other parameters aren't.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether to mask IPv4 and IPv6 addresses, wherever they appear.
+    pub fn addresses(mut self, addresses: bool) -> Self {
+        self.addresses = addresses;
+        self
+    }
+
+    /// Whether to mask the jails' hostnames, those given by `host.hostname`
+    /// and `host.domainname`, wherever they appear.
+    pub fn hostnames(mut self, hostnames: bool) -> Self {
+        self.hostnames = hostnames;
+        self
+    }
+
+    /// Mask paths matching the glob pattern, eg. `/home/*`, wherever they
+    /// appear.
+    pub fn path(mut self, pattern: &str) -> Result<Self, glob::PatternError> {
+        self.paths.push(Pattern::new(pattern)?);
+        Ok(self)
+    }
+
+    /// Replace the whole value of the named parameter with `REDACTED`.
+    /// Variables are named with their `$`, eg. `$password`.
+    pub fn param(mut self, name: &str) -> Self {
+        self.params.push(name.to_string());
+        self
+    }
+}
+
+// The hostnames set in a configuration, along with the variables hostnames
+// are built from and the values every variable is given.
+#[derive(Default)]
+struct Hostnames {
+    hostnames:   Vec<String>,
+    referenced:  Vec<String>,
+    definitions: Vec<(String, String)>,
+}
+
+impl Hostnames {
+    fn add(&mut self, hostname: &str) {
+        if !hostname.is_empty() && !self.hostnames.iter().any(|known| known == hostname) {
+            self.hostnames.push(hostname.to_string());
+        }
+    }
+
+    // Add the values of the variables hostnames are built from, which would
+    // otherwise give away the parts they name, eg. the domain of
+    // `host.hostname = "web.$domain";`. Variables may themselves be built
+    // from others.
+    fn add_referenced(&mut self) {
+        let mut i = 0;
+
+        while i < self.referenced.len() {
+            let values: Vec<String> = self.definitions.iter()
+                .filter(|(name, _)| *name == self.referenced[i])
+                .map(|(_, raw)| raw.clone())
+                .collect();
+
+            for raw in values {
+                let (text, names) = references(&raw);
+
+                if names.is_empty() {
+                    self.add(&text);
+                }
+
+                for name in names {
+                    if !self.referenced.iter().any(|known| known == name) {
+                        self.referenced.push(name.to_string());
+                    }
+                }
+            }
+
+            i += 1;
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Hostnames {
+    fn visit_param_value(&mut self, param: &JailParamValue<'a>) {
+        if !HOSTNAME_PARAMS.contains(&param.name()) {
+            return;
+        }
+
+        let (_, names) = references(param.raw());
+
+        if names.is_empty() {
+            self.add(param.value());
+        }
+
+        for name in names {
+            if !self.referenced.iter().any(|known| known == name) {
+                self.referenced.push(name.to_string());
+            }
+        }
+    }
+
+    fn visit_variable(&mut self, var: &JailVariable<'a>) {
+        self.definitions.push((var.name().to_string(), var.raw().to_string()));
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | ':' | '/' | '-' | '_')
+}
+
+// The replacement for the nth distinct address of its family.
+fn address(original: IpAddr, n: usize) -> IpAddr {
+    // Past the end of the ranges, which a configuration won't reach,
+    // replacements repeat.
+    let n = n as u32;
+
+    match original {
+        // 198.18.0.0/15, reserved for benchmarking.
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(0xc612_0000 + (n % 0x1_fffe) + 1)),
+        // 2001:db8::/32, reserved for documentation.
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from((0x2001_0db8 << 96) | (u128::from(n) + 1))),
+    }
+}
+
+// Redacts values, giving each distinct one the same replacement throughout.
+struct Redactor<'p> {
+    policy:    &'p RedactPolicy,
+    hostnames: Vec<String>,
+    seen:      HashMap<String, String>,
+    v4:        usize,
+    v6:        usize,
+    paths:     usize,
+}
+
+impl<'p> Redactor<'p> {
+    // The replacement for a word, if it needs one.
+    fn word(&mut self, word: &str) -> Option<String> {
+        // An address may have a prefix length, or be a netmask, which is
+        // kept. The address is replaced the same way with or without it.
+        let (key, suffix) = match word.find('/') {
+            Some(slash) if self.policy.addresses && word[..slash].parse::<IpAddr>().is_ok() => {
+                word.split_at(slash)
+            },
+            _ => (word, ""),
+        };
+
+        if let Some(replacement) = self.seen.get(key) {
+            return Some(format!("{}{}", replacement, suffix));
+        }
+
+        let replacement = self.replace(key)?;
+        self.seen.insert(key.to_string(), replacement.clone());
+
+        Some(format!("{}{}", replacement, suffix))
+    }
+
+    fn replace(&mut self, word: &str) -> Option<String> {
+        if let Ok(addr) = word.parse::<IpAddr>() {
+            if !self.policy.addresses || addr.is_loopback() || addr.is_unspecified() {
+                return None;
+            }
+
+            let counter = if addr.is_ipv4() { &mut self.v4 } else { &mut self.v6 };
+            let replacement = address(addr, *counter);
+            *counter += 1;
+
+            return Some(replacement.to_string());
+        }
+
+        if let Some(n) = self.hostnames.iter().position(|hostname| hostname == word) {
+            return Some(format!("host{}.example.org", n + 1));
+        }
+
+        if word.starts_with('/') && self.policy.paths.iter().any(|pattern| pattern.matches(word)) {
+            self.paths += 1;
+            return Some(format!("/redacted/{}", self.paths));
+        }
+
+        None
+    }
+
+    // Text with each word needing it replaced, or None if none do.
+    fn text(&mut self, text: &str) -> Option<String> {
+        let mut output = String::with_capacity(text.len());
+        let mut changed = false;
+        let mut rest = text;
+
+        while !rest.is_empty() {
+            let start = rest.find(is_word_char).unwrap_or(rest.len());
+            output.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            // Variable names, after a `$` or `${`, are kept.
+            let variable = output.ends_with('$') || output.ends_with("${");
+            let end = rest.find(|c| !is_word_char(c)).unwrap_or(rest.len());
+
+            // A dot ending a sentence isn't part of the word.
+            let word = match rest[..end].trim_end_matches('.') {
+                ""   => &rest[..end],
+                word => word,
+            };
+
+            match self.word(word).filter(|_| !variable) {
+                Some(replacement) => {
+                    output.push_str(&replacement);
+                    changed = true;
+                },
+                None => output.push_str(word),
+            }
+
+            rest = &rest[word.len()..];
+        }
+
+        Some(output).filter(|_| changed)
+    }
+
+    // The redacted value of a parameter or variable, and how to write it,
+    // or None if it's unchanged.
+    fn value(&mut self, name: &str, value: &str, raw: &str) -> Option<(String, String)> {
+        let value = if self.policy.params.iter().any(|param| param == name) {
+            REDACTED.to_string()
+        }
+        else {
+            self.text(value)?
+        };
+
+        // Keep a `$` taken literally that way.
+        let raw = if quote_style(raw) == QuoteStyle::Single && value.contains('$') {
+            quote_literal(&value)
+        }
+        else {
+            raw_value(&value)
+        };
+
+        Some((value, raw))
+    }
+}
+
+impl<'p, 'a> VisitorMut<'a> for Redactor<'p> {
+    fn visit_comment_mut(&mut self, comment: &mut JailComment<'a>) {
+        if let Some(text) = self.text(comment.comment()) {
+            comment.comment = Cow::Owned(text);
+            comment.source = Cow::Owned(comment.to_string());
+        }
+    }
+
+    fn visit_include_mut(&mut self, include: &mut JailInclude<'a>) {
+        if let Some(path) = self.text(include.path()) {
+            include.path = Cow::Owned(path);
+            include.source = Cow::Owned(include.to_string());
+        }
+    }
+
+    fn visit_param_value_mut(&mut self, param: &mut JailParamValue<'a>) {
+        if let Some((value, raw)) = self.value(param.name(), param.value(), param.raw()) {
+            param.value = Cow::Owned(value);
+            param.raw = Cow::Owned(raw);
+            param.source = Cow::Owned(param.to_string());
+        }
+    }
+
+    fn visit_variable_mut(&mut self, var: &mut JailVariable<'a>) {
+        let name = format!("${}", var.name());
+
+        if let Some((value, raw)) = self.value(&name, var.value(), var.raw()) {
+            var.value = Cow::Owned(value);
+            var.raw = Cow::Owned(raw);
+            var.source = Cow::Owned(var.to_string());
+        }
+    }
+}
+
+impl<'a> JailConfig<'a> {
+    /// A copy of the configuration with the values the policy covers
+    /// masked, for sharing. The jails, parameters and comments are kept, and
+    /// each distinct value masked is given the same replacement everywhere,
+    /// see `RedactPolicy`.
+    pub fn redact(&self, policy: &RedactPolicy) -> JailConfig<'static> {
+        let mut hostnames = Hostnames::default();
+
+        if policy.hostnames {
+            walk(self.statements(), &mut hostnames);
+
+            // Hostnames built from variables appear in full once expanded.
+            if let Ok(resolved) = self.resolve_variables() {
+                walk(resolved.statements(), &mut hostnames);
+            }
+
+            hostnames.add_referenced();
+        }
+
+        let mut redactor = Redactor {
+            policy,
+            hostnames: hostnames.hostnames,
+            seen:      HashMap::new(),
+            v4:        0,
+            v6:        0,
+            paths:     0,
+        };
+
+        let mut statements = into_owned(self.statements().to_vec());
+        walk_mut(&mut statements, &mut redactor);
+
+        JailConfig::new(statements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        # Managed from 203.0.113.50.
+        $secret = "hunter2";
+        path = "/usr/jails/$name";
+        www {
+            host.hostname = www.corp.internal;
+            ip4.addr = "em0|10.1.2.3/24", 127.0.0.1;
+            ip6.addr = 2001:470::5;
+            exec.start = "/home/alice/bin/start www.corp.internal 10.1.2.3";
+            mount.fstab = '/home/alice/$name.fstab';
+        }
+        db {
+            ip4.addr = 10.1.2.4;
+        }
+        "#);
+
+    #[test]
+    fn test_redact() {
+        let config = JailConfig::parse(INPUT).unwrap();
+        let policy = RedactPolicy::new()
+            .path("/home/alice/*").unwrap()
+            .param("$secret");
+
+        assert_eq!(config.redact(&policy).to_string(), indoc!(r#"
+            # Managed from 198.18.0.1.
+            $secret = REDACTED;
+            path = "/usr/jails/$name";
+            www {
+                host.hostname = host1.example.org;
+                ip4.addr = "em0|198.18.0.2/24,127.0.0.1";
+                ip6.addr = 2001:db8::1;
+                exec.start = "/redacted/1 host1.example.org 198.18.0.2";
+                mount.fstab = "/redacted/2\$name.fstab";
+            }
+
+            db {
+                ip4.addr = 198.18.0.3;
+            }
+            "#));
+    }
+
+    #[test]
+    fn test_redact_hostname_variables() {
+        let config = JailConfig::parse(indoc!(r#"
+            $dom = "secret.corp";
+            web {
+                host.hostname = "web.$dom";
+                exec.start = "ping -c1 web.secret.corp";
+            }
+            "#)).unwrap();
+
+        let redacted = config.redact(&RedactPolicy::new()).to_string();
+
+        assert!(!redacted.contains("secret.corp"), "{}", redacted);
+        assert!(redacted.contains("host.hostname = \"web.$dom\";"), "{}", redacted);
+    }
+
+    #[test]
+    fn test_redact_policy() {
+        let config = JailConfig::parse(INPUT).unwrap();
+        let policy = RedactPolicy::new()
+            .addresses(false)
+            .hostnames(false);
+
+        let redacted = config.redact(&policy);
+
+        assert_eq!(redacted, JailConfig::new(into_owned(config.statements().to_vec())));
+        assert!(RedactPolicy::new().path("[").is_err());
+    }
+}
@@ -0,0 +1,231 @@
+// Conversion of comments from one style to another.
+//
+// Configurations gathered from different places tend to mix `#`, `//` and
+// `/* */` comments. restyle_comments rewrites every comment in the chosen
+// style while leaving the rest of the input, whitespace included, exactly as
+// it was, so that the only lines to change are those holding comments.
+//
+// Line comments are converted one for one, so converting to C style and back
+// gives the input again. A C style comment spanning several lines becomes a
+// line comment per line, indented to match the line it started on, with any
+// `*` running down its left edge dropped. A few comments can't be converted
+// without changing what they say or where they are, and are left as they
+// were:
+//
+//   - A C style comment with more of the configuration after it on the same
+//     line, which a line comment would swallow.
+//   - A line comment containing `*/`, which would end a C style comment
+//     early.
+use crate::lex::{
+    lex,
+    Token,
+};
+use crate::{
+    parse,
+    CommentStyle,
+    ParseError,
+    Span,
+};
+
+// The style and text of a comment, without its markers.
+fn split_comment(comment: &str) -> (CommentStyle, &str) {
+    if let Some(text) = comment.strip_prefix('#') {
+        (CommentStyle::Shell, text)
+    }
+    else if let Some(text) = comment.strip_prefix("//") {
+        (CommentStyle::CPP, text)
+    }
+    else {
+        (CommentStyle::C, &comment[2..comment.len() - 2])
+    }
+}
+
+fn line_marker(style: CommentStyle) -> &'static str {
+    match style {
+        CommentStyle::CPP => "//",
+        _                 => "#",
+    }
+}
+
+// The lines of a C style comment's text, ready to become line comments.
+fn comment_lines(text: &str) -> Vec<String> {
+    let mut lines: Vec<&str> = text.split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect();
+
+    // Drop the lines holding nothing but the markers, eg. of a comment
+    // starting `/*` and ending ` */` on lines of their own.
+    if lines.len() > 1 && lines[lines.len() - 1].trim().is_empty() {
+        lines.pop();
+    }
+
+    let first = match lines[0] {
+        first if lines.len() > 1 && first.trim().is_empty() => None,
+        first => Some(first.trim_end().to_string()),
+    };
+
+    let mut rest: Vec<&str> = lines[1..].iter().map(|line| line.trim_start()).collect();
+
+    if !rest.is_empty() && rest.iter().all(|line| line.starts_with('*')) {
+        rest = rest.iter().map(|line| &line[1..]).collect();
+    }
+
+    let mut converted: Vec<String> = first.into_iter().collect();
+
+    converted.extend(rest.iter().map(|line| match line.trim_end() {
+        ""   => String::new(),
+        line if line.starts_with(char::is_whitespace) => line.to_string(),
+        line => format!(" {}", line),
+    }));
+
+    converted
+}
+
+// The indentation of the line containing offset.
+fn indent_at(input: &str, offset: usize) -> &str {
+    let start = input[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+    let line = &input[start..offset];
+
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+// Whether more than whitespace follows the token at index on its line.
+fn code_follows(tokens: &[(Token<'_>, Span)], index: usize) -> bool {
+    for (token, _) in &tokens[index + 1..] {
+        match token {
+            Token::Whitespace(space) if space.contains('\n') => return false,
+            Token::Whitespace(_)                            => {},
+            _                                               => return true,
+        }
+    }
+
+    false
+}
+
+/// Rewrite every comment in input in the given style, leaving everything
+/// else as it was. Comments which can't be converted without changing their
+/// meaning or moving the configuration around them are left alone.
+pub fn restyle_comments(input: &str, style: CommentStyle) -> Result<String, ParseError> {
+    parse(input)?;
+
+    let tokens = lex(input);
+    let mut out = String::with_capacity(input.len());
+
+    for (i, (token, span)) in tokens.iter().enumerate() {
+        let comment = match token {
+            Token::Comment(comment) => *comment,
+            _                       => {
+                out.push_str(&input[span.range()]);
+                continue;
+            },
+        };
+
+        let (from, text) = split_comment(comment);
+
+        // A line comment ends before the `\r` of a `\r\n` line ending.
+        let (text, cr) = match text.strip_suffix('\r') {
+            Some(text) if from != CommentStyle::C => (text, "\r"),
+            _                                     => (text, ""),
+        };
+
+        match (from, style) {
+            (from, to) if from == to => out.push_str(comment),
+            (CommentStyle::C, to) => {
+                if code_follows(&tokens, i) {
+                    out.push_str(comment);
+                    continue;
+                }
+
+                let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+                let indent = indent_at(input, span.start().offset());
+                let marker = line_marker(to);
+
+                for (n, line) in comment_lines(text).iter().enumerate() {
+                    if n > 0 {
+                        out.push_str(newline);
+                        out.push_str(indent);
+                    }
+
+                    out.push_str(marker);
+                    out.push_str(line);
+                }
+            },
+            (_, CommentStyle::C) if text.contains("*/") => out.push_str(comment),
+            (_, CommentStyle::C) => {
+                let pad = if text.starts_with(char::is_whitespace) { " " } else { "" };
+
+                out.push_str(&format!("/*{}{}*/{}", text.trim_end(), pad, cr));
+            },
+            (_, to) => {
+                out.push_str(&format!("{}{}{}", line_marker(to), text, cr));
+            },
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::JailConfig;
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        # Jails are started at boot.
+        exec.clean;   // as root
+        www {
+            /* The web server. */
+            path = "/jails/#www";
+            /*
+             * Started after
+             *   the database.
+             */
+            depend = db;
+            mount.devfs;# no space
+            /* inline */ persist;
+        }
+        "#);
+
+    #[test]
+    fn test_restyle_comments() {
+        let shell = restyle_comments(INPUT, CommentStyle::Shell).unwrap();
+
+        assert_eq!(shell, indoc!(r#"
+            # Jails are started at boot.
+            exec.clean;   # as root
+            www {
+                # The web server.
+                path = "/jails/#www";
+                # Started after
+                #   the database.
+                depend = db;
+                mount.devfs;# no space
+                /* inline */ persist;
+            }
+            "#));
+
+        let cpp = restyle_comments(&shell, CommentStyle::CPP).unwrap();
+        assert!(cpp.contains("mount.devfs;// no space"));
+        assert!(cpp.contains("    //   the database.\n"));
+
+        let c = restyle_comments(&shell, CommentStyle::C).unwrap();
+        assert!(c.starts_with("/* Jails are started at boot. */\nexec.clean;   /* as root */\n"));
+
+        // Line comments survive a trip through C style.
+        assert_eq!(restyle_comments(&c, CommentStyle::Shell).unwrap(), shell);
+        assert_eq!(
+            JailConfig::parse(&c).unwrap().normalize(),
+            JailConfig::parse(INPUT).unwrap().normalize(),
+        );
+    }
+
+    #[test]
+    fn test_restyle_comments_unconvertible() {
+        assert_eq!(
+            restyle_comments("# a */ b\npersist;\r\n//x\r\n", CommentStyle::C).unwrap(),
+            "# a */ b\npersist;\r\n/*x*/\r\n",
+        );
+        assert!(restyle_comments("www {", CommentStyle::C).is_err());
+    }
+}
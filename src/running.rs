@@ -0,0 +1,222 @@
+// Comparison of configuration against the jails running on this host.
+//
+// Hand made changes to running jails, such as an address added with
+// `jail -m`, are lost the next time the jail is restarted from jail.conf.
+// diff_running finds them by reading the running jails with jail_get(2) and
+// comparing them with the effective parameters of the configured jails.
+//
+// This module is only available on FreeBSD with the `sys` feature enabled.
+use std::fmt;
+use crate::sys::{
+    running_jails,
+    RunningJail,
+    SysError,
+};
+use crate::value::parse_ip_addr;
+use crate::{
+    EffectiveParams,
+    JailConfig,
+};
+
+// Parameters compared only when the configuration sets them, since the
+// kernel always reports a value.
+const COMPARED: &[&str] = &["path", "host.hostname"];
+
+// Address parameters, which are always compared so that addresses added to
+// a jail configured without any are found.
+const ADDRESSES: &[&str] = &["ip4.addr", "ip6.addr"];
+
+/// A difference between the configuration and the running jails.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RunningDiff {
+    /// A configured jail isn't running.
+    NotRunning {
+        jail: String,
+    },
+    /// A running jail isn't configured.
+    NotConfigured {
+        jail: String,
+        jid:  i32,
+    },
+    /// A parameter of a running jail differs from the configuration.
+    Param {
+        jail:       String,
+        name:       String,
+        configured: Vec<String>,
+        running:    Vec<String>,
+    },
+}
+
+impl fmt::Display for RunningDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunningDiff::NotRunning { jail } => {
+                write!(f, "{}: configured but not running", jail)
+            },
+            RunningDiff::NotConfigured { jail, jid } => {
+                write!(f, "{}: running as jid {} but not configured", jail, jid)
+            },
+            RunningDiff::Param { jail, name, configured, running } => {
+                write!(
+                    f,
+                    "{}: {} is {:?} but configured as {:?}",
+                    jail,
+                    name,
+                    running.join(","),
+                    configured.join(","),
+                )
+            },
+        }
+    }
+}
+
+// Addresses in a canonical form, without interfaces or prefix lengths, and
+// sorted, since the kernel doesn't keep them in the order they were given.
+fn addresses(values: &[String]) -> Vec<String> {
+    let mut addrs: Vec<String> = values.iter()
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            parse_ip_addr(entry).map_or_else(|| entry.to_string(), |addr| addr.to_string())
+        })
+        .collect();
+
+    addrs.sort();
+    addrs
+}
+
+fn compare(jail: &str, params: &EffectiveParams, running: &RunningJail)
+-> Vec<RunningDiff> {
+    let mut diffs = Vec::new();
+    let empty = Vec::new();
+
+    let mut push = |name: &str, configured: Vec<String>, running: Vec<String>| {
+        if configured != running {
+            diffs.push(RunningDiff::Param {
+                jail: jail.to_string(),
+                name: name.to_string(),
+                configured,
+                running,
+            });
+        }
+    };
+
+    for name in COMPARED {
+        if let Some(values) = params.get(*name) {
+            let configured = values.last().cloned().into_iter().collect();
+            let actual = running.params().get(*name).unwrap_or(&empty).clone();
+
+            push(*name, configured, actual);
+        }
+    }
+
+    for name in ADDRESSES {
+        let configured = addresses(params.get(*name).unwrap_or(&empty));
+        let actual = addresses(running.params().get(*name).unwrap_or(&empty));
+
+        push(*name, configured, actual);
+    }
+
+    diffs
+}
+
+impl<'a> JailConfig<'a> {
+    /// Compare the configuration with the jails running on this host. See
+    /// `diff_against`.
+    pub fn diff_running(&self) -> Result<Vec<RunningDiff>, SysError> {
+        Ok(self.diff_against(&running_jails()?))
+    }
+
+    /// Compare the configuration with the given running jails, returning the
+    /// configured jails which aren't running, the running jails which aren't
+    /// configured, and any differences in the path, hostname or addresses of
+    /// those which are both. The path and hostname are only compared if
+    /// they're configured.
+    ///
+    /// Values are compared as written, so variables should be resolved
+    /// first, see `resolve_variables`.
+    pub fn diff_against(&self, running: &[RunningJail]) -> Vec<RunningDiff> {
+        let mut diffs = Vec::new();
+        let mut names: Vec<&str> = Vec::new();
+
+        for block in self.jails() {
+            if !names.contains(&block.name()) {
+                names.push(block.name());
+            }
+        }
+
+        for name in &names {
+            let params = self.effective_params(name).unwrap_or_default();
+
+            match running.iter().find(|jail| jail.name() == *name) {
+                Some(jail) => diffs.extend(compare(name, &params, jail)),
+                None       => diffs.push(RunningDiff::NotRunning {
+                    jail: name.to_string(),
+                }),
+            }
+        }
+
+        for jail in running {
+            if !names.contains(&jail.name()) {
+                diffs.push(RunningDiff::NotConfigured {
+                    jail: jail.name().to_string(),
+                    jid:  jail.jid(),
+                });
+            }
+        }
+
+        diffs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    fn running(jid: i32, name: &str, params: &[(&str, &[&str])]) -> RunningJail {
+        let params = params.iter()
+            .map(|(name, values)| {
+                (name.to_string(), values.iter().map(|v| v.to_string()).collect())
+            })
+            .collect();
+
+        RunningJail::new(jid, name.to_string(), params)
+    }
+
+    #[test]
+    fn test_diff_against() {
+        let input = indoc!(r#"
+            nginx {
+                path = /usr/jails/nginx;
+                ip4.addr = "lo1|127.0.1.1/32";
+                ip4.addr += 127.0.1.2;
+            }
+            db {
+                path = /usr/jails/db;
+            }
+            "#);
+
+        let config = JailConfig::parse(input).unwrap();
+        let jails = vec![
+            running(1, "nginx", &[
+                ("path", &["/usr/jails/nginx"]),
+                ("host.hostname", &["nginx.example.org"]),
+                ("ip4.addr", &["127.0.1.2", "127.0.1.1", "127.0.1.9"]),
+            ]),
+            running(2, "www", &[("path", &["/usr/jails/www"])]),
+        ];
+
+        let diffs: Vec<String> = config.diff_against(&jails)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(diffs, vec![
+            "nginx: ip4.addr is \"127.0.1.1,127.0.1.2,127.0.1.9\" but configured as \"127.0.1.1,127.0.1.2\"",
+            "db: configured but not running",
+            "www: running as jid 2 but not configured",
+        ]);
+    }
+}
@@ -0,0 +1,381 @@
+// Saving of configuration to files.
+//
+// Writing /etc/jail.conf in place leaves it truncated if the process dies,
+// or the machine loses power, part way through. write_atomic writes to a
+// temporary file next to the destination instead, flushes it to disk and
+// renames it over the destination, so that the file is always either the
+// old configuration or the new one. The temporary file is given the mode,
+// and where permitted the owner, of the file it replaces. A destination
+// which is a symlink has the file it points to replaced, leaving the link
+// in place.
+//
+// SaveOptions can also ask for the file being replaced to be kept as a
+// backup beside it, named for when it was replaced, eg.
+// jail.conf.bak.20240131154502. Timestamps are in UTC, so that backups sort
+// in the order they were made whatever the host's timezone. Backups made in
+// the same second are numbered rather than overwriting one another.
+use std::fs::{
+    self,
+    File,
+    OpenOptions,
+};
+use std::io::{
+    self,
+    Write,
+};
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::process;
+use std::time::{
+    SystemTime,
+    UNIX_EPOCH,
+};
+use crate::LosslessConfig;
+
+/// Options controlling `LosslessConfig::save_with`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SaveOptions {
+    backup: bool,
+}
+
+impl SaveOptions {
+    /// The default options: replace the file without a backup.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether to keep the file being replaced as a timestamped backup.
+    pub fn backup(mut self, backup: bool) -> Self {
+        self.backup = backup;
+        self
+    }
+}
+
+// A time as YYYYMMDDHHMMSS in UTC.
+fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // Convert days since the epoch to a date in the proleptic Gregorian
+    // calendar, counting from 0000-03-01 so leap days fall at year ends.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+    )
+}
+
+// Copy the file at path to a backup named for the current time, returning
+// the backup's path, or None if there's no file to back up. An earlier
+// backup is never overwritten: should one have been made in the same
+// second, the new one is numbered, eg. jail.conf.bak.20240131154502.1.
+fn backup(path: &Path) -> io::Result<Option<PathBuf>> {
+    let mut source = match File::open(path) {
+        Ok(source)                                    => source,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e)                                        => return Err(e),
+    };
+
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".bak.{}", timestamp(SystemTime::now())));
+
+    let mut attempt = 0;
+
+    loop {
+        let mut backup = name.clone();
+
+        if attempt > 0 {
+            backup.push(format!(".{}", attempt));
+        }
+
+        let backup = PathBuf::from(backup);
+
+        match OpenOptions::new().write(true).create_new(true).open(&backup) {
+            Ok(mut file) => {
+                file.set_permissions(source.metadata()?.permissions())?;
+                io::copy(&mut source, &mut file)?;
+
+                return Ok(Some(backup));
+            },
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => {
+                attempt += 1;
+            },
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Create a temporary file in dir for writing the named file, returning it
+// along with its path.
+fn temp_file(dir: &Path, name: &str) -> io::Result<(File, PathBuf)> {
+    let mut attempt = 0;
+
+    loop {
+        let path = dir.join(format!(".{}.{}.{}.tmp", name, process::id(), attempt));
+
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((file, path)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => {
+                attempt += 1;
+            },
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Give file the owner, if possible, and the permissions of the original.
+// The owner is changed first, as changing it may clear setuid and setgid
+// bits.
+fn copy_attributes(file: &File, original: &fs::Metadata) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{
+            fchown,
+            MetadataExt,
+        };
+
+        // Only root may give files away, so the owner is kept where
+        // possible and otherwise left as the writer.
+        let _ = fchown(file, Some(original.uid()), Some(original.gid()));
+    }
+
+    file.set_permissions(original.permissions())
+}
+
+// Write contents to the temporary file. Its attributes are copied from the
+// original before anything is written, so that a configuration only its
+// owner may read is never readable by anyone else, even briefly.
+fn write_temp(
+    file: &mut File,
+    contents: &str,
+    original: Option<&fs::Metadata>,
+) -> io::Result<()> {
+    if let Some(original) = original {
+        copy_attributes(file, original)?;
+    }
+
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()
+}
+
+/// Replace the contents of the file at path atomically: the new contents
+/// are written to a temporary file in the same directory, synced to disk
+/// and renamed into place, with the original file's mode and owner kept
+/// where possible. Should this fail, the original file is untouched.
+pub fn write_atomic<P: AsRef<Path>>(path: P, contents: &str) -> io::Result<()> {
+    let path = path.as_ref();
+
+    // Replace the file a symlink points to rather than the link itself.
+    let path = match fs::canonicalize(path) {
+        Ok(target)                                    => target,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => path.to_path_buf(),
+        Err(e)                                        => return Err(e),
+    };
+
+    let name = path.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?
+        .to_string_lossy()
+        .into_owned();
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _                                        => Path::new("."),
+    };
+
+    let original = match fs::metadata(&path) {
+        Ok(meta)                                      => Some(meta),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e)                                        => return Err(e),
+    };
+
+    let (mut file, temp) = temp_file(dir, &name)?;
+
+    let result = write_temp(&mut file, contents, original.as_ref())
+        .and_then(|_| fs::rename(&temp, &path));
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    // Make the rename itself durable. Not every platform can open a
+    // directory to sync it, and the file is in place either way.
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+
+    Ok(())
+}
+
+impl<'a> LosslessConfig<'a> {
+    /// Write the configuration to path, replacing the file atomically so
+    /// that a crash part way through never leaves it truncated. See
+    /// `write_atomic`.
+    pub fn save_atomic<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        write_atomic(path, &self.to_source())
+    }
+
+    /// Write the configuration to path atomically, as `save_atomic` does,
+    /// first copying the file being replaced to a timestamped backup if the
+    /// options ask for one. Returns the path of the backup, if one was made.
+    pub fn save_with<P: AsRef<Path>>(&self, path: P, options: &SaveOptions)
+    -> io::Result<Option<PathBuf>> {
+        let path = path.as_ref();
+
+        let backup = if options.backup {
+            backup(path)?
+        }
+        else {
+            None
+        };
+
+        self.save_atomic(path)?;
+
+        Ok(backup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::scratch_dir;
+
+    #[test]
+    fn test_save_atomic() {
+        let dir = scratch_dir("save-atomic");
+        let path = dir.join("jail.conf");
+
+        fs::write(&path, "www {\n    path = /jails/www;\n}\n").unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+
+        let input = fs::read_to_string(&path).unwrap();
+        let mut config = LosslessConfig::parse(&input).unwrap();
+
+        config.set_param("www", "persist", "1").unwrap();
+        config.save_atomic(&path).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), config.to_source());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o640);
+        }
+
+        let entries: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_with_backup() {
+        let dir = scratch_dir("save-backup");
+        let path = dir.join("jail.conf");
+        let original = "www {\n    persist;\n}\n";
+
+        fs::write(&path, original).unwrap();
+
+        let mut config = LosslessConfig::parse(original).unwrap();
+
+        config.set_param("www", "path", "/jails/www").unwrap();
+        config.remove_param("www", "persist").unwrap();
+        config.rename_jail("www", "web").unwrap();
+
+        let backup = config.save_with(&path, &SaveOptions::new().backup(true))
+            .unwrap()
+            .unwrap();
+
+        let name = backup.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("jail.conf.bak."));
+        assert_eq!(name.len(), "jail.conf.bak.".len() + 14);
+        assert_eq!(fs::read_to_string(&backup).unwrap(), original);
+        assert_eq!(fs::read_to_string(&path).unwrap(), config.to_source());
+
+        assert_eq!(config.change_summary(), concat!(
+            "www: set path = /jails/www\n",
+            "www: removed persist\n",
+            "renamed jail www to web\n",
+        ));
+        assert_eq!(config.take_journal().len(), 3);
+        assert!(config.journal().is_empty());
+
+        let new = dir.join("new.conf");
+        assert_eq!(config.save_with(&new, &SaveOptions::new().backup(true)).unwrap(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_backup_same_second() {
+        let dir = scratch_dir("save-backup-same-second");
+        let path = dir.join("jail.conf");
+
+        fs::write(&path, "persist;\n").unwrap();
+        let first = backup(&path).unwrap().unwrap();
+
+        fs::write(&path, "exec.clean;\n").unwrap();
+        let second = backup(&path).unwrap().unwrap();
+
+        // The two may straddle a second, but never share a name.
+        assert_ne!(first, second);
+        assert_eq!(fs::read_to_string(&first).unwrap(), "persist;\n");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "exec.clean;\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_timestamp() {
+        use std::time::Duration;
+
+        assert_eq!(timestamp(UNIX_EPOCH), "19700101000000");
+        assert_eq!(timestamp(UNIX_EPOCH + Duration::from_secs(1_700_000_000)), "20231114221320");
+        assert_eq!(timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400)), "20000229000000");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_through_symlink() {
+        let dir = scratch_dir("save-symlink");
+        let target = dir.join("jail.conf.real");
+        let link = dir.join("jail.conf");
+
+        fs::write(&target, "persist;\n").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_atomic(&link, "exec.clean;\n").unwrap();
+
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "exec.clean;\n");
+
+        write_atomic(dir.join("new.conf"), "persist;\n").unwrap();
+        assert_eq!(fs::read_to_string(dir.join("new.conf")).unwrap(), "persist;\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
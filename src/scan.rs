@@ -0,0 +1,526 @@
+// Lightweight scanning of jail.conf input.
+//
+// The scanner understands just enough of the grammar to find jail block
+// headers and their matching closing braces, skipping over comments and
+// quoted strings along the way. Block bodies are left unparsed until they're
+// asked for, so tools that only care about jail names, or a single jail, don't
+// pay for a full parse of a large file. Whether a statement is a block is
+// decided by the parser's own recognition of its name, so the two agree on
+// which blocks there are.
+use std::slice;
+use tracing::debug;
+use crate::error::expected;
+use crate::escape::inner;
+use crate::limit;
+use crate::span;
+use crate::{
+    block_header,
+    is_bare_char,
+    is_name_char,
+    parse_block,
+    remaining,
+    JailBlock,
+    JailConf,
+    ParseError,
+    ParseOptions,
+};
+
+#[derive(Debug, PartialEq)]
+pub struct LazyBlock<'a> {
+    name:   &'a str,
+    body:   &'a str,
+    source: &'a str,
+}
+
+impl<'a> LazyBlock<'a> {
+    /// The name of the jail this block configures, without any quotes.
+    /// Escape sequences in quoted names are not decoded.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// The unparsed text between the block's braces.
+    pub fn body(&self) -> &'a str {
+        self.body
+    }
+
+    /// The whole block, from its name to the closing brace.
+    pub fn source_text(&self) -> &'a str {
+        self.source
+    }
+
+    /// Fully parse the block. Error positions and spans are relative to the
+    /// start of the block.
+    pub fn parse(&self) -> Result<JailBlock<'a>, ParseError> {
+        limit::check(self.source, &ParseOptions::new())?;
+
+        let offset = match parse_block(self.source) {
+            Ok((_, mut statement)) => {
+                span::assign(self.source, slice::from_mut(&mut statement));
+
+                match statement {
+                    JailConf::Block(block) => return Ok(block),
+                    _                      => 0,
+                }
+            },
+            Err(e) => {
+                self.source.len() - remaining(self.source, e).len()
+            },
+        };
+
+        Err(ParseError::new(
+            self.source.as_bytes(),
+            offset,
+            expected(&self.source[offset..]),
+        ))
+    }
+}
+
+// Returns the position of the newline ending the line that pos is on, or the
+// end of input.
+fn skip_line(bytes: &[u8], pos: usize) -> usize {
+    bytes[pos..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(bytes.len(), |offset| pos + offset)
+}
+
+// Skips a C style comment starting at pos, returning the position after the
+// closing */.
+fn skip_c_comment(bytes: &[u8], pos: usize) -> Result<usize, ParseError> {
+    bytes[pos + 2..]
+        .windows(2)
+        .position(|w| w == b"*/")
+        .map(|offset| pos + 2 + offset + 2)
+        .ok_or_else(|| ParseError::new(bytes, pos, "'*/' to close comment"))
+}
+
+// Skips a double quoted string starting at pos, returning the position after
+// the closing quote. Backslash escaped characters are skipped over.
+fn skip_string(bytes: &[u8], pos: usize) -> Result<usize, ParseError> {
+    let mut i = pos + 1;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"'  => return Ok(i + 1),
+            _     => i += 1,
+        }
+    }
+
+    Err(ParseError::new(bytes, pos, "closing '\"' after value"))
+}
+
+// Skips a single quoted string starting at pos, returning the position after
+// the closing quote. There are no escapes within single quotes.
+fn skip_single_string(bytes: &[u8], pos: usize) -> Result<usize, ParseError> {
+    bytes[pos + 1..]
+        .iter()
+        .position(|&b| b == b'\'')
+        .map(|offset| pos + 1 + offset + 1)
+        .ok_or_else(|| ParseError::new(bytes, pos, "closing \"'\" after value"))
+}
+
+// What a Scanner found at a position in the input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Found {
+    /// A comment of any style.
+    Comment,
+    /// A quoted string, backslash escape or variable reference, which can
+    /// only be part of a name or value.
+    Text,
+    /// Any other byte.
+    Byte(u8),
+}
+
+// Whether a byte outside of strings is part of a bare word. A comma
+// separates list items, so ends a word.
+fn is_word_byte(b: u8) -> bool {
+    !b.is_ascii() || b == b'$' || (b != b',' && is_bare_char(char::from(b)))
+}
+
+// Walks input as the parser would, a piece at a time, for scanners which only
+// need to find braces and semicolons. Comments, strings and escapes are
+// recognised in the same order as the parser does: within a string there
+// are no comments, within a comment there are no strings, and `//` or `/*`
+// part way through a bare word, eg. `/jails//www`, doesn't start a comment.
+// Iteration stops after an unterminated comment or string.
+pub(crate) struct Scanner<'b> {
+    bytes:  &'b [u8],
+    pos:    usize,
+    // Whether the previous piece was part of a bare word.
+    word:   bool,
+    failed: bool,
+}
+
+impl<'b> Scanner<'b> {
+    // Scan bytes from pos, which must not be part way through a word.
+    pub(crate) fn new(bytes: &'b [u8], pos: usize) -> Self {
+        Self {
+            bytes,
+            pos,
+            word:   false,
+            failed: false,
+        }
+    }
+
+    // The piece at pos, where it ends and whether it's part of a bare word.
+    fn piece(&self, pos: usize) -> Result<(Found, usize, bool), ParseError> {
+        let bytes = self.bytes;
+        let next = bytes.get(pos + 1).copied();
+
+        let piece = match (bytes[pos], next) {
+            (b'#', _) => (Found::Comment, skip_line(bytes, pos), false),
+            (b'/', Some(b'/')) if !self.word => {
+                (Found::Comment, skip_line(bytes, pos), false)
+            },
+            (b'/', Some(b'*')) if !self.word => {
+                (Found::Comment, skip_c_comment(bytes, pos)?, false)
+            },
+            (b'"', _)  => (Found::Text, skip_string(bytes, pos)?, false),
+            (b'\'', _) => (Found::Text, skip_single_string(bytes, pos)?, false),
+            // A continued line is whitespace, anything else escaped is part
+            // of a word and never starts a comment or string, eg. `a\"b`.
+            (b'\\', Some(b'\n')) | (b'\\', Some(b'\r')) => {
+                (Found::Byte(b'\\'), pos + 1, false)
+            },
+            (b'\\', Some(_)) => (Found::Text, pos + 2, true),
+            (b'$', Some(b'{')) => match reference_end(bytes, pos) {
+                Some(end) => (Found::Text, end, true),
+                None      => (Found::Byte(b'$'), pos + 1, true),
+            },
+            (b, _) => (Found::Byte(b), pos + 1, is_word_byte(b)),
+        };
+
+        Ok(piece)
+    }
+}
+
+// The end of the variable reference starting at pos, eg. `${name}`.
+fn reference_end(bytes: &[u8], pos: usize) -> Option<usize> {
+    let close = pos + bytes[pos..].iter().position(|&b| b == b'}')?;
+    let name = std::str::from_utf8(&bytes[pos + 2..close]).ok()?;
+
+    name.chars().all(is_name_char).then_some(close + 1)
+}
+
+impl Iterator for Scanner<'_> {
+    type Item = Result<(usize, Found), ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.pos >= self.bytes.len() {
+            return None;
+        }
+
+        let pos = self.pos;
+
+        match self.piece(pos) {
+            Ok((found, end, word)) => {
+                self.pos = end;
+                self.word = word;
+
+                Some(Ok((pos, found)))
+            },
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+// Given the position of an opening brace, find the position of its matching
+// closing brace.
+fn matching_brace(bytes: &[u8], open: usize) -> Result<usize, ParseError> {
+    let mut depth = 0;
+
+    for piece in Scanner::new(bytes, open) {
+        match piece? {
+            (_, Found::Byte(b'{')) => depth += 1,
+            (pos, Found::Byte(b'}')) => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return Ok(pos);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    Err(ParseError::new(bytes, open, "'}' to close jail block"))
+}
+
+// Iterator over the top level blocks in some input. Iteration stops after
+// the first error.
+struct Blocks<'a> {
+    input:  &'a str,
+    pos:    usize,
+    failed: bool,
+}
+
+impl<'a> Blocks<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            pos:    0,
+            failed: false,
+        }
+    }
+
+    fn next_block(&mut self) -> Result<Option<LazyBlock<'a>>, ParseError> {
+        let input = self.input;
+        let bytes = input.as_bytes();
+
+        // Start of the statement we're currently in. Whether it's a block is
+        // decided from its start, as the parser decides.
+        let mut statement = None;
+
+        for piece in Scanner::new(bytes, self.pos) {
+            let (pos, found) = piece?;
+
+            match (found, statement) {
+                (Found::Comment, _) => {},
+                (Found::Byte(b'{'), start) => {
+                    return Err(match start {
+                        Some(start) => ParseError::new(bytes, start, expected(&input[start..])),
+                        None        => ParseError::new(bytes, pos, "a jail name before '{'"),
+                    });
+                },
+                (Found::Byte(b'}'), _) => return Err(ParseError::new(
+                    bytes,
+                    pos,
+                    "a parameter, comment or jail block before '}'",
+                )),
+                (Found::Byte(b';'), _) => statement = None,
+                (Found::Byte(b), None) if b.is_ascii_whitespace() => {},
+                (_, None) => {
+                    statement = Some(pos);
+
+                    let open = match block_header(&input[pos..]) {
+                        Some(header) => pos + header - 1,
+                        None         => continue,
+                    };
+                    let close = matching_brace(bytes, open)?;
+
+                    self.pos = close + 1;
+
+                    return Ok(Some(LazyBlock{
+                        name:   inner(input[pos..open].trim()),
+                        body:   &input[open + 1..close],
+                        source: &input[pos..=close],
+                    }));
+                },
+                (_, Some(_)) => {},
+            }
+        }
+
+        self.pos = bytes.len();
+
+        Ok(None)
+    }
+}
+
+impl<'a> Iterator for Blocks<'a> {
+    type Item = Result<LazyBlock<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        match self.next_block() {
+            Ok(block) => block.map(Ok),
+            Err(e)    => {
+                self.failed = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+/// Scan the given input for top level jail blocks without parsing their
+/// bodies.
+pub fn scan_blocks(input: &str) -> Result<Vec<LazyBlock<'_>>, ParseError> {
+    let blocks: Vec<LazyBlock> = Blocks::new(input).collect::<Result<_, _>>()?;
+
+    debug!("scanned {} blocks", blocks.len());
+
+    Ok(blocks)
+}
+
+/// Find the named jail and parse only its block, leaving the rest of the
+/// input unparsed. Scanning stops at the first block with a matching name.
+pub fn extract_jail<'a>(input: &'a str, name: &str)
+-> Result<Option<JailBlock<'a>>, ParseError> {
+    for block in Blocks::new(input) {
+        let block = block?;
+
+        if block.name() == name {
+            debug!("found jail {}", name);
+            return block.parse().map(Some);
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_scan_blocks() {
+        let input = indoc!(r#"
+            persist;
+            # A comment with a { brace
+            nginx {
+                exec.start = "echo }";
+                /* } */
+            }
+
+            jail2 {
+                persist;
+            }
+            "#);
+
+        let res = scan_blocks(input).unwrap();
+        let names: Vec<&str> = res.iter().map(|b| b.name()).collect();
+
+        assert_eq!(names, vec!["nginx", "jail2"]);
+        assert_eq!(res[1].body(), "\n    persist;\n");
+        assert_eq!(res[1].source_text(), "jail2 {\n    persist;\n}");
+    }
+
+    #[test]
+    fn test_scan_blocks_nested_braces() {
+        let input = "outer { inner { persist; } }";
+        let res = scan_blocks(input).unwrap();
+
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].name(), "outer");
+        assert_eq!(res[0].body(), " inner { persist; } ");
+    }
+
+    #[test]
+    fn test_scan_blocks_comment_markers_in_words() {
+        let input = indoc!(r#"
+            www {
+                path = /jails//www; // {
+                exec.start = echo\"x;
+            }
+            db {}
+            "#);
+
+        let res = scan_blocks(input).unwrap();
+        let names: Vec<&str> = res.iter().map(|b| b.name()).collect();
+
+        assert_eq!(names, vec!["www", "db"]);
+        assert_eq!(res[0].parse().unwrap().to_string(), crate::parse(input).unwrap()[0].to_string());
+    }
+
+    #[test]
+    fn test_scan_blocks_agrees_with_parse() {
+        let inputs = [
+            "${}",
+            "x.\\\n{}",
+            "$x = 1;\nwww {}",
+            "${x} = 1;\nwww { persist; }",
+            ".include \"/etc/jail.conf.d/*.conf\";\n\"db\" {}",
+            "'web server' {}\n.x {}",
+            "a,b { persist; }\nc\t{ }",
+            "www\n{}",
+        ];
+
+        for input in inputs {
+            let scanned = scan_blocks(input);
+
+            let parsed = match crate::parse(input) {
+                Ok(parsed) => parsed,
+                // Block bodies aren't looked at until they're parsed.
+                Err(_)     => {
+                    let failed = scanned.map_or(true, |blocks| {
+                        blocks.iter().any(|block| block.parse().is_err())
+                    });
+
+                    assert!(failed, "{:?} scanned but doesn't parse", input);
+                    continue;
+                },
+            };
+
+            let blocks: Vec<String> = parsed.iter()
+                .filter_map(|statement| match statement {
+                    JailConf::Block(block) => Some(block.to_string()),
+                    _                      => None,
+                })
+                .collect();
+
+            let scanned: Vec<String> = scanned.unwrap()
+                .iter()
+                .map(|block| block.parse().unwrap().to_string())
+                .collect();
+
+            assert_eq!(scanned, blocks, "{:?}", input);
+        }
+
+        assert_eq!(scan_blocks("${}").unwrap()[0].name(), "$");
+        assert!(scan_blocks("x.\\\n{}").is_err());
+    }
+
+    #[test]
+    fn test_scan_blocks_unbalanced_is_err() {
+        assert!(scan_blocks("nginx {\n    persist;\n").is_err());
+        assert!(scan_blocks("persist;\n}\n").is_err());
+    }
+
+    #[test]
+    fn test_scan_blocks_error_position() {
+        let err = scan_blocks("persist;\nnginx {\n    path = \"/a;\n}\n").unwrap_err();
+
+        assert_eq!(err.line(), 3);
+        assert_eq!(err.column(), 12);
+        assert_eq!(err.expected(), "closing '\"' after value");
+    }
+
+    #[test]
+    fn test_lazy_block_parse() {
+        let input = indoc!(r#"
+            nginx {
+                host.hostname = "nginx";
+            }
+            "#);
+
+        let res = scan_blocks(input).unwrap();
+        let block = res[0].parse().unwrap();
+
+        assert_eq!(block.source_text(), res[0].source_text());
+    }
+
+    #[test]
+    fn test_extract_jail() {
+        let input = indoc!(r#"
+            nginx {
+                host.hostname = "nginx";
+            }
+
+            # The body of this block isn't valid, but it's never parsed
+            broken {
+                this is not valid
+            }
+
+            jail2 {
+                persist;
+            }
+            "#);
+
+        let block = extract_jail(input, "jail2").unwrap().unwrap();
+        assert_eq!(block.source_text(), "jail2 {\n    persist;\n}");
+
+        let block = extract_jail(input, "nginx").unwrap().unwrap();
+        assert_eq!(block.params[0].source_text(), r#"host.hostname = "nginx";"#);
+
+        assert!(extract_jail(input, "missing").unwrap().is_none());
+        assert!(extract_jail(input, "broken").is_err());
+    }
+}
@@ -0,0 +1,504 @@
+// Built in metadata about the parameters understood by jail(8).
+//
+// The table here is exposed as data so that editors and external validators
+// can stay in sync with the crate: each parameter's name, type, default, the
+// release it appeared in and a one line description drawn from jail(8), for
+// completion menus, hover text and generated documentation. With the `serde`
+// feature enabled every type can be serialized, eg. to JSON with
+// serde_json:
+//
+// let json = serde_json::to_string(jailconf::schema::parameters())?;
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{
+    Serialize,
+    Serializer,
+};
+
+/// FreeBSD releases that the parameter table knows about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Version {
+    FreeBSD12_0,
+    FreeBSD12_1,
+    FreeBSD12_2,
+    FreeBSD12_3,
+    FreeBSD12_4,
+    FreeBSD13_0,
+    FreeBSD13_1,
+    FreeBSD13_2,
+    FreeBSD13_3,
+    FreeBSD13_4,
+    FreeBSD13_5,
+    FreeBSD14_0,
+    FreeBSD14_1,
+    FreeBSD14_2,
+    FreeBSD14_3,
+}
+
+impl Version {
+    /// Every known release, oldest first.
+    pub const ALL: &'static [Version] = &[
+        Version::FreeBSD12_0,
+        Version::FreeBSD12_1,
+        Version::FreeBSD12_2,
+        Version::FreeBSD12_3,
+        Version::FreeBSD12_4,
+        Version::FreeBSD13_0,
+        Version::FreeBSD13_1,
+        Version::FreeBSD13_2,
+        Version::FreeBSD13_3,
+        Version::FreeBSD13_4,
+        Version::FreeBSD13_5,
+        Version::FreeBSD14_0,
+        Version::FreeBSD14_1,
+        Version::FreeBSD14_2,
+        Version::FreeBSD14_3,
+    ];
+
+    /// The release number, eg. "13.2".
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Version::FreeBSD12_0 => "12.0",
+            Version::FreeBSD12_1 => "12.1",
+            Version::FreeBSD12_2 => "12.2",
+            Version::FreeBSD12_3 => "12.3",
+            Version::FreeBSD12_4 => "12.4",
+            Version::FreeBSD13_0 => "13.0",
+            Version::FreeBSD13_1 => "13.1",
+            Version::FreeBSD13_2 => "13.2",
+            Version::FreeBSD13_3 => "13.3",
+            Version::FreeBSD13_4 => "13.4",
+            Version::FreeBSD13_5 => "13.5",
+            Version::FreeBSD14_0 => "14.0",
+            Version::FreeBSD14_1 => "14.1",
+            Version::FreeBSD14_2 => "14.2",
+            Version::FreeBSD14_3 => "14.3",
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Version {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// The type of value a parameter expects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum ParamType {
+    /// A boolean, which may be given without a value.
+    Bool,
+    /// An integer.
+    Int,
+    /// A free form string.
+    String,
+    /// A list of strings, usually built with `+=`.
+    List,
+    /// A list of IPv4 addresses, optionally in `iface|addr/prefix` form.
+    Ip4,
+    /// A list of IPv6 addresses, optionally in `iface|addr/prefix` form.
+    Ip6,
+    /// One of "new", "inherit" or "disable".
+    JailSys,
+}
+
+impl ParamType {
+    /// A description of the type for messages, eg. "an integer".
+    pub fn description(&self) -> &'static str {
+        match self {
+            ParamType::Bool    => "a boolean",
+            ParamType::Int     => "an integer",
+            ParamType::String  => "a string",
+            ParamType::List    => "a list",
+            ParamType::Ip4     => "a list of IPv4 addresses",
+            ParamType::Ip6     => "a list of IPv6 addresses",
+            ParamType::JailSys => "one of new, inherit or disable",
+        }
+    }
+}
+
+/// Metadata about a single jail parameter.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Parameter {
+    name:        &'static str,
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    kind:        ParamType,
+    default:     Option<&'static str>,
+    since:       Version,
+    description: &'static str,
+}
+
+impl Parameter {
+    /// The parameter name, eg. "allow.mount".
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The type of value the parameter expects.
+    pub fn kind(&self) -> ParamType {
+        self.kind
+    }
+
+    /// The value used when the parameter isn't set, if it has a fixed one.
+    pub fn default(&self) -> Option<&'static str> {
+        self.default
+    }
+
+    /// The first release the parameter is available on.
+    pub fn since(&self) -> Version {
+        self.since
+    }
+
+    /// What the parameter does, in a sentence paraphrasing jail(8).
+    pub fn description(&self) -> &'static str {
+        self.description
+    }
+
+    /// Whether the parameter is available on the given release.
+    pub fn available_on(&self, version: Version) -> bool {
+        version >= self.since
+    }
+
+    /// What to use instead, if jail(8) documents the parameter as
+    /// deprecated.
+    pub fn deprecated(&self) -> Option<&'static str> {
+        DEPRECATED.iter()
+            .find(|(name, _)| *name == self.name)
+            .map(|(_, instead)| *instead)
+    }
+}
+
+// Parameters which are still accepted but deprecated, with their
+// replacements.
+static DEPRECATED: &[(&str, &str)] = &[
+    ("allow.sysvipc", "sysvmsg, sysvsem and sysvshm"),
+];
+
+macro_rules! param {
+    ($name:expr, $kind:ident, $default:expr, $since:ident, $description:expr) => {
+        Parameter {
+            name:        $name,
+            kind:        ParamType::$kind,
+            default:     $default,
+            since:       Version::$since,
+            description: $description,
+        }
+    };
+}
+
+static PARAMETERS: &[Parameter] = &[
+    param!("jid",                            Int,     None,            FreeBSD12_0,
+           "The jail identifier, assigned automatically if not given."),
+    param!("name",                           String,  None,            FreeBSD12_0,
+           "The jail name, which may be used in place of the jid."),
+    param!("path",                           String,  None,            FreeBSD12_0,
+           "The directory which is the root of the jail."),
+    param!("ip4",                            JailSys, None,            FreeBSD12_0,
+           "Whether the jail is limited to ip4.addr, may use any IPv4 address, or none."),
+    param!("ip4.addr",                       Ip4,     None,            FreeBSD12_0,
+           "A list of IPv4 addresses assigned to the jail."),
+    param!("ip4.saddrsel",                   Bool,    Some("true"),    FreeBSD12_0,
+           "Use source address selection for unbound IPv4 sockets in the jail."),
+    param!("ip6",                            JailSys, None,            FreeBSD12_0,
+           "Whether the jail is limited to ip6.addr, may use any IPv6 address, or none."),
+    param!("ip6.addr",                       Ip6,     None,            FreeBSD12_0,
+           "A list of IPv6 addresses assigned to the jail."),
+    param!("ip6.saddrsel",                   Bool,    Some("true"),    FreeBSD12_0,
+           "Use source address selection for unbound IPv6 sockets in the jail."),
+    param!("vnet",                           JailSys, Some("inherit"), FreeBSD12_0,
+           "Give the jail its own virtual network stack, with its own interfaces and routes."),
+    param!("host",                           JailSys, None,            FreeBSD12_0,
+           "Whether the jail has its own hostname, domainname and host identifiers."),
+    param!("host.hostname",                  String,  None,            FreeBSD12_0,
+           "The hostname of the jail."),
+    param!("host.domainname",                String,  None,            FreeBSD12_0,
+           "The NIS domain name of the jail."),
+    param!("host.hostuuid",                  String,  None,            FreeBSD12_0,
+           "The host UUID of the jail."),
+    param!("host.hostid",                    Int,     None,            FreeBSD12_0,
+           "The host ID of the jail."),
+    param!("securelevel",                    Int,     None,            FreeBSD12_0,
+           "The securelevel of the jail, which may be raised but not lowered from within it."),
+    param!("devfs_ruleset",                  Int,     Some("4"),       FreeBSD12_0,
+           "The number of the devfs ruleset enforced when mounting devfs in the jail."),
+    param!("children.max",                   Int,     Some("0"),       FreeBSD12_0,
+           "The number of child jails the jail may create."),
+    param!("enforce_statfs",                 Int,     Some("2"),       FreeBSD12_0,
+           "Which mount points the jail can see: 0 all, 1 those below its root, 2 only its root."),
+    param!("persist",                        Bool,    Some("false"),   FreeBSD12_0,
+           "Keep the jail in existence without any processes in it."),
+    param!("osrelease",                      String,  None,            FreeBSD12_0,
+           "The release reported by uname(1) within the jail."),
+    param!("osreldate",                      Int,     None,            FreeBSD12_0,
+           "The value of kern.osreldate within the jail."),
+    param!("allow.set_hostname",             Bool,    Some("true"),    FreeBSD12_0,
+           "Allow the jail's hostname to be changed from within it."),
+    param!("allow.sysvipc",                  Bool,    Some("false"),   FreeBSD12_0,
+           "Allow access to the host's System V IPC primitives."),
+    param!("allow.raw_sockets",              Bool,    Some("false"),   FreeBSD12_0,
+           "Allow the jail to create raw sockets, as used by ping(8) and traceroute(8)."),
+    param!("allow.chflags",                  Bool,    Some("false"),   FreeBSD12_0,
+           "Treat privileged users within the jail as unprivileged by system file flags."),
+    param!("allow.mount",                    Bool,    Some("false"),   FreeBSD12_0,
+           "Allow privileged users within the jail to mount file systems marked as jail safe."),
+    param!("allow.mount.devfs",              Bool,    Some("false"),   FreeBSD12_0,
+           "Allow privileged users within the jail to mount devfs."),
+    param!("allow.mount.fdescfs",            Bool,    Some("false"),   FreeBSD12_0,
+           "Allow privileged users within the jail to mount fdescfs."),
+    param!("allow.mount.linprocfs",          Bool,    Some("false"),   FreeBSD12_0,
+           "Allow privileged users within the jail to mount linprocfs."),
+    param!("allow.mount.linsysfs",           Bool,    Some("false"),   FreeBSD12_0,
+           "Allow privileged users within the jail to mount linsysfs."),
+    param!("allow.mount.nullfs",             Bool,    Some("false"),   FreeBSD12_0,
+           "Allow privileged users within the jail to mount nullfs."),
+    param!("allow.mount.procfs",             Bool,    Some("false"),   FreeBSD12_0,
+           "Allow privileged users within the jail to mount procfs."),
+    param!("allow.mount.tmpfs",              Bool,    Some("false"),   FreeBSD12_0,
+           "Allow privileged users within the jail to mount tmpfs."),
+    param!("allow.mount.zfs",                Bool,    Some("false"),   FreeBSD12_0,
+           "Allow privileged users within the jail to mount ZFS datasets delegated to it."),
+    param!("allow.mount.fusefs",             Bool,    Some("false"),   FreeBSD12_1,
+           "Allow privileged users within the jail to mount fusefs."),
+    param!("allow.mount.lindebugfs",         Bool,    Some("false"),   FreeBSD13_0,
+           "Allow privileged users within the jail to mount lindebugfs."),
+    param!("allow.quotas",                   Bool,    Some("false"),   FreeBSD12_0,
+           "Allow privileged users within the jail to administer file system quotas."),
+    param!("allow.socket_af",                Bool,    Some("false"),   FreeBSD12_0,
+           "Allow sockets of protocol families other than IP, routing and UNIX domain."),
+    param!("allow.vmm",                      Bool,    Some("false"),   FreeBSD12_0,
+           "Allow the jail access to the bhyve(8) virtual machine monitor."),
+    param!("allow.mlock",                    Bool,    Some("false"),   FreeBSD12_1,
+           "Allow the jail to lock and unlock physical pages in memory."),
+    param!("allow.reserved_ports",           Bool,    Some("true"),    FreeBSD12_1,
+           "Allow privileged users within the jail to bind to ports below 1024."),
+    param!("allow.unprivileged_proc_debug",  Bool,    Some("false"),   FreeBSD12_1,
+           "Allow unprivileged processes within the jail to use debugging facilities."),
+    param!("allow.suser",                    Bool,    Some("true"),    FreeBSD12_1,
+           "Whether the super-user within the jail keeps its privileges."),
+    param!("allow.read_msgbuf",              Bool,    Some("false"),   FreeBSD13_0,
+           "Allow the jail to read the kernel message buffer."),
+    param!("allow.nfsd",                     Bool,    Some("false"),   FreeBSD13_3,
+           "Allow the jail to run nfsd(8) and related daemons."),
+    param!("allow.extattr",                  Bool,    Some("false"),   FreeBSD14_0,
+           "Allow privileged users within the jail to manipulate system extended attributes."),
+    param!("allow.adjtime",                  Bool,    Some("false"),   FreeBSD14_0,
+           "Allow privileged users within the jail to slew the system clock."),
+    param!("allow.settime",                  Bool,    Some("false"),   FreeBSD14_0,
+           "Allow privileged users within the jail to set the system clock."),
+    param!("allow.routing",                  Bool,    Some("false"),   FreeBSD14_1,
+           "Allow privileged users within a non-VNET jail to modify the system routing table."),
+    param!("allow.dying",                    Bool,    Some("false"),   FreeBSD12_0,
+           "Allow a jail which is being removed to be attached to or changed."),
+    param!("linux",                          JailSys, None,            FreeBSD12_0,
+           "Whether the jail has its own Linux emulation environment."),
+    param!("linux.osname",                   String,  None,            FreeBSD12_0,
+           "The operating system name reported to Linux binaries."),
+    param!("linux.osrelease",                String,  None,            FreeBSD12_0,
+           "The operating system release reported to Linux binaries."),
+    param!("linux.oss_version",              String,  None,            FreeBSD12_0,
+           "The OSS audio version reported to Linux binaries."),
+    param!("sysvmsg",                        JailSys, Some("disable"), FreeBSD12_0,
+           "Access to System V message queues: the jail's own, the host's, or none."),
+    param!("sysvsem",                        JailSys, Some("disable"), FreeBSD12_0,
+           "Access to System V semaphores: the jail's own, the host's, or none."),
+    param!("sysvshm",                        JailSys, Some("disable"), FreeBSD12_0,
+           "Access to System V shared memory: the jail's own, the host's, or none."),
+    param!("zfs.mount_snapshot",             Int,     Some("0"),       FreeBSD14_0,
+           "Allow the jail to mount ZFS snapshots from the .zfs/snapshot directory."),
+    param!("meta",                           String,  None,            FreeBSD14_0,
+           "Free form metadata about the jail, readable from the host."),
+    param!("env",                            String,  None,            FreeBSD14_0,
+           "Free form metadata about the jail, readable from within it."),
+    param!("exec.prepare",                   List,    None,            FreeBSD13_1,
+           "Commands to run in the system environment to prepare the jail, before anything else."),
+    param!("exec.prestart",                  List,    None,            FreeBSD12_0,
+           "Commands to run in the system environment before the jail is created."),
+    param!("exec.created",                   List,    None,            FreeBSD12_0,
+           "Commands to run in the system environment right after the jail is created."),
+    param!("exec.start",                     List,    None,            FreeBSD12_0,
+           "Commands to run in the jail environment when it's created, eg. /bin/sh /etc/rc."),
+    param!("command",                        List,    None,            FreeBSD12_0,
+           "A synonym for exec.start, for use when creating a jail from the command line."),
+    param!("exec.poststart",                 List,    None,            FreeBSD12_0,
+           "Commands to run in the system environment after the jail's start commands."),
+    param!("exec.prestop",                   List,    None,            FreeBSD12_0,
+           "Commands to run in the system environment before the jail is removed."),
+    param!("exec.stop",                      List,    None,            FreeBSD12_0,
+           "Commands to run in the jail environment before it's removed, eg. /etc/rc.shutdown."),
+    param!("exec.poststop",                  List,    None,            FreeBSD12_0,
+           "Commands to run in the system environment after the jail is removed."),
+    param!("exec.release",                   List,    None,            FreeBSD13_1,
+           "Commands to run in the system environment after all other actions are done."),
+    param!("exec.clean",                     Bool,    Some("false"),   FreeBSD12_0,
+           "Run commands in a clean environment, as with login(1)."),
+    param!("exec.jail_user",                 String,  None,            FreeBSD12_0,
+           "The user to run commands as within the jail."),
+    param!("exec.system_jail_user",          Bool,    Some("false"),   FreeBSD12_0,
+           "Look up exec.jail_user in the system's password database rather than the jail's."),
+    param!("exec.system_user",               String,  None,            FreeBSD12_0,
+           "The user to run commands as in the system environment."),
+    param!("exec.timeout",                   Int,     None,            FreeBSD12_0,
+           "The number of seconds to wait for a command to finish."),
+    param!("exec.consolelog",                String,  None,            FreeBSD12_0,
+           "A file to direct the output of commands to."),
+    param!("exec.fib",                       Int,     None,            FreeBSD12_0,
+           "The FIB, routing table, to run commands in."),
+    param!("stop.timeout",                   Int,     Some("10"),      FreeBSD12_0,
+           "The number of seconds to wait for the jail's processes to exit when it's removed."),
+    param!("interface",                      String,  None,            FreeBSD12_0,
+           "A network interface to add the jail's addresses to."),
+    param!("vnet.interface",                 List,    None,            FreeBSD12_0,
+           "Network interfaces to give to a VNET jail after it's created."),
+    param!("ip_hostname",                    Bool,    Some("false"),   FreeBSD12_0,
+           "Resolve host.hostname and add its addresses to ip4.addr and ip6.addr."),
+    param!("mount",                          List,    None,            FreeBSD12_0,
+           "Filesystems to mount before creating the jail, in fstab(5) format."),
+    param!("mount.fstab",                    String,  None,            FreeBSD12_0,
+           "An fstab(5) file of filesystems to mount before creating the jail."),
+    param!("mount.devfs",                    Bool,    Some("false"),   FreeBSD12_0,
+           "Mount devfs on the jail's /dev."),
+    param!("mount.fdescfs",                  Bool,    Some("false"),   FreeBSD12_0,
+           "Mount fdescfs on the jail's /dev/fd."),
+    param!("mount.procfs",                   Bool,    Some("false"),   FreeBSD12_0,
+           "Mount procfs on the jail's /proc."),
+    param!("depend",                         List,    None,            FreeBSD12_0,
+           "Jails which must be created before this one, and removed after it."),
+];
+
+/// Every parameter known to the crate.
+pub fn parameters() -> &'static [Parameter] {
+    PARAMETERS
+}
+
+/// Look up a single parameter by name.
+pub fn parameter(name: &str) -> Option<&'static Parameter> {
+    PARAMETERS.iter().find(|param| param.name == name)
+}
+
+/// The parameters available on a particular release.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Schema {
+    version: Version,
+}
+
+impl Schema {
+    /// The schema for the given release.
+    pub fn new(version: Version) -> Self {
+        Self {
+            version,
+        }
+    }
+
+    /// The schema for the newest known release.
+    pub fn latest() -> Self {
+        Self::new(Version::ALL[Version::ALL.len() - 1])
+    }
+
+    /// The release this schema describes.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Every parameter available on the release.
+    pub fn parameters(&self) -> impl Iterator<Item = &'static Parameter> {
+        let version = self.version;
+
+        PARAMETERS.iter().filter(move |param| param.available_on(version))
+    }
+
+    /// Look up a single parameter by name, if it's available on the release.
+    pub fn parameter(&self, name: &str) -> Option<&'static Parameter> {
+        parameter(name).filter(|param| param.available_on(self.version))
+    }
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        Self::latest()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parameter_lookup() {
+        let param = parameter("enforce_statfs").unwrap();
+
+        assert_eq!(param.kind(), ParamType::Int);
+        assert_eq!(param.default(), Some("2"));
+        assert!(parameter("no.such.param").is_none());
+    }
+
+    #[test]
+    fn test_parameter_availability() {
+        let param = parameter("allow.adjtime").unwrap();
+
+        assert!(!param.available_on(Version::FreeBSD13_2));
+        assert!(param.available_on(Version::FreeBSD14_0));
+    }
+
+    #[test]
+    fn test_schema_by_version() {
+        let old = Schema::new(Version::FreeBSD12_0);
+        let latest = Schema::latest();
+
+        assert_eq!(latest.version(), Version::FreeBSD14_3);
+        assert!(old.parameter("allow.mount.fusefs").is_none());
+        assert!(latest.parameter("allow.mount.fusefs").is_some());
+        assert!(old.parameters().count() < latest.parameters().count());
+    }
+
+    #[test]
+    fn test_parameter_descriptions() {
+        assert_eq!(
+            parameter("host.hostname").unwrap().description(),
+            "The hostname of the jail.",
+        );
+
+        for param in parameters() {
+            assert!(param.description().ends_with('.'), "{}", param.name());
+        }
+    }
+
+    #[test]
+    fn test_parameter_names_unique() {
+        for (i, param) in PARAMETERS.iter().enumerate() {
+            assert!(
+                PARAMETERS[i + 1..].iter().all(|p| p.name != param.name),
+                "duplicate parameter {}",
+                param.name,
+            );
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_parameter_serialize() {
+        let param = parameter("persist").unwrap();
+        let json = serde_json::to_string(param).unwrap();
+
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"name":"persist","type":"bool","default":"false","since":"12.0","#,
+                r#""description":"Keep the jail in existence without any processes in it."}"#,
+            ),
+        );
+    }
+}
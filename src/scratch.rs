@@ -0,0 +1,18 @@
+// Scratch directories for the tests which need files on disk.
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+// Create an empty scratch directory for a test. The name must be unique
+// among the tests, as they run in parallel, so is prefixed with the test's
+// module, eg. `include-glob`.
+pub(crate) fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir()
+        .join(format!("jailconf-{}-{}", name, process::id()));
+
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
@@ -0,0 +1,248 @@
+// Generation of a series of jails from a template.
+//
+// Provisioning jails in bulk means many blocks differing only in a number,
+// the name and path built from it and an address of their own. The address
+// plan is the part most easily got wrong, with two jails ending up on the
+// same address or one falling off the end of the subnet, so generate_series
+// hands out addresses itself, one per jail in order through a subnet, and
+// refuses a series the subnet hasn't room for before rendering any of it.
+//
+// Each jail is rendered with render_template, so values are quoted for
+// where they appear, from the placeholders:
+//
+//   - `{{n}}`, the jail's number from the range,
+//   - `{{ip}}`, its address,
+//   - `{{prefix}}`, the subnet's prefix length.
+use std::error;
+use std::fmt;
+use std::net::{
+    IpAddr,
+    Ipv4Addr,
+    Ipv6Addr,
+};
+use crate::{
+    into_owned,
+    parse,
+    render_template,
+    JailConfig,
+    Subnet,
+    TemplateError,
+};
+
+/// Why a series couldn't be generated, see `generate_series`.
+#[derive(Debug, PartialEq)]
+pub enum SeriesError {
+    /// The subnet doesn't have an address for every jail in the series.
+    Exhausted {
+        subnet:    Subnet,
+        requested: usize,
+        available: u128,
+    },
+    /// A jail's block couldn't be rendered.
+    Template {
+        n:     u32,
+        error: TemplateError,
+    },
+}
+
+impl fmt::Display for SeriesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SeriesError::Exhausted { subnet, requested, available } => {
+                write!(
+                    f,
+                    "{} jails requested but subnet {} has room for {}",
+                    requested,
+                    subnet,
+                    available,
+                )
+            },
+            SeriesError::Template { n, error } => {
+                write!(f, "couldn't render jail {}: {}", n, error)
+            },
+        }
+    }
+}
+
+impl error::Error for SeriesError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SeriesError::Template { error, .. } => Some(error),
+            _                                   => None,
+        }
+    }
+}
+
+// The first address to hand out and the last which may be, as integers of
+// the subnet's family. Addresses start from the one the subnet was given
+// with, or the first after the network address if that's what it was given
+// with. The broadcast address of an IPv4 subnet is never handed out.
+fn host_range(subnet: &Subnet) -> (u128, u128) {
+    let (addr, bits) = match subnet.addr() {
+        IpAddr::V4(addr) => (u128::from(u32::from(addr)), 32),
+        IpAddr::V6(addr) => (u128::from(addr), 128),
+    };
+
+    let hosts = match u32::from(subnet.prefix()) {
+        prefix if prefix == bits => 0,
+        prefix                   => u128::MAX >> (128 - bits + prefix),
+    };
+    let network = addr & !hosts;
+    let mut last = network | hosts;
+
+    // A /31 or /32, and likewise for IPv6, has no network or broadcast
+    // address to keep clear of.
+    if hosts <= 1 {
+        return (addr, last);
+    }
+
+    if bits == 32 {
+        last -= 1;
+    }
+
+    (addr.max(network + 1), last)
+}
+
+fn to_addr(subnet: &Subnet, value: u128) -> IpAddr {
+    match subnet.addr() {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(value as u32)),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from(value)),
+    }
+}
+
+/// Render the template once for each number in numbers, giving each jail
+/// the next address of the subnet, see `src/series.rs` for the
+/// placeholders. The jails are returned in order as one configuration.
+///
+/// If the subnet runs out of addresses before the series does, nothing is
+/// rendered and `SeriesError::Exhausted` says how many addresses there are.
+pub fn generate_series<R>(template: &str, numbers: R, subnet: Subnet)
+-> Result<JailConfig<'static>, SeriesError>
+where
+    R: IntoIterator<Item = u32>,
+{
+    let numbers: Vec<u32> = numbers.into_iter().collect();
+    let (first, last) = host_range(&subnet);
+    let available = match last.checked_sub(first) {
+        Some(span) => span + 1,
+        None       => 0,
+    };
+
+    if numbers.len() as u128 > available {
+        return Err(SeriesError::Exhausted {
+            subnet,
+            requested: numbers.len(),
+            available,
+        });
+    }
+
+    let prefix = subnet.prefix().to_string();
+    let mut output = String::new();
+
+    for (n, addr) in numbers.into_iter().zip(first..) {
+        let ip = to_addr(&subnet, addr).to_string();
+        let number = n.to_string();
+
+        let context = |name: &str| {
+            let value = match name {
+                "n"      => &number,
+                "ip"     => &ip,
+                "prefix" => &prefix,
+                _        => return None,
+            };
+
+            Some(value.to_string())
+        };
+
+        let rendered = render_template(template, &context)
+            .map_err(|error| SeriesError::Template { n, error })?;
+
+        if !output.is_empty() {
+            output.push('\n');
+        }
+
+        output.push_str(&rendered);
+
+        if !output.ends_with('\n') {
+            output.push('\n');
+        }
+    }
+
+    // Each block parsed on its own, so together they do too.
+    let statements = parse(&output).expect("rendered blocks parse");
+
+    Ok(JailConfig::new(into_owned(statements)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const TEMPLATE: &str = indoc!(r#"
+        web{{n}} {
+            host.hostname = "web{{n}}.example.org";
+            ip4.addr = "{{ip}}/{{prefix}}";
+            path = /jails/web{{n}};
+        }
+        "#);
+
+    #[test]
+    fn test_generate_series() {
+        let subnet: Subnet = "10.0.0.0/24".parse().unwrap();
+        let config = generate_series(TEMPLATE, 1..=50, subnet).unwrap();
+
+        let names: Vec<_> = config.jails().map(|block| block.name()).collect();
+        assert_eq!(names.len(), 50);
+        assert_eq!(names[0], "web1");
+        assert_eq!(names[49], "web50");
+
+        assert_eq!(config.get_param("web1", "ip4.addr").as_deref(), Some("10.0.0.1/24"));
+        assert_eq!(config.get_param("web50", "ip4.addr").as_deref(), Some("10.0.0.50/24"));
+        assert_eq!(config.get_param("web7", "path").as_deref(), Some("/jails/web7"));
+
+        // Addresses continue from the one the subnet is given with.
+        let subnet: Subnet = "2001:db8::fe/120".parse().unwrap();
+        let template = "db{{n}} {\n    ip6.addr = {{ip}};\n}\n";
+        let config = generate_series(template, vec![3, 9], subnet).unwrap();
+
+        assert_eq!(config.get_param("db3", "ip6.addr").as_deref(), Some("2001:db8::fe"));
+        assert_eq!(config.get_param("db9", "ip6.addr").as_deref(), Some("2001:db8::ff"));
+        assert!(generate_series(template, 1..=3, subnet).is_err());
+    }
+
+    #[test]
+    fn test_generate_series_exhausted() {
+        let subnet: Subnet = "10.0.0.0/29".parse().unwrap();
+
+        assert!(generate_series(TEMPLATE, 1..=6, subnet).is_ok());
+
+        let err = generate_series(TEMPLATE, 1..=7, subnet).unwrap_err();
+        assert_eq!(err, SeriesError::Exhausted {
+            subnet,
+            requested: 7,
+            available: 6,
+        });
+        assert_eq!(err.to_string(), "7 jails requested but subnet 10.0.0.0/29 has room for 6");
+
+        let subnet: Subnet = "10.0.0.5/32".parse().unwrap();
+        assert!(generate_series(TEMPLATE, 1..=1, subnet).is_ok());
+        assert!(generate_series(TEMPLATE, 1..=2, subnet).is_err());
+
+        // Only the broadcast address is left.
+        let subnet: Subnet = "10.0.0.7/29".parse().unwrap();
+        assert!(generate_series(TEMPLATE, 1..=1, subnet).is_err());
+    }
+
+    #[test]
+    fn test_generate_series_template_error() {
+        let subnet: Subnet = "10.0.0.0/24".parse().unwrap();
+        let err = generate_series("web{{n}} {\n    path = {{root}};\n}\n", 1..=2, subnet)
+            .unwrap_err();
+
+        assert_eq!(err, SeriesError::Template {
+            n:     1,
+            error: TemplateError::Missing { names: vec!["root".to_string()] },
+        });
+    }
+}
@@ -0,0 +1,153 @@
+// A configuration shared between threads.
+//
+// A daemon serving requests about its jails reads the configuration from
+// many threads while, every so often, a reload replaces it. ArcJailConfig
+// holds the current configuration behind an Arc. Readers take a snapshot,
+// which stays as it was for as long as they hold it, and a reload or update
+// swaps in a whole new configuration, so no reader ever sees one half
+// changed. Updates are copy-on-write: the statements are copied, changed and
+// made into a new configuration, leaving any snapshots taken before alone.
+//
+// The lock is only held while the Arc is cloned or swapped, or while an
+// update runs, so a slow reader never holds up a reload. Configurations are
+// parsed before the lock is taken.
+use std::sync::{
+    Arc,
+    PoisonError,
+    RwLock,
+};
+use crate::{
+    into_owned,
+    parse,
+    JailConfOwned,
+    JailConfig,
+    ParseError,
+};
+
+/// A handle to a configuration shared between threads, see `load`, `store`
+/// and `update`. Clones of the handle share the same configuration.
+#[derive(Clone, Debug, Default)]
+pub struct ArcJailConfig {
+    current: Arc<RwLock<Arc<JailConfig<'static>>>>,
+}
+
+impl ArcJailConfig {
+    /// Share the given configuration.
+    pub fn new(config: JailConfig<'static>) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(config))),
+        }
+    }
+
+    /// A snapshot of the current configuration. It's unaffected by any
+    /// later `store`, `reload` or `update`.
+    pub fn load(&self) -> Arc<JailConfig<'static>> {
+        // The Arc is only ever replaced whole, so a panic while the lock was
+        // held can't have left it half changed.
+        let current = self.current.read().unwrap_or_else(PoisonError::into_inner);
+
+        Arc::clone(&current)
+    }
+
+    /// Replace the configuration, returning the one replaced.
+    pub fn store(&self, config: JailConfig<'static>) -> Arc<JailConfig<'static>> {
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+
+        std::mem::replace(&mut *current, Arc::new(config))
+    }
+
+    /// Parse input and, if it parses, replace the configuration with it,
+    /// returning the new configuration. On an error the configuration is
+    /// left as it was.
+    pub fn reload(&self, input: &str) -> Result<Arc<JailConfig<'static>>, ParseError> {
+        let config = Arc::new(JailConfig::new(into_owned(parse(input)?)));
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+
+        *current = Arc::clone(&config);
+
+        Ok(config)
+    }
+
+    /// Change a copy of the current configuration's statements with f and
+    /// replace the configuration with the result, returning it. Updates are
+    /// made one at a time, so concurrent updates each see the result of the
+    /// last. If f panics, the configuration is left as it was.
+    pub fn update<F>(&self, f: F) -> Arc<JailConfig<'static>>
+    where
+        F: FnOnce(&mut Vec<JailConfOwned>),
+    {
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        let mut statements = current.statements().to_vec();
+
+        f(&mut statements);
+
+        *current = Arc::new(JailConfig::new(statements));
+
+        Arc::clone(&current)
+    }
+}
+
+impl From<JailConfig<'static>> for ArcJailConfig {
+    fn from(config: JailConfig<'static>) -> Self {
+        Self::new(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use crate::builder::param_bool;
+
+    #[test]
+    fn test_shared_is_send_sync() {
+        fn send_sync<T: Send + Sync>() {}
+
+        send_sync::<ArcJailConfig>();
+        send_sync::<JailConfig<'static>>();
+    }
+
+    #[test]
+    fn test_reload() {
+        let shared = ArcJailConfig::default();
+        let before = shared.load();
+
+        shared.reload("www {\n    persist;\n}\n").unwrap();
+
+        assert!(before.statements().is_empty());
+        assert!(shared.load().jail("www").is_some());
+
+        assert!(shared.reload("www {\n").is_err());
+        assert!(shared.load().jail("www").is_some());
+
+        let replaced = shared.store(JailConfig::default());
+
+        assert!(replaced.jail("www").is_some());
+        assert!(shared.load().statements().is_empty());
+    }
+
+    #[test]
+    fn test_update() {
+        let shared = ArcJailConfig::new(JailConfig::default());
+        let before = shared.load();
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+
+                thread::spawn(move || {
+                    shared.update(|statements| {
+                        statements.push(param_bool("persist"));
+                    });
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert!(before.statements().is_empty());
+        assert_eq!(shared.load().statements().len(), 4);
+    }
+}
@@ -0,0 +1,284 @@
+// Locations of parsed nodes within their input.
+//
+// Each node's source text is borrowed from the input it was parsed from, so
+// once parsing is complete its offset can be found from where that text lies
+// in the input. Spans are filled in this way by `parse`, rather than by the
+// individual parsers, which only ever see the input remaining to them.
+// Nodes which weren't parsed, such as those made with the builders, have an
+// empty span at the start of the input.
+use std::ops::Range;
+use crate::JailConf;
+
+/// A location in the input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    offset: usize,
+    line:   usize,
+    column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            line:   1,
+            column: 1,
+        }
+    }
+}
+
+impl Position {
+    /// The byte offset into the input.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The line, starting from 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The column, starting from 1. Columns are in bytes unless a tab width
+    /// was set, see `ParseOptions::tab_width`.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    // Where a position after an edit is once it's made, given where the
+    // replaced text ended and where its replacement ends. Positions on the
+    // line the replaced text ended on move along it as well as down.
+    fn shifted(self, old_end: Position, new_end: Position) -> Self {
+        let column = if self.line == old_end.line {
+            self.column - old_end.column + new_end.column
+        }
+        else {
+            self.column
+        };
+
+        Self {
+            offset: self.offset - old_end.offset + new_end.offset,
+            line:   self.line - old_end.line + new_end.line,
+            column,
+        }
+    }
+}
+
+/// The part of the input a node was parsed from.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Span {
+    start: Position,
+    end:   Position,
+}
+
+impl Span {
+    /// The position of the first byte of the node.
+    pub fn start(&self) -> Position {
+        self.start
+    }
+
+    /// The position just after the last byte of the node.
+    pub fn end(&self) -> Position {
+        self.end
+    }
+
+    /// The byte offsets of the node, for slicing the input.
+    pub fn range(&self) -> Range<usize> {
+        self.start.offset..self.end.offset
+    }
+}
+
+// Converts byte offsets into positions.
+pub(crate) struct Lines {
+    starts:    Vec<usize>,
+    base:      Position,
+    // The offsets of tabs, only kept for a tab width other than 1.
+    tabs:      Vec<usize>,
+    tab_width: usize,
+}
+
+impl Lines {
+    pub(crate) fn new(input: &str) -> Self {
+        Self::starting_at(input, Position::default())
+    }
+
+    // Positions within input, which itself starts at base in some larger
+    // input.
+    pub(crate) fn starting_at(input: &str, base: Position) -> Self {
+        let starts = Some(0).into_iter()
+            .chain(input.match_indices('\n').map(|(newline, _)| newline + 1))
+            .collect();
+
+        Self {
+            starts,
+            base,
+            tabs:      Vec::new(),
+            tab_width: 1,
+        }
+    }
+
+    // Count a tab in input as moving on to the next multiple of width.
+    pub(crate) fn with_tab_width(mut self, input: &str, width: usize) -> Self {
+        if width > 1 {
+            self.tabs = input.match_indices('\t').map(|(tab, _)| tab).collect();
+            self.tab_width = width;
+        }
+
+        self
+    }
+
+    // The column of offset on the line starting at start, which is at
+    // first_column.
+    fn column(&self, start: usize, offset: usize, first_column: usize) -> usize {
+        let first_tab = self.tabs.partition_point(|&tab| tab < start);
+        let mut column = first_column;
+        let mut from = start;
+
+        for &tab in self.tabs[first_tab..].iter().take_while(|&&tab| tab < offset) {
+            column += tab - from;
+            column = (column - 1) / self.tab_width * self.tab_width + self.tab_width + 1;
+            from = tab + 1;
+        }
+
+        column + offset - from
+    }
+
+    pub(crate) fn position(&self, offset: usize) -> Position {
+        let line = match self.starts.binary_search(&offset) {
+            Ok(line)  => line,
+            Err(next) => next - 1,
+        };
+
+        // Only the first line starts part way through a line of the larger
+        // input.
+        let first_column = if line == 0 { self.base.column } else { 1 };
+
+        Position {
+            offset: self.base.offset + offset,
+            line:   self.base.line + line,
+            column: self.column(self.starts[line], offset, first_column),
+        }
+    }
+
+    pub(crate) fn span(&self, range: Range<usize>) -> Span {
+        Span {
+            start: self.position(range.start),
+            end:   self.position(range.end),
+        }
+    }
+}
+
+fn span_mut<'s>(statement: &'s mut JailConf) -> &'s mut Span {
+    match statement {
+        JailConf::Block(block)      => &mut block.span,
+        JailConf::Comment(comment)  => &mut comment.span,
+        JailConf::Include(include)  => &mut include.span,
+        JailConf::ParamBool(param)  => &mut param.span,
+        JailConf::ParamValue(param) => &mut param.span,
+        JailConf::Variable(var)     => &mut var.span,
+    }
+}
+
+fn assign_lines(input: &str, lines: &Lines, statements: &mut [JailConf]) {
+    let base = input.as_ptr() as usize;
+
+    for statement in statements {
+        let source = statement.source_text();
+        let start = match (source.as_ptr() as usize).checked_sub(base) {
+            Some(start) if start + source.len() <= input.len() => start,
+            // Source text which doesn't come from the input has no span.
+            _ => continue,
+        };
+        let end = start + source.len();
+
+        *span_mut(statement) = lines.span(start..end);
+
+        if let JailConf::Block(block) = statement {
+            assign_lines(input, lines, &mut block.params);
+        }
+    }
+}
+
+// Fill in the spans of statements parsed from input, and of their contents.
+pub(crate) fn assign(input: &str, statements: &mut [JailConf]) {
+    assign_lines(input, &Lines::new(input), statements);
+}
+
+// Fill in spans as assign does, with tabs the given width.
+pub(crate) fn assign_with_tabs(input: &str, tab_width: usize, statements: &mut [JailConf]) {
+    let lines = Lines::new(input).with_tab_width(input, tab_width);
+
+    assign_lines(input, &lines, statements);
+}
+
+// Fill in spans as assign does, for input starting at base in some larger
+// input.
+pub(crate) fn assign_at(input: &str, base: Position, statements: &mut [JailConf]) {
+    assign_lines(input, &Lines::starting_at(input, base), statements);
+}
+
+// Move the spans of statements after an edit, and of their contents, to
+// where they are once it's made, see Position::shifted.
+pub(crate) fn shift(statements: &mut [JailConf], old_end: Position, new_end: Position) {
+    for statement in statements {
+        let span = span_mut(statement);
+
+        span.start = span.start.shifted(old_end, new_end);
+        span.end = span.end.shifted(old_end, new_end);
+
+        if let JailConf::Block(block) = statement {
+            shift(&mut block.params, old_end, new_end);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn test_positions() {
+        let lines = Lines::new("ab\ncd\n\nef");
+
+        assert_eq!(lines.position(0), Position { offset: 0, line: 1, column: 1 });
+        assert_eq!(lines.position(4), Position { offset: 4, line: 2, column: 2 });
+        assert_eq!(lines.position(6), Position { offset: 6, line: 3, column: 1 });
+        assert_eq!(lines.position(9), Position { offset: 9, line: 4, column: 3 });
+    }
+
+    #[test]
+    fn test_tab_width() {
+        let input = "\ta\n  \tb\n\t\tc\n1234\td";
+        let lines = Lines::new(input).with_tab_width(input, 4);
+
+        assert_eq!(lines.position(1).column(), 5);
+        assert_eq!(lines.position(6).column(), 5);
+        assert_eq!(lines.position(10).column(), 9);
+        assert_eq!(lines.position(17).column(), 9);
+        assert_eq!(Lines::new(input).position(17).column(), 6);
+    }
+
+    #[test]
+    fn test_parsed_spans() {
+        let input = "persist;\nnginx {\n    ip4.addr = 127.0.1.1;\n}\n";
+        let res = parse(input).unwrap();
+
+        let span = res[1].span();
+        assert_eq!(&input[span.range()], res[1].source_text());
+        assert_eq!((span.start().line(), span.start().column()), (2, 1));
+        assert_eq!((span.end().line(), span.end().column()), (4, 2));
+
+        match &res[1] {
+            JailConf::Block(block) => {
+                let param = block.params()[0].span();
+
+                assert_eq!(param.range(), 21..42);
+                assert_eq!(param.start().line(), 3);
+                assert_eq!(param.start().column(), 5);
+            },
+            _ => panic!("expected a block"),
+        }
+
+        assert_eq!(res[0].span().range(), 0..8);
+    }
+}
@@ -0,0 +1,192 @@
+// Statistics on a configuration.
+//
+// Counts of what a configuration is made of, for a quick look at whether a
+// generated configuration came out as expected. Statements are counted as
+// they're written, at any depth, so a parameter set globally counts once
+// however many jails it applies to, and the per jail counts are of the
+// parameters set in the jail's own blocks.
+use crate::visit::{
+    walk,
+    walk_block,
+    Visitor,
+};
+use crate::{
+    JailBlock,
+    JailComment,
+    JailConfig,
+    JailInclude,
+    JailParamBool,
+    JailParamValue,
+    JailVariable,
+};
+
+/// Counts of the statements in a configuration, see `JailConfig::stats`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigStats {
+    blocks:    usize,
+    wildcards: usize,
+    params:    usize,
+    appends:   usize,
+    variables: usize,
+    comments:  usize,
+    includes:  usize,
+    jails:     Vec<(String, usize)>,
+}
+
+impl ConfigStats {
+    /// The number of jails configured, each once however many blocks
+    /// configure it. Wildcard blocks aren't jails.
+    pub fn jails(&self) -> usize {
+        self.jails.len()
+    }
+
+    /// The number of blocks, including wildcard blocks.
+    pub fn blocks(&self) -> usize {
+        self.blocks
+    }
+
+    /// The number of wildcard blocks, eg. `* { ... }`.
+    pub fn wildcards(&self) -> usize {
+        self.wildcards
+    }
+
+    /// The number of parameters set, with or without a value.
+    pub fn params(&self) -> usize {
+        self.params
+    }
+
+    /// The number of parameters and variables appended to with `+=`.
+    pub fn appends(&self) -> usize {
+        self.appends
+    }
+
+    /// The number of variable definitions.
+    pub fn variables(&self) -> usize {
+        self.variables
+    }
+
+    /// The number of comments.
+    pub fn comments(&self) -> usize {
+        self.comments
+    }
+
+    /// The number of `.include` directives.
+    pub fn includes(&self) -> usize {
+        self.includes
+    }
+
+    /// The number of parameters set in each jail's own blocks, in the order
+    /// the jails are first configured.
+    pub fn jail_params(&self) -> &[(String, usize)] {
+        &self.jails
+    }
+}
+
+impl<'a> Visitor<'a> for ConfigStats {
+    fn visit_block(&mut self, block: &JailBlock<'a>) {
+        self.blocks += 1;
+
+        if block.is_wildcard() {
+            self.wildcards += 1;
+        }
+        else {
+            let params = block.params()
+                .iter()
+                .filter(|statement| statement.is_param())
+                .count();
+
+            match self.jails.iter_mut().find(|(name, _)| name == block.name()) {
+                Some((_, count)) => *count += params,
+                None             => self.jails.push((block.name().to_string(), params)),
+            }
+        }
+
+        walk_block(block, self);
+    }
+
+    fn visit_comment(&mut self, _comment: &JailComment<'a>) {
+        self.comments += 1;
+    }
+
+    fn visit_include(&mut self, _include: &JailInclude<'a>) {
+        self.includes += 1;
+    }
+
+    fn visit_param_bool(&mut self, _param: &JailParamBool<'a>) {
+        self.params += 1;
+    }
+
+    fn visit_param_value(&mut self, param: &JailParamValue<'a>) {
+        self.params += 1;
+
+        if param.is_append() {
+            self.appends += 1;
+        }
+    }
+
+    fn visit_variable(&mut self, var: &JailVariable<'a>) {
+        self.variables += 1;
+
+        if var.is_append() {
+            self.appends += 1;
+        }
+    }
+}
+
+impl<'a> JailConfig<'a> {
+    /// Counts of the jails, parameters, variables, comments and includes in
+    /// the configuration, see `ConfigStats`.
+    pub fn stats(&self) -> ConfigStats {
+        let mut stats = ConfigStats::default();
+
+        walk(self.statements(), &mut stats);
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_stats() {
+        let input = indoc!(r#"
+            # Defaults for every jail.
+            $domain = example.org;
+            path = "/usr/jails/$name";
+            .include "/etc/jail.conf.d/*.conf";
+            * {
+                persist;
+            }
+            www {
+                host.hostname = "www.$domain";
+                ip4.addr = 192.0.2.1;
+                ip4.addr += 192.0.2.2;
+            }
+            db {
+                # No network.
+                mount.devfs;
+            }
+            www {
+                exec.start = "/bin/sh /etc/rc";
+            }
+            "#);
+
+        let stats = JailConfig::parse(input).unwrap().stats();
+
+        assert_eq!(stats.jails(), 2);
+        assert_eq!(stats.blocks(), 4);
+        assert_eq!(stats.wildcards(), 1);
+        assert_eq!(stats.params(), 7);
+        assert_eq!(stats.appends(), 1);
+        assert_eq!(stats.variables(), 1);
+        assert_eq!(stats.comments(), 2);
+        assert_eq!(stats.includes(), 1);
+        assert_eq!(stats.jail_params(), [
+            ("www".to_string(), 4),
+            ("db".to_string(), 1),
+        ]);
+    }
+}
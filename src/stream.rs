@@ -0,0 +1,278 @@
+// Parsing from a reader, one statement at a time.
+//
+// `parse` needs the whole input in memory, and gives nothing back until all
+// of it has been parsed. parse_reader instead reads a line at a time, parsing
+// each top level statement as soon as enough of it has been read and
+// discarding its text once it's been handed out. Only the statement being
+// parsed is held in memory, which for very large generated configurations
+// is far smaller than the whole, and errors are reported as soon as the
+// statement containing them has been read.
+//
+// Statements are yielded as they're found, so annotations are attached to a
+// block from the comments yielded before it, and spans and error positions
+// are given relative to the start of the whole input.
+use std::io::{
+    self,
+    BufRead,
+};
+use crate::error::expected;
+use crate::limit;
+use crate::scan::{
+    Found,
+    Scanner,
+};
+use crate::span::{
+    self,
+    Lines,
+};
+use crate::{
+    annotation,
+    parse,
+    parse_statement,
+    Annotations,
+    JailConf,
+    ParseError,
+    ParseOptions,
+    Position,
+};
+
+// Whether text holds the whole of its first statement, so that failing to
+// parse it is an error rather than a sign that more input is needed. This is
+// the case once a `;` or the brace closing a block is found outside of any
+// comment or string.
+fn complete(text: &str) -> bool {
+    let mut depth = 0;
+
+    for piece in Scanner::new(text.as_bytes(), 0) {
+        match piece {
+            Ok((_, Found::Byte(b'{')))               => depth += 1,
+            Ok((_, Found::Byte(b'}'))) if depth <= 1 => return true,
+            Ok((_, Found::Byte(b'}')))               => depth -= 1,
+            Ok((_, Found::Byte(b';'))) if depth == 0 => return true,
+            Ok(_)                                    => {},
+            // An unterminated comment or string may end on a later line.
+            Err(_)                                   => return false,
+        }
+    }
+
+    false
+}
+
+// What a reader must do next.
+pub(crate) enum Step {
+    // Hand out a statement.
+    Statement(JailConf<'static>),
+    // Read another line into the buffer.
+    ReadLine,
+    // Stop, having read the whole input.
+    Done,
+}
+
+// The input read but not yet handed out as statements, shared by the readers
+// for blocking and async IO, which differ only in how they read lines.
+#[derive(Default)]
+pub(crate) struct Pending {
+    pub(crate) buffer: String,
+    // The position of the start of the buffer within the whole input.
+    base:              Position,
+    annotations:       Annotations,
+    eof:               bool,
+}
+
+impl Pending {
+    // Drop the first len bytes of the buffer, moving its base past them.
+    fn consume(&mut self, len: usize) {
+        self.base = Lines::starting_at(&self.buffer[..len], self.base).position(len);
+        self.buffer.drain(..len);
+    }
+
+    // Note the result of reading a line into the buffer, zero bytes being
+    // the end of input.
+    pub(crate) fn line_read(&mut self, result: io::Result<usize>) -> Result<(), ParseError> {
+        match result {
+            Ok(0)  => {
+                self.eof = true;
+                Ok(())
+            },
+            Ok(_)  => Ok(()),
+            Err(e) => {
+                let expected = format!("readable input ({})", e);
+                let input = self.buffer.as_bytes();
+
+                Err(ParseError::new(input, input.len(), &expected).starting_at(self.base))
+            },
+        }
+    }
+
+    // The error for a statement at the start of the buffer which can't be
+    // parsed.
+    fn error(&self) -> ParseError {
+        let error = match parse(&self.buffer) {
+            Err(e) => e,
+            Ok(_)  => ParseError::new(self.buffer.as_bytes(), 0, expected(&self.buffer)),
+        };
+
+        error.starting_at(self.base)
+    }
+
+    pub(crate) fn step(&mut self) -> Result<Step, ParseError> {
+        let space = self.buffer.len() - self.buffer.trim_start().len();
+        self.consume(space);
+
+        if self.buffer.is_empty() {
+            return Ok(if self.eof { Step::Done } else { Step::ReadLine });
+        }
+
+        // Parsing after every line would parse long blocks over and over, so
+        // wait until the statement has been read in full. Comments end at the
+        // end of a line, and so are tried as soon as they've been read.
+        let comment = ["#", "//", "/*"].iter().any(|c| self.buffer.starts_with(c));
+
+        if !self.eof && !comment && !complete(&self.buffer) {
+            return Ok(Step::ReadLine);
+        }
+
+        limit::check(&self.buffer, &ParseOptions::new())
+            .map_err(|e| e.starting_at(self.base))?;
+
+        let parsed = match parse_statement(&self.buffer) {
+            Ok((rest, mut statement)) => {
+                let statements = std::slice::from_mut(&mut statement);
+
+                span::assign_at(&self.buffer, self.base, statements);
+                annotation::collect(&mut self.annotations, &mut statement);
+
+                Some((self.buffer.len() - rest.len(), statement.into_owned()))
+            },
+            Err(_) => None,
+        };
+
+        match parsed {
+            Some((len, statement)) => {
+                self.consume(len);
+
+                Ok(Step::Statement(statement))
+            },
+            None if self.eof || complete(&self.buffer) => Err(self.error()),
+            None => Ok(Step::ReadLine),
+        }
+    }
+}
+
+/// An iterator over the statements read from a reader, see `parse_reader`.
+pub struct StatementReader<R> {
+    reader:  R,
+    pending: Pending,
+    failed:  bool,
+}
+
+impl<R: BufRead> StatementReader<R> {
+    fn next_statement(&mut self) -> Result<Option<JailConf<'static>>, ParseError> {
+        loop {
+            match self.pending.step()? {
+                Step::Statement(statement) => return Ok(Some(statement)),
+                Step::Done                 => return Ok(None),
+                Step::ReadLine             => {
+                    let read = self.reader.read_line(&mut self.pending.buffer);
+
+                    self.pending.line_read(read)?;
+                },
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for StatementReader<R> {
+    type Item = Result<JailConf<'static>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        match self.next_statement() {
+            Ok(statement) => statement.map(Ok),
+            Err(e)        => {
+                self.failed = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+/// Parse statements from a reader as they're read, without holding the
+/// whole input in memory. Each top level statement is yielded as soon as it
+/// has been parsed, and iteration stops after the first error.
+pub fn parse_reader<R: BufRead>(reader: R) -> StatementReader<R> {
+    StatementReader {
+        reader,
+        pending: Pending::default(),
+        failed:  false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        persist; $base = /jails;
+        # @owner: team-web
+        "web server" {
+            path = "$base/www";
+            exec.start = "/bin/sh \
+                /etc/rc";
+        }
+        /*
+         * Multi-line comment.
+         */
+        db { persist; }
+        "#);
+
+    fn read(input: &str) -> Vec<Result<JailConf<'static>, ParseError>> {
+        parse_reader(input.as_bytes()).collect()
+    }
+
+    #[test]
+    fn test_parse_reader_matches_parse() {
+        let streamed: Vec<JailConf> = read(INPUT).into_iter()
+            .map(Result::unwrap)
+            .collect();
+
+        assert_eq!(streamed, parse(INPUT).unwrap());
+    }
+
+    #[test]
+    fn test_parse_reader_error_position() {
+        let input = "persist;\nnginx {\n    path = \"/a\"\n}\nnever { reached; }\n";
+        let results = read(input);
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert_eq!(results[1].as_ref().unwrap_err(), &parse(input).unwrap_err());
+    }
+
+    #[test]
+    fn test_parse_reader_error_at_end() {
+        let input = "persist;\n/* unterminated\n";
+        let results = read(input);
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].as_ref().unwrap_err(), &parse(input).unwrap_err());
+    }
+
+    #[test]
+    fn test_complete() {
+        assert!(complete("persist;"));
+        assert!(complete("www {\n    persist;\n}"));
+        assert!(complete("path = \"a;\" ;"));
+        assert!(!complete("path = \"a;"));
+        assert!(!complete("www {\n    persist;\n"));
+        assert!(!complete("path = ${name} \\\n"));
+        assert!(!complete("/* a; */"));
+        assert!(complete("path = /jails//www;"));
+        assert!(complete("path = a\\\";"));
+        assert!(!complete("path = a,// x;"));
+    }
+}
@@ -0,0 +1,504 @@
+// Parsing which follows jail(8)'s own grammar.
+//
+// The default parser is line oriented and forgiving in places jail(8)
+// isn't, and stricter in others. With ParseOptions::strict, input is instead
+// split into tokens by lex, as jail(8)'s lexer splits it, and parsed by the
+// rules of its yacc grammar, so that what's accepted is what jail(8)
+// accepts:
+//
+//   - Unquoted words are made of jail(8)'s word characters, plus `$`
+//     references and backslash escapes. The default parser takes nearly any
+//     character in a parameter or jail name, eg. a `,` or a control
+//     character, but refuses a `+`, so `c++ { }` is only a jail in strict
+//     mode.
+//   - A `${` reference must be closed by a `}`. The default parser takes
+//     an unclosed one within double quotes, which is only refused once
+//     variables are expanded.
+//   - Escapes are those of escape::unescape in both modes, and single
+//     quoted strings are literal. C style comments don't nest in either.
+//   - Tokens may be separated by any whitespace or comments, so a statement
+//     may be split across lines anywhere, eg. with the `{` of a block on the
+//     line after its name, which the default parser refuses. Comments within
+//     a statement are dropped.
+//   - The pieces of a value are only joined when nothing separates them,
+//     eg. `"/bin/sh "/etc/rc`. The default parser also joins pieces
+//     separated by spaces, which jail(8) refuses.
+//   - Every parameter ends with a `;`, and a `;` on its own is an empty
+//     statement, eg. after a block's `}`, which the default parser refuses.
+use std::borrow::Cow;
+use std::ops::Range;
+use crate::annotation::{
+    attach,
+    Annotations,
+};
+use crate::escape::{
+    decode,
+    quote_style,
+};
+use crate::lex::{
+    lex,
+    Token,
+};
+use crate::{
+    bare_len,
+    CommentStyle,
+    JailBlock,
+    JailComment,
+    JailConf,
+    JailInclude,
+    JailParamBool,
+    JailParamValue,
+    JailVariable,
+    ParseError,
+    Span,
+};
+
+// The offset of a `${` without a closing `}` in the text of a double quoted
+// string, skipping escaped characters.
+fn unclosed_reference(text: &str) -> Option<usize> {
+    let mut chars = text.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            },
+            '$' if text[i..].starts_with("${") => {
+                let close = match text[i..].find('}') {
+                    Some(close) => close,
+                    None        => return Some(i),
+                };
+
+                // Carry on after the reference.
+                while chars.next().is_some_and(|(j, _)| j < i + close) {}
+            },
+            _ => {},
+        }
+    }
+
+    None
+}
+
+// The error for a word or string which jail(8)'s lexer wouldn't take as a
+// single token, if it isn't one.
+fn check_token(token: &Token, start: usize) -> Option<(usize, &'static str)> {
+    match token {
+        Token::Word(word) => {
+            let len = bare_len(word);
+
+            if len == word.len() {
+                None
+            }
+            else if word[len..].starts_with("${") {
+                Some((start + len, "'}' to close variable reference"))
+            }
+            else {
+                Some((start + len, "a character allowed in a word"))
+            }
+        },
+        Token::String(string) if string.starts_with('"') => {
+            let inner = &string[1..string.len() - 1];
+            let unclosed = unclosed_reference(inner)?;
+
+            Some((start + 1 + unclosed, "'}' to close variable reference"))
+        },
+        Token::Unknown(text) if text.starts_with("/*") => {
+            Some((start, "'*/' to close comment"))
+        },
+        Token::Unknown(_) => Some((start, "closing quote after string")),
+        _                 => None,
+    }
+}
+
+fn comment<'a>(text: &'a str) -> JailComment<'a> {
+    let (comment, style) = if let Some(rest) = text.strip_prefix('#') {
+        (rest, CommentStyle::Shell)
+    }
+    else if let Some(rest) = text.strip_prefix("//") {
+        (rest, CommentStyle::CPP)
+    }
+    else {
+        (&text[2..text.len() - 2], CommentStyle::C)
+    };
+
+    JailComment {
+        comment: comment.into(),
+        style,
+        source:  text.into(),
+        span:    Span::default(),
+    }
+}
+
+// A string, or a list of them, as written: where it is in the input, and
+// its text if that isn't simply the input there.
+struct Raw<'a> {
+    range: Range<usize>,
+    text:  Option<String>,
+    input: &'a str,
+}
+
+impl<'a> Raw<'a> {
+    fn raw(&self) -> Cow<'a, str> {
+        match &self.text {
+            Some(text) => Cow::Owned(text.clone()),
+            None       => Cow::Borrowed(&self.input[self.range.clone()]),
+        }
+    }
+
+    fn decoded(&self) -> Cow<'a, str> {
+        match &self.text {
+            Some(text) => Cow::Owned(decode(text).into_owned()),
+            None       => decode(&self.input[self.range.clone()]),
+        }
+    }
+}
+
+struct Parser<'a> {
+    input:  &'a str,
+    // Every token but whitespace, with where it is in the input.
+    tokens: Vec<(Token<'a>, Range<usize>)>,
+    pos:    usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        let tokens = lex(input).into_iter()
+            .filter(|(token, _)| !matches!(token, Token::Whitespace(_)))
+            .map(|(token, span)| (token, span.range()))
+            .collect();
+
+        Self {
+            input,
+            tokens,
+            pos: 0,
+        }
+    }
+
+    fn error(&self, offset: usize, expected: &str) -> ParseError {
+        ParseError::new(self.input.as_bytes(), offset, expected)
+    }
+
+    // Where the current token starts, or the end of input.
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.input.len(), |(_, range)| range.start)
+    }
+
+    // The current token, skipping any comments when within a statement.
+    fn peek(&mut self, skip_comments: bool) -> Result<Option<Token<'a>>, ParseError> {
+        while skip_comments && matches!(self.tokens.get(self.pos), Some((Token::Comment(_), _))) {
+            self.pos += 1;
+        }
+
+        match self.tokens.get(self.pos) {
+            Some((token, range)) => match check_token(token, range.start) {
+                Some((offset, expected)) => Err(self.error(offset, expected)),
+                None                     => Ok(Some(*token)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    // Consume the current token if it's the one given, skipping comments.
+    fn expect(&mut self, want: Token, expected: &str) -> Result<usize, ParseError> {
+        match self.peek(true)? {
+            Some(token) if token == want => {
+                let end = self.tokens[self.pos].1.end;
+                self.pos += 1;
+
+                Ok(end)
+            },
+            _ => Err(self.error(self.offset(), expected)),
+        }
+    }
+
+    // A string: a word or quoted string, joined with any which follow it
+    // with nothing in between. Returns the range of the input it covers.
+    fn string(&mut self, expected: &str) -> Result<Range<usize>, ParseError> {
+        match self.peek(true)? {
+            Some(Token::Word(_)) | Some(Token::String(_)) => {},
+            _ => return Err(self.error(self.offset(), expected)),
+        }
+
+        let mut range = self.tokens[self.pos].1.clone();
+        self.pos += 1;
+
+        while let Some((Token::Word(_), next)) | Some((Token::String(_), next)) = self.tokens.get(self.pos) {
+            if next.start != range.end {
+                break;
+            }
+
+            range.end = next.end;
+            self.peek(false)?;
+            self.pos += 1;
+        }
+
+        // lex ends a word at the `{` of an unclosed `${`.
+        if self.input[..range.end].ends_with('$') && self.input[range.end..].starts_with('{') {
+            return Err(self.error(range.end - 1, "'}' to close variable reference"));
+        }
+
+        Ok(range)
+    }
+
+    // A value: strings separated by commas. When comments or newlines come
+    // between them, the strings are joined by ", " instead.
+    fn value(&mut self) -> Result<Raw<'a>, ParseError> {
+        let mut strings = vec![self.string("a value")?];
+
+        loop {
+            match self.peek(true)? {
+                Some(Token::Comma) => {
+                    self.pos += 1;
+                    strings.push(self.string("a value after ','")?);
+                },
+                Some(Token::Word(_)) | Some(Token::String(_)) => {
+                    return Err(self.error(self.offset(), "',' or ';' between values"));
+                },
+                _ => break,
+            }
+        }
+
+        let range = strings[0].start..strings[strings.len() - 1].end;
+        let written = &self.input[range.clone()];
+        let commented = self.tokens.iter()
+            .any(|(token, at)| matches!(token, Token::Comment(_)) && range.contains(&at.start));
+
+        let text = (commented || written.contains('\n')).then(|| {
+            strings.iter()
+                .map(|string| &self.input[string.clone()])
+                .collect::<Vec<_>>()
+                .join(", ")
+        });
+
+        Ok(Raw {
+            range,
+            text,
+            input: self.input,
+        })
+    }
+
+    // A statement starting with a string: a parameter, variable, include or
+    // jail block.
+    fn statement(&mut self) -> Result<JailConf<'a>, ParseError> {
+        let name_range = self.string("a parameter, comment or jail block")?;
+        let start = name_range.start;
+        let input = self.input;
+        let name = &input[name_range];
+
+        let source = |end: usize| Cow::Borrowed(&input[start..end]);
+
+        if name == ".include" {
+            let path = self.value()?;
+            let end = self.expect(Token::Semi, "';' after include path")?;
+
+            return Ok(JailConf::Include(JailInclude {
+                path:   path.decoded(),
+                source: source(end),
+                span:   Span::default(),
+            }));
+        }
+
+        match self.peek(true)? {
+            Some(Token::LBrace) => {
+                self.pos += 1;
+
+                let params = self.statements(true)?;
+                let end = self.expect(Token::RBrace, "'}' to close jail block")?;
+
+                Ok(JailConf::Block(JailBlock {
+                    name:        decode(name),
+                    quote:       quote_style(name),
+                    params,
+                    annotations: Annotations::new(),
+                    source:      source(end),
+                    span:        Span::default(),
+                }))
+            },
+            Some(Token::Semi) if !name.starts_with('$') => {
+                let end = self.expect(Token::Semi, "';' after parameter name")?;
+
+                Ok(JailConf::ParamBool(JailParamBool {
+                    name:   decode(name),
+                    source: source(end),
+                    span:   Span::default(),
+                }))
+            },
+            Some(Token::Assign) | Some(Token::Append) => {
+                let append = self.peek(true)? == Some(Token::Append);
+                self.pos += 1;
+
+                let raw = self.value()?;
+                let end = self.expect(Token::Semi, "';' after parameter value")?;
+
+                match name.strip_prefix('$') {
+                    Some(var) => {
+                        let var = var.strip_prefix('{')
+                            .and_then(|var| var.strip_suffix('}'))
+                            .unwrap_or(var);
+
+                        Ok(JailConf::Variable(JailVariable {
+                            name:   var.into(),
+                            value:  raw.decoded(),
+                            raw:    raw.raw(),
+                            append,
+                            source: source(end),
+                            span:   Span::default(),
+                        }))
+                    },
+                    None => Ok(JailConf::ParamValue(JailParamValue {
+                        name:   decode(name),
+                        value:  raw.decoded(),
+                        raw:    raw.raw(),
+                        append,
+                        source: source(end),
+                        span:   Span::default(),
+                    })),
+                }
+            },
+            _ if name.starts_with('$') => {
+                Err(self.error(self.offset(), "'=' or '+=' after variable name"))
+            },
+            _ => Err(self.error(self.offset(), "';' after parameter name")),
+        }
+    }
+
+    // The statements up to the end of input, or the `}` ending the block
+    // they're in.
+    fn statements(&mut self, in_block: bool) -> Result<Vec<JailConf<'a>>, ParseError> {
+        let mut statements = Vec::new();
+
+        loop {
+            match self.peek(false)? {
+                Some(Token::Comment(text)) => {
+                    statements.push(JailConf::Comment(comment(text)));
+                    self.pos += 1;
+                },
+                // An empty statement.
+                Some(Token::Semi) => self.pos += 1,
+                Some(Token::RBrace) if in_block => break,
+                Some(Token::Word(_)) | Some(Token::String(_)) => {
+                    statements.push(self.statement()?);
+                },
+                None if !in_block => break,
+                None => return Err(self.error(self.offset(), "'}' to close jail block")),
+                Some(Token::RBrace) => {
+                    return Err(self.error(self.offset(), "a parameter, comment or jail block before '}'"));
+                },
+                Some(_) => {
+                    return Err(self.error(self.offset(), "a parameter, comment or jail block"));
+                },
+            }
+        }
+
+        Ok(attach(statements))
+    }
+}
+
+// Parse input by jail(8)'s grammar.
+pub(crate) fn parse(input: &str) -> Result<Vec<JailConf<'_>>, ParseError> {
+    Parser::new(input).statements(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parse_with,
+        ParseOptions,
+    };
+    use indoc::indoc;
+
+    fn strict(input: &str) -> Result<Vec<JailConf<'_>>, ParseError> {
+        parse_with(input, &ParseOptions::new().strict(true))
+    }
+
+    #[test]
+    fn test_strict_matches_default() {
+        let input = indoc!(r#"
+            # Global settings.
+            $base = "/usr/jails";
+            exec.start = "/bin/sh /etc/rc";
+            mount.devfs;
+            .include "/etc/jail.conf.d/*.conf";
+
+            /* The web server. */
+            www {
+                path = "$base/www";
+                ip4.addr = 192.0.2.1, "lo1|127.0.1.1";
+                ip4.addr += 192.0.2.2;
+                host.hostname = 'www.example.org'; // Its own name.
+                exec.poststart = "echo \"started ${name}\"";
+            }
+            "my-jail.example.org" {
+                persist;
+            }
+            "#);
+
+        assert_eq!(strict(input), crate::parse(input));
+    }
+
+    #[test]
+    fn test_strict_accepts() {
+        let input = "www\n{\n    persist\n    ;\n    ip4.addr =\n        192.0.2.1, /* primary */\n        192.0.2.2;\n};\n;\nc++ { exec.start = \"/bin/sh \"/etc/rc; }\n";
+
+        assert!(crate::parse(input).is_err());
+
+        let config = strict(input).unwrap();
+        assert_eq!(config.len(), 2);
+
+        let www = match &config[0] {
+            JailConf::Block(block) => block,
+            _                      => panic!("expected a block"),
+        };
+
+        assert_eq!(www.params().len(), 2);
+        assert_eq!(www.params()[0].source_text(), "persist\n    ;");
+
+        match &www.params()[1] {
+            JailConf::ParamValue(param) => {
+                assert_eq!(param.raw(), "192.0.2.1, 192.0.2.2");
+                assert_eq!(param.value(), "192.0.2.1,192.0.2.2");
+            },
+            _ => panic!("expected a parameter"),
+        }
+
+        match &config[1] {
+            JailConf::Block(block) => {
+                assert_eq!(block.name(), "c++");
+
+                match &block.params()[0] {
+                    JailConf::ParamValue(param) => assert_eq!(param.value(), "/bin/sh /etc/rc"),
+                    _                           => panic!("expected a parameter"),
+                }
+            },
+            _ => panic!("expected a block"),
+        }
+    }
+
+    #[test]
+    fn test_strict_refuses() {
+        let cases = [
+            ("allow,mount;", 5, "';' after parameter name"),
+            ("path = /usr/jails/${name;", 18, "'}' to close variable reference"),
+            ("path = \"/usr/jails/${name\";", 19, "'}' to close variable reference"),
+            ("exec.start = \"/bin/sh\" \"/etc/rc\";", 23, "',' or ';' between values"),
+            ("www {\n    persist;\n", 19, "'}' to close jail block"),
+            ("www {\n    persist\n}\n", 18, "';' after parameter name"),
+            ("path = /usr/\u{1}jails;", 12, "a character allowed in a word"),
+            ("$base;", 5, "'=' or '+=' after variable name"),
+            ("}", 0, "a parameter, comment or jail block before '}'"),
+            ("/* unclosed", 0, "'*/' to close comment"),
+        ];
+
+        for (input, offset, expected) in cases.iter() {
+            let error = strict(input).unwrap_err();
+
+            assert_eq!((error.offset(), error.expected()), (*offset, *expected), "{}", input);
+        }
+
+        // The default parser accepts these.
+        assert!(crate::parse("allow,mount;").is_ok());
+        assert!(crate::parse("path = \"/usr/jails/${name\";").is_ok());
+        assert!(crate::parse("exec.start = \"/bin/sh\" \"/etc/rc\";").is_ok());
+    }
+}
@@ -0,0 +1,487 @@
+// Creating, updating and querying jails with jail_set(2) and jail_get(2).
+//
+// Both calls take parameters as an array of iovecs, alternating between a
+// parameter's name and its value in the kernel's binary form: integers for
+// numeric and jailsys parameters, arrays of in_addr or in6_addr for
+// addresses, and NUL terminated strings for everything else. Boolean
+// parameters are given by name alone, with false ones prefixed by "no".
+// Parameters which only jail(8) understands, such as exec.start or
+// mount.devfs, aren't kernel parameters and are skipped.
+//
+// This module is only available on FreeBSD with the `sys` feature enabled.
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::error;
+use std::ffi::CString;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::net::{
+    IpAddr,
+    Ipv4Addr,
+    Ipv6Addr,
+};
+use crate::name::negate;
+use crate::schema::{
+    parameter,
+    ParamType,
+};
+use crate::value::{
+    parse_bool,
+    parse_ip_addr,
+};
+use crate::{
+    EffectiveParams,
+    JailConfig,
+};
+
+// Parameters which are interpreted by jail(8) rather than the kernel, along
+// with any parameters under them, eg. exec.start under exec.
+const PSEUDO_PARAMS: &[&str] = &[
+    "allow.dying",
+    "command",
+    "depend",
+    "exec",
+    "interface",
+    "ip_hostname",
+    "mount",
+    "vnet.interface",
+];
+
+// The size of the buffers string parameters are read into.
+const STRING_BUFLEN: usize = 1024;
+
+// The most addresses read for each address family.
+const MAX_ADDRS: usize = 256;
+
+#[derive(Debug)]
+pub enum SysError {
+    /// The jail isn't configured.
+    UnknownJail {
+        name: String,
+    },
+    /// A value couldn't be converted for the kernel.
+    InvalidValue {
+        name:  String,
+        value: String,
+    },
+    /// A system call failed, with the kernel's explanation if it gave one.
+    Call {
+        call:    &'static str,
+        message: String,
+        source:  io::Error,
+    },
+}
+
+impl fmt::Display for SysError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SysError::UnknownJail { name } => {
+                write!(f, "jail {} is not configured", name)
+            },
+            SysError::InvalidValue { name, value } => {
+                write!(f, "{} = {:?} can't be passed to the kernel", name, value)
+            },
+            SysError::Call { call, message, source } if message.is_empty() => {
+                write!(f, "{}: {}", call, source)
+            },
+            SysError::Call { call, message, .. } => {
+                write!(f, "{}: {}", call, message)
+            },
+        }
+    }
+}
+
+impl error::Error for SysError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SysError::Call { source, .. } => Some(source),
+            _                             => None,
+        }
+    }
+}
+
+/// A jail running on this host, as reported by jail_get(2).
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunningJail {
+    jid:    i32,
+    name:   String,
+    params: EffectiveParams,
+}
+
+impl RunningJail {
+    pub(crate) fn new(jid: i32, name: String, params: EffectiveParams) -> Self {
+        Self {
+            jid,
+            name,
+            params,
+        }
+    }
+
+    /// The jail's ID.
+    pub fn jid(&self) -> i32 {
+        self.jid
+    }
+
+    /// The jail's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The jail's path, hostname and addresses, keyed by parameter name in
+    /// the same form as `JailConfig::effective_params`.
+    pub fn params(&self) -> &EffectiveParams {
+        &self.params
+    }
+}
+
+fn is_pseudo(name: &str) -> bool {
+    PSEUDO_PARAMS.iter().any(|pseudo| {
+        name == *pseudo || name.starts_with(&format!("{}.", pseudo))
+    })
+}
+
+fn c_string(name: &str, value: &str) -> Result<CString, SysError> {
+    CString::new(value).map_err(|_| SysError::InvalidValue {
+        name:  name.to_string(),
+        value: value.to_string(),
+    })
+}
+
+// A parameter name with its value in the kernel's binary form, or no value
+// for a boolean.
+struct Param {
+    name:  CString,
+    value: Option<Vec<u8>>,
+}
+
+// Convert a parameter for the kernel. Values of list parameters may each be a
+// comma separated list.
+fn encode(name: &str, values: &[String]) -> Result<Param, SysError> {
+    let invalid = |value: &str| SysError::InvalidValue {
+        name:  name.to_string(),
+        value: value.to_string(),
+    };
+
+    let last = values.last().map_or("", String::as_str);
+    let kind = parameter(name).map_or(ParamType::String, |param| param.kind());
+
+    let (name, value) = match kind {
+        ParamType::Bool => {
+            match parse_bool(last).ok_or_else(|| invalid(last))? {
+                true  => (name.to_string(), None),
+                false => (negate(name), None),
+            }
+        },
+        ParamType::Int => {
+            let int: libc::c_int = last.trim().parse().map_err(|_| invalid(last))?;
+
+            (name.to_string(), Some(int.to_ne_bytes().to_vec()))
+        },
+        ParamType::JailSys => {
+            let sys = match last {
+                "new"     => libc::JAIL_SYS_NEW,
+                "inherit" => libc::JAIL_SYS_INHERIT,
+                "disable" => libc::JAIL_SYS_DISABLE,
+                other     => match parse_bool(other) {
+                    Some(true)  => libc::JAIL_SYS_NEW,
+                    Some(false) => libc::JAIL_SYS_DISABLE,
+                    None        => return Err(invalid(other)),
+                },
+            };
+
+            (name.to_string(), Some(sys.to_ne_bytes().to_vec()))
+        },
+        ParamType::Ip4 | ParamType::Ip6 => {
+            let mut bytes = Vec::new();
+            let entries = values.iter()
+                .flat_map(|value| value.split(','))
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty());
+
+            for entry in entries {
+                match (kind, parse_ip_addr(entry)) {
+                    (ParamType::Ip4, Some(IpAddr::V4(addr))) => {
+                        bytes.extend_from_slice(&addr.octets());
+                    },
+                    (ParamType::Ip6, Some(IpAddr::V6(addr))) => {
+                        bytes.extend_from_slice(&addr.octets());
+                    },
+                    _ => return Err(invalid(entry)),
+                }
+            }
+
+            (name.to_string(), Some(bytes))
+        },
+        ParamType::String | ParamType::List => {
+            let value = c_string(name, last)?;
+
+            (name.to_string(), Some(value.into_bytes_with_nul()))
+        },
+    };
+
+    Ok(Param {
+        name: c_string(&name, &name)?,
+        value,
+    })
+}
+
+fn iovec(bytes: &[u8]) -> libc::iovec {
+    libc::iovec {
+        iov_base: bytes.as_ptr() as *mut libc::c_void,
+        iov_len:  bytes.len(),
+    }
+}
+
+fn empty_iovec() -> libc::iovec {
+    libc::iovec {
+        iov_base: std::ptr::null_mut(),
+        iov_len:  0,
+    }
+}
+
+// The error for a failed call, including the message the kernel left in
+// errmsg.
+fn call_error(call: &'static str, errmsg: &[u8]) -> SysError {
+    let source = io::Error::last_os_error();
+    let end = errmsg.iter().position(|b| *b == 0).unwrap_or(errmsg.len());
+
+    SysError::Call {
+        call,
+        message: String::from_utf8_lossy(&errmsg[..end]).into_owned(),
+        source,
+    }
+}
+
+// Call jail_set(2) with the named jail's parameters.
+fn set(jail: &str, params: &EffectiveParams, flags: libc::c_int)
+-> Result<i32, SysError> {
+    let mut encoded = vec![Param {
+        name:  c_string("name", "name")?,
+        value: Some(c_string("name", jail)?.into_bytes_with_nul()),
+    }];
+
+    for (name, values) in params {
+        if name != "name" && !is_pseudo(name) {
+            encoded.push(encode(name, values)?);
+        }
+    }
+
+    let errmsg_name = c_string("errmsg", "errmsg")?;
+    let mut errmsg = vec![0u8; STRING_BUFLEN];
+    let mut iov = Vec::with_capacity(encoded.len() * 2 + 2);
+
+    for param in &encoded {
+        iov.push(iovec(param.name.as_bytes_with_nul()));
+        iov.push(param.value.as_deref().map_or_else(empty_iovec, iovec));
+    }
+
+    iov.push(iovec(errmsg_name.as_bytes_with_nul()));
+    iov.push(libc::iovec {
+        iov_base: errmsg.as_mut_ptr() as *mut libc::c_void,
+        iov_len:  errmsg.len(),
+    });
+
+    // The iovecs point into encoded and errmsg, which outlive the call.
+    let jid = unsafe {
+        libc::jail_set(iov.as_mut_ptr(), iov.len() as libc::c_uint, flags)
+    };
+
+    if jid < 0 {
+        return Err(call_error("jail_set", &errmsg));
+    }
+
+    Ok(jid)
+}
+
+/// Create a jail with the given parameters, returning its jail ID.
+pub fn create_jail(jail: &str, params: &EffectiveParams) -> Result<i32, SysError> {
+    set(jail, params, libc::JAIL_CREATE)
+}
+
+/// Change the parameters of a running jail, returning its jail ID.
+pub fn update_jail(jail: &str, params: &EffectiveParams) -> Result<i32, SysError> {
+    set(jail, params, libc::JAIL_UPDATE)
+}
+
+// Decode a string read by jail_get(2).
+fn decode_string(buffer: &[u8]) -> String {
+    let end = buffer.iter().position(|b| *b == 0).unwrap_or(buffer.len());
+
+    String::from_utf8_lossy(&buffer[..end]).into_owned()
+}
+
+// Decode addresses read by jail_get(2), of which len bytes were filled in.
+fn decode_addrs(buffer: &[u8], len: usize, v4: bool) -> Vec<String> {
+    let size = if v4 { 4 } else { 16 };
+
+    buffer[..len.min(buffer.len())]
+        .chunks_exact(size)
+        .map(|chunk| {
+            if v4 {
+                let octets: [u8; 4] = chunk.try_into().expect("chunk of 4 bytes");
+
+                Ipv4Addr::from(octets).to_string()
+            }
+            else {
+                let octets: [u8; 16] = chunk.try_into().expect("chunk of 16 bytes");
+
+                Ipv6Addr::from(octets).to_string()
+            }
+        })
+        .collect()
+}
+
+// Read the jail after lastjid, or None once there are no more.
+fn next_jail(lastjid: i32) -> Result<Option<RunningJail>, SysError> {
+    let names: Vec<CString> = [
+        "lastjid", "jid", "name", "path", "host.hostname", "ip4.addr", "ip6.addr", "errmsg",
+    ]
+        .iter()
+        .map(|name| c_string(name, name))
+        .collect::<Result<_, _>>()?;
+
+    let mut lastjid = lastjid.to_ne_bytes();
+    let mut jid = [0u8; mem::size_of::<libc::c_int>()];
+    let mut name = vec![0u8; STRING_BUFLEN];
+    let mut path = vec![0u8; STRING_BUFLEN];
+    let mut hostname = vec![0u8; STRING_BUFLEN];
+    let mut ip4 = vec![0u8; MAX_ADDRS * 4];
+    let mut ip6 = vec![0u8; MAX_ADDRS * 16];
+    let mut errmsg = vec![0u8; STRING_BUFLEN];
+
+    let buffers: [&mut [u8]; 8] = [
+        &mut lastjid, &mut jid, &mut name, &mut path, &mut hostname, &mut ip4, &mut ip6,
+        &mut errmsg,
+    ];
+
+    let mut iov = Vec::with_capacity(names.len() * 2);
+
+    for (name, buffer) in names.iter().zip(buffers) {
+        iov.push(iovec(name.as_bytes_with_nul()));
+        iov.push(libc::iovec {
+            iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
+            iov_len:  buffer.len(),
+        });
+    }
+
+    // The iovecs point into the buffers above, which outlive the call.
+    let result = unsafe {
+        libc::jail_get(iov.as_mut_ptr(), iov.len() as libc::c_uint, 0)
+    };
+
+    if result < 0 {
+        if io::Error::last_os_error().raw_os_error() == Some(libc::ENOENT) {
+            return Ok(None);
+        }
+
+        return Err(call_error("jail_get", &errmsg));
+    }
+
+    // jail_get sets the lengths of the address arrays to the bytes used.
+    let ip4_len = iov[11].iov_len;
+    let ip6_len = iov[13].iov_len;
+
+    let mut params = BTreeMap::new();
+
+    params.insert("path".to_string(), vec![decode_string(&path)]);
+    params.insert("host.hostname".to_string(), vec![decode_string(&hostname)]);
+
+    for (param, addrs) in [
+        ("ip4.addr", decode_addrs(&ip4, ip4_len, true)),
+        ("ip6.addr", decode_addrs(&ip6, ip6_len, false)),
+    ] {
+        if !addrs.is_empty() {
+            params.insert(param.to_string(), addrs);
+        }
+    }
+
+    Ok(Some(RunningJail::new(
+        libc::c_int::from_ne_bytes(jid),
+        decode_string(&name),
+        params,
+    )))
+}
+
+/// Every jail running on this host, in order of jail ID.
+pub fn running_jails() -> Result<Vec<RunningJail>, SysError> {
+    let mut jails = Vec::new();
+    let mut lastjid = 0;
+
+    while let Some(jail) = next_jail(lastjid)? {
+        lastjid = jail.jid;
+        jails.push(jail);
+    }
+
+    Ok(jails)
+}
+
+impl<'a> JailConfig<'a> {
+    /// Create the named jail from its effective parameters, returning its
+    /// jail ID. Variables should be resolved first, see
+    /// `resolve_variables`. Only the jail itself is created: commands such
+    /// as exec.start and mounts such as mount.devfs are left to jail(8).
+    pub fn create_jail(&self, jail: &str) -> Result<i32, SysError> {
+        let params = self.effective_params(jail)
+            .ok_or_else(|| SysError::UnknownJail { name: jail.to_string() })?;
+
+        create_jail(jail, &params)
+    }
+
+    /// Update the running jail with the named jail's effective parameters,
+    /// returning its jail ID. See `create_jail`.
+    pub fn update_jail(&self, jail: &str) -> Result<i32, SysError> {
+        let params = self.effective_params(jail)
+            .ok_or_else(|| SysError::UnknownJail { name: jail.to_string() })?;
+
+        update_jail(jail, &params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let values = |values: &[&str]| -> Vec<String> {
+            values.iter().map(|value| value.to_string()).collect()
+        };
+
+        let param = encode("allow.mount", &values(&["false"])).unwrap();
+        assert_eq!(param.name.to_str().unwrap(), "allow.nomount");
+        assert_eq!(param.value, None);
+
+        let param = encode("persist", &values(&["true"])).unwrap();
+        assert_eq!(param.name.to_str().unwrap(), "persist");
+
+        let param = encode("children.max", &values(&["3"])).unwrap();
+        assert_eq!(param.value, Some(3i32.to_ne_bytes().to_vec()));
+
+        let param = encode("ip4.addr", &values(&["lo1|127.0.1.1/32, 10.0.0.1", "10.0.0.2"]))
+            .unwrap();
+        assert_eq!(param.value, Some(vec![127, 0, 1, 1, 10, 0, 0, 1, 10, 0, 0, 2]));
+
+        let param = encode("path", &values(&["/jails/a"])).unwrap();
+        assert_eq!(param.value, Some(b"/jails/a\0".to_vec()));
+
+        assert!(encode("ip6.addr", &values(&["127.0.0.1"])).is_err());
+        assert!(encode("enforce_statfs", &values(&["two"])).is_err());
+    }
+
+    #[test]
+    fn test_is_pseudo() {
+        assert!(is_pseudo("exec.start"));
+        assert!(is_pseudo("mount.devfs"));
+        assert!(is_pseudo("mount"));
+        assert!(!is_pseudo("allow.mount"));
+        assert!(!is_pseudo("path"));
+    }
+
+    #[test]
+    fn test_decode_addrs() {
+        let buffer = [127, 0, 0, 1, 10, 0, 0, 1, 0, 0, 0, 0];
+
+        assert_eq!(decode_addrs(&buffer, 8, true), vec!["127.0.0.1", "10.0.0.1"]);
+    }
+}
@@ -0,0 +1,344 @@
+// Rendering of configuration templates.
+//
+// A template is jail.conf with `{{name}}` placeholders, eg. a skeleton jail
+// block filled in for each customer. Replacing placeholders as plain text
+// breaks as soon as a value contains a quote, space or `$`, so
+// render_template looks at where each placeholder appears and writes its
+// value so that it's read back exactly as given:
+//
+//   - Within double quotes, the value is escaped, including any `$` so that
+//     it isn't taken as a variable reference.
+//   - Within single quotes, the value is written as is, unless it contains a
+//     single quote, in which case the quotes are closed around a double
+//     quoted fragment holding it.
+//   - Elsewhere, the value is written bare if it can be and is quoted if not.
+//     Adjacent fragments are concatenated, so this works part way through a
+//     bare value too, eg. `path = /jails/{{name}};`.
+//   - Within comments, the value is written as is, with line breaks
+//     replaced by spaces in line comments and any `*/` broken up in C style
+//     ones. Comments are found as the parser finds them, so the `//` of
+//     `/jails//{{name}}` doesn't start one.
+//
+// Values come from a TemplateContext, which is implemented for maps and for
+// closures looking values up by name. The rendered text is parsed before
+// it's returned, so a template which renders to invalid configuration is an
+// error rather than output jail(8) would refuse.
+use std::borrow::Borrow;
+use std::collections::{
+    BTreeMap,
+    HashMap,
+};
+use std::error;
+use std::fmt;
+use std::hash::Hash;
+use crate::builder::raw_value;
+use crate::escape::{
+    escape_literal,
+    quote_literal,
+};
+use crate::{
+    is_bare_char,
+    parse,
+    ParseError,
+};
+
+/// The values to fill a template's placeholders with.
+pub trait TemplateContext {
+    /// The value for the named placeholder, or None if there isn't one.
+    fn value(&self, name: &str) -> Option<String>;
+}
+
+impl<K, V> TemplateContext for HashMap<K, V>
+where
+    K: Borrow<str> + Eq + Hash,
+    V: AsRef<str>,
+{
+    fn value(&self, name: &str) -> Option<String> {
+        self.get(name).map(|value| value.as_ref().to_string())
+    }
+}
+
+impl<K, V> TemplateContext for BTreeMap<K, V>
+where
+    K: Borrow<str> + Ord,
+    V: AsRef<str>,
+{
+    fn value(&self, name: &str) -> Option<String> {
+        self.get(name).map(|value| value.as_ref().to_string())
+    }
+}
+
+impl<F> TemplateContext for F
+where
+    F: Fn(&str) -> Option<String>,
+{
+    fn value(&self, name: &str) -> Option<String> {
+        self(name)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TemplateError {
+    /// A `{{` without a closing `}}`, or with something other than a name
+    /// between them. The offset is that of the `{{` in the template.
+    InvalidPlaceholder {
+        offset: usize,
+    },
+    /// Placeholders with no value in the context, in the order they first
+    /// appear.
+    Missing {
+        names: Vec<String>,
+    },
+    /// The rendered template isn't valid configuration.
+    Parse(ParseError),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TemplateError::InvalidPlaceholder { offset } => {
+                write!(f, "invalid placeholder at offset {}", offset)
+            },
+            TemplateError::Missing { names } => {
+                write!(f, "no value for placeholders: {}", names.join(", "))
+            },
+            TemplateError::Parse(e) => {
+                write!(f, "rendered template doesn't parse: {}", e)
+            },
+        }
+    }
+}
+
+impl error::Error for TemplateError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            TemplateError::Parse(e) => Some(e),
+            _                       => None,
+        }
+    }
+}
+
+// Where in the template a placeholder appears.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Context {
+    Bare,
+    Double,
+    Single,
+    LineComment,
+    BlockComment,
+}
+
+fn is_placeholder_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_.-".contains(c)
+}
+
+// The name of the placeholder at the start of input, and the length of the
+// whole placeholder.
+fn placeholder(input: &str) -> Option<(&str, usize)> {
+    let close = input.find("}}")?;
+    let name = input[2..close].trim();
+
+    if name.is_empty() || !name.chars().all(is_placeholder_char) {
+        return None;
+    }
+
+    Some((name, close + 2))
+}
+
+// Write value as it must appear in the given context, returning whether it
+// was written as a bare word.
+fn write_value(out: &mut String, value: &str, context: Context) -> bool {
+    match context {
+        Context::Bare if !value.is_empty() && raw_value(value) == value => {
+            out.push_str(value);
+            return true;
+        },
+        Context::Bare => out.push_str(&quote_literal(value)),
+        Context::Double => out.push_str(&escape_literal(value)),
+        Context::Single if value.contains('\'') => {
+            out.push('\'');
+            out.push_str(&quote_literal(value));
+            out.push('\'');
+        },
+        Context::Single => out.push_str(value),
+        Context::LineComment => out.push_str(&value.replace(['\r', '\n'], " ")),
+        Context::BlockComment => out.push_str(&value.replace("*/", "* /")),
+    }
+
+    false
+}
+
+/// Fill in the `{{name}}` placeholders of a template with values from the
+/// context, quoting and escaping each value for where it appears. Every
+/// placeholder without a value is reported at once, and the result is
+/// checked to be valid configuration.
+pub fn render_template<C>(template: &str, context: &C) -> Result<String, TemplateError>
+where
+    C: TemplateContext + ?Sized,
+{
+    let mut out = String::with_capacity(template.len());
+    let mut missing: Vec<String> = Vec::new();
+    let mut state = Context::Bare;
+    let mut chars = template.char_indices();
+    // Whether the output ends part way through a bare word, where `//` and
+    // `/*` don't start a comment.
+    let mut word = false;
+
+    while let Some((i, c)) = chars.next() {
+        let rest = &template[i..];
+
+        if rest.starts_with("{{") {
+            let (name, len) = placeholder(rest)
+                .ok_or(TemplateError::InvalidPlaceholder { offset: i })?;
+
+            word = match context.value(name) {
+                Some(value) => write_value(&mut out, &value, state),
+                None => {
+                    if !missing.iter().any(|m| m == name) {
+                        missing.push(name.to_string());
+                    }
+
+                    false
+                },
+            };
+
+            chars.nth(len - 2);
+            continue;
+        }
+
+        out.push(c);
+
+        let after_word = word;
+        word = false;
+
+        state = match (state, c) {
+            (Context::Bare, '"')  => Context::Double,
+            (Context::Bare, '\'') => Context::Single,
+            (Context::Bare, '#')  => Context::LineComment,
+            (Context::Bare, '/') if rest.starts_with("//") && !after_word => {
+                out.push('/');
+                chars.next();
+                Context::LineComment
+            },
+            (Context::Bare, '/') if rest.starts_with("/*") && !after_word => {
+                out.push('*');
+                chars.next();
+                Context::BlockComment
+            },
+            (Context::Bare, '\\') | (Context::Double, '\\') => {
+                if let Some((_, escaped)) = chars.next() {
+                    out.push(escaped);
+                }
+
+                word = state == Context::Bare;
+                state
+            },
+            (Context::Double, '"')
+            | (Context::Single, '\'')
+            | (Context::LineComment, '\n') => Context::Bare,
+            (Context::BlockComment, '*') if rest.starts_with("*/") => {
+                out.push('/');
+                chars.next();
+                Context::Bare
+            },
+            (Context::Bare, c) => {
+                word = c == '$' || (c != ',' && is_bare_char(c));
+                Context::Bare
+            },
+            (state, _) => state,
+        };
+    }
+
+    if !missing.is_empty() {
+        return Err(TemplateError::Missing { names: missing });
+    }
+
+    parse(&out).map_err(TemplateError::Parse)?;
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::JailConfig;
+    use indoc::indoc;
+
+    const TEMPLATE: &str = indoc!(r#"
+        # Jail for {{customer}}
+        {{name}} {
+            host.hostname = "{{name}}.example.com";
+            path = /jails/{{name}};
+            exec.start = '/bin/sh {{script}}';
+            ip4.addr = {{ addr }};
+        }
+        "#);
+
+    #[test]
+    fn test_render_template() {
+        let context: HashMap<&str, &str> = [
+            ("customer", "Bob's\nShop"),
+            ("name", "bob shop"),
+            ("script", "it's.sh"),
+            ("addr", "192.0.2.1"),
+        ].iter().cloned().collect();
+
+        let rendered = render_template(TEMPLATE, &context).unwrap();
+
+        assert_eq!(rendered, indoc!(r#"
+            # Jail for Bob's Shop
+            "bob shop" {
+                host.hostname = "bob shop.example.com";
+                path = /jails/"bob shop";
+                exec.start = '/bin/sh '"it's.sh"'';
+                ip4.addr = 192.0.2.1;
+            }
+            "#));
+
+        let config = JailConfig::parse(&rendered).unwrap();
+
+        assert_eq!(config.get_param("bob shop", "path").as_deref(), Some("/jails/bob shop"));
+        assert_eq!(config.get_param("bob shop", "exec.start").as_deref(), Some("/bin/sh it's.sh"));
+    }
+
+    #[test]
+    fn test_render_template_escapes_values() {
+        let context = |name: &str| match name {
+            "value" => Some("say \"$HOME\"".to_string()),
+            _       => None,
+        };
+
+        let rendered = render_template("a = \"{{value}}\"; b = {{value}};", &context).unwrap();
+        let config = JailConfig::parse(&rendered).unwrap();
+
+        assert_eq!(config.globals().collect::<Vec<_>>(), vec![
+            ("a", "say \"$HOME\""),
+            ("b", "say \"$HOME\""),
+        ]);
+    }
+
+    #[test]
+    fn test_render_template_errors() {
+        let context: BTreeMap<String, String> = BTreeMap::new();
+
+        assert_eq!(
+            render_template("{{a}} { x = {{b}}; y = {{a}}; }", &context),
+            Err(TemplateError::Missing { names: vec!["a".to_string(), "b".to_string()] }),
+        );
+
+        assert_eq!(
+            render_template("persist;\n{{a", &context),
+            Err(TemplateError::InvalidPlaceholder { offset: 9 }),
+        );
+
+        let context = |_: &str| Some("x".to_string());
+        assert_eq!(
+            render_template("path = /jails//{{name}}; # {{name}}\n", &context),
+            Ok("path = /jails//x; # x\n".to_string()),
+        );
+        assert!(matches!(
+            render_template("{{a}} {", &context),
+            Err(TemplateError::Parse(_)),
+        ));
+    }
+}
@@ -0,0 +1,208 @@
+// Assertions for the tests of crates building on the parser.
+//
+// The crate's own tests and corpus check that rendering a configuration and
+// parsing it again gives back the same configuration, and that rendering or
+// formatting a second time changes nothing. Crates which add their own
+// transforms, or generate configurations to feed to the parser, need the
+// same guarantees of their output, so the checks are public here as
+// assertions to call from their tests, each panicking with the input and
+// output at fault.
+//
+// Each takes any input: those taking text panic if it doesn't parse, so
+// property tests with generated text should only pass on what `parse`
+// accepts, and those taking a JailConfig accept one built any way at all,
+// including from the arbitrary feature's generators.
+use crate::{
+    format_config,
+    into_owned,
+    FormatOptions,
+    JailConfig,
+    LosslessConfig,
+};
+
+// Parse input which the caller expects to parse.
+#[track_caller]
+fn parse_expected(input: &str) -> JailConfig<'_> {
+    match JailConfig::parse(input) {
+        Ok(config) => config,
+        Err(error) => panic!("input doesn't parse: {}\n--- input\n{}", error, input),
+    }
+}
+
+/// Assert that the configuration survives being rendered and parsed again:
+/// the rendered text parses, to a configuration `semantically_eq` to the
+/// original, and rendering that gives the same text.
+#[track_caller]
+pub fn assert_config_round_trip(config: &JailConfig) {
+    let rendered = config.to_string();
+    let reparsed = match JailConfig::parse(&rendered) {
+        Ok(reparsed) => reparsed,
+        Err(error) => {
+            panic!("rendered configuration doesn't parse: {}\n--- rendered\n{}", error, rendered)
+        },
+    };
+
+    assert!(
+        reparsed.semantically_eq(config),
+        "rendered configuration parses to a different configuration\n--- rendered\n{}--- reparsed\n{}",
+        rendered,
+        reparsed,
+    );
+
+    let rerendered = reparsed.to_string();
+
+    assert!(
+        rerendered == rendered,
+        "rendering isn't idempotent\n--- first\n{}--- second\n{}",
+        rendered,
+        rerendered,
+    );
+}
+
+/// Assert that input parses and survives being rendered and parsed again,
+/// see `assert_config_round_trip`.
+#[track_caller]
+pub fn assert_round_trip(input: &str) {
+    assert_config_round_trip(&parse_expected(input));
+}
+
+/// Assert that input parses and that formatting it is idempotent: the
+/// formatted text parses, to a configuration `semantically_eq` to the input,
+/// and formatting that gives the same text.
+#[track_caller]
+pub fn assert_format_idempotent(input: &str, options: &FormatOptions) {
+    let config = parse_expected(input);
+    let formatted = format_config(config.statements(), options);
+    let reparsed = match JailConfig::parse(&formatted) {
+        Ok(reparsed) => reparsed,
+        Err(error) => {
+            panic!("formatted configuration doesn't parse: {}\n--- formatted\n{}", error, formatted)
+        },
+    };
+
+    assert!(
+        reparsed.semantically_eq(&config),
+        "formatting changes the configuration\n--- input\n{}--- formatted\n{}",
+        input,
+        formatted,
+    );
+
+    let reformatted = format_config(reparsed.statements(), options);
+
+    assert!(
+        reformatted == formatted,
+        "formatting isn't idempotent\n--- first\n{}--- second\n{}",
+        formatted,
+        reformatted,
+    );
+}
+
+/// Assert that input parses to a lossless tree which gives it back byte for
+/// byte.
+#[track_caller]
+pub fn assert_lossless(input: &str) {
+    let source = match LosslessConfig::parse(input) {
+        Ok(config) => config.to_source(),
+        Err(error) => panic!("input doesn't parse: {}\n--- input\n{}", error, input),
+    };
+
+    assert!(
+        source == input,
+        "lossless tree doesn't reproduce the input\n--- input\n{}--- source\n{}",
+        input,
+        source,
+    );
+}
+
+/// Assert that two inputs parse to configurations which are
+/// `semantically_eq`.
+#[track_caller]
+pub fn assert_semantically_eq(left: &str, right: &str) {
+    let left_config = parse_expected(left);
+    let right_config = parse_expected(right);
+
+    assert!(
+        left_config.semantically_eq(&right_config),
+        "configurations differ\n--- left\n{}--- right\n{}",
+        left,
+        right,
+    );
+}
+
+/// Assert that a transform of parsed configurations keeps the guarantees of
+/// the parser: its output for input survives a round trip, see
+/// `assert_config_round_trip`, and applying it to its own output changes
+/// nothing further.
+#[track_caller]
+pub fn assert_transform_idempotent<F>(input: &str, transform: F)
+where
+    F: Fn(JailConfig<'static>) -> JailConfig<'static>,
+{
+    let config = JailConfig::new(into_owned(parse_expected(input).into_statements()));
+    let once = transform(config);
+
+    assert_config_round_trip(&once);
+
+    let twice = transform(once.clone());
+
+    assert!(
+        twice.semantically_eq(&once),
+        "transform isn't idempotent\n--- once\n{}--- twice\n{}",
+        once,
+        twice,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        # Defaults.
+        exec.start = "/bin/sh /etc/rc";
+        persist;
+
+        www {
+            host.hostname = "www.example.org";
+            ip4.addr  = 192.0.2.1;
+            ip4.addr += 192.0.2.2;
+        }
+        "#);
+
+    #[test]
+    fn test_assertions() {
+        assert_round_trip(INPUT);
+        assert_format_idempotent(INPUT, &FormatOptions::new());
+        assert_lossless(INPUT);
+        assert_semantically_eq(INPUT, indoc!(r#"
+            www { host.hostname = www.example.org; ip4.addr = "192.0.2.1"; ip4.addr += 192.0.2.2; }
+            persist;
+            exec.start = "/bin/sh /etc/rc";
+            "#));
+        assert_transform_idempotent(INPUT, |config| config.normalize());
+    }
+
+    #[test]
+    #[should_panic(expected = "configurations differ")]
+    fn test_assert_semantically_eq_fails() {
+        assert_semantically_eq("persist;\n", "allow.mount;\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "input doesn't parse")]
+    fn test_assert_round_trip_unparseable() {
+        assert_round_trip("www {\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "transform isn't idempotent")]
+    fn test_assert_transform_idempotent_fails() {
+        assert_transform_idempotent("persist;\n", |config| {
+            let mut statements = config.into_statements();
+            statements.push(crate::builder::param_value("ip4.addr", "192.0.2.1", true));
+
+            JailConfig::new(statements)
+        });
+    }
+}
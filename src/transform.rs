@@ -0,0 +1,385 @@
+// Reordering passes over a configuration.
+//
+// normalize rewrites a configuration into a canonical form, dropping
+// comments and merging blocks along the way. The passes here only move
+// statements around, keeping everything else as it was, for tidying a file
+// a person still edits. Each is a Transform, and transforms compose: a list
+// of them, or two joined with `then`, is itself a transform applying each in
+// turn.
+//
+// Comments move with the statement that follows them, so a comment
+// describing a jail or a parameter stays with it. Nothing is moved in a way
+// jail(8) would read differently: parameters are only reordered within runs
+// of parameters, so none moves past a variable, include or nested block,
+// and parameters with the same name stay in the order they were given.
+use crate::annotation;
+use crate::{
+    JailConf,
+    JailConfig,
+};
+
+/// A pass over a configuration's statements, see `SortJails`, `GroupParams`
+/// and `HoistGlobals`.
+pub trait Transform {
+    /// Transform the statements.
+    fn apply<'a>(&self, statements: Vec<JailConf<'a>>) -> Vec<JailConf<'a>>;
+
+    /// A transform applying this one and then next.
+    fn then<T>(self, next: T) -> Then<Self, T>
+    where
+        Self: Sized,
+        T: Transform,
+    {
+        Then {
+            first:  self,
+            second: next,
+        }
+    }
+}
+
+/// Two transforms applied in turn, see `Transform::then`.
+#[derive(Clone, Debug)]
+pub struct Then<A, B> {
+    first:  A,
+    second: B,
+}
+
+impl<A, B> Transform for Then<A, B>
+where
+    A: Transform,
+    B: Transform,
+{
+    fn apply<'a>(&self, statements: Vec<JailConf<'a>>) -> Vec<JailConf<'a>> {
+        self.second.apply(self.first.apply(statements))
+    }
+}
+
+impl<T> Transform for Box<T>
+where
+    T: Transform + ?Sized,
+{
+    fn apply<'a>(&self, statements: Vec<JailConf<'a>>) -> Vec<JailConf<'a>> {
+        (**self).apply(statements)
+    }
+}
+
+// Each transform in order.
+impl<T> Transform for Vec<T>
+where
+    T: Transform,
+{
+    fn apply<'a>(&self, statements: Vec<JailConf<'a>>) -> Vec<JailConf<'a>> {
+        self.iter().fold(statements, |statements, transform| transform.apply(statements))
+    }
+}
+
+// Split statements into units of a statement and the comments before it. A
+// run of comments at the end is a unit of its own.
+fn units(statements: Vec<JailConf>) -> Vec<Vec<JailConf>> {
+    let mut units = Vec::new();
+    let mut unit = Vec::new();
+
+    for statement in statements {
+        let comment = matches!(statement, JailConf::Comment(_));
+
+        unit.push(statement);
+
+        if !comment {
+            units.push(std::mem::take(&mut unit));
+        }
+    }
+
+    if !unit.is_empty() {
+        units.push(unit);
+    }
+
+    units
+}
+
+// The statement a unit is for, the last in it.
+fn subject<'u, 'a>(unit: &'u [JailConf<'a>]) -> &'u JailConf<'a> {
+    &unit[unit.len() - 1]
+}
+
+// The group a parameter belongs to, the part of its name before the first
+// dot, eg. exec for exec.start. Names without a dot, eg. persist or path,
+// are grouped together.
+pub(crate) fn param_group(name: &str) -> &str {
+    match name.find('.') {
+        Some(dot) => &name[..dot],
+        None      => "",
+    }
+}
+
+// The group of the parameter a statement sets, None if it isn't one.
+pub(crate) fn statement_group<'s>(statement: &'s JailConf) -> Option<&'s str> {
+    match statement {
+        JailConf::ParamBool(param)  => Some(param_group(param.name())),
+        JailConf::ParamValue(param) => Some(param_group(param.name())),
+        _                           => None,
+    }
+}
+
+/// Sort the top level jail blocks by name. Blocks for the same jail keep
+/// their order, and the other statements stay where they were. jail(8)
+/// starts jails without a `depend` in the order they're given, so this
+/// changes that order.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SortJails;
+
+impl Transform for SortJails {
+    fn apply<'a>(&self, statements: Vec<JailConf<'a>>) -> Vec<JailConf<'a>> {
+        let mut units = units(statements);
+        let slots: Vec<usize> = (0..units.len())
+            .filter(|&i| matches!(subject(&units[i]), JailConf::Block(_)))
+            .collect();
+
+        let mut blocks: Vec<Vec<JailConf>> = slots.iter()
+            .map(|&i| std::mem::take(&mut units[i]))
+            .collect();
+
+        blocks.sort_by(|a, b| match (subject(a), subject(b)) {
+            (JailConf::Block(a), JailConf::Block(b)) => a.name().cmp(b.name()),
+            _                                        => unreachable!("only blocks are sorted"),
+        });
+
+        for (slot, block) in slots.into_iter().zip(blocks) {
+            units[slot] = block;
+        }
+
+        annotation::attach(units.into_iter().flatten().collect())
+    }
+}
+
+/// Group the parameters in each run of parameters, at the top level and in
+/// every block, by the part of their name before the first dot, eg. exec.*,
+/// allow.* and mount.*. Parameters without a dot come first, then each
+/// group in the order it first appears. See `FormatOptions::separate_groups`
+/// for setting the groups apart with blank lines.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GroupParams;
+
+// Group the units of a run of parameters.
+fn group_run<'a>(run: Vec<Vec<JailConf<'a>>>, output: &mut Vec<JailConf<'a>>) {
+    let mut groups: Vec<(&str, Vec<usize>)> = Vec::new();
+
+    for (i, unit) in run.iter().enumerate() {
+        let group = statement_group(subject(unit)).unwrap_or_default();
+
+        match groups.iter_mut().find(|(name, _)| *name == group) {
+            Some((_, members)) => members.push(i),
+            None               => groups.push((group, vec![i])),
+        }
+    }
+
+    // Stable, so only the parameters without a dot move.
+    groups.sort_by_key(|(name, _)| !name.is_empty());
+
+    let order: Vec<usize> = groups.into_iter().flat_map(|(_, members)| members).collect();
+    let mut run: Vec<Option<Vec<JailConf>>> = run.into_iter().map(Some).collect();
+
+    for i in order {
+        output.extend(run[i].take().into_iter().flatten());
+    }
+}
+
+fn group_params(statements: Vec<JailConf>) -> Vec<JailConf> {
+    let mut output = Vec::new();
+    let mut run = Vec::new();
+
+    for mut unit in units(statements) {
+        if statement_group(subject(&unit)).is_some() {
+            run.push(unit);
+            continue;
+        }
+
+        group_run(std::mem::take(&mut run), &mut output);
+
+        if let Some(JailConf::Block(block)) = unit.last_mut() {
+            block.params = group_params(std::mem::take(&mut block.params));
+        }
+
+        output.extend(unit);
+    }
+
+    group_run(run, &mut output);
+
+    output
+}
+
+impl Transform for GroupParams {
+    fn apply<'a>(&self, statements: Vec<JailConf<'a>>) -> Vec<JailConf<'a>> {
+        group_params(statements)
+    }
+}
+
+/// Move the top level parameters and variables given after the first jail
+/// block to just before it, so that the defaults every jail takes are read
+/// first. They keep their order among themselves, and includes stay where
+/// they were.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HoistGlobals;
+
+impl Transform for HoistGlobals {
+    fn apply<'a>(&self, statements: Vec<JailConf<'a>>) -> Vec<JailConf<'a>> {
+        let units = units(statements);
+        let first_block = units.iter()
+            .position(|unit| matches!(subject(unit), JailConf::Block(_)))
+            .unwrap_or(units.len());
+
+        let mut units = units.into_iter();
+        let mut output: Vec<JailConf> = units.by_ref().take(first_block).flatten().collect();
+        let mut rest = Vec::new();
+
+        for unit in units {
+            let global = matches!(
+                subject(&unit),
+                JailConf::ParamBool(_) | JailConf::ParamValue(_) | JailConf::Variable(_),
+            );
+
+            if global {
+                output.extend(unit);
+            }
+            else {
+                rest.extend(unit);
+            }
+        }
+
+        output.extend(rest);
+
+        annotation::attach(output)
+    }
+}
+
+impl<'a> JailConfig<'a> {
+    /// Apply a transform to the configuration's statements, see
+    /// `Transform`.
+    pub fn transform<T>(self, transform: &T) -> Self
+    where
+        T: Transform + ?Sized,
+    {
+        Self::new(transform.apply(self.into_statements()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        format_config,
+        FormatOptions,
+    };
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        exec.clean;
+        # The web server.
+        www {
+            mount.devfs;
+            exec.start = "/bin/sh /etc/rc";
+            path = /jails/www;
+            allow.raw_sockets;
+            exec.stop = "/bin/sh /etc/rc.shutdown";
+            $ip = 192.0.2.1;
+            ip4.addr = $ip;
+            host.hostname = www;
+        }
+        persist;
+        db {
+            allow.mount;
+        }
+        $domain = example.org;
+        "#);
+
+    fn format(config: JailConfig) -> String {
+        format_config(config.statements(), &FormatOptions::new().align(false))
+    }
+
+    #[test]
+    fn test_sort_jails() {
+        let config = JailConfig::parse(INPUT).unwrap().transform(&SortJails);
+        let names: Vec<_> = config.jails().map(|block| block.name()).collect();
+
+        assert_eq!(names, ["db", "www"]);
+        assert!(matches!(&config.statements()[1], JailConf::Block(block) if block.name() == "db"));
+        assert!(matches!(&config.statements()[2], JailConf::ParamBool(_)));
+        assert!(matches!(&config.statements()[3], JailConf::Comment(_)));
+    }
+
+    #[test]
+    fn test_group_params() {
+        let config = JailConfig::parse(INPUT).unwrap().transform(&GroupParams);
+        let options = FormatOptions::new().align(false).separate_groups(true);
+
+        assert_eq!(format_config(config.statements(), &options), indoc!(r#"
+            exec.clean;
+            # The web server.
+            www {
+                path = /jails/www;
+
+                mount.devfs;
+
+                exec.start = "/bin/sh /etc/rc";
+                exec.stop = "/bin/sh /etc/rc.shutdown";
+
+                allow.raw_sockets;
+                $ip = 192.0.2.1;
+                ip4.addr = $ip;
+
+                host.hostname = www;
+            }
+
+            persist;
+
+            db {
+                allow.mount;
+            }
+
+            $domain = example.org;
+            "#));
+    }
+
+    #[test]
+    fn test_hoist_globals() {
+        let config = JailConfig::parse(INPUT).unwrap().transform(&HoistGlobals);
+
+        assert_eq!(format(config), indoc!(r#"
+            exec.clean;
+            persist;
+            $domain = example.org;
+            # The web server.
+            www {
+                mount.devfs;
+                exec.start = "/bin/sh /etc/rc";
+                path = /jails/www;
+                allow.raw_sockets;
+                exec.stop = "/bin/sh /etc/rc.shutdown";
+                $ip = 192.0.2.1;
+                ip4.addr = $ip;
+                host.hostname = www;
+            }
+
+            db {
+                allow.mount;
+            }
+            "#));
+    }
+
+    #[test]
+    fn test_transforms_compose() {
+        let chained = HoistGlobals.then(SortJails).then(GroupParams);
+        let listed: Vec<Box<dyn Transform>> = vec![
+            Box::new(HoistGlobals),
+            Box::new(SortJails),
+            Box::new(GroupParams),
+        ];
+
+        let config = JailConfig::parse(INPUT).unwrap();
+        let expected = format(config.clone().transform(&HoistGlobals)
+            .transform(&SortJails)
+            .transform(&GroupParams));
+
+        assert_eq!(format(config.clone().transform(&chained)), expected);
+        assert_eq!(format(config.transform(&listed)), expected);
+    }
+}
@@ -0,0 +1,139 @@
+// A readable dump of parsed statements.
+//
+// The Debug output of the AST repeats each node's source text and spells out
+// every field of its span, so that for a configuration of any size the
+// structure is lost in the noise. dump_tree prints one node per line
+// instead, indented by its nesting, with its kind, the few details that
+// tell it apart and where it was parsed from:
+//
+//   Block www @ 1:1-4:2
+//     ParamValue path = "/jails/www" @ 2:5-2:24
+//     ParamBool persist @ 3:5-3:13
+//
+// Positions are line:column, with the end just after the node's last byte.
+// This is meant for debugging the parser and seeing how input is read, not
+// for machines; `to_string` gives jail.conf and serde gives structured data.
+use std::fmt::Write;
+use crate::{
+    JailConf,
+    JailConfig,
+    Span,
+};
+
+const INDENT: &str = "  ";
+
+// The start and end of span as line:column.
+fn position(span: Span) -> String {
+    let (start, end) = (span.start(), span.end());
+
+    format!("{}:{}-{}:{}", start.line(), start.column(), end.line(), end.column())
+}
+
+// The assignment operator for a value.
+fn operator(append: bool) -> &'static str {
+    if append {
+        "+="
+    }
+    else {
+        "="
+    }
+}
+
+fn dump(out: &mut String, statements: &[JailConf], depth: usize) {
+    for statement in statements {
+        let indent = INDENT.repeat(depth);
+        let span = position(statement.span());
+
+        // Writing to a String can't fail.
+        let _ = match statement {
+            JailConf::Block(block) => {
+                writeln!(out, "{}Block {} @ {}", indent, block.name(), span)
+            },
+            JailConf::Comment(comment) => {
+                writeln!(out, "{}Comment {:?} @ {}", indent, comment.comment(), span)
+            },
+            JailConf::Include(include) => {
+                writeln!(out, "{}Include {:?} @ {}", indent, include.path(), span)
+            },
+            JailConf::ParamBool(param) => {
+                writeln!(out, "{}ParamBool {} @ {}", indent, param.name(), span)
+            },
+            JailConf::ParamValue(param) => {
+                writeln!(
+                    out,
+                    "{}ParamValue {} {} {:?} @ {}",
+                    indent,
+                    param.name(),
+                    operator(param.is_append()),
+                    param.value(),
+                    span,
+                )
+            },
+            JailConf::Variable(var) => {
+                writeln!(
+                    out,
+                    "{}Variable ${} {} {:?} @ {}",
+                    indent,
+                    var.name(),
+                    operator(var.is_append()),
+                    var.value(),
+                    span,
+                )
+            },
+        };
+
+        if let JailConf::Block(block) = statement {
+            dump(out, block.params(), depth + 1);
+        }
+    }
+}
+
+/// Describe the statements one node per line, indented by their nesting,
+/// with each node's kind, name or value, and span.
+pub fn dump_tree(statements: &[JailConf]) -> String {
+    let mut out = String::new();
+
+    dump(&mut out, statements, 0);
+
+    out
+}
+
+impl<'a> JailConfig<'a> {
+    /// Describe the configuration one node per line, see `dump_tree`.
+    pub fn dump_tree(&self) -> String {
+        dump_tree(self.statements())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use crate::parse;
+
+    #[test]
+    fn test_dump_tree() {
+        let input = indoc!(r#"
+            # Jails
+            $base = "/jails";
+            www {
+                path = "$base/www";
+                ip4.addr += 192.0.2.1;
+                persist;
+            }
+            .include "/etc/jail.conf.d/*.conf";
+            "#);
+
+        let config = parse(input).unwrap();
+
+        assert_eq!(dump_tree(&config), indoc!(r#"
+            Comment " Jails" @ 1:1-1:8
+            Variable $base = "/jails" @ 2:1-2:18
+            Block www @ 3:1-7:2
+              ParamValue path = "$base/www" @ 4:5-4:24
+              ParamValue ip4.addr += "192.0.2.1" @ 5:5-5:27
+              ParamBool persist @ 6:5-6:13
+            Include "/etc/jail.conf.d/*.conf" @ 8:1-8:36
+            "#));
+    }
+}
@@ -0,0 +1,708 @@
+// Conversion to and from UCL.
+//
+// Some jail managers and much of FreeBSD's newer tooling keep configuration
+// in libucl's format rather than jail.conf. The two are close enough to
+// translate between:
+//
+//   # Jails are started at boot.
+//   exec.clean = true;
+//   .include "/etc/jail.conf.d/*.conf";
+//   www {
+//       path = "/jails/www";
+//       ip4.addr = ["192.0.2.10", "192.0.2.11"];
+//       "exec.start+" = "/usr/local/bin/app";
+//   }
+//
+// Blocks become objects, nested as deeply as they were. A parameter set
+// without a value is `true`. The values a parameter is given with `=` and
+// then `+=` become a single array, while values added with `+=` to a
+// parameter not assigned in the same block are kept under the key with `+`
+// on the end, so that they still add to the value inherited from outside
+// it. Variables are keys starting with `$`. Comments are written as UCL
+// comments and `.include` directives as UCL's macro of the same name.
+//
+// from_ucl reads the same shape back, along with anything else in it UCL
+// allows for: `:` instead of `=`, JSON syntax, `yes`, `no`, `on` and `off`
+// for booleans and so on. Heredocs and macros other than `.include` have no
+// jail.conf equivalent and are refused, as are arrays of objects.
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+use crate::builder::{
+    block,
+    param_bool,
+    param_value,
+    variable,
+};
+use crate::{
+    CommentStyle,
+    JailBlock,
+    JailComment,
+    JailConf,
+    JailConfig,
+    JailInclude,
+    Span,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UclError {
+    /// Something other than a key, value or comment was found.
+    Syntax {
+        line: usize,
+        text: String,
+    },
+    /// A quoted string or comment isn't closed.
+    UnterminatedQuote {
+        line: usize,
+    },
+    /// The input uses UCL which has no jail.conf equivalent.
+    Unsupported {
+        line: usize,
+        text: String,
+    },
+}
+
+impl fmt::Display for UclError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UclError::Syntax { line, text } => {
+                write!(f, "line {}: unexpected {}", line, text)
+            },
+            UclError::UnterminatedQuote { line } => {
+                write!(f, "line {}: unterminated quote", line)
+            },
+            UclError::Unsupported { line, text } => {
+                write!(f, "line {}: no jail.conf equivalent for {}", line, text)
+            },
+        }
+    }
+}
+
+impl error::Error for UclError {}
+
+// Write a key, quoting it unless it's a plain word.
+fn write_key(out: &mut String, key: &str) {
+    let bare = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c));
+
+    if bare {
+        out.push_str(key);
+    }
+    else {
+        write_string(out, key);
+    }
+}
+
+fn write_string(out: &mut String, value: &str) {
+    out.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"'  => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c    => out.push(c),
+        }
+    }
+
+    out.push('"');
+}
+
+// A key within a block, and everything it's set to there.
+enum Entry<'c, 'a> {
+    Comment(&'c JailComment<'a>),
+    Include(&'c str),
+    Block(&'c JailBlock<'a>),
+    Flag(String),
+    Values {
+        key:    String,
+        values: Vec<&'c str>,
+    },
+}
+
+impl<'c, 'a> Entry<'c, 'a> {
+    fn key(&self) -> Option<&str> {
+        match self {
+            Entry::Flag(key) | Entry::Values { key, .. } => Some(key),
+            _                                            => None,
+        }
+    }
+}
+
+// Gather each key's values, in the order the keys first appear. An
+// assignment replaces whatever the key was set to before it.
+fn entries<'c, 'a>(statements: &'c [JailConf<'a>]) -> Vec<Entry<'c, 'a>> {
+    let mut entries: Vec<Entry> = Vec::new();
+
+    for statement in statements {
+        let (name, value, append) = match statement {
+            JailConf::Comment(comment) => {
+                entries.push(Entry::Comment(comment));
+                continue;
+            },
+            JailConf::Include(include) => {
+                entries.push(Entry::Include(include.path()));
+                continue;
+            },
+            JailConf::Block(block) => {
+                entries.push(Entry::Block(block));
+                continue;
+            },
+            JailConf::ParamBool(param) => (param.name().to_string(), None, false),
+            JailConf::ParamValue(param) => {
+                (param.name().to_string(), Some(param.value()), param.is_append())
+            },
+            JailConf::Variable(var) => {
+                (format!("${}", var.name()), Some(var.value()), var.is_append())
+            },
+        };
+
+        let appended = format!("{}+", name);
+        let existing = entries.iter()
+            .position(|entry| entry.key() == Some(&name) || entry.key() == Some(&appended));
+
+        match (existing, value) {
+            (Some(index), Some(value)) if append => match &mut entries[index] {
+                Entry::Values { values, .. } => values.push(value),
+                entry => {
+                    *entry = Entry::Values {
+                        key:    name,
+                        values: vec![value],
+                    };
+                },
+            },
+            (Some(index), Some(value)) => {
+                entries[index] = Entry::Values {
+                    key:    name,
+                    values: vec![value],
+                };
+            },
+            (Some(index), None) => entries[index] = Entry::Flag(name),
+            (None, Some(value)) => entries.push(Entry::Values {
+                key:    if append { appended } else { name },
+                values: vec![value],
+            }),
+            (None, None) => entries.push(Entry::Flag(name)),
+        }
+    }
+
+    entries
+}
+
+fn write_statements(out: &mut String, statements: &[JailConf], depth: usize) {
+    let indent = "    ".repeat(depth);
+
+    for entry in entries(statements) {
+        out.push_str(&indent);
+
+        match entry {
+            Entry::Comment(comment) => match comment.style() {
+                CommentStyle::C => out.push_str(&format!("/*{}*/", comment.comment())),
+                _               => out.push_str(&format!("#{}", comment.comment())),
+            },
+            Entry::Include(path) => {
+                out.push_str(".include ");
+                write_string(out, path);
+                out.push(';');
+            },
+            Entry::Block(block) => {
+                write_key(out, block.name());
+                out.push_str(" {\n");
+                write_statements(out, block.params(), depth + 1);
+                out.push_str(&indent);
+                out.push('}');
+            },
+            Entry::Flag(key) => {
+                write_key(out, &key);
+                out.push_str(" = true;");
+            },
+            Entry::Values { key, values } => {
+                write_key(out, &key);
+                out.push_str(" = ");
+
+                if let [value] = values.as_slice() {
+                    write_string(out, value);
+                }
+                else {
+                    out.push('[');
+
+                    for (i, value) in values.iter().enumerate() {
+                        if i > 0 {
+                            out.push_str(", ");
+                        }
+
+                        write_string(out, value);
+                    }
+
+                    out.push(']');
+                }
+
+                out.push(';');
+            },
+        }
+
+        out.push('\n');
+    }
+}
+
+// A value read from UCL.
+enum Value {
+    Bool(bool),
+    String(String),
+    List(Vec<String>),
+    Object(Vec<JailConf<'static>>),
+}
+
+struct Parser<'i> {
+    input: &'i str,
+    pos:   usize,
+}
+
+fn is_atom_end(c: char) -> bool {
+    c.is_whitespace() || ";,{}[]#\"'".contains(c)
+}
+
+impl<'i> Parser<'i> {
+    fn rest(&self) -> &'i str {
+        &self.input[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn line(&self) -> usize {
+        self.input[..self.pos].matches('\n').count() + 1
+    }
+
+    fn syntax(&self) -> UclError {
+        let text = match self.peek() {
+            Some(c) => format!("{:?}", c),
+            None    => "end of input".to_string(),
+        };
+
+        UclError::Syntax {
+            line: self.line(),
+            text,
+        }
+    }
+
+    fn unsupported(&self, text: &str) -> UclError {
+        UclError::Unsupported {
+            line: self.line(),
+            text: text.to_string(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    // Skip spaces and tabs, stopping at the end of the line.
+    fn skip_blanks(&mut self) {
+        let rest = self.rest();
+
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t']).len();
+    }
+
+    fn comment(&mut self) -> Result<Option<JailComment<'static>>, UclError> {
+        let rest = self.rest();
+
+        let (text, style, len) = if let Some(text) = rest.strip_prefix('#') {
+            let text = text.split('\n').next().unwrap_or_default();
+            let text = text.strip_suffix('\r').unwrap_or(text);
+
+            (text, CommentStyle::Shell, text.len() + 1)
+        }
+        else if let Some(text) = rest.strip_prefix("/*") {
+            let end = text.find("*/")
+                .ok_or(UclError::UnterminatedQuote { line: self.line() })?;
+
+            (&text[..end], CommentStyle::C, end + 4)
+        }
+        else {
+            return Ok(None);
+        };
+
+        self.pos += len;
+
+        let mut comment = JailComment {
+            comment: Cow::Owned(text.to_string()),
+            style,
+            source:  Cow::Borrowed(""),
+            span:    Span::default(),
+        };
+
+        comment.source = Cow::Owned(comment.to_string());
+        Ok(Some(comment))
+    }
+
+    fn string(&mut self) -> Result<String, UclError> {
+        let line = self.line();
+        let quote = self.peek().unwrap_or('"');
+        let mut chars = self.rest().char_indices().skip(1);
+        let mut value = String::new();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                c if c == quote => {
+                    self.pos += i + 1;
+                    return Ok(value);
+                },
+                '\\' if quote == '\'' => match chars.next() {
+                    Some((_, '\'')) => value.push('\''),
+                    Some((_, c))    => {
+                        value.push('\\');
+                        value.push(c);
+                    },
+                    None => break,
+                },
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some('t') => value.push('\t'),
+                    Some('b') => value.push('\u{8}'),
+                    Some('f') => value.push('\u{c}'),
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                        let c = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+
+                        value.push(c.unwrap_or(char::REPLACEMENT_CHARACTER));
+                    },
+                    Some(c) => value.push(c),
+                    None    => break,
+                },
+                c => value.push(c),
+            }
+        }
+
+        Err(UclError::UnterminatedQuote { line })
+    }
+
+    fn atom(&mut self) -> Result<&'i str, UclError> {
+        let rest = self.rest();
+        let len = rest.find(is_atom_end).unwrap_or(rest.len());
+
+        if len == 0 {
+            return Err(self.syntax());
+        }
+
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn key(&mut self) -> Result<(String, bool), UclError> {
+        match self.peek() {
+            Some('"') | Some('\'') => Ok((self.string()?, true)),
+            _ => {
+                let rest = self.rest();
+                let len = rest.find(|c| is_atom_end(c) || c == '=' || c == ':')
+                    .unwrap_or(rest.len());
+
+                if len == 0 {
+                    return Err(self.syntax());
+                }
+
+                self.pos += len;
+                Ok((rest[..len].to_string(), false))
+            },
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, UclError> {
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                Ok(Value::Object(self.statements(true)?))
+            },
+            Some('[') => {
+                self.pos += 1;
+                self.list()
+            },
+            Some('"') | Some('\'') => Ok(Value::String(self.string()?)),
+            _ if self.rest().starts_with("<<") => Err(self.unsupported("heredoc")),
+            _ => Ok(match self.atom()? {
+                "true" | "yes" | "on"  => Value::Bool(true),
+                "false" | "no" | "off" => Value::Bool(false),
+                atom                   => Value::String(atom.to_string()),
+            }),
+        }
+    }
+
+    fn list(&mut self) -> Result<Value, UclError> {
+        let mut values = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+
+            if self.comment()?.is_some() {
+                continue;
+            }
+
+            match self.peek() {
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Value::List(values));
+                },
+                Some(',') => self.pos += 1,
+                None      => return Err(self.syntax()),
+                _         => match self.value()? {
+                    Value::Bool(value)   => values.push(value.to_string()),
+                    Value::String(value) => values.push(value),
+                    _                    => return Err(self.unsupported("nested array or object")),
+                },
+            }
+        }
+    }
+
+    // The statements an entry's key and value stand for.
+    fn entry(&mut self, statements: &mut Vec<JailConf<'static>>) -> Result<(), UclError> {
+        let (key, quoted) = self.key()?;
+
+        if key == ".include" && !quoted {
+            self.skip_blanks();
+
+            let path = match self.value()? {
+                Value::String(path) => path,
+                _                   => return Err(self.unsupported(".include without a path")),
+            };
+
+            let mut include = JailInclude {
+                path:   Cow::Owned(path),
+                source: Cow::Borrowed(""),
+                span:   Span::default(),
+            };
+
+            include.source = Cow::Owned(include.to_string());
+            statements.push(JailConf::Include(include));
+            return Ok(());
+        }
+
+        if key.starts_with('.') && !quoted {
+            return Err(self.unsupported(&format!("macro {}", key)));
+        }
+
+        self.skip_blanks();
+
+        if let Some('=') | Some(':') = self.peek() {
+            self.pos += 1;
+            self.skip_whitespace();
+        }
+
+        let values = match self.value()? {
+            Value::Object(params) => {
+                statements.push(JailConf::Block(block(key, params)));
+                return Ok(());
+            },
+            Value::Bool(true) if !key.starts_with('$') && !key.ends_with('+') => {
+                statements.push(param_bool(&key));
+                return Ok(());
+            },
+            Value::Bool(value)   => vec![value.to_string()],
+            Value::String(value) => vec![value],
+            Value::List(values)  => values,
+        };
+
+        let (name, append) = match key.strip_suffix('+') {
+            Some(name) => (name, true),
+            None       => (key.as_str(), false),
+        };
+
+        for (i, value) in values.iter().enumerate() {
+            let append = append || i > 0;
+
+            statements.push(match name.strip_prefix('$') {
+                Some(name) => variable(name, value, append),
+                None       => param_value(name, value, append),
+            });
+        }
+
+        Ok(())
+    }
+
+    // Read statements up to the end of the input, or the closing brace of
+    // the object being read.
+    fn statements(&mut self, object: bool) -> Result<Vec<JailConf<'static>>, UclError> {
+        let mut statements = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+
+            if let Some(comment) = self.comment()? {
+                statements.push(JailConf::Comment(comment));
+                continue;
+            }
+
+            match self.peek() {
+                Some('}') if object => {
+                    self.pos += 1;
+                    return Ok(statements);
+                },
+                None if !object => return Ok(statements),
+                None | Some('}') => return Err(self.syntax()),
+                Some(';') | Some(',') => self.pos += 1,
+                _ => {
+                    self.entry(&mut statements)?;
+                    self.skip_blanks();
+
+                    if let Some(';') | Some(',') = self.peek() {
+                        self.pos += 1;
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl JailConfig<'static> {
+    /// Read configuration from UCL, as written by `to_ucl`. Objects become
+    /// jail blocks, arrays a parameter assigned its first value and appended
+    /// the rest, and `true` a parameter without a value.
+    pub fn from_ucl(input: &str) -> Result<Self, UclError> {
+        let mut parser = Parser {
+            input,
+            pos: 0,
+        };
+
+        parser.skip_whitespace();
+
+        // The top level object may be given braces, as in JSON.
+        let statements = if parser.peek() == Some('{') {
+            parser.pos += 1;
+
+            let statements = parser.statements(true)?;
+            parser.skip_whitespace();
+
+            if parser.peek().is_some() {
+                return Err(parser.syntax());
+            }
+
+            statements
+        }
+        else {
+            parser.statements(false)?
+        };
+
+        Ok(JailConfig::new(statements))
+    }
+}
+
+impl<'a> JailConfig<'a> {
+    /// Write the configuration as UCL, with a nested object for each block
+    /// and the values given to a parameter with `+=` gathered into an
+    /// array. See `from_ucl` for the way back.
+    pub fn to_ucl(&self) -> String {
+        let mut out = String::new();
+
+        write_statements(&mut out, self.statements(), 0);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        # Jails are started at boot.
+        exec.clean;
+        $root = "/jails";
+        .include "/etc/jail.conf.d/*.conf";
+        ip4.addr = 192.0.2.1;
+        www {
+            path = "$root/www";
+            ip4.addr += 192.0.2.10;
+            exec.start = "/bin/sh /etc/rc";
+            /* Then the application. */
+            exec.start += "/usr/local/bin/app \"$name\"";
+            "*" {
+                persist;
+            }
+        }
+        "#);
+
+    #[test]
+    fn test_to_ucl() {
+        let config = JailConfig::parse(INPUT).unwrap();
+
+        assert_eq!(config.to_ucl(), indoc!(r#"
+            # Jails are started at boot.
+            exec.clean = true;
+            "$root" = "/jails";
+            .include "/etc/jail.conf.d/*.conf";
+            ip4.addr = "192.0.2.1";
+            www {
+                path = "$root/www";
+                "ip4.addr+" = "192.0.2.10";
+                exec.start = ["/bin/sh /etc/rc", "/usr/local/bin/app \"$name\""];
+                /* Then the application. */
+                "*" {
+                    persist = true;
+                }
+            }
+            "#));
+    }
+
+    #[test]
+    fn test_ucl_round_trip() {
+        let config = JailConfig::parse(INPUT).unwrap();
+        let converted = JailConfig::from_ucl(&config.to_ucl()).unwrap();
+
+        assert_eq!(converted.to_ucl(), config.to_ucl());
+        assert_eq!(converted.get_values("www", "ip4.addr"), vec!["192.0.2.1", "192.0.2.10"]);
+        assert_eq!(converted.get_values("www", "exec.start"), config.get_values("www", "exec.start"));
+        assert_eq!(converted.get_param("www", "path").as_deref(), Some("$root/www"));
+    }
+
+    #[test]
+    fn test_from_ucl_json() {
+        let config = JailConfig::from_ucl(indoc!(r#"
+            {
+                "www": {
+                    "path": "/jails/www",
+                    "devfs_ruleset": 4,
+                    "allow.mount": off,
+                    "mount.devfs": yes,
+                    "ip4.addr": ["192.0.2.10", "192.0.2.11"]
+                }
+            }
+            "#)).unwrap();
+
+        assert_eq!(config.to_string(), indoc!(r#"
+            www {
+                path = /jails/www;
+                devfs_ruleset = 4;
+                allow.mount = false;
+                mount.devfs;
+                ip4.addr = 192.0.2.10;
+                ip4.addr += 192.0.2.11;
+            }
+            "#));
+    }
+
+    #[test]
+    fn test_from_ucl_errors() {
+        assert_eq!(
+            JailConfig::from_ucl("www {\n  path = \"/jails;\n}\n").unwrap_err(),
+            UclError::UnterminatedQuote { line: 2 },
+        );
+        assert_eq!(
+            JailConfig::from_ucl("www {\n  path = /jails/www;\n").unwrap_err(),
+            UclError::Syntax { line: 3, text: "end of input".to_string() },
+        );
+        assert_eq!(
+            JailConfig::from_ucl("motd = <<EOD\nhi\nEOD\n").unwrap_err(),
+            UclError::Unsupported { line: 1, text: "heredoc".to_string() },
+        );
+        assert_eq!(
+            JailConfig::from_ucl(".priority 5;").unwrap_err(),
+            UclError::Unsupported { line: 1, text: "macro .priority".to_string() },
+        );
+    }
+}
@@ -0,0 +1,305 @@
+// Parsing of input which might not be UTF-8.
+//
+// jail.conf files in the wild sometimes have comments written in latin-1 or
+// some other legacy encoding. Converting such input with String::from_utf8
+// before parsing it gives an error without a line or column, and converting
+// it with from_utf8_lossy gives errors at offsets into the converted text
+// rather than the file. parse_bytes takes the bytes as read, and by its
+// Utf8Policy either refuses invalid UTF-8 with a ParseError at the first
+// invalid byte, or replaces each invalid sequence with U+FFFD and parses the
+// rest. Errors from parsing lossily converted input are moved back to their
+// position in the original bytes. Spans of the statements parsed are within
+// the converted text.
+//
+// Some values legitimately hold bytes which aren't UTF-8, eg. an `exec.*`
+// command written out by another tool. parse_bytes_preserving parses such
+// input lossily too, but keeps the bytes as read in a ByteConfig, which
+// gives them back exactly, whole or for each statement and value. Each
+// U+FFFD standing in for an invalid sequence is known by its offset, so one
+// which was in the input as a character is told apart from one which
+// wasn't.
+use std::borrow::Cow;
+use std::str;
+use crate::error::ParseErrorKind;
+use crate::{
+    into_owned,
+    parse,
+    JailConf,
+    JailConfOwned,
+    JailConfig,
+    ParseError,
+};
+
+/// How `parse_bytes` treats input which isn't valid UTF-8.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Utf8Policy {
+    /// Refuse the input with a `ParseErrorKind::InvalidUtf8` error at the
+    /// first invalid byte.
+    Strict,
+    /// Replace each invalid sequence with U+FFFD and parse the rest.
+    Lossy,
+}
+
+// The replacements made converting input lossily, each as its offset in the
+// text and in input, and the length of the sequence it replaced.
+type Replacements = Vec<(usize, usize, usize)>;
+
+// Replace invalid sequences in input with U+FFFD, returning the text along
+// with the replacements made.
+fn lossy(input: &[u8]) -> (String, Replacements) {
+    let mut text = String::with_capacity(input.len());
+    let mut replaced = Vec::new();
+    let mut rest = input;
+
+    loop {
+        match str::from_utf8(rest) {
+            Ok(valid) => {
+                text.push_str(valid);
+                break;
+            },
+            Err(e) => {
+                let (valid, invalid) = rest.split_at(e.valid_up_to());
+                let len = e.error_len().unwrap_or(invalid.len());
+
+                // The bytes before the error are valid.
+                text.push_str(str::from_utf8(valid).unwrap_or_default());
+
+                let offset = input.len() - invalid.len();
+                replaced.push((text.len(), offset, len));
+
+                text.push(char::REPLACEMENT_CHARACTER);
+                rest = &invalid[len..];
+            },
+        }
+    }
+
+    (text, replaced)
+}
+
+// The offset in the original input of an offset into the lossily converted
+// text.
+fn original_offset(offset: usize, replaced: &[(usize, usize, usize)]) -> usize {
+    let replacement = char::REPLACEMENT_CHARACTER.len_utf8();
+
+    match replaced.iter().rev().find(|(at, _, _)| *at <= offset) {
+        Some((at, original, _)) if offset < at + replacement => *original,
+        Some((at, original, len)) => original + len + (offset - at - replacement),
+        None                      => offset,
+    }
+}
+
+// Parse input lossily, returning the statements along with the
+// replacements made, as lossy gives them.
+fn parse_lossy(input: &[u8])
+-> Result<(Vec<JailConfOwned>, Replacements), ParseError> {
+    let (text, replaced) = lossy(input);
+
+    match parse(&text) {
+        Ok(statements) => Ok((into_owned(statements), replaced)),
+        Err(e) => {
+            let offset = original_offset(e.offset(), &replaced);
+
+            Err(ParseError::new(input, offset, e.expected()).with_kind(e.kind()))
+        },
+    }
+}
+
+/// Parse input given as bytes, treating invalid UTF-8 as policy says.
+/// Errors give their position in input.
+pub fn parse_bytes(input: &[u8], policy: Utf8Policy)
+-> Result<Vec<JailConfOwned>, ParseError> {
+    match (str::from_utf8(input), policy) {
+        (Ok(text), _) => parse(text).map(into_owned),
+        (Err(e), Utf8Policy::Strict) => {
+            Err(ParseError::new(input, e.valid_up_to(), "valid UTF-8")
+                .with_kind(ParseErrorKind::InvalidUtf8))
+        },
+        (Err(_), Utf8Policy::Lossy) => parse_lossy(input).map(|(statements, _)| statements),
+    }
+}
+
+/// A configuration parsed from bytes which may not be UTF-8, along with the
+/// bytes as read, see `parse_bytes_preserving`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ByteConfig {
+    input:    Vec<u8>,
+    replaced: Replacements,
+    config:   JailConfig<'static>,
+}
+
+impl ByteConfig {
+    /// The configuration, with each invalid sequence replaced by U+FFFD.
+    /// Spans are within this converted text.
+    pub fn config(&self) -> &JailConfig<'static> {
+        &self.config
+    }
+
+    /// The input exactly as it was read.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.input
+    }
+
+    /// Whether the input was valid UTF-8, in which case the configuration
+    /// is exactly as read too.
+    pub fn is_utf8(&self) -> bool {
+        self.replaced.is_empty()
+    }
+
+    // The range of input a range of the converted text came from.
+    fn original_range(&self, start: usize, end: usize) -> (usize, usize) {
+        (original_offset(start, &self.replaced), original_offset(end, &self.replaced))
+    }
+
+    /// The bytes a statement of `config()` was parsed from.
+    pub fn source_bytes(&self, statement: &JailConf) -> &[u8] {
+        let range = statement.span().range();
+        let (start, end) = self.original_range(range.start, range.end);
+
+        &self.input[start..end]
+    }
+
+    /// The value of a parameter or variable of `config()` as bytes, with
+    /// quotes and escapes removed as for its `value()`, and each invalid
+    /// sequence the bytes it was in the input. Borrowed from the node when
+    /// the value was valid UTF-8. None for other statements.
+    pub fn value_bytes<'c>(&self, statement: &'c JailConf) -> Option<Cow<'c, [u8]>> {
+        let (value, raw) = match statement {
+            JailConf::ParamValue(param) => (param.value(), param.raw()),
+            JailConf::Variable(var)     => (var.value(), var.raw()),
+            _                           => return None,
+        };
+
+        let range = statement.span().range();
+
+        if !self.replaced.iter().any(|(at, _, _)| range.contains(at)) {
+            return Some(Cow::Borrowed(value.as_bytes()));
+        }
+
+        // Each U+FFFD of the raw value, in order, as the bytes it replaced,
+        // or None for one which was in the input. Escapes neither make nor
+        // remove them, so they're the same ones as in the value.
+        let raw_start = range.start + statement.source_text().rfind(raw).unwrap_or(0);
+        let mut replacements = raw.char_indices()
+            .filter(|(_, c)| *c == char::REPLACEMENT_CHARACTER)
+            .map(|(i, _)| {
+                self.replaced.iter()
+                    .find(|(at, _, _)| *at == raw_start + i)
+                    .map(|(_, original, len)| &self.input[*original..original + len])
+            });
+
+        let mut bytes = Vec::with_capacity(value.len());
+        let mut buf = [0; 4];
+
+        for c in value.chars() {
+            let original = match c {
+                char::REPLACEMENT_CHARACTER => replacements.next().flatten(),
+                _                           => None,
+            };
+
+            match original {
+                Some(original) => bytes.extend_from_slice(original),
+                None           => bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes()),
+            }
+        }
+
+        Some(Cow::Owned(bytes))
+    }
+}
+
+/// Parse input given as bytes, replacing invalid UTF-8 as
+/// `Utf8Policy::Lossy` does, but keeping the bytes as read so that they can
+/// be given back exactly, see `ByteConfig`.
+pub fn parse_bytes_preserving(input: &[u8]) -> Result<ByteConfig, ParseError> {
+    let (statements, replaced) = parse_lossy(input)?;
+
+    Ok(ByteConfig {
+        input: input.to_vec(),
+        replaced,
+        config: JailConfig::new(statements),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::JailConf;
+
+    #[test]
+    fn test_parse_bytes_strict() {
+        let input = b"# caf\xe9\nwww {\n    persist;\n}\n";
+        let error = parse_bytes(input, Utf8Policy::Strict).unwrap_err();
+
+        assert_eq!(error.kind(), ParseErrorKind::InvalidUtf8);
+        assert_eq!(error.offset(), 5);
+        assert_eq!(error.line(), 1);
+        assert_eq!(error.column(), 6);
+        assert_eq!(error.to_string(), "error at line 1, column 6: expected valid UTF-8");
+
+        let config = parse_bytes(b"persist;\n", Utf8Policy::Strict).unwrap();
+        assert_eq!(config, parse("persist;\n").unwrap());
+    }
+
+    #[test]
+    fn test_parse_bytes_lossy() {
+        let input = b"# caf\xe9\nwww {\n    persist;\n}\n";
+        let config = parse_bytes(input, Utf8Policy::Lossy).unwrap();
+
+        assert_eq!(config.len(), 2);
+        assert!(matches!(&config[0], JailConf::Comment(c) if c.to_string() == "# caf\u{fffd}"));
+
+        // The error is at its offset in the input, though each replacement
+        // is longer than the byte it replaced.
+        let input = b"# \xe9\xe9 x\nwww {\n    path = /a\n}\n";
+        let error = parse_bytes(input, Utf8Policy::Lossy).unwrap_err();
+
+        assert_eq!(error.kind(), ParseErrorKind::Syntax);
+        assert_eq!(error.offset(), 17);
+        assert_eq!(error.line(), 3);
+        assert_eq!(error.column(), 5);
+    }
+
+    #[test]
+    fn test_parse_bytes_preserving() {
+        let input = b"# caf\xe9\nwww {\n    exec.start = \"printf '\xff\xfe' \xef\xbf\xbd\";\n    $v = \"a\\tb\xff\";\n}\n";
+        let config = parse_bytes_preserving(input).unwrap();
+
+        assert!(!config.is_utf8());
+        assert_eq!(config.as_bytes(), &input[..]);
+
+        let www = match &config.config().statements()[1] {
+            JailConf::Block(block) => block,
+            _                      => panic!("expected a block"),
+        };
+
+        let start = &www.params()[0];
+        assert_eq!(config.source_bytes(start), &b"exec.start = \"printf '\xff\xfe' \xef\xbf\xbd\";"[..]);
+
+        // The U+FFFD which was in the input stays one.
+        assert_eq!(
+            config.value_bytes(start).unwrap().as_ref(),
+            &b"printf '\xff\xfe' \xef\xbf\xbd"[..],
+        );
+        assert_eq!(config.value_bytes(&www.params()[1]).unwrap().as_ref(), &b"a\tb\xff"[..]);
+        assert_eq!(config.source_bytes(&config.config().statements()[0]), &b"# caf\xe9"[..]);
+
+        let config = parse_bytes_preserving(b"path = /a;\n").unwrap();
+        let path = &config.config().statements()[0];
+
+        assert!(config.is_utf8());
+        assert!(matches!(config.value_bytes(path), Some(Cow::Borrowed(b"/a"))));
+        assert!(parse_bytes_preserving(b"path = \xff\n").is_err());
+    }
+
+    #[test]
+    fn test_original_offset() {
+        let (text, replaced) = lossy(b"a\xffb\xe9\xe9c");
+
+        assert_eq!(text, "a\u{fffd}b\u{fffd}\u{fffd}c");
+        assert_eq!(original_offset(0, &replaced), 0);
+        assert_eq!(original_offset(1, &replaced), 1);
+        assert_eq!(original_offset(2, &replaced), 1);
+        assert_eq!(original_offset(4, &replaced), 2);
+        assert_eq!(original_offset(5, &replaced), 3);
+        assert_eq!(original_offset(11, &replaced), 5);
+    }
+}
@@ -0,0 +1,462 @@
+// Validation of parameters against the schema.
+//
+// Every parameter set in a configuration is looked up in the schema for a
+// release, and its value checked against the type the schema gives. Each
+// problem found is returned as a ValidationError pointing at the statement
+// at fault, so that all of them can be reported at once. Names are checked
+// first: the "no" form of a boolean is validated as the boolean, while
+// malformed names and old spellings jail(8) refuses are errors of their own.
+//
+// validate_for looks at a configuration from the point of view of the
+// release it's going to be deployed to instead, warning about parameters
+// which that release doesn't have yet and parameters which are deprecated.
+// These are warnings rather than errors since the same configuration is
+// often shared between hosts running different releases.
+use std::error;
+use std::fmt;
+use std::net::IpAddr;
+use crate::name::{
+    NameError,
+    ParamName,
+};
+use crate::schema::{
+    parameter,
+    ParamType,
+    Schema,
+    Version,
+};
+use crate::value::{
+    parse_bool,
+    parse_ip_addr,
+};
+use crate::warning::{
+    deprecation,
+    Warning,
+    WarningKind,
+};
+use crate::{
+    JailConf,
+    JailConfig,
+    Span,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationError {
+    /// The parameter isn't known on the release being validated against.
+    UnknownParameter {
+        jail: Option<String>,
+        name: String,
+        span: Span,
+    },
+    /// The parameter's value isn't of the type the parameter expects.
+    InvalidValue {
+        jail:     Option<String>,
+        name:     String,
+        value:    String,
+        expected: ParamType,
+        span:     Span,
+    },
+    /// The parameter's name isn't well formed.
+    InvalidName {
+        jail:  Option<String>,
+        name:  String,
+        error: NameError,
+        span:  Span,
+    },
+    /// The parameter's name is an old spelling of another, eg. ip4_addr.
+    Misspelled {
+        jail:    Option<String>,
+        name:    String,
+        instead: String,
+        span:    Span,
+    },
+}
+
+impl ValidationError {
+    /// The jail the parameter was set for, or None for a global parameter.
+    pub fn jail(&self) -> Option<&str> {
+        match self {
+            ValidationError::UnknownParameter { jail, .. } |
+            ValidationError::InvalidValue { jail, .. } |
+            ValidationError::InvalidName { jail, .. } |
+            ValidationError::Misspelled { jail, .. } => jail.as_deref(),
+        }
+    }
+
+    /// The name of the parameter.
+    pub fn name(&self) -> &str {
+        match self {
+            ValidationError::UnknownParameter { name, .. } |
+            ValidationError::InvalidValue { name, .. } |
+            ValidationError::InvalidName { name, .. } |
+            ValidationError::Misspelled { name, .. } => name,
+        }
+    }
+
+    /// Where the statement setting the parameter was parsed from.
+    pub fn span(&self) -> Span {
+        match self {
+            ValidationError::UnknownParameter { span, .. } |
+            ValidationError::InvalidValue { span, .. } |
+            ValidationError::InvalidName { span, .. } |
+            ValidationError::Misspelled { span, .. } => *span,
+        }
+    }
+
+    /// A short identifier for the kind of error, eg. "unknown-parameter".
+    pub fn code(&self) -> &'static str {
+        match self {
+            ValidationError::UnknownParameter { .. } => "unknown-parameter",
+            ValidationError::InvalidValue { .. }     => "invalid-value",
+            ValidationError::InvalidName { .. }      => "invalid-name",
+            ValidationError::Misspelled { .. }       => "misspelled",
+        }
+    }
+
+    /// A description of the error, without the jail it's in.
+    pub fn message(&self) -> String {
+        match self {
+            ValidationError::UnknownParameter { name, .. } => {
+                format!("unknown parameter {}", name)
+            },
+            ValidationError::InvalidValue { name, value, expected, .. } => {
+                format!("{} = {:?} is not {}", name, value, expected.description())
+            },
+            ValidationError::InvalidName { error, .. } => error.to_string(),
+            ValidationError::Misspelled { name, instead, .. } => {
+                format!("unknown parameter {}, did you mean {}?", name, instead)
+            },
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(jail) = self.jail() {
+            write!(f, "{}: ", jail)?;
+        }
+
+        f.write_str(&self.message())
+    }
+}
+
+impl error::Error for ValidationError {}
+
+// Whether every address in a list is of the expected family.
+fn addrs_valid(value: &str, v4: bool) -> bool {
+    value.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .all(|entry| match parse_ip_addr(entry) {
+            Some(IpAddr::V4(_)) => v4,
+            Some(IpAddr::V6(_)) => !v4,
+            None                => false,
+        })
+}
+
+// Whether a value is acceptable for a parameter of the given type. Values
+// given without one, eg. `persist;`, are only valid for booleans and jailsys
+// parameters, where they mean true or "new" respectively.
+fn valid(kind: ParamType, value: Option<&str>) -> bool {
+    let value = match value {
+        Some(value) => value,
+        None        => return kind == ParamType::Bool || kind == ParamType::JailSys,
+    };
+
+    match kind {
+        ParamType::Bool    => parse_bool(value).is_some(),
+        ParamType::Int     => value.trim().parse::<i64>().is_ok(),
+        ParamType::String  => true,
+        ParamType::List    => true,
+        ParamType::Ip4     => addrs_valid(value, true),
+        ParamType::Ip6     => addrs_valid(value, false),
+        ParamType::JailSys => {
+            ["new", "inherit", "disable"].contains(&value) || parse_bool(value).is_some()
+        },
+    }
+}
+
+// Check the parameters in statements, adding any problems to errors.
+fn check(
+    schema: &Schema,
+    jail: Option<&str>,
+    statements: &[JailConf],
+    errors: &mut Vec<ValidationError>,
+) {
+    for statement in statements {
+        let (name, value, span) = match statement {
+            JailConf::ParamBool(param)  => (param.name(), None, param.span()),
+            JailConf::ParamValue(param) => {
+                (param.name(), Some(param.value()), param.span())
+            },
+            _ => continue,
+        };
+
+        let jail_name = || jail.map(String::from);
+
+        let parsed = match ParamName::parse(name) {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                errors.push(ValidationError::InvalidName {
+                    jail: jail_name(),
+                    name: name.to_string(),
+                    error,
+                    span,
+                });
+                continue;
+            },
+        };
+
+        if parsed.is_respelled() {
+            errors.push(ValidationError::Misspelled {
+                jail:    jail_name(),
+                name:    name.to_string(),
+                instead: parsed.canonical(),
+                span,
+            });
+            continue;
+        }
+
+        let param = match schema.parameter(parsed.name()) {
+            Some(param) => param,
+            None        => {
+                errors.push(ValidationError::UnknownParameter {
+                    jail: jail_name(),
+                    name: name.to_string(),
+                    span,
+                });
+                continue;
+            },
+        };
+
+        // Variable references can't be checked until they're expanded.
+        if value.is_some_and(|value| value.contains('$')) {
+            continue;
+        }
+
+        if !valid(param.kind(), value) {
+            errors.push(ValidationError::InvalidValue {
+                jail:     jail_name(),
+                name:     name.to_string(),
+                value:    value.unwrap_or("").to_string(),
+                expected: param.kind(),
+                span,
+            });
+        }
+    }
+}
+
+// Check the parameters in statements against a release, adding any
+// warnings.
+fn check_release(
+    version: Version,
+    jail: Option<&str>,
+    statements: &[JailConf],
+    warnings: &mut Vec<Warning>,
+) {
+    for statement in statements {
+        let (name, span) = match statement {
+            JailConf::ParamBool(param)  => (param.name(), param.span()),
+            JailConf::ParamValue(param) => (param.name(), param.span()),
+            _                           => continue,
+        };
+
+        // Parameters unknown on every release are left to validate.
+        let param = match ParamName::parse(name).ok().and_then(|name| parameter(name.name())) {
+            Some(param) => param,
+            None        => continue,
+        };
+
+        if !param.available_on(version) {
+            let message = format!(
+                "{} isn't available on FreeBSD {}, it was added in {}",
+                name,
+                version,
+                param.since(),
+            );
+
+            warnings.push(Warning::new(WarningKind::Unavailable, message, jail, span));
+        }
+
+        warnings.extend(deprecation(name, jail, span));
+    }
+}
+
+// The span of the first parameter in statements, or within their blocks,
+// which isn't available on the release, for ParseOptions::target_version.
+pub(crate) fn first_unavailable(version: Version, statements: &[JailConf]) -> Option<Span> {
+    let mut warnings = Vec::new();
+
+    check_release(version, None, statements, &mut warnings);
+
+    let nested = statements.iter()
+        .filter_map(|statement| match statement {
+            JailConf::Block(block) => first_unavailable(version, block.params()),
+            _                      => None,
+        });
+
+    warnings.iter()
+        .filter(|warning| warning.kind() == WarningKind::Unavailable)
+        .map(Warning::span)
+        .chain(nested)
+        .min_by_key(|span| span.start().offset())
+}
+
+impl<'a> JailConfig<'a> {
+    /// Check every parameter against the schema for the newest known
+    /// release, see `validate_with`.
+    pub fn validate(&self) -> Vec<ValidationError> {
+        self.validate_with(&Schema::latest())
+    }
+
+    /// Check every parameter against the given schema, returning a
+    /// ValidationError for each parameter the release doesn't know about
+    /// and for each value of the wrong type. Values referring to variables
+    /// are only checked once expanded, see `resolve_variables`.
+    pub fn validate_with(&self, schema: &Schema) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+
+        check(schema, None, self.statements(), &mut errors);
+
+        for block in self.jails() {
+            check(schema, Some(block.name()), block.params(), &mut errors);
+        }
+
+        errors
+    }
+
+    /// Check the configuration against the release it's to be deployed to,
+    /// returning a Warning for each parameter the release doesn't have yet
+    /// and for each deprecated parameter. Parameters no release knows are
+    /// reported by `validate` instead.
+    pub fn validate_for(&self, version: Version) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+
+        check_release(version, None, self.statements(), &mut warnings);
+
+        for block in self.jails() {
+            check_release(version, Some(block.name()), block.params(), &mut warnings);
+        }
+
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        persist;
+        enforce_statfs = two;
+        nginx {
+            allow.mount = maybe;
+            allow.nfsd;
+            ip4.addr = "lo1|127.0.1.1/32, 127.0.1.2";
+            ip6.addr = 127.0.1.3;
+            vnet;
+            path = "/usr/jails/$name";
+            no.such.param = 1;
+        }
+        "#);
+
+    #[test]
+    fn test_validate() {
+        let config = JailConfig::parse(INPUT).unwrap();
+        let errors: Vec<String> = config.validate()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(errors, vec![
+            "enforce_statfs = \"two\" is not an integer",
+            "nginx: allow.mount = \"maybe\" is not a boolean",
+            "nginx: ip6.addr = \"127.0.1.3\" is not a list of IPv6 addresses",
+            "nginx: unknown parameter no.such.param",
+        ]);
+    }
+
+    #[test]
+    fn test_validate_names() {
+        let config = JailConfig::parse(indoc!(r#"
+            allow.nomount;
+            allow.noraw_sockets = true;
+            nginx {
+                ip4_addr = 127.0.1.1;
+                allow..mount;
+                mount.nodevfs = maybe;
+            }
+            "#)).unwrap();
+
+        let errors: Vec<String> = config.validate()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(errors, vec![
+            "nginx: unknown parameter ip4_addr, did you mean ip4.addr?",
+            "nginx: parameter name allow..mount has an empty component",
+            "nginx: mount.nodevfs = \"maybe\" is not a boolean",
+        ]);
+
+        let codes: Vec<&str> = config.validate()
+            .iter()
+            .map(ValidationError::code)
+            .collect();
+
+        assert_eq!(codes, vec!["misspelled", "invalid-name", "invalid-value"]);
+        assert_eq!(config.validate()[0].message(), "unknown parameter ip4_addr, did you mean ip4.addr?");
+    }
+
+    #[test]
+    fn test_validate_for_release() {
+        let config = JailConfig::parse(INPUT).unwrap();
+        let errors = config.validate_with(&Schema::new(Version::FreeBSD13_0));
+
+        let unknown: Vec<&str> = errors.iter()
+            .filter(|error| matches!(error, ValidationError::UnknownParameter { .. }))
+            .map(ValidationError::name)
+            .collect();
+
+        assert_eq!(unknown, vec!["allow.nfsd", "no.such.param"]);
+    }
+
+    #[test]
+    fn test_validate_for() {
+        let config = JailConfig::parse(indoc!(r#"
+            allow.sysvipc;
+            nginx {
+                allow.nfsd;
+                allow.mount.fusefs;
+                no.such.param;
+            }
+            "#)).unwrap();
+
+        let warnings: Vec<String> = config.validate_for(Version::FreeBSD13_2)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(warnings, vec![
+            "warning[deprecated-parameter]: allow.sysvipc is deprecated, use sysvmsg, sysvsem and sysvshm instead",
+            "warning[unavailable-parameter]: nginx: allow.nfsd isn't available on FreeBSD 13.2, it was added in 13.3",
+        ]);
+
+        let old = config.validate_for(Version::FreeBSD12_0);
+        assert_eq!(old.len(), 3);
+        assert_eq!(old[2].kind(), WarningKind::Unavailable);
+        assert_eq!(old[2].span().start().line(), 4);
+        assert_eq!(config.validate_for(Version::FreeBSD14_0).len(), 1);
+    }
+
+    #[test]
+    fn test_validation_error_span() {
+        let config = JailConfig::parse(INPUT).unwrap();
+        let errors = config.validate();
+
+        assert_eq!(errors[0].jail(), None);
+        assert_eq!(&INPUT[errors[0].span().range()], "enforce_statfs = two;");
+        assert_eq!(errors[1].span().start().line(), 4);
+    }
+}
@@ -0,0 +1,270 @@
+// Typed access to parameter values.
+//
+// Values are stored as the strings they were written as. The getters here
+// coerce the effective value of a parameter for a jail into the type the
+// caller expects, reporting a ValueError if it can't be.
+use std::error;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use crate::name::negate;
+use crate::JailConfig;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValueError {
+    /// The parameter isn't set for the jail, or the jail doesn't exist.
+    Missing {
+        jail: String,
+        name: String,
+    },
+    /// The parameter's value couldn't be converted to the requested type.
+    Invalid {
+        name:     String,
+        value:    String,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueError::Missing { jail, name } => {
+                write!(f, "{} is not set for jail {}", name, jail)
+            },
+            ValueError::Invalid { name, value, expected } => {
+                write!(f, "{} = {:?} is not {}", name, value, expected)
+            },
+        }
+    }
+}
+
+impl error::Error for ValueError {}
+
+pub(crate) fn invalid(name: &str, value: &str, expected: &'static str) -> ValueError {
+    ValueError::Invalid {
+        name:  name.to_string(),
+        value: value.to_string(),
+        expected,
+    }
+}
+
+// Interpret a boolean value as jail(8) does.
+pub(crate) fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1"  => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _                            => None,
+    }
+}
+
+// Parse an address from an ip4.addr or ip6.addr list entry. These may name
+// an interface and give a prefix length, eg. `lo1|127.0.1.1/32`, both of
+// which are ignored.
+pub(crate) fn parse_ip_addr(entry: &str) -> Option<IpAddr> {
+    let addr = match entry.find('|') {
+        Some(bar) => &entry[bar + 1..],
+        None      => entry,
+    };
+
+    let addr = match addr.find(['/', ' ']) {
+        Some(end) => &addr[..end],
+        None      => addr,
+    };
+
+    addr.parse().ok()
+}
+
+impl<'a> JailConfig<'a> {
+    // The effective value of a parameter, or a Missing error.
+    fn required(&self, jail: &str, name: &str) -> Result<String, ValueError> {
+        self.get_param(jail, name)
+            .ok_or_else(|| ValueError::Missing {
+                jail: jail.to_string(),
+                name: name.to_string(),
+            })
+    }
+
+    /// The value of a boolean parameter. Parameters given without a value,
+    /// eg. `persist;`, are true, otherwise the value may be one of true,
+    /// false, yes, no, on, off, 1 or 0. The parameter may also be set with
+    /// its "no" form, eg. `allow.nomount;` for allow.mount, whichever is
+    /// given last taking effect.
+    pub fn get_bool(&self, jail: &str, name: &str) -> Result<bool, ValueError> {
+        let negative = negate(name);
+
+        let (param, value) = self.params(jail)
+            .filter(|(param, _)| *param == name || *param == negative)
+            .last()
+            .ok_or_else(|| ValueError::Missing {
+                jail: jail.to_string(),
+                name: name.to_string(),
+            })?;
+
+        let parsed = parse_bool(value).ok_or_else(|| invalid(param, value, "a boolean"))?;
+
+        Ok(parsed != (param == negative))
+    }
+
+    /// The value of an integer parameter, eg. `enforce_statfs`.
+    pub fn get_int(&self, jail: &str, name: &str) -> Result<i64, ValueError> {
+        let value = self.required(jail, name)?;
+
+        value.trim()
+            .parse()
+            .map_err(|_| invalid(name, &value, "an integer"))
+    }
+
+    /// The value of a path parameter, eg. `path`.
+    pub fn get_path(&self, jail: &str, name: &str) -> Result<PathBuf, ValueError> {
+        let value = self.required(jail, name)?;
+
+        if value.is_empty() {
+            return Err(invalid(name, &value, "a path"));
+        }
+
+        Ok(PathBuf::from(value))
+    }
+
+    /// The elements of a list parameter. Lists may be built up with `+=`,
+    /// given as comma separated values, or both. A parameter which isn't set
+    /// is an empty list.
+    pub fn get_list(&self, jail: &str, name: &str) -> Vec<String> {
+        self.get_values(jail, name)
+            .iter()
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|element| !element.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    /// The addresses in an address list parameter, eg. `ip4.addr` or
+    /// `ip6.addr`. Entries may be given as `iface|addr/prefix`, only the
+    /// address itself is returned.
+    pub fn get_ip_addrs(&self, jail: &str, name: &str)
+    -> Result<Vec<IpAddr>, ValueError> {
+        self.get_list(jail, name)
+            .iter()
+            .map(|entry| {
+                parse_ip_addr(entry)
+                    .ok_or_else(|| invalid(name, entry, "an IP address"))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use std::net::{
+        Ipv4Addr,
+        Ipv6Addr,
+    };
+
+    const INPUT: &str = indoc!(r#"
+        persist;
+        enforce_statfs = 2;
+        nginx {
+            path = "/usr/jails/nginx";
+            allow.mount = "false";
+            allow.raw_sockets = maybe;
+            ip4.addr = "lo1|127.0.1.1/32, 127.0.1.2";
+            ip4.addr += "em0|192.0.2.1";
+            ip6.addr = "lo1|fd00::1/64";
+            exec.start = "one";
+        }
+        "#);
+
+    #[test]
+    fn test_get_bool() {
+        let config = JailConfig::parse(INPUT).unwrap();
+
+        assert_eq!(config.get_bool("nginx", "persist"), Ok(true));
+        assert_eq!(config.get_bool("nginx", "allow.mount"), Ok(false));
+        assert_eq!(
+            config.get_bool("nginx", "allow.raw_sockets"),
+            Err(ValueError::Invalid {
+                name:     "allow.raw_sockets".into(),
+                value:    "maybe".into(),
+                expected: "a boolean",
+            }),
+        );
+        assert_eq!(
+            config.get_bool("nginx", "allow.chflags"),
+            Err(ValueError::Missing {
+                jail: "nginx".into(),
+                name: "allow.chflags".into(),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_get_bool_negated() {
+        let config = JailConfig::parse(indoc!(r#"
+            allow.set_hostname;
+            allow.nomount;
+            nginx {
+                allow.noset_hostname;
+                allow.nochflags = false;
+                allow.noraw_sockets = maybe;
+            }
+            "#)).unwrap();
+
+        assert_eq!(config.get_bool("nginx", "allow.set_hostname"), Ok(false));
+        assert_eq!(config.get_bool("nginx", "allow.mount"), Ok(false));
+        assert_eq!(config.get_bool("nginx", "allow.chflags"), Ok(true));
+        assert!(matches!(
+            config.get_bool("nginx", "allow.raw_sockets"),
+            Err(ValueError::Invalid { name, .. }) if name == "allow.noraw_sockets",
+        ));
+
+        let params = config.effective_params("nginx").unwrap();
+
+        assert_eq!(params["allow.set_hostname"], vec!["false"]);
+        assert_eq!(params["allow.mount"], vec!["false"]);
+        assert_eq!(params["allow.chflags"], vec!["true"]);
+        assert!(!params.contains_key("allow.noset_hostname"));
+        assert_eq!(params["allow.noraw_sockets"], vec!["maybe"]);
+    }
+
+    #[test]
+    fn test_get_int_and_path() {
+        let config = JailConfig::parse(INPUT).unwrap();
+
+        assert_eq!(config.get_int("nginx", "enforce_statfs"), Ok(2));
+        assert!(config.get_int("nginx", "path").is_err());
+        assert_eq!(
+            config.get_path("nginx", "path"),
+            Ok(PathBuf::from("/usr/jails/nginx")),
+        );
+    }
+
+    #[test]
+    fn test_get_list() {
+        let config = JailConfig::parse(INPUT).unwrap();
+
+        assert_eq!(config.get_list("nginx", "ip4.addr"), vec![
+            "lo1|127.0.1.1/32",
+            "127.0.1.2",
+            "em0|192.0.2.1",
+        ]);
+        assert_eq!(config.get_list("nginx", "exec.start"), vec!["one"]);
+        assert!(config.get_list("nginx", "exec.stop").is_empty());
+    }
+
+    #[test]
+    fn test_get_ip_addrs() {
+        let config = JailConfig::parse(INPUT).unwrap();
+
+        assert_eq!(config.get_ip_addrs("nginx", "ip4.addr"), Ok(vec![
+            IpAddr::V4(Ipv4Addr::new(127, 0, 1, 1)),
+            IpAddr::V4(Ipv4Addr::new(127, 0, 1, 2)),
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+        ]));
+        assert_eq!(config.get_ip_addrs("nginx", "ip6.addr"), Ok(vec![
+            IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1)),
+        ]));
+        assert!(config.get_ip_addrs("nginx", "path").is_err());
+    }
+}
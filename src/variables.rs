@@ -0,0 +1,973 @@
+// Expansion of variables within parameter values.
+//
+// Values may refer to variables as either `$name` or `${name}`. A variable is
+// either one defined with `$var = value;`, or any parameter in scope, eg.
+// `${host.hostname}`. Within a jail block, `$name` always refers to the name
+// of the jail.
+//
+// Definitions in a block are visible only within that block, while those at
+// the top level are visible everywhere. A definition in a block overrides a
+// global one of the same name, whether it's of a variable or a parameter,
+// since jail(8) keeps both in one namespace. As with jail(8), references are
+// expanded after all definitions have been read, so a variable may be used
+// before the line that defines it.
+//
+// Expansion works on values as they were written, so that an escaped `\$`
+// is left alone, and escape sequences are decoded afterwards. Single quoted
+// values are taken literally and never expanded.
+//
+// Variables not defined in the file can be supplied by a VariableResolver,
+// eg. from the environment or a secrets store, with resolve_variables_with.
+// Definitions in the file take precedence over the resolver. Rather than
+// stopping at the first variable that can't be found, every missing name is
+// collected and reported in one error.
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{
+    BTreeMap,
+    HashMap,
+};
+use std::env;
+use std::error;
+use std::fmt;
+use crate::escape::{
+    escape_literal,
+    fragments,
+    inner,
+    quote_literal,
+    quote_style,
+    unescape,
+};
+use crate::wildcard::is_wildcard;
+use crate::{
+    JailConf,
+    QuoteStyle,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum VariableError {
+    /// A variable was referenced but never defined. The jail is the one
+    /// being resolved when the reference was found, if any.
+    Undefined {
+        name: String,
+        jail: Option<String>,
+    },
+    /// A variable refers back to itself, either directly or through other
+    /// variables.
+    Recursive {
+        name: String,
+    },
+    /// A `${` reference without its closing brace.
+    Unterminated {
+        value: String,
+    },
+    /// Variables defined neither in the configuration nor by the resolver,
+    /// in the order they were first referenced.
+    Unresolved {
+        names: Vec<String>,
+    },
+}
+
+impl fmt::Display for VariableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VariableError::Undefined { name, jail: Some(jail) } => {
+                write!(f, "undefined variable ${} in jail {}", name, jail)
+            },
+            VariableError::Undefined { name, jail: None } => {
+                write!(f, "undefined variable ${}", name)
+            },
+            VariableError::Recursive { name } => {
+                write!(f, "variable ${} refers to itself", name)
+            },
+            VariableError::Unterminated { value } => {
+                write!(f, "unterminated variable reference in {:?}", value)
+            },
+            VariableError::Unresolved { names } => {
+                let names: Vec<String> = names.iter()
+                    .map(|name| format!("${}", name))
+                    .collect();
+
+                write!(f, "undefined variables {}", names.join(", "))
+            },
+        }
+    }
+}
+
+impl error::Error for VariableError {}
+
+/// A source of values for variables not defined in the configuration.
+pub trait VariableResolver {
+    /// The value of the named variable, or None if it's unknown. Values are
+    /// used literally, without further expansion.
+    fn resolve(&self, name: &str) -> Option<String>;
+}
+
+impl<F> VariableResolver for F
+where
+    F: Fn(&str) -> Option<String>,
+{
+    fn resolve(&self, name: &str) -> Option<String> {
+        self(name)
+    }
+}
+
+impl VariableResolver for HashMap<String, String> {
+    fn resolve(&self, name: &str) -> Option<String> {
+        self.get(name).cloned()
+    }
+}
+
+impl VariableResolver for BTreeMap<String, String> {
+    fn resolve(&self, name: &str) -> Option<String> {
+        self.get(name).cloned()
+    }
+}
+
+/// Resolves variables from the process environment.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Environment;
+
+impl VariableResolver for Environment {
+    fn resolve(&self, name: &str) -> Option<String> {
+        env::var(name).ok()
+    }
+}
+
+#[derive(Clone, Default)]
+struct Scope<'r> {
+    jail:      Option<String>,
+    variables: HashMap<String, String>,
+    params:    HashMap<String, String>,
+    resolver:  Option<&'r dyn VariableResolver>,
+    // Where to collect undefined names, when they're all to be reported
+    // rather than only the first.
+    missing:   Option<&'r RefCell<Vec<String>>>,
+}
+
+// Assign a value to a name. += on a name which already has a value appends
+// to it as another list element.
+fn assign(map: &mut HashMap<String, String>, name: &str, value: &str, append: bool) {
+    match map.get_mut(name) {
+        Some(existing) if append => {
+            existing.push(',');
+            existing.push_str(value);
+        },
+        _ => {
+            map.insert(name.to_string(), value.to_string());
+        },
+    }
+}
+
+// Variables and parameters share one namespace, as they do in jail(8), so
+// defining one hides the other of the same name. Appending to a name the
+// other holds carries on from its value.
+fn shadow(
+    map: &mut HashMap<String, String>,
+    other: &mut HashMap<String, String>,
+    name: &str,
+    append: bool,
+) {
+    if let Some(value) = other.remove(name) {
+        if append && !map.contains_key(name) {
+            map.insert(name.to_string(), value);
+        }
+    }
+}
+
+// The text of a value to expand, as written. Single quoted values are
+// escaped so that they're kept literally when substituted into others.
+// Values of several fragments are joined.
+fn expandable(raw: &str) -> Cow<'_, str> {
+    let fragments = fragments(raw);
+
+    if fragments.len() > 1 {
+        return Cow::Owned(fragments.into_iter().map(expandable).collect());
+    }
+
+    match quote_style(raw) {
+        QuoteStyle::Single => Cow::Owned(escape_literal(inner(raw))),
+        _                  => Cow::Borrowed(inner(raw)),
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+// Split a value, as written, into its literal text and the names of the
+// variables it refers to, in order. Single quoted fragments are all text,
+// escaped characters are kept without their backslash, and an unterminated
+// `${` ends the references.
+pub(crate) fn references(raw: &str) -> (String, Vec<&str>) {
+    let mut text = String::new();
+    let mut names = Vec::new();
+
+    for fragment in fragments(raw) {
+        let mut rest = inner(fragment);
+
+        if quote_style(fragment) == QuoteStyle::Single {
+            text.push_str(rest);
+            continue;
+        }
+
+        while let Some(special) = rest.find(['$', '\\']) {
+            text.push_str(&rest[..special]);
+
+            let after = &rest[special + 1..];
+
+            if rest[special..].starts_with('\\') {
+                let escaped = after.chars().next().map_or(0, char::len_utf8);
+
+                text.push_str(&after[..escaped]);
+                rest = &after[escaped..];
+                continue;
+            }
+
+            let name = if after.starts_with('{') {
+                match after.find('}') {
+                    Some(close) => {
+                        rest = &after[close + 1..];
+                        &after[1..close]
+                    },
+                    None => {
+                        rest = after;
+                        break;
+                    },
+                }
+            }
+            else {
+                let len = after.find(|c: char| !is_name_char(c))
+                    .unwrap_or(after.len());
+
+                rest = &after[len..];
+                &after[..len]
+            };
+
+            if name.is_empty() {
+                text.push('$');
+            }
+            else {
+                names.push(name);
+            }
+        }
+
+        text.push_str(rest);
+    }
+
+    (text, names)
+}
+
+impl Scope<'_> {
+    // Add the definitions from the given statements to the scope. Nested
+    // blocks have their own scope and are skipped.
+    fn define(&mut self, statements: &[JailConf]) {
+        for statement in statements {
+            match statement {
+                JailConf::Variable(var) => {
+                    let value = expandable(var.raw());
+
+                    shadow(&mut self.variables, &mut self.params, var.name(), var.is_append());
+                    assign(&mut self.variables, var.name(), &value, var.is_append());
+                },
+                JailConf::ParamValue(param) => {
+                    let value = expandable(param.raw());
+
+                    shadow(&mut self.params, &mut self.variables, param.name(), param.is_append());
+                    assign(&mut self.params, param.name(), &value, param.is_append());
+                },
+                _ => {},
+            }
+        }
+    }
+
+    // Find the fully expanded value of a variable. stack holds the variables
+    // currently being expanded, to catch definitions which refer to
+    // themselves.
+    fn lookup(&self, name: &str, stack: &mut Vec<String>)
+    -> Result<String, VariableError> {
+        if name == "name" {
+            if let Some(jail) = &self.jail {
+                return Ok(jail.clone());
+            }
+        }
+
+        let value = match self.variables.get(name).or_else(|| self.params.get(name)) {
+            Some(value) => value,
+            None        => return self.external(name),
+        };
+
+        if stack.iter().any(|n| n == name) {
+            return Err(VariableError::Recursive {
+                name: name.to_string(),
+            });
+        }
+
+        stack.push(name.to_string());
+        let expanded = self.expand_with(value, stack)?.into_owned();
+        stack.pop();
+
+        Ok(expanded)
+    }
+
+    // The value of a variable not defined in the configuration. Values from
+    // the resolver are escaped, so that they're kept literally once
+    // substituted. Undefined names are either collected, standing in for an
+    // empty value meanwhile, or an error.
+    fn external(&self, name: &str) -> Result<String, VariableError> {
+        if let Some(value) = self.resolver.and_then(|r| r.resolve(name)) {
+            return Ok(escape_literal(&value));
+        }
+
+        match self.missing {
+            Some(missing) => {
+                let mut missing = missing.borrow_mut();
+
+                if !missing.iter().any(|n| n == name) {
+                    missing.push(name.to_string());
+                }
+
+                Ok(String::new())
+            },
+            None => Err(VariableError::Undefined {
+                name: name.to_string(),
+                jail: self.jail.clone(),
+            }),
+        }
+    }
+
+    // Expand all references in value. Values without any references are
+    // returned as they are.
+    fn expand<'v>(&self, value: &'v str) -> Result<Cow<'v, str>, VariableError> {
+        self.expand_with(value, &mut Vec::new())
+    }
+
+    fn expand_with<'v>(&self, value: &'v str, stack: &mut Vec<String>)
+    -> Result<Cow<'v, str>, VariableError> {
+        if !value.contains('$') {
+            return Ok(Cow::Borrowed(value));
+        }
+
+        let mut expanded = String::with_capacity(value.len());
+        let mut rest = value;
+
+        while let Some(special) = rest.find(['$', '\\']) {
+            expanded.push_str(&rest[..special]);
+
+            // Escaped characters, including `\$`, are kept as they are to be
+            // decoded later.
+            if rest[special..].starts_with('\\') {
+                let escaped = rest[special + 1..]
+                    .chars()
+                    .next()
+                    .map_or(0, char::len_utf8);
+
+                expanded.push_str(&rest[special..special + 1 + escaped]);
+                rest = &rest[special + 1 + escaped..];
+                continue;
+            }
+
+            let after = &rest[special + 1..];
+
+            let name = if after.starts_with('{') {
+                let close = after.find('}')
+                    .ok_or_else(|| VariableError::Unterminated {
+                        value: value.to_string(),
+                    })?;
+
+                rest = &after[close + 1..];
+                &after[1..close]
+            }
+            else {
+                let len = after.find(|c: char| !is_name_char(c))
+                    .unwrap_or(after.len());
+
+                rest = &after[len..];
+                &after[..len]
+            };
+
+            // A lone $ isn't a reference.
+            if name.is_empty() {
+                expanded.push('$');
+                continue;
+            }
+
+            expanded.push_str(&self.lookup(name, stack)?);
+        }
+
+        expanded.push_str(rest);
+
+        Ok(Cow::Owned(expanded))
+    }
+
+    // Expand a value in place, requoting it so that the raw value matches.
+    fn expand_value<'a>(&self, value: &mut Cow<'a, str>, raw: &mut Cow<'a, str>)
+    -> Result<(), VariableError> {
+        let literal = fragments(raw)
+            .iter()
+            .all(|fragment| quote_style(fragment) == QuoteStyle::Single);
+
+        if literal {
+            return Ok(());
+        }
+
+        let expanded = match self.expand(&expandable(raw))? {
+            Cow::Borrowed(_)     => return Ok(()),
+            Cow::Owned(expanded) => unescape(&expanded).into_owned(),
+        };
+
+        *raw = Cow::Owned(quote_literal(&expanded));
+        *value = Cow::Owned(expanded);
+
+        Ok(())
+    }
+
+    // Expand every value in statements, recursing into blocks with a scope
+    // of their own.
+    fn resolve(&self, statements: &mut [JailConf]) -> Result<(), VariableError> {
+        for statement in statements {
+            match statement {
+                JailConf::ParamValue(param) => {
+                    self.expand_value(&mut param.value, &mut param.raw)?;
+                },
+                JailConf::Variable(var) => {
+                    self.expand_value(&mut var.value, &mut var.raw)?;
+                },
+                JailConf::Block(block) => {
+                    let mut scope = self.clone();
+                    scope.jail = Some(block.name().to_string())
+                        .filter(|name| !is_wildcard(name));
+                    scope.define(&block.params);
+                    scope.resolve(&mut block.params)?;
+                },
+                _ => {},
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Find the first global parameter that can't be expanded without knowing
+// which jail it's for, eg. `path = "/jails/$name";`.
+fn jail_dependent(config: &[JailConf], scope: &Scope)
+-> Result<Option<VariableError>, VariableError> {
+    for statement in config {
+        if let JailConf::ParamValue(param) = statement {
+            if param.quote_style() == QuoteStyle::Single {
+                continue;
+            }
+
+            match scope.expand(inner(param.raw())) {
+                Ok(_)                                   => {},
+                Err(err @ VariableError::Undefined{..}) => return Ok(Some(err)),
+                Err(err)                                => return Err(err),
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Expand variable references in every value of the configuration.
+///
+/// Global parameters which refer to per jail variables, such as `$name`, can
+/// only be expanded once the jail is known. When there are any, all global
+/// parameters are copied into the start of every block and expanded there,
+/// leaving only variables and comments at the top level. Wildcard blocks
+/// referring to them are likewise copied into each block they apply to, and
+/// dropped. This keeps the effective parameters of each jail the same as
+/// jail(8) would see them.
+pub fn resolve_variables<'a>(config: &[JailConf<'a>])
+-> Result<Vec<JailConf<'a>>, VariableError> {
+    resolve(config, Scope::default())
+}
+
+/// Expand variable references as `resolve_variables` does, looking up any
+/// variable the configuration doesn't define with the resolver. If any
+/// variables can't be found anywhere, all of their names are reported in a
+/// single `VariableError::Unresolved`.
+pub fn resolve_variables_with<'a, R>(config: &[JailConf<'a>], resolver: &R)
+-> Result<Vec<JailConf<'a>>, VariableError>
+where
+    R: VariableResolver,
+{
+    let missing = RefCell::new(Vec::new());
+
+    let scope = Scope {
+        resolver: Some(resolver),
+        missing:  Some(&missing),
+        ..Scope::default()
+    };
+
+    let config = resolve(config, scope)?;
+    let names = missing.into_inner();
+
+    if !names.is_empty() {
+        return Err(VariableError::Unresolved { names });
+    }
+
+    Ok(config)
+}
+
+// Whether any wildcard block has a value which can't be expanded without
+// knowing which jail it's for.
+fn wildcards_dependent(config: &[JailConf], globals: &Scope)
+-> Result<bool, VariableError> {
+    for statement in config {
+        if let JailConf::Block(block) = statement {
+            if !block.is_wildcard() {
+                continue;
+            }
+
+            let mut scope = globals.clone();
+            scope.define(&block.params);
+
+            if jail_dependent(&block.params, &scope)?.is_some() {
+                return Ok(true);
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+// Move the parameters of every wildcard block into the start of each block
+// they apply to, dropping the wildcard blocks.
+fn expand_wildcards<'a>(config: Vec<JailConf<'a>>) -> Vec<JailConf<'a>> {
+    let (wildcards, mut config): (Vec<_>, Vec<_>) = config
+        .into_iter()
+        .partition(|statement| {
+            matches!(statement, JailConf::Block(block) if block.is_wildcard())
+        });
+
+    for statement in &mut config {
+        if let JailConf::Block(block) = statement {
+            let mut statements: Vec<_> = wildcards.iter()
+                .filter_map(|wildcard| match wildcard {
+                    JailConf::Block(wildcard) if wildcard.applies_to(block.name()) => {
+                        Some(wildcard.params.iter().cloned())
+                    },
+                    _ => None,
+                })
+                .flatten()
+                .collect();
+
+            statements.append(&mut block.params);
+            block.params = statements;
+        }
+    }
+
+    config
+}
+
+fn resolve<'a>(config: &[JailConf<'a>], mut globals: Scope)
+-> Result<Vec<JailConf<'a>>, VariableError> {
+    let mut config = config.to_vec();
+    globals.define(&config);
+
+    // Probe without collecting, so that undefined references are seen.
+    let probe = Scope { missing: None, ..globals.clone() };
+
+    // Wildcard blocks referring to per jail variables are expanded within
+    // each jail they apply to, as global parameters are below.
+    if wildcards_dependent(&config, &probe)? {
+        config = expand_wildcards(config);
+    }
+
+    if let Some(err) = jail_dependent(&config, &probe)? {
+        let jails = config.iter()
+            .filter(|statement| {
+                matches!(statement, JailConf::Block(block) if !block.is_wildcard())
+            })
+            .count();
+
+        // Without any jails, the reference can never be satisfied. When
+        // collecting, it's reported along with any others below.
+        if jails == 0 {
+            if globals.missing.is_none() {
+                return Err(err);
+            }
+        }
+        else {
+            // Wildcard blocks have no name for the global parameters to
+            // refer to, so are expanded into the jails first. The global
+            // parameters then go ahead of theirs, keeping the precedence of
+            // globals, then wildcards, then the jail's own.
+            config = expand_wildcards(config);
+
+            let (params, rest): (Vec<_>, Vec<_>) = config
+                .into_iter()
+                .partition(|statement| {
+                    matches!(statement, JailConf::ParamBool(_) | JailConf::ParamValue(_))
+                });
+
+            config = rest;
+
+            for statement in &mut config {
+                if let JailConf::Block(block) = statement {
+                    let mut statements = params.clone();
+                    statements.append(&mut block.params);
+                    block.params = statements;
+                }
+            }
+        }
+    }
+
+    globals.resolve(&mut config)?;
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parse,
+        JailConfig,
+    };
+    use indoc::indoc;
+
+    // Find the value of a parameter in the named block.
+    fn block_value<'c>(config: &'c [JailConf], jail: &str, name: &str)
+    -> Option<&'c str> {
+        config.iter()
+            .filter_map(|statement| match statement {
+                JailConf::Block(block) if block.name() == jail => Some(block),
+                _                                              => None,
+            })
+            .flat_map(|block| block.params())
+            .filter_map(|statement| match statement {
+                JailConf::ParamValue(param) if param.name() == name => {
+                    Some(param.value())
+                },
+                _ => None,
+            })
+            .next_back()
+    }
+
+    #[test]
+    fn test_resolve_user_variable() {
+        let input = indoc!(r#"
+            $base = "/usr/jails";
+            nginx {
+                path = "$base/${name}";
+                host.hostname = "$name.example.com";
+                exec.start = "echo ${host.hostname}";
+            }
+            "#);
+
+        let config = resolve_variables(&parse(input).unwrap()).unwrap();
+
+        assert_eq!(block_value(&config, "nginx", "path"), Some("/usr/jails/nginx"));
+        assert_eq!(
+            block_value(&config, "nginx", "exec.start"),
+            Some("echo nginx.example.com"),
+        );
+    }
+
+    #[test]
+    fn test_resolve_global_name_per_jail() {
+        let input = indoc!(r#"
+            path = "/jails/$name";
+            persist;
+            nginx {
+                host.hostname = "nginx";
+            }
+            postgres {
+                path = "/db";
+            }
+            "#);
+
+        let config = resolve_variables(&parse(input).unwrap()).unwrap();
+
+        // Only the two blocks remain at the top level.
+        assert_eq!(config.len(), 2);
+        assert_eq!(block_value(&config, "nginx", "path"), Some("/jails/nginx"));
+        assert_eq!(block_value(&config, "postgres", "path"), Some("/db"));
+    }
+
+    #[test]
+    fn test_resolve_requotes_raw() {
+        let config = resolve_variables(
+            &parse("$dir = \"/tmp\";\npath = $dir;\n").unwrap()
+        ).unwrap();
+
+        match &config[1] {
+            JailConf::ParamValue(param) => {
+                assert_eq!(param.value(), "/tmp");
+                assert_eq!(param.raw(), "\"/tmp\"");
+            },
+            _ => panic!("expected a parameter"),
+        }
+    }
+
+    #[test]
+    fn test_resolve_undefined() {
+        let input = "nginx {\n    path = \"$nope\";\n}\n";
+        let res = resolve_variables(&parse(input).unwrap());
+
+        assert_eq!(res, Err(VariableError::Undefined {
+            name: "nope".into(),
+            jail: Some("nginx".into()),
+        }));
+    }
+
+    #[test]
+    fn test_resolve_recursive() {
+        let input = "$a = \"$b\";\n$b = \"${a}\";\n";
+        let res = resolve_variables(&parse(input).unwrap());
+
+        assert_eq!(res, Err(VariableError::Recursive {
+            name: "b".into(),
+        }));
+    }
+
+    #[test]
+    fn test_resolve_escaped_dollar() {
+        let input = "$a = \"x\";\nprice = \"\\$a is $a\";\n";
+        let config = resolve_variables(&parse(input).unwrap()).unwrap();
+
+        match &config[1] {
+            JailConf::ParamValue(param) => {
+                assert_eq!(param.value(), "$a is x");
+                assert_eq!(param.raw(), "\"\\$a is x\"");
+            },
+            _ => panic!("expected a parameter"),
+        }
+    }
+
+    #[test]
+    fn test_resolve_single_quoted() {
+        let input = "$a = 'lit$b\\n';\nnginx {\n    x = '$a';\n    y = \"$a\";\n}\n";
+        let config = resolve_variables(&parse(input).unwrap()).unwrap();
+
+        assert_eq!(block_value(&config, "nginx", "x"), Some("$a"));
+        assert_eq!(block_value(&config, "nginx", "y"), Some("lit$b\\n"));
+    }
+
+    #[test]
+    fn test_resolve_adjacent_strings() {
+        let input = "$a = \"x\";\nnginx {\n    y = '$a' \"$a\" '-';\n    z = 'one' ' two';\n}\n";
+        let config = resolve_variables(&parse(input).unwrap()).unwrap();
+
+        assert_eq!(block_value(&config, "nginx", "y"), Some("$ax-"));
+        assert_eq!(block_value(&config, "nginx", "z"), Some("one two"));
+    }
+
+    #[test]
+    fn test_resolve_with_resolver() {
+        let input = indoc!(r#"
+            $base = "/jails";
+            nginx {
+                path = "$base/$name";
+                exec.start = "/bin/sh -c 'echo ${TOKEN}'";
+                host.hostname = "$name.$domain";
+            }
+            "#);
+
+        let resolver = |name: &str| match name {
+            "TOKEN"  => Some("a\"$b".to_string()),
+            "domain" => Some("example.com".to_string()),
+            "base"   => Some("/ignored".to_string()),
+            _        => None,
+        };
+
+        let config = resolve_variables_with(&parse(input).unwrap(), &resolver).unwrap();
+
+        assert_eq!(block_value(&config, "nginx", "path"), Some("/jails/nginx"));
+        assert_eq!(
+            block_value(&config, "nginx", "exec.start"),
+            Some("/bin/sh -c 'echo a\"$b'"),
+        );
+        assert_eq!(block_value(&config, "nginx", "host.hostname"), Some("nginx.example.com"));
+    }
+
+    #[test]
+    fn test_resolve_with_unresolved() {
+        let input = indoc!(r#"
+            path = "/jails/$name/$a";
+            nginx {
+                x = "$b $a ${c}";
+            }
+            postgres {
+                y = "$b";
+            }
+            "#);
+
+        let mut resolver = HashMap::new();
+        resolver.insert("c".to_string(), "c".to_string());
+
+        let res = resolve_variables_with(&parse(input).unwrap(), &resolver);
+        let err = res.unwrap_err();
+
+        assert_eq!(err, VariableError::Unresolved {
+            names: vec!["a".into(), "b".into()],
+        });
+        assert_eq!(err.to_string(), "undefined variables $a, $b");
+
+        // Without any jails, $name is missing too.
+        let res = resolve_variables_with(&parse("path = \"$name\";").unwrap(), &resolver);
+
+        assert_eq!(res, Err(VariableError::Unresolved { names: vec!["name".into()] }));
+    }
+
+    #[test]
+    fn test_resolve_manual_examples() {
+        // The examples of VARIABLES in jail.conf(5).
+        let input = indoc!(r#"
+            $parentdir = "/var/jail";
+            path = "$parentdir/$name";
+            foo {
+                host.hostname = foo.example.org;
+            }
+            bar {
+                host.hostname = bar.example.org;
+                path = "/var/jail/${host.hostname}";
+            }
+            "#);
+
+        let config = resolve_variables(&parse(input).unwrap()).unwrap();
+
+        assert_eq!(block_value(&config, "foo", "path"), Some("/var/jail/foo"));
+        assert_eq!(block_value(&config, "bar", "path"), Some("/var/jail/bar.example.org"));
+    }
+
+    #[test]
+    fn test_resolve_block_scope() {
+        let input = indoc!(r#"
+            $suffix = ".example.org";
+            $name = "ignored";
+            foo {
+                $suffix = ".example.net";
+                host.hostname = "$name$suffix";
+            }
+            bar {
+                host.hostname = "$name$suffix";
+                $local = 1;
+            }
+            "#);
+
+        let config = resolve_variables(&parse(input).unwrap()).unwrap();
+
+        // A definition in a block overrides the global one for that block
+        // only, and `$name` is always the jail's name.
+        assert_eq!(block_value(&config, "foo", "host.hostname"), Some("foo.example.net"));
+        assert_eq!(block_value(&config, "bar", "host.hostname"), Some("bar.example.org"));
+
+        // Definitions in a block aren't visible in others.
+        let input = "a {\n    $local = 1;\n}\nb {\n    x = $local;\n}\n";
+
+        assert_eq!(resolve_variables(&parse(input).unwrap()), Err(VariableError::Undefined {
+            name: "local".into(),
+            jail: Some("b".into()),
+        }));
+    }
+
+    #[test]
+    fn test_resolve_wildcards() {
+        let input = indoc!(r#"
+            $base = "/jails";
+            * {
+                path = "$base/$name";
+                persist;
+            }
+            www {
+                host.hostname = "$name.example.org";
+            }
+            db {
+                path = "/data/db";
+            }
+            "#);
+
+        let config = resolve_variables(&parse(input).unwrap()).unwrap();
+        let config = JailConfig::new(config);
+
+        // The wildcard's values are expanded for each jail in turn.
+        assert_eq!(config.wildcards().count(), 0);
+        assert_eq!(config.get_param("www", "path").as_deref(), Some("/jails/www"));
+        assert_eq!(config.get_param("www", "persist").as_deref(), Some("true"));
+        assert_eq!(config.get_param("db", "path").as_deref(), Some("/data/db"));
+    }
+
+    #[test]
+    fn test_resolve_wildcards_global_name() {
+        let input = indoc!(r#"
+            path = "/jails/$name";
+            host.hostname = global;
+            * {
+                persist;
+                host.hostname = "wildcard";
+            }
+            www {
+            }
+            db {
+                host.hostname = "$name.example.org";
+            }
+            "#);
+
+        let config = resolve_variables(&parse(input).unwrap()).unwrap();
+        let config = JailConfig::new(config);
+
+        assert_eq!(config.wildcards().count(), 0);
+        assert_eq!(config.get_param("www", "path").as_deref(), Some("/jails/www"));
+        assert_eq!(config.get_param("www", "persist").as_deref(), Some("true"));
+        assert_eq!(config.get_param("db", "path").as_deref(), Some("/jails/db"));
+
+        // Wildcards still override globals, and jails override both.
+        assert_eq!(config.get_param("www", "host.hostname").as_deref(), Some("wildcard"));
+        assert_eq!(config.get_param("db", "host.hostname").as_deref(), Some("db.example.org"));
+
+        let original = JailConfig::parse(input).unwrap();
+
+        assert_eq!(
+            config.effective_params("www").unwrap()["host.hostname"],
+            original.effective_params("www").unwrap()["host.hostname"],
+        );
+    }
+
+    #[test]
+    fn test_resolve_wildcards_only_global_name() {
+        // Without any jails for it, $name can't be resolved.
+        let input = "path = \"/jails/$name\";\n* {\n    persist;\n}\n";
+        let res = resolve_variables(&parse(input).unwrap());
+
+        assert_eq!(res, Err(VariableError::Undefined {
+            name: "name".into(),
+            jail: None,
+        }));
+    }
+
+    #[test]
+    fn test_resolve_shared_names() {
+        // Variables and parameters share one namespace, so a parameter in a
+        // block overrides a global variable of the same name, and the other
+        // way around.
+        let input = indoc!(r#"
+            $domain = example.org;
+            devfs_ruleset = 4;
+            foo {
+                domain = example.net;
+                $devfs_ruleset = 5;
+                host.hostname = "$name.$domain";
+                exec.start = "echo $devfs_ruleset";
+            }
+            bar {
+                host.hostname = "$name.$domain";
+            }
+            "#);
+
+        let config = resolve_variables(&parse(input).unwrap()).unwrap();
+
+        assert_eq!(block_value(&config, "foo", "host.hostname"), Some("foo.example.net"));
+        assert_eq!(block_value(&config, "foo", "exec.start"), Some("echo 5"));
+        assert_eq!(block_value(&config, "bar", "host.hostname"), Some("bar.example.org"));
+    }
+
+    #[test]
+    fn test_resolve_lone_dollar() {
+        let scope = Scope::default();
+
+        assert_eq!(scope.expand("a $ b").unwrap(), "a $ b");
+    }
+}
@@ -0,0 +1,228 @@
+// Traversal of parsed configuration.
+//
+// Statements nest within jail blocks, so anything looking at every parameter
+// of a configuration has to recurse into blocks itself. A Visitor instead
+// overrides the methods for the kinds of statement it's interested in and
+// hands the statements to walk, which calls them for every statement at any
+// depth, in order.
+//
+// Each method's default does nothing, apart from visit_block which walks the
+// block's contents. A visitor overriding visit_block can call walk_block to
+// carry on into the block, or not to skip it.
+//
+// VisitorMut is the same over mutable statements, for rewriting. Its
+// visit_statement_mut sees every statement before it's dispatched to the
+// other methods, so can replace statements of any kind outright. Blocks keep
+// the source text they were parsed with, so write the configuration out with
+// Display to see the result of any changes.
+use crate::{
+    JailBlock,
+    JailComment,
+    JailConf,
+    JailInclude,
+    JailParamBool,
+    JailParamValue,
+    JailVariable,
+};
+
+/// Callbacks for each kind of statement, see `walk`.
+pub trait Visitor<'a> {
+    /// Called for each jail block. Walks the block's contents by default.
+    fn visit_block(&mut self, block: &JailBlock<'a>) {
+        walk_block(block, self);
+    }
+
+    /// Called for each comment.
+    fn visit_comment(&mut self, _comment: &JailComment<'a>) {}
+
+    /// Called for each include directive.
+    fn visit_include(&mut self, _include: &JailInclude<'a>) {}
+
+    /// Called for each parameter given without a value, eg. `persist;`.
+    fn visit_param_bool(&mut self, _param: &JailParamBool<'a>) {}
+
+    /// Called for each parameter given with a value.
+    fn visit_param_value(&mut self, _param: &JailParamValue<'a>) {}
+
+    /// Called for each variable definition.
+    fn visit_variable(&mut self, _var: &JailVariable<'a>) {}
+}
+
+/// Callbacks for each kind of statement over mutable statements, see
+/// `walk_mut`.
+pub trait VisitorMut<'a> {
+    /// Called for every statement, before the method for its kind. Calls
+    /// that method by default.
+    fn visit_statement_mut(&mut self, statement: &mut JailConf<'a>) {
+        walk_statement_mut(statement, self);
+    }
+
+    /// Called for each jail block. Walks the block's contents by default.
+    fn visit_block_mut(&mut self, block: &mut JailBlock<'a>) {
+        walk_block_mut(block, self);
+    }
+
+    /// Called for each comment.
+    fn visit_comment_mut(&mut self, _comment: &mut JailComment<'a>) {}
+
+    /// Called for each include directive.
+    fn visit_include_mut(&mut self, _include: &mut JailInclude<'a>) {}
+
+    /// Called for each parameter given without a value, eg. `persist;`.
+    fn visit_param_bool_mut(&mut self, _param: &mut JailParamBool<'a>) {}
+
+    /// Called for each parameter given with a value.
+    fn visit_param_value_mut(&mut self, _param: &mut JailParamValue<'a>) {}
+
+    /// Called for each variable definition.
+    fn visit_variable_mut(&mut self, _var: &mut JailVariable<'a>) {}
+}
+
+/// Call the visitor's method for each statement, in order, recursing into
+/// blocks through `Visitor::visit_block`.
+pub fn walk<'a, V>(statements: &[JailConf<'a>], visitor: &mut V)
+where
+    V: Visitor<'a> + ?Sized,
+{
+    for statement in statements {
+        match statement {
+            JailConf::Block(block)      => visitor.visit_block(block),
+            JailConf::Comment(comment)  => visitor.visit_comment(comment),
+            JailConf::Include(include)  => visitor.visit_include(include),
+            JailConf::ParamBool(param)  => visitor.visit_param_bool(param),
+            JailConf::ParamValue(param) => visitor.visit_param_value(param),
+            JailConf::Variable(var)     => visitor.visit_variable(var),
+        }
+    }
+}
+
+/// Walk the contents of a block.
+pub fn walk_block<'a, V>(block: &JailBlock<'a>, visitor: &mut V)
+where
+    V: Visitor<'a> + ?Sized,
+{
+    walk(block.params(), visitor);
+}
+
+/// Call the visitor's methods for each statement, in order, recursing into
+/// blocks through `VisitorMut::visit_block_mut`.
+pub fn walk_mut<'a, V>(statements: &mut [JailConf<'a>], visitor: &mut V)
+where
+    V: VisitorMut<'a> + ?Sized,
+{
+    for statement in statements {
+        visitor.visit_statement_mut(statement);
+    }
+}
+
+/// Call the visitor's method for the kind of statement.
+pub fn walk_statement_mut<'a, V>(statement: &mut JailConf<'a>, visitor: &mut V)
+where
+    V: VisitorMut<'a> + ?Sized,
+{
+    match statement {
+        JailConf::Block(block)      => visitor.visit_block_mut(block),
+        JailConf::Comment(comment)  => visitor.visit_comment_mut(comment),
+        JailConf::Include(include)  => visitor.visit_include_mut(include),
+        JailConf::ParamBool(param)  => visitor.visit_param_bool_mut(param),
+        JailConf::ParamValue(param) => visitor.visit_param_value_mut(param),
+        JailConf::Variable(var)     => visitor.visit_variable_mut(var),
+    }
+}
+
+/// Walk the contents of a block.
+pub fn walk_block_mut<'a, V>(block: &mut JailBlock<'a>, visitor: &mut V)
+where
+    V: VisitorMut<'a> + ?Sized,
+{
+    walk_mut(&mut block.params, visitor);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parse,
+        JailConfigBuilder,
+    };
+    use indoc::indoc;
+
+    const INPUT: &str = indoc!(r#"
+        # Defaults
+        persist;
+        www {
+            path = /jails/www;
+            allow.mount;
+        }
+        db {
+            path = /jails/db;
+        }
+        "#);
+
+    #[derive(Default)]
+    struct Paths {
+        jail:  Option<String>,
+        paths: Vec<(Option<String>, String)>,
+    }
+
+    impl<'a> Visitor<'a> for Paths {
+        fn visit_block(&mut self, block: &JailBlock<'a>) {
+            self.jail = Some(block.name().to_string());
+            walk_block(block, self);
+            self.jail = None;
+        }
+
+        fn visit_param_value(&mut self, param: &JailParamValue<'a>) {
+            if param.name() == "path" {
+                self.paths.push((self.jail.clone(), param.value().to_string()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_walk() {
+        let config = parse(INPUT).unwrap();
+        let mut visitor = Paths::default();
+
+        walk(&config, &mut visitor);
+
+        assert_eq!(visitor.paths, vec![
+            (Some("www".to_string()), "/jails/www".to_string()),
+            (Some("db".to_string()), "/jails/db".to_string()),
+        ]);
+    }
+
+    // Replaces every `allow.mount;` with `allow.mount = 0;`.
+    struct DisallowMount;
+
+    impl<'a> VisitorMut<'a> for DisallowMount {
+        fn visit_statement_mut(&mut self, statement: &mut JailConf<'a>) {
+            match statement {
+                JailConf::ParamBool(param) if param.name() == "allow.mount" => {
+                    let replacement = JailConfigBuilder::new()
+                        .param("allow.mount", "0")
+                        .build()
+                        .into_statements()
+                        .remove(0);
+
+                    *statement = replacement;
+                },
+                _ => walk_statement_mut(statement, self),
+            }
+        }
+    }
+
+    #[test]
+    fn test_walk_mut() {
+        let mut config = parse(INPUT).unwrap();
+
+        walk_mut(&mut config, &mut DisallowMount);
+
+        match &config[2] {
+            JailConf::Block(block) => {
+                assert_eq!(block.params()[1].source_text(), "allow.mount = 0;");
+            },
+            _ => panic!("expected a block"),
+        }
+    }
+}
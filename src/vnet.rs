@@ -0,0 +1,312 @@
+// Consistency of vnet jails and the interfaces handed to them.
+//
+// A vnet jail has its own network stack, with only lo0 until interfaces are
+// handed to it, usually with `vnet.interface`. The most common setup gives
+// it one end of an epair(4), created in `exec.prestart` with eg.
+// `ifconfig epair0 create` and destroyed in `exec.poststop`, the jail taking
+// epair0b and the host keeping epair0a. An interface can only be in one jail
+// at a time, so a second jail given the same one fails to start, and an
+// epair which is created but never destroyed is left behind on the host
+// every time the jail stops.
+//
+// Commands are only read as far as finding ifconfig(8) run with an
+// interface followed by `create`, `destroy` or `vnet`, which is how the
+// handbook and most setups write them. Interfaces created by scripts, eg.
+// jib, can't be followed and are left alone.
+use crate::lint::{
+    enabled,
+    Lint,
+    Severity,
+};
+use crate::JailConfig;
+
+// The parameters whose commands are run on the host before the jail starts,
+// in which interfaces are created and handed over.
+const START_PARAMS: &[&str] = &["exec.prestart", "exec.created"];
+
+// The parameters whose commands are run on the host once the jail stops.
+const STOP_PARAMS: &[&str] = &["exec.poststop"];
+
+/// A jail's vnet networking, see `JailConfig::vnet`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JailVnet {
+    vnet:       bool,
+    interfaces: Vec<String>,
+    created:    Vec<String>,
+    destroyed:  Vec<String>,
+    moved:      Vec<String>,
+}
+
+impl JailVnet {
+    /// Whether the jail has its own network stack, `vnet` or `vnet = new`.
+    pub fn is_vnet(&self) -> bool {
+        self.vnet
+    }
+
+    /// The interfaces given by `vnet.interface`, in order.
+    pub fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+
+    /// The epair(4) pairs created by `exec.prestart` or `exec.created`, by
+    /// the name of the pair, eg. `epair0` for epair0a and epair0b.
+    pub fn created_epairs(&self) -> &[String] {
+        &self.created
+    }
+
+    /// The epair(4) pairs destroyed by `exec.poststop`, by the name of the
+    /// pair. Destroying either end destroys both.
+    pub fn destroyed_epairs(&self) -> &[String] {
+        &self.destroyed
+    }
+
+    /// The interfaces handed to the jail by commands rather than by
+    /// `vnet.interface`, eg. `ifconfig epair0b vnet www`.
+    pub fn moved_interfaces(&self) -> &[String] {
+        &self.moved
+    }
+}
+
+/// The name of the epair(4) pair an interface belongs to, eg. `epair0` for
+/// `epair0`, `epair0a` or `epair0b`. None if it isn't a numbered epair.
+pub fn epair(interface: &str) -> Option<&str> {
+    let number = interface.strip_prefix("epair")?;
+    let number = number.strip_suffix(['a', 'b']).unwrap_or(number);
+
+    if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    Some(&interface[.."epair".len() + number.len()])
+}
+
+// Each ifconfig(8) run in a command, as the interface and the word after it.
+fn ifconfig(command: &str) -> Vec<(&str, &str)> {
+    let words: Vec<&str> = command
+        .split(|c: char| c.is_whitespace() || matches!(c, ';' | '&' | '|' | '"' | '\''))
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    words.windows(3)
+        .filter(|window| window[0] == "ifconfig" || window[0].ends_with("/ifconfig"))
+        .map(|window| (window[1], window[2]))
+        .collect()
+}
+
+// Add item to list if it isn't there already.
+fn push_unique(list: &mut Vec<String>, item: &str) {
+    if !list.iter().any(|known| known == item) {
+        list.push(item.to_string());
+    }
+}
+
+impl<'a> JailConfig<'a> {
+    /// The vnet networking of the named jail: whether it's a vnet jail, the
+    /// interfaces handed to it and the epair(4) pairs its commands create
+    /// and destroy. None if there's no such jail.
+    ///
+    /// Values are as written, use `resolve_variables` first to expand any
+    /// variable references in them.
+    pub fn vnet(&self, jail: &str) -> Option<JailVnet> {
+        let params = self.effective_params(jail)?;
+        let values = |name: &str| params.get(name).into_iter().flatten();
+
+        let mut vnet = JailVnet {
+            vnet: values("vnet").next_back().is_some_and(|value| enabled(value)),
+            ..JailVnet::default()
+        };
+
+        for value in values("vnet.interface") {
+            for interface in value.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+                push_unique(&mut vnet.interfaces, interface);
+            }
+        }
+
+        for command in START_PARAMS.iter().flat_map(|name| values(name)) {
+            for (interface, action) in ifconfig(command) {
+                match (action, epair(interface)) {
+                    ("create", Some(pair)) => push_unique(&mut vnet.created, pair),
+                    ("vnet", _)            => push_unique(&mut vnet.moved, interface),
+                    _                      => {},
+                }
+            }
+        }
+
+        for command in STOP_PARAMS.iter().flat_map(|name| values(name)) {
+            for (interface, action) in ifconfig(command) {
+                if let ("destroy", Some(pair)) = (action, epair(interface)) {
+                    push_unique(&mut vnet.destroyed, pair);
+                }
+            }
+        }
+
+        Some(vnet)
+    }
+}
+
+// Lints for the vnet jails of a configuration, see `JailConfig::lint`.
+// Variables are expanded first where they can be, since interface names are
+// often built from them, eg. `epair${id}b`.
+pub(crate) fn check(config: &JailConfig) -> Vec<Lint> {
+    let resolved = config.resolve_variables();
+    let config = resolved.as_ref().unwrap_or(config);
+
+    let mut lints = Vec::new();
+    let mut seen: Vec<(String, &str)> = Vec::new();
+
+    for jail in config.jail_names() {
+        let vnet = match config.vnet(jail) {
+            Some(vnet) if vnet.is_vnet() => vnet,
+            _                            => continue,
+        };
+
+        if vnet.interfaces().is_empty() && vnet.moved_interfaces().is_empty() {
+            lints.push(Lint::new(
+                Severity::Warning,
+                "vnet-without-interface",
+                "vnet jail has no interfaces but lo0, give it one with vnet.interface".to_string(),
+                Some(jail),
+                config.setter_span(jail, "vnet"),
+            ));
+        }
+
+        for interface in vnet.interfaces() {
+            let span = config.setter_span(jail, "vnet.interface");
+
+            // Names still holding a variable can't be compared.
+            if interface.contains('$') {
+                continue;
+            }
+
+            match seen.iter().find(|(other, _)| other == interface) {
+                Some((_, other)) => lints.push(Lint::new(
+                    Severity::Error,
+                    "duplicate-vnet-interface",
+                    format!("vnet.interface {} is also given to jail {}", interface, other),
+                    Some(jail),
+                    span,
+                )),
+                None => seen.push((interface.clone(), jail)),
+            }
+
+            let pair = match epair(interface) {
+                Some(pair) => pair,
+                None       => continue,
+            };
+
+            if !vnet.created_epairs().iter().any(|created| created == pair) {
+                lints.push(Lint::new(
+                    Severity::Info,
+                    "epair-not-created",
+                    format!(
+                        "{} isn't created by exec.prestart, it must exist before the jail starts",
+                        interface,
+                    ),
+                    Some(jail),
+                    span,
+                ));
+            }
+        }
+
+        for pair in vnet.created_epairs() {
+            if !vnet.destroyed_epairs().contains(pair) {
+                lints.push(Lint::new(
+                    Severity::Warning,
+                    "epair-not-destroyed",
+                    format!(
+                        "{} is created but not destroyed by exec.poststop, it's left behind when the jail stops",
+                        pair,
+                    ),
+                    Some(jail),
+                    config.setter_span(jail, "exec.prestart"),
+                ));
+            }
+        }
+    }
+
+    lints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_epair() {
+        assert_eq!(epair("epair0"), Some("epair0"));
+        assert_eq!(epair("epair12b"), Some("epair12"));
+        assert_eq!(epair("epair"), None);
+        assert_eq!(epair("epairb"), None);
+        assert_eq!(epair("e0b_www"), None);
+    }
+
+    #[test]
+    fn test_vnet() {
+        let input = indoc!(r#"
+            www {
+                vnet;
+                vnet.interface = epair0b;
+                exec.prestart = "/sbin/ifconfig epair0 create up";
+                exec.prestart += "ifconfig bridge0 addm epair0a";
+                exec.created = "ifconfig epair1b vnet www";
+                exec.poststop = "/sbin/ifconfig epair0a destroy";
+            }
+            db {
+                vnet = inherit;
+            }
+            "#);
+
+        let config = JailConfig::parse(input).unwrap();
+        let vnet = config.vnet("www").unwrap();
+
+        assert!(vnet.is_vnet());
+        assert_eq!(vnet.interfaces(), ["epair0b"]);
+        assert_eq!(vnet.created_epairs(), ["epair0"]);
+        assert_eq!(vnet.destroyed_epairs(), ["epair0"]);
+        assert_eq!(vnet.moved_interfaces(), ["epair1b"]);
+
+        assert!(!config.vnet("db").unwrap().is_vnet());
+        assert!(config.vnet("missing").is_none());
+    }
+
+    #[test]
+    fn test_vnet_lint() {
+        let input = indoc!(r#"
+            $pair = "epair5";
+            vnet;
+            www {
+                vnet.interface = "${pair}b";
+                exec.prestart = "ifconfig ${pair} create";
+                exec.poststop = "ifconfig ${pair}a destroy";
+            }
+            db {
+                vnet.interface = epair5b;
+                exec.prestart = "ifconfig epair7 create";
+            }
+            cache {
+                ip4.addr = 192.0.2.1;
+            }
+            host {
+                vnet = 0;
+            }
+            "#);
+
+        let config = JailConfig::parse(input).unwrap();
+        let lints: Vec<String> = check(&config)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(lints, vec![
+            "error[duplicate-vnet-interface]: db: vnet.interface epair5b is also given to jail www",
+            "info[epair-not-created]: db: epair5b isn't created by exec.prestart, it must exist before the jail starts",
+            "warning[epair-not-destroyed]: db: epair7 is created but not destroyed by exec.poststop, it's left behind when the jail stops",
+            "warning[vnet-without-interface]: cache: vnet jail has no interfaces but lo0, give it one with vnet.interface",
+        ]);
+
+        let lints = check(&config);
+        assert_eq!(lints[0].span().start().line(), 9);
+        assert_eq!(lints[3].span().start().line(), 2);
+    }
+}
@@ -0,0 +1,350 @@
+// Warnings about configuration which is valid, but odd.
+//
+// Some input parses without error yet is unlikely to be what was meant: a
+// parameter name jail(8) would never know, a parameter documented as
+// deprecated, a `$` in single quotes where it won't be expanded, or a comment
+// holding what looks like a parameter someone commented out. Refusing such
+// input would reject working configurations, while accepting it silently
+// hides mistakes, so parse_with_warnings returns the configuration along
+// with a Warning for each oddity found.
+use std::fmt;
+use crate::escape::{
+    fragments,
+    inner,
+    quote_style,
+};
+use crate::schema::parameter;
+use crate::visit::{
+    walk,
+    walk_block,
+    Visitor,
+};
+use crate::{
+    parse,
+    JailBlock,
+    JailComment,
+    JailConf,
+    JailConfig,
+    JailParamBool,
+    JailParamValue,
+    JailVariable,
+    ParseError,
+    QuoteStyle,
+    Span,
+};
+
+/// The kind of oddity a Warning was raised for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    /// A parameter name unlike any jail(8) uses, eg. one with capitals.
+    UnusualName,
+    /// A parameter jail(8) documents as deprecated.
+    Deprecated,
+    /// A parameter the release being targeted doesn't have yet.
+    Unavailable,
+    /// Quoting which probably doesn't do what was intended.
+    SuspiciousQuoting,
+    /// A comment which looks like a commented out parameter.
+    CommentedOut,
+}
+
+impl WarningKind {
+    /// A short identifier for the kind, eg. "unusual-name".
+    pub fn code(&self) -> &'static str {
+        match self {
+            WarningKind::UnusualName       => "unusual-name",
+            WarningKind::Deprecated        => "deprecated-parameter",
+            WarningKind::Unavailable       => "unavailable-parameter",
+            WarningKind::SuspiciousQuoting => "suspicious-quoting",
+            WarningKind::CommentedOut      => "commented-out-parameter",
+        }
+    }
+}
+
+/// Something odd, but not invalid, about a configuration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    kind:    WarningKind,
+    message: String,
+    jail:    Option<String>,
+    span:    Span,
+}
+
+impl Warning {
+    pub(crate) fn new(kind: WarningKind, message: String, jail: Option<&str>, span: Span) -> Self {
+        Self {
+            kind,
+            message,
+            jail: jail.map(String::from),
+            span,
+        }
+    }
+
+    /// What the warning was raised for.
+    pub fn kind(&self) -> WarningKind {
+        self.kind
+    }
+
+    /// A description of the oddity.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The jail the oddity was found in, or None outside of any jail.
+    pub fn jail(&self) -> Option<&str> {
+        self.jail.as_deref()
+    }
+
+    /// Where the statement at fault was parsed from.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "warning[{}]: ", self.kind.code())?;
+
+        if let Some(jail) = &self.jail {
+            write!(f, "{}: ", jail)?;
+        }
+
+        f.write_str(&self.message)
+    }
+}
+
+/// A parsed configuration along with any warnings about it, see
+/// `parse_with_warnings`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseOutput<'a> {
+    config:   JailConfig<'a>,
+    warnings: Vec<Warning>,
+}
+
+impl<'a> ParseOutput<'a> {
+    /// The parsed configuration.
+    pub fn config(&self) -> &JailConfig<'a> {
+        &self.config
+    }
+
+    /// The warnings found, in the order of the statements they're for.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// The configuration and warnings, taken apart.
+    pub fn into_parts(self) -> (JailConfig<'a>, Vec<Warning>) {
+        (self.config, self.warnings)
+    }
+}
+
+// Whether a name not in the schema is unlike any parameter name, eg.
+// `Allow.Mount` or `allow..mount`.
+fn is_unusual(name: &str) -> bool {
+    let usual_char = |c: char| {
+        c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '.'
+    };
+
+    parameter(name).is_none()
+        && (!name.chars().all(usual_char)
+            || name.starts_with('.')
+            || name.ends_with('.')
+            || name.contains(".."))
+}
+
+// Whether an escape in a double quoted or bare fragment does nothing but drop
+// its backslash, eg. the `\d` of a regular expression.
+fn pointless_escape(fragment: &str) -> Option<char> {
+    let mut chars = inner(fragment).chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            continue;
+        }
+
+        match chars.next() {
+            Some(c) if c.is_ascii_alphanumeric() && !"abfnrtvx01234567".contains(c) => {
+                return Some(c);
+            },
+            _ => {},
+        }
+    }
+
+    None
+}
+
+// The name of the parameter a comment holds, if it's nothing but a single
+// parameter jail(8) knows, eg. `# allow.mount;`.
+fn commented_out(comment: &str) -> Option<String> {
+    let statements = parse(comment.trim()).ok()?;
+
+    let name = match statements.as_slice() {
+        [JailConf::ParamBool(param)]  => param.name(),
+        [JailConf::ParamValue(param)] => param.name(),
+        _                             => return None,
+    };
+
+    parameter(name).map(|param| param.name().to_string())
+}
+
+// A warning for the parameter if jail(8) documents it as deprecated.
+pub(crate) fn deprecation(name: &str, jail: Option<&str>, span: Span) -> Option<Warning> {
+    let instead = parameter(name)?.deprecated()?;
+    let message = format!("{} is deprecated, use {} instead", name, instead);
+
+    Some(Warning::new(WarningKind::Deprecated, message, jail, span))
+}
+
+struct Collector {
+    jails:    Vec<String>,
+    warnings: Vec<Warning>,
+}
+
+impl Collector {
+    fn warn(&mut self, kind: WarningKind, message: String, span: Span) {
+        let warning = Warning::new(kind, message, self.jails.last().map(String::as_str), span);
+
+        self.warnings.push(warning);
+    }
+
+    fn name(&mut self, name: &str, span: Span) {
+        if is_unusual(name) {
+            let message = format!("unusual parameter name {}", name);
+            self.warn(WarningKind::UnusualName, message, span);
+        }
+
+        let jail = self.jails.last().map(String::as_str);
+
+        if let Some(warning) = deprecation(name, jail, span) {
+            self.warnings.push(warning);
+        }
+    }
+
+    fn value(&mut self, name: &str, raw: &str, span: Span) {
+        for fragment in fragments(raw) {
+            match quote_style(fragment) {
+                QuoteStyle::Single if inner(fragment).contains('$') => {
+                    let message = format!(
+                        "{} has a $ within single quotes, which isn't expanded",
+                        name,
+                    );
+
+                    self.warn(WarningKind::SuspiciousQuoting, message, span);
+                },
+                QuoteStyle::Single => {},
+                _ => if let Some(c) = pointless_escape(fragment) {
+                    let message = format!(
+                        "{} has \\{} in its value, which is just {}",
+                        name,
+                        c,
+                        c,
+                    );
+
+                    self.warn(WarningKind::SuspiciousQuoting, message, span);
+                },
+            }
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Collector {
+    fn visit_block(&mut self, block: &JailBlock<'a>) {
+        self.jails.push(block.name().to_string());
+        walk_block(block, self);
+        self.jails.pop();
+    }
+
+    fn visit_comment(&mut self, comment: &JailComment<'a>) {
+        if let Some(name) = commented_out(comment.comment()) {
+            let message = format!("comment looks like a commented out {} parameter", name);
+            self.warn(WarningKind::CommentedOut, message, comment.span());
+        }
+    }
+
+    fn visit_param_bool(&mut self, param: &JailParamBool<'a>) {
+        self.name(param.name(), param.span());
+    }
+
+    fn visit_param_value(&mut self, param: &JailParamValue<'a>) {
+        self.name(param.name(), param.span());
+        self.value(param.name(), param.raw(), param.span());
+    }
+
+    fn visit_variable(&mut self, var: &JailVariable<'a>) {
+        self.value(&format!("${}", var.name()), var.raw(), var.span());
+    }
+}
+
+impl<'a> JailConfig<'a> {
+    /// Look for oddities which are valid, but probably unintended: unusual
+    /// parameter names, deprecated parameters, suspicious quoting and
+    /// commented out parameters.
+    pub fn warnings(&self) -> Vec<Warning> {
+        let mut collector = Collector {
+            jails:    Vec::new(),
+            warnings: Vec::new(),
+        };
+
+        walk(self.statements(), &mut collector);
+
+        collector.warnings
+    }
+}
+
+/// Parse a configuration, returning warnings about anything odd in it along
+/// with the configuration. Only invalid syntax is an error.
+pub fn parse_with_warnings(input: &str) -> Result<ParseOutput<'_>, ParseError> {
+    let config = JailConfig::parse(input)?;
+    let warnings = config.warnings();
+
+    Ok(ParseOutput { config, warnings })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_parse_with_warnings() {
+        let input = indoc!(r#"
+            # allow.raw_sockets;
+            # Jails are started at boot.
+            allow.sysvipc;
+            Allow.Mount;
+            custom.param = 1;
+            www {
+                exec.start = '/bin/echo $HOME';
+                path = "C:\data\jails";
+                $dir = "\d+";
+            }
+            "#);
+
+        let output = parse_with_warnings(input).unwrap();
+        let warnings: Vec<String> = output.warnings()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(warnings, vec![
+            "warning[commented-out-parameter]: comment looks like a commented out allow.raw_sockets parameter",
+            "warning[deprecated-parameter]: allow.sysvipc is deprecated, use sysvmsg, sysvsem and sysvshm instead",
+            "warning[unusual-name]: unusual parameter name Allow.Mount",
+            "warning[suspicious-quoting]: www: exec.start has a $ within single quotes, which isn't expanded",
+            "warning[suspicious-quoting]: www: path has \\d in its value, which is just d",
+            "warning[suspicious-quoting]: www: $dir has \\d in its value, which is just d",
+        ]);
+
+        assert_eq!(output.warnings()[2].kind(), WarningKind::UnusualName);
+        assert_eq!(output.warnings()[2].span().start().line(), 4);
+
+        let (config, _) = output.into_parts();
+        assert_eq!(config.jails().count(), 1);
+    }
+
+    #[test]
+    fn test_parse_with_warnings_errors() {
+        assert!(parse_with_warnings("www {").is_err());
+        assert_eq!(parse_with_warnings("persist;\n").unwrap().warnings(), &[]);
+    }
+}
@@ -0,0 +1,57 @@
+// Bindings for JavaScript, through wasm-bindgen.
+//
+// The parser has no platform specific code, so built for
+// wasm32-unknown-unknown it can run in a browser, eg. for a jail.conf
+// linter or playground. parse_to_json is exported to JavaScript. Its result
+// is always JSON, either the statements parsed, as serde serializes them, or
+// where the input stopped parsing and why:
+//
+//   {"statements": [...]}
+//   {"error": {"message": "...", "offset": 12, "line": 2, "column": 5}}
+use serde_json::{
+    json,
+    Value,
+};
+use wasm_bindgen::prelude::wasm_bindgen;
+use crate::parse;
+
+// The result of parsing input, as a JSON value.
+fn parse_value(input: &str) -> Value {
+    match parse(input) {
+        Ok(statements) => json!({
+            "statements": statements,
+        }),
+        Err(e) => json!({
+            "error": {
+                "message": e.to_string(),
+                "offset":  e.offset(),
+                "line":    e.line(),
+                "column":  e.column(),
+            },
+        }),
+    }
+}
+
+/// Parse input, returning JSON holding either the statements parsed or the
+/// error stopping the parse.
+#[wasm_bindgen]
+pub fn parse_to_json(input: &str) -> String {
+    parse_value(input).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_to_json() {
+        let json: Value = serde_json::from_str(&parse_to_json("www {\n    persist;\n}\n")).unwrap();
+
+        assert_eq!(json["statements"].as_array().map(Vec::len), Some(1));
+
+        let json: Value = serde_json::from_str(&parse_to_json("www {\n    persist\n")).unwrap();
+
+        assert_eq!(json["error"]["line"], 2);
+        assert!(json.get("statements").is_none());
+    }
+}
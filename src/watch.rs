@@ -0,0 +1,162 @@
+// Reloading of configuration as it changes.
+//
+// Daemons which act on jail.conf want to see changes to it without being
+// restarted. watch_config watches a file and calls back with the
+// configuration parsed afresh each time its contents change, or with the
+// error if it can't be read or parsed, so a daemon can carry on with the
+// last good configuration until the file is fixed.
+//
+// The directory holding the file is watched rather than the file itself,
+// since editors and write_atomic replace a file by renaming another over
+// it, which a watch on the file would lose track of. Events for the other
+// files in the directory are ignored, as are changes which leave the
+// contents as they were, eg. a file being touched.
+use std::fs;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use notify::{
+    Event,
+    EventKind,
+    RecommendedWatcher,
+    RecursiveMode,
+    Watcher,
+};
+use crate::{
+    into_owned,
+    parse,
+    Error,
+    JailConfig,
+};
+
+/// Watches a configuration file, see `watch_config`. The file is watched
+/// for as long as this is kept, and no longer once it's dropped.
+#[derive(Debug)]
+pub struct ConfigWatcher {
+    path:     PathBuf,
+    // Held only so that the watch lasts as long as this does.
+    _watcher: RecommendedWatcher,
+}
+
+impl ConfigWatcher {
+    /// The file being watched.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+// Read and parse the file at path.
+fn load(path: &Path) -> Result<(String, JailConfig<'static>), Error> {
+    let text = fs::read_to_string(path).map_err(|error| Error::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    let config = parse(&text).map_err(|error| Error::Parse {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    let config = JailConfig::new(into_owned(config));
+
+    Ok((text, config))
+}
+
+/// Watch the configuration file at path, calling callback with the
+/// configuration each time the file's contents change, or with the error
+/// reading or parsing it. The callback is called from a thread of the
+/// watcher's, not the caller's.
+pub fn watch_config<P, F>(path: P, mut callback: F) -> notify::Result<ConfigWatcher>
+where
+    P: AsRef<Path>,
+    F: FnMut(Result<JailConfig<'static>, Error>) + Send + 'static,
+{
+    let path = path.as_ref().to_path_buf();
+    let name = path.file_name()
+        .ok_or_else(|| notify::Error::generic("path has no file name"))?
+        .to_os_string();
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _                                        => PathBuf::from("."),
+    };
+
+    // The contents last loaded, so that changes which leave them as they
+    // were don't call back again.
+    let mut last = fs::read_to_string(&path).ok();
+    let file = path.clone();
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let event = match event {
+            Ok(event) => event,
+            Err(_)    => return,
+        };
+
+        let relevant = matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        ) && event.paths.iter().any(|changed| changed.file_name() == Some(&name));
+
+        if !relevant {
+            return;
+        }
+
+        match load(&file) {
+            Ok((text, _)) if last.as_deref() == Some(text.as_str()) => {},
+            Ok((text, config)) => {
+                last = Some(text);
+                callback(Ok(config));
+            },
+            Err(e) => {
+                last = None;
+                callback(Err(e));
+            },
+        }
+    })?;
+
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+    Ok(ConfigWatcher {
+        path,
+        _watcher: watcher,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use crate::scratch::scratch_dir;
+    use crate::write_atomic;
+
+    #[test]
+    fn test_watch_config() {
+        let dir = scratch_dir("watch");
+
+        let path = dir.join("jail.conf");
+        fs::write(&path, "www {\n    persist;\n}\n").unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let watcher = watch_config(&path, move |result| {
+            let _ = tx.send(result);
+        }).unwrap();
+
+        assert_eq!(watcher.path(), path);
+
+        let next = || rx.recv_timeout(Duration::from_secs(10)).unwrap();
+
+        fs::write(dir.join("other.conf"), "db {\n}\n").unwrap();
+        write_atomic(&path, "www {\n    path = /jails/www;\n}\n").unwrap();
+
+        let config = next().unwrap();
+        assert_eq!(config.get_param("www", "path").as_deref(), Some("/jails/www"));
+
+        write_atomic(&path, "www {\n").unwrap();
+        assert!(matches!(next(), Err(Error::Parse { .. })));
+
+        drop(watcher);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
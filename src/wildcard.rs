@@ -0,0 +1,158 @@
+// Wildcard jail blocks.
+//
+// A block whose name has a `*` component isn't a jail itself. jail(8)
+// applies its parameters to every jail whose name matches, so a `*` block
+// sets defaults for every jail and a `web.*` block for the jails within
+// `web`. A `*` component matches exactly one component of a jail's name,
+// except when it's the last, where it matches one or more, so `web.*`
+// matches both `web.www` and `web.www.static`, though not `web` itself.
+//
+// The parameters applying to a jail are the global ones, then those of
+// every wildcard block it matches in the order the blocks are given, then
+// those of its own blocks. Wildcard blocks aren't applied to each other.
+use crate::{
+    JailBlock,
+    JailConf,
+    JailConfig,
+};
+
+// Whether a name is a wildcard pattern rather than the name of a jail.
+pub(crate) fn is_wildcard(name: &str) -> bool {
+    name.split('.').any(|component| component == "*")
+}
+
+// Whether the jail's name matches a wildcard pattern, as jail(8) matches
+// them.
+pub(crate) fn wildcard_matches(pattern: &str, jail: &str) -> bool {
+    let mut patterns = pattern.split('.').peekable();
+    let mut names = jail.split('.');
+
+    while let Some(component) = patterns.next() {
+        let name = match names.next() {
+            Some(name) => name,
+            None       => return false,
+        };
+
+        if component == "*" && patterns.peek().is_none() {
+            return true;
+        }
+
+        if component != "*" && component != name {
+            return false;
+        }
+    }
+
+    names.next().is_none()
+}
+
+impl<'a> JailBlock<'a> {
+    /// Whether the block is a wildcard, such as `*` or `web.*`, whose
+    /// parameters apply to the jails matching it rather than a jail of its
+    /// own.
+    pub fn is_wildcard(&self) -> bool {
+        is_wildcard(self.name())
+    }
+
+    /// Whether the block applies to the named jail, either as that jail's
+    /// own block or as a wildcard matching it.
+    pub fn applies_to(&self, jail: &str) -> bool {
+        if self.is_wildcard() {
+            !is_wildcard(jail) && wildcard_matches(self.name(), jail)
+        }
+        else {
+            self.name() == jail
+        }
+    }
+}
+
+impl<'a> JailConfig<'a> {
+    /// The wildcard blocks, in the order they were given.
+    pub fn wildcards(&self) -> impl Iterator<Item = &JailBlock<'a>> {
+        self.jails().filter(|block| block.is_wildcard())
+    }
+
+    // The statements setting the parameters of the named jail, in the
+    // order they apply: the global ones, those of the wildcard blocks
+    // matching the jail, then those of its own blocks.
+    pub(crate) fn applying<'c>(&'c self, jail: &str) -> Vec<&'c JailConf<'a>> {
+        let wildcards = self.wildcards()
+            .filter(|block| block.applies_to(jail))
+            .flat_map(|block| block.params());
+
+        let own = self.jails()
+            .filter(|block| block.name() == jail)
+            .flat_map(|block| block.params());
+
+        self.statements().iter().chain(wildcards).chain(own).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_wildcard_matches() {
+        assert!(wildcard_matches("*", "www"));
+        assert!(wildcard_matches("*", "web.www"));
+        assert!(wildcard_matches("web.*", "web.www"));
+        assert!(wildcard_matches("web.*", "web.www.static"));
+        assert!(wildcard_matches("*.www", "web.www"));
+        assert!(!wildcard_matches("*.www", "web.www.static"));
+        assert!(!wildcard_matches("web.*", "web"));
+        assert!(!wildcard_matches("web.*", "db.www"));
+        assert!(!wildcard_matches("web.*.static", "web.www"));
+
+        assert!(is_wildcard("*"));
+        assert!(is_wildcard("web.*"));
+        assert!(!is_wildcard("www"));
+        assert!(!is_wildcard("web*"));
+    }
+
+    #[test]
+    fn test_wildcard_blocks() {
+        let input = indoc!(r#"
+            path = "/jails/default";
+            * {
+                persist;
+                host.hostname = "default";
+            }
+            web.* {
+                path = "/jails/web";
+                ip4.addr = 192.0.2.1;
+            }
+            web.www {
+                ip4.addr += 192.0.2.2;
+                host.hostname = "www";
+            }
+            db {
+            }
+            "#);
+
+        let config = JailConfig::parse(input).unwrap();
+
+        let names: Vec<&str> = config.wildcards().map(|block| block.name()).collect();
+        assert_eq!(names, vec!["*", "web.*"]);
+
+        // Wildcards take precedence over globals, and the jail's own block
+        // over wildcards.
+        let www = config.effective_params("web.www").unwrap();
+        assert_eq!(www["path"], vec!["/jails/web"]);
+        assert_eq!(www["ip4.addr"], vec!["192.0.2.1", "192.0.2.2"]);
+        assert_eq!(www["host.hostname"], vec!["www"]);
+        assert_eq!(www["persist"], vec!["true"]);
+
+        let db = config.effective_params("db").unwrap();
+        assert_eq!(db["path"], vec!["/jails/default"]);
+        assert_eq!(db["host.hostname"], vec!["default"]);
+        assert!(!db.contains_key("ip4.addr"));
+
+        assert_eq!(config.get_param("web.www", "path").as_deref(), Some("/jails/web"));
+        assert_eq!(config.get_values("db", "host.hostname"), vec!["default"]);
+
+        // Wildcards aren't applied to each other.
+        let web = config.effective_params("web.*").unwrap();
+        assert!(!web.contains_key("persist"));
+    }
+}
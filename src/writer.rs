@@ -0,0 +1,266 @@
+// Rendering of configuration back into jail.conf syntax.
+//
+// Every AST type implements Display, producing one statement per line with
+// block contents indented by four spaces. Values are written exactly as they
+// were given, so quoting is preserved.
+use std::fmt;
+use std::io;
+use crate::builder::name_quote;
+use crate::escape::quote;
+use crate::{
+    CommentStyle,
+    JailBlock,
+    JailComment,
+    JailConf,
+    JailConfig,
+    JailInclude,
+    JailParamBool,
+    JailParamValue,
+    JailVariable,
+    QuoteStyle,
+};
+
+const INDENT: &str = "    ";
+
+// Write a statement at the given nesting depth.
+fn write_statement(f: &mut fmt::Formatter, statement: &JailConf, depth: usize)
+-> fmt::Result {
+    for _ in 0..depth {
+        f.write_str(INDENT)?;
+    }
+
+    match statement {
+        JailConf::Block(block)      => write_block(f, block, depth),
+        JailConf::Comment(comment)  => write!(f, "{}", comment),
+        JailConf::Include(include)  => write!(f, "{}", include),
+        JailConf::ParamBool(param)  => write!(f, "{}", param),
+        JailConf::ParamValue(param) => write!(f, "{}", param),
+        JailConf::Variable(var)     => write!(f, "{}", var),
+    }
+}
+
+// Write a block, indenting its contents one level deeper than the block
+// itself.
+fn write_block(f: &mut fmt::Formatter, block: &JailBlock, depth: usize)
+-> fmt::Result {
+    // A bare name is written quoted if escapes were decoded from it, as
+    // written bare it would mean something else.
+    match block.quote {
+        QuoteStyle::None if name_quote(&block.name) == QuoteStyle::None => {
+            write!(f, "{}", block.name)?
+        },
+        QuoteStyle::None | QuoteStyle::Double => f.write_str(&quote(&block.name))?,
+        QuoteStyle::Single => write!(f, "'{}'", block.name)?,
+    }
+
+    writeln!(f, " {{")?;
+
+    for param in &block.params {
+        write_statement(f, param, depth + 1)?;
+        writeln!(f)?;
+    }
+
+    for _ in 0..depth {
+        f.write_str(INDENT)?;
+    }
+
+    write!(f, "}}")
+}
+
+impl<'a> fmt::Display for JailComment<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.style {
+            CommentStyle::C     => write!(f, "/*{}*/", self.comment),
+            CommentStyle::CPP   => write!(f, "//{}", self.comment),
+            CommentStyle::Shell => write!(f, "#{}", self.comment),
+        }
+    }
+}
+
+// The path is stored decoded, so it's always written double quoted with
+// escapes, which the parser decodes back to the same path.
+impl<'a> fmt::Display for JailInclude<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, ".include {};", quote(&self.path))
+    }
+}
+
+impl<'a> fmt::Display for JailParamBool<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{};", self.name)
+    }
+}
+
+impl<'a> fmt::Display for JailParamValue<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = if self.append { "+=" } else { "=" };
+
+        write!(f, "{} {} {};", self.name, op, self.raw)
+    }
+}
+
+impl<'a> fmt::Display for JailVariable<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = if self.append { "+=" } else { "=" };
+
+        write!(f, "${} {} {};", self.name, op, self.raw)
+    }
+}
+
+impl<'a> fmt::Display for JailBlock<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_block(f, self, 0)
+    }
+}
+
+impl<'a> fmt::Display for JailConf<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_statement(f, self, 0)
+    }
+}
+
+impl<'a> fmt::Display for JailConfig<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&to_string(self.statements()))
+    }
+}
+
+/// Write the given configuration to writer in jail.conf syntax.
+///
+/// Top level blocks are followed by a blank line to separate them from the
+/// statements after them.
+pub fn write_config<W>(config: &[JailConf], writer: &mut W) -> io::Result<()>
+where
+    W: io::Write,
+{
+    for (i, statement) in config.iter().enumerate() {
+        if i > 0 {
+            if let JailConf::Block(_) = config[i - 1] {
+                writeln!(writer)?;
+            }
+        }
+
+        writeln!(writer, "{}", statement)?;
+    }
+
+    Ok(())
+}
+
+/// Render the given configuration to a string in jail.conf syntax.
+pub fn to_string(config: &[JailConf]) -> String {
+    let mut output = Vec::new();
+
+    // Writing to a Vec can't fail.
+    write_config(config, &mut output).expect("write to Vec failed");
+
+    // Everything written came from str, so is valid UTF-8.
+    String::from_utf8(output).expect("rendered config is not UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use indoc::indoc;
+
+    #[test]
+    fn test_to_string() {
+        let input = indoc!(r#"
+            /* Global settings */
+            persist;
+            exec.clean;     # Clean environment
+            allow.raw_sockets=0;
+            nginx {
+              // Web server
+              host.hostname =   "nginx";
+              ip4.addr += "lo1|127.0.1.1/32";
+            }
+            jail2 { persist; }
+            "#);
+
+        let expected = indoc!(r#"
+            /* Global settings */
+            persist;
+            exec.clean;
+            # Clean environment
+            allow.raw_sockets = 0;
+            nginx {
+                // Web server
+                host.hostname = "nginx";
+                ip4.addr += "lo1|127.0.1.1/32";
+            }
+
+            jail2 {
+                persist;
+            }
+            "#);
+
+        let config = parse(input).unwrap();
+
+        assert_eq!(to_string(&config), expected);
+    }
+
+    #[test]
+    fn test_to_string_reparses() {
+        let input = indoc!(r#"
+            nginx {
+                exec.stop = "/bin/sh /etc/rc.shutdown";
+                persist;
+            }
+            "#);
+
+        let config = parse(input).unwrap();
+        let output = to_string(&config);
+
+        assert_eq!(parse(&output).unwrap().len(), config.len());
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_display_variable() {
+        let config = parse("$base = \"/usr/jails\";\n").unwrap();
+
+        assert_eq!(config[0].to_string(), "$base = \"/usr/jails\";");
+    }
+
+    #[test]
+    fn test_display_nested_block() {
+        let input = "outer {\n    inner {\n        persist;\n    }\n}";
+        let config = parse(input).unwrap();
+
+        assert_eq!(config[0].to_string(), input);
+    }
+
+    #[test]
+    fn test_display_include_round_trip() {
+        let inputs = [
+            (".include '#x';", ".include \"#x\";"),
+            (".include \"a\\\"b\";", ".include \"a\\\"b\";"),
+            (".include \"a\\\\b\";", ".include \"a\\\\b\";"),
+            (".include 'it\\s';", ".include \"it\\\\s\";"),
+            (".include \"tab\\there\";", ".include \"tab\\there\";"),
+            (".include jail.conf.d/*.conf;", ".include \"jail.conf.d/*.conf\";"),
+        ];
+
+        for (input, expected) in inputs {
+            let config = parse(input).unwrap();
+            let output = config[0].to_string();
+            let reparsed = parse(&output).unwrap();
+
+            assert_eq!(output, expected);
+            assert_eq!(reparsed[0].to_string(), output);
+
+            match (&config[0], &reparsed[0]) {
+                (JailConf::Include(a), JailConf::Include(b)) => assert_eq!(a.path(), b.path()),
+                statements => panic!("expected includes, got {:?}", statements),
+            }
+        }
+    }
+
+    #[test]
+    fn test_display_escaped_bare_name() {
+        let config = parse("a\\\\b { persist; }\n").unwrap();
+
+        assert_eq!(config[0].to_string(), "\"a\\\\b\" {\n    persist;\n}");
+    }
+}
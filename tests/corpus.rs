@@ -0,0 +1,26 @@
+// The corpus of sample configurations in tests/corpus.
+//
+// Each sample is checked with jailconf::check_corpus. After a change to
+// what's reported, review the differences and record the new results with:
+//
+//   JAILCONF_BLESS=1 cargo test --test corpus
+use std::path::Path;
+use jailconf::check_corpus;
+
+#[test]
+fn test_corpus() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let reports = check_corpus(&dir).unwrap();
+
+    assert!(!reports.is_empty(), "no samples in {}", dir.display());
+
+    let failures: Vec<String> = reports.iter()
+        .flat_map(|report| {
+            report.failures()
+                .iter()
+                .map(move |failure| format!("{}: {}", report.path().display(), failure))
+        })
+        .collect();
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}